@@ -4,9 +4,17 @@ use std::fs::{read_dir, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const BLOCK_SZ: usize = 512;
 
+/// Stamp the packed files with the time the image was built.
+fn host_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_nanos() as u64)
+}
+
 struct BlockFile(Mutex<File>);
 
 impl BlockDevice for BlockFile {
@@ -49,6 +57,13 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
+        .arg(
+            Arg::with_name("extra")
+                .short("e")
+                .long("extra")
+                .takes_value(true)
+                .help("Dir of prebuilt binaries (e.g. static musl ones) copied as they are"),
+        )
         .get_matches();
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
@@ -64,6 +79,7 @@ fn easy_fs_pack() -> std::io::Result<()> {
     })));
     // 32MiB, at most 4095 files
     let efs = EasyFileSystem::create(block_file, 32 * 2048, 1);
+    efs.lock().set_clock(host_clock);
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
     let apps: Vec<_> = read_dir(src_path)
         .unwrap()
@@ -84,6 +100,19 @@ fn easy_fs_pack() -> std::io::Result<()> {
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
     }
+    if let Some(extra_path) = matches.value_of("extra") {
+        for dir_entry in read_dir(extra_path)? {
+            let path = dir_entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let mut all_data: Vec<u8> = Vec::new();
+            File::open(&path)?.read_to_end(&mut all_data)?;
+            let inode = root_inode.create(name).unwrap();
+            inode.write_at(0, all_data.as_slice());
+        }
+    }
     // list apps
     // for app in root_inode.ls() {
     //     println!("{}", app);
@@ -76,8 +76,12 @@ impl Drop for BlockCache {
 
 const BLOCK_CACHE_SIZE: usize = 16;
 
+/// Blocks are cached per device, partitions of one disk number theirs
+/// from 0 each.
+type CacheKey = (usize, usize);
+
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    queue: VecDeque<(CacheKey, Arc<Mutex<BlockCache>>)>,
 }
 
 impl BlockCacheManager {
@@ -92,7 +96,8 @@ impl BlockCacheManager {
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
+        let key = (Arc::as_ptr(&block_device) as *const () as usize, block_id);
+        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == key) {
             Arc::clone(&pair.1)
         } else {
             // substitute
@@ -114,7 +119,7 @@ impl BlockCacheManager {
                 block_id,
                 Arc::clone(&block_device),
             )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
+            self.queue.push_back((key, Arc::clone(&block_cache)));
             block_cache
         }
     }
@@ -134,9 +139,33 @@ pub fn get_block_cache(
         .get_block_cache(block_id, block_device)
 }
 
+/// The block if it is cached, without loading it.
+pub fn peek_block_cache(
+    block_id: usize,
+    block_device: &Arc<dyn BlockDevice>,
+) -> Option<Arc<Mutex<BlockCache>>> {
+    let key = (Arc::as_ptr(block_device) as *const () as usize, block_id);
+    BLOCK_CACHE_MANAGER
+        .lock()
+        .queue
+        .iter()
+        .find(|pair| pair.0 == key)
+        .map(|pair| Arc::clone(&pair.1))
+}
+
 pub fn block_cache_sync_all() {
     let manager = BLOCK_CACHE_MANAGER.lock();
     for (_, cache) in manager.queue.iter() {
         cache.lock().sync();
     }
 }
+
+/// Write back every block and forget them, once nothing will use the
+/// devices any more.
+pub fn block_cache_invalidate_all() {
+    let mut manager = BLOCK_CACHE_MANAGER.lock();
+    for (_, cache) in manager.queue.iter() {
+        cache.lock().sync();
+    }
+    manager.queue.clear();
+}
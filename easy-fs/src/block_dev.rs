@@ -4,4 +4,7 @@ pub trait BlockDevice: Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     fn write_block(&self, block_id: usize, buf: &[u8]);
     fn handle_irq(&self);
+    /// Start reading `block_ids`, which are likely to be read soon, without
+    /// waiting for them. A device which cannot read ahead ignores this.
+    fn prefetch(&self, _block_ids: &[usize]) {}
 }
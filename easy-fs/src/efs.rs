@@ -1,6 +1,6 @@
 use super::{
-    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
-    SuperBlock,
+    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, FileTime,
+    Inode, SuperBlock,
 };
 use crate::BLOCK_SZ;
 use alloc::sync::Arc;
@@ -12,10 +12,17 @@ pub struct EasyFileSystem {
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32,
     data_area_start_block: u32,
+    /// wall clock in nanoseconds since the epoch, for the inode timestamps
+    clock: fn() -> u64,
 }
 
 type DataBlock = [u8; BLOCK_SZ];
 
+/// Until a clock is set, every timestamp is the epoch.
+fn no_clock() -> u64 {
+    0
+}
+
 impl EasyFileSystem {
     pub fn create(
         block_device: Arc<dyn BlockDevice>,
@@ -41,6 +48,7 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            clock: no_clock,
         };
         // clear all blocks
         for i in 0..total_blocks {
@@ -72,12 +80,22 @@ impl EasyFileSystem {
         get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
             .lock()
             .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-                disk_inode.initialize(DiskInodeType::Directory);
+                disk_inode.initialize(DiskInodeType::Directory, efs.now());
             });
         block_cache_sync_all();
         Arc::new(Mutex::new(efs))
     }
 
+    /// Whether `block_device` holds an easy-fs, read past the block cache.
+    pub fn probe(block_device: &dyn BlockDevice) -> bool {
+        let mut block = [0u32; BLOCK_SZ / 4];
+        block_device.read_block(0, unsafe {
+            core::slice::from_raw_parts_mut(block.as_mut_ptr() as *mut u8, BLOCK_SZ)
+        });
+        let super_block = unsafe { &*(block.as_ptr() as *const SuperBlock) };
+        super_block.is_valid()
+    }
+
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
         // read SuperBlock
         get_block_cache(0, Arc::clone(&block_device))
@@ -95,11 +113,20 @@ impl EasyFileSystem {
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                    clock: no_clock,
                 };
                 Arc::new(Mutex::new(efs))
             })
     }
 
+    pub fn set_clock(&mut self, clock: fn() -> u64) {
+        self.clock = clock;
+    }
+
+    pub(crate) fn now(&self) -> FileTime {
+        FileTime::from_ns((self.clock)())
+    }
+
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
         let block_device = Arc::clone(&efs.lock().block_device);
         // acquire efs lock temporarily
@@ -118,6 +145,14 @@ impl EasyFileSystem {
         )
     }
 
+    /// Inverse of get_disk_inode_pos.
+    pub fn get_inode_id(&self, block_id: u32, block_offset: usize) -> u32 {
+        let inode_size = core::mem::size_of::<DiskInode>();
+        let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
+        (block_id - self.inode_area_start_block) * inodes_per_block
+            + (block_offset / inode_size) as u32
+    }
+
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
     }
@@ -126,6 +161,11 @@ impl EasyFileSystem {
         self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
     }
 
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
+    }
+
     /// Return a block ID not ID in the data area.
     pub fn alloc_data(&mut self) -> u32 {
         self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
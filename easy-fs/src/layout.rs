@@ -1,10 +1,10 @@
-use super::{get_block_cache, BlockDevice, BLOCK_SZ};
+use super::{get_block_cache, peek_block_cache, BlockDevice, BLOCK_SZ};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter, Result};
 
-const EFS_MAGIC: u32 = 0x3b800001;
-const INODE_DIRECT_COUNT: usize = 28;
+const EFS_MAGIC: u32 = 0x3b800002;
+const INODE_DIRECT_COUNT: usize = 22;
 const NAME_LENGTH_LIMIT: usize = 27;
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
@@ -62,6 +62,30 @@ impl SuperBlock {
 pub enum DiskInodeType {
     File,
     Directory,
+    Fifo,
+}
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+const RELATIME_INTERVAL_NS: u64 = 24 * 3600 * NSEC_PER_SEC;
+
+/// A point in time as stored on disk, seconds since the epoch last until 2106.
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct FileTime {
+    sec: u32,
+    nsec: u32,
+}
+
+impl FileTime {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            sec: (ns / NSEC_PER_SEC) as u32,
+            nsec: (ns % NSEC_PER_SEC) as u32,
+        }
+    }
+    pub fn to_ns(self) -> u64 {
+        self.sec as u64 * NSEC_PER_SEC + self.nsec as u64
+    }
 }
 
 type IndirectBlock = [u32; BLOCK_SZ / 4];
@@ -74,16 +98,40 @@ pub struct DiskInode {
     pub indirect1: u32,
     pub indirect2: u32,
     type_: DiskInodeType,
+    /// directory entries naming this inode, it fits in the padding after type_
+    pub nlink: u16,
+    /// last read
+    pub atime: FileTime,
+    /// last change of the data
+    pub mtime: FileTime,
+    /// last change of the data or the inode itself
+    pub ctime: FileTime,
 }
 
 impl DiskInode {
     /// indirect1 and indirect2 block are allocated only when they are needed.
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    pub fn initialize(&mut self, type_: DiskInodeType, now: FileTime) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
         self.type_ = type_;
+        self.nlink = 1;
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
+    }
+    /// The data changed, which changes the inode as well.
+    pub fn touch_modified(&mut self, now: FileTime) {
+        self.mtime = now;
+        self.ctime = now;
+    }
+    /// Like relatime: atime only moves when it is not newer than the last
+    /// change or a day old, so most reads leave the inode block clean.
+    pub fn needs_atime_update(&self, now: FileTime) -> bool {
+        self.atime <= self.mtime
+            || self.atime <= self.ctime
+            || now.to_ns() >= self.atime.to_ns() + RELATIME_INTERVAL_NS
     }
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
@@ -92,6 +140,9 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    pub fn is_fifo(&self) -> bool {
+        self.type_ == DiskInodeType::Fifo
+    }
     /// Return block number correspond to size.
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -296,6 +347,25 @@ impl DiskInode {
         offset: usize,
         buf: &mut [u8],
         block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        self.read_blocks(offset, buf, block_device, false)
+    }
+    /// As `read_at`, whole blocks not cached are read by the device
+    /// straight into `buf` without being cached.
+    pub fn read_at_direct(
+        &self,
+        offset: usize,
+        buf: &mut [u8],
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        self.read_blocks(offset, buf, block_device, true)
+    }
+    fn read_blocks(
+        &self,
+        offset: usize,
+        buf: &mut [u8],
+        block_device: &Arc<dyn BlockDevice>,
+        direct: bool,
     ) -> usize {
         let mut start = offset;
         let end = (offset + buf.len()).min(self.size as usize);
@@ -311,15 +381,19 @@ impl DiskInode {
             // read and update read size
             let block_read_size = end_current_block - start;
             let dst = &mut buf[read_size..read_size + block_read_size];
-            get_block_cache(
-                self.get_block_id(start_block as u32, block_device) as usize,
-                Arc::clone(block_device),
-            )
-            .lock()
-            .read(0, |data_block: &DataBlock| {
-                let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
-                dst.copy_from_slice(src);
-            });
+            let block_id = self.get_block_id(start_block as u32, block_device) as usize;
+            let cached = peek_block_cache(block_id, block_device);
+            if direct && block_read_size == BLOCK_SZ && cached.is_none() {
+                block_device.read_block(block_id, dst);
+            } else {
+                cached
+                    .unwrap_or_else(|| get_block_cache(block_id, Arc::clone(block_device)))
+                    .lock()
+                    .read(0, |data_block: &DataBlock| {
+                        let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
+                        dst.copy_from_slice(src);
+                    });
+            }
             read_size += block_read_size;
             // move to next block
             if end_current_block == end {
@@ -330,6 +404,22 @@ impl DiskInode {
         }
         read_size
     }
+    /// Blocks holding the bytes `[offset, offset + len)` of the file, as
+    /// far as it reaches.
+    pub fn block_ids(
+        &self,
+        offset: usize,
+        len: usize,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<usize> {
+        let end = (offset + len).min(self.size as usize);
+        if offset >= end {
+            return Vec::new();
+        }
+        (offset / BLOCK_SZ..(end + BLOCK_SZ - 1) / BLOCK_SZ)
+            .map(|inner_id| self.get_block_id(inner_id as u32, block_device) as usize)
+            .collect()
+    }
     /// File size must be adjusted before.
     pub fn write_at(
         &mut self,
@@ -1,12 +1,50 @@
 use super::{
     block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    EasyFileSystem, FileTime, DIRENT_SZ,
 };
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use lazy_static::*;
 use spin::{Mutex, MutexGuard};
 
+/// [`Inode::identity`]
+type InodeKey = (usize, usize, usize);
+
+/// The inodes someone holds an `Inode` of. An unlinked one in there is an
+/// orphan: its data stays until the last `Inode` is dropped.
+#[derive(Default)]
+struct LiveInodes {
+    counts: BTreeMap<InodeKey, usize>,
+    orphans: BTreeSet<InodeKey>,
+}
+
+impl LiveInodes {
+    fn get(&mut self, key: InodeKey) {
+        *self.counts.entry(key).or_insert(0) += 1;
+    }
+    /// True if that was the last one of an orphan, which is to be freed.
+    fn put(&mut self, key: InodeKey) -> bool {
+        let count = self.counts.get_mut(&key).unwrap();
+        *count -= 1;
+        if *count > 0 {
+            return false;
+        }
+        self.counts.remove(&key);
+        self.orphans.remove(&key)
+    }
+    /// Make an unlinked inode an orphan if it is in use, false if it is not
+    /// and can be freed right away.
+    fn orphan(&mut self, key: InodeKey) -> bool {
+        self.counts.contains_key(&key) && self.orphans.insert(key)
+    }
+}
+
+lazy_static! {
+    static ref LIVE_INODES: Mutex<LiveInodes> = Mutex::new(LiveInodes::default());
+}
+
 pub struct Inode {
     block_id: usize,
     block_offset: usize,
@@ -22,12 +60,14 @@ impl Inode {
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
-        Self {
+        let inode = Self {
             block_id: block_id as usize,
             block_offset,
             fs,
             block_device,
-        }
+        };
+        LIVE_INODES.lock().get(inode.identity());
+        inode
     }
 
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
@@ -43,6 +83,13 @@ impl Inode {
     }
 
     fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
+        self.find_dirent(name, disk_inode)
+            .map(|(_, inode_id)| inode_id)
+    }
+
+    /// Index and inode of the entry called `name`. Removed entries have an
+    /// empty name and are never found.
+    fn find_dirent(&self, name: &str, disk_inode: &DiskInode) -> Option<(usize, u32)> {
         // assert it is a directory
         assert!(disk_inode.is_dir());
         let file_count = (disk_inode.size as usize) / DIRENT_SZ;
@@ -53,12 +100,47 @@ impl Inode {
                 DIRENT_SZ,
             );
             if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
+                return Some((i, dirent.inode_number() as u32));
             }
         }
         None
     }
 
+    fn inode_id(&self, fs: &EasyFileSystem) -> u32 {
+        fs.get_inode_id(self.block_id as u32, self.block_offset)
+    }
+
+    /// Same for every path leading to this inode.
+    pub fn inode_number(&self) -> u32 {
+        self.inode_id(&self.fs.lock())
+    }
+
+    /// The file system and the place of the inode on its disk, telling
+    /// files apart across file systems without taking a lock.
+    pub fn identity(&self) -> (usize, usize, usize) {
+        (
+            Arc::as_ptr(&self.fs) as usize,
+            self.block_id,
+            self.block_offset,
+        )
+    }
+
+    /// Put `name` into the first free entry of this directory, or append it.
+    fn add_dirent(&self, name: &str, inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        self.modify_disk_inode(|dir_inode| {
+            let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+            let index = self
+                .find_dirent("", dir_inode)
+                .map_or(file_count, |(i, _)| i);
+            // increase size
+            self.increase_size(((index + 1) * DIRENT_SZ) as u32, dir_inode, fs);
+            // write dirent
+            let dirent = DirEntry::new(name, inode_id);
+            dir_inode.write_at(index * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+            dir_inode.touch_modified(fs.now());
+        });
+    }
+
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
         let fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
@@ -92,6 +174,19 @@ impl Inode {
     }
 
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+
+    pub fn create_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+
+    /// Create a named pipe, its data lives in memory only.
+    pub fn create_fifo(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Fifo)
+    }
+
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Option<Arc<Inode>> {
         let mut fs = self.fs.lock();
         let op = |root_inode: &mut DiskInode| {
             // assert it is a directory
@@ -110,22 +205,9 @@ impl Inode {
         get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
+                new_inode.initialize(type_, fs.now());
             });
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
-        });
+        self.add_dirent(name, new_inode_id, &mut fs);
 
         let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
         block_cache_sync_all();
@@ -150,21 +232,206 @@ impl Inode {
                     disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
                     DIRENT_SZ,
                 );
-                v.push(String::from(dirent.name()));
+                if !dirent.name().is_empty() {
+                    v.push(String::from(dirent.name()));
+                }
             }
             v
         })
     }
 
-    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+    /// Name and inode of the `index`-th entry of a directory, None past the
+    /// last one. A removed entry has an empty name and no inode.
+    pub fn dirent(&self, index: usize) -> Option<(String, Option<Arc<Inode>>)> {
+        let fs = self.fs.lock();
+        if index == 0 {
+            self.touch_accessed(fs.now());
+        }
+        let dirent = self.read_disk_inode(|disk_inode| {
+            if (index + 1) * DIRENT_SZ > disk_inode.size as usize {
+                return None;
+            }
+            let mut dirent = DirEntry::empty();
+            disk_inode.read_at(index * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+            Some(dirent)
+        })?;
+        if dirent.name().is_empty() {
+            return Some((String::new(), None));
+        }
+        let (block_id, block_offset) = fs.get_disk_inode_pos(dirent.inode_number());
+        let inode = Self::new(
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        );
+        Some((String::from(dirent.name()), Some(Arc::new(inode))))
+    }
+
+    /// Give `target` another name in this directory. Directories cannot be
+    /// linked, and `name` must not exist yet.
+    pub fn link(&self, name: &str, target: &Inode) -> bool {
+        let mut fs = self.fs.lock();
+        if self.read_disk_inode(|dir_inode| self.find_inode_id(name, dir_inode).is_some())
+            || target.read_disk_inode(|disk_inode| disk_inode.is_dir())
+        {
+            return false;
+        }
+        target.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink += 1;
+            disk_inode.ctime = fs.now();
+        });
+        let inode_id = target.inode_id(&fs);
+        self.add_dirent(name, inode_id, &mut fs);
+        block_cache_sync_all();
+        true
+    }
+
+    /// Remove the entry `name`, and the inode with its data once no entry
+    /// names it anymore and no `Inode` of it is left.
+    pub fn unlink(&self, name: &str) -> bool {
+        let mut fs = self.fs.lock();
+        let found = self.read_disk_inode(|dir_inode| self.find_dirent(name, dir_inode));
+        let (index, inode_id) = match found {
+            Some(found) => found,
+            None => return false,
+        };
+        let now = fs.now();
+        self.modify_disk_inode(|dir_inode| {
+            dir_inode.write_at(
+                index * DIRENT_SZ,
+                DirEntry::empty().as_bytes(),
+                &self.block_device,
+            );
+            dir_inode.touch_modified(now);
+        });
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let nlink = get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(block_offset, |disk_inode: &mut DiskInode| {
+                disk_inode.nlink -= 1;
+                disk_inode.ctime = now;
+                disk_inode.nlink
+            });
+        let key = (
+            Arc::as_ptr(&self.fs) as usize,
+            block_id as usize,
+            block_offset,
+        );
+        if nlink == 0 && !LIVE_INODES.lock().orphan(key) {
+            free_inode(&mut fs, inode_id, &self.block_device);
+        }
+        block_cache_sync_all();
+        true
+    }
+
+    /// Whether a directory has no entries left.
+    pub fn is_empty_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+            (0..file_count).all(|i| {
+                let mut dirent = DirEntry::empty();
+                disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device);
+                dirent.name().is_empty()
+            })
+        })
+    }
+
+    pub fn is_dir(&self) -> bool {
         let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    pub fn is_fifo(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_fifo())
+    }
+
+    /// Directory entries naming this inode.
+    pub fn nlink(&self) -> u32 {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.nlink as u32)
+    }
+
+    pub fn size(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+
+    /// Timestamps as (atime, mtime, ctime), nanoseconds since the epoch.
+    pub fn times(&self) -> (u64, u64, u64) {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            (
+                disk_inode.atime.to_ns(),
+                disk_inode.mtime.to_ns(),
+                disk_inode.ctime.to_ns(),
+            )
+        })
+    }
+
+    /// Set atime and mtime where given, ctime always becomes now.
+    pub fn set_times(&self, atime: Option<u64>, mtime: Option<u64>) {
+        let fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            if let Some(atime) = atime {
+                disk_inode.atime = FileTime::from_ns(atime);
+            }
+            if let Some(mtime) = mtime {
+                disk_inode.mtime = FileTime::from_ns(mtime);
+            }
+            disk_inode.ctime = fs.now();
+        });
+        block_cache_sync_all();
+    }
+
+    /// Reads only dirty the inode block when relatime asks for it, and
+    /// leave it to the next sync.
+    fn touch_accessed(&self, now: FileTime) {
+        if self.read_disk_inode(|disk_inode| disk_inode.needs_atime_update(now)) {
+            self.modify_disk_inode(|disk_inode| disk_inode.atime = now);
+        }
+    }
+
+    /// A read served from elsewhere than the disk, for the atime.
+    pub fn accessed(&self) {
+        let fs = self.fs.lock();
+        self.touch_accessed(fs.now());
+    }
+
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let fs = self.fs.lock();
+        self.touch_accessed(fs.now());
         self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
     }
 
+    /// Read as `read_at` does, for a caller keeping its own copy: the
+    /// blocks are not cached on the way.
+    pub fn read_at_direct(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let fs = self.fs.lock();
+        self.touch_accessed(fs.now());
+        self.read_disk_inode(|disk_inode| {
+            disk_inode.read_at_direct(offset, buf, &self.block_device)
+        })
+    }
+
+    /// Have the device start reading `[offset, offset + len)` of the file,
+    /// a sequential reader will want it next.
+    pub fn read_ahead(&self, offset: usize, len: usize) {
+        let _fs = self.fs.lock();
+        let block_ids = self
+            .read_disk_inode(|disk_inode| disk_inode.block_ids(offset, len, &self.block_device));
+        if !block_ids.is_empty() {
+            self.block_device.prefetch(&block_ids);
+        }
+    }
+
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
         let mut fs = self.fs.lock();
         let size = self.modify_disk_inode(|disk_inode| {
             self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
+            disk_inode.touch_modified(fs.now());
             disk_inode.write_at(offset, buf, &self.block_device)
         });
         block_cache_sync_all();
@@ -180,7 +447,33 @@ impl Inode {
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
+            disk_inode.touch_modified(fs.now());
         });
         block_cache_sync_all();
     }
 }
+
+impl Drop for Inode {
+    fn drop(&mut self) {
+        if LIVE_INODES.lock().put(self.identity()) {
+            let mut fs = self.fs.lock();
+            let inode_id = self.inode_id(&fs);
+            free_inode(&mut fs, inode_id, &self.block_device);
+            block_cache_sync_all();
+        }
+    }
+}
+
+/// Give the data and the inode `inode_id` back to the file system.
+fn free_inode(fs: &mut EasyFileSystem, inode_id: u32, block_device: &Arc<dyn BlockDevice>) {
+    let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+    let data_blocks = get_block_cache(block_id as usize, Arc::clone(block_device))
+        .lock()
+        .modify(block_offset, |disk_inode: &mut DiskInode| {
+            disk_inode.clear_size(block_device)
+        });
+    for data_block in data_blocks.into_iter() {
+        fs.dealloc_data(data_block);
+    }
+    fs.dealloc_inode(inode_id);
+}
@@ -0,0 +1,147 @@
+//! The build configuration of the kernel, for its build script.
+//!
+//! A config file is written like the `.config` of linux, one option a line:
+//!
+//! ```text
+//! CONFIG_BOARD="qemu"
+//! CONFIG_MAX_HARTS=8
+//! CONFIG_KERNEL_HEAP_SIZE=0x100_0000
+//! CONFIG_KASAN=y
+//! # CONFIG_UNIFIED is not set
+//! ```
+//!
+//! Options are strings, numbers in decimal or hex, or y and n. The same
+//! file is included by the Makefile, which turns the bool options of cargo
+//! features into `--features`.
+
+use std::fmt::Write;
+
+const PREFIX: &str = "CONFIG_";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Bool(bool),
+    Int(usize),
+    Str(String),
+}
+
+impl Value {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        match text {
+            "y" => return Ok(Self::Bool(true)),
+            "n" => return Ok(Self::Bool(false)),
+            _ => {}
+        }
+        if let Some(string) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+            return Ok(Self::Str(string.to_string()));
+        }
+        let digits = text.replace('_', "");
+        let int = match digits.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => digits.parse(),
+        };
+        int.map(Self::Int)
+            .map_err(|_| format!("`{}` is no string, number or y/n", text))
+    }
+}
+
+/// The options in the order of the file, later lines overriding earlier.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    options: Vec<(String, Value)>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            if let Some(name) = line
+                .strip_prefix("# ")
+                .and_then(|comment| comment.strip_suffix(" is not set"))
+            {
+                let name = name.strip_prefix(PREFIX).ok_or_else(|| error(name.to_string()))?;
+                config.set(name, Value::Bool(false));
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("`{}` is no option", line)))?;
+            let name = name
+                .trim()
+                .strip_prefix(PREFIX)
+                .ok_or_else(|| error(format!("`{}` does not start with {}", name, PREFIX)))?;
+            config.set(name, Value::parse(value).map_err(error)?);
+        }
+        Ok(config)
+    }
+
+    /// `name` without the CONFIG_ prefix.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.options
+            .iter()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        match self.options.iter_mut().find(|(option, _)| option == name) {
+            Some((_, old)) => *old = value,
+            None => self.options.push((name.to_string(), value)),
+        }
+    }
+
+    /// Rust source with a `pub const` for every option, named without the
+    /// prefix, and `CONFIG_TEXT` holding [`Config::to_text`].
+    pub fn to_rust(&self) -> String {
+        let mut source = String::from("// generated from the kernel config by build.rs\n");
+        for (name, value) in self.options.iter() {
+            let _ = match value {
+                Value::Bool(bool) => writeln!(source, "pub const {}: bool = {};", name, bool),
+                Value::Int(int) => writeln!(source, "pub const {}: usize = {};", name, int),
+                Value::Str(string) => writeln!(source, "pub const {}: &str = {:?};", name, string),
+            };
+        }
+        let _ = writeln!(source, "pub const CONFIG_TEXT: &str = {:?};", self.to_text());
+        source
+    }
+
+    /// The options in config file syntax.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, value) in self.options.iter() {
+            let _ = match value {
+                Value::Bool(true) => writeln!(text, "{}{}=y", PREFIX, name),
+                Value::Bool(false) => writeln!(text, "# {}{} is not set", PREFIX, name),
+                Value::Int(int) => writeln!(text, "{}{}={}", PREFIX, name, int),
+                Value::Str(string) => writeln!(text, "{}{}=\"{}\"", PREFIX, name, string),
+            };
+        }
+        text
+    }
+}
+
+#[test]
+fn parse_test() {
+    let config = Config::parse(
+        "# comment\nCONFIG_BOARD=\"qemu\"\nCONFIG_HEAP=0x100_0000\nCONFIG_HZ=100\n\
+         CONFIG_KASAN=y\n# CONFIG_UNIFIED is not set\nCONFIG_HZ=250\n",
+    )
+    .unwrap();
+    assert_eq!(config.get("BOARD"), Some(&Value::Str("qemu".into())));
+    assert_eq!(config.get("HEAP"), Some(&Value::Int(0x100_0000)));
+    assert_eq!(config.get("HZ"), Some(&Value::Int(250)));
+    assert_eq!(config.get("KASAN"), Some(&Value::Bool(true)));
+    assert_eq!(config.get("UNIFIED"), Some(&Value::Bool(false)));
+    assert_eq!(
+        Config::parse(&config.to_text()).unwrap().to_text(),
+        config.to_text()
+    );
+    assert!(Config::parse("HZ=100\n").is_err());
+    assert!(Config::parse("CONFIG_HZ=fast\n").is_err());
+}
@@ -1,6 +1,184 @@
+use kconfig::{Config, Value};
+use std::path::Path;
+
 static TARGET_PATH: &str = "../user/target/riscv64gc-unknown-none-elf/release/";
 
+/// Subsystems built as cargo features, each with a bool option of its name
+/// in upper case.
+const FEATURES: [&str; 7] = [
+    "hypervisor",
+    "unified",
+    "kasan",
+    "frame_debug",
+    "rc_audit",
+    "ktest",
+    "sdcard",
+];
+/// Options src/config.rs must get, with their kind.
+const NUMBERS: [&str; 11] = [
+    "CLOCK_FREQ",
+    "MEMORY_END",
+    "MAX_HARTS",
+    "USER_STACK_SIZE",
+    "KERNEL_STACK_SIZE",
+    "KERNEL_HEAP_SIZE",
+    "PAGE_CACHE_PAGES",
+    "READAHEAD_BLOCKS",
+    "HZ",
+    "NUM_VTS",
+    "TRACE_RING_SIZE",
+];
+const STRINGS: [&str; 2] = ["BOARD", "MMIO_RANGES"];
+
+fn load_config() -> Config {
+    let path = std::env::var("KCONFIG").unwrap_or_else(|_| String::from("defconfig"));
+    println!("cargo:rerun-if-env-changed=KCONFIG");
+    println!("cargo:rerun-if-changed={}", path);
+    let text = std::fs::read_to_string(&path)
+        .unwrap_or_else(|error| panic!("cannot read the config {}: {}", path, error));
+    let mut config = Config::parse(&text).unwrap_or_else(|error| panic!("{}: {}", path, error));
+    // the features decide, the config only tells the Makefile which to pass
+    for feature in FEATURES {
+        let name = feature.to_uppercase();
+        let enabled = std::env::var_os(format!("CARGO_FEATURE_{}", name)).is_some();
+        if config.get(&name) == Some(&Value::Bool(true)) && !enabled {
+            println!(
+                "cargo:warning=CONFIG_{}=y in {} but the {} feature is off, build with make",
+                name, path, feature
+            );
+        }
+        config.set(&name, Value::Bool(enabled));
+    }
+    check_config(&config, &path);
+    config
+}
+
+fn check_config(config: &Config, path: &str) {
+    for name in NUMBERS {
+        match config.get(name) {
+            Some(Value::Int(int)) if *int > 0 => {}
+            _ => panic!("{}: CONFIG_{} must be a number above 0", path, name),
+        }
+    }
+    for name in STRINGS {
+        if !matches!(config.get(name), Some(Value::Str(_))) {
+            panic!("{}: CONFIG_{} must be a string", path, name);
+        }
+    }
+    if let Some(Value::Str(board)) = config.get("BOARD") {
+        if !Path::new(&format!("src/boards/{}.rs", board)).exists() {
+            panic!("{}: there is no board {}", path, board);
+        }
+    }
+}
+
+/// `MMIO`, the ranges of CONFIG_MMIO_RANGES the kernel maps for devices,
+/// given as `base:size` separated by commas.
+fn mmio_ranges(config: &Config) -> String {
+    let Some(Value::Str(ranges)) = config.get("MMIO_RANGES") else {
+        unreachable!()
+    };
+    let number = |text: &str| {
+        let digits = text.trim().replace('_', "");
+        match digits.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => digits.parse(),
+        }
+        .unwrap_or_else(|_| panic!("CONFIG_MMIO_RANGES: `{}` is no number", text))
+    };
+    let mut source = String::from("pub const MMIO: &[(usize, usize)] = &[\n");
+    for range in ranges.split(',').filter(|range| !range.trim().is_empty()) {
+        let (base, size) = range
+            .split_once(':')
+            .unwrap_or_else(|| panic!("CONFIG_MMIO_RANGES: `{}` is no base:size", range));
+        source += &format!("    ({:#x}, {:#x}),\n", number(base), number(size));
+    }
+    source + "];\n"
+}
+
+/// src/boards/<CONFIG_BOARD>.rs, included as the board module by main.rs.
+fn board_source(config: &Config) -> String {
+    let Some(Value::Str(board)) = config.get("BOARD") else {
+        unreachable!()
+    };
+    let path = Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap())
+        .join("src/boards")
+        .join(format!("{}.rs", board));
+    format!("include!({:?});\n", path)
+}
+
+/// Without the hash rustc appends to every symbol.
+fn strip_hash(name: &str) -> &str {
+    match name.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            path
+        }
+        _ => name,
+    }
+}
+
+/// The function symbols of the last link as assembly for src/ksyms.rs:
+/// their number, the addresses in order, where each name starts in the
+/// names and where the last one ends, then the names. `KSYMS` is the output
+/// of `nm --numeric-sort --demangle` the Makefile keeps, the table stays
+/// empty without it.
+fn symbol_table() -> String {
+    let mut symbols: Vec<(u64, String)> = Vec::new();
+    println!("cargo:rerun-if-env-changed=KSYMS");
+    if let Ok(path) = std::env::var("KSYMS") {
+        println!("cargo:rerun-if-changed={}", path);
+        let text = std::fs::read_to_string(&path).unwrap_or_default();
+        for line in text.lines() {
+            // `0000000080200000 T os::rust_main::h0123456789abcdef`
+            let mut fields = line.splitn(3, ' ');
+            let (addr, name) = match (fields.next(), fields.next(), fields.next()) {
+                // mapping symbols and local labels are no functions
+                (Some(addr), Some("T" | "t"), Some(name))
+                    if !name.starts_with('$') && !name.starts_with(".L") =>
+                {
+                    (addr, name)
+                }
+                _ => continue,
+            };
+            let addr = match u64::from_str_radix(addr, 16) {
+                Ok(addr) => addr,
+                Err(_) => continue,
+            };
+            // the first of several names for an address wins
+            if symbols.last().map_or(true, |(last, _)| *last < addr) {
+                symbols.push((addr, String::from(strip_hash(name))));
+            }
+        }
+    }
+    let mut asm =
+        String::from("    .section .ksyms, \"a\"\n    .balign 8\n    .globl ksyms\nksyms:\n");
+    asm += &format!("    .quad {}\n", symbols.len());
+    for (addr, _) in symbols.iter() {
+        asm += &format!("    .quad {:#x}\n", addr);
+    }
+    let mut offset = 0;
+    for (_, name) in symbols.iter() {
+        asm += &format!("    .4byte {}\n", offset);
+        offset += name.len();
+    }
+    asm += &format!("    .4byte {}\n", offset);
+    for (_, name) in symbols.iter() {
+        let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+        asm += &format!("    .ascii \"{}\"\n", escaped);
+    }
+    asm
+}
+
 fn main() {
     println!("cargo:rerun-if-changed=../user/src/");
     println!("cargo:rerun-if-changed={}", TARGET_PATH);
+    let config = load_config();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(
+        Path::new(&out_dir).join("kconfig.rs"),
+        config.to_rust() + &mmio_ranges(&config),
+    )
+    .unwrap();
+    std::fs::write(Path::new(&out_dir).join("board.rs"), board_source(&config)).unwrap();
+    std::fs::write(Path::new(&out_dir).join("ksyms.S"), symbol_table()).unwrap();
 }
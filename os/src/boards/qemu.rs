@@ -1,18 +1,25 @@
-pub const CLOCK_FREQ: usize = 12500000;
-pub const MEMORY_END: usize = 0x8800_0000;
-
-pub const MMIO: &[(usize, usize)] = &[
-    (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
-    (0x2000000, 0x10000),     // core local interrupter (CLINT)
-    (0xc000000, 0x210000),    // VIRT_PLIC in virt machine
-    (0x10000000, 0x9000),     // VIRT_UART0 with GPU  in virt machine
-];
-
 pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
 pub type CharDeviceImpl = crate::drivers::chardev::NS16550a<VIRT_UART>;
 
 pub const VIRT_PLIC: usize = 0xC00_0000;
 pub const VIRT_UART: usize = 0x1000_0000;
+/// virtio-mmio transports, QEMU fills them from the last one down
+pub const VIRTIO_MMIO_SLOTS: &[usize] = &[
+    0x1000_1000,
+    0x1000_2000,
+    0x1000_3000,
+    0x1000_4000,
+    0x1000_5000,
+    0x1000_6000,
+    0x1000_7000,
+    0x1000_8000,
+];
+pub const VIRT_PCIE_ECAM: usize = 0x3000_0000;
+pub const PCI_BUSES: u8 = 1;
+/// the part of the 32-bit PCI memory window mapped above
+pub const PCI_MMIO_WINDOW: (usize, usize) = (0x4000_0000, 0x4100_0000);
+/// goldfish RTC, nanoseconds since the epoch
+pub const VIRT_RTC: usize = 0x10_1000;
 #[allow(unused)]
 pub const VIRTGPU_XRES: u32 = 1280;
 #[allow(unused)]
@@ -21,7 +28,27 @@ pub const VIRTGPU_YRES: u32 = 800;
 use crate::drivers::block::BLOCK_DEVICE;
 use crate::drivers::chardev::{CharDevice, UART};
 use crate::drivers::plic::{IntrTargetPriority, PLIC};
-use crate::drivers::{KEYBOARD_DEVICE, MOUSE_DEVICE};
+use crate::drivers::sound::SOUND_DEVICE;
+use crate::drivers::{KEYBOARD_DEVICE, MOUSE_DEVICE, TABLET_DEVICE, TABLET_SLOT};
+
+/// Wall clock of the RTC in nanoseconds, reading the low half latches the
+/// high one.
+pub fn rtc_time_ns() -> u64 {
+    unsafe {
+        let low = (VIRT_RTC as *const u32).read_volatile() as u64;
+        let high = ((VIRT_RTC + 4) as *const u32).read_volatile() as u64;
+        high << 32 | low
+    }
+}
+
+/// PLIC source of the virtio-mmio slot at `base`, the first slot has 1.
+fn virtio_mmio_irq(base: usize) -> usize {
+    (base - VIRTIO_MMIO_SLOTS[0]) / 0x1000 + 1
+}
+
+fn sound_slot() -> Option<usize> {
+    SOUND_DEVICE.as_ref().and_then(|sound| sound.mmio_slot())
+}
 
 pub fn device_init() {
     use riscv::register::sie;
@@ -31,8 +58,11 @@ pub fn device_init() {
     let machine = IntrTargetPriority::Machine;
     plic.set_threshold(hart_id, supervisor, 0);
     plic.set_threshold(hart_id, machine, 1);
-    //irq nums: 5 keyboard, 6 mouse, 8 block, 10 uart
-    for intr_src_id in [5usize, 6, 8, 10] {
+    //irq nums: 5 keyboard, 6 mouse, 8 block, 10 uart, then a tablet and a
+    //sound device on virtio-mmio if any
+    let tablet = TABLET_SLOT.map(virtio_mmio_irq);
+    let sound = sound_slot().map(virtio_mmio_irq);
+    for intr_src_id in [5usize, 6, 8, 10].into_iter().chain(tablet).chain(sound) {
         plic.enable(hart_id, supervisor, intr_src_id);
         plic.set_priority(intr_src_id, 1);
     }
@@ -41,14 +71,32 @@ pub fn device_init() {
     }
 }
 
+/// Mask every device interrupt, drivers poll from here on.
+pub fn device_quiesce() {
+    use riscv::register::sie;
+    let mut plic = unsafe { PLIC::new(VIRT_PLIC) };
+    plic.set_threshold(0, IntrTargetPriority::Supervisor, 7);
+    unsafe {
+        sie::clear_sext();
+        sie::clear_stimer();
+    }
+}
+
 pub fn irq_handler() {
     let mut plic = unsafe { PLIC::new(VIRT_PLIC) };
     let intr_src_id = plic.claim(0, IntrTargetPriority::Supervisor);
+    crate::random::add_interrupt_entropy(intr_src_id);
     match intr_src_id {
         5 => KEYBOARD_DEVICE.handle_irq(),
         6 => MOUSE_DEVICE.handle_irq(),
         8 => BLOCK_DEVICE.handle_irq(),
         10 => UART.handle_irq(),
+        irq if TABLET_SLOT.map(virtio_mmio_irq) == Some(irq) => {
+            TABLET_DEVICE.as_ref().unwrap().handle_irq()
+        }
+        irq if sound_slot().map(virtio_mmio_irq) == Some(irq) => {
+            SOUND_DEVICE.as_ref().unwrap().handle_irq()
+        }
         _ => panic!("unsupported IRQ {}", intr_src_id),
     }
     plic.complete(0, IntrTargetPriority::Supervisor, intr_src_id);
@@ -1,12 +1,26 @@
+/// Generated by build.rs from the config file, defconfig unless KCONFIG
+/// names another: stack and heap sizes, MAX_HARTS, HZ, the clock and
+/// memory of the board, the MMIO ranges it maps and the like, and whether
+/// each subsystem built as a cargo feature is on.
 #[allow(unused)]
+mod kconfig {
+    include!(concat!(env!("OUT_DIR"), "/kconfig.rs"));
+}
+pub use kconfig::*;
 
-pub const USER_STACK_SIZE: usize = 4096 * 2;
-pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
-pub const KERNEL_HEAP_SIZE: usize = 0x100_0000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
-
-pub use crate::board::{CLOCK_FREQ, MEMORY_END, MMIO};
+/// brk grows the heap from here up to MMAP_BASE, above the physical memory
+/// a unified space maps for the kernel
+pub const BRK_BASE: usize = 0x1_0000_0000;
+/// mmap places mappings at the first free range from here up
+pub const MMAP_BASE: usize = 0x2_0000_0000;
+/// kernel stacks grow down from here
+#[cfg(not(feature = "unified"))]
+pub const KERNEL_STACK_TOP: usize = TRAMPOLINE;
+/// kernel stacks get a gigabyte of their own, shared by every address space
+#[cfg(feature = "unified")]
+pub const KERNEL_STACK_TOP: usize = 0xffff_ffff_c000_0000;
@@ -1,18 +1,33 @@
 use crate::drivers::chardev::CharDevice;
 use crate::drivers::chardev::UART;
+use crate::drivers::console::log_port;
+use crate::drivers::gpu::fbcon::FB_CONSOLE;
 use core::fmt::{self, Write};
 
 struct Stdout;
 
 impl Write for Stdout {
+    /// Kernel messages go to the log port of the virtio-console once there
+    /// is one, the UART is left to the shell.
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            UART.write(c as u8);
+        if let Some((device, port)) = log_port() {
+            device.write(port, s.as_bytes());
+            return Ok(());
         }
+        write_terminal(s.as_bytes());
         Ok(())
     }
 }
 
+/// Output for the terminal on the UART, shown on the framebuffer console
+/// as well.
+pub fn write_terminal(bytes: &[u8]) {
+    for &byte in bytes {
+        UART.write(byte);
+    }
+    FB_CONSOLE.write(bytes);
+}
+
 pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
@@ -1,14 +1,47 @@
+mod partition;
+// for the boards with an SD card on SPI, none in the tree yet
+#[cfg(feature = "sdcard")]
+#[allow(unused)]
+mod sdcard;
 mod virtio_blk;
 
+pub use partition::{scan_partitions, Partition, PartitionKind};
+#[cfg(feature = "sdcard")]
+#[allow(unused)]
+pub use sdcard::{SDCard, SDCardError};
 pub use virtio_blk::VirtIOBlock;
 
 use crate::board::BlockDeviceImpl;
 use alloc::sync::Arc;
-use easy_fs::BlockDevice;
+use alloc::vec::Vec;
+use easy_fs::{BlockDevice, EasyFileSystem};
 use lazy_static::*;
 
 lazy_static! {
+    /// the whole disk
     pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+    pub static ref PARTITIONS: Vec<Arc<Partition>> = scan_partitions(&BLOCK_DEVICE);
+    /// The first partition holding an easy-fs, or the whole disk if none
+    /// does, so that an image without a partition table still boots.
+    pub static ref ROOT_DEVICE: Arc<dyn BlockDevice> = PARTITIONS
+        .iter()
+        .find(|&partition| EasyFileSystem::probe(&**partition))
+        .map_or_else(
+            || BLOCK_DEVICE.clone(),
+            |partition| partition.clone() as Arc<dyn BlockDevice>,
+        );
+}
+
+pub fn list_partitions() {
+    for (index, partition) in PARTITIONS.iter().enumerate() {
+        println!(
+            "[kernel] partition {}: blocks {}..{} {:?}",
+            index + 1,
+            partition.start(),
+            partition.start() + partition.block_count(),
+            partition.kind
+        );
+    }
 }
 
 #[allow(unused)]
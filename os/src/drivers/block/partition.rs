@@ -0,0 +1,156 @@
+//! MBR and GPT partition tables. Every partition found becomes a
+//! BlockDevice of its own whose blocks count from its first sector.
+
+use super::BlockDevice;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use easy_fs::BLOCK_SZ;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_TABLE_OFFSET: usize = 0x1be;
+const MBR_ENTRY_SIZE: usize = 16;
+/// type of the single MBR entry covering a GPT disk
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_LBA: usize = 1;
+const GPT_NAME_LEN: usize = 36;
+
+/// What the partition table says about one partition.
+#[derive(Clone, Debug)]
+pub enum PartitionKind {
+    /// system id byte of an MBR entry
+    Mbr(u8),
+    /// type GUID and UTF-16 name of a GPT entry
+    Gpt([u8; 16], String),
+}
+
+pub struct Partition {
+    device: Arc<dyn BlockDevice>,
+    /// first block on the device
+    start: usize,
+    /// number of blocks
+    len: usize,
+    pub kind: PartitionKind,
+}
+
+impl Partition {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    pub fn block_count(&self) -> usize {
+        self.len
+    }
+}
+
+impl BlockDevice for Partition {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        assert!(block_id < self.len, "block {} past the partition", block_id);
+        self.device.read_block(self.start + block_id, buf);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert!(block_id < self.len, "block {} past the partition", block_id);
+        self.device.write_block(self.start + block_id, buf);
+    }
+    fn handle_irq(&self) {
+        self.device.handle_irq();
+    }
+    fn prefetch(&self, block_ids: &[usize]) {
+        let block_ids: Vec<usize> = block_ids
+            .iter()
+            .filter(|&&block_id| block_id < self.len)
+            .map(|block_id| self.start + block_id)
+            .collect();
+        self.device.prefetch(&block_ids);
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Partitions of `device` in table order, empty if it has no partition
+/// table. Logical partitions inside an extended one are not followed.
+pub fn scan_partitions(device: &Arc<dyn BlockDevice>) -> Vec<Arc<Partition>> {
+    let mut mbr = vec![0u8; BLOCK_SZ];
+    device.read_block(0, &mut mbr);
+    if mbr[510..512] != MBR_SIGNATURE {
+        return Vec::new();
+    }
+    let entries: Vec<&[u8]> = mbr[MBR_TABLE_OFFSET..MBR_TABLE_OFFSET + 4 * MBR_ENTRY_SIZE]
+        .chunks(MBR_ENTRY_SIZE)
+        .collect();
+    if entries
+        .iter()
+        .any(|entry| entry[4] == MBR_TYPE_GPT_PROTECTIVE)
+    {
+        return scan_gpt(device);
+    }
+    entries
+        .iter()
+        .filter(|entry| entry[4] != 0 && !MBR_TYPE_EXTENDED.contains(&entry[4]))
+        .map(|entry| {
+            Arc::new(Partition {
+                device: device.clone(),
+                start: u32_at(entry, 8) as usize,
+                len: u32_at(entry, 12) as usize,
+                kind: PartitionKind::Mbr(entry[4]),
+            })
+        })
+        .collect()
+}
+
+fn scan_gpt(device: &Arc<dyn BlockDevice>) -> Vec<Arc<Partition>> {
+    let mut header = vec![0u8; BLOCK_SZ];
+    device.read_block(GPT_HEADER_LBA, &mut header);
+    if &header[..8] != GPT_SIGNATURE {
+        return Vec::new();
+    }
+    let entries_lba = u64_at(&header, 72) as usize;
+    let entry_count = u32_at(&header, 80) as usize;
+    let entry_size = u32_at(&header, 84) as usize;
+    if !(128..=BLOCK_SZ).contains(&entry_size) || BLOCK_SZ % entry_size != 0 {
+        return Vec::new();
+    }
+    let per_block = BLOCK_SZ / entry_size;
+    let mut partitions = Vec::new();
+    let mut block = vec![0u8; BLOCK_SZ];
+    for index in 0..entry_count {
+        if index % per_block == 0 {
+            device.read_block(entries_lba + index / per_block, &mut block);
+        }
+        let entry = &block[index % per_block * entry_size..][..entry_size];
+        let type_guid: [u8; 16] = entry[..16].try_into().unwrap();
+        // an all-zero type marks an unused entry
+        if type_guid == [0; 16] {
+            continue;
+        }
+        let first = u64_at(entry, 32) as usize;
+        let last = u64_at(entry, 40) as usize;
+        if last < first {
+            continue;
+        }
+        let name = char::decode_utf16(
+            entry[56..56 + 2 * GPT_NAME_LEN]
+                .chunks(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .take_while(|&unit| unit != 0),
+        )
+        .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+        partitions.push(Arc::new(Partition {
+            device: device.clone(),
+            start: first,
+            len: last - first + 1,
+            kind: PartitionKind::Gpt(type_guid, name),
+        }));
+    }
+    partitions
+}
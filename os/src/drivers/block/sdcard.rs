@@ -0,0 +1,300 @@
+//! SD cards in SPI mode.
+//!
+//! Ref: SD Specifications Part 1, Physical Layer Simplified Specification,
+//! chapter 7 "SPI Mode".
+
+use super::BlockDevice;
+use crate::drivers::bus::spi::SpiBus;
+use crate::sync::UPIntrFreeCell;
+use crate::timer::get_time_ms;
+use crate::trace::{trace, TracePoint};
+
+/// cards must be identified at 400kHz or below
+const INIT_CLOCK: usize = 400_000;
+const TRANSFER_CLOCK: usize = 20_000_000;
+
+const CMD0_GO_IDLE_STATE: u8 = 0;
+const CMD8_SEND_IF_COND: u8 = 8;
+const CMD16_SET_BLOCKLEN: u8 = 16;
+const CMD17_READ_SINGLE_BLOCK: u8 = 17;
+const CMD24_WRITE_BLOCK: u8 = 24;
+const CMD55_APP_CMD: u8 = 55;
+const CMD58_READ_OCR: u8 = 58;
+const ACMD41_SD_SEND_OP_COND: u8 = 41;
+
+const R1_IDLE: u8 = 0x01;
+const R1_ILLEGAL_COMMAND: u8 = 0x04;
+/// 2.7-3.6V and the check pattern of CMD8
+const IF_COND: u32 = 0x1aa;
+/// host supports high capacity, in the argument of ACMD41
+const HCS: u32 = 1 << 30;
+/// card is high capacity and addressed in blocks, in the OCR
+const OCR_CCS: u32 = 1 << 30;
+
+const TOKEN_START_BLOCK: u8 = 0xfe;
+const DATA_RESPONSE_MASK: u8 = 0x1f;
+const DATA_ACCEPTED: u8 = 0x05;
+
+const BLOCK_SIZE: usize = 512;
+const INIT_TIMEOUT_MS: usize = 1000;
+const READ_TIMEOUT_MS: usize = 100;
+const WRITE_TIMEOUT_MS: usize = 500;
+/// attempts at a block before the card is given up on
+const RETRIES: usize = 3;
+
+#[derive(Debug)]
+pub enum SDCardError {
+    /// the card answered with these R1 error bits
+    Command(u8, u8),
+    /// no answer in time to this command
+    Timeout(u8),
+    /// CMD8 did not echo the check pattern
+    BadVoltage,
+    /// the card rejected a written block with this data response
+    WriteRejected(u8),
+}
+
+/// CRC7 of a command frame, shifted into place with the end bit set.
+fn crc7(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        for bit in (0..8).rev() {
+            let feedback = (crc >> 6) ^ (byte >> bit) & 1;
+            crc = (crc << 1) & 0x7f;
+            if feedback != 0 {
+                crc ^= 0x09;
+            }
+        }
+    }
+    crc << 1 | 1
+}
+
+struct SDCardInner<S: SpiBus> {
+    spi: S,
+    /// SDHC and SDXC cards take block numbers instead of byte offsets
+    block_addressing: bool,
+}
+
+impl<S: SpiBus> SDCardInner<S> {
+    /// Send command `index` and return its R1, leaving the card selected
+    /// for the rest of the response.
+    fn command(&mut self, index: u8, arg: u32) -> Result<u8, SDCardError> {
+        self.spi.deselect();
+        self.spi.transfer(0xff);
+        self.spi.select();
+        // wait until the card is no longer busy from a previous write
+        self.wait_for(WRITE_TIMEOUT_MS, |byte| byte == 0xff)
+            .map_err(|_| SDCardError::Timeout(index))?;
+        let mut frame = [0u8; 6];
+        frame[0] = 0x40 | index;
+        frame[1..5].copy_from_slice(&arg.to_be_bytes());
+        frame[5] = crc7(&frame[..5]);
+        for byte in frame {
+            self.spi.transfer(byte);
+        }
+        // R1 comes within 8 bytes, its top bit is always 0
+        for _ in 0..8 {
+            let r1 = self.spi.transfer(0xff);
+            if r1 & 0x80 == 0 {
+                return Ok(r1);
+            }
+        }
+        Err(SDCardError::Timeout(index))
+    }
+
+    fn end_command(&mut self) {
+        self.spi.deselect();
+        self.spi.transfer(0xff);
+    }
+
+    /// Read bytes until `done` accepts one, which is returned.
+    fn wait_for(&mut self, timeout_ms: usize, done: impl Fn(u8) -> bool) -> Result<u8, ()> {
+        let deadline = get_time_ms() + timeout_ms;
+        loop {
+            let byte = self.spi.transfer(0xff);
+            if done(byte) {
+                return Ok(byte);
+            }
+            if get_time_ms() > deadline {
+                return Err(());
+            }
+        }
+    }
+
+    fn read_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        for byte in bytes.iter_mut() {
+            *byte = self.spi.transfer(0xff);
+        }
+        u32::from_be_bytes(bytes)
+    }
+
+    fn init(&mut self) -> Result<(), SDCardError> {
+        self.spi.set_clock(INIT_CLOCK);
+        // at least 74 clocks with the card deselected put it in SPI mode
+        self.spi.deselect();
+        for _ in 0..10 {
+            self.spi.transfer(0xff);
+        }
+        let r1 = self.command(CMD0_GO_IDLE_STATE, 0)?;
+        self.end_command();
+        if r1 != R1_IDLE {
+            return Err(SDCardError::Command(CMD0_GO_IDLE_STATE, r1));
+        }
+        // version 1 cards know no CMD8 and no high capacity
+        let r1 = self.command(CMD8_SEND_IF_COND, IF_COND)?;
+        let version2 = r1 & R1_ILLEGAL_COMMAND == 0;
+        if version2 && self.read_u32() & 0xfff != IF_COND {
+            self.end_command();
+            return Err(SDCardError::BadVoltage);
+        }
+        self.end_command();
+        let deadline = get_time_ms() + INIT_TIMEOUT_MS;
+        loop {
+            self.command(CMD55_APP_CMD, 0)?;
+            self.end_command();
+            let r1 = self.command(ACMD41_SD_SEND_OP_COND, if version2 { HCS } else { 0 })?;
+            self.end_command();
+            match r1 {
+                0 => break,
+                R1_IDLE if get_time_ms() <= deadline => continue,
+                R1_IDLE => return Err(SDCardError::Timeout(ACMD41_SD_SEND_OP_COND)),
+                r1 => return Err(SDCardError::Command(ACMD41_SD_SEND_OP_COND, r1)),
+            }
+        }
+        if version2 {
+            let r1 = self.command(CMD58_READ_OCR, 0)?;
+            let ocr = self.read_u32();
+            self.end_command();
+            if r1 != 0 {
+                return Err(SDCardError::Command(CMD58_READ_OCR, r1));
+            }
+            self.block_addressing = ocr & OCR_CCS != 0;
+        }
+        if !self.block_addressing {
+            let r1 = self.command(CMD16_SET_BLOCKLEN, BLOCK_SIZE as u32)?;
+            self.end_command();
+            if r1 != 0 {
+                return Err(SDCardError::Command(CMD16_SET_BLOCKLEN, r1));
+            }
+        }
+        self.spi.set_clock(TRANSFER_CLOCK);
+        Ok(())
+    }
+
+    fn address(&self, block_id: usize) -> u32 {
+        if self.block_addressing {
+            block_id as u32
+        } else {
+            (block_id * BLOCK_SIZE) as u32
+        }
+    }
+
+    fn read_block(&mut self, block_id: usize, buf: &mut [u8]) -> Result<(), SDCardError> {
+        let result = self.try_read_block(block_id, buf);
+        self.end_command();
+        result
+    }
+
+    fn try_read_block(&mut self, block_id: usize, buf: &mut [u8]) -> Result<(), SDCardError> {
+        let r1 = self.command(CMD17_READ_SINGLE_BLOCK, self.address(block_id))?;
+        if r1 != 0 {
+            return Err(SDCardError::Command(CMD17_READ_SINGLE_BLOCK, r1));
+        }
+        let token = self
+            .wait_for(READ_TIMEOUT_MS, |byte| byte != 0xff)
+            .map_err(|_| SDCardError::Timeout(CMD17_READ_SINGLE_BLOCK))?;
+        if token != TOKEN_START_BLOCK {
+            // an error token instead of the data
+            return Err(SDCardError::Command(CMD17_READ_SINGLE_BLOCK, token));
+        }
+        for byte in buf.iter_mut() {
+            *byte = self.spi.transfer(0xff);
+        }
+        // CRC16, unchecked since CRCs are off in SPI mode
+        self.spi.transfer(0xff);
+        self.spi.transfer(0xff);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block_id: usize, buf: &[u8]) -> Result<(), SDCardError> {
+        let result = self.try_write_block(block_id, buf);
+        self.end_command();
+        result
+    }
+
+    fn try_write_block(&mut self, block_id: usize, buf: &[u8]) -> Result<(), SDCardError> {
+        let r1 = self.command(CMD24_WRITE_BLOCK, self.address(block_id))?;
+        if r1 != 0 {
+            return Err(SDCardError::Command(CMD24_WRITE_BLOCK, r1));
+        }
+        self.spi.transfer(0xff);
+        self.spi.transfer(TOKEN_START_BLOCK);
+        for &byte in buf {
+            self.spi.transfer(byte);
+        }
+        self.spi.transfer(0xff);
+        self.spi.transfer(0xff);
+        let response = self.spi.transfer(0xff) & DATA_RESPONSE_MASK;
+        if response != DATA_ACCEPTED {
+            return Err(SDCardError::WriteRejected(response));
+        }
+        // the card holds the line low while it programs the block
+        self.wait_for(WRITE_TIMEOUT_MS, |byte| byte != 0)
+            .map_err(|_| SDCardError::Timeout(CMD24_WRITE_BLOCK))?;
+        Ok(())
+    }
+}
+
+/// An SD card on an SPI bus, for boards without virtio such as the K210.
+/// Transfers are polled a block at a time.
+pub struct SDCard<S: SpiBus> {
+    inner: UPIntrFreeCell<SDCardInner<S>>,
+}
+
+impl<S: SpiBus> SDCard<S> {
+    pub fn new(spi: S) -> Result<Self, SDCardError> {
+        let mut inner = SDCardInner {
+            spi,
+            block_addressing: false,
+        };
+        inner.init()?;
+        Ok(Self {
+            inner: unsafe { UPIntrFreeCell::new(inner) },
+        })
+    }
+}
+
+impl<S: SpiBus + 'static> BlockDevice for SDCard<S> {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        trace(TracePoint::BlockIo, [block_id, 0]);
+        let mut inner = self.inner.exclusive_access();
+        let mut result = Ok(());
+        for _ in 0..RETRIES {
+            result = inner.read_block(block_id, buf);
+            if result.is_ok() {
+                return;
+            }
+        }
+        panic!(
+            "Error when reading SD card block {}: {:?}",
+            block_id, result
+        );
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        trace(TracePoint::BlockIo, [block_id, 1]);
+        let mut inner = self.inner.exclusive_access();
+        let mut result = Ok(());
+        for _ in 0..RETRIES {
+            result = inner.write_block(block_id, buf);
+            if result.is_ok() {
+                return;
+            }
+        }
+        panic!(
+            "Error when writing SD card block {}: {:?}",
+            block_id, result
+        );
+    }
+    fn handle_irq(&self) {}
+}
@@ -1,87 +1,430 @@
+//! virtio-blk on a [`VirtQueue`] of our own, with a request queue in front
+//! of the device.
+//!
+//! Block requests wait in the queue in block order and go to the device
+//! in one sweep up from where the last one ended. Requests for adjacent
+//! blocks in the same direction become one device request of several
+//! buffers, and everything that fits into the virtqueue goes out with a
+//! single notify. Every request has a ticket its owner sleeps on until the
+//! interrupt of the device reports it finished, so they complete in
+//! whatever order the device finishes them and others run meanwhile. Only
+//! while nothing is scheduled, at boot and on the way down, the used ring
+//! is polled.
+//!
+//! Ref: Virtual I/O Device (VIRTIO) Version 1.1, 5.2 "Block Device".
+
 use super::BlockDevice;
+use crate::config::READAHEAD_BLOCKS;
 use crate::drivers::bus::virtio::VirtioHal;
+use crate::drivers::bus::virtio_mmio::VirtioMmio;
+use crate::drivers::bus::virtqueue::VirtQueue;
+use crate::mm::{dma_alloc, DmaBuffer};
 use crate::sync::{Condvar, UPIntrFreeCell};
-use crate::task::schedule;
+use crate::task::{schedule, TaskContext};
+use crate::trace::{trace, TracePoint};
 use crate::DEV_NON_BLOCKING_ACCESS;
-use alloc::collections::BTreeMap;
-use virtio_drivers::{BlkResp, RespStatus, VirtIOBlk, VirtIOHeader};
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use core::mem::size_of;
+use easy_fs::BLOCK_SZ;
+use virtio_drivers::Hal;
 
-#[allow(unused)]
 const VIRTIO0: usize = 0x10008000;
+const VIRTIO_ID_BLOCK: u32 = 2;
+
+const REQUEST_QUEUE: u16 = 0;
+const QUEUE_SIZE: u16 = 64;
+/// Blocks merged into one device request at most, each takes a descriptor
+/// besides those of the header and the status.
+const MAX_MERGE: usize = 16;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+#[repr(C)]
+struct RequestHeader {
+    type_: u32,
+    reserved: u32,
+    /// in units of 512 bytes, which is what a block is
+    sector: u64,
+}
+
+/// A block to transfer, waiting for its turn at the device.
+struct Request {
+    write: bool,
+    /// physical address of the buffer, which stays put until it is done
+    buf: usize,
+}
+
+/// Requests of one ticket each, by block and then ticket, as they go out.
+type RequestKey = (usize, usize);
+
+/// A block read before anyone asked for it.
+struct Prefetched {
+    buf: Box<[u8; BLOCK_SZ]>,
+    ticket: usize,
+    /// written meanwhile, dropped once the device is done with it
+    stale: bool,
+}
+
+struct VirtIOBlockInner {
+    mmio: VirtioMmio,
+    queue: VirtQueue,
+    /// headers and status bytes of the requests at the device, one slot
+    /// each
+    dma: DmaBuffer,
+    free_slots: Vec<usize>,
+    next_ticket: usize,
+    waiting: BTreeMap<RequestKey, Request>,
+    /// requests at the device by the head of their chain, with their slot
+    in_flight: BTreeMap<u16, (usize, Vec<RequestKey>)>,
+    /// blocks at the device, no other request for them goes out meanwhile
+    busy: BTreeSet<usize>,
+    /// whether the requests of finished tickets went well, until their
+    /// owners take it
+    finished: BTreeMap<usize, bool>,
+    /// tasks asleep on tickets, woken when those finish
+    sleepers: BTreeMap<usize, Condvar>,
+    /// where the last request ended, the sweep goes on from there
+    position: usize,
+    /// blocks read ahead of their readers, at most READAHEAD_BLOCKS
+    prefetched: BTreeMap<usize, Prefetched>,
+    /// the blocks read ahead in the order they were asked for
+    prefetch_order: VecDeque<usize>,
+}
 
 pub struct VirtIOBlock {
-    virtio_blk: UPIntrFreeCell<VirtIOBlk<'static, VirtioHal>>,
-    condvars: BTreeMap<u16, Condvar>,
+    inner: UPIntrFreeCell<VirtIOBlockInner>,
+}
+
+impl VirtIOBlockInner {
+    fn header(&self, slot: usize) -> *mut RequestHeader {
+        self.dma.as_ptr::<RequestHeader>().wrapping_add(slot)
+    }
+
+    fn status(&self, slot: usize) -> *mut u8 {
+        (self.dma.paddr() + QUEUE_SIZE as usize * size_of::<RequestHeader>() + slot) as *mut u8
+    }
+
+    /// Queue a transfer of the block at `buf` and return its ticket.
+    fn enqueue(&mut self, block_id: usize, write: bool, buf: usize) -> usize {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        let buf = VirtioHal::virt_to_phys(buf);
+        self.waiting
+            .insert((block_id, ticket), Request { write, buf });
+        ticket
+    }
+
+    /// The first request of the sweep whose block is not at the device.
+    fn next_request(&self) -> Option<RequestKey> {
+        let ready = |key: &&RequestKey| !self.busy.contains(&key.0);
+        self.waiting
+            .keys()
+            .filter(|key| key.0 >= self.position)
+            .find(ready)
+            .or_else(|| self.waiting.keys().find(ready))
+            .copied()
+    }
+
+    /// The request `first` and those for the blocks right after it in the
+    /// same direction.
+    fn merge(&self, first: RequestKey) -> Vec<RequestKey> {
+        let write = self.waiting[&first].write;
+        let mut keys = Vec::from([first]);
+        for (&key, request) in self.waiting.range((first.0 + 1, 0)..) {
+            let last = keys.last().unwrap().0;
+            if keys.len() == MAX_MERGE
+                || key.0 != last + 1
+                || request.write != write
+                || self.busy.contains(&key.0)
+            {
+                break;
+            }
+            keys.push(key);
+        }
+        keys
+    }
+
+    /// Hand the device what fits into the queue and tell it once.
+    fn dispatch(&mut self) {
+        let mut submitted = false;
+        while let Some(first) = self.next_request() {
+            let slot = match self.free_slots.last() {
+                Some(&slot) => slot,
+                None => break,
+            };
+            let keys = self.merge(first);
+            let write = self.waiting[&first].write;
+            unsafe {
+                self.header(slot).write_volatile(RequestHeader {
+                    type_: if write {
+                        VIRTIO_BLK_T_OUT
+                    } else {
+                        VIRTIO_BLK_T_IN
+                    },
+                    reserved: 0,
+                    sector: first.0 as u64,
+                });
+                self.status(slot).write_volatile(0xff);
+            }
+            let header = (self.header(slot) as usize, size_of::<RequestHeader>());
+            let status = (self.status(slot) as usize, 1);
+            let buffers = keys.iter().map(|key| (self.waiting[key].buf, BLOCK_SZ));
+            let head = if write {
+                let readable: Vec<_> = [header].into_iter().chain(buffers).collect();
+                self.queue.add(&readable, &[status])
+            } else {
+                let writable: Vec<_> = buffers.chain([status]).collect();
+                self.queue.add(&[header], &writable)
+            };
+            let head = match head {
+                Some(head) => head,
+                // the queue is full, the next completion makes room
+                None => break,
+            };
+            self.free_slots.pop();
+            for key in keys.iter() {
+                self.waiting.remove(key);
+                self.busy.insert(key.0);
+            }
+            self.position = keys.last().unwrap().0 + 1;
+            self.in_flight.insert(head, (slot, keys));
+            submitted = true;
+        }
+        if submitted {
+            self.mmio.notify(REQUEST_QUEUE);
+        }
+    }
+
+    /// Collect what the device finished and send out what waits.
+    fn reap(&mut self) {
+        while let Some((head, _)) = self.queue.pop_used() {
+            let (slot, keys) = self.in_flight.remove(&head).unwrap();
+            let ok = unsafe { self.status(slot).read_volatile() } == VIRTIO_BLK_S_OK;
+            self.free_slots.push(slot);
+            for (block_id, ticket) in keys {
+                self.busy.remove(&block_id);
+                self.finished.insert(ticket, ok);
+                if let Some(sleepers) = self.sleepers.remove(&ticket) {
+                    sleepers.signal_all();
+                }
+            }
+        }
+        self.dispatch();
+    }
+
+    fn remove_prefetched(&mut self, block_id: usize) {
+        if let Some(block) = self.prefetched.remove(&block_id) {
+            self.prefetch_order.retain(|&id| id != block_id);
+            self.waiting.remove(&(block_id, block.ticket));
+            self.finished.remove(&block.ticket);
+        }
+    }
+
+    /// Put the current task to sleep until `ticket` finishes.
+    fn sleep(&mut self, ticket: usize) -> *mut TaskContext {
+        self.sleepers
+            .entry(ticket)
+            .or_insert_with(Condvar::new)
+            .wait_no_sched()
+    }
+
+    /// Whether the device is done with the buffer of a prefetched block,
+    /// or has not started on it.
+    fn prefetch_idle(&self, block_id: usize) -> bool {
+        let ticket = self.prefetched[&block_id].ticket;
+        self.finished.contains_key(&ticket) || self.waiting.contains_key(&(block_id, ticket))
+    }
+
+    /// Drop the oldest block the device is done with if there are too
+    /// many, false if it is busy with all of them.
+    fn make_room(&mut self) -> bool {
+        if self.prefetched.len() < READAHEAD_BLOCKS {
+            return true;
+        }
+        match self
+            .prefetch_order
+            .iter()
+            .copied()
+            .find(|&block_id| self.prefetch_idle(block_id))
+        {
+            Some(oldest) => {
+                self.remove_prefetched(oldest);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// What a waiter does next.
+enum Wait {
+    /// the ticket finished, whether it went well
+    Done(bool),
+    Sleep(*mut TaskContext),
+    /// no interrupts, look at the device again
+    Poll,
 }
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
-        if nb {
-            let mut resp = BlkResp::default();
-            let task_cx_ptr = self.virtio_blk.exclusive_session(|blk| {
-                let token = unsafe { blk.read_block_nb(block_id, buf, &mut resp).unwrap() };
-                self.condvars.get(&token).unwrap().wait_no_sched()
-            });
-            schedule(task_cx_ptr);
-            assert_eq!(
-                resp.status(),
-                RespStatus::Ok,
-                "Error when reading VirtIOBlk"
-            );
-        } else {
-            self.virtio_blk
-                .exclusive_access()
-                .read_block(block_id, buf)
-                .expect("Error when reading VirtIOBlk");
+        trace(TracePoint::BlockIo, [block_id, 0]);
+        assert_eq!(buf.len(), BLOCK_SZ);
+        if self.read_prefetched(block_id, buf) {
+            return;
         }
+        let ticket = self.inner.exclusive_session(|inner| {
+            let ticket = inner.enqueue(block_id, false, buf.as_mut_ptr() as usize);
+            inner.dispatch();
+            ticket
+        });
+        assert!(self.wait(ticket), "Error when reading VirtIOBlk");
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
-        if nb {
-            let mut resp = BlkResp::default();
-            let task_cx_ptr = self.virtio_blk.exclusive_session(|blk| {
-                let token = unsafe { blk.write_block_nb(block_id, buf, &mut resp).unwrap() };
-                self.condvars.get(&token).unwrap().wait_no_sched()
-            });
-            schedule(task_cx_ptr);
-            assert_eq!(
-                resp.status(),
-                RespStatus::Ok,
-                "Error when writing VirtIOBlk"
-            );
-        } else {
-            self.virtio_blk
-                .exclusive_access()
-                .write_block(block_id, buf)
-                .expect("Error when writing VirtIOBlk");
-        }
+        trace(TracePoint::BlockIo, [block_id, 1]);
+        assert_eq!(buf.len(), BLOCK_SZ);
+        let ticket = self.inner.exclusive_session(|inner| {
+            // what was read ahead of the block is out of date
+            if inner.prefetched.contains_key(&block_id) {
+                if inner.prefetch_idle(block_id) {
+                    inner.remove_prefetched(block_id);
+                } else {
+                    inner.prefetched.get_mut(&block_id).unwrap().stale = true;
+                }
+            }
+            let ticket = inner.enqueue(block_id, true, buf.as_ptr() as usize);
+            inner.dispatch();
+            ticket
+        });
+        assert!(self.wait(ticket), "Error when writing VirtIOBlk");
     }
     fn handle_irq(&self) {
-        self.virtio_blk.exclusive_session(|blk| {
-            while let Ok(token) = blk.pop_used() {
-                self.condvars.get(&token).unwrap().signal();
+        self.inner.exclusive_session(|inner| {
+            inner.mmio.ack_interrupt();
+            inner.reap();
+        });
+    }
+    fn prefetch(&self, block_ids: &[usize]) {
+        self.inner.exclusive_session(|inner| {
+            for &block_id in block_ids {
+                if inner.prefetched.contains_key(&block_id) {
+                    continue;
+                }
+                if !inner.make_room() {
+                    break;
+                }
+                let mut buf = Box::new([0; BLOCK_SZ]);
+                let ticket = inner.enqueue(block_id, false, buf.as_mut_ptr() as usize);
+                inner.prefetched.insert(
+                    block_id,
+                    Prefetched {
+                        buf,
+                        ticket,
+                        stale: false,
+                    },
+                );
+                inner.prefetch_order.push_back(block_id);
             }
+            inner.dispatch();
         });
     }
 }
 
 impl VirtIOBlock {
     pub fn new() -> Self {
-        let virtio_blk = unsafe {
-            UPIntrFreeCell::new(
-                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
+        let mmio = VirtioMmio::find_all(VIRTIO_ID_BLOCK)
+            .find(|mmio| mmio.base() == VIRTIO0)
+            .expect("no virtio-blk device");
+        mmio.negotiate(0).expect("virtio-blk refused the features");
+        let queue = VirtQueue::new(QUEUE_SIZE);
+        mmio.setup_queue(REQUEST_QUEUE, &queue);
+        mmio.driver_ok();
+        let inner = VirtIOBlockInner {
+            mmio,
+            queue,
+            dma: dma_alloc(
+                QUEUE_SIZE as usize * (size_of::<RequestHeader>() + 1),
+                size_of::<RequestHeader>(),
             )
+            .unwrap(),
+            free_slots: (0..QUEUE_SIZE as usize).collect(),
+            next_ticket: 0,
+            waiting: BTreeMap::new(),
+            in_flight: BTreeMap::new(),
+            busy: BTreeSet::new(),
+            finished: BTreeMap::new(),
+            sleepers: BTreeMap::new(),
+            position: 0,
+            prefetched: BTreeMap::new(),
+            prefetch_order: VecDeque::new(),
         };
-        let mut condvars = BTreeMap::new();
-        let channels = virtio_blk.exclusive_access().virt_queue_size();
-        for i in 0..channels {
-            let condvar = Condvar::new();
-            condvars.insert(i, condvar);
-        }
         Self {
-            virtio_blk,
-            condvars,
+            inner: unsafe { UPIntrFreeCell::new(inner) },
+        }
+    }
+
+    /// Whether `ticket` went well, once it finished. Sleeps until the
+    /// interrupt says so, or polls the device while nothing is scheduled.
+    fn wait(&self, ticket: usize) -> bool {
+        loop {
+            let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
+            let wait = self.inner.exclusive_session(|inner| {
+                if !nb {
+                    inner.reap();
+                }
+                match inner.finished.remove(&ticket) {
+                    Some(ok) => Wait::Done(ok),
+                    None if nb => Wait::Sleep(inner.sleep(ticket)),
+                    None => Wait::Poll,
+                }
+            });
+            match wait {
+                Wait::Done(ok) => return ok,
+                Wait::Sleep(task_cx_ptr) => schedule(task_cx_ptr),
+                Wait::Poll => {}
+            }
+        }
+    }
+
+    /// Take `block_id` into `buf` if it was read ahead, waiting for the
+    /// device if it is still reading it.
+    fn read_prefetched(&self, block_id: usize, buf: &mut [u8]) -> bool {
+        loop {
+            let nb = *DEV_NON_BLOCKING_ACCESS.exclusive_access();
+            let wait = self.inner.exclusive_session(|inner| {
+                if !nb {
+                    inner.reap();
+                }
+                match inner.prefetched.get(&block_id).map(|block| block.stale) {
+                    None => return Wait::Done(false),
+                    Some(true) => {
+                        if inner.prefetch_idle(block_id) {
+                            inner.remove_prefetched(block_id);
+                        }
+                        return Wait::Done(false);
+                    }
+                    Some(false) => {}
+                }
+                let ticket = inner.prefetched[&block_id].ticket;
+                let ok = match inner.finished.get(&ticket) {
+                    Some(&ok) => ok,
+                    None if nb => return Wait::Sleep(inner.sleep(ticket)),
+                    None => return Wait::Poll,
+                };
+                if ok {
+                    buf.copy_from_slice(&inner.prefetched[&block_id].buf[..]);
+                }
+                inner.remove_prefetched(block_id);
+                Wait::Done(ok)
+            });
+            match wait {
+                Wait::Done(copied) => return copied,
+                Wait::Sleep(task_cx_ptr) => schedule(task_cx_ptr),
+                Wait::Poll => {}
+            }
         }
     }
 }
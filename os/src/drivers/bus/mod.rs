@@ -1 +1,47 @@
+pub mod pci;
+// no board in the tree has SPI yet
+#[cfg(feature = "sdcard")]
+#[allow(unused)]
+pub mod spi;
 pub mod virtio;
+pub mod virtio_mmio;
+pub mod virtio_pci;
+pub mod virtio_transport;
+pub mod virtqueue;
+
+use crate::board::{PCI_BUSES, PCI_MMIO_WINDOW, VIRT_PCIE_ECAM};
+use alloc::vec::Vec;
+use lazy_static::*;
+use pci::PciFunction;
+use virtio_pci::virtio_device_type;
+
+lazy_static! {
+    pub static ref PCI_FUNCTIONS: Vec<PciFunction> = pci::enumerate(
+        VIRT_PCIE_ECAM,
+        PCI_BUSES,
+        PCI_MMIO_WINDOW.0,
+        PCI_MMIO_WINDOW.1
+    );
+}
+
+/// Report the functions on the bus. Nothing is enabled here, the polled
+/// virtio drivers take their device through [`virtio_transport`].
+pub fn list_pci_functions() {
+    for function in PCI_FUNCTIONS.iter() {
+        let address = function.address;
+        print!(
+            "[kernel] pci {:02x}:{:02x}.{} {:04x}:{:04x} class {:02x}{:02x}",
+            address.bus,
+            address.device,
+            address.function,
+            function.vendor_id,
+            function.device_id,
+            function.class,
+            function.subclass
+        );
+        match virtio_device_type(function) {
+            Some(device_type) => println!(" virtio type {}", device_type),
+            None => println!(),
+        }
+    }
+}
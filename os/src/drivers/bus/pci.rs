@@ -0,0 +1,208 @@
+//! PCI functions behind an ECAM window, with their memory BARs placed in
+//! a window of the host bridge.
+//!
+//! Ref: PCI Local Bus Specification 3.0, chapter 6 "Configuration Space",
+//! and PCI Express Base Specification, 7.2.2 "ECAM".
+
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+
+pub const PCI_VENDOR_ID: usize = 0x00;
+pub const PCI_DEVICE_ID: usize = 0x02;
+const PCI_COMMAND: usize = 0x04;
+const PCI_STATUS: usize = 0x06;
+const PCI_CLASS_REVISION: usize = 0x08;
+const PCI_HEADER_TYPE: usize = 0x0e;
+const PCI_BAR0: usize = 0x10;
+pub const PCI_SUBSYSTEM_ID: usize = 0x2e;
+const PCI_CAPABILITY_LIST: usize = 0x34;
+
+const COMMAND_MEMORY: u16 = 1 << 1;
+const COMMAND_BUS_MASTER: u16 = 1 << 2;
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+const HEADER_MULTI_FUNCTION: u8 = 0x80;
+const HEADER_TYPE_MASK: u8 = 0x7f;
+
+const BAR_IO: u32 = 0x1;
+const BAR_TYPE_MASK: u32 = 0x6;
+const BAR_TYPE_64: u32 = 0x4;
+const BAR_MEMORY_MASK: u32 = !0xf;
+
+/// Address of a function on the bus.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+/// A memory BAR as placed by the scan.
+#[derive(Copy, Clone, Debug)]
+pub struct Bar {
+    pub address: usize,
+    pub size: usize,
+}
+
+#[derive(Debug)]
+pub struct PciFunction {
+    pub address: PciAddress,
+    /// configuration space of the function
+    config: usize,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub bars: [Option<Bar>; 6],
+}
+
+impl PciFunction {
+    pub fn read8(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.config + offset) as *const u8) }
+    }
+    pub fn read16(&self, offset: usize) -> u16 {
+        unsafe { read_volatile((self.config + offset) as *const u16) }
+    }
+    pub fn read32(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.config + offset) as *const u32) }
+    }
+    pub fn write16(&self, offset: usize, value: u16) {
+        unsafe { write_volatile((self.config + offset) as *mut u16, value) }
+    }
+    pub fn write32(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.config + offset) as *mut u32, value) }
+    }
+
+    /// Offsets of the capabilities with id `id` in the capability list.
+    pub fn capabilities(&self, id: u8) -> Vec<usize> {
+        let mut found = Vec::new();
+        if self.read16(PCI_STATUS) & STATUS_CAPABILITIES == 0 {
+            return found;
+        }
+        let mut offset = (self.read8(PCI_CAPABILITY_LIST) & !0x3) as usize;
+        // a malformed list could loop, there is room for at most 48
+        for _ in 0..48 {
+            if offset == 0 {
+                break;
+            }
+            if self.read8(offset) == id {
+                found.push(offset);
+            }
+            offset = (self.read8(offset + 1) & !0x3) as usize;
+        }
+        found
+    }
+
+    /// Decode the memory and bus master bits so the device can be driven.
+    pub fn enable(&self) {
+        let command = self.read16(PCI_COMMAND);
+        self.write16(PCI_COMMAND, command | COMMAND_MEMORY | COMMAND_BUS_MASTER);
+    }
+}
+
+/// Bump allocator over the memory window of the host bridge.
+struct Window {
+    next: usize,
+    end: usize,
+}
+
+impl Window {
+    fn alloc(&mut self, size: usize) -> Option<usize> {
+        // BARs are aligned to their size, which is a power of two
+        let address = (self.next + size - 1) & !(size - 1);
+        if address + size > self.end {
+            return None;
+        }
+        self.next = address + size;
+        Some(address)
+    }
+}
+
+/// Size the memory BARs of `function` and place them in
+/// `window`. I/O BARs are left unassigned.
+fn assign_bars(function: &PciFunction, window: &mut Window) -> [Option<Bar>; 6] {
+    let mut bars = [None; 6];
+    let mut index = 0;
+    while index < 6 {
+        let offset = PCI_BAR0 + index * 4;
+        let original = function.read32(offset);
+        let is_64 = original & BAR_IO == 0 && original & BAR_TYPE_MASK == BAR_TYPE_64;
+        function.write32(offset, !0);
+        let mask = function.read32(offset);
+        function.write32(offset, original);
+        let step = if is_64 { 2 } else { 1 };
+        if original & BAR_IO != 0 || mask & BAR_MEMORY_MASK == 0 {
+            index += step;
+            continue;
+        }
+        let size = (!(mask & BAR_MEMORY_MASK)).wrapping_add(1) as usize;
+        if let Some(address) = window.alloc(size) {
+            function.write32(offset, address as u32 | (original & !BAR_MEMORY_MASK));
+            if is_64 {
+                function.write32(offset + 4, (address as u64 >> 32) as u32);
+            }
+            bars[index] = Some(Bar { address, size });
+        }
+        index += step;
+    }
+    bars
+}
+
+/// Every function on buses `0..buses` of the ECAM window at `ecam`, with
+/// memory BARs placed in `window_start..window_end`. Functions stay
+/// disabled until a driver calls [`PciFunction::enable`].
+pub fn enumerate(
+    ecam: usize,
+    buses: u8,
+    window_start: usize,
+    window_end: usize,
+) -> Vec<PciFunction> {
+    let mut window = Window {
+        next: window_start,
+        end: window_end,
+    };
+    let mut functions = Vec::new();
+    for bus in 0..buses {
+        for device in 0..32u8 {
+            for function in 0..8u8 {
+                let address = PciAddress {
+                    bus,
+                    device,
+                    function,
+                };
+                let config = ecam
+                    + ((bus as usize) << 20 | (device as usize) << 15 | (function as usize) << 12);
+                let mut found = PciFunction {
+                    address,
+                    config,
+                    vendor_id: 0,
+                    device_id: 0,
+                    class: 0,
+                    subclass: 0,
+                    bars: [None; 6],
+                };
+                found.vendor_id = found.read16(PCI_VENDOR_ID);
+                // nothing answers at this address
+                if found.vendor_id == 0xffff {
+                    if function == 0 {
+                        break;
+                    }
+                    continue;
+                }
+                found.device_id = found.read16(PCI_DEVICE_ID);
+                let class_revision = found.read32(PCI_CLASS_REVISION);
+                found.class = (class_revision >> 24) as u8;
+                found.subclass = (class_revision >> 16) as u8;
+                let header_type = found.read8(PCI_HEADER_TYPE);
+                // bridges keep their windows and bus numbers as they are
+                if header_type & HEADER_TYPE_MASK == 0 {
+                    found.bars = assign_bars(&found, &mut window);
+                }
+                functions.push(found);
+                if function == 0 && header_type & HEADER_MULTI_FUNCTION == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    functions
+}
@@ -0,0 +1,102 @@
+//! SPI masters, byte at a time.
+//!
+//! Ref: DesignWare DW_apb_ssi databook, and the SPI chapter of the K210
+//! manual for where the K210 moved the CTRLR0 fields.
+
+use volatile::{ReadOnly, Volatile};
+
+/// An SPI master in mode 0 with one device selected at a time.
+pub trait SpiBus: Send {
+    /// Clock the bus at `hz` or the fastest speed below it.
+    fn set_clock(&mut self, hz: usize);
+    /// Pull the chip select of the device low.
+    fn select(&mut self);
+    fn deselect(&mut self);
+    /// Shift `byte` out while shifting the reply in.
+    fn transfer(&mut self, byte: u8) -> u8;
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct DwSpiRegisters {
+    ctrlr0: Volatile<u32>,
+    ctrlr1: Volatile<u32>,
+    /// enable, the other registers can only be written while it is 0
+    ssienr: Volatile<u32>,
+    mwcr: Volatile<u32>,
+    /// slave enable, a bit per chip select line
+    ser: Volatile<u32>,
+    /// clock divider, even and at least 2
+    baudr: Volatile<u32>,
+    txftlr: Volatile<u32>,
+    rxftlr: Volatile<u32>,
+    txflr: ReadOnly<u32>,
+    /// number of frames in the receive fifo
+    rxflr: ReadOnly<u32>,
+    sr: ReadOnly<u32>,
+    imr: Volatile<u32>,
+    _reserved: [u32; 12],
+    /// data, every word of the window reads and writes the fifos
+    dr: Volatile<u32>,
+}
+
+/// Frame size field of CTRLR0 on the K210, 8-bit frames. Transmit and
+/// receive mode and standard SPI frames are both 0.
+const CTRLR0_DFS_8: u32 = 7 << 16;
+
+/// DesignWare SSI as SPI0 and SPI1 of the K210. Pins and the controller
+/// clock are set up by the board before this is used.
+pub struct DwSpi {
+    base: usize,
+    /// frequency of the controller clock the divider works on
+    input_clock: usize,
+    chip_select: u32,
+}
+
+impl DwSpi {
+    /// # Safety
+    /// `base` must be the registers of an SSI no one else drives.
+    pub unsafe fn new(base: usize, input_clock: usize, chip_select: u32) -> Self {
+        let mut spi = Self {
+            base,
+            input_clock,
+            chip_select,
+        };
+        let regs = spi.regs();
+        regs.ssienr.write(0);
+        regs.imr.write(0);
+        regs.ser.write(0);
+        regs.ctrlr0.write(CTRLR0_DFS_8);
+        regs.txftlr.write(0);
+        regs.rxftlr.write(0);
+        regs.ssienr.write(1);
+        spi
+    }
+
+    fn regs(&mut self) -> &mut DwSpiRegisters {
+        unsafe { &mut *(self.base as *mut DwSpiRegisters) }
+    }
+}
+
+impl SpiBus for DwSpi {
+    fn set_clock(&mut self, hz: usize) {
+        let divider = self.input_clock.div_ceil(hz).clamp(2, 0xfffe);
+        let regs = self.regs();
+        regs.ssienr.write(0);
+        regs.baudr.write((divider + divider % 2) as u32);
+        regs.ssienr.write(1);
+    }
+    fn select(&mut self) {
+        let line = 1 << self.chip_select;
+        self.regs().ser.write(line);
+    }
+    fn deselect(&mut self) {
+        self.regs().ser.write(0);
+    }
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let regs = self.regs();
+        regs.dr.write(byte as u32);
+        while regs.rxflr.read() == 0 {}
+        regs.dr.read() as u8
+    }
+}
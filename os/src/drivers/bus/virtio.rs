@@ -1,38 +1,32 @@
-use crate::mm::{
-    frame_alloc_more, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
-    StepByOne, VirtAddr,
-};
+use crate::mm::{dma_alloc, kernel_token, DmaBuffer, PageTable, VirtAddr};
 use crate::sync::UPIntrFreeCell;
-use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
 use lazy_static::*;
 use virtio_drivers::Hal;
 
+const PAGE_SIZE: usize = 4096;
+
 lazy_static! {
-    static ref QUEUE_FRAMES: UPIntrFreeCell<Vec<FrameTracker>> =
-        unsafe { UPIntrFreeCell::new(Vec::new()) };
+    /// buffers handed to virtio-drivers, by physical address
+    static ref QUEUE_BUFFERS: UPIntrFreeCell<BTreeMap<usize, DmaBuffer>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
 }
 
 pub struct VirtioHal;
 
 impl Hal for VirtioHal {
     fn dma_alloc(pages: usize) -> usize {
-        let trakcers = frame_alloc_more(pages);
-        let ppn_base = trakcers.as_ref().unwrap().last().unwrap().ppn;
-        QUEUE_FRAMES
-            .exclusive_access()
-            .append(&mut trakcers.unwrap());
-        let pa: PhysAddr = ppn_base.into();
-        pa.0
+        let buffer = dma_alloc(pages * PAGE_SIZE, PAGE_SIZE).unwrap();
+        let pa = buffer.paddr();
+        QUEUE_BUFFERS.exclusive_access().insert(pa, buffer);
+        pa
     }
 
-    fn dma_dealloc(pa: usize, pages: usize) -> i32 {
-        let pa = PhysAddr::from(pa);
-        let mut ppn_base: PhysPageNum = pa.into();
-        for _ in 0..pages {
-            frame_dealloc(ppn_base);
-            ppn_base.step();
+    fn dma_dealloc(pa: usize, _pages: usize) -> i32 {
+        match QUEUE_BUFFERS.exclusive_access().remove(&pa) {
+            Some(_) => 0,
+            None => -1,
         }
-        0
     }
 
     fn phys_to_virt(addr: usize) -> usize {
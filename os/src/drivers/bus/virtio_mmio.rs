@@ -0,0 +1,152 @@
+//! Raw virtio-mmio registers, for drivers that set up their own
+//! [`VirtQueue`]s because virtio-drivers has no driver for their device or
+//! one that does too little.
+//!
+//! Ref: Virtual I/O Device (VIRTIO) Version 1.1, 4.2 "Virtio Over MMIO".
+
+use super::virtqueue::VirtQueue;
+use crate::board::VIRTIO_MMIO_SLOTS;
+use core::ptr::{read_volatile, write_volatile};
+
+const MMIO_MAGIC: usize = 0x000;
+const MMIO_VERSION: usize = 0x004;
+const MMIO_DEVICE_ID: usize = 0x008;
+const MMIO_DEVICE_FEATURES: usize = 0x010;
+const MMIO_DEVICE_FEATURES_SEL: usize = 0x014;
+const MMIO_DRIVER_FEATURES: usize = 0x020;
+const MMIO_DRIVER_FEATURES_SEL: usize = 0x024;
+const MMIO_GUEST_PAGE_SIZE: usize = 0x028;
+const MMIO_QUEUE_SEL: usize = 0x030;
+const MMIO_QUEUE_NUM_MAX: usize = 0x034;
+const MMIO_QUEUE_NUM: usize = 0x038;
+const MMIO_QUEUE_ALIGN: usize = 0x03c;
+const MMIO_QUEUE_PFN: usize = 0x040;
+const MMIO_QUEUE_READY: usize = 0x044;
+const MMIO_QUEUE_NOTIFY: usize = 0x050;
+const MMIO_INTERRUPT_STATUS: usize = 0x060;
+const MMIO_INTERRUPT_ACK: usize = 0x064;
+const MMIO_STATUS: usize = 0x070;
+const MMIO_QUEUE_DESC: usize = 0x080;
+const MMIO_QUEUE_DRIVER: usize = 0x090;
+const MMIO_QUEUE_DEVICE: usize = 0x0a0;
+const MMIO_CONFIG: usize = 0x100;
+
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+/// VIRTIO_F_VERSION_1, in the second word of the features
+const FEATURE_VERSION_1: u32 = 1;
+
+const PAGE_SIZE: usize = 4096;
+
+/// The register block of one virtio-mmio slot.
+#[derive(Copy, Clone)]
+pub struct VirtioMmio {
+    base: usize,
+}
+
+impl VirtioMmio {
+    /// The first slot of the board holding a device of type `device_id`.
+    pub fn find(device_id: u32) -> Option<Self> {
+        Self::find_all(device_id).next()
+    }
+
+    /// Every slot holding a device of type `device_id`.
+    pub fn find_all(device_id: u32) -> impl Iterator<Item = Self> {
+        VIRTIO_MMIO_SLOTS
+            .iter()
+            .map(|&base| Self { base })
+            .filter(move |mmio| {
+                mmio.read(MMIO_MAGIC) == VIRTIO_MAGIC && mmio.read(MMIO_DEVICE_ID) == device_id
+            })
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Reset the device and accept those of `features`, bits of the first
+    /// word, that it offers. Returns the accepted ones, None if the device
+    /// refused them. Queues are set up next, then [`Self::driver_ok`].
+    pub fn negotiate(&self, features: u32) -> Option<u32> {
+        self.write(MMIO_STATUS, 0);
+        self.write(MMIO_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        self.write(MMIO_DEVICE_FEATURES_SEL, 1);
+        let version_1 = self.read(MMIO_DEVICE_FEATURES) & FEATURE_VERSION_1;
+        self.write(MMIO_DEVICE_FEATURES_SEL, 0);
+        let accepted = self.read(MMIO_DEVICE_FEATURES) & features;
+        self.write(MMIO_DRIVER_FEATURES_SEL, 1);
+        self.write(MMIO_DRIVER_FEATURES, version_1);
+        self.write(MMIO_DRIVER_FEATURES_SEL, 0);
+        self.write(MMIO_DRIVER_FEATURES, accepted);
+        self.write(
+            MMIO_STATUS,
+            STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK,
+        );
+        if self.read(MMIO_STATUS) & STATUS_FEATURES_OK == 0 {
+            return None;
+        }
+        if self.read(MMIO_VERSION) == 1 {
+            self.write(MMIO_GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        }
+        Some(accepted)
+    }
+
+    /// Let the device start using the queues.
+    pub fn driver_ok(&self) {
+        self.write(
+            MMIO_STATUS,
+            STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK | STATUS_DRIVER_OK,
+        );
+    }
+
+    /// Hand `queue` to the device as its queue number `index`.
+    pub fn setup_queue(&self, index: u16, queue: &VirtQueue) {
+        self.write(MMIO_QUEUE_SEL, index as u32);
+        assert!(self.read(MMIO_QUEUE_NUM_MAX) >= queue.size() as u32);
+        self.write(MMIO_QUEUE_NUM, queue.size() as u32);
+        if self.read(MMIO_VERSION) == 1 {
+            self.write(MMIO_QUEUE_ALIGN, PAGE_SIZE as u32);
+            self.write(MMIO_QUEUE_PFN, (queue.desc_address() / PAGE_SIZE) as u32);
+        } else {
+            for (offset, address) in [
+                (MMIO_QUEUE_DESC, queue.desc_address()),
+                (MMIO_QUEUE_DRIVER, queue.avail_address()),
+                (MMIO_QUEUE_DEVICE, queue.used_address()),
+            ] {
+                self.write(offset, address as u32);
+                self.write(offset + 4, (address >> 32) as u32);
+            }
+            self.write(MMIO_QUEUE_READY, 1);
+        }
+    }
+
+    pub fn notify(&self, index: u16) {
+        self.write(MMIO_QUEUE_NOTIFY, index as u32);
+    }
+
+    /// Clear the interrupts the device raised, for drivers that take them.
+    pub fn ack_interrupt(&self) {
+        let status = self.read(MMIO_INTERRUPT_STATUS);
+        self.write(MMIO_INTERRUPT_ACK, status);
+    }
+
+    /// A word of the device specific configuration.
+    pub fn config_read32(&self, offset: usize) -> u32 {
+        self.read(MMIO_CONFIG + offset)
+    }
+
+    pub fn config_read8(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.base + MMIO_CONFIG + offset) as *const u8) }
+    }
+}
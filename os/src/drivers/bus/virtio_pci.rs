@@ -0,0 +1,203 @@
+//! The virtio-pci transport: the registers virtio-mmio has at fixed
+//! offsets are found through vendor capabilities of the PCI function.
+//!
+//! Ref: Virtual I/O Device (VIRTIO) Version 1.1, 4.1 "Virtio Over PCI Bus".
+
+use super::pci::{PciFunction, PCI_SUBSYSTEM_ID};
+use super::virtqueue::VirtQueue;
+use core::ptr::{read_volatile, write_volatile};
+
+const VIRTIO_VENDOR_ID: u16 = 0x1af4;
+/// transitional devices, the type is in the subsystem id
+const TRANSITIONAL_DEVICE_IDS: core::ops::Range<u16> = 0x1000..0x1040;
+/// modern devices are 0x1040 plus the type
+const MODERN_DEVICE_ID_BASE: u16 = 0x1040;
+
+const PCI_CAP_ID_VNDR: u8 = 0x09;
+const VIRTIO_PCI_CAP_COMMON_CFG: u8 = 1;
+const VIRTIO_PCI_CAP_NOTIFY_CFG: u8 = 2;
+const VIRTIO_PCI_CAP_DEVICE_CFG: u8 = 4;
+
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+/// VIRTIO_F_VERSION_1, which a modern device requires
+const FEATURE_VERSION_1: u64 = 1 << 32;
+
+/// The device type of `function`, as in virtio-mmio's DeviceID register,
+/// None if it is no virtio device.
+pub fn virtio_device_type(function: &PciFunction) -> Option<u16> {
+    if function.vendor_id != VIRTIO_VENDOR_ID {
+        return None;
+    }
+    if TRANSITIONAL_DEVICE_IDS.contains(&function.device_id) {
+        Some(function.read16(PCI_SUBSYSTEM_ID))
+    } else {
+        function.device_id.checked_sub(MODERN_DEVICE_ID_BASE)
+    }
+}
+
+/// `struct virtio_pci_common_cfg`, fields the drivers leave alone included.
+#[repr(C)]
+#[allow(dead_code)]
+struct CommonCfg {
+    device_feature_select: u32,
+    device_feature: u32,
+    driver_feature_select: u32,
+    driver_feature: u32,
+    msix_config: u16,
+    num_queues: u16,
+    device_status: u8,
+    config_generation: u8,
+    queue_select: u16,
+    queue_size: u16,
+    queue_msix_vector: u16,
+    queue_enable: u16,
+    queue_notify_off: u16,
+    queue_desc: u64,
+    queue_driver: u64,
+    queue_device: u64,
+}
+
+macro_rules! common_read {
+    ($self:expr, $field:ident) => {
+        unsafe { read_volatile(core::ptr::addr_of!((*$self.common).$field)) }
+    };
+}
+
+macro_rules! common_write {
+    ($self:expr, $field:ident, $value:expr) => {
+        unsafe { write_volatile(core::ptr::addr_of_mut!((*$self.common).$field), $value) }
+    };
+}
+
+/// A virtio device on the PCI bus, driven through the same steps as one
+/// on virtio-mmio: status handshake, feature negotiation, queue setup and
+/// notification. Drivers using it poll, so the ISR status is not used.
+pub struct VirtioPciTransport {
+    common: *mut CommonCfg,
+    notify: usize,
+    notify_off_multiplier: u32,
+    device_config: usize,
+}
+
+unsafe impl Send for VirtioPciTransport {}
+unsafe impl Sync for VirtioPciTransport {}
+
+impl VirtioPciTransport {
+    /// None if `function` is no virtio device or lacks a structure the
+    /// transport needs. Enables the function on success.
+    pub fn probe(function: &PciFunction) -> Option<Self> {
+        virtio_device_type(function)?;
+        let (mut common, mut notify, mut device_config) = (None, None, None);
+        let mut notify_off_multiplier = 0;
+        for cap in function.capabilities(PCI_CAP_ID_VNDR) {
+            // struct virtio_pci_cap
+            let cfg_type = function.read8(cap + 3);
+            let bar = function.read8(cap + 4) as usize;
+            let offset = function.read32(cap + 8) as usize;
+            let length = function.read32(cap + 12) as usize;
+            let address = match function.bars.get(bar).copied().flatten() {
+                Some(bar) if offset + length <= bar.size => bar.address + offset,
+                _ => continue,
+            };
+            // the first structure of each type is the preferred one
+            match cfg_type {
+                VIRTIO_PCI_CAP_COMMON_CFG if common.is_none() => common = Some(address),
+                VIRTIO_PCI_CAP_NOTIFY_CFG if notify.is_none() => {
+                    notify = Some(address);
+                    notify_off_multiplier = function.read32(cap + 16);
+                }
+                VIRTIO_PCI_CAP_DEVICE_CFG if device_config.is_none() => {
+                    device_config = Some(address)
+                }
+                _ => {}
+            }
+        }
+        let transport = Self {
+            common: common? as *mut CommonCfg,
+            notify: notify?,
+            notify_off_multiplier,
+            device_config: device_config?,
+        };
+        function.enable();
+        Some(transport)
+    }
+
+    fn status(&self) -> u8 {
+        common_read!(self, device_status)
+    }
+
+    /// Writing 0 resets the device, the reset is done once it reads back 0.
+    fn set_status(&self, status: u8) {
+        common_write!(self, device_status, status);
+        if status == 0 {
+            while self.status() != 0 {}
+        }
+    }
+
+    fn device_features(&self) -> u64 {
+        common_write!(self, device_feature_select, 0);
+        let low = common_read!(self, device_feature) as u64;
+        common_write!(self, device_feature_select, 1);
+        let high = common_read!(self, device_feature) as u64;
+        high << 32 | low
+    }
+
+    fn set_driver_features(&self, features: u64) {
+        common_write!(self, driver_feature_select, 0);
+        common_write!(self, driver_feature, features as u32);
+        common_write!(self, driver_feature_select, 1);
+        common_write!(self, driver_feature, (features >> 32) as u32);
+    }
+
+    /// As [`VirtioMmio::negotiate`]: reset the device and accept those of
+    /// `features`, bits of the first word, that it offers.
+    ///
+    /// [`VirtioMmio::negotiate`]: super::virtio_mmio::VirtioMmio::negotiate
+    pub fn negotiate(&self, features: u32) -> Option<u32> {
+        self.set_status(0);
+        self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        let offered = self.device_features();
+        let accepted = offered as u32 & features;
+        self.set_driver_features(offered & FEATURE_VERSION_1 | accepted as u64);
+        self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+        if self.status() & STATUS_FEATURES_OK == 0 {
+            return None;
+        }
+        Some(accepted)
+    }
+
+    /// Let the device start using the queues.
+    pub fn driver_ok(&self) {
+        self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK | STATUS_DRIVER_OK);
+    }
+
+    /// Hand `queue` to the device as its queue number `index` and enable it.
+    pub fn setup_queue(&self, index: u16, queue: &VirtQueue) {
+        common_write!(self, queue_select, index);
+        assert!(common_read!(self, queue_size) >= queue.size());
+        common_write!(self, queue_size, queue.size());
+        common_write!(self, queue_desc, queue.desc_address() as u64);
+        common_write!(self, queue_driver, queue.avail_address() as u64);
+        common_write!(self, queue_device, queue.used_address() as u64);
+        common_write!(self, queue_enable, 1);
+    }
+
+    pub fn notify(&self, index: u16) {
+        common_write!(self, queue_select, index);
+        let offset = common_read!(self, queue_notify_off) as usize;
+        let address = self.notify + offset * self.notify_off_multiplier as usize;
+        unsafe { write_volatile(address as *mut u16, index) }
+    }
+
+    /// A word of the device specific configuration.
+    pub fn config_read32(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.device_config + offset) as *const u32) }
+    }
+
+    pub fn config_read8(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.device_config + offset) as *const u8) }
+    }
+}
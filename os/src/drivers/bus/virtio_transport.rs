@@ -0,0 +1,87 @@
+//! A virtio device behind either transport, for the drivers which set up
+//! their queues themselves. virtio-mmio slots are looked at first, then
+//! the virtio functions on the PCI bus.
+
+use super::virtio_mmio::VirtioMmio;
+use super::virtio_pci::{virtio_device_type, VirtioPciTransport};
+use super::virtqueue::VirtQueue;
+use super::PCI_FUNCTIONS;
+use alloc::boxed::Box;
+
+#[derive(Copy, Clone)]
+pub enum VirtioTransport {
+    Mmio(VirtioMmio),
+    Pci(&'static VirtioPciTransport),
+}
+
+impl VirtioTransport {
+    /// The first device of type `device_id`. A PCI function is only
+    /// enabled once a driver takes it.
+    pub fn find(device_id: u32) -> Option<Self> {
+        if let Some(mmio) = VirtioMmio::find(device_id) {
+            return Some(Self::Mmio(mmio));
+        }
+        PCI_FUNCTIONS
+            .iter()
+            .filter(|function| virtio_device_type(function) == Some(device_id as u16))
+            .find_map(VirtioPciTransport::probe)
+            .map(|pci| Self::Pci(Box::leak(Box::new(pci))))
+    }
+
+    /// Base of the virtio-mmio slot, whose interrupt the board can route.
+    pub fn mmio_slot(&self) -> Option<usize> {
+        match self {
+            Self::Mmio(mmio) => Some(mmio.base()),
+            Self::Pci(_) => None,
+        }
+    }
+
+    /// Only a device on virtio-mmio interrupts.
+    pub fn ack_interrupt(&self) {
+        if let Self::Mmio(mmio) = self {
+            mmio.ack_interrupt();
+        }
+    }
+
+    pub fn negotiate(&self, features: u32) -> Option<u32> {
+        match self {
+            Self::Mmio(mmio) => mmio.negotiate(features),
+            Self::Pci(pci) => pci.negotiate(features),
+        }
+    }
+
+    pub fn driver_ok(&self) {
+        match self {
+            Self::Mmio(mmio) => mmio.driver_ok(),
+            Self::Pci(pci) => pci.driver_ok(),
+        }
+    }
+
+    pub fn setup_queue(&self, index: u16, queue: &VirtQueue) {
+        match self {
+            Self::Mmio(mmio) => mmio.setup_queue(index, queue),
+            Self::Pci(pci) => pci.setup_queue(index, queue),
+        }
+    }
+
+    pub fn notify(&self, index: u16) {
+        match self {
+            Self::Mmio(mmio) => mmio.notify(index),
+            Self::Pci(pci) => pci.notify(index),
+        }
+    }
+
+    pub fn config_read32(&self, offset: usize) -> u32 {
+        match self {
+            Self::Mmio(mmio) => mmio.config_read32(offset),
+            Self::Pci(pci) => pci.config_read32(offset),
+        }
+    }
+
+    pub fn config_read8(&self, offset: usize) -> u8 {
+        match self {
+            Self::Mmio(mmio) => mmio.config_read8(offset),
+            Self::Pci(pci) => pci.config_read8(offset),
+        }
+    }
+}
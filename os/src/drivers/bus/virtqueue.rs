@@ -0,0 +1,159 @@
+//! Split virtqueues for drivers written against the virtio spec directly
+//! rather than through virtio-drivers.
+//!
+//! Ref: Virtual I/O Device (VIRTIO) Version 1.1, 2.6 "Split Virtqueues".
+
+use crate::mm::{dma_alloc, DmaBuffer};
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::sync::atomic::{fence, Ordering};
+
+const PAGE_SIZE: usize = 4096;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+#[repr(C)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// A queue of `size` descriptors in one physically contiguous block laid
+/// out as legacy devices expect it, which modern ones accept as well.
+pub struct VirtQueue {
+    size: u16,
+    /// the block, the kernel maps it at its physical address
+    ring: DmaBuffer,
+    free: Vec<u16>,
+    /// entries of the used ring already taken
+    last_used: u16,
+}
+
+impl VirtQueue {
+    pub fn new(size: u16) -> Self {
+        let ring = dma_alloc(Self::layout_size(size), PAGE_SIZE).unwrap();
+        Self {
+            size,
+            ring,
+            free: (0..size).rev().collect(),
+            last_used: 0,
+        }
+    }
+
+    fn avail_offset(size: u16) -> usize {
+        size as usize * size_of::<Descriptor>()
+    }
+
+    fn used_offset(size: u16) -> usize {
+        // flags, idx, the ring and used_event, then aligned to a page
+        (Self::avail_offset(size) + 2 * (3 + size as usize)).next_multiple_of(PAGE_SIZE)
+    }
+
+    fn layout_size(size: u16) -> usize {
+        Self::used_offset(size) + 2 * 3 + size as usize * size_of::<UsedElem>()
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn desc_address(&self) -> usize {
+        self.ring.paddr()
+    }
+    pub fn avail_address(&self) -> usize {
+        self.ring.paddr() + Self::avail_offset(self.size)
+    }
+    pub fn used_address(&self) -> usize {
+        self.ring.paddr() + Self::used_offset(self.size)
+    }
+
+    fn descriptor(&self, index: u16) -> *mut Descriptor {
+        self.ring
+            .as_ptr::<Descriptor>()
+            .wrapping_add(index as usize)
+    }
+
+    fn avail_idx(&self) -> *mut u16 {
+        (self.avail_address() + 2) as *mut u16
+    }
+
+    fn used_idx(&self) -> *const u16 {
+        (self.used_address() + 2) as *const u16
+    }
+
+    /// Place a chain of the buffers in `readable` followed by those in
+    /// `writable`, given as physical address and length, and return its
+    /// head. None if there are not enough free descriptors.
+    pub fn add(&mut self, readable: &[(usize, usize)], writable: &[(usize, usize)]) -> Option<u16> {
+        let count = readable.len() + writable.len();
+        if count == 0 || count > self.free.len() {
+            return None;
+        }
+        let chain: Vec<u16> = (0..count).map(|_| self.free.pop().unwrap()).collect();
+        let buffers = readable
+            .iter()
+            .map(|&buffer| (buffer, 0))
+            .chain(writable.iter().map(|&buffer| (buffer, DESC_F_WRITE)));
+        for (i, ((addr, len), flags)) in buffers.enumerate() {
+            let next = chain.get(i + 1).copied();
+            unsafe {
+                self.descriptor(chain[i]).write_volatile(Descriptor {
+                    addr: addr as u64,
+                    len: len as u32,
+                    flags: flags | if next.is_some() { DESC_F_NEXT } else { 0 },
+                    next: next.unwrap_or(0),
+                });
+            }
+        }
+        unsafe {
+            let idx = self.avail_idx().read_volatile();
+            let slot = (self.avail_address() + 4) as *mut u16;
+            slot.add((idx % self.size) as usize)
+                .write_volatile(chain[0]);
+            // the device must see the entry before the index moves past it
+            fence(Ordering::SeqCst);
+            self.avail_idx().write_volatile(idx.wrapping_add(1));
+        }
+        Some(chain[0])
+    }
+
+    /// Take the next chain the device is done with, returning its head and
+    /// the number of bytes the device wrote, and free its descriptors.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if unsafe { self.used_idx().read_volatile() } == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let ring = (self.used_address() + 4) as *const UsedElem;
+        let elem = unsafe {
+            ring.add((self.last_used % self.size) as usize)
+                .read_volatile()
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+        let head = elem.id as u16;
+        let mut index = head;
+        loop {
+            self.free.push(index);
+            let descriptor = unsafe { self.descriptor(index).read_volatile() };
+            if descriptor.flags & DESC_F_NEXT == 0 {
+                break;
+            }
+            index = descriptor.next;
+        }
+        Some((head, elem.len))
+    }
+
+    /// Whether no chain is waiting for the device.
+    pub fn is_idle(&self) -> bool {
+        self.free.len() == self.size as usize
+    }
+}
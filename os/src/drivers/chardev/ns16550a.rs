@@ -2,9 +2,9 @@
 ///! Ref: ns16550a datasheet: https://datasheetspdf.com/pdf-file/605590/NationalSemiconductor/NS16550A/1
 ///! Ref: ns16450 datasheet: https://datasheetspdf.com/pdf-file/1311818/NationalSemiconductor/NS16450/1
 use super::CharDevice;
-use crate::sync::{Condvar, UPIntrFreeCell};
-use crate::task::schedule;
-use alloc::collections::VecDeque;
+use crate::fs::console_input;
+use crate::sync::UPIntrFreeCell;
+use alloc::vec::Vec;
 use bitflags::*;
 use volatile::{ReadOnly, Volatile, WriteOnly};
 
@@ -121,31 +121,23 @@ impl NS16550aRaw {
 
 struct NS16550aInner {
     ns16550a: NS16550aRaw,
-    read_buffer: VecDeque<u8>,
 }
 
+/// Input is not kept here but handed to the virtual terminals as it comes.
 pub struct NS16550a<const BASE_ADDR: usize> {
     inner: UPIntrFreeCell<NS16550aInner>,
-    condvar: Condvar,
 }
 
 impl<const BASE_ADDR: usize> NS16550a<BASE_ADDR> {
     pub fn new() -> Self {
         let inner = NS16550aInner {
             ns16550a: NS16550aRaw::new(BASE_ADDR),
-            read_buffer: VecDeque::new(),
         };
         //inner.ns16550a.init();
         Self {
             inner: unsafe { UPIntrFreeCell::new(inner) },
-            condvar: Condvar::new(),
         }
     }
-
-    pub fn read_buffer_is_empty(&self) -> bool {
-        self.inner
-            .exclusive_session(|inner| inner.read_buffer.is_empty())
-    }
 }
 
 impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
@@ -155,32 +147,19 @@ impl<const BASE_ADDR: usize> CharDevice for NS16550a<BASE_ADDR> {
         drop(inner);
     }
 
-    fn read(&self) -> u8 {
-        loop {
-            let mut inner = self.inner.exclusive_access();
-            if let Some(ch) = inner.read_buffer.pop_front() {
-                return ch;
-            } else {
-                let task_cx_ptr = self.condvar.wait_no_sched();
-                drop(inner);
-                schedule(task_cx_ptr);
-            }
-        }
-    }
     fn write(&self, ch: u8) {
         let mut inner = self.inner.exclusive_access();
         inner.ns16550a.write(ch);
     }
     fn handle_irq(&self) {
-        let mut count = 0;
+        let mut received = Vec::new();
         self.inner.exclusive_session(|inner| {
             while let Some(ch) = inner.ns16550a.read() {
-                count += 1;
-                inner.read_buffer.push_back(ch);
+                received.push(ch);
             }
         });
-        if count > 0 {
-            self.condvar.signal();
+        if !received.is_empty() {
+            console_input(&received);
         }
     }
 }
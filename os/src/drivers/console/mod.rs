@@ -0,0 +1,372 @@
+//! virtio-console with VIRTIO_CONSOLE_F_MULTIPORT, so that the kernel log,
+//! shells and debuggers each get a port of their own next to the UART.
+//!
+//! Ref: Virtual I/O Device (VIRTIO) Version 1.1, 5.3 "Console Device".
+//! The device is not part of the default QEMU command line, see HVC in
+//! the Makefile. Like virtio-snd it is polled and takes no interrupts.
+
+use crate::drivers::bus::virtio_mmio::VirtioMmio;
+use crate::drivers::bus::virtqueue::VirtQueue;
+use crate::mm::{dma_alloc, DmaBuffer};
+use crate::sync::UPIntrFreeCell;
+use crate::timer::get_time_ms;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+
+const VIRTIO_ID_CONSOLE: u32 = 3;
+const F_MULTIPORT: u32 = 1 << 1;
+/// offset of max_nr_ports in `struct virtio_console_config`
+const CONFIG_MAX_NR_PORTS: usize = 4;
+
+const CONTROL_RX_QUEUE: u16 = 2;
+const CONTROL_TX_QUEUE: u16 = 3;
+const QUEUE_SIZE: u16 = 16;
+/// ports beyond this many are refused when the device adds them
+const MAX_PORTS: usize = 4;
+
+// control events, `struct virtio_console_control`
+const DEVICE_READY: u16 = 0;
+const DEVICE_ADD: u16 = 1;
+const DEVICE_REMOVE: u16 = 2;
+const PORT_READY: u16 = 3;
+const CONSOLE_PORT: u16 = 4;
+const PORT_OPEN: u16 = 6;
+const PORT_NAME: u16 = 7;
+const CONTROL_SIZE: usize = 8;
+
+/// buffers of each queue, half a page of them for receiving and half for
+/// sending
+const SLOTS: usize = 8;
+const SLOT_SIZE: usize = 256;
+const TX_OFFSET: usize = SLOTS * SLOT_SIZE;
+/// how long the device gets to announce its ports after DEVICE_READY
+const ANNOUNCE_TIMEOUT_MS: usize = 100;
+
+/// Queues and a page of buffers, for a port or for control messages.
+struct Channel {
+    rx: VirtQueue,
+    tx: VirtQueue,
+    dma: DmaBuffer,
+    /// head of the chain using each receive and send slot
+    rx_slots: [Option<u16>; SLOTS],
+    tx_slots: [Option<u16>; SLOTS],
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            rx: VirtQueue::new(QUEUE_SIZE),
+            tx: VirtQueue::new(QUEUE_SIZE),
+            dma: dma_alloc(2 * TX_OFFSET, SLOT_SIZE).unwrap(),
+            rx_slots: [None; SLOTS],
+            tx_slots: [None; SLOTS],
+        }
+    }
+
+    fn setup(&self, mmio: &VirtioMmio, rx_index: u16) {
+        mmio.setup_queue(rx_index, &self.rx);
+        mmio.setup_queue(rx_index + 1, &self.tx);
+    }
+
+    fn post_rx(&mut self, slot: usize) {
+        let buffer = self.dma.paddr() + slot * SLOT_SIZE;
+        self.rx_slots[slot] = self.rx.add(&[], &[(buffer, SLOT_SIZE)]);
+    }
+
+    /// Next buffer the device filled, as its slot and length. The slot
+    /// must be posted again once its contents are taken.
+    fn pop_rx(&mut self) -> Option<(usize, usize)> {
+        let (head, len) = self.rx.pop_used()?;
+        let slot = self.rx_slots.iter().position(|&s| s == Some(head))?;
+        self.rx_slots[slot] = None;
+        Some((slot, len as usize))
+    }
+
+    fn rx_buffer(&self, slot: usize, len: usize) -> &[u8] {
+        let start = (self.dma.paddr() + slot * SLOT_SIZE) as *const u8;
+        unsafe { core::slice::from_raw_parts(start, len.min(SLOT_SIZE)) }
+    }
+
+    fn reclaim_tx(&mut self) {
+        while let Some((head, _)) = self.tx.pop_used() {
+            for slot in self.tx_slots.iter_mut() {
+                if *slot == Some(head) {
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    /// Queue up to a slot of `data`, None if all slots are busy.
+    fn submit(&mut self, data: &[u8]) -> Option<usize> {
+        self.reclaim_tx();
+        let slot = self.tx_slots.iter().position(Option::is_none)?;
+        let len = data.len().min(SLOT_SIZE);
+        let buffer = self.dma.paddr() + TX_OFFSET + slot * SLOT_SIZE;
+        unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), buffer as *mut u8, len) };
+        self.tx_slots[slot] = Some(self.tx.add(&[(buffer, len)], &[])?);
+        Some(len)
+    }
+}
+
+struct Port {
+    channel: Channel,
+    /// added by the device and not removed since
+    present: bool,
+    name: Option<String>,
+    received: VecDeque<u8>,
+}
+
+struct VirtIOConsoleInner {
+    mmio: VirtioMmio,
+    /// None without VIRTIO_CONSOLE_F_MULTIPORT, when only port 0 exists
+    control: Option<Channel>,
+    /// indexed by port id
+    ports: Vec<Port>,
+}
+
+/// A virtio-console whose ports are byte streams, read without blocking.
+pub struct VirtIOConsole {
+    inner: UPIntrFreeCell<VirtIOConsoleInner>,
+}
+
+lazy_static! {
+    /// None if the machine has no virtio-console.
+    pub static ref CONSOLE_DEVICE: Option<Arc<VirtIOConsole>> =
+        VirtioMmio::find(VIRTIO_ID_CONSOLE)
+            .and_then(VirtIOConsole::probe)
+            .map(Arc::new);
+}
+
+/// Port the kernel log goes to, usize::MAX while it goes to the UART.
+static LOG_PORT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Port 0 has queues 0 and 1, the control queues come next, then two for
+/// each further port.
+fn rx_queue_of(id: usize) -> u16 {
+    if id == 0 {
+        0
+    } else {
+        2 + 2 * id as u16
+    }
+}
+
+impl VirtIOConsoleInner {
+    /// Send a control message and wait for the device to take it.
+    fn send_control(&mut self, id: usize, event: u16, value: u16) {
+        let control = self.control.as_mut().unwrap();
+        let message = control.dma.paddr() + TX_OFFSET;
+        unsafe {
+            write_volatile(message as *mut u32, id as u32);
+            write_volatile((message + 4) as *mut u16, event);
+            write_volatile((message + 6) as *mut u16, value);
+        }
+        control.tx.add(&[(message, CONTROL_SIZE)], &[]).unwrap();
+        self.mmio.notify(CONTROL_TX_QUEUE);
+        while control.tx.pop_used().is_none() {}
+    }
+
+    /// Handle the control messages the device sent.
+    fn poll_control(&mut self) {
+        loop {
+            let control = match self.control.as_mut() {
+                Some(control) => control,
+                None => return,
+            };
+            let (slot, len) = match control.pop_rx() {
+                Some(received) => received,
+                None => return,
+            };
+            let at = control.dma.paddr() + slot * SLOT_SIZE;
+            let (id, event) = unsafe {
+                (
+                    read_volatile(at as *const u32) as usize,
+                    read_volatile((at + 4) as *const u16),
+                )
+            };
+            let name = (event == PORT_NAME && len > CONTROL_SIZE).then(|| {
+                let bytes = &control.rx_buffer(slot, len)[CONTROL_SIZE..];
+                String::from_utf8_lossy(bytes).into_owned()
+            });
+            control.post_rx(slot);
+            self.mmio.notify(CONTROL_RX_QUEUE);
+            match event {
+                DEVICE_ADD if id < self.ports.len() => {
+                    self.ports[id].present = true;
+                    self.send_control(id, PORT_READY, 1);
+                    // the kernel keeps every port open, buffering its input
+                    self.send_control(id, PORT_OPEN, 1);
+                }
+                DEVICE_ADD => self.send_control(id, PORT_READY, 0),
+                DEVICE_REMOVE if id < self.ports.len() => {
+                    self.ports[id].present = false;
+                    self.ports[id].name = None;
+                }
+                CONSOLE_PORT if id < self.ports.len() => self.send_control(id, PORT_OPEN, 1),
+                PORT_NAME if id < self.ports.len() => self.ports[id].name = name,
+                _ => {}
+            }
+        }
+    }
+
+    /// Move what the device received on `id` to its buffer.
+    fn poll_port(&mut self, id: usize) {
+        let port = &mut self.ports[id];
+        while let Some((slot, len)) = port.channel.pop_rx() {
+            let data = port.channel.rx_buffer(slot, len);
+            port.received.extend(data.iter());
+            port.channel.post_rx(slot);
+            self.mmio.notify(rx_queue_of(id));
+        }
+    }
+}
+
+impl VirtIOConsole {
+    fn probe(mmio: VirtioMmio) -> Option<Self> {
+        let features = mmio.negotiate(F_MULTIPORT)?;
+        let multiport = features & F_MULTIPORT != 0;
+        let port_count = if multiport {
+            (mmio.config_read32(CONFIG_MAX_NR_PORTS) as usize).min(MAX_PORTS)
+        } else {
+            1
+        };
+        let mut inner = VirtIOConsoleInner {
+            mmio,
+            control: multiport.then(Channel::new),
+            ports: (0..port_count)
+                .map(|_| Port {
+                    channel: Channel::new(),
+                    // without multiport, port 0 is there from the start
+                    present: !multiport,
+                    name: None,
+                    received: VecDeque::new(),
+                })
+                .collect(),
+        };
+        for (id, port) in inner.ports.iter().enumerate() {
+            port.channel.setup(&mmio, rx_queue_of(id));
+        }
+        if let Some(control) = inner.control.as_ref() {
+            control.setup(&mmio, CONTROL_RX_QUEUE);
+        }
+        mmio.driver_ok();
+        for (id, port) in inner.ports.iter_mut().enumerate() {
+            for slot in 0..SLOTS {
+                port.channel.post_rx(slot);
+            }
+            mmio.notify(rx_queue_of(id));
+        }
+        if let Some(control) = inner.control.as_mut() {
+            for slot in 0..SLOTS {
+                control.post_rx(slot);
+            }
+            mmio.notify(CONTROL_RX_QUEUE);
+        }
+        if multiport {
+            inner.send_control(0, DEVICE_READY, 1);
+            // the ports are announced in reply, give the device a moment
+            let deadline = get_time_ms() + ANNOUNCE_TIMEOUT_MS;
+            while get_time_ms() < deadline {
+                inner.poll_control();
+            }
+        }
+        Some(Self {
+            inner: unsafe { UPIntrFreeCell::new(inner) },
+        })
+    }
+
+    /// Ids and names of the ports the device added.
+    pub fn ports(&self) -> Vec<(usize, Option<String>)> {
+        let mut inner = self.inner.exclusive_access();
+        inner.poll_control();
+        inner
+            .ports
+            .iter()
+            .enumerate()
+            .filter(|(_, port)| port.present)
+            .map(|(id, port)| (id, port.name.clone()))
+            .collect()
+    }
+
+    pub fn port_named(&self, name: &str) -> Option<usize> {
+        self.ports()
+            .into_iter()
+            .find(|(_, port_name)| port_name.as_deref() == Some(name))
+            .map(|(id, _)| id)
+    }
+
+    pub fn has_port(&self, id: usize) -> bool {
+        self.ports().iter().any(|&(port, _)| port == id)
+    }
+
+    /// Take what port `id` received, up to `buf.len()` bytes. Returns at
+    /// once, 0 if nothing is there.
+    pub fn read(&self, id: usize, buf: &mut [u8]) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        inner.poll_control();
+        if id >= inner.ports.len() {
+            return 0;
+        }
+        inner.poll_port(id);
+        let received = &mut inner.ports[id].received;
+        let len = buf.len().min(received.len());
+        for (byte, ch) in buf.iter_mut().zip(received.drain(..len)) {
+            *byte = ch;
+        }
+        len
+    }
+
+    /// Send all of `data` on port `id`, spinning while the device has not
+    /// freed a slot, so that it can be used wherever the kernel prints.
+    pub fn write(&self, id: usize, data: &[u8]) {
+        let mut inner = self.inner.exclusive_access();
+        if id >= inner.ports.len() || !inner.ports[id].present {
+            return;
+        }
+        let mut written = 0;
+        while written < data.len() {
+            if let Some(len) = inner.ports[id].channel.submit(&data[written..]) {
+                inner.mmio.notify(rx_queue_of(id) + 1);
+                written += len;
+            }
+        }
+    }
+}
+
+/// The device and port the kernel log goes to, if not the UART.
+pub fn log_port() -> Option<(&'static VirtIOConsole, usize)> {
+    let id = LOG_PORT.load(Ordering::Relaxed);
+    if id == usize::MAX {
+        return None;
+    }
+    CONSOLE_DEVICE.as_deref().map(|device| (device, id))
+}
+
+/// Report the ports and send the kernel log to the one named "log" from
+/// now on.
+pub fn init() {
+    let device = match CONSOLE_DEVICE.as_ref() {
+        Some(device) => device,
+        None => return,
+    };
+    let ports = device.ports();
+    for (id, name) in ports.iter() {
+        println!(
+            "[kernel] hvc{}: {}",
+            id,
+            name.as_deref().unwrap_or("(unnamed)")
+        );
+    }
+    if let Some(&(id, _)) = ports
+        .iter()
+        .find(|(_, name)| name.as_deref() == Some("log"))
+    {
+        println!("[kernel] kernel log continues on hvc{}", id);
+        LOG_PORT.store(id, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,473 @@
+//! A compositor for the windows of user programs. A surface is a
+//! rectangle of pixels at a place on the screen; the surfaces are drawn in
+//! stacking order over a plain background, the last one on top.
+//!
+//! While there are surfaces, the input of the virtio keyboard, mouse and
+//! tablet goes to them: keys to the focused surface, pointer motion and
+//! buttons to the surface under the pointer. A click focuses and raises
+//! that surface. Programs reach the compositor through fs::surface.
+
+use super::{fbcon, GPU_DEVICE};
+use crate::board::{VIRTGPU_XRES, VIRTGPU_YRES};
+use crate::config::PAGE_SIZE;
+use crate::drivers::input::{EventQueue, KEYBOARD_DEVICE, MOUSE_DEVICE, TABLET_DEVICE};
+use crate::mm::{frame_alloc_for, FrameOwner, FrameTracker};
+use crate::sync::UPIntrFreeCell;
+use crate::syscall::{SysError, SysResult};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const SCREEN_WIDTH: usize = VIRTGPU_XRES as usize;
+const SCREEN_HEIGHT: usize = VIRTGPU_YRES as usize;
+/// B8G8R8A8, in the surfaces as on the framebuffer
+pub const BYTES_PER_PIXEL: usize = 4;
+const STRIDE: usize = SCREEN_WIDTH * BYTES_PER_PIXEL;
+const BACKGROUND: [u8; BYTES_PER_PIXEL] = [0x50, 0x38, 0x20, 0xff];
+const MAX_SURFACES: usize = 16;
+/// events a surface keeps before the oldest are dropped
+const MAX_PENDING: usize = 256;
+
+const EV_SYN: u16 = 0;
+const EV_KEY: u16 = 1;
+const EV_REL: u16 = 2;
+/// a tablet moved, and to the surfaces the pointer, whatever moved it
+const EV_ABS: u16 = 3;
+/// key codes from here on are buttons of pointing devices
+const BTN_MISC: u16 = 0x100;
+const REL_X: u16 = 0;
+const REL_Y: u16 = 1;
+const ABS_X: u16 = 0;
+const ABS_Y: u16 = 1;
+/// the positions of the QEMU tablet go from 0 to this, excluded
+const ABS_RANGE: i32 = 0x8000;
+
+/// An event as a surface reads it: a key or button as the device reported
+/// it, EV_REL for the wheel, or EV_ABS once the pointer moved. `x` and `y`
+/// are where the pointer is, relative to the surface.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SurfaceEvent {
+    pub type_: u16,
+    pub code: u16,
+    pub value: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// The pixels of a surface, in frames of their own: a few surfaces of the
+/// size of the screen would use up the kernel heap.
+struct Pixels {
+    frames: Vec<FrameTracker>,
+}
+
+impl Pixels {
+    /// `len` bytes of opaque black, None if the frames are not there.
+    fn new(len: usize) -> Option<Self> {
+        let frames = (0..len.div_ceil(PAGE_SIZE))
+            .map(|_| frame_alloc_for(FrameOwner::Kernel("surface")))
+            .collect::<Option<Vec<_>>>()?;
+        for frame in frames.iter() {
+            for pixel in frame
+                .ppn
+                .get_bytes_array()
+                .chunks_exact_mut(BYTES_PER_PIXEL)
+            {
+                pixel[3] = 0xff;
+            }
+        }
+        Some(Self { frames })
+    }
+
+    /// The runs of frame bytes `offset..offset + len` is made of, with
+    /// where each starts in the range.
+    fn runs(
+        &self,
+        offset: usize,
+        len: usize,
+    ) -> impl Iterator<Item = (usize, &'static mut [u8])> + '_ {
+        let mut done = 0;
+        core::iter::from_fn(move || {
+            if done == len {
+                return None;
+            }
+            let at = offset + done;
+            let page_offset = at % PAGE_SIZE;
+            let run = (PAGE_SIZE - page_offset).min(len - done);
+            let bytes = &mut self.frames[at / PAGE_SIZE].ppn.get_bytes_array()
+                [page_offset..page_offset + run];
+            let start = done;
+            done += run;
+            Some((start, bytes))
+        })
+    }
+
+    fn read(&self, offset: usize, dst: &mut [u8]) {
+        for (start, run) in self.runs(offset, dst.len()) {
+            dst[start..start + run.len()].copy_from_slice(run);
+        }
+    }
+
+    fn write(&mut self, offset: usize, src: &[u8]) {
+        for (start, run) in self.runs(offset, src.len()) {
+            run.copy_from_slice(&src[start..start + run.len()]);
+        }
+    }
+}
+
+struct Surface {
+    id: usize,
+    x: i32,
+    y: i32,
+    width: usize,
+    height: usize,
+    pixels: Pixels,
+    events: VecDeque<SurfaceEvent>,
+}
+
+impl Surface {
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x < self.x + self.width as i32
+            && y < self.y + self.height as i32
+    }
+
+    fn push_event(&mut self, type_: u16, code: u16, value: i32, pointer: (i32, i32)) {
+        if self.events.len() == MAX_PENDING {
+            self.events.pop_front();
+        }
+        self.events.push_back(SurfaceEvent {
+            type_,
+            code,
+            value,
+            x: pointer.0 - self.x,
+            y: pointer.1 - self.y,
+        });
+    }
+
+    /// The part of the screen `width` x `height` pixels at (`x`, `y`) of
+    /// the surface cover.
+    fn on_screen(&self, x: usize, y: usize, width: usize, height: usize) -> Rect {
+        Rect::clipped(
+            self.x as i64 + x as i64,
+            self.y as i64 + y as i64,
+            width,
+            height,
+        )
+    }
+}
+
+/// A part of the screen, the ends excluded.
+#[derive(Copy, Clone)]
+struct Rect {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+impl Rect {
+    const SCREEN: Self = Self {
+        x0: 0,
+        y0: 0,
+        x1: SCREEN_WIDTH,
+        y1: SCREEN_HEIGHT,
+    };
+
+    fn clipped(x: i64, y: i64, width: usize, height: usize) -> Self {
+        let clip = |value: i64, max: usize| value.clamp(0, max as i64) as usize;
+        Self {
+            x0: clip(x, SCREEN_WIDTH),
+            y0: clip(y, SCREEN_HEIGHT),
+            x1: clip(x + width as i64, SCREEN_WIDTH),
+            y1: clip(y + height as i64, SCREEN_HEIGHT),
+        }
+    }
+}
+
+struct Compositor {
+    /// bottom to top
+    surfaces: Vec<Surface>,
+    next_id: usize,
+    /// id of the surface keys go to
+    focus: Option<usize>,
+    pointer: (i32, i32),
+    /// the pointer moved since the last EV_SYN of the mouse
+    moved: bool,
+    /// of every input device, while there are surfaces
+    inputs: Vec<Arc<EventQueue>>,
+}
+
+lazy_static! {
+    static ref COMPOSITOR: UPIntrFreeCell<Compositor> = unsafe {
+        UPIntrFreeCell::new(Compositor {
+            surfaces: Vec::new(),
+            next_id: 0,
+            focus: None,
+            pointer: (SCREEN_WIDTH as i32 / 2, SCREEN_HEIGHT as i32 / 2),
+            moved: false,
+            inputs: Vec::new(),
+        })
+    };
+}
+
+impl Compositor {
+    fn surface_mut(&mut self, id: usize) -> Option<&mut Surface> {
+        self.surfaces.iter_mut().find(|surface| surface.id == id)
+    }
+
+    /// Index of the topmost surface under the pointer.
+    fn under_pointer(&self) -> Option<usize> {
+        let pointer = self.pointer;
+        self.surfaces
+            .iter()
+            .rposition(|surface| surface.contains(pointer))
+    }
+
+    /// Draw `rect` of the screen anew, the caller flushes.
+    fn compose(&self, rect: Rect) {
+        let fb = GPU_DEVICE.get_framebuffer();
+        for y in rect.y0..rect.y1 {
+            let row = &mut fb[y * STRIDE..(y + 1) * STRIDE];
+            for pixel in row[rect.x0 * BYTES_PER_PIXEL..rect.x1 * BYTES_PER_PIXEL]
+                .chunks_exact_mut(BYTES_PER_PIXEL)
+            {
+                pixel.copy_from_slice(&BACKGROUND);
+            }
+            for surface in self.surfaces.iter() {
+                let surface_y = y as i32 - surface.y;
+                if surface_y < 0 || surface_y >= surface.height as i32 {
+                    continue;
+                }
+                let x0 = surface.x.max(rect.x0 as i32) as usize;
+                let x1 = (surface.x + surface.width as i32).min(rect.x1 as i32);
+                if x1 <= x0 as i32 {
+                    continue;
+                }
+                let x1 = x1 as usize;
+                let start = (surface_y as usize * surface.width + (x0 as i32 - surface.x) as usize)
+                    * BYTES_PER_PIXEL;
+                surface
+                    .pixels
+                    .read(start, &mut row[x0 * BYTES_PER_PIXEL..x1 * BYTES_PER_PIXEL]);
+            }
+        }
+    }
+
+    /// Hand an event of any of the input devices on. Returns whether the
+    /// screen changed.
+    fn route(&mut self, type_: u16, code: u16, value: i32) -> bool {
+        if type_ == EV_KEY && code < BTN_MISC {
+            let (focus, pointer) = (self.focus, self.pointer);
+            if let Some(surface) = focus.and_then(|id| self.surface_mut(id)) {
+                surface.push_event(type_, code, value, pointer);
+            }
+            return false;
+        }
+        self.route_pointer(type_, code, value)
+    }
+
+    /// Returns whether the screen changed.
+    fn route_pointer(&mut self, type_: u16, code: u16, value: i32) -> bool {
+        let mut changed = false;
+        let (type_, code) = match (type_, code) {
+            (EV_REL, REL_X) => {
+                self.pointer.0 = (self.pointer.0 + value).clamp(0, SCREEN_WIDTH as i32 - 1);
+                self.moved = true;
+                return false;
+            }
+            (EV_REL, REL_Y) => {
+                self.pointer.1 = (self.pointer.1 + value).clamp(0, SCREEN_HEIGHT as i32 - 1);
+                self.moved = true;
+                return false;
+            }
+            (EV_ABS, ABS_X) => {
+                self.pointer.0 = (value.clamp(0, ABS_RANGE - 1) as i64 * SCREEN_WIDTH as i64
+                    / ABS_RANGE as i64) as i32;
+                self.moved = true;
+                return false;
+            }
+            (EV_ABS, ABS_Y) => {
+                self.pointer.1 = (value.clamp(0, ABS_RANGE - 1) as i64 * SCREEN_HEIGHT as i64
+                    / ABS_RANGE as i64) as i32;
+                self.moved = true;
+                return false;
+            }
+            (EV_SYN, _) if self.moved => {
+                self.moved = false;
+                (EV_ABS, 0)
+            }
+            (EV_KEY, _) | (EV_REL, _) => (type_, code),
+            _ => return false,
+        };
+        let Some(mut index) = self.under_pointer() else {
+            return false;
+        };
+        if type_ == EV_KEY && value == 1 {
+            self.focus = Some(self.surfaces[index].id);
+            if index + 1 < self.surfaces.len() {
+                let surface = self.surfaces.remove(index);
+                let rect = surface.on_screen(0, 0, surface.width, surface.height);
+                self.surfaces.push(surface);
+                index = self.surfaces.len() - 1;
+                self.compose(rect);
+                changed = true;
+            }
+        }
+        let pointer = self.pointer;
+        self.surfaces[index].push_event(type_, code, value, pointer);
+        changed
+    }
+}
+
+/// Hand what the input devices reported so far to the surfaces.
+fn route_input() {
+    let mut compositor = COMPOSITOR.exclusive_access();
+    let pointer = compositor.pointer;
+    let mut changed = false;
+    for device in compositor.inputs.clone() {
+        while let Some(event) = device.pop() {
+            changed |= compositor.route(event.type_(), event.code(), event.value());
+        }
+    }
+    let moved_to = compositor.pointer;
+    drop(compositor);
+    if changed {
+        GPU_DEVICE.flush();
+    }
+    if moved_to != pointer {
+        GPU_DEVICE.move_cursor(moved_to.0 as u32, moved_to.1 as u32);
+    }
+}
+
+/// Put a `width` x `height` surface with its corner at (`x`, `y`) of the
+/// screen, on top and focused, and return its id. It starts out black.
+pub fn create(x: isize, y: isize, width: usize, height: usize) -> SysResult {
+    if width == 0 || height == 0 || width > SCREEN_WIDTH || height > SCREEN_HEIGHT {
+        return Err(SysError::EINVAL);
+    }
+    // at least a pixel of it on the screen
+    if x <= -(width as isize)
+        || y <= -(height as isize)
+        || x >= SCREEN_WIDTH as isize
+        || y >= SCREEN_HEIGHT as isize
+    {
+        return Err(SysError::EINVAL);
+    }
+    let mut compositor = COMPOSITOR.exclusive_access();
+    if compositor.surfaces.len() == MAX_SURFACES {
+        return Err(SysError::ENOMEM);
+    }
+    let pixels = Pixels::new(width * height * BYTES_PER_PIXEL).ok_or(SysError::ENOMEM)?;
+    let first = compositor.surfaces.is_empty();
+    if first {
+        fbcon::release();
+        compositor.inputs = [&*KEYBOARD_DEVICE, &*MOUSE_DEVICE]
+            .into_iter()
+            .chain(TABLET_DEVICE.as_ref())
+            .map(|device| device.subscribe())
+            .collect();
+    }
+    let id = compositor.next_id;
+    compositor.next_id += 1;
+    let surface = Surface {
+        id,
+        x: x as i32,
+        y: y as i32,
+        width,
+        height,
+        pixels,
+        events: VecDeque::new(),
+    };
+    let rect = if first {
+        Rect::SCREEN
+    } else {
+        surface.on_screen(0, 0, width, height)
+    };
+    compositor.surfaces.push(surface);
+    compositor.focus = Some(id);
+    compositor.compose(rect);
+    let pointer = compositor.pointer;
+    drop(compositor);
+    GPU_DEVICE.flush();
+    if first {
+        GPU_DEVICE.move_cursor(pointer.0 as u32, pointer.1 as u32);
+    }
+    Ok(id)
+}
+
+/// Take surface `id` off the screen. Once the last one is gone, the text
+/// console gets the screen back.
+pub fn destroy(id: usize) {
+    let mut compositor = COMPOSITOR.exclusive_access();
+    let Some(index) = compositor
+        .surfaces
+        .iter()
+        .position(|surface| surface.id == id)
+    else {
+        return;
+    };
+    let surface = compositor.surfaces.remove(index);
+    if compositor.focus == Some(id) {
+        compositor.focus = compositor.surfaces.last().map(|surface| surface.id);
+    }
+    if compositor.surfaces.is_empty() {
+        compositor.inputs.clear();
+        drop(compositor);
+        fbcon::init();
+        return;
+    }
+    compositor.compose(surface.on_screen(0, 0, surface.width, surface.height));
+    drop(compositor);
+    GPU_DEVICE.flush();
+}
+
+/// Width and height of surface `id`.
+pub fn size(id: usize) -> Option<(usize, usize)> {
+    COMPOSITOR
+        .exclusive_access()
+        .surface_mut(id)
+        .map(|surface| (surface.width, surface.height))
+}
+
+/// Replace the pixels of surface `id` from byte `offset` on, rows of the
+/// width of the surface, with `bytes`, which the caller checked fit. They
+/// show once `update` is called.
+pub fn write(id: usize, offset: usize, bytes: &[u8]) {
+    let mut compositor = COMPOSITOR.exclusive_access();
+    if let Some(surface) = compositor.surface_mut(id) {
+        surface.pixels.write(offset, bytes);
+    }
+}
+
+/// Show the `width` x `height` pixels at (`x`, `y`) of surface `id`.
+pub fn update(id: usize, x: usize, y: usize, width: usize, height: usize) {
+    let mut compositor = COMPOSITOR.exclusive_access();
+    let Some(surface) = compositor.surface_mut(id) else {
+        return;
+    };
+    let rect = surface.on_screen(x, y, width, height);
+    compositor.compose(rect);
+    drop(compositor);
+    GPU_DEVICE.flush();
+}
+
+/// The oldest event routed to surface `id`.
+pub fn pop_event(id: usize) -> Option<SurfaceEvent> {
+    route_input();
+    COMPOSITOR
+        .exclusive_access()
+        .surface_mut(id)?
+        .events
+        .pop_front()
+}
+
+pub fn has_events(id: usize) -> bool {
+    route_input();
+    COMPOSITOR
+        .exclusive_access()
+        .surface_mut(id)
+        .map_or(false, |surface| !surface.events.is_empty())
+}
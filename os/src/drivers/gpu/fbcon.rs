@@ -0,0 +1,383 @@
+//! A text console on the virtio-gpu framebuffer, mirroring the UART so the
+//! system can be used on the graphical display alone.
+//!
+//! Glyphs come from the 8x13 bitmap font of embedded-graphics. Of the
+//! ANSI escape sequences, SGR colors and bold, cursor movement and
+//! erasing the screen or a line are understood, the rest is dropped.
+//!
+//! Writes only draw and note the rows they touched, the timer tick sends
+//! the framebuffer to the host when a row has changed since the last one.
+
+use super::GPU_DEVICE;
+use crate::board::{VIRTGPU_XRES, VIRTGPU_YRES};
+use crate::sync::UPIntrFreeCell;
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, Ordering};
+use embedded_graphics::mono_font::ascii::{FONT_8X13, FONT_8X13_BOLD};
+use embedded_graphics::mono_font::MonoTextStyleBuilder;
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::*;
+use embedded_graphics::text::{Baseline, Text};
+use lazy_static::*;
+
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 13;
+const COLS: usize = VIRTGPU_XRES as usize / CELL_WIDTH;
+const ROWS: usize = VIRTGPU_YRES as usize / CELL_HEIGHT;
+/// B8G8R8A8
+const BYTES_PER_PIXEL: usize = 4;
+const STRIDE: usize = VIRTGPU_XRES as usize * BYTES_PER_PIXEL;
+const TAB_WIDTH: usize = 8;
+
+/// The colors of SGR 30-37, then their bright variants of 90-97.
+const PALETTE: [Rgb888; 16] = [
+    Rgb888::new(0, 0, 0),
+    Rgb888::new(170, 0, 0),
+    Rgb888::new(0, 170, 0),
+    Rgb888::new(170, 85, 0),
+    Rgb888::new(0, 0, 170),
+    Rgb888::new(170, 0, 170),
+    Rgb888::new(0, 170, 170),
+    Rgb888::new(170, 170, 170),
+    Rgb888::new(85, 85, 85),
+    Rgb888::new(255, 85, 85),
+    Rgb888::new(85, 255, 85),
+    Rgb888::new(255, 255, 85),
+    Rgb888::new(85, 85, 255),
+    Rgb888::new(255, 85, 255),
+    Rgb888::new(85, 255, 255),
+    Rgb888::new(255, 255, 255),
+];
+const DEFAULT_FG: usize = 7;
+const DEFAULT_BG: usize = 0;
+
+/// The framebuffer as an embedded-graphics target.
+struct Surface<'a> {
+    fb: &'a mut [u8],
+}
+
+impl OriginDimensions for Surface<'_> {
+    fn size(&self) -> Size {
+        Size::new(VIRTGPU_XRES, VIRTGPU_YRES)
+    }
+}
+
+impl DrawTarget for Surface<'_> {
+    type Color = Rgb888;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x < 0 || point.y < 0 || point.x >= VIRTGPU_XRES as i32 {
+                continue;
+            }
+            let idx = point.y as usize * STRIDE + point.x as usize * BYTES_PER_PIXEL;
+            if let Some(pixel) = self.fb.get_mut(idx..idx + 3) {
+                pixel.copy_from_slice(&[color.b(), color.g(), color.r()]);
+            }
+        }
+        Ok(())
+    }
+}
+
+enum ParseState {
+    Normal,
+    /// after ESC
+    Escape,
+    /// after ESC [, with the numeric parameters so far
+    Csi(Vec<usize>),
+}
+
+struct FbConsoleInner {
+    col: usize,
+    row: usize,
+    fg: usize,
+    bg: usize,
+    bold: bool,
+    state: ParseState,
+    /// rows drawn on since the last flush
+    dirty: Option<Range<usize>>,
+}
+
+impl FbConsoleInner {
+    fn mark_dirty(&mut self, rows: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(rows.start)..dirty.end.max(rows.end),
+            None => rows,
+        });
+    }
+
+    fn fill_rows(&mut self, fb: &mut [u8], rows: core::ops::Range<usize>, color: Rgb888) {
+        let pixel = [color.b(), color.g(), color.r(), 0xff];
+        let start = rows.start * CELL_HEIGHT * STRIDE;
+        let end = (rows.end * CELL_HEIGHT * STRIDE).min(fb.len());
+        for chunk in fb[start..end].chunks_exact_mut(BYTES_PER_PIXEL) {
+            chunk.copy_from_slice(&pixel);
+        }
+        self.mark_dirty(rows);
+    }
+
+    fn fill_cells(&mut self, fb: &mut [u8], row: usize, cols: core::ops::Range<usize>) {
+        let color = PALETTE[self.bg];
+        let pixel = [color.b(), color.g(), color.r(), 0xff];
+        for y in row * CELL_HEIGHT..(row + 1) * CELL_HEIGHT {
+            let start = y * STRIDE + cols.start * CELL_WIDTH * BYTES_PER_PIXEL;
+            let end = y * STRIDE + cols.end * CELL_WIDTH * BYTES_PER_PIXEL;
+            for chunk in fb[start..end].chunks_exact_mut(BYTES_PER_PIXEL) {
+                chunk.copy_from_slice(&pixel);
+            }
+        }
+        self.mark_dirty(row..row + 1);
+    }
+
+    /// Invert the cell under the cursor, doing it twice restores it.
+    fn toggle_cursor(&mut self, fb: &mut [u8]) {
+        if self.row >= ROWS || self.col >= COLS {
+            return;
+        }
+        for y in self.row * CELL_HEIGHT..(self.row + 1) * CELL_HEIGHT {
+            let start = y * STRIDE + self.col * CELL_WIDTH * BYTES_PER_PIXEL;
+            for chunk in
+                fb[start..start + CELL_WIDTH * BYTES_PER_PIXEL].chunks_exact_mut(BYTES_PER_PIXEL)
+            {
+                for byte in chunk[..3].iter_mut() {
+                    *byte = !*byte;
+                }
+            }
+        }
+        self.mark_dirty(self.row..self.row + 1);
+    }
+
+    fn scroll_up(&mut self, fb: &mut [u8]) {
+        let line = CELL_HEIGHT * STRIDE;
+        fb.copy_within(line..ROWS * line, 0);
+        self.fill_rows(fb, ROWS - 1..ROWS, PALETTE[self.bg]);
+        self.mark_dirty(0..ROWS);
+    }
+
+    fn new_line(&mut self, fb: &mut [u8]) {
+        self.col = 0;
+        if self.row + 1 < ROWS {
+            self.row += 1;
+        } else {
+            self.scroll_up(fb);
+        }
+    }
+
+    fn draw_glyph(&mut self, fb: &mut [u8], ch: u8) {
+        if self.col >= COLS {
+            self.new_line(fb);
+        }
+        let ch = if ch.is_ascii_graphic() || ch == b' ' {
+            ch as char
+        } else {
+            '?'
+        };
+        let font = if self.bold {
+            &FONT_8X13_BOLD
+        } else {
+            &FONT_8X13
+        };
+        let fg = if self.bold && self.fg < 8 {
+            self.fg + 8
+        } else {
+            self.fg
+        };
+        let style = MonoTextStyleBuilder::new()
+            .font(font)
+            .text_color(PALETTE[fg])
+            .background_color(PALETTE[self.bg])
+            .build();
+        let position = Point::new(
+            (self.col * CELL_WIDTH) as i32,
+            (self.row * CELL_HEIGHT) as i32,
+        );
+        let mut buf = [0u8; 4];
+        let _ = Text::with_baseline(ch.encode_utf8(&mut buf), position, style, Baseline::Top)
+            .draw(&mut Surface { fb });
+        self.mark_dirty(self.row..self.row + 1);
+        self.col += 1;
+    }
+
+    /// SGR, the parameters of ESC [ ... m.
+    fn select_graphic_rendition(&mut self, params: &[usize]) {
+        for &param in params {
+            match param {
+                0 => {
+                    self.fg = DEFAULT_FG;
+                    self.bg = DEFAULT_BG;
+                    self.bold = false;
+                }
+                1 => self.bold = true,
+                22 => self.bold = false,
+                30..=37 => self.fg = param - 30,
+                39 => self.fg = DEFAULT_FG,
+                40..=47 => self.bg = param - 40,
+                49 => self.bg = DEFAULT_BG,
+                90..=97 => self.fg = param - 90 + 8,
+                100..=107 => self.bg = param - 100 + 8,
+                _ => {}
+            }
+        }
+    }
+
+    fn control_sequence(&mut self, fb: &mut [u8], params: &[usize], command: u8) {
+        // a missing or zero count means one
+        let count = params.first().copied().unwrap_or(0).max(1);
+        match command {
+            b'm' => self.select_graphic_rendition(params),
+            b'A' => self.row = self.row.saturating_sub(count),
+            b'B' => self.row = (self.row + count).min(ROWS - 1),
+            b'C' => self.col = (self.col + count).min(COLS - 1),
+            b'D' => self.col = self.col.saturating_sub(count),
+            b'H' | b'f' => {
+                self.row = (count - 1).min(ROWS - 1);
+                let col = params.get(1).copied().unwrap_or(0).max(1);
+                self.col = (col - 1).min(COLS - 1);
+            }
+            b'J' => match params.first().copied().unwrap_or(0) {
+                // from the cursor to the end of the screen
+                0 => {
+                    self.fill_cells(fb, self.row, self.col.min(COLS)..COLS);
+                    self.fill_rows(fb, self.row + 1..ROWS, PALETTE[self.bg]);
+                }
+                2 | 3 => self.fill_rows(fb, 0..ROWS, PALETTE[self.bg]),
+                _ => {}
+            },
+            b'K' => match params.first().copied().unwrap_or(0) {
+                0 => self.fill_cells(fb, self.row, self.col.min(COLS)..COLS),
+                1 => self.fill_cells(fb, self.row, 0..(self.col + 1).min(COLS)),
+                2 => self.fill_cells(fb, self.row, 0..COLS),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn put(&mut self, fb: &mut [u8], byte: u8) {
+        match core::mem::replace(&mut self.state, ParseState::Normal) {
+            ParseState::Normal => match byte {
+                0x1b => self.state = ParseState::Escape,
+                // the UART gets no carriage return after a line feed either,
+                // but a terminal emulator is set to add one
+                b'\n' => self.new_line(fb),
+                b'\r' => self.col = 0,
+                0x08 => self.col = self.col.saturating_sub(1),
+                b'\t' => self.col = ((self.col / TAB_WIDTH + 1) * TAB_WIDTH).min(COLS),
+                0x07 => {}
+                _ => self.draw_glyph(fb, byte),
+            },
+            ParseState::Escape => {
+                if byte == b'[' {
+                    self.state = ParseState::Csi(Vec::new());
+                }
+            }
+            ParseState::Csi(mut params) => match byte {
+                b'0'..=b'9' => {
+                    if params.is_empty() {
+                        params.push(0);
+                    }
+                    let last = params.last_mut().unwrap();
+                    *last = last
+                        .saturating_mul(10)
+                        .saturating_add((byte - b'0') as usize);
+                    self.state = ParseState::Csi(params);
+                }
+                b';' => {
+                    if params.is_empty() {
+                        params.push(0);
+                    }
+                    params.push(0);
+                    self.state = ParseState::Csi(params);
+                }
+                // private sequences such as ESC [ ? 25 h
+                b'?' => self.state = ParseState::Csi(params),
+                0x40..=0x7e => self.control_sequence(fb, &params, byte),
+                _ => {}
+            },
+        }
+    }
+}
+
+pub struct FbConsole {
+    inner: UPIntrFreeCell<FbConsoleInner>,
+}
+
+lazy_static! {
+    pub static ref FB_CONSOLE: FbConsole = FbConsole {
+        inner: unsafe {
+            UPIntrFreeCell::new(FbConsoleInner {
+                col: 0,
+                row: 0,
+                fg: DEFAULT_FG,
+                bg: DEFAULT_BG,
+                bold: false,
+                state: ParseState::Normal,
+                dirty: None,
+            })
+        },
+    };
+}
+
+/// Drawn on only between `init` and `release`.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+impl FbConsole {
+    /// Draw `bytes`, dropped if the console is already being drawn on by
+    /// whatever this interrupted.
+    pub fn write(&self, bytes: &[u8]) {
+        if !ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(mut inner) = self.inner.try_exclusive_access() else {
+            return;
+        };
+        let fb = GPU_DEVICE.get_framebuffer();
+        inner.toggle_cursor(fb);
+        for &byte in bytes {
+            inner.put(fb, byte);
+        }
+        inner.toggle_cursor(fb);
+    }
+}
+
+/// Send the framebuffer to the host if a row has been drawn on since the
+/// last time, called on the timer tick. virtio-drivers only transfers the
+/// whole framebuffer, so the dirty rows decide whether there is a flush
+/// at all.
+pub fn flush() {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let dirty = match FB_CONSOLE.inner.try_exclusive_access() {
+        Some(mut inner) => inner.dirty.take(),
+        None => None,
+    };
+    if dirty.is_some() {
+        GPU_DEVICE.flush();
+    }
+}
+
+/// Clear the display and start mirroring the UART on it, once the GPU is
+/// set up.
+pub fn init() {
+    let fb = GPU_DEVICE.get_framebuffer();
+    let mut inner = FB_CONSOLE.inner.exclusive_access();
+    inner.col = 0;
+    inner.row = 0;
+    inner.fill_rows(fb, 0..ROWS, PALETTE[DEFAULT_BG]);
+    inner.toggle_cursor(fb);
+    drop(inner);
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+/// Leave the display to a program drawing on the framebuffer itself, or
+/// to the compositor. [`init`] takes it back on an empty screen. The panic
+/// handler lets go of it too, whatever it panicked in may hold the GPU.
+pub fn release() {
+    ACTIVE.store(false, Ordering::Relaxed);
+}
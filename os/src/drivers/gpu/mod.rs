@@ -1,3 +1,6 @@
+pub mod compositor;
+pub mod fbcon;
+
 use crate::drivers::bus::virtio::VirtioHal;
 use crate::sync::UPIntrFreeCell;
 use alloc::{sync::Arc, vec::Vec};
@@ -8,6 +11,8 @@ use virtio_drivers::{VirtIOGpu, VirtIOHeader};
 const VIRTIO7: usize = 0x10007000;
 pub trait GpuDevice: Send + Sync + Any {
     fn update_cursor(&self);
+    /// Put the hot spot of the pointer at (`x`, `y`) on the screen.
+    fn move_cursor(&self, x: u32, y: u32);
     fn get_framebuffer(&self) -> &mut [u8];
     fn flush(&self);
 }
@@ -65,4 +70,7 @@ impl GpuDevice for VirtIOGpuWrapper {
         }
     }
     fn update_cursor(&self) {}
+    fn move_cursor(&self, x: u32, y: u32) {
+        self.gpu.exclusive_access().move_cursor(x, y).unwrap();
+    }
 }
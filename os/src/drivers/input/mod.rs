@@ -1,17 +1,66 @@
 use crate::drivers::bus::virtio::VirtioHal;
+use crate::drivers::bus::virtio_mmio::VirtioMmio;
 use crate::sync::{Condvar, UPIntrFreeCell};
 use crate::task::schedule;
+use crate::timer::realtime_ns;
 use alloc::collections::VecDeque;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 use core::any::Any;
 use virtio_drivers::{VirtIOHeader, VirtIOInput};
 
 const VIRTIO5: usize = 0x10005000;
 const VIRTIO6: usize = 0x10006000;
+const VIRTIO_ID_INPUT: u32 = 18;
+/// events a queue keeps before the oldest are dropped
+const MAX_QUEUED: usize = 256;
+
+/// An event as the driver packs it, type << 48 | code << 32 | value, with
+/// the wall clock time it came in at.
+#[derive(Copy, Clone)]
+pub struct TimedEvent {
+    pub raw: u64,
+    pub time_ns: u64,
+}
+
+impl TimedEvent {
+    pub fn type_(&self) -> u16 {
+        (self.raw >> 48) as u16
+    }
+    pub fn code(&self) -> u16 {
+        (self.raw >> 32) as u16
+    }
+    pub fn value(&self) -> i32 {
+        self.raw as i32
+    }
+}
+
+/// The events of a device for one reader of its own, see
+/// [`InputDevice::subscribe`].
+pub struct EventQueue {
+    events: UPIntrFreeCell<VecDeque<TimedEvent>>,
+}
+
+impl EventQueue {
+    fn push(&self, event: TimedEvent) {
+        let mut events = self.events.exclusive_access();
+        if events.len() == MAX_QUEUED {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+    pub fn pop(&self) -> Option<TimedEvent> {
+        self.events.exclusive_access().pop_front()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.events.exclusive_access().is_empty()
+    }
+}
 
 struct VirtIOInputInner {
     virtio_input: VirtIOInput<'static, VirtioHal>,
     events: VecDeque<u64>,
+    subscribers: Vec<Weak<EventQueue>>,
 }
 
 struct VirtIOInputWrapper {
@@ -23,11 +72,21 @@ pub trait InputDevice: Send + Sync + Any {
     fn read_event(&self) -> u64;
     fn handle_irq(&self);
     fn is_empty(&self) -> bool;
+    /// A queue getting every event from now on, while it is alive. The
+    /// events of `read_event` are not taken from it.
+    fn subscribe(&self) -> Arc<EventQueue>;
 }
 
 lazy_static::lazy_static!(
     pub static ref KEYBOARD_DEVICE: Arc<dyn InputDevice> = Arc::new(VirtIOInputWrapper::new(VIRTIO5));
     pub static ref MOUSE_DEVICE: Arc<dyn InputDevice> = Arc::new(VirtIOInputWrapper::new(VIRTIO6));
+    /// Slot of an input device besides the keyboard and the mouse, a
+    /// virtio-tablet reporting absolute positions. See TABLET in the Makefile.
+    pub static ref TABLET_SLOT: Option<usize> = VirtioMmio::find_all(VIRTIO_ID_INPUT)
+        .map(|mmio| mmio.base())
+        .find(|&base| base != VIRTIO5 && base != VIRTIO6);
+    pub static ref TABLET_DEVICE: Option<Arc<dyn InputDevice>> = TABLET_SLOT
+        .map(|base| Arc::new(VirtIOInputWrapper::new(base)) as Arc<dyn InputDevice>);
 );
 
 impl VirtIOInputWrapper {
@@ -37,6 +96,7 @@ impl VirtIOInputWrapper {
                 VirtIOInput::<VirtioHal>::new(&mut *(addr as *mut VirtIOHeader)).unwrap()
             },
             events: VecDeque::new(),
+            subscribers: Vec::new(),
         };
         Self {
             inner: unsafe { UPIntrFreeCell::new(inner) },
@@ -66,18 +126,42 @@ impl InputDevice for VirtIOInputWrapper {
     fn handle_irq(&self) {
         let mut count = 0;
         let mut result = 0;
+        let time_ns = realtime_ns();
         self.inner.exclusive_session(|inner| {
             inner.virtio_input.ack_interrupt();
+            inner
+                .subscribers
+                .retain(|subscriber| subscriber.strong_count() > 0);
             while let Some(event) = inner.virtio_input.pop_pending_event() {
                 count += 1;
                 result = (event.event_type as u64) << 48
                     | (event.code as u64) << 32
                     | (event.value) as u64;
+                if inner.events.len() == MAX_QUEUED {
+                    inner.events.pop_front();
+                }
                 inner.events.push_back(result);
+                for subscriber in inner.subscribers.iter().filter_map(Weak::upgrade) {
+                    subscriber.push(TimedEvent {
+                        raw: result,
+                        time_ns,
+                    });
+                }
             }
         });
         if count > 0 {
             self.condvar.signal();
         };
     }
+
+    fn subscribe(&self) -> Arc<EventQueue> {
+        let queue = Arc::new(EventQueue {
+            events: unsafe { UPIntrFreeCell::new(VecDeque::new()) },
+        });
+        self.inner
+            .exclusive_access()
+            .subscribers
+            .push(Arc::downgrade(&queue));
+        queue
+    }
 }
@@ -1,12 +1,15 @@
 pub mod block;
 pub mod bus;
 pub mod chardev;
+pub mod console;
 pub mod gpu;
 pub mod input;
 pub mod net;
+pub mod p9;
 pub mod plic;
+pub mod sound;
 
-pub use block::BLOCK_DEVICE;
+pub use block::{list_partitions, BLOCK_DEVICE, ROOT_DEVICE};
 pub use bus::*;
 pub use gpu::*;
 pub use input::*;
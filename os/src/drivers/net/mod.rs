@@ -14,7 +14,10 @@ lazy_static! {
 
 pub trait NetDevice: Send + Sync + Any {
     fn transmit(&self, data: &[u8]);
+    /// Waits for a frame if none came in yet.
     fn receive(&self, data: &mut [u8]) -> usize;
+    /// Whether a frame is there, so `receive` does not wait.
+    fn can_recv(&self) -> bool;
 }
 
 pub struct VirtIONetWrapper(UPIntrFreeCell<VirtIONet<'static, VirtioHal>>);
@@ -33,6 +36,10 @@ impl NetDevice for VirtIONetWrapper {
             .recv(data)
             .expect("can't receive data")
     }
+
+    fn can_recv(&self) -> bool {
+        self.0.exclusive_access().can_recv()
+    }
 }
 
 impl VirtIONetWrapper {
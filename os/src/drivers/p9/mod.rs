@@ -0,0 +1,103 @@
+//! virtio-9p, the transport only: a request goes out in one buffer and
+//! the reply comes back in another. The 9P protocol is spoken by fs::p9.
+//!
+//! Ref: the virtio spec only assigns the device id, the transport is
+//! described in "Plan 9 Remote Resource Sharing for Virtualization" (Van
+//! Hensbergen et al.) and the configuration in linux virtio_9p.h.
+//! The device is not part of the default QEMU command line, see SHARE in
+//! the Makefile. Like virtio-snd it is polled and takes no interrupts.
+
+use crate::drivers::bus::virtio_transport::VirtioTransport;
+use crate::drivers::bus::virtqueue::VirtQueue;
+use crate::mm::{dma_alloc, DmaBuffer};
+use crate::sync::UPIntrFreeCell;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const VIRTIO_ID_9P: u32 = 9;
+/// VIRTIO_9P_MOUNT_TAG, the tag is in the configuration
+const F_MOUNT_TAG: u32 = 1;
+
+const REQUEST_QUEUE: u16 = 0;
+const QUEUE_SIZE: u16 = 4;
+
+const PAGE_SIZE: usize = 4096;
+/// largest message either way, the msize offered in Tversion
+pub const MAX_MESSAGE: usize = 2 * PAGE_SIZE;
+
+struct VirtIO9pInner {
+    transport: VirtioTransport,
+    queue: VirtQueue,
+    /// the request, then the reply
+    dma: DmaBuffer,
+}
+
+/// A virtio-9p device, one request in flight at a time.
+pub struct VirtIO9p {
+    /// what the host calls the shared directory
+    tag: String,
+    inner: UPIntrFreeCell<VirtIO9pInner>,
+}
+
+lazy_static! {
+    /// None if the machine shares no directory.
+    pub static ref P9_DEVICE: Option<Arc<VirtIO9p>> = VirtioTransport::find(VIRTIO_ID_9P)
+        .and_then(VirtIO9p::probe)
+        .map(Arc::new);
+}
+
+impl VirtIO9p {
+    fn probe(transport: VirtioTransport) -> Option<Self> {
+        if transport.negotiate(F_MOUNT_TAG)? & F_MOUNT_TAG == 0 {
+            return None;
+        }
+        // struct virtio_9p_config: tag_len, then the tag without a NUL
+        let tag_len =
+            transport.config_read8(0) as usize | (transport.config_read8(1) as usize) << 8;
+        let tag_bytes: Vec<u8> = (0..tag_len)
+            .map(|i| transport.config_read8(2 + i))
+            .collect();
+        let inner = VirtIO9pInner {
+            transport,
+            queue: VirtQueue::new(QUEUE_SIZE),
+            dma: dma_alloc(2 * MAX_MESSAGE, PAGE_SIZE).unwrap(),
+        };
+        transport.setup_queue(REQUEST_QUEUE, &inner.queue);
+        transport.driver_ok();
+        Some(Self {
+            tag: String::from_utf8_lossy(&tag_bytes).into_owned(),
+            inner: unsafe { UPIntrFreeCell::new(inner) },
+        })
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Send the message in `request` and return the reply, both at most
+    /// [`MAX_MESSAGE`] bytes and starting with their size.
+    pub fn request(&self, request: &[u8]) -> Vec<u8> {
+        let mut guard = self.inner.exclusive_access();
+        let inner = &mut *guard;
+        let reply_at = inner.dma.paddr() + MAX_MESSAGE;
+        unsafe {
+            core::ptr::copy_nonoverlapping(request.as_ptr(), inner.dma.as_ptr(), request.len());
+        }
+        inner
+            .queue
+            .add(
+                &[(inner.dma.paddr(), request.len())],
+                &[(reply_at, MAX_MESSAGE)],
+            )
+            .unwrap();
+        inner.transport.notify(REQUEST_QUEUE);
+        let len = loop {
+            if let Some((_, len)) = inner.queue.pop_used() {
+                break (len as usize).min(MAX_MESSAGE);
+            }
+        };
+        unsafe { core::slice::from_raw_parts(reply_at as *const u8, len) }.to_vec()
+    }
+}
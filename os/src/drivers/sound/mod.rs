@@ -0,0 +1,374 @@
+//! virtio-snd, playback on the first output stream only.
+//!
+//! Ref: Virtual I/O Device (VIRTIO) Version 1.2, 5.14 "Sound Device".
+//! The device is not part of the default QEMU command line, see SOUND in
+//! the Makefile. On virtio-mmio the board routes the device's interrupt
+//! here and writers sleep until a buffer has played, on virtio-pci they
+//! look again a timer tick later.
+
+use crate::config::HZ;
+use crate::drivers::bus::virtio_transport::VirtioTransport;
+use crate::drivers::bus::virtqueue::VirtQueue;
+use crate::mm::{dma_alloc, DmaBuffer};
+use crate::sync::{Condvar, UPIntrFreeCell, UPIntrRefMut};
+use crate::task::{schedule, sleep_interruptible};
+use crate::timer::get_time_ns;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use lazy_static::*;
+
+const VIRTIO_ID_SOUND: u32 = 25;
+
+const CONTROL_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 2;
+const QUEUE_SIZE: u16 = 16;
+
+const R_PCM_INFO: u32 = 0x0100;
+const R_PCM_SET_PARAMS: u32 = 0x0101;
+const R_PCM_PREPARE: u32 = 0x0102;
+const R_PCM_RELEASE: u32 = 0x0103;
+const R_PCM_START: u32 = 0x0104;
+const R_PCM_STOP: u32 = 0x0105;
+const S_OK: u32 = 0x8000;
+const D_OUTPUT: u8 = 0;
+
+/// VIRTIO_SND_PCM_FMT_*
+pub const PCM_FMT_U8: u8 = 4;
+pub const PCM_FMT_S16: u8 = 5;
+/// VIRTIO_SND_PCM_RATE_*, indexed by the constant
+const PCM_RATES: [u32; 14] = [
+    5512, 8000, 11025, 16000, 22050, 32000, 44100, 48000, 64000, 88200, 96000, 176400, 192000,
+    384000,
+];
+
+const PAGE_SIZE: usize = 4096;
+/// buffers handed to the device at a time, a page each
+const SLOTS: usize = 4;
+/// offsets in the page of small structures
+const XFER_OFFSET: usize = 0;
+const STATUS_OFFSET: usize = 256;
+const REQUEST_OFFSET: usize = 1024;
+const RESPONSE_OFFSET: usize = 2048;
+
+/// `struct virtio_snd_pcm_info`.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct PcmInfo {
+    hda_fn_nid: u32,
+    features: u32,
+    formats: u64,
+    rates: u64,
+    direction: u8,
+    channels_min: u8,
+    channels_max: u8,
+    _padding: [u8; 5],
+}
+
+/// `struct virtio_snd_pcm_set_params`.
+#[repr(C)]
+struct PcmSetParams {
+    code: u32,
+    stream_id: u32,
+    buffer_bytes: u32,
+    period_bytes: u32,
+    features: u32,
+    channels: u8,
+    format: u8,
+    rate: u8,
+    _padding: u8,
+}
+
+/// Sample format, channel count and rate of the output stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PcmParams {
+    pub format: u8,
+    pub channels: u8,
+    pub rate: u32,
+}
+
+impl PcmParams {
+    fn frame_size(&self) -> usize {
+        let sample = if self.format == PCM_FMT_S16 { 2 } else { 1 };
+        sample * self.channels as usize
+    }
+}
+
+#[derive(Debug)]
+pub enum SoundError {
+    /// the stream cannot play this format, rate or channel count
+    Unsupported,
+    /// the device answered a request with this status
+    Device(u32),
+}
+
+struct VirtIOSoundInner {
+    transport: VirtioTransport,
+    control: VirtQueue,
+    tx: VirtQueue,
+    stream_id: u32,
+    info: PcmInfo,
+    params: PcmParams,
+    started: bool,
+    /// one page for requests and statuses, then a page per slot
+    dma: DmaBuffer,
+    /// head of the chain using each slot
+    in_flight: [Option<u16>; SLOTS],
+    /// the start of a frame a write ended in, queued with the next write
+    partial: Vec<u8>,
+}
+
+pub struct VirtIOSound {
+    inner: UPIntrFreeCell<VirtIOSoundInner>,
+    /// signalled when the device has used buffers of the tx queue
+    tx_done: Condvar,
+    /// the virtio-mmio slot, None on virtio-pci where nothing interrupts
+    slot: Option<usize>,
+}
+
+lazy_static! {
+    /// None if the machine has no sound device.
+    pub static ref SOUND_DEVICE: Option<Arc<VirtIOSound>> =
+        VirtioTransport::find(VIRTIO_ID_SOUND)
+            .and_then(VirtIOSound::probe)
+            .map(Arc::new);
+}
+
+impl VirtIOSoundInner {
+    /// Send `request` on the control queue and wait for the device to
+    /// answer with a status and `response_len` more bytes.
+    fn request<T>(&mut self, request: &T, response_len: usize) -> Result<(), SoundError> {
+        let request_len = core::mem::size_of::<T>();
+        let request_at = self.dma.paddr() + REQUEST_OFFSET;
+        let response_at = self.dma.paddr() + RESPONSE_OFFSET;
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                request as *const T as *const u8,
+                request_at as *mut u8,
+                request_len,
+            );
+        }
+        self.control
+            .add(
+                &[(request_at, request_len)],
+                &[(response_at, 4 + response_len)],
+            )
+            .unwrap();
+        self.transport.notify(CONTROL_QUEUE);
+        while self.control.pop_used().is_none() {}
+        match unsafe { read_volatile(response_at as *const u32) } {
+            S_OK => Ok(()),
+            status => Err(SoundError::Device(status)),
+        }
+    }
+
+    fn pcm_command(&mut self, code: u32) -> Result<(), SoundError> {
+        self.request(&[code, self.stream_id], 0)
+    }
+
+    fn set_params(&mut self, params: PcmParams) -> Result<(), SoundError> {
+        let rate = PCM_RATES
+            .iter()
+            .position(|&rate| rate == params.rate)
+            .filter(|&index| self.info.rates & 1 << index != 0)
+            .ok_or(SoundError::Unsupported)?;
+        if self.info.formats & 1 << params.format == 0
+            || !(self.info.channels_min..=self.info.channels_max).contains(&params.channels)
+        {
+            return Err(SoundError::Unsupported);
+        }
+        self.stop();
+        // fails harmlessly the first time, when nothing is prepared yet
+        let _ = self.pcm_command(R_PCM_RELEASE);
+        self.request(
+            &PcmSetParams {
+                code: R_PCM_SET_PARAMS,
+                stream_id: self.stream_id,
+                buffer_bytes: (SLOTS * PAGE_SIZE) as u32,
+                period_bytes: PAGE_SIZE as u32,
+                features: 0,
+                channels: params.channels,
+                format: params.format,
+                rate: rate as u8,
+                _padding: 0,
+            },
+            0,
+        )?;
+        self.pcm_command(R_PCM_PREPARE)?;
+        self.params = params;
+        self.partial.clear();
+        Ok(())
+    }
+
+    /// Free the slots of buffers the device is done with.
+    fn reclaim(&mut self) {
+        while let Some((head, _)) = self.tx.pop_used() {
+            for slot in self.in_flight.iter_mut() {
+                if *slot == Some(head) {
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    /// Queue up to a page of `data` in a free slot, None if all are busy.
+    fn submit(&mut self, data: &[u8]) -> Option<usize> {
+        self.reclaim();
+        let slot = self.in_flight.iter().position(Option::is_none)?;
+        let len = data.len().min(PAGE_SIZE) / self.params.frame_size() * self.params.frame_size();
+        let xfer_at = self.dma.paddr() + XFER_OFFSET + slot * 4;
+        let status_at = self.dma.paddr() + STATUS_OFFSET + slot * 8;
+        let buffer_at = self.dma.paddr() + (1 + slot) * PAGE_SIZE;
+        unsafe {
+            write_volatile(xfer_at as *mut u32, self.stream_id);
+            core::ptr::copy_nonoverlapping(data.as_ptr(), buffer_at as *mut u8, len);
+        }
+        let head = self
+            .tx
+            .add(&[(xfer_at, 4), (buffer_at, len)], &[(status_at, 8)])?;
+        self.in_flight[slot] = Some(head);
+        self.transport.notify(TX_QUEUE);
+        if !self.started {
+            self.started = self.pcm_command(R_PCM_START).is_ok();
+        }
+        Some(len)
+    }
+
+    fn stop(&mut self) {
+        if self.started {
+            let _ = self.pcm_command(R_PCM_STOP);
+            self.started = false;
+        }
+    }
+}
+
+impl VirtIOSound {
+    /// Bring up the device behind `transport` with its first output stream
+    /// set to 8-bit mono at 8000Hz, the defaults of /dev/dsp.
+    fn probe(transport: VirtioTransport) -> Option<Self> {
+        transport.negotiate(0)?;
+        let mut inner = VirtIOSoundInner {
+            transport,
+            control: VirtQueue::new(QUEUE_SIZE),
+            tx: VirtQueue::new(QUEUE_SIZE),
+            stream_id: 0,
+            info: PcmInfo::default(),
+            params: PcmParams {
+                format: PCM_FMT_U8,
+                channels: 1,
+                rate: 8000,
+            },
+            started: false,
+            dma: dma_alloc((1 + SLOTS) * PAGE_SIZE, PAGE_SIZE).unwrap(),
+            in_flight: [None; SLOTS],
+            partial: Vec::new(),
+        };
+        transport.setup_queue(CONTROL_QUEUE, &inner.control);
+        transport.setup_queue(TX_QUEUE, &inner.tx);
+        transport.driver_ok();
+
+        // virtio_snd_config: jacks, streams, chmaps
+        let streams = transport.config_read32(4);
+        let info_size = core::mem::size_of::<PcmInfo>();
+        for stream_id in 0..streams {
+            inner
+                .request(&[R_PCM_INFO, stream_id, 1, info_size as u32], info_size)
+                .ok()?;
+            let info = unsafe {
+                read_volatile((inner.dma.paddr() + RESPONSE_OFFSET + 4) as *const PcmInfo)
+            };
+            if info.direction == D_OUTPUT {
+                inner.stream_id = stream_id;
+                inner.info = info;
+                let params = inner.params;
+                inner.set_params(params).ok()?;
+                return Some(Self {
+                    inner: unsafe { UPIntrFreeCell::new(inner) },
+                    tx_done: Condvar::new(),
+                    slot: transport.mmio_slot(),
+                });
+            }
+        }
+        None
+    }
+
+    pub fn params(&self) -> PcmParams {
+        self.inner.exclusive_access().params
+    }
+
+    /// Wait for what is queued to play out, then switch to `params`.
+    pub fn set_params(&self, params: PcmParams) -> Result<(), SoundError> {
+        self.drain();
+        self.inner.exclusive_access().set_params(params)
+    }
+
+    /// Base of the virtio-mmio slot, for the board to route its interrupt.
+    pub fn mmio_slot(&self) -> Option<usize> {
+        self.slot
+    }
+
+    pub fn handle_irq(&self) {
+        self.inner.exclusive_session(|inner| {
+            inner.transport.ack_interrupt();
+            inner.reclaim();
+        });
+        self.tx_done.signal_all();
+    }
+
+    /// Sleep until the device has used a buffer, giving up `inner`.
+    fn wait_tx(&self, inner: UPIntrRefMut<'_, VirtIOSoundInner>) {
+        if self.slot.is_some() {
+            let task_cx_ptr = self.tx_done.wait_no_sched();
+            drop(inner);
+            schedule(task_cx_ptr);
+        } else {
+            drop(inner);
+            sleep_interruptible(Some(get_time_ns() + 1_000_000_000 / HZ as u64));
+        }
+    }
+
+    /// Queue all of `data`, waiting for the device to free buffers when
+    /// they are all in use. A trailing partial frame is kept and completed
+    /// by the next write, so every byte counts as written.
+    pub fn write(&self, mut data: &[u8]) -> usize {
+        let written = data.len();
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            let frame_size = inner.params.frame_size();
+            if !inner.partial.is_empty() {
+                let take = (frame_size - inner.partial.len()).min(data.len());
+                inner.partial.extend_from_slice(&data[..take]);
+                data = &data[take..];
+                if inner.partial.len() < frame_size {
+                    return written;
+                }
+                let frame = core::mem::take(&mut inner.partial);
+                if inner.submit(&frame).is_none() {
+                    inner.partial = frame;
+                    self.wait_tx(inner);
+                    continue;
+                }
+            }
+            if data.len() < frame_size {
+                inner.partial.extend_from_slice(data);
+                return written;
+            }
+            match inner.submit(data) {
+                Some(len) => data = &data[len..],
+                None => self.wait_tx(inner),
+            }
+        }
+    }
+
+    /// Wait until every queued buffer has been played.
+    pub fn drain(&self) {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            inner.reclaim();
+            if inner.tx.is_idle() {
+                return;
+            }
+            self.wait_tx(inner);
+        }
+    }
+}
@@ -1,9 +1,20 @@
     .section .text.entry
     .globl _start
 _start:
+    # the SBI passes the hart id in a0, the kernel keeps it in tp, and
+    # the device tree in a1, both stay arguments of rust_main
+    mv tp, a0
     la sp, boot_stack_top
     call rust_main
 
+    .section .text
+    .globl _start_secondary
+_start_secondary:
+    # a hart started by smp::boot_other_harts, a1 is the top of its stack
+    mv tp, a0
+    mv sp, a1
+    call rust_main_secondary
+
     .section .bss.stack
     .globl boot_stack_lower_bound
 boot_stack_lower_bound:
@@ -0,0 +1,154 @@
+//! What the flattened device tree the firmware passes in a1 says about
+//! memory: the RAM of the /memory node and the ranges the firmware keeps
+//! for itself, from /reserved-memory and the memory reservation block.
+//!
+//! Everything is read once at boot, before the frame allocator hands out
+//! the frames the tree itself lies in.
+
+use alloc::vec::Vec;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Physical memory as the device tree describes it, ranges as (start, end).
+#[derive(Debug, Default)]
+pub struct MemoryMap {
+    pub memory: Vec<(usize, usize)>,
+    pub reserved: Vec<(usize, usize)>,
+}
+
+struct Fdt {
+    base: *const u8,
+}
+
+impl Fdt {
+    fn be32(&self, offset: usize) -> u32 {
+        let bytes = unsafe { core::ptr::read_unaligned(self.base.add(offset) as *const [u8; 4]) };
+        u32::from_be_bytes(bytes)
+    }
+    fn be64(&self, offset: usize) -> u64 {
+        (self.be32(offset) as u64) << 32 | self.be32(offset + 4) as u64
+    }
+    /// A number of `cells` 32-bit cells, as reg has addresses and sizes.
+    fn cells(&self, offset: usize, cells: u32) -> usize {
+        (0..cells as usize).fold(0, |value, i| {
+            value << 32 | self.be32(offset + 4 * i) as usize
+        })
+    }
+    fn cstr(&self, offset: usize) -> &[u8] {
+        let mut len = 0;
+        while unsafe { *self.base.add(offset + len) } != 0 {
+            len += 1;
+        }
+        unsafe { core::slice::from_raw_parts(self.base.add(offset), len) }
+    }
+}
+
+/// Read the memory map from the tree at `dtb`, None if there is no valid
+/// tree there.
+pub fn memory_map(dtb: usize) -> Option<MemoryMap> {
+    if dtb == 0 || dtb % 4 != 0 {
+        return None;
+    }
+    let fdt = Fdt {
+        base: dtb as *const u8,
+    };
+    if fdt.be32(0) != FDT_MAGIC {
+        return None;
+    }
+    let struct_offset = fdt.be32(8) as usize;
+    let strings_offset = fdt.be32(12) as usize;
+    let rsvmap_offset = fdt.be32(16) as usize;
+    let mut map = MemoryMap::default();
+
+    // the reservation block, (address, size) pairs up to an empty one
+    let mut offset = rsvmap_offset;
+    loop {
+        let (start, size) = (fdt.be64(offset) as usize, fdt.be64(offset + 8) as usize);
+        if size == 0 {
+            break;
+        }
+        map.reserved.push((start, start + size));
+        offset += 16;
+    }
+
+    // #address-cells and #size-cells of the root and of /reserved-memory,
+    // which give the layout of reg in their children
+    let mut root_cells = (2, 1);
+    let mut reserved_cells = root_cells;
+    let mut depth = 0;
+    // the child of the root we are in is /memory or /reserved-memory
+    let mut in_memory = false;
+    let mut in_reserved = false;
+    let mut offset = struct_offset;
+    loop {
+        let token = fdt.be32(offset);
+        offset += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = fdt.cstr(offset);
+                offset = (offset + name.len() + 1).next_multiple_of(4);
+                depth += 1;
+                if depth == 2 {
+                    let node = name.split(|&byte| byte == b'@').next().unwrap();
+                    in_memory = node == b"memory";
+                    in_reserved = node == b"reserved-memory";
+                    if in_reserved {
+                        reserved_cells = root_cells;
+                    }
+                }
+            }
+            FDT_END_NODE => {
+                depth -= 1;
+                if depth == 1 {
+                    in_memory = false;
+                    in_reserved = false;
+                }
+            }
+            FDT_PROP => {
+                let len = fdt.be32(offset) as usize;
+                let name = fdt.cstr(strings_offset + fdt.be32(offset + 4) as usize);
+                let value = offset + 8;
+                offset = (value + len).next_multiple_of(4);
+                match (depth, name) {
+                    (1, b"#address-cells") => root_cells.0 = fdt.be32(value),
+                    (1, b"#size-cells") => root_cells.1 = fdt.be32(value),
+                    (2, b"#address-cells") if in_reserved => reserved_cells.0 = fdt.be32(value),
+                    (2, b"#size-cells") if in_reserved => reserved_cells.1 = fdt.be32(value),
+                    (2, b"reg") if in_memory => {
+                        map.memory.extend(fdt_ranges(&fdt, value, len, root_cells))
+                    }
+                    (3, b"reg") if in_reserved => {
+                        map.reserved
+                            .extend(fdt_ranges(&fdt, value, len, reserved_cells))
+                    }
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            _ => return None,
+        }
+    }
+    Some(map)
+}
+
+/// The (start, end) ranges of a reg property of `len` bytes at `value`.
+fn fdt_ranges(
+    fdt: &Fdt,
+    value: usize,
+    len: usize,
+    (address_cells, size_cells): (u32, u32),
+) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let entry = 4 * (address_cells + size_cells) as usize;
+    (0..len / entry).map(move |i| {
+        let offset = value + i * entry;
+        let start = fdt.cells(offset, address_cells);
+        let size = fdt.cells(offset + 4 * address_cells as usize, size_cells);
+        (start, start + size)
+    })
+}
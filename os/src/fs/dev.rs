@@ -0,0 +1,98 @@
+//! Character devices under /dev and the files of /proc, looked up by name
+//! before the disk.
+
+use super::dsp::Dsp;
+use super::evdev::EventDevice;
+use super::fbdev::Framebuffer;
+use super::hvc::Hvc;
+use super::proc::open_proc;
+use super::rtc::Rtc;
+use super::stdio::Terminal;
+use super::tty::{active_tty, TTYS};
+use super::{File, OpenFlags};
+use crate::config::NUM_VTS;
+use crate::drivers::console::CONSOLE_DEVICE;
+use crate::drivers::sound::SOUND_DEVICE;
+use crate::drivers::{KEYBOARD_DEVICE, MOUSE_DEVICE, TABLET_DEVICE};
+use crate::mm::UserBuffer;
+use crate::random::{add_entropy_bytes, get_random_bytes};
+use alloc::sync::Arc;
+
+/// /dev/random and /dev/urandom. The pool is seeded at boot, so both read
+/// without blocking; data written is mixed into the pool.
+pub struct RandomDevice {
+    readable: bool,
+    writable: bool,
+}
+
+impl File for RandomDevice {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        for slice in buf.buffers.iter_mut() {
+            get_random_bytes(slice);
+        }
+        buf.len()
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        for slice in buf.buffers.iter() {
+            add_entropy_bytes(slice);
+        }
+        buf.len()
+    }
+}
+
+/// Return None if `name` is neither a device nor under /proc.
+pub fn open_device(name: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    let (readable, writable) = flags.read_write();
+    match name {
+        "/dev/random" | "/dev/urandom" => Some(Arc::new(RandomDevice { readable, writable })),
+        "/dev/fb0" => Some(Arc::new(Framebuffer)),
+        "/dev/input/event0" => Some(Arc::new(EventDevice::new(
+            KEYBOARD_DEVICE.clone(),
+            "virtio keyboard",
+        ))),
+        "/dev/input/event1" => Some(Arc::new(EventDevice::new(
+            MOUSE_DEVICE.clone(),
+            "virtio mouse",
+        ))),
+        "/dev/input/event2" => TABLET_DEVICE.clone().map(|device| {
+            Arc::new(EventDevice::new(device, "virtio tablet")) as Arc<dyn File + Send + Sync>
+        }),
+        "/dev/rtc" | "/dev/rtc0" => Some(Arc::new(Rtc)),
+        "/dev/dsp" | "/dev/audio" => SOUND_DEVICE
+            .clone()
+            .map(|device| Arc::new(Dsp::new(device)) as Arc<dyn File + Send + Sync>),
+        _ => open_tty(name, readable, writable)
+            .or_else(|| open_proc(name))
+            .or_else(|| open_console_port(name)),
+    }
+}
+
+/// /dev/tty1 to /dev/ttyN for the virtual terminals, /dev/tty0 for the one
+/// active at the time of the open.
+fn open_tty(name: &str, readable: bool, writable: bool) -> Option<Arc<dyn File + Send + Sync>> {
+    let index: usize = name.strip_prefix("/dev/tty")?.parse().ok()?;
+    let tty = match index {
+        0 => active_tty(),
+        1..=NUM_VTS => &TTYS[index - 1],
+        _ => return None,
+    };
+    Some(Arc::new(Terminal::new(tty, readable, writable)))
+}
+
+/// /dev/hvcN by port id, or /dev/virtio-ports/NAME by the name the host
+/// gave the port.
+fn open_console_port(name: &str) -> Option<Arc<dyn File + Send + Sync>> {
+    let device = CONSOLE_DEVICE.clone()?;
+    let port = if let Some(id) = name.strip_prefix("/dev/hvc") {
+        id.parse().ok().filter(|&id| device.has_port(id))?
+    } else {
+        device.port_named(name.strip_prefix("/dev/virtio-ports/")?)?
+    };
+    Some(Arc::new(Hvc::new(device, port)))
+}
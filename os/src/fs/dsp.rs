@@ -0,0 +1,87 @@
+//! /dev/dsp and /dev/audio, playback in the style of OSS: written bytes
+//! are samples, the format is set by ioctl.
+
+use super::ioctl::{io, iowr, read_arg, write_arg};
+use super::File;
+use crate::drivers::sound::{PcmParams, VirtIOSound, PCM_FMT_S16, PCM_FMT_U8};
+use crate::mm::UserBuffer;
+use crate::syscall::{SysError, SysResult};
+use alloc::sync::Arc;
+
+pub const SNDCTL_DSP_SYNC: u32 = io(b'P', 1);
+pub const SNDCTL_DSP_SPEED: u32 = iowr::<i32>(b'P', 2);
+pub const SNDCTL_DSP_SETFMT: u32 = iowr::<i32>(b'P', 5);
+pub const SNDCTL_DSP_CHANNELS: u32 = iowr::<i32>(b'P', 6);
+
+const AFMT_U8: i32 = 0x08;
+const AFMT_S16_LE: i32 = 0x10;
+
+pub struct Dsp {
+    device: Arc<VirtIOSound>,
+}
+
+impl Dsp {
+    pub fn new(device: Arc<VirtIOSound>) -> Self {
+        Self { device }
+    }
+
+    /// Switch to the parameters `change` makes of the current ones,
+    /// EINVAL if the device cannot play them.
+    fn set(&self, change: impl FnOnce(&mut PcmParams)) -> Result<(), SysError> {
+        let mut params = self.device.params();
+        change(&mut params);
+        if params != self.device.params() {
+            self.device
+                .set_params(params)
+                .map_err(|_| SysError::EINVAL)?;
+        }
+        Ok(())
+    }
+}
+
+impl File for Dsp {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        buf.buffers
+            .iter()
+            .map(|slice| self.device.write(slice))
+            .sum()
+    }
+    /// The setters write back what is in effect, as OSS does.
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        match cmd {
+            SNDCTL_DSP_SYNC => self.device.drain(),
+            SNDCTL_DSP_SPEED => {
+                let rate: i32 = read_arg(arg)?;
+                self.set(|params| params.rate = rate as u32)?;
+                write_arg(arg, &(self.device.params().rate as i32))?;
+            }
+            SNDCTL_DSP_SETFMT => {
+                let afmt: i32 = read_arg(arg)?;
+                let format = match afmt {
+                    AFMT_U8 => PCM_FMT_U8,
+                    AFMT_S16_LE => PCM_FMT_S16,
+                    _ => return Err(SysError::EINVAL),
+                };
+                self.set(|params| params.format = format)?;
+            }
+            SNDCTL_DSP_CHANNELS => {
+                let channels: i32 = read_arg(arg)?;
+                if !(1..=2).contains(&channels) {
+                    return Err(SysError::EINVAL);
+                }
+                self.set(|params| params.channels = channels as u8)?;
+            }
+            _ => return Err(SysError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
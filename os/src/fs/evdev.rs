@@ -0,0 +1,119 @@
+//! /dev/input/event0 and event1, the virtio keyboard and mouse, and
+//! event2 for a virtio tablet if there is one. Every open file gets each
+//! event of its device, stamped with the time it came in, whoever else
+//! reads them.
+
+use super::ioctl::{ioc, ioc_base, ioc_size, ior, write_arg, write_bytes, IOC_READ};
+use super::{File, PollEvents, StatusFlags};
+use crate::drivers::{EventQueue, InputDevice};
+use crate::mm::UserBuffer;
+use crate::syscall::{SysError, SysResult};
+use crate::task::suspend_current_and_run_next;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+const EV_VERSION: i32 = 0x010001;
+
+pub const EVIOCGVERSION: u32 = ior::<i32>(b'E', 0x01);
+/// EVIOCGNAME(0), the size field holds the length of the buffer
+pub const EVIOCGNAME: u32 = ioc(IOC_READ, b'E', 0x06, 0);
+
+/// `struct input_event` of linux.
+#[repr(C)]
+struct InputEvent {
+    sec: i64,
+    usec: i64,
+    type_: u16,
+    code: u16,
+    value: i32,
+}
+
+pub struct EventDevice {
+    queue: Arc<EventQueue>,
+    name: &'static str,
+    status: StatusFlags,
+}
+
+impl EventDevice {
+    pub fn new(device: Arc<dyn InputDevice>, name: &'static str) -> Self {
+        Self {
+            queue: device.subscribe(),
+            name,
+            status: StatusFlags::default(),
+        }
+    }
+}
+
+impl File for EventDevice {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Whole events only, waiting for the first one and taking as many of
+    /// the queued ones as fit after it.
+    fn read(&self, buf: UserBuffer) -> usize {
+        let count = buf.len() / size_of::<InputEvent>();
+        let mut buf_iter = buf.into_iter();
+        for read in 0..count {
+            let timed = loop {
+                if let Some(timed) = self.queue.pop() {
+                    break timed;
+                }
+                if read > 0 {
+                    return read * size_of::<InputEvent>();
+                }
+                suspend_current_and_run_next();
+            };
+            let event = InputEvent {
+                sec: (timed.time_ns / 1_000_000_000) as i64,
+                usec: (timed.time_ns % 1_000_000_000 / 1000) as i64,
+                type_: timed.type_(),
+                code: timed.code(),
+                value: timed.value(),
+            };
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    &event as *const _ as *const u8,
+                    size_of::<InputEvent>(),
+                )
+            };
+            for (byte, byte_ref) in bytes.iter().zip(&mut buf_iter) {
+                unsafe {
+                    *byte_ref = *byte;
+                }
+            }
+        }
+        count * size_of::<InputEvent>()
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        if cmd == EVIOCGVERSION {
+            write_arg(arg, &EV_VERSION)?;
+            Ok(0)
+        } else if ioc_base(cmd) == EVIOCGNAME {
+            // truncated to the buffer but always terminated
+            let mut name: Vec<u8> = self.name.bytes().chain(Some(0)).collect();
+            name.truncate(ioc_size(cmd));
+            if let Some(last) = name.last_mut() {
+                *last = 0;
+            }
+            write_bytes(arg, &name)?;
+            Ok(name.len())
+        } else {
+            Err(SysError::ENOTTY)
+        }
+    }
+    fn poll(&self) -> PollEvents {
+        let mut events = PollEvents::empty();
+        events.set(PollEvents::POLLIN, !self.queue.is_empty());
+        events
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+}
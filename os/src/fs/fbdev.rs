@@ -0,0 +1,145 @@
+//! /dev/fb0, the virtio-gpu framebuffer. Pixels are drawn through the
+//! mapping of sys_framebuffer; the file only answers the fbdev ioctls.
+
+use super::ioctl::write_arg;
+use super::File;
+use crate::board::{VIRTGPU_XRES, VIRTGPU_YRES};
+use crate::drivers::GPU_DEVICE;
+use crate::mm::UserBuffer;
+use crate::syscall::{SysError, SysResult};
+
+pub const FBIOGET_VSCREENINFO: u32 = 0x4600;
+pub const FBIOGET_FSCREENINFO: u32 = 0x4602;
+pub const FBIOPAN_DISPLAY: u32 = 0x4606;
+
+const FB_TYPE_PACKED_PIXELS: u32 = 0;
+const FB_VISUAL_TRUECOLOR: u32 = 2;
+const BITS_PER_PIXEL: u32 = 32;
+
+/// `struct fb_bitfield` of linux.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+/// `struct fb_var_screeninfo` of linux.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct FbVarScreeninfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+/// `struct fb_fix_screeninfo` of linux.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct FbFixScreeninfo {
+    id: [u8; 16],
+    smem_start: usize,
+    smem_len: u32,
+    type_: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: usize,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+pub struct Framebuffer;
+
+impl Framebuffer {
+    fn var_screeninfo() -> FbVarScreeninfo {
+        // B8G8R8A8, blue in the lowest byte of every pixel
+        let channel = |offset| FbBitfield {
+            offset,
+            length: 8,
+            msb_right: 0,
+        };
+        FbVarScreeninfo {
+            xres: VIRTGPU_XRES,
+            yres: VIRTGPU_YRES,
+            xres_virtual: VIRTGPU_XRES,
+            yres_virtual: VIRTGPU_YRES,
+            bits_per_pixel: BITS_PER_PIXEL,
+            red: channel(16),
+            green: channel(8),
+            blue: channel(0),
+            transp: channel(24),
+            ..Default::default()
+        }
+    }
+
+    fn fix_screeninfo() -> FbFixScreeninfo {
+        let mut id = [0u8; 16];
+        id[..10].copy_from_slice(b"virtio-gpu");
+        FbFixScreeninfo {
+            id,
+            smem_len: GPU_DEVICE.get_framebuffer().len() as u32,
+            type_: FB_TYPE_PACKED_PIXELS,
+            visual: FB_VISUAL_TRUECOLOR,
+            line_length: VIRTGPU_XRES * BITS_PER_PIXEL / 8,
+            ..Default::default()
+        }
+    }
+}
+
+impl File for Framebuffer {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        match cmd {
+            FBIOGET_VSCREENINFO => write_arg(arg, &Self::var_screeninfo())?,
+            FBIOGET_FSCREENINFO => write_arg(arg, &Self::fix_screeninfo())?,
+            // there is a single buffer, panning only pushes it to the host
+            FBIOPAN_DISPLAY => GPU_DEVICE.flush(),
+            _ => return Err(SysError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
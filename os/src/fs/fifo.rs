@@ -0,0 +1,157 @@
+use super::inode::lookup;
+use super::pipe::{pipe_capacity, Pipe, PipeRingBuffer};
+use super::{File, OpenFlags, PollEvents, StatusFlags};
+use crate::mm::UserBuffer;
+use crate::sync::{Condvar, UPIntrFreeCell};
+use crate::task::schedule;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use lazy_static::*;
+
+/// In-memory state of a named pipe which has been opened at least once.
+/// All openers of the same direction share a single pipe end, so the end
+/// is closed when the last of them goes away.
+struct FifoSlot {
+    buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>,
+    read_end: Weak<Pipe>,
+    write_end: Weak<Pipe>,
+    /// bumped every time an end is opened, so that a waiting opener notices
+    /// a peer even if it has already gone again
+    read_opens: usize,
+    write_opens: usize,
+    /// openers waiting for a peer
+    waiters: Arc<Condvar>,
+}
+
+impl FifoSlot {
+    fn new() -> Self {
+        Self {
+            buffer: Arc::new(unsafe { UPIntrFreeCell::new(PipeRingBuffer::new(pipe_capacity(0))) }),
+            read_end: Weak::new(),
+            write_end: Weak::new(),
+            read_opens: 0,
+            write_opens: 0,
+            waiters: Arc::new(Condvar::new()),
+        }
+    }
+    fn unused(&self) -> bool {
+        self.read_end.upgrade().is_none() && self.write_end.upgrade().is_none()
+    }
+    fn open_read_end(&mut self) -> Arc<Pipe> {
+        self.read_opens += 1;
+        self.read_end.upgrade().unwrap_or_else(|| {
+            let read_end = Arc::new(Pipe::read_end_with_buffer(self.buffer.clone()));
+            self.buffer.exclusive_access().set_read_end(&read_end);
+            self.read_end = Arc::downgrade(&read_end);
+            read_end
+        })
+    }
+    fn open_write_end(&mut self) -> Arc<Pipe> {
+        self.write_opens += 1;
+        self.write_end.upgrade().unwrap_or_else(|| {
+            let write_end = Arc::new(Pipe::write_end_with_buffer(self.buffer.clone()));
+            self.buffer.exclusive_access().set_write_end(&write_end);
+            self.write_end = Arc::downgrade(&write_end);
+            write_end
+        })
+    }
+}
+
+lazy_static! {
+    /// FIFOs by inode number, every path to one leads to the same slot.
+    static ref FIFOS: UPIntrFreeCell<BTreeMap<u32, FifoSlot>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+pub struct Fifo {
+    read_end: Option<Arc<Pipe>>,
+    write_end: Option<Arc<Pipe>>,
+    status: StatusFlags,
+}
+
+/// Return None if `path` is not a FIFO. Opening only one direction blocks
+/// until the other one has been opened as well.
+pub fn open_fifo(path: &str, flags: OpenFlags) -> Option<Arc<Fifo>> {
+    let inode = lookup(path)?;
+    if !inode.is_fifo() {
+        return None;
+    }
+    let id = inode.inode_number();
+    let (readable, writable) = flags.read_write();
+    let mut fifos = FIFOS.exclusive_access();
+    if fifos.get(&id).map_or(true, |slot| slot.unused()) {
+        // data left in an abandoned FIFO is discarded
+        fifos.insert(id, FifoSlot::new());
+    }
+    let slot = fifos.get_mut(&id).unwrap();
+    let read_end = readable.then(|| slot.open_read_end());
+    let write_end = writable.then(|| slot.open_write_end());
+    slot.waiters.signal_all();
+    if !(readable && writable) {
+        let peer_opens = |slot: &FifoSlot| {
+            if readable {
+                slot.write_opens
+            } else {
+                slot.read_opens
+            }
+        };
+        let seen = peer_opens(slot);
+        let peer_present = |slot: &FifoSlot| {
+            if readable {
+                slot.write_end.upgrade().is_some()
+            } else {
+                slot.read_end.upgrade().is_some()
+            }
+        };
+        if !peer_present(slot) {
+            let waiters = slot.waiters.clone();
+            loop {
+                let task_cx_ptr = waiters.wait_no_sched();
+                drop(fifos);
+                schedule(task_cx_ptr);
+                fifos = FIFOS.exclusive_access();
+                if peer_opens(fifos.get(&id).unwrap()) != seen {
+                    break;
+                }
+            }
+        }
+    }
+    Some(Arc::new(Fifo {
+        read_end,
+        write_end,
+        status: StatusFlags::default(),
+    }))
+}
+
+impl File for Fifo {
+    fn readable(&self) -> bool {
+        self.read_end.is_some()
+    }
+    fn writable(&self) -> bool {
+        self.write_end.is_some()
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        let nonblocking = self.status.get().contains(OpenFlags::NONBLOCK);
+        self.read_end
+            .as_ref()
+            .unwrap()
+            .read_or_wait(buf, nonblocking)
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let nonblocking = self.status.get().contains(OpenFlags::NONBLOCK);
+        self.write_end
+            .as_ref()
+            .unwrap()
+            .write_or_wait(buf, nonblocking)
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+    fn poll(&self) -> PollEvents {
+        let mut events = PollEvents::empty();
+        for end in self.read_end.iter().chain(self.write_end.iter()) {
+            events |= end.poll();
+        }
+        events
+    }
+}
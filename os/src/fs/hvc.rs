@@ -0,0 +1,107 @@
+//! /dev/hvcN, the ports of the virtio-console as terminals. Each port has
+//! the line discipline of a pty slave, with the port in place of the
+//! master.
+
+use super::pty::{copy_to_user, PtyInner};
+use super::tty::{LocalModes, VMIN};
+use super::{File, PollEvents, StatusFlags};
+use crate::drivers::console::VirtIOConsole;
+use crate::mm::UserBuffer;
+use crate::sync::UPIntrFreeCell;
+use crate::syscall::SysResult;
+use crate::task::suspend_current_and_run_next;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+lazy_static! {
+    /// Line state of each port, kept across opens as for a real terminal.
+    static ref LINES: UPIntrFreeCell<BTreeMap<usize, Arc<UPIntrFreeCell<PtyInner>>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+pub struct Hvc {
+    device: Arc<VirtIOConsole>,
+    port: usize,
+    line: Arc<UPIntrFreeCell<PtyInner>>,
+    status: StatusFlags,
+}
+
+impl Hvc {
+    pub fn new(device: Arc<VirtIOConsole>, port: usize) -> Self {
+        let line = LINES
+            .exclusive_access()
+            .entry(port)
+            .or_insert_with(|| Arc::new(unsafe { UPIntrFreeCell::new(PtyInner::new()) }))
+            .clone();
+        Self {
+            device,
+            port,
+            line,
+            status: StatusFlags::default(),
+        }
+    }
+
+    /// Run what the port received through the line discipline and send
+    /// what it produced, echo included.
+    fn pump(&self) {
+        let mut received = [0u8; 64];
+        let len = self.device.read(self.port, &mut received);
+        let mut line = self.line.exclusive_access();
+        for &ch in received[..len].iter() {
+            line.receive_byte(ch);
+        }
+        let output: Vec<u8> = line.output.drain(..).collect();
+        drop(line);
+        if !output.is_empty() {
+            self.device.write(self.port, &output);
+        }
+    }
+}
+
+impl File for Hvc {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        loop {
+            self.pump();
+            let mut line = self.line.exclusive_access();
+            if !line.input.is_empty() {
+                return copy_to_user(&mut buf, &mut line.input);
+            }
+            if line.eof {
+                line.eof = false;
+                return 0;
+            }
+            if !line.termios.lflag().contains(LocalModes::ICANON) && line.termios.cc[VMIN] == 0 {
+                return 0;
+            }
+            drop(line);
+            suspend_current_and_run_next();
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        for slice in buf.buffers.iter() {
+            self.device.write(self.port, slice);
+        }
+        buf.len()
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        self.line.exclusive_access().ioctl(cmd, arg)
+    }
+    fn poll(&self) -> PollEvents {
+        self.pump();
+        let line = self.line.exclusive_access();
+        let mut events = PollEvents::POLLOUT;
+        events.set(PollEvents::POLLIN, !line.input.is_empty() || line.eof);
+        events
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+}
@@ -1,30 +1,86 @@
-use super::File;
-use crate::drivers::BLOCK_DEVICE;
-use crate::mm::UserBuffer;
+use super::{Dirent, File, StatusFlags};
+use crate::config::{PAGE_SIZE, READAHEAD_BLOCKS};
+use crate::drivers::ROOT_DEVICE;
+use crate::mm::{page_cache, UserBuffer};
 use crate::sync::UPIntrFreeCell;
+use crate::timer::realtime_ns;
+use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::*;
-use easy_fs::{EasyFileSystem, Inode};
+use easy_fs::{EasyFileSystem, Inode, BLOCK_SZ};
 use lazy_static::*;
 
 pub struct OSInode {
     readable: bool,
     writable: bool,
+    /// where it was opened, for the *at calls relative to a directory
+    path: String,
+    status: StatusFlags,
     inner: UPIntrFreeCell<OSInodeInner>,
 }
 
 pub struct OSInodeInner {
     offset: usize,
     inode: Arc<Inode>,
+    readahead: ReadAhead,
+}
+
+/// Sequential reads of an open file, which have the disk read ahead of
+/// them.
+#[derive(Default)]
+struct ReadAhead {
+    /// where the next read starts if it goes on from the last
+    next: usize,
+    /// bytes to read ahead, doubling with every sequential read
+    window: usize,
+}
+
+impl ReadAhead {
+    /// Note a read of `len` bytes at `offset`, return how far ahead of
+    /// it the disk should read.
+    fn advance(&mut self, offset: usize, len: usize) -> usize {
+        if offset != self.next {
+            self.window = 0;
+        } else {
+            self.window = (self.window * 2)
+                .max(PAGE_SIZE)
+                .min(READAHEAD_BLOCKS * BLOCK_SZ);
+        }
+        self.next = offset + len;
+        self.window
+    }
+}
+
+impl OSInodeInner {
+    /// Read at the offset and move past what was read.
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let window = self.readahead.advance(self.offset, buf.len());
+        if window > 0 {
+            // the blocks of this read are waited for one after another,
+            // the later ones are on their way meanwhile
+            page_cache::read_ahead(&self.inode, self.offset, buf.len() + window);
+        }
+        let len = page_cache::read(&self.inode, self.offset, buf);
+        self.offset += len;
+        len
+    }
 }
 
 impl OSInode {
-    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+    pub fn new(readable: bool, writable: bool, path: String, inode: Arc<Inode>) -> Self {
         Self {
             readable,
             writable,
-            inner: unsafe { UPIntrFreeCell::new(OSInodeInner { offset: 0, inode }) },
+            path,
+            status: StatusFlags::default(),
+            inner: unsafe {
+                UPIntrFreeCell::new(OSInodeInner {
+                    offset: 0,
+                    inode,
+                    readahead: ReadAhead::default(),
+                })
+            },
         }
     }
     pub fn read_all(&self) -> Vec<u8> {
@@ -32,20 +88,33 @@ impl OSInode {
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
         loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
+            let len = inner.read(&mut buffer);
             if len == 0 {
                 break;
             }
-            inner.offset += len;
             v.extend_from_slice(&buffer[..len]);
         }
         v
     }
+    pub fn write_all(&self, data: &[u8]) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        let len = page_cache::write(&inner.inode, inner.offset, data);
+        inner.offset += len;
+        len
+    }
 }
 
+/// Longest name in a directory, with its terminating NUL.
+pub const DIRENT_NAME_MAX: usize = 28;
+
+const DT_FIFO: u8 = 1;
+const DT_DIR: u8 = 4;
+const DT_REG: u8 = 8;
+
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        let efs = EasyFileSystem::open(ROOT_DEVICE.clone());
+        efs.lock().set_clock(realtime_ns);
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
 }
@@ -65,6 +134,10 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        /// every write goes to the end of the file
+        const APPEND = 1 << 11;
+        /// reads and writes which would block fail with EAGAIN
+        const NONBLOCK = 1 << 12;
     }
 }
 
@@ -72,37 +145,92 @@ impl OpenFlags {
     /// Do not check validity for simplicity
     /// Return (readable, writable)
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
+        let access = *self & (Self::WRONLY | Self::RDWR);
+        if access.is_empty() {
             (true, false)
-        } else if self.contains(Self::WRONLY) {
+        } else if access.contains(Self::WRONLY) {
             (false, true)
         } else {
             (true, true)
         }
     }
+    /// The flags kept in `StatusFlags`.
+    pub fn status(self) -> Self {
+        self & (Self::APPEND | Self::NONBLOCK)
+    }
 }
 
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+/// Walk `path` from the root, `.` and `..` included.
+pub fn lookup(path: &str) -> Option<Arc<Inode>> {
+    let mut dirs: Vec<Arc<Inode>> = Vec::new();
+    let mut inode = ROOT_INODE.clone();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => inode = dirs.pop().unwrap_or_else(|| ROOT_INODE.clone()),
+            name => {
+                let next = inode.find(name)?;
+                dirs.push(core::mem::replace(&mut inode, next));
+            }
+        }
+    }
+    Some(inode)
+}
+
+/// The directory holding the last component of `path`, and that name.
+pub fn lookup_parent(path: &str) -> Option<(Arc<Inode>, &str)> {
+    let path = path.trim_end_matches('/');
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if matches!(name, "" | "." | "..") {
+        return None;
+    }
+    let dir = lookup(dir)?;
+    if !dir.is_dir() {
+        return None;
+    }
+    Some((dir, name))
+}
+
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
+    let inode = if flags.contains(OpenFlags::CREATE) {
+        let (dir, name) = lookup_parent(path)?;
+        if let Some(inode) = dir.find(name) {
             // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
+            if !inode.is_dir() {
+                page_cache::invalidate(&inode);
+                inode.clear();
+            }
+            inode
         } else {
             // create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
+            dir.create(name)?
         }
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
-            Arc::new(OSInode::new(readable, writable, inode))
-        })
+        let inode = lookup(path)?;
+        if flags.contains(OpenFlags::TRUNC) && !inode.is_dir() {
+            page_cache::invalidate(&inode);
+            inode.clear();
+        }
+        inode
+    };
+    // directories are only listed, their entries change through the syscalls
+    if inode.is_dir() && writable {
+        return None;
     }
+    Some(Arc::new(OSInode::new(
+        readable,
+        writable,
+        path.to_string(),
+        inode,
+    )))
+}
+
+/// Fail if `path` already exists.
+pub fn make_fifo(path: &str) -> bool {
+    lookup_parent(path)
+        .and_then(|(dir, name)| dir.create_fifo(name))
+        .is_some()
 }
 
 impl File for OSInode {
@@ -116,24 +244,73 @@ impl File for OSInode {
         let mut inner = self.inner.exclusive_access();
         let mut total_read_size = 0usize;
         for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
+            let read_size = inner.read(slice);
             if read_size == 0 {
                 break;
             }
-            inner.offset += read_size;
             total_read_size += read_size;
         }
         total_read_size
     }
     fn write(&self, buf: UserBuffer) -> usize {
         let mut inner = self.inner.exclusive_access();
+        if self.status.get().contains(OpenFlags::APPEND) {
+            inner.offset = inner.inode.size();
+        }
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
+            let write_size = page_cache::write(&inner.inode, inner.offset, slice);
             assert_eq!(write_size, slice.len());
             inner.offset += write_size;
             total_write_size += write_size;
         }
         total_write_size
     }
+    fn read_dir(&self, max_len: usize, record: fn(&Dirent) -> Vec<u8>) -> Option<Vec<u8>> {
+        let mut inner = self.inner.exclusive_access();
+        if !inner.inode.is_dir() {
+            return None;
+        }
+        // the offset of a directory counts entries
+        let mut records = Vec::new();
+        while let Some((name, inode)) = inner.inode.dirent(inner.offset) {
+            // a removed entry
+            let inode = match inode {
+                Some(inode) => inode,
+                None => {
+                    inner.offset += 1;
+                    continue;
+                }
+            };
+            let kind = if inode.is_dir() {
+                DT_DIR
+            } else if inode.is_fifo() {
+                DT_FIFO
+            } else {
+                DT_REG
+            };
+            let bytes = record(&Dirent {
+                ino: inode.inode_number() as u64,
+                kind,
+                name: name.as_str(),
+                next_offset: inner.offset + 1,
+            });
+            if records.len() + bytes.len() > max_len {
+                break;
+            }
+            inner.offset += 1;
+            records.extend_from_slice(&bytes);
+        }
+        Some(records)
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+    fn inode(&self) -> Option<Arc<Inode>> {
+        Some(self.inner.exclusive_access().inode.clone())
+    }
+    fn dir_path(&self) -> Option<String> {
+        let is_dir = self.inner.exclusive_access().inode.is_dir();
+        is_dir.then(|| self.path.clone())
+    }
 }
@@ -0,0 +1,90 @@
+//! Request numbers and argument passing of ioctl.
+//!
+//! Numbers follow the linux `_IOC` layout: the direction of the transfer
+//! and the size of the argument above a type letter per driver and a
+//! command number. The terminal requests such as TCGETS are older than
+//! that layout and carry neither.
+
+use crate::mm::{translated_byte_buffer, translated_byte_buffer_mut};
+use crate::syscall::SysError;
+use crate::task::current_user_token;
+use core::mem::{size_of, MaybeUninit};
+
+const IOC_TYPESHIFT: u32 = 8;
+const IOC_SIZESHIFT: u32 = 16;
+const IOC_DIRSHIFT: u32 = 30;
+const IOC_SIZEMASK: u32 = (1 << 14) - 1;
+
+/// the argument is no pointer
+pub const IOC_NONE: u32 = 0;
+/// user space passes data in
+pub const IOC_WRITE: u32 = 1;
+/// user space gets data out
+pub const IOC_READ: u32 = 2;
+
+pub const fn ioc(dir: u32, type_: u8, nr: u8, size: usize) -> u32 {
+    dir << IOC_DIRSHIFT
+        | (size as u32) << IOC_SIZESHIFT
+        | (type_ as u32) << IOC_TYPESHIFT
+        | nr as u32
+}
+
+pub const fn io(type_: u8, nr: u8) -> u32 {
+    ioc(IOC_NONE, type_, nr, 0)
+}
+
+pub const fn ior<T>(type_: u8, nr: u8) -> u32 {
+    ioc(IOC_READ, type_, nr, size_of::<T>())
+}
+
+pub const fn iow<T>(type_: u8, nr: u8) -> u32 {
+    ioc(IOC_WRITE, type_, nr, size_of::<T>())
+}
+
+pub const fn iowr<T>(type_: u8, nr: u8) -> u32 {
+    ioc(IOC_READ | IOC_WRITE, type_, nr, size_of::<T>())
+}
+
+/// `cmd` with its size field cleared, for requests such as EVIOCGNAME
+/// whose size is the length of a user buffer.
+pub const fn ioc_base(cmd: u32) -> u32 {
+    cmd & !(IOC_SIZEMASK << IOC_SIZESHIFT)
+}
+
+pub const fn ioc_size(cmd: u32) -> usize {
+    ((cmd >> IOC_SIZESHIFT) & IOC_SIZEMASK) as usize
+}
+
+/// Copy the argument `arg` points to into the kernel, EFAULT if it is no
+/// user memory.
+pub fn read_arg<T: Copy>(arg: usize) -> Result<T, SysError> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let bytes =
+        unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+    let buffers = translated_byte_buffer(current_user_token(), arg as *const u8, bytes.len())?;
+    let mut copied = 0;
+    for buffer in buffers {
+        bytes[copied..copied + buffer.len()].copy_from_slice(buffer);
+        copied += buffer.len();
+    }
+    Ok(unsafe { value.assume_init() })
+}
+
+/// Copy `value` out to where `arg` points, EFAULT if it is no user memory.
+pub fn write_arg<T: Copy>(arg: usize, value: &T) -> Result<(), SysError> {
+    let bytes =
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    write_bytes(arg, bytes)
+}
+
+/// Copy `bytes` out to where `arg` points.
+pub fn write_bytes(arg: usize, bytes: &[u8]) -> Result<(), SysError> {
+    let buffers = translated_byte_buffer_mut(current_user_token(), arg as *mut u8, bytes.len())?;
+    let mut copied = 0;
+    for buffer in buffers {
+        let len = buffer.len();
+        buffer.copy_from_slice(&bytes[copied..copied + len]);
+        copied += len;
+    }
+    Ok(())
+}
@@ -1,16 +1,120 @@
+mod dev;
+mod dsp;
+mod evdev;
+mod fbdev;
+mod fifo;
+mod hvc;
 mod inode;
+pub mod ioctl;
+mod mount;
+mod p9;
 mod pipe;
+mod proc;
+mod pty;
+mod rtc;
+mod stat;
 mod stdio;
+mod surface;
+mod tty;
 
 use crate::mm::UserBuffer;
+use crate::syscall::{SysError, SysResult};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use easy_fs::Inode;
+
+bitflags! {
+    /// `events` and `revents` of poll, same bits as linux.
+    pub struct PollEvents: u16 {
+        const POLLIN = 0x1;
+        const POLLPRI = 0x2;
+        const POLLOUT = 0x4;
+        const POLLERR = 0x8;
+        const POLLHUP = 0x10;
+        const POLLNVAL = 0x20;
+    }
+}
+
+/// A directory entry on its way into one of the getdents record formats.
+pub struct Dirent<'a> {
+    pub ino: u64,
+    /// `d_type` of linux
+    pub kind: u8,
+    pub name: &'a str,
+    /// directory offset of the entry after this one
+    pub next_offset: usize,
+}
+
+/// O_APPEND and O_NONBLOCK of an open file, which fcntl may change after
+/// it is opened. Every fd dup'ed from the file shares them.
+#[derive(Default)]
+pub struct StatusFlags(AtomicU32);
+
+impl StatusFlags {
+    pub fn get(&self) -> OpenFlags {
+        OpenFlags::from_bits_truncate(self.0.load(Relaxed))
+    }
+    pub fn set(&self, flags: OpenFlags) {
+        self.0.store(flags.status().bits(), Relaxed);
+    }
+}
 
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Request `cmd` of the device behind the file, numbered as in
+    /// [`ioctl`]. ENOTTY if the file knows no such request.
+    fn ioctl(&self, _cmd: u32, _arg: usize) -> SysResult {
+        Err(SysError::ENOTTY)
+    }
+    /// The next directory entries turned into bytes by `record`, as many as
+    /// fit in `max_len` bytes. None if this is no directory.
+    fn read_dir(&self, _max_len: usize, _record: fn(&Dirent) -> Vec<u8>) -> Option<Vec<u8>> {
+        None
+    }
+    /// None for files whose reads and writes ignore the status flags.
+    fn status(&self) -> Option<&StatusFlags> {
+        None
+    }
+    /// Stat of a file on another filesystem than easy-fs, which has no
+    /// inode to take it from.
+    fn stat(&self) -> Option<Stat> {
+        None
+    }
+    /// Inode behind the file, None if it cannot be mapped.
+    fn inode(&self) -> Option<Arc<Inode>> {
+        None
+    }
+    /// Path the *at calls resolve relative names against, None if this is
+    /// no directory.
+    fn dir_path(&self) -> Option<String> {
+        None
+    }
+    /// POLLIN and POLLOUT if reading or writing would not block now, with
+    /// POLLHUP or POLLERR once the other end is gone. A file which never
+    /// blocks is always ready.
+    fn poll(&self) -> PollEvents {
+        let mut events = PollEvents::empty();
+        events.set(PollEvents::POLLIN, self.readable());
+        events.set(PollEvents::POLLOUT, self.writable());
+        events
+    }
 }
 
-pub use inode::{list_apps, open_file, OpenFlags};
+pub use dev::open_device;
+pub use fifo::open_fifo;
+pub use inode::{
+    list_apps, lookup, lookup_parent, make_fifo, open_file, OpenFlags, DIRENT_NAME_MAX,
+};
+pub use mount::{mount, resolve_mount, umount, umount_all};
 pub use pipe::make_pipe;
+pub use pty::make_pty;
+pub use stat::{Stat, Statx};
 pub use stdio::{Stdin, Stdout};
+pub use surface::Surface;
+pub use tty::{active_tty, console_input};
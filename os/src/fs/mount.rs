@@ -0,0 +1,98 @@
+//! Filesystems mounted over directories of easy-fs. Only 9P shares can be
+//! mounted, paths below a mount point never reach easy-fs.
+
+use super::inode::lookup;
+use super::p9::P9Fs;
+use crate::drivers::p9::P9_DEVICE;
+use crate::sync::UPIntrFreeCell;
+use crate::syscall::SysError;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+struct Mount {
+    /// the mount point, as its path components
+    at: Vec<String>,
+    fs: Arc<P9Fs>,
+}
+
+lazy_static! {
+    static ref MOUNTS: UPIntrFreeCell<Vec<Mount>> = unsafe { UPIntrFreeCell::new(Vec::new()) };
+}
+
+/// Components of `path` from the root, with `.` and `..` resolved as
+/// lookup does.
+fn components(path: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                names.pop();
+            }
+            name => names.push(name.to_string()),
+        }
+    }
+    names
+}
+
+/// The filesystem `path` is on and the path within it, None for easy-fs.
+pub fn resolve_mount(path: &str) -> Option<(Arc<P9Fs>, Vec<String>)> {
+    let names = components(path);
+    let mounts = MOUNTS.exclusive_access();
+    mounts
+        .iter()
+        .filter(|mount| names.starts_with(&mount.at))
+        .max_by_key(|mount| mount.at.len())
+        .map(|mount| (mount.fs.clone(), names[mount.at.len()..].to_vec()))
+}
+
+/// Attach the share tagged `source` over the directory `target`.
+pub fn mount(source: &str, target: &str, fstype: &str) -> Result<(), SysError> {
+    if fstype != "9p" {
+        return Err(SysError::ENODEV);
+    }
+    let at = components(target);
+    if resolve_mount(target).is_some() {
+        // on a share already, or the mount point of one
+        return Err(SysError::EBUSY);
+    }
+    if MOUNTS
+        .exclusive_access()
+        .iter()
+        .any(|mount| mount.fs.tag() == source)
+    {
+        return Err(SysError::EBUSY);
+    }
+    if !lookup(target).ok_or(SysError::ENOENT)?.is_dir() {
+        return Err(SysError::ENOTDIR);
+    }
+    let device = P9_DEVICE
+        .clone()
+        .filter(|device| device.tag() == source)
+        .ok_or(SysError::ENOENT)?;
+    let fs = Arc::new(P9Fs::attach(device)?);
+    MOUNTS.exclusive_access().push(Mount { at, fs });
+    Ok(())
+}
+
+/// Detach every share, whatever is still open on them, on the way down.
+pub fn umount_all() {
+    MOUNTS.exclusive_access().clear();
+}
+
+/// Detach what is mounted at `target`, EBUSY while files on it are open.
+pub fn umount(target: &str) -> Result<(), SysError> {
+    let at = components(target);
+    let mut mounts = MOUNTS.exclusive_access();
+    let index = mounts
+        .iter()
+        .position(|mount| mount.at == at)
+        .ok_or(SysError::EINVAL)?;
+    if Arc::strong_count(&mounts[index].fs) > 1 {
+        return Err(SysError::EBUSY);
+    }
+    mounts.remove(index);
+    Ok(())
+}
@@ -0,0 +1,619 @@
+//! A 9P2000.L client over virtio-9p, for a host directory mounted over a
+//! directory of easy-fs with `mount -t 9p TAG DIR`.
+//!
+//! Ref: https://github.com/chaos/diod/blob/master/protocol.md for the
+//! messages of 9P2000.L, the rest is as in 9P2000 (intro(5) of Plan 9).
+
+use super::{Dirent, File, OpenFlags, Stat, StatusFlags};
+use crate::drivers::p9::{VirtIO9p, MAX_MESSAGE};
+use crate::mm::UserBuffer;
+use crate::sync::UPIntrFreeCell;
+use crate::syscall::SysError;
+use crate::timer::TimeSpec;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+const RLERROR: u8 = 7;
+const TLOPEN: u8 = 12;
+const TLCREATE: u8 = 14;
+const TGETATTR: u8 = 24;
+const TSETATTR: u8 = 26;
+const TREADDIR: u8 = 40;
+const TMKDIR: u8 = 72;
+const TUNLINKAT: u8 = 76;
+const TVERSION: u8 = 100;
+const TATTACH: u8 = 104;
+const TWALK: u8 = 110;
+const TREAD: u8 = 116;
+const TWRITE: u8 = 118;
+const TCLUNK: u8 = 120;
+
+const VERSION: &str = "9P2000.L";
+const NOTAG: u16 = !0;
+/// every request uses the same tag, there is one in flight at a time
+const TAG: u16 = 1;
+const NOFID: u32 = !0;
+const ROOT_FID: u32 = 0;
+/// names a single Twalk may take
+const MAXWELEM: usize = 16;
+/// size, type, tag, fid, offset and count: the longest header before data
+const IO_HEADER: usize = 4 + 1 + 2 + 4 + 8 + 4;
+
+/// the fields of `struct stat`, in the request mask of Tgetattr
+const GETATTR_BASIC: u64 = 0x7ff;
+const SETATTR_SIZE: u32 = 0x8;
+/// open flags of linux, which Tlopen takes
+const L_RDONLY: u32 = 0;
+const L_WRONLY: u32 = 1;
+const L_RDWR: u32 = 2;
+const L_TRUNC: u32 = 0o1000;
+const AT_REMOVEDIR: u32 = 0x200;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+
+/// The errno of an Rlerror, for the ones the kernel knows.
+fn error_of(ecode: u32) -> SysError {
+    match ecode {
+        1 => SysError::EPERM,
+        2 => SysError::ENOENT,
+        13 => SysError::EACCES,
+        16 => SysError::EBUSY,
+        17 => SysError::EEXIST,
+        20 => SysError::ENOTDIR,
+        21 => SysError::EISDIR,
+        22 => SysError::EINVAL,
+        39 => SysError::ENOTEMPTY,
+        _ => SysError::EIO,
+    }
+}
+
+/// A T-message under construction, the size is filled in when it is sent.
+struct Message(Vec<u8>);
+
+impl Message {
+    fn new(kind: u8) -> Self {
+        Self(vec![0; 4]).u8(kind).u16(TAG)
+    }
+    fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+    fn u16(mut self, value: u16) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    fn u32(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    fn u64(mut self, value: u64) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+    fn str(self, value: &str) -> Self {
+        let mut message = self.u16(value.len() as u16);
+        message.0.extend_from_slice(value.as_bytes());
+        message
+    }
+    fn bytes(self, data: &[u8]) -> Self {
+        let mut message = self.u32(data.len() as u32);
+        message.0.extend_from_slice(data);
+        message
+    }
+}
+
+/// An R-message past its header. Reading beyond the end gives zeros, a
+/// short reply cannot crash the kernel.
+struct Reply {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Reply {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        if let Some(slice) = self.data.get(self.pos..self.pos + N) {
+            bytes.copy_from_slice(slice);
+        }
+        self.pos += N;
+        bytes
+    }
+    fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+    fn slice(&mut self, len: usize) -> &[u8] {
+        let start = self.pos.min(self.data.len());
+        let end = (self.pos + len).min(self.data.len());
+        self.pos += len;
+        &self.data[start..end]
+    }
+    fn str(&mut self) -> String {
+        let len = self.u16() as usize;
+        String::from_utf8_lossy(self.slice(len)).into_owned()
+    }
+    /// type, version and path; only the path is used
+    fn qid(&mut self) -> u64 {
+        self.u8();
+        self.u32();
+        self.u64()
+    }
+}
+
+/// What Rgetattr tells of a file.
+struct Attr {
+    mode: u32,
+    ino: u64,
+    nlink: u64,
+    size: u64,
+    blksize: u64,
+    blocks: u64,
+    /// seconds and nanoseconds of atime, mtime and ctime
+    times: [(u64, u64); 3],
+}
+
+impl Attr {
+    fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    fn stat(&self) -> Stat {
+        let [atime, mtime, ctime] = self.times.map(|(sec, nsec)| TimeSpec {
+            tv_sec: sec as usize,
+            tv_nsec: nsec as usize,
+        });
+        Stat::of_host(
+            self.ino,
+            self.mode,
+            self.nlink as u32,
+            self.size,
+            self.blksize as u32,
+            self.blocks,
+            [atime, mtime, ctime],
+        )
+    }
+}
+
+/// A host directory attached over a virtio-9p device.
+pub struct P9Fs {
+    device: Arc<VirtIO9p>,
+    /// largest message the server accepts, at most MAX_MESSAGE
+    msize: usize,
+    next_fid: AtomicU32,
+}
+
+impl P9Fs {
+    /// Agree on the protocol and attach to the root of the share.
+    pub fn attach(device: Arc<VirtIO9p>) -> Result<Self, SysError> {
+        let mut fs = Self {
+            device,
+            msize: MAX_MESSAGE,
+            next_fid: AtomicU32::new(ROOT_FID + 1),
+        };
+        let mut version = Message::new(TVERSION).u32(MAX_MESSAGE as u32).str(VERSION);
+        version.0[5..7].copy_from_slice(&NOTAG.to_le_bytes());
+        let mut reply = fs.rpc(version)?;
+        fs.msize = (reply.u32() as usize).min(MAX_MESSAGE);
+        if reply.str() != VERSION {
+            return Err(SysError::EINVAL);
+        }
+        fs.rpc(
+            Message::new(TATTACH)
+                .u32(ROOT_FID)
+                .u32(NOFID)
+                .str("root")
+                .str("")
+                .u32(0),
+        )?;
+        Ok(fs)
+    }
+
+    pub fn tag(&self) -> &str {
+        self.device.tag()
+    }
+
+    /// Send `message` and wait for the reply, whose type must follow that
+    /// of the message.
+    fn rpc(&self, mut message: Message) -> Result<Reply, SysError> {
+        let size = message.0.len() as u32;
+        message.0[..4].copy_from_slice(&size.to_le_bytes());
+        let kind = message.0[4];
+        let mut reply = Reply {
+            data: self.device.request(&message.0),
+            pos: 4,
+        };
+        let reply_kind = reply.u8();
+        reply.u16();
+        match reply_kind {
+            RLERROR => Err(error_of(reply.u32())),
+            _ if reply_kind == kind + 1 => Ok(reply),
+            _ => Err(SysError::EIO),
+        }
+    }
+
+    /// A new fid for the file `names` leads to from the root.
+    fn walk(&self, names: &[String]) -> Result<u32, SysError> {
+        let fid = self.next_fid.fetch_add(1, Ordering::Relaxed);
+        let mut from = ROOT_FID;
+        let mut chunks: Vec<&[String]> = names.chunks(MAXWELEM).collect();
+        if chunks.is_empty() {
+            // walking no names clones the fid
+            chunks.push(&[]);
+        }
+        for chunk in chunks {
+            let mut message = Message::new(TWALK)
+                .u32(from)
+                .u32(fid)
+                .u16(chunk.len() as u16);
+            for name in chunk {
+                message = message.str(name);
+            }
+            let walked = match self.rpc(message) {
+                Ok(mut reply) if reply.u16() as usize == chunk.len() => Ok(()),
+                // fewer names walked, the new fid was not made
+                Ok(_) => Err(SysError::ENOENT),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = walked {
+                if from == fid {
+                    self.clunk(fid);
+                }
+                return Err(err);
+            }
+            from = fid;
+        }
+        Ok(fid)
+    }
+
+    /// The parent of `names` walked to and the last name, EINVAL for the
+    /// root of the share.
+    fn walk_parent<'a>(&self, names: &'a [String]) -> Result<(u32, &'a str), SysError> {
+        let (name, dir) = names.split_last().ok_or(SysError::EINVAL)?;
+        Ok((self.walk(dir)?, name.as_str()))
+    }
+
+    fn clunk(&self, fid: u32) {
+        let _ = self.rpc(Message::new(TCLUNK).u32(fid));
+    }
+
+    fn getattr(&self, fid: u32) -> Result<Attr, SysError> {
+        let mut reply = self.rpc(Message::new(TGETATTR).u32(fid).u64(GETATTR_BASIC))?;
+        reply.u64(); // valid
+        let ino = reply.qid();
+        let mode = reply.u32();
+        reply.u32(); // uid
+        reply.u32(); // gid
+        let nlink = reply.u64();
+        reply.u64(); // rdev
+        let size = reply.u64();
+        let blksize = reply.u64();
+        let blocks = reply.u64();
+        let mut times = [(0, 0); 3];
+        for time in times.iter_mut() {
+            *time = (reply.u64(), reply.u64());
+        }
+        Ok(Attr {
+            mode,
+            ino,
+            nlink,
+            size,
+            blksize,
+            blocks,
+            times,
+        })
+    }
+
+    fn truncate(&self, fid: u32) -> Result<(), SysError> {
+        let mut message = Message::new(TSETATTR)
+            .u32(fid)
+            .u32(SETATTR_SIZE)
+            .u32(0)
+            .u32(0)
+            .u32(0)
+            .u64(0);
+        // atime and mtime, left alone
+        for _ in 0..4 {
+            message = message.u64(0);
+        }
+        self.rpc(message).map(|_| ())
+    }
+
+    /// Open the file at `names`, made if CREATE is given, as easy-fs does:
+    /// CREATE empties an existing file too.
+    pub fn open(
+        self: &Arc<Self>,
+        names: &[String],
+        flags: OpenFlags,
+        path: &str,
+    ) -> Result<Arc<P9File>, SysError> {
+        let (readable, writable) = flags.read_write();
+        let mode = match (readable, writable) {
+            (true, false) => L_RDONLY,
+            (false, true) => L_WRONLY,
+            _ => L_RDWR,
+        };
+        let fid = match self.walk(names) {
+            Ok(fid) => fid,
+            Err(SysError::ENOENT) if flags.contains(OpenFlags::CREATE) => {
+                let (fid, name) = self.walk_parent(names)?;
+                let created = Message::new(TLCREATE)
+                    .u32(fid)
+                    .str(name)
+                    .u32(mode)
+                    .u32(0o644)
+                    .u32(0);
+                if let Err(err) = self.rpc(created) {
+                    self.clunk(fid);
+                    return Err(err);
+                }
+                // the fid of the directory now stands for the new file
+                return Ok(Arc::new(P9File::new(
+                    self.clone(),
+                    fid,
+                    readable,
+                    writable,
+                    false,
+                    path,
+                )));
+            }
+            Err(err) => return Err(err),
+        };
+        let opened = self.getattr(fid).and_then(|attr| {
+            if attr.is_dir() && writable {
+                return Err(SysError::EISDIR);
+            }
+            let truncate = !attr.is_dir() && flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC);
+            let lopen_flags = mode | if truncate { L_TRUNC } else { 0 };
+            self.rpc(Message::new(TLOPEN).u32(fid).u32(lopen_flags))?;
+            Ok(attr.is_dir())
+        });
+        match opened {
+            Ok(is_dir) => Ok(Arc::new(P9File::new(
+                self.clone(),
+                fid,
+                readable,
+                writable,
+                is_dir,
+                path,
+            ))),
+            Err(err) => {
+                self.clunk(fid);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn stat(&self, names: &[String]) -> Result<Stat, SysError> {
+        let fid = self.walk(names)?;
+        let attr = self.getattr(fid);
+        self.clunk(fid);
+        Ok(attr?.stat())
+    }
+
+    pub fn mkdir(&self, names: &[String]) -> Result<(), SysError> {
+        let (fid, name) = self.walk_parent(names)?;
+        let made = self.rpc(Message::new(TMKDIR).u32(fid).str(name).u32(0o755).u32(0));
+        self.clunk(fid);
+        made.map(|_| ())
+    }
+
+    /// Remove a file, or an empty directory if `dir` is set.
+    pub fn unlink(&self, names: &[String], dir: bool) -> Result<(), SysError> {
+        let (fid, name) = self.walk_parent(names)?;
+        let flags = if dir { AT_REMOVEDIR } else { 0 };
+        let removed = self.rpc(Message::new(TUNLINKAT).u32(fid).str(name).u32(flags));
+        self.clunk(fid);
+        removed.map(|_| ())
+    }
+
+    /// Most bytes a Tread or Twrite can move.
+    fn iounit(&self) -> usize {
+        self.msize - IO_HEADER
+    }
+}
+
+struct P9FileInner {
+    offset: u64,
+    /// where the next Treaddir continues, as the server counts
+    dir_offset: u64,
+}
+
+/// A file on the host, open through its own fid.
+pub struct P9File {
+    fs: Arc<P9Fs>,
+    fid: u32,
+    readable: bool,
+    writable: bool,
+    is_dir: bool,
+    path: String,
+    status: StatusFlags,
+    inner: UPIntrFreeCell<P9FileInner>,
+}
+
+impl P9File {
+    fn new(
+        fs: Arc<P9Fs>,
+        fid: u32,
+        readable: bool,
+        writable: bool,
+        is_dir: bool,
+        path: &str,
+    ) -> Self {
+        Self {
+            fs,
+            fid,
+            readable,
+            writable,
+            is_dir,
+            path: path.into(),
+            status: StatusFlags::default(),
+            inner: unsafe {
+                UPIntrFreeCell::new(P9FileInner {
+                    offset: 0,
+                    dir_offset: 0,
+                })
+            },
+        }
+    }
+
+    /// Fill `buf` from `offset`, short only at the end of the file.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, SysError> {
+        let mut done = 0;
+        while done < buf.len() {
+            let count = (buf.len() - done).min(self.fs.iounit());
+            let mut reply = self.fs.rpc(
+                Message::new(TREAD)
+                    .u32(self.fid)
+                    .u64(offset + done as u64)
+                    .u32(count as u32),
+            )?;
+            let len = reply.u32() as usize;
+            let data = reply.slice(len.min(count));
+            buf[done..done + data.len()].copy_from_slice(data);
+            done += data.len();
+            if data.len() < count {
+                break;
+            }
+        }
+        Ok(done)
+    }
+
+    fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize, SysError> {
+        let mut done = 0;
+        for chunk in data.chunks(self.fs.iounit()) {
+            let mut reply = self.fs.rpc(
+                Message::new(TWRITE)
+                    .u32(self.fid)
+                    .u64(offset + done as u64)
+                    .bytes(chunk),
+            )?;
+            let written = reply.u32() as usize;
+            done += written;
+            if written < chunk.len() {
+                break;
+            }
+        }
+        Ok(done)
+    }
+
+    /// The whole file, for exec.
+    pub fn read_all(&self) -> Result<Vec<u8>, SysError> {
+        let mut data = Vec::new();
+        let mut chunk = vec![0u8; self.fs.iounit()];
+        loop {
+            let len = self.read_at(data.len() as u64, &mut chunk)?;
+            if len == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(&chunk[..len]);
+        }
+    }
+}
+
+impl File for P9File {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        let mut total = 0;
+        for slice in buf.buffers.iter_mut() {
+            let len = self.read_at(inner.offset, *slice).unwrap_or(0);
+            inner.offset += len as u64;
+            total += len;
+            if len < slice.len() {
+                break;
+            }
+        }
+        total
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        if self.status.get().contains(OpenFlags::APPEND) {
+            if let Ok(attr) = self.fs.getattr(self.fid) {
+                inner.offset = attr.size;
+            }
+        }
+        let mut total = 0;
+        for slice in buf.buffers.iter() {
+            let len = self.write_at(inner.offset, *slice).unwrap_or(0);
+            inner.offset += len as u64;
+            total += len;
+            if len < slice.len() {
+                break;
+            }
+        }
+        total
+    }
+    fn read_dir(&self, max_len: usize, record: fn(&Dirent) -> Vec<u8>) -> Option<Vec<u8>> {
+        if !self.is_dir {
+            return None;
+        }
+        let mut inner = self.inner.exclusive_access();
+        let mut records = Vec::new();
+        loop {
+            let count = self.fs.iounit().min(4096) as u32;
+            let mut reply = match self.fs.rpc(
+                Message::new(TREADDIR)
+                    .u32(self.fid)
+                    .u64(inner.dir_offset)
+                    .u32(count),
+            ) {
+                Ok(reply) => reply,
+                Err(_) => return Some(records),
+            };
+            let count = reply.u32() as usize;
+            let end = reply.pos + count;
+            if count == 0 {
+                return Some(records);
+            }
+            while reply.pos < end {
+                let ino = reply.qid();
+                let next_offset = reply.u64();
+                let kind = reply.u8();
+                let name = reply.str();
+                let bytes = record(&Dirent {
+                    ino,
+                    kind,
+                    name: name.as_str(),
+                    next_offset: next_offset as usize,
+                });
+                if records.len() + bytes.len() > max_len {
+                    return Some(records);
+                }
+                inner.dir_offset = next_offset;
+                records.extend_from_slice(&bytes);
+            }
+        }
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+    fn dir_path(&self) -> Option<String> {
+        self.is_dir.then(|| self.path.clone())
+    }
+    fn stat(&self) -> Option<Stat> {
+        self.fs.getattr(self.fid).ok().map(|attr| attr.stat())
+    }
+}
+
+impl Drop for P9File {
+    fn drop(&mut self) {
+        self.fs.clunk(self.fid);
+    }
+}
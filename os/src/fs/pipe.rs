@@ -1,14 +1,17 @@
-use super::File;
+use super::{File, OpenFlags, PollEvents, StatusFlags};
 use crate::mm::UserBuffer;
-use crate::sync::UPIntrFreeCell;
+use crate::sync::{Condvar, UPIntrFreeCell};
 use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
 
-use crate::task::suspend_current_and_run_next;
+use crate::task::schedule;
 
 pub struct Pipe {
     readable: bool,
     writable: bool,
     buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>,
+    status: StatusFlags,
 }
 
 impl Pipe {
@@ -17,6 +20,7 @@ impl Pipe {
             readable: true,
             writable: false,
             buffer,
+            status: StatusFlags::default(),
         }
     }
     pub fn write_end_with_buffer(buffer: Arc<UPIntrFreeCell<PipeRingBuffer>>) -> Self {
@@ -24,84 +28,108 @@ impl Pipe {
             readable: false,
             writable: true,
             buffer,
+            status: StatusFlags::default(),
         }
     }
 }
 
-const RING_BUFFER_SIZE: usize = 32;
-
-#[derive(Copy, Clone, PartialEq)]
-enum RingBufferStatus {
-    Full,
-    Empty,
-    Normal,
-}
+/// Writes of at most this many bytes are never interleaved with other writers.
+pub const PIPE_BUF: usize = 512;
+const PIPE_DEFAULT_SIZE: usize = 4096;
+const PIPE_MAX_SIZE: usize = 0x10000;
 
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
+    arr: Vec<u8>,
     head: usize,
-    tail: usize,
-    status: RingBufferStatus,
+    len: usize,
     write_end: Option<Weak<Pipe>>,
+    read_end: Option<Weak<Pipe>>,
+    /// tasks waiting for data
+    readers: Condvar,
+    /// tasks waiting for free space
+    writers: Condvar,
 }
 
 impl PipeRingBuffer {
-    pub fn new() -> Self {
+    pub fn new(capacity: usize) -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; capacity],
             head: 0,
-            tail: 0,
-            status: RingBufferStatus::Empty,
+            len: 0,
             write_end: None,
+            read_end: None,
+            readers: Condvar::new(),
+            writers: Condvar::new(),
         }
     }
     pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
         self.write_end = Some(Arc::downgrade(write_end));
     }
+    pub fn set_read_end(&mut self, read_end: &Arc<Pipe>) {
+        self.read_end = Some(Arc::downgrade(read_end));
+    }
+    pub fn capacity(&self) -> usize {
+        self.arr.len()
+    }
     pub fn write_byte(&mut self, byte: u8) {
-        self.status = RingBufferStatus::Normal;
-        self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
-        if self.tail == self.head {
-            self.status = RingBufferStatus::Full;
-        }
+        let tail = (self.head + self.len) % self.capacity();
+        self.arr[tail] = byte;
+        self.len += 1;
     }
     pub fn read_byte(&mut self) -> u8 {
-        self.status = RingBufferStatus::Normal;
         let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = RingBufferStatus::Empty;
-        }
+        self.head = (self.head + 1) % self.capacity();
+        self.len -= 1;
         c
     }
     pub fn available_read(&self) -> usize {
-        if self.status == RingBufferStatus::Empty {
-            0
-        } else if self.tail > self.head {
-            self.tail - self.head
-        } else {
-            self.tail + RING_BUFFER_SIZE - self.head
-        }
+        self.len
     }
     pub fn available_write(&self) -> usize {
-        if self.status == RingBufferStatus::Full {
-            0
+        self.capacity() - self.len
+    }
+    pub fn all_write_ends_closed(&self) -> bool {
+        self.write_end
+            .as_ref()
+            .map_or(true, |write_end| write_end.upgrade().is_none())
+    }
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.read_end
+            .as_ref()
+            .map_or(true, |read_end| read_end.upgrade().is_none())
+    }
+    /// Readiness of the read end or of the write end.
+    pub fn poll(&self, read_end: bool) -> PollEvents {
+        let mut events = PollEvents::empty();
+        if read_end {
+            let hung_up = self.all_write_ends_closed();
+            events.set(PollEvents::POLLIN, self.len > 0 || hung_up);
+            events.set(PollEvents::POLLHUP, hung_up);
         } else {
-            RING_BUFFER_SIZE - self.available_read()
+            let broken = self.all_read_ends_closed();
+            events.set(PollEvents::POLLOUT, self.available_write() > 0 || broken);
+            events.set(PollEvents::POLLERR, broken);
         }
+        events
     }
-    pub fn all_write_ends_closed(&self) -> bool {
-        self.write_end.as_ref().unwrap().upgrade().is_none()
+}
+
+pub fn pipe_capacity(capacity: usize) -> usize {
+    if capacity == 0 {
+        PIPE_DEFAULT_SIZE
+    } else {
+        capacity.clamp(PIPE_BUF, PIPE_MAX_SIZE)
     }
 }
 
-/// Return (read_end, write_end)
-pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
-    let buffer = Arc::new(unsafe { UPIntrFreeCell::new(PipeRingBuffer::new()) });
+/// Return (read_end, write_end), a capacity of 0 selects the default size.
+pub fn make_pipe(capacity: usize) -> (Arc<Pipe>, Arc<Pipe>) {
+    let buffer =
+        Arc::new(unsafe { UPIntrFreeCell::new(PipeRingBuffer::new(pipe_capacity(capacity))) });
     let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
     let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
     buffer.exclusive_access().set_write_end(&write_end);
+    buffer.exclusive_access().set_read_end(&read_end);
     (read_end, write_end)
 }
 
@@ -112,7 +140,24 @@ impl File for Pipe {
     fn writable(&self) -> bool {
         self.writable
     }
+    fn poll(&self) -> PollEvents {
+        self.buffer.exclusive_access().poll(self.readable)
+    }
     fn read(&self, buf: UserBuffer) -> usize {
+        self.read_or_wait(buf, self.status.get().contains(OpenFlags::NONBLOCK))
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.write_or_wait(buf, self.status.get().contains(OpenFlags::NONBLOCK))
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+}
+
+impl Pipe {
+    /// Read until `buf` is full or every writer is gone. With `nonblocking`
+    /// it returns as soon as the pipe runs empty instead.
+    pub fn read_or_wait(&self, buf: UserBuffer, nonblocking: bool) -> usize {
         assert!(self.readable());
         let want_to_read = buf.len();
         let mut buf_iter = buf.into_iter();
@@ -121,13 +166,15 @@ impl File for Pipe {
             let mut ring_buffer = self.buffer.exclusive_access();
             let loop_read = ring_buffer.available_read();
             if loop_read == 0 {
-                if ring_buffer.all_write_ends_closed() {
+                if ring_buffer.all_write_ends_closed() || nonblocking {
                     return already_read;
                 }
+                let task_cx_ptr = ring_buffer.readers.wait_no_sched();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                schedule(task_cx_ptr);
                 continue;
             }
+            let mut finished = false;
             for _ in 0..loop_read {
                 if let Some(byte_ref) = buf_iter.next() {
                     unsafe {
@@ -135,39 +182,75 @@ impl File for Pipe {
                     }
                     already_read += 1;
                     if already_read == want_to_read {
-                        return want_to_read;
+                        finished = true;
+                        break;
                     }
                 } else {
-                    return already_read;
+                    finished = true;
+                    break;
+                }
+            }
+            // there is free space now
+            ring_buffer.writers.signal();
+            if finished {
+                if ring_buffer.available_read() > 0 {
+                    ring_buffer.readers.signal();
                 }
+                return already_read;
             }
         }
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    /// Write all of `buf` unless the readers are gone. With `nonblocking`
+    /// it returns what fitted once the pipe is full instead.
+    pub fn write_or_wait(&self, buf: UserBuffer, nonblocking: bool) -> usize {
         assert!(self.writable());
         let want_to_write = buf.len();
+        // small writes must not be interleaved with data of other writers
+        let atomic = want_to_write <= PIPE_BUF;
         let mut buf_iter = buf.into_iter();
         let mut already_write = 0usize;
         loop {
             let mut ring_buffer = self.buffer.exclusive_access();
+            if ring_buffer.all_read_ends_closed() {
+                return already_write;
+            }
             let loop_write = ring_buffer.available_write();
-            if loop_write == 0 {
+            if loop_write == 0 || (atomic && loop_write < want_to_write) {
+                if nonblocking {
+                    return already_write;
+                }
+                let task_cx_ptr = ring_buffer.writers.wait_no_sched();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                schedule(task_cx_ptr);
                 continue;
             }
             // write at most loop_write bytes
-            for _ in 0..loop_write {
-                if let Some(byte_ref) = buf_iter.next() {
-                    ring_buffer.write_byte(unsafe { *byte_ref });
-                    already_write += 1;
-                    if already_write == want_to_write {
-                        return want_to_write;
-                    }
-                } else {
-                    return already_write;
+            let chunk = loop_write.min(want_to_write - already_write);
+            for _ in 0..chunk {
+                let byte_ref = buf_iter.next().unwrap();
+                ring_buffer.write_byte(unsafe { *byte_ref });
+            }
+            already_write += chunk;
+            ring_buffer.readers.signal();
+            if already_write == want_to_write {
+                if ring_buffer.available_write() > 0 {
+                    ring_buffer.writers.signal();
                 }
+                return want_to_write;
             }
         }
     }
 }
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        // let the peers notice that this end has been closed
+        let ring_buffer = self.buffer.exclusive_access();
+        if self.writable {
+            ring_buffer.readers.signal_all();
+        }
+        if self.readable {
+            ring_buffer.writers.signal_all();
+        }
+    }
+}
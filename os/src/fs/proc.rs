@@ -0,0 +1,232 @@
+//! Files under /proc, text the kernel makes up when they are opened.
+
+use super::File;
+use crate::config::{BRK_BASE, CONFIG_TEXT, PAGE_SIZE, USER_STACK_SIZE};
+use crate::ksyms;
+use crate::mm::{MapPermission, UserBuffer, VirtAddr};
+use crate::net::dhcp::net_config;
+use crate::profile;
+use crate::sync::UPIntrFreeCell;
+use crate::task::{current_process, pid2process, ProcessControlBlock};
+use crate::trace::{self, TraceRecord, TRACE_POINTS};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// The text of a /proc file as it was when opened, read from the start on.
+/// Writes go to `control` a line at a time.
+pub struct ProcFile {
+    text: String,
+    offset: UPIntrFreeCell<usize>,
+    control: Option<fn(&str)>,
+}
+
+impl ProcFile {
+    fn new(text: String, control: Option<fn(&str)>) -> Self {
+        Self {
+            text,
+            offset: unsafe { UPIntrFreeCell::new(0) },
+            control,
+        }
+    }
+}
+
+impl File for ProcFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        self.control.is_some()
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut offset = self.offset.exclusive_access();
+        let mut read = 0;
+        for slice in buf.buffers.iter_mut() {
+            let rest = &self.text.as_bytes()[*offset..];
+            let len = slice.len().min(rest.len());
+            slice[..len].copy_from_slice(&rest[..len]);
+            *offset += len;
+            read += len;
+        }
+        read
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let control = match self.control {
+            Some(control) => control,
+            None => return 0,
+        };
+        let bytes: Vec<u8> = buf
+            .buffers
+            .iter()
+            .flat_map(|slice| slice.iter().copied())
+            .collect();
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            control(line.trim());
+        }
+        bytes.len()
+    }
+}
+
+/// Writing 1 to /proc/profile starts the profiler afresh, 2 to 5 start it
+/// recording one to four kernel callers as well and 0 stops it.
+fn control_profile(command: &str) {
+    match command.parse::<usize>() {
+        Ok(0) => profile::stop(),
+        Ok(mode) => profile::start(mode - 1),
+        Err(_) => {}
+    }
+}
+
+/// /proc/trace, taking out the records of the tracepoints. Reads never
+/// block and give whole records only, none once the rings are empty.
+pub struct TraceFile;
+
+impl File for TraceFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        let records = trace::drain(buf.len() / size_of::<TraceRecord>());
+        let mut buf_iter = buf.into_iter();
+        for record in records.iter() {
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    record as *const _ as *const u8,
+                    size_of::<TraceRecord>(),
+                )
+            };
+            for (byte, byte_ref) in bytes.iter().zip(&mut buf_iter) {
+                unsafe {
+                    *byte_ref = *byte;
+                }
+            }
+        }
+        records.len() * size_of::<TraceRecord>()
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+}
+
+/// A line `name 0` or `name 1` for each tracepoint and the number of
+/// records lost to full rings.
+fn trace_events() -> String {
+    let mut text = String::new();
+    for point in TRACE_POINTS {
+        text += &format!("{} {}\n", point.name(), trace::enabled(point) as usize);
+    }
+    text += &format!("lost {}\n", trace::lost());
+    text
+}
+
+/// Writing `name 1` to /proc/trace_events enables a tracepoint and
+/// `name 0` disables it, `all` stands for every tracepoint.
+fn control_trace_events(command: &str) {
+    let (name, enable) = match command.split_once(' ') {
+        Some((name, "0")) => (name, false),
+        Some((name, "1")) => (name, true),
+        _ => return,
+    };
+    if name == "all" {
+        for point in TRACE_POINTS {
+            trace::set_enabled(point, enable);
+        }
+    } else if let Some(point) = trace::TracePoint::from_name(name) {
+        trace::set_enabled(point, enable);
+    }
+}
+
+/// /proc/<pid>/maps, a line `10000-12000 r-xp 00000000 00:00 7` for each
+/// user area as on linux: its addresses, permissions with `s` for shared
+/// and `p` for private, then the file offset, the device, always `00:00`
+/// with a single file system, and the inode, 0 for anonymous memory. Heap
+/// and stacks are named after that.
+fn maps(process: &ProcessControlBlock) -> String {
+    let inner = process.inner_exclusive_access();
+    let stacks: Vec<usize> = inner
+        .tasks
+        .iter()
+        .flatten()
+        .filter_map(|task| {
+            task.inner_exclusive_access()
+                .res
+                .as_ref()
+                .map(|res| res.ustack_top() - USER_STACK_SIZE)
+        })
+        .collect();
+    let mut text = String::new();
+    for (vpn_range, perm, file) in inner.memory_set.user_mappings() {
+        let start = VirtAddr::from(vpn_range.get_start()).0;
+        let end = VirtAddr::from(vpn_range.get_end()).0;
+        let flag = |bit, c| if perm.contains(bit) { c } else { '-' };
+        let shared = file.as_ref().is_some_and(|file| file.shared);
+        let (offset, inode) = file
+            .as_ref()
+            .map_or((0, 0), |file| (file.offset, file.inode.inode_number()));
+        text += &format!(
+            "{:x}-{:x} {}{}{}{} {:08x} 00:00 {}",
+            start,
+            end,
+            flag(MapPermission::R, 'r'),
+            flag(MapPermission::W, 'w'),
+            flag(MapPermission::X, 'x'),
+            if shared { 's' } else { 'p' },
+            offset,
+            inode,
+        );
+        if file.is_none() && start >= BRK_BASE && end <= inner.brk.next_multiple_of(PAGE_SIZE) {
+            text += " [heap]";
+        } else if stacks.iter().any(|&bottom| (start..end).contains(&bottom)) {
+            text += " [stack]";
+        }
+        text += "\n";
+    }
+    text
+}
+
+/// /proc/resolv.conf, the DNS server DHCP handed out as a `nameserver`
+/// line.
+fn resolv_conf() -> String {
+    let [a, b, c, d] = net_config().dns.to_be_bytes();
+    format!("nameserver {}.{}.{}.{}\n", a, b, c, d)
+}
+
+/// The process /proc/<pid>/... or /proc/self/... is about and the rest
+/// of the path.
+fn proc_process(name: &str) -> Option<(Arc<ProcessControlBlock>, &str)> {
+    let (pid, rest) = name.strip_prefix("/proc/")?.split_once('/')?;
+    let process = match pid {
+        "self" => current_process(),
+        pid => pid2process(pid.parse().ok()?)?,
+    };
+    Some((process, rest))
+}
+
+/// Return None if `name` is not under /proc.
+pub fn open_proc(name: &str) -> Option<Arc<dyn File + Send + Sync>> {
+    match name {
+        "/proc/profile" => Some(Arc::new(ProcFile::new(
+            profile::report(),
+            Some(control_profile),
+        ))),
+        "/proc/trace" => Some(Arc::new(TraceFile)),
+        "/proc/ksyms" => Some(Arc::new(ProcFile::new(ksyms::listing(), None))),
+        "/proc/config" => Some(Arc::new(ProcFile::new(String::from(CONFIG_TEXT), None))),
+        "/proc/resolv.conf" => Some(Arc::new(ProcFile::new(resolv_conf(), None))),
+        #[cfg(feature = "rc_audit")]
+        "/proc/rc_audit" => Some(Arc::new(ProcFile::new(crate::task::audit(), None))),
+        "/proc/trace_events" => Some(Arc::new(ProcFile::new(
+            trace_events(),
+            Some(control_trace_events),
+        ))),
+        _ => match proc_process(name)? {
+            (process, "maps") => Some(Arc::new(ProcFile::new(maps(&process), None))),
+            _ => None,
+        },
+    }
+}
@@ -0,0 +1,262 @@
+use super::ioctl::{read_arg, write_arg};
+use super::tty::{
+    InputModes, LocalModes, Termios, TCGETS, TCSETS, TCSETSF, TCSETSW, VEOF, VERASE, VKILL, VMIN,
+};
+use super::{File, PollEvents, StatusFlags};
+use crate::mm::UserBuffer;
+use crate::sync::UPIntrFreeCell;
+use crate::syscall::{SysError, SysResult};
+use crate::task::suspend_current_and_run_next;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// State shared by both ends of a pseudo-terminal. Bytes written to the
+/// master go through the line discipline of the slave before they can be
+/// read from the slave, and everything the slave writes (including echo)
+/// is read back by the master.
+pub struct PtyInner {
+    pub(super) termios: Termios,
+    /// bytes ready to be read by the slave
+    pub(super) input: VecDeque<u8>,
+    /// line being edited in canonical mode
+    line: Vec<u8>,
+    /// bytes ready to be read by the master
+    pub(super) output: VecDeque<u8>,
+    /// set when an end-of-file character is typed on an empty line
+    pub(super) eof: bool,
+    master_closed: bool,
+    slave_closed: bool,
+}
+
+impl PtyInner {
+    pub(super) fn new() -> Self {
+        let mut termios = Termios::new();
+        termios.iflag = InputModes::ICRNL.bits();
+        termios.lflag = (LocalModes::ICANON | LocalModes::ECHO | LocalModes::ECHOE).bits();
+        Self {
+            termios,
+            input: VecDeque::new(),
+            line: Vec::new(),
+            output: VecDeque::new(),
+            eof: false,
+            master_closed: false,
+            slave_closed: false,
+        }
+    }
+
+    fn echo(&mut self, ch: u8) {
+        let lflag = self.termios.lflag();
+        if lflag.contains(LocalModes::ECHO) || (ch == b'\n' && lflag.contains(LocalModes::ECHONL)) {
+            self.output.push_back(ch);
+        }
+    }
+
+    fn echo_erase(&mut self) {
+        if self
+            .termios
+            .lflag()
+            .contains(LocalModes::ECHO | LocalModes::ECHOE)
+        {
+            self.output.extend(b"\x08 \x08".iter());
+        }
+    }
+
+    pub(super) fn ioctl(&mut self, cmd: u32, arg: usize) -> SysResult {
+        match cmd {
+            TCGETS => write_arg(arg, &self.termios)?,
+            TCSETS | TCSETSW | TCSETSF => {
+                self.termios = read_arg(arg)?;
+                if cmd == TCSETSF {
+                    self.input.clear();
+                    self.line.clear();
+                }
+            }
+            _ => return Err(SysError::ENOTTY),
+        }
+        Ok(0)
+    }
+
+    /// Feed one byte typed on the master side into the line discipline.
+    pub(super) fn receive_byte(&mut self, mut ch: u8) {
+        if ch == b'\r' && self.termios.iflag().contains(InputModes::ICRNL) {
+            ch = b'\n';
+        }
+        if !self.termios.lflag().contains(LocalModes::ICANON) {
+            self.echo(ch);
+            self.input.push_back(ch);
+            return;
+        }
+        if ch == self.termios.cc[VERASE] || ch == 0x08 {
+            if self.line.pop().is_some() {
+                self.echo_erase();
+            }
+        } else if ch == self.termios.cc[VKILL] {
+            while self.line.pop().is_some() {
+                self.echo_erase();
+            }
+        } else if ch == self.termios.cc[VEOF] {
+            if self.line.is_empty() {
+                self.eof = true;
+            }
+            let line = core::mem::take(&mut self.line);
+            self.input.extend(line.into_iter());
+        } else {
+            self.echo(ch);
+            self.line.push(ch);
+            if ch == b'\n' {
+                let line = core::mem::take(&mut self.line);
+                self.input.extend(line.into_iter());
+            }
+        }
+    }
+}
+
+pub struct PtyMaster {
+    inner: Arc<UPIntrFreeCell<PtyInner>>,
+    status: StatusFlags,
+}
+
+pub struct PtySlave {
+    inner: Arc<UPIntrFreeCell<PtyInner>>,
+    status: StatusFlags,
+}
+
+/// Return (master, slave)
+pub fn make_pty() -> (Arc<PtyMaster>, Arc<PtySlave>) {
+    let inner = Arc::new(unsafe { UPIntrFreeCell::new(PtyInner::new()) });
+    let master = Arc::new(PtyMaster {
+        inner: inner.clone(),
+        status: StatusFlags::default(),
+    });
+    let slave = Arc::new(PtySlave {
+        inner,
+        status: StatusFlags::default(),
+    });
+    (master, slave)
+}
+
+pub(super) fn copy_to_user(buf: &mut UserBuffer, queue: &mut VecDeque<u8>) -> usize {
+    let mut copied = 0usize;
+    for slice in buf.buffers.iter_mut() {
+        for byte in slice.iter_mut() {
+            if let Some(ch) = queue.pop_front() {
+                *byte = ch;
+                copied += 1;
+            } else {
+                return copied;
+            }
+        }
+    }
+    copied
+}
+
+impl File for PtyMaster {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if !inner.output.is_empty() {
+                return copy_to_user(&mut buf, &mut inner.output);
+            }
+            if inner.slave_closed {
+                return 0;
+            }
+            drop(inner);
+            suspend_current_and_run_next();
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        for slice in buf.buffers.iter() {
+            for ch in slice.iter() {
+                inner.receive_byte(*ch);
+            }
+        }
+        buf.len()
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        self.inner.exclusive_access().ioctl(cmd, arg)
+    }
+    fn poll(&self) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut events = PollEvents::POLLOUT;
+        events.set(
+            PollEvents::POLLIN,
+            !inner.output.is_empty() || inner.slave_closed,
+        );
+        events.set(PollEvents::POLLHUP, inner.slave_closed);
+        events
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        self.inner.exclusive_access().master_closed = true;
+    }
+}
+
+impl File for PtySlave {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if !inner.input.is_empty() {
+                return copy_to_user(&mut buf, &mut inner.input);
+            }
+            if inner.eof {
+                inner.eof = false;
+                return 0;
+            }
+            let nonblocking =
+                !inner.termios.lflag().contains(LocalModes::ICANON) && inner.termios.cc[VMIN] == 0;
+            if inner.master_closed || nonblocking {
+                return 0;
+            }
+            drop(inner);
+            suspend_current_and_run_next();
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        for slice in buf.buffers.iter() {
+            inner.output.extend(slice.iter());
+        }
+        buf.len()
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        self.inner.exclusive_access().ioctl(cmd, arg)
+    }
+    fn poll(&self) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut events = PollEvents::POLLOUT;
+        events.set(
+            PollEvents::POLLIN,
+            !inner.input.is_empty() || inner.eof || inner.master_closed,
+        );
+        events.set(PollEvents::POLLHUP, inner.master_closed);
+        events
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        self.inner.exclusive_access().slave_closed = true;
+    }
+}
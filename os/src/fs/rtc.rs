@@ -0,0 +1,125 @@
+//! /dev/rtc0, the wall clock in broken-down UTC time.
+
+use super::ioctl::{ior, iow, read_arg, write_arg};
+use super::File;
+use crate::mm::UserBuffer;
+use crate::syscall::{SysError, SysResult};
+use crate::timer::{realtime_ns, set_realtime_ns};
+
+/// `struct rtc_time` of linux, the fields of `struct tm`.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct RtcTime {
+    sec: i32,
+    min: i32,
+    hour: i32,
+    /// day of the month, from 1
+    mday: i32,
+    /// month, from 0
+    mon: i32,
+    /// years since 1900
+    year: i32,
+    wday: i32,
+    yday: i32,
+    isdst: i32,
+}
+
+pub const RTC_RD_TIME: u32 = ior::<RtcTime>(b'p', 0x09);
+pub const RTC_SET_TIME: u32 = iow::<RtcTime>(b'p', 0x0a);
+
+const SECS_PER_DAY: i64 = 86400;
+
+/// Days since 1970-01-01 of a date in the proleptic gregorian calendar,
+/// `month` counted from 1.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl RtcTime {
+    fn from_secs(secs: i64) -> Self {
+        let days = secs.div_euclid(SECS_PER_DAY);
+        let rem = secs.rem_euclid(SECS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            sec: (rem % 60) as i32,
+            min: (rem / 60 % 60) as i32,
+            hour: (rem / 3600) as i32,
+            mday: day as i32,
+            mon: (month - 1) as i32,
+            year: (year - 1900) as i32,
+            // 1970-01-01 was a thursday
+            wday: (days + 4).rem_euclid(7) as i32,
+            yday: (days - days_from_civil(year, 1, 1)) as i32,
+            isdst: 0,
+        }
+    }
+
+    /// None unless every field is in range and the time is after the epoch.
+    fn to_secs(self) -> Option<i64> {
+        let year = self.year as i64 + 1900;
+        let month = self.mon as i64 + 1;
+        let valid = (0..60).contains(&self.sec)
+            && (0..60).contains(&self.min)
+            && (0..24).contains(&self.hour)
+            && (1..=12).contains(&month)
+            && year >= 1970
+            && self.mday >= 1
+            && civil_from_days(days_from_civil(year, month, self.mday as i64)).1 == month;
+        if !valid {
+            return None;
+        }
+        let days = days_from_civil(year, month, self.mday as i64);
+        Some(days * SECS_PER_DAY + self.hour as i64 * 3600 + self.min as i64 * 60 + self.sec as i64)
+    }
+}
+
+pub struct Rtc;
+
+impl File for Rtc {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        match cmd {
+            RTC_RD_TIME => {
+                let secs = (realtime_ns() / 1_000_000_000) as i64;
+                write_arg(arg, &RtcTime::from_secs(secs))?;
+            }
+            RTC_SET_TIME => {
+                let time: RtcTime = read_arg(arg)?;
+                let secs = time.to_secs().ok_or(SysError::EINVAL)?;
+                set_realtime_ns(secs as u64 * 1_000_000_000);
+            }
+            _ => return Err(SysError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
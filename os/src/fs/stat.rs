@@ -0,0 +1,182 @@
+use crate::timer::TimeSpec;
+use easy_fs::{Inode, BLOCK_SZ};
+
+const S_IFIFO: u32 = 0o010000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// `struct stat` of the generic linux ABI riscv64 uses.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: i32,
+    __pad2: i32,
+    pub blocks: i64,
+    pub atime_sec: i64,
+    pub atime_nsec: u64,
+    pub mtime_sec: i64,
+    pub mtime_nsec: u64,
+    pub ctime_sec: i64,
+    pub ctime_nsec: u64,
+    __unused: [u32; 2],
+}
+
+/// `struct statx_timestamp` of linux.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct StatxTimestamp {
+    pub sec: i64,
+    pub nsec: u32,
+    __reserved: i32,
+}
+
+/// `struct statx` of linux.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Statx {
+    /// which of the fields below are filled in
+    pub mask: u32,
+    pub blksize: u32,
+    pub attributes: u64,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u16,
+    __spare0: u16,
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    pub attributes_mask: u64,
+    pub atime: StatxTimestamp,
+    pub btime: StatxTimestamp,
+    pub ctime: StatxTimestamp,
+    pub mtime: StatxTimestamp,
+    pub rdev_major: u32,
+    pub rdev_minor: u32,
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    __spare2: [u64; 14],
+}
+
+const STATX_ATIME: u32 = 0x20;
+const STATX_MTIME: u32 = 0x40;
+const STATX_CTIME: u32 = 0x80;
+const STATX_INO: u32 = 0x100;
+const STATX_SIZE: u32 = 0x200;
+const STATX_BLOCKS: u32 = 0x400;
+/// Everything in `struct stat`, there is no birth time.
+const STATX_BASIC_STATS: u32 = 0x7ff;
+
+impl Statx {
+    pub fn of_stat(stat: &Stat) -> Self {
+        // devices have no inode, and with it no number, size or times
+        let mask = if stat.ino == 0 {
+            STATX_BASIC_STATS
+                & !(STATX_INO | STATX_SIZE | STATX_BLOCKS | STATX_ATIME | STATX_MTIME | STATX_CTIME)
+        } else {
+            STATX_BASIC_STATS
+        };
+        let timestamp = |sec: i64, nsec: u64| StatxTimestamp {
+            sec,
+            nsec: nsec as u32,
+            ..Default::default()
+        };
+        Self {
+            mask,
+            blksize: stat.blksize as u32,
+            nlink: stat.nlink,
+            uid: stat.uid,
+            gid: stat.gid,
+            mode: stat.mode as u16,
+            ino: stat.ino,
+            size: stat.size as u64,
+            blocks: stat.blocks as u64,
+            atime: timestamp(stat.atime_sec, stat.atime_nsec),
+            ctime: timestamp(stat.ctime_sec, stat.ctime_nsec),
+            mtime: timestamp(stat.mtime_sec, stat.mtime_nsec),
+            ..Default::default()
+        }
+    }
+}
+
+impl Stat {
+    pub fn of_inode(inode: &Inode) -> Self {
+        let mode = if inode.is_dir() {
+            S_IFDIR | 0o755
+        } else if inode.is_fifo() {
+            S_IFIFO | 0o644
+        } else {
+            S_IFREG | 0o644
+        };
+        let size = inode.size();
+        let (atime, mtime, ctime) = inode.times();
+        let (atime, mtime, ctime) = (
+            TimeSpec::from_ns(atime),
+            TimeSpec::from_ns(mtime),
+            TimeSpec::from_ns(ctime),
+        );
+        Self {
+            ino: inode.inode_number() as u64,
+            mode,
+            nlink: inode.nlink(),
+            size: size as i64,
+            blksize: BLOCK_SZ as i32,
+            // counted in 512-byte units whatever the block size
+            blocks: ((size + 511) / 512) as i64,
+            atime_sec: atime.tv_sec as i64,
+            atime_nsec: atime.tv_nsec as u64,
+            mtime_sec: mtime.tv_sec as i64,
+            mtime_nsec: mtime.tv_nsec as u64,
+            ctime_sec: ctime.tv_sec as i64,
+            ctime_nsec: ctime.tv_nsec as u64,
+            ..Default::default()
+        }
+    }
+
+    /// Files of a filesystem on the host, which reports all of it.
+    pub fn of_host(
+        ino: u64,
+        mode: u32,
+        nlink: u32,
+        size: u64,
+        blksize: u32,
+        blocks: u64,
+        [atime, mtime, ctime]: [TimeSpec; 3],
+    ) -> Self {
+        Self {
+            ino,
+            mode,
+            nlink,
+            size: size as i64,
+            blksize: blksize as i32,
+            blocks: blocks as i64,
+            atime_sec: atime.tv_sec as i64,
+            atime_nsec: atime.tv_nsec as u64,
+            mtime_sec: mtime.tv_sec as i64,
+            mtime_nsec: mtime.tv_nsec as u64,
+            ctime_sec: ctime.tv_sec as i64,
+            ctime_nsec: ctime.tv_nsec as u64,
+            ..Default::default()
+        }
+    }
+
+    /// Files without an inode: pipes, terminals and other devices.
+    pub fn of_device() -> Self {
+        Self {
+            mode: S_IFCHR | 0o666,
+            nlink: 1,
+            blksize: BLOCK_SZ as i32,
+            ..Default::default()
+        }
+    }
+}
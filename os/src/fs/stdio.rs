@@ -1,10 +1,66 @@
-use super::File;
-use crate::drivers::chardev::CharDevice;
-use crate::drivers::chardev::UART;
+use super::tty::{Tty, TTYS};
+use super::{File, PollEvents, StatusFlags};
 use crate::mm::UserBuffer;
+use crate::syscall::SysResult;
 
-pub struct Stdin;
-pub struct Stdout;
+/// The first virtual terminal, where initproc starts out.
+pub struct Stdin {
+    tty: &'static Tty,
+    status: StatusFlags,
+}
+pub struct Stdout {
+    tty: &'static Tty,
+}
+
+impl Default for Stdin {
+    fn default() -> Self {
+        Self {
+            tty: &TTYS[0],
+            status: StatusFlags::default(),
+        }
+    }
+}
+
+impl Default for Stdout {
+    fn default() -> Self {
+        Self { tty: &TTYS[0] }
+    }
+}
+
+/// /dev/ttyN, a virtual terminal opened for reading and writing alike.
+pub struct Terminal {
+    tty: &'static Tty,
+    readable: bool,
+    writable: bool,
+    status: StatusFlags,
+}
+
+impl Terminal {
+    pub fn new(tty: &'static Tty, readable: bool, writable: bool) -> Self {
+        Self {
+            tty,
+            readable,
+            writable,
+            status: StatusFlags::default(),
+        }
+    }
+}
+
+fn poll_input(tty: &Tty) -> PollEvents {
+    if tty.input_pending() {
+        PollEvents::POLLIN
+    } else {
+        PollEvents::empty()
+    }
+}
+
+fn write_output(tty: &Tty, user_buf: UserBuffer) -> usize {
+    // straight to the terminal, the kernel log may have moved elsewhere
+    for buffer in user_buf.buffers.iter() {
+        tty.write(buffer);
+    }
+    user_buf.len()
+}
 
 impl File for Stdin {
     fn readable(&self) -> bool {
@@ -13,18 +69,21 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
-        //println!("before UART.read() in Stdin::read()");
-        let ch = UART.read();
-        unsafe {
-            user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
-        }
-        1
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        self.tty.read(user_buf)
     }
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        self.tty.ioctl(cmd, arg)
+    }
+    fn poll(&self) -> PollEvents {
+        poll_input(self.tty)
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
 }
 
 impl File for Stdout {
@@ -38,9 +97,35 @@ impl File for Stdout {
         panic!("Cannot read from stdout!");
     }
     fn write(&self, user_buf: UserBuffer) -> usize {
-        for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
-        }
-        user_buf.len()
+        write_output(self.tty, user_buf)
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        self.tty.ioctl(cmd, arg)
+    }
+}
+
+impl File for Terminal {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        self.tty.read(user_buf)
+    }
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        write_output(self.tty, user_buf)
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        self.tty.ioctl(cmd, arg)
+    }
+    fn poll(&self) -> PollEvents {
+        let mut events = poll_input(self.tty);
+        events.set(PollEvents::POLLOUT, self.writable);
+        events
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
     }
 }
@@ -0,0 +1,131 @@
+//! Surfaces of the compositor as files, made by sys_surface_create. The
+//! program draws into a buffer of its own and hands the damaged
+//! rectangles of it to the compositor with SURFACE_DAMAGE, much like
+//! DRM_IOCTL_MODE_DIRTYFB of linux. Reading gives the input routed to the
+//! surface, closing the last fd takes the surface off the screen.
+
+use super::ioctl::{iow, read_arg};
+use super::{File, PollEvents, StatusFlags};
+use crate::drivers::gpu::compositor::{self, SurfaceEvent, BYTES_PER_PIXEL};
+use crate::mm::{translated_byte_buffer, UserBuffer};
+use crate::syscall::{SysError, SysResult};
+use crate::task::{current_user_token, suspend_current_and_run_next};
+use core::mem::size_of;
+
+/// `struct surface_damage`: the rectangle at (`x`, `y`) of the surface
+/// changed. `pixels` is the whole buffer of the program, rows of the
+/// width of the surface, four bytes B, G, R, A a pixel.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SurfaceDamage {
+    pixels: usize,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+pub const SURFACE_DAMAGE: u32 = iow::<SurfaceDamage>(b'S', 0x01);
+
+pub struct Surface {
+    id: usize,
+    status: StatusFlags,
+}
+
+impl Surface {
+    pub fn new(x: isize, y: isize, width: usize, height: usize) -> Result<Self, SysError> {
+        Ok(Self {
+            id: compositor::create(x, y, width, height)?,
+            status: StatusFlags::default(),
+        })
+    }
+
+    fn damage(&self, damage: SurfaceDamage) -> SysResult {
+        let (surface_width, surface_height) = compositor::size(self.id).unwrap();
+        let (x, y) = (damage.x as usize, damage.y as usize);
+        let (width, height) = (damage.width as usize, damage.height as usize);
+        if x + width > surface_width || y + height > surface_height {
+            return Err(SysError::EINVAL);
+        }
+        if width == 0 || height == 0 {
+            return Ok(0);
+        }
+        let token = current_user_token();
+        let row_len = width * BYTES_PER_PIXEL;
+        for row in y..y + height {
+            let start = damage
+                .pixels
+                .checked_add((row * surface_width + x) * BYTES_PER_PIXEL)
+                .ok_or(SysError::EFAULT)?;
+            let mut offset = (row * surface_width + x) * BYTES_PER_PIXEL;
+            for buffer in translated_byte_buffer(token, start as *const u8, row_len)? {
+                compositor::write(self.id, offset, buffer);
+                offset += buffer.len();
+            }
+        }
+        compositor::update(self.id, x, y, width, height);
+        Ok(0)
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        compositor::destroy(self.id);
+    }
+}
+
+impl File for Surface {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Whole events only, waiting for the first one and taking as many of
+    /// the queued ones as fit after it.
+    fn read(&self, buf: UserBuffer) -> usize {
+        let count = buf.len() / size_of::<SurfaceEvent>();
+        let mut buf_iter = buf.into_iter();
+        for read in 0..count {
+            let event = loop {
+                if let Some(event) = compositor::pop_event(self.id) {
+                    break event;
+                }
+                if read > 0 {
+                    return read * size_of::<SurfaceEvent>();
+                }
+                suspend_current_and_run_next();
+            };
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    &event as *const _ as *const u8,
+                    size_of::<SurfaceEvent>(),
+                )
+            };
+            for (byte, byte_ref) in bytes.iter().zip(&mut buf_iter) {
+                unsafe {
+                    *byte_ref = *byte;
+                }
+            }
+        }
+        count * size_of::<SurfaceEvent>()
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        if cmd == SURFACE_DAMAGE {
+            self.damage(read_arg(arg)?)
+        } else {
+            Err(SysError::ENOTTY)
+        }
+    }
+    fn poll(&self) -> PollEvents {
+        let mut events = PollEvents::empty();
+        events.set(PollEvents::POLLIN, compositor::has_events(self.id));
+        events
+    }
+    fn status(&self) -> Option<&StatusFlags> {
+        Some(&self.status)
+    }
+}
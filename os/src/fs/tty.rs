@@ -0,0 +1,496 @@
+//! The line discipline of the virtual terminals.
+//!
+//! The UART is shared by [`NUM_VTS`] terminals, each with a line discipline,
+//! input and foreground process group of its own. Only the active one is
+//! shown, on the UART and the framebuffer console; the others keep what
+//! they are written in a history that is played back once they are switched
+//! to. Typing ^] and then a digit from 1 switches, ^] twice sends ^]; after
+//! ^] anything else, a digit past the last terminal included, is typed as
+//! usual.
+
+use super::ioctl::{read_arg, write_arg};
+use crate::config::NUM_VTS;
+use crate::console::write_terminal;
+use crate::mm::UserBuffer;
+use crate::sync::{Condvar, UPIntrFreeCell};
+use crate::syscall::{SysError, SysResult};
+use crate::task::{schedule, signal_group, suspend_current_and_run_next, SignalFlags};
+use crate::timer::get_time_ms;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use bitflags::*;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lazy_static::*;
+
+// ioctl request numbers, same as asm-generic on riscv64 linux
+pub const TCGETS: u32 = 0x5401;
+pub const TCSETS: u32 = 0x5402;
+pub const TCSETSW: u32 = 0x5403;
+pub const TCSETSF: u32 = 0x5404;
+pub const TIOCGPGRP: u32 = 0x540f;
+pub const TIOCSPGRP: u32 = 0x5410;
+pub const TIOCGWINSZ: u32 = 0x5413;
+pub const TIOCSWINSZ: u32 = 0x5414;
+pub const VT_GETSTATE: u32 = 0x5603;
+pub const VT_ACTIVATE: u32 = 0x5606;
+
+/// output a terminal keeps to redraw itself, a little more than a screen
+const HISTORY_SIZE: usize = 16384;
+/// ^], the prefix of the switching hotkey
+const SWITCH_KEY: u8 = 0x1d;
+
+const NCCS: usize = 19;
+// indexes of control characters in Termios::cc
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+
+bitflags! {
+    pub struct InputModes: u32 {
+        const ICRNL = 0o400;
+    }
+
+    pub struct LocalModes: u32 {
+        const ISIG = 0o1;
+        const ICANON = 0o2;
+        const ECHO = 0o10;
+        const ECHOE = 0o20;
+        const ECHONL = 0o100;
+    }
+}
+
+/// Layout compatible with `struct termios` of linux.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    /// The console starts in raw mode without echo since user_shell
+    /// does its own echoing and line editing.
+    pub fn new() -> Self {
+        let mut cc = [0u8; NCCS];
+        cc[VINTR] = 0x03;
+        cc[VQUIT] = 0x1c;
+        cc[VERASE] = 0x7f;
+        cc[VKILL] = 0x15;
+        cc[VEOF] = 0x04;
+        cc[VMIN] = 1;
+        cc[VSUSP] = 0x1a;
+        Self {
+            iflag: 0,
+            oflag: 0,
+            cflag: 0,
+            lflag: 0,
+            line: 0,
+            cc,
+        }
+    }
+    pub fn iflag(&self) -> InputModes {
+        InputModes::from_bits_truncate(self.iflag)
+    }
+    pub fn lflag(&self) -> LocalModes {
+        LocalModes::from_bits_truncate(self.lflag)
+    }
+}
+
+/// Layout compatible with `struct vt_stat` of linux.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct VtStat {
+    /// the active terminal, from 1
+    pub active: u16,
+    pub signal: u16,
+    /// bit n set for terminal n in use, all of them are
+    pub state: u16,
+}
+
+/// Layout compatible with `struct winsize` of linux.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct WinSize {
+    pub rows: u16,
+    pub cols: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+impl WinSize {
+    /// The serial console cannot be asked, assume a classic terminal.
+    pub fn new() -> Self {
+        Self {
+            rows: 24,
+            cols: 80,
+            xpixel: 0,
+            ypixel: 0,
+        }
+    }
+}
+
+pub struct TtyInner {
+    termios: Termios,
+    /// a finished line which has not been consumed completely in canonical mode
+    line: VecDeque<u8>,
+    /// bytes typed while the terminal was active, not read yet
+    input: VecDeque<u8>,
+    /// the last [`HISTORY_SIZE`] bytes written
+    history: VecDeque<u8>,
+    /// process group which receives the signals typed on the terminal, 0 if none
+    foreground: usize,
+    winsize: WinSize,
+}
+
+pub struct Tty {
+    /// from 0, /dev/tty1 is the first
+    index: usize,
+    inner: UPIntrFreeCell<TtyInner>,
+    condvar: Condvar,
+}
+
+lazy_static! {
+    pub static ref TTYS: Vec<Tty> = (0..NUM_VTS).map(Tty::new).collect();
+}
+
+/// index of the terminal shown and typed on
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+/// ^] was typed, the next byte picks the terminal
+static SWITCH_PENDING: AtomicBool = AtomicBool::new(false);
+/// index + 1 of the terminal switched to and not redrawn yet, 0 if none
+static REDRAW_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+pub fn active_tty() -> &'static Tty {
+    &TTYS[ACTIVE.load(Ordering::Relaxed)]
+}
+
+/// Show terminal `index` in place of the active one. This may run in the
+/// UART interrupt, so the redraw from its history is left to a task: its
+/// reader, which is woken for it, or whoever writes to it next.
+pub fn switch_tty(index: usize) {
+    if index >= NUM_VTS || ACTIVE.swap(index, Ordering::Relaxed) == index {
+        return;
+    }
+    REDRAW_PENDING.store(index + 1, Ordering::Relaxed);
+    TTYS[index].condvar.signal_all();
+}
+
+/// Bytes received on the UART, for the active terminal unless they switch.
+pub fn console_input(bytes: &[u8]) {
+    for &ch in bytes {
+        if SWITCH_PENDING.swap(false, Ordering::Relaxed) {
+            match ch {
+                b'1'..=b'9' if ((ch - b'1') as usize) < NUM_VTS => {
+                    switch_tty((ch - b'1') as usize);
+                    continue;
+                }
+                // anything else cancels and is typed as usual
+                _ => {}
+            }
+        } else if ch == SWITCH_KEY {
+            SWITCH_PENDING.store(true, Ordering::Relaxed);
+            continue;
+        }
+        active_tty().receive(ch);
+    }
+}
+
+impl Tty {
+    fn new(index: usize) -> Self {
+        Self {
+            index,
+            inner: unsafe {
+                UPIntrFreeCell::new(TtyInner {
+                    termios: Termios::new(),
+                    line: VecDeque::new(),
+                    input: VecDeque::new(),
+                    history: VecDeque::new(),
+                    foreground: 0,
+                    winsize: WinSize::new(),
+                })
+            },
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        ACTIVE.load(Ordering::Relaxed) == self.index
+    }
+
+    /// Play the history back if the terminal has been switched to since.
+    fn redraw_if_switched(&self) {
+        if REDRAW_PENDING
+            .compare_exchange(self.index + 1, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        let history: Vec<u8> = self.inner.exclusive_session(|inner| {
+            let (front, back) = inner.history.as_slices();
+            let mut history = front.iter().chain(back.iter()).copied();
+            // a trimmed history starts at a line, not in an escape sequence
+            if inner.history.len() == HISTORY_SIZE {
+                history.position(|ch| ch == b'\n');
+            }
+            history.collect()
+        });
+        // reset the attributes and clear the screen before playing back
+        write_terminal(b"\x1b[0m\x1b[2J\x1b[H");
+        write_terminal(&history);
+    }
+
+    /// Keep `bytes` for a redraw and show them if the terminal is active.
+    pub fn write(&self, bytes: &[u8]) {
+        self.redraw_if_switched();
+        self.inner.exclusive_session(|inner| {
+            let overflow = (inner.history.len() + bytes.len()).saturating_sub(HISTORY_SIZE);
+            inner.history.drain(..overflow.min(inner.history.len()));
+            let keep = bytes.len().min(HISTORY_SIZE);
+            inner.history.extend(&bytes[bytes.len() - keep..]);
+            if self.is_active() {
+                write_terminal(bytes);
+            }
+        });
+    }
+
+    /// A byte typed on the terminal; ^C and friends signal the foreground
+    /// job instead of being read.
+    fn receive(&self, ch: u8) {
+        if self.intercept(ch) {
+            return;
+        }
+        self.inner
+            .exclusive_session(|inner| inner.input.push_back(ch));
+        self.condvar.signal();
+    }
+
+    /// Called for every byte received. With ISIG, the interrupt, quit and
+    /// suspend characters are swallowed and signal the foreground group.
+    fn intercept(&self, ch: u8) -> bool {
+        let (termios, foreground) = self
+            .inner
+            .exclusive_session(|inner| (inner.termios, inner.foreground));
+        if !termios.lflag().contains(LocalModes::ISIG) {
+            return false;
+        }
+        let signal = if ch == termios.cc[VINTR] {
+            SignalFlags::SIGINT
+        } else if ch == termios.cc[VQUIT] {
+            SignalFlags::SIGQUIT
+        } else if ch == termios.cc[VSUSP] {
+            SignalFlags::SIGTSTP
+        } else {
+            return false;
+        };
+        if foreground != 0 {
+            signal_group(foreground, signal);
+        }
+        true
+    }
+
+    /// Whether a read would find something. In canonical mode a partial
+    /// line counts as well, the read then waits for the rest of it.
+    pub fn input_pending(&self) -> bool {
+        self.inner
+            .exclusive_session(|inner| !inner.line.is_empty() || !inner.input.is_empty())
+    }
+
+    fn translate(termios: &Termios, ch: u8) -> u8 {
+        if ch == b'\r' && termios.iflag().contains(InputModes::ICRNL) {
+            b'\n'
+        } else {
+            ch
+        }
+    }
+
+    fn input_byte(&self, termios: &Termios) -> u8 {
+        loop {
+            self.redraw_if_switched();
+            let mut inner = self.inner.exclusive_access();
+            if let Some(ch) = inner.input.pop_front() {
+                return Self::translate(termios, ch);
+            }
+            let task_cx_ptr = self.condvar.wait_no_sched();
+            drop(inner);
+            schedule(task_cx_ptr);
+        }
+    }
+
+    fn try_input_byte(&self, termios: &Termios) -> Option<u8> {
+        self.inner
+            .exclusive_session(|inner| inner.input.pop_front())
+            .map(|ch| Self::translate(termios, ch))
+    }
+
+    fn input_byte_timeout(&self, termios: &Termios, expire_ms: usize) -> Option<u8> {
+        loop {
+            self.redraw_if_switched();
+            if let Some(ch) = self.try_input_byte(termios) {
+                return Some(ch);
+            }
+            if get_time_ms() >= expire_ms {
+                return None;
+            }
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn echo(&self, termios: &Termios, ch: u8) {
+        let lflag = termios.lflag();
+        if lflag.contains(LocalModes::ECHO) || (ch == b'\n' && lflag.contains(LocalModes::ECHONL)) {
+            self.write(&[ch]);
+        }
+    }
+
+    fn echo_erase(&self, termios: &Termios) {
+        if termios
+            .lflag()
+            .contains(LocalModes::ECHO | LocalModes::ECHOE)
+        {
+            self.write(b"\x08 \x08");
+        }
+    }
+
+    fn read_canonical(&self, termios: &Termios) -> Vec<u8> {
+        let mut line: Vec<u8> = Vec::new();
+        loop {
+            let ch = self.input_byte(termios);
+            if ch == termios.cc[VERASE] || ch == 0x08 {
+                if line.pop().is_some() {
+                    self.echo_erase(termios);
+                }
+            } else if ch == termios.cc[VKILL] {
+                while line.pop().is_some() {
+                    self.echo_erase(termios);
+                }
+            } else if ch == termios.cc[VEOF] {
+                return line;
+            } else {
+                self.echo(termios, ch);
+                line.push(ch);
+                if ch == b'\n' {
+                    return line;
+                }
+            }
+        }
+    }
+
+    /// Non-canonical read following the VMIN/VTIME rules of termios(3).
+    fn read_raw(&self, termios: &Termios, want: usize) -> Vec<u8> {
+        let vmin = (termios.cc[VMIN] as usize).min(want);
+        let vtime_ms = termios.cc[VTIME] as usize * 100;
+        let mut bytes: Vec<u8> = Vec::new();
+        while bytes.len() < want {
+            let ch = if bytes.len() >= vmin.max(1) {
+                // request satisfied, only take what has already arrived
+                self.try_input_byte(termios)
+            } else if vtime_ms == 0 {
+                if vmin == 0 {
+                    self.try_input_byte(termios)
+                } else {
+                    Some(self.input_byte(termios))
+                }
+            } else if vmin > 0 && bytes.is_empty() {
+                // the inter-byte timer starts after the first byte
+                Some(self.input_byte(termios))
+            } else {
+                self.input_byte_timeout(termios, get_time_ms() + vtime_ms)
+            };
+            if let Some(ch) = ch {
+                self.echo(termios, ch);
+                bytes.push(ch);
+            } else {
+                break;
+            }
+        }
+        bytes
+    }
+
+    pub fn read(&self, mut buf: UserBuffer) -> usize {
+        let termios = self.inner.exclusive_session(|inner| inner.termios);
+        let want = buf.len();
+        let bytes = if termios.lflag().contains(LocalModes::ICANON) {
+            if self.inner.exclusive_session(|inner| inner.line.is_empty()) {
+                let line = self.read_canonical(&termios);
+                self.inner
+                    .exclusive_session(|inner| inner.line.extend(line.iter()));
+            }
+            self.inner.exclusive_session(|inner| {
+                let len = want.min(inner.line.len());
+                inner.line.drain(..len).collect::<Vec<u8>>()
+            })
+        } else {
+            self.read_raw(&termios, want)
+        };
+        let mut copied = 0;
+        for slice in buf.buffers.iter_mut() {
+            let len = slice.len().min(bytes.len() - copied);
+            slice[..len].copy_from_slice(&bytes[copied..copied + len]);
+            copied += len;
+            if copied == bytes.len() {
+                break;
+            }
+        }
+        copied
+    }
+
+    pub fn ioctl(&self, cmd: u32, arg: usize) -> SysResult {
+        match cmd {
+            TCGETS => write_arg(arg, &self.inner.exclusive_session(|inner| inner.termios))?,
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios: Termios = read_arg(arg)?;
+                self.inner.exclusive_session(|inner| {
+                    inner.termios = termios;
+                    if cmd == TCSETSF {
+                        inner.line.clear();
+                        inner.input.clear();
+                    }
+                });
+            }
+            TIOCGPGRP => write_arg(
+                arg,
+                &(self.inner.exclusive_session(|inner| inner.foreground) as i32),
+            )?,
+            TIOCSPGRP => {
+                let pgid: i32 = read_arg(arg)?;
+                if pgid <= 0 {
+                    return Err(SysError::EINVAL);
+                }
+                self.inner
+                    .exclusive_session(|inner| inner.foreground = pgid as usize);
+            }
+            TIOCGWINSZ => write_arg(arg, &self.inner.exclusive_session(|inner| inner.winsize))?,
+            TIOCSWINSZ => {
+                let winsize: WinSize = read_arg(arg)?;
+                self.inner
+                    .exclusive_session(|inner| inner.winsize = winsize);
+            }
+            VT_GETSTATE => write_arg(
+                arg,
+                &VtStat {
+                    active: ACTIVE.load(Ordering::Relaxed) as u16 + 1,
+                    signal: 0,
+                    state: ((1 << NUM_VTS) - 1) << 1,
+                },
+            )?,
+            VT_ACTIVATE => {
+                if arg == 0 || arg > NUM_VTS {
+                    return Err(SysError::EINVAL);
+                }
+                switch_tty(arg - 1);
+                TTYS[arg - 1].redraw_if_switched();
+            }
+            _ => return Err(SysError::ENOTTY),
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,247 @@
+//! A tiny hypervisor on the H extension. One guest runs in VS-mode on top of
+//! a G-stage page table; its SBI calls and accesses to the emulated UART trap
+//! back to HS-mode and are handled here.
+//!
+//! The firmware has to delegate VS-mode ecalls and guest page faults to
+//! HS-mode, as OpenSBI does on harts with the H extension.
+
+use crate::mm::{frame_alloc_for, FrameOwner, FrameTracker, PhysAddr, PhysPageNum};
+use alloc::alloc::{alloc_zeroed, dealloc, Layout};
+use alloc::vec::Vec;
+use core::arch::{asm, global_asm};
+use riscv::register::{scause, stval};
+
+global_asm!(include_str!("hv.S"));
+
+extern "C" {
+    fn __hv_probe() -> usize;
+    fn __hv_run(cpu: *mut GuestCpu);
+    fn __hv_guest_start();
+    fn __hv_guest_end();
+}
+
+const GUEST_RAM_BASE: usize = 0x8000_0000;
+const GUEST_RAM_PAGES: usize = 16;
+const GUEST_UART: usize = 0x1000_0000;
+const UART_THR: usize = 0;
+const UART_LSR: usize = 5;
+const LSR_THRE: u8 = 0x20;
+
+const HSTATUS_SPV: usize = 1 << 7;
+const HSTATUS_SPVP: usize = 1 << 8;
+const HSTATUS_VSXL_64: usize = 2 << 32;
+const HGATP_SV39X4: usize = 8 << 60;
+
+const EXC_VIRTUAL_SUPERVISOR_ECALL: usize = 10;
+const EXC_LOAD_GUEST_PAGE_FAULT: usize = 21;
+const EXC_STORE_GUEST_PAGE_FAULT: usize = 23;
+
+const SBI_LEGACY_CONSOLE_PUTCHAR: usize = 1;
+const SBI_LEGACY_SHUTDOWN: usize = 8;
+const SBI_ERR_NOT_SUPPORTED: isize = -2;
+
+/// Saved state of the guest hart and of the host while the guest runs,
+/// laid out for hv.S.
+#[repr(C)]
+#[derive(Default)]
+struct GuestCpu {
+    x: [usize; 32],
+    sepc: usize,
+    hstatus: usize,
+    /// ra, sp, s0-s11
+    host: [usize; 14],
+    host_stvec: usize,
+    host_sscratch: usize,
+}
+
+/// Sv39x4 G-stage page table, translating guest physical addresses.
+struct GuestMemory {
+    /// the root table covers 16KiB and must be aligned to it
+    root: *mut usize,
+    tables: Vec<FrameTracker>,
+    ram: Vec<FrameTracker>,
+}
+
+// valid, readable, writable, executable, user, accessed and dirty
+const PTE_LEAF: usize = 0xdf;
+const GUEST_FRAMES: FrameOwner = FrameOwner::Kernel("hv");
+const PTE_V: usize = 1;
+const ROOT_LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(0x4000, 0x4000) };
+
+impl GuestMemory {
+    fn new() -> Self {
+        let mut memory = Self {
+            root: unsafe { alloc_zeroed(ROOT_LAYOUT) } as *mut usize,
+            tables: Vec::new(),
+            ram: Vec::new(),
+        };
+        for page in 0..GUEST_RAM_PAGES {
+            let frame = frame_alloc_for(GUEST_FRAMES).unwrap();
+            memory.map(GUEST_RAM_BASE + page * 0x1000, frame.ppn);
+            memory.ram.push(frame);
+        }
+        memory
+    }
+
+    fn map(&mut self, gpa: usize, ppn: PhysPageNum) {
+        let mut table = self.root;
+        for (level, index) in [
+            (gpa >> 30) & 0x7ff,
+            (gpa >> 21) & 0x1ff,
+            (gpa >> 12) & 0x1ff,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            // the kernel maps physical memory one to one
+            let pte = unsafe { &mut *table.add(index) };
+            if level == 2 {
+                *pte = ppn.0 << 10 | PTE_LEAF;
+                return;
+            }
+            if *pte & PTE_V == 0 {
+                let frame = frame_alloc_for(GUEST_FRAMES).unwrap();
+                *pte = frame.ppn.0 << 10 | PTE_V;
+                self.tables.push(frame);
+            }
+            table = ((*pte >> 10) << 12) as *mut usize;
+        }
+    }
+
+    /// Host physical address backing `gpa` in guest RAM.
+    fn translate(&self, gpa: usize) -> Option<usize> {
+        let page = gpa.checked_sub(GUEST_RAM_BASE)? / 0x1000;
+        let frame = self.ram.get(page)?;
+        Some(PhysAddr::from(frame.ppn).0 + gpa % 0x1000)
+    }
+
+    fn read_u16(&self, gpa: usize) -> Option<u16> {
+        self.translate(gpa)
+            .map(|pa| unsafe { (pa as *const u16).read_volatile() })
+    }
+
+    fn hgatp(&self) -> usize {
+        HGATP_SV39X4 | (self.root as usize >> 12)
+    }
+}
+
+impl Drop for GuestMemory {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.root as *mut u8, ROOT_LAYOUT) };
+    }
+}
+
+fn h_extension_present() -> bool {
+    unsafe { __hv_probe() != 0 }
+}
+
+fn htval() -> usize {
+    let htval: usize;
+    unsafe { asm!("csrr {}, 0x643", out(reg) htval) };
+    htval
+}
+
+/// Emulate a byte access of the guest to its UART, return false for any
+/// other access.
+fn emulate_uart(cpu: &mut GuestCpu, memory: &GuestMemory, gpa: usize) -> bool {
+    let inst = match (memory.read_u16(cpu.sepc), memory.read_u16(cpu.sepc + 2)) {
+        (Some(low), Some(high)) => low as usize | (high as usize) << 16,
+        _ => return false,
+    };
+    let offset = match gpa.checked_sub(GUEST_UART) {
+        Some(offset) if offset < 8 => offset,
+        _ => return false,
+    };
+    let funct3 = (inst >> 12) & 0b111;
+    match (inst & 0x7f, funct3) {
+        // sb
+        (0x23, 0b000) => {
+            if offset == UART_THR {
+                print!("{}", cpu.x[(inst >> 20) & 0x1f] as u8 as char);
+            }
+        }
+        // lb, lbu
+        (0x03, 0b000) | (0x03, 0b100) => {
+            let rd = (inst >> 7) & 0x1f;
+            if rd != 0 {
+                cpu.x[rd] = if offset == UART_LSR {
+                    LSR_THRE as usize
+                } else {
+                    0
+                };
+            }
+        }
+        _ => return false,
+    }
+    cpu.sepc += 4;
+    true
+}
+
+/// Handle an SBI call of the guest, return false when it shuts down.
+fn handle_sbi_call(cpu: &mut GuestCpu) -> bool {
+    cpu.sepc += 4;
+    match cpu.x[17] {
+        SBI_LEGACY_CONSOLE_PUTCHAR => {
+            print!("{}", cpu.x[10] as u8 as char);
+            cpu.x[10] = 0;
+        }
+        SBI_LEGACY_SHUTDOWN => return false,
+        _ => cpu.x[10] = SBI_ERR_NOT_SUPPORTED as usize,
+    }
+    true
+}
+
+/// Boot the built-in guest and run it until it shuts down.
+pub fn run_demo() {
+    if !h_extension_present() {
+        println!("[hv] no H extension, not starting the guest");
+        return;
+    }
+    let memory = GuestMemory::new();
+    let image = unsafe {
+        core::slice::from_raw_parts(
+            __hv_guest_start as usize as *const u8,
+            __hv_guest_end as usize - __hv_guest_start as usize,
+        )
+    };
+    memory.ram[0].ppn.get_bytes_array()[..image.len()].copy_from_slice(image);
+    unsafe {
+        // nothing is delegated to VS-mode, every guest trap comes here
+        asm!("csrw 0x602, zero", "csrw 0x603, zero", "csrw 0x280, zero");
+        asm!("csrw 0x680, {}", ".word 0x62000073", in(reg) memory.hgatp());
+    }
+    let mut cpu = GuestCpu {
+        sepc: GUEST_RAM_BASE,
+        hstatus: HSTATUS_SPV | HSTATUS_SPVP | HSTATUS_VSXL_64,
+        ..Default::default()
+    };
+    println!("[hv] starting the guest");
+    loop {
+        unsafe { __hv_run(&mut cpu) };
+        let scause = scause::read();
+        let handled = match scause.bits() {
+            EXC_VIRTUAL_SUPERVISOR_ECALL => {
+                if !handle_sbi_call(&mut cpu) {
+                    break;
+                }
+                true
+            }
+            EXC_LOAD_GUEST_PAGE_FAULT | EXC_STORE_GUEST_PAGE_FAULT => {
+                let gpa = htval() << 2 | stval::read() & 0b11;
+                emulate_uart(&mut cpu, &memory, gpa)
+            }
+            _ => false,
+        };
+        if !handled {
+            println!(
+                "[hv] unhandled guest trap {:?}, stval = {:#x}, sepc = {:#x}",
+                scause.cause(),
+                stval::read(),
+                cpu.sepc
+            );
+            break;
+        }
+    }
+    unsafe { asm!("csrw 0x680, zero", ".word 0x62000073") };
+    println!("[hv] guest shut down");
+}
@@ -0,0 +1,101 @@
+//! Names of kernel functions, to print for code addresses.
+//!
+//! build.rs turns the function symbols of the previous link into a table
+//! at the end of .rodata, and the Makefile links a second time whenever
+//! they changed. The table only moves data, so the code it describes stays
+//! where the symbols put it. A kernel built by cargo alone has no table and
+//! prints plain addresses.
+
+use alloc::string::String;
+use core::fmt::{self, Display, Formatter, Write};
+
+core::arch::global_asm!(
+    include_str!(concat!(env!("OUT_DIR"), "/ksyms.S")),
+    options(raw)
+);
+
+extern "C" {
+    fn stext();
+    fn etext();
+    fn ksyms();
+}
+
+struct Table {
+    addrs: &'static [usize],
+    /// where each name starts in `names`, one more for where the last ends
+    starts: &'static [u32],
+    names: *const u8,
+}
+
+fn table() -> Table {
+    unsafe {
+        let base = ksyms as usize as *const usize;
+        let count = *base;
+        let addrs = core::slice::from_raw_parts(base.add(1), count);
+        let starts = core::slice::from_raw_parts(base.add(1 + count) as *const u32, count + 1);
+        let names = starts.as_ptr().add(count + 1) as *const u8;
+        Table {
+            addrs,
+            starts,
+            names,
+        }
+    }
+}
+
+impl Table {
+    fn name(&self, index: usize) -> &'static str {
+        let start = self.starts[index] as usize;
+        let len = self.starts[index + 1] as usize - start;
+        unsafe {
+            core::str::from_utf8_unchecked(core::slice::from_raw_parts(self.names.add(start), len))
+        }
+    }
+}
+
+/// The function `addr` is in and how far into it, None outside of the
+/// kernel code or without a table.
+pub fn lookup(addr: usize) -> Option<(&'static str, usize)> {
+    if !(stext as usize..etext as usize).contains(&addr) {
+        return None;
+    }
+    let table = table();
+    let index = table.addrs.partition_point(|&start| start <= addr);
+    let index = index.checked_sub(1)?;
+    Some((table.name(index), addr - table.addrs[index]))
+}
+
+/// An address printed as `0x80201234 <os::rust_main+0x34>` when it is known
+/// and as `0x80201234` when not.
+pub struct Symbolized(pub usize);
+
+impl Display for Symbolized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match lookup(self.0) {
+            Some((name, offset)) => write!(f, "{:#x} <{}+{:#x}>", self.0, name, offset),
+            None => write!(f, "{:#x}", self.0),
+        }
+    }
+}
+
+/// The name of the function at `addr`, or the address if it has none, for
+/// output that groups by function.
+pub fn function_name(addr: usize) -> String {
+    let mut text = String::new();
+    match lookup(addr) {
+        Some((name, _)) => text.push_str(name),
+        None => write!(text, "{:#x}", addr).unwrap(),
+    }
+    text
+}
+
+/// /proc/ksyms, a line `80201000 os::rust_main` for each function as
+/// /proc/kallsyms has it, for tools resolving the kernel addresses of
+/// /proc/trace.
+pub fn listing() -> String {
+    let table = table();
+    let mut text = String::new();
+    for (index, addr) in table.addrs.iter().enumerate() {
+        writeln!(text, "{:x} {}", addr, table.name(index)).unwrap();
+    }
+    text
+}
@@ -1,4 +1,7 @@
+use crate::drivers::gpu::fbcon;
+use crate::ksyms::Symbolized;
 use crate::sbi::shutdown;
+use crate::smp::stop_other_harts;
 use crate::task::current_kstack_top;
 use core::arch::asm;
 use core::panic::PanicInfo;
@@ -6,6 +9,8 @@ use log::*;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    stop_other_harts();
+    fbcon::release();
     if let Some(location) = info.location() {
         error!(
             "[kernel] Panicked at {}:{} {}",
@@ -22,6 +27,26 @@ fn panic(info: &PanicInfo) -> ! {
     shutdown(true)
 }
 
+/// Return addresses of the callers of the function this is inlined into,
+/// walking the frame pointers while they stay on the current stack.
+#[inline(always)]
+#[allow(unused)]
+pub fn call_site<const N: usize>() -> [usize; N] {
+    let mut site = [0; N];
+    let (mut fp, sp): (usize, usize);
+    unsafe { asm!("mv {}, s0", "mv {}, sp", out(reg) fp, out(reg) sp) };
+    for ra in site.iter_mut() {
+        if fp < sp || fp - sp > 0x10000 || fp % 8 != 0 {
+            break;
+        }
+        unsafe {
+            *ra = *((fp - 8) as *const usize);
+            fp = *((fp - 16) as *const usize);
+        }
+    }
+    site
+}
+
 unsafe fn backtrace() {
     let mut fp: usize;
     let stop = current_kstack_top();
@@ -31,7 +56,7 @@ unsafe fn backtrace() {
         if fp == stop {
             break;
         }
-        println!("#{}:ra={:#x}", i, *((fp - 8) as *const usize));
+        println!("#{}:ra={}", i, Symbolized(*((fp - 8) as *const usize)));
         fp = *((fp - 16) as *const usize);
     }
     println!("---END   BACKTRACE---");
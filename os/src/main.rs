@@ -10,22 +10,33 @@ extern crate alloc;
 #[macro_use]
 extern crate bitflags;
 
-#[path = "boards/qemu.rs"]
-mod board;
+/// src/boards/<board>.rs of CONFIG_BOARD
+mod board {
+    include!(concat!(env!("OUT_DIR"), "/board.rs"));
+}
 
 #[macro_use]
 mod console;
 mod config;
 mod drivers;
+mod fdt;
 mod fs;
+#[cfg(feature = "hypervisor")]
+mod hv;
+mod ksyms;
 mod lang_items;
 mod mm;
 mod net;
+mod power;
+mod profile;
+mod random;
 mod sbi;
+mod smp;
 mod sync;
 mod syscall;
 mod task;
 mod timer;
+mod trace;
 mod trap;
 
 use crate::drivers::chardev::CharDevice;
@@ -52,22 +63,47 @@ lazy_static! {
         unsafe { UPIntrFreeCell::new(false) };
 }
 
+/// Kernel self tests, built with KTEST=on.
+#[cfg(feature = "ktest")]
+fn self_test() {
+    mm::self_test();
+    random::chacha20_test();
+    smp::smp_test();
+}
+
+/// `dtb` is where the firmware left the device tree.
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(_hart_id: usize, dtb: usize) -> ! {
     clear_bss();
-    mm::init();
+    mm::init(dtb);
     UART.init();
+    println!("KERN: memory up to {:#x}", mm::memory_end());
     println!("KERN: init gpu");
     let _gpu = GPU_DEVICE.clone();
+    drivers::gpu::fbcon::init();
     println!("KERN: init keyboard");
     let _keyboard = KEYBOARD_DEVICE.clone();
     println!("KERN: init mouse");
     let _mouse = MOUSE_DEVICE.clone();
+    let _tablet = drivers::TABLET_DEVICE.clone();
     println!("KERN: init trap");
     trap::init();
+    smp::init();
+    smp::boot_other_harts();
+    #[cfg(feature = "ktest")]
+    self_test();
+    #[cfg(feature = "hypervisor")]
+    hv::run_demo();
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
+    timer::init_realtime();
+    timer::enable_user_counters();
     board::device_init();
+    println!("KERN: init network");
+    net::dhcp::init();
+    drivers::list_pci_functions();
+    drivers::console::init();
+    drivers::list_partitions();
     fs::list_apps();
     task::add_initproc();
     *DEV_NON_BLOCKING_ACCESS.exclusive_access() = true;
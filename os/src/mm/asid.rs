@@ -0,0 +1,176 @@
+//! Address space identifiers. Every user address space gets an ASID, so
+//! switching satp keeps the TLB entries of the others. ASIDs are handed out
+//! in generations: when they run out the whole TLB is flushed and a new
+//! generation starts, every address space then takes a fresh ASID the next
+//! time it is activated. The kernel space always uses ASID 0.
+
+use crate::config::PAGE_SIZE;
+use crate::sbi::remote_sfence_vma;
+use crate::sync::UPIntrFreeCell;
+use crate::task::hart_id;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::*;
+use riscv::register::satp;
+
+pub const SATP_ASID_SHIFT: usize = 44;
+const SATP_ASID_MASK: usize = 0xffff;
+/// Ranges of more pages flush the whole address space at once.
+const FLUSH_ALL_PAGES: usize = 32;
+
+/// harts which have activated the kernel space
+static KERNEL_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+struct AsidAllocator {
+    /// implemented ASID bits, 0 without ASID support
+    bits: usize,
+    generation: usize,
+    next: usize,
+    /// freed in the current generation
+    recycled: Vec<usize>,
+}
+
+lazy_static! {
+    static ref ASID_ALLOCATOR: UPIntrFreeCell<AsidAllocator> = unsafe {
+        UPIntrFreeCell::new(AsidAllocator {
+            bits: probe_asid_bits(),
+            generation: 1,
+            next: 1,
+            recycled: Vec::new(),
+        })
+    };
+}
+
+/// Unimplemented ASID bits read as zero after writing all ones.
+fn probe_asid_bits() -> usize {
+    let old = satp::read().bits();
+    unsafe {
+        satp::write(old | SATP_ASID_MASK << SATP_ASID_SHIFT);
+    }
+    let asids = (satp::read().bits() >> SATP_ASID_SHIFT) & SATP_ASID_MASK;
+    unsafe {
+        satp::write(old);
+        asm!("sfence.vma");
+    }
+    (asids + 1).trailing_zeros() as usize
+}
+
+pub fn asids_supported() -> bool {
+    ASID_ALLOCATOR.exclusive_access().bits != 0
+}
+
+fn flush_asid(asid: usize) {
+    unsafe { asm!("sfence.vma zero, {}", in(reg) asid) };
+}
+
+/// Flush `pages` pages from `start` of `asid` on the harts in `harts`,
+/// local ones with sfence.vma and the others through the SBI. `asid` is
+/// None without ASID support.
+fn shootdown(harts: usize, start: usize, pages: usize, asid: Option<usize>) {
+    let this_hart = 1 << hart_id();
+    let all = pages > FLUSH_ALL_PAGES;
+    if harts & this_hart != 0 {
+        match (all, asid) {
+            (true, Some(asid)) => flush_asid(asid),
+            (true, None) => unsafe { asm!("sfence.vma") },
+            (false, _) => {
+                for va in (start..start + pages * PAGE_SIZE).step_by(PAGE_SIZE) {
+                    unsafe { asm!("sfence.vma {}, {}", in(reg) va, in(reg) asid.unwrap_or(0)) };
+                }
+            }
+        }
+    }
+    let others = harts & !this_hart;
+    if others != 0 {
+        let (start, size) = if all {
+            (0, usize::MAX)
+        } else {
+            (start, pages * PAGE_SIZE)
+        };
+        remote_sfence_vma(others, start, size, asid);
+    }
+}
+
+/// ASID of a user address space and the generation it was taken from,
+/// generation 0 before the first activation.
+#[derive(Default)]
+pub struct Asid {
+    asid: usize,
+    generation: usize,
+    /// harts which may cache translations of this ASID
+    harts: usize,
+}
+
+impl Asid {
+    /// ASID to put in satp, taking one from the current generation if needed.
+    pub fn get(&mut self) -> usize {
+        let mut allocator = ASID_ALLOCATOR.exclusive_access();
+        self.harts |= 1 << hart_id();
+        if allocator.bits == 0 || self.generation == allocator.generation {
+            return self.asid;
+        }
+        self.harts = 1 << hart_id();
+        if let Some(asid) = allocator.recycled.pop() {
+            // entries of the previous user may still be cached on any hart
+            shootdown(all_harts(), 0, usize::MAX, Some(asid));
+            self.asid = asid;
+        } else {
+            if allocator.next == 1 << allocator.bits {
+                allocator.generation += 1;
+                allocator.next = 1;
+                shootdown(all_harts(), 0, usize::MAX, None);
+            }
+            self.asid = allocator.next;
+            allocator.next += 1;
+        }
+        self.generation = allocator.generation;
+        self.asid
+    }
+
+    /// Drop cached translations of `pages` pages from `start` after their
+    /// mappings have changed, on every hart the space has run on.
+    pub fn flush(&self, start: usize, pages: usize) {
+        let allocator = ASID_ALLOCATOR.exclusive_access();
+        let asid = match allocator.bits {
+            0 => None,
+            _ if self.generation == allocator.generation => Some(self.asid),
+            // an ASID of an older generation has nothing cached
+            _ => return,
+        };
+        drop(allocator);
+        shootdown(self.harts, start, pages, asid);
+    }
+}
+
+impl Drop for Asid {
+    fn drop(&mut self) {
+        if self.generation == 0 {
+            return;
+        }
+        let mut allocator = ASID_ALLOCATOR.exclusive_access();
+        if allocator.bits != 0 && self.generation == allocator.generation {
+            allocator.recycled.push(self.asid);
+        }
+    }
+}
+
+fn all_harts() -> usize {
+    KERNEL_HARTS.load(Ordering::Acquire)
+}
+
+/// Drop cached translations of the kernel space, on every hart.
+pub fn flush_kernel(start: usize, pages: usize) {
+    // user spaces share the kernel mappings under their own ASIDs
+    let asid = if cfg!(feature = "unified") {
+        None
+    } else {
+        Some(0)
+    };
+    shootdown(all_harts(), start, pages, asid);
+}
+
+/// Called by each hart switching to the kernel space.
+pub fn kernel_space_activated() {
+    KERNEL_HARTS.fetch_or(1 << hart_id(), Ordering::AcqRel);
+}
@@ -0,0 +1,84 @@
+//! Buffers devices read and write on their own.
+//!
+//! A device sees physical memory only, so a buffer of more than one page
+//! has to be made of frames that follow each other, and rings and tables
+//! often have to start at some alignment as well. The kernel maps all of
+//! physical memory at the same addresses, which makes the physical address
+//! of a buffer also the one to access it at.
+
+use super::frame_allocator::{frame_alloc_contiguous, frame_dealloc};
+use super::{PhysAddr, PhysPageNum};
+use crate::config::PAGE_SIZE;
+use core::fmt::{self, Debug, Formatter};
+
+/// Physically contiguous, zeroed pages, freed when dropped.
+pub struct DmaBuffer {
+    ppn: PhysPageNum,
+    pages: usize,
+}
+
+impl DmaBuffer {
+    /// Physical address of the first byte, to give the device.
+    pub fn paddr(&self) -> usize {
+        PhysAddr::from(self.ppn).0
+    }
+    /// Where the kernel accesses the buffer, the same as `paddr`.
+    pub fn vaddr(&self) -> usize {
+        self.paddr()
+    }
+    pub fn size(&self) -> usize {
+        self.pages * PAGE_SIZE
+    }
+    pub fn as_ptr<T>(&self) -> *mut T {
+        self.vaddr() as *mut T
+    }
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.as_ptr(), self.size()) }
+    }
+}
+
+impl Debug for DmaBuffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "DmaBuffer:PA={:#x},pages={}",
+            self.paddr(),
+            self.pages
+        ))
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        for ppn in self.ppn.0..self.ppn.0 + self.pages {
+            frame_dealloc(ppn.into());
+        }
+    }
+}
+
+/// A buffer of at least `size` bytes starting at a multiple of `align`, a
+/// power of two. Every buffer is page aligned, so a smaller `align` asks
+/// for nothing more. None if no such run of frames is left.
+pub fn dma_alloc(size: usize, align: usize) -> Option<DmaBuffer> {
+    assert!(align.is_power_of_two(), "DMA alignment {:#x}", align);
+    let pages = size.div_ceil(PAGE_SIZE).max(1);
+    let align_pages = (align / PAGE_SIZE).max(1);
+    let ppn = frame_alloc_contiguous(pages, align_pages)?;
+    let buffer = DmaBuffer { ppn, pages };
+    unsafe { core::ptr::write_bytes(buffer.as_ptr::<u8>(), 0, buffer.size()) };
+    Some(buffer)
+}
+
+#[cfg(feature = "ktest")]
+pub fn dma_alloc_test() {
+    let ring = dma_alloc(3 * PAGE_SIZE, 4 * PAGE_SIZE).unwrap();
+    println!("{:?}", ring);
+    assert_eq!(ring.paddr() % (4 * PAGE_SIZE), 0);
+    assert_eq!(ring.size(), 3 * PAGE_SIZE);
+    let mut small = dma_alloc(100, 64).unwrap();
+    println!("{:?}", small);
+    assert!(small.as_mut_slice().iter().all(|&byte| byte == 0));
+    small.as_mut_slice().fill(0xa5);
+    drop(small);
+    drop(ring);
+    println!("dma_alloc_test passed!");
+}
@@ -1,10 +1,23 @@
+use super::memory_end;
 use super::{PhysAddr, PhysPageNum};
-use crate::config::MEMORY_END;
+#[cfg(feature = "frame_debug")]
+use crate::config::PAGE_SIZE;
+#[cfg(feature = "frame_debug")]
+use crate::lang_items::call_site;
 use crate::sync::UPIntrFreeCell;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
 
+/// Who a frame is allocated for, remembered with the `frame_debug` feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameOwner {
+    /// a kernel subsystem
+    Kernel(&'static str),
+    /// the address space of a process, by pid
+    Process(usize),
+}
+
 pub struct FrameTracker {
     pub ppn: PhysPageNum,
 }
@@ -35,7 +48,8 @@ impl Drop for FrameTracker {
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
-    fn alloc_more(&mut self, pages: usize) -> Option<Vec<PhysPageNum>>;
+    /// `pages` frames in a row, the first at a multiple of `align` frames.
+    fn alloc_contiguous(&mut self, pages: usize, align: usize) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
 }
 
@@ -43,14 +57,39 @@ pub struct StackFrameAllocator {
     current: usize,
     end: usize,
     recycled: Vec<usize>,
+    /// frames the firmware keeps, ascending (start, end) ranges not yet
+    /// passed by `current`
+    reserved: Vec<(usize, usize)>,
 }
 
 impl StackFrameAllocator {
-    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum, reserved: &[(usize, usize)]) {
         self.current = l.0;
         self.end = r.0;
+        self.reserved = reserved
+            .iter()
+            .map(|&(start, end)| {
+                (
+                    PhysAddr::from(start).floor().0,
+                    PhysAddr::from(end).ceil().0,
+                )
+            })
+            .filter(|&(start, end)| start < end && end > l.0 && start < r.0)
+            .collect();
+        self.reserved.sort_unstable();
+        self.skip_reserved();
         // println!("last {} Physical Frames.", self.end - self.current);
     }
+    /// Move `current` past a reserved range it has run into.
+    fn skip_reserved(&mut self) {
+        while let Some(&(start, end)) = self.reserved.first() {
+            if self.current < start {
+                break;
+            }
+            self.current = self.current.max(end);
+            self.reserved.remove(0);
+        }
+    }
 }
 impl FrameAllocator for StackFrameAllocator {
     fn new() -> Self {
@@ -58,27 +97,42 @@ impl FrameAllocator for StackFrameAllocator {
             current: 0,
             end: 0,
             recycled: Vec::new(),
+            reserved: Vec::new(),
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
         if let Some(ppn) = self.recycled.pop() {
             Some(ppn.into())
-        } else if self.current == self.end {
+        } else if self.current >= self.end {
             None
         } else {
             self.current += 1;
-            Some((self.current - 1).into())
+            let ppn = self.current - 1;
+            self.skip_reserved();
+            Some(ppn.into())
         }
     }
-    fn alloc_more(&mut self, pages: usize) -> Option<Vec<PhysPageNum>> {
-        if self.current + pages >= self.end {
-            None
-        } else {
-            self.current += pages;
-            let arr: Vec<usize> = (1..pages + 1).collect();
-            let v = arr.iter().map(|x| (self.current - x).into()).collect();
-            Some(v)
+    fn alloc_contiguous(&mut self, pages: usize, align: usize) -> Option<PhysPageNum> {
+        // recycled frames are scattered, runs come from the untouched part
+        let mut start = self.current.next_multiple_of(align);
+        // a run must not cross a reserved range, retry after it
+        while let Some(&(reserved, _)) = self.reserved.first() {
+            if start + pages <= reserved || start + pages > self.end {
+                break;
+            }
+            self.recycled.extend(self.current..reserved);
+            self.current = reserved;
+            self.skip_reserved();
+            start = self.current.next_multiple_of(align);
+        }
+        if start + pages > self.end {
+            return None;
         }
+        // the frames skipped for the alignment stay usable one by one
+        self.recycled.extend(self.current..start);
+        self.current = start + pages;
+        self.skip_reserved();
+        Some(start.into())
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
@@ -98,44 +152,194 @@ lazy_static! {
         unsafe { UPIntrFreeCell::new(FrameAllocatorImpl::new()) };
 }
 
-pub fn init_frame_allocator() {
+/// Hand out the frames from the kernel image up to the end of memory, all
+/// but the `reserved` ranges.
+pub fn init_frame_allocator(reserved: &[(usize, usize)]) {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
+    let (l, r) = (
         PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
+        PhysAddr::from(memory_end()).floor(),
     );
+    FRAME_ALLOCATOR.exclusive_access().init(l, r, reserved);
+    #[cfg(feature = "frame_debug")]
+    FRAME_DEBUG.exclusive_access().init(l, r);
 }
 
-pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
+/// Return addresses kept for the last allocation and free of a frame.
+#[cfg(feature = "frame_debug")]
+const SITE_DEPTH: usize = 4;
+
+/// What the `frame_debug` feature remembers of a frame.
+#[cfg(feature = "frame_debug")]
+#[derive(Clone, Copy)]
+struct FrameRecord {
+    allocated: bool,
+    owner: Option<FrameOwner>,
+    alloc_site: [usize; SITE_DEPTH],
+    free_site: [usize; SITE_DEPTH],
+}
+
+#[cfg(feature = "frame_debug")]
+impl FrameRecord {
+    const NEVER_ALLOCATED: Self = Self {
+        allocated: false,
+        owner: None,
+        alloc_site: [0; SITE_DEPTH],
+        free_site: [0; SITE_DEPTH],
+    };
+}
+
+/// Bookkeeping of the `frame_debug` feature, indexed by frame.
+#[cfg(feature = "frame_debug")]
+struct FrameDebug {
+    base: usize,
+    /// in frames of their own, they outgrow the kernel heap with memory
+    records: &'static mut [FrameRecord],
+}
+
+#[cfg(feature = "frame_debug")]
+impl FrameDebug {
+    /// Take the frames for the records of `[l, r)` from the allocator.
+    fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        let frames = r.0 - l.0;
+        let pages = (frames * core::mem::size_of::<FrameRecord>()).div_ceil(PAGE_SIZE);
+        let first = FRAME_ALLOCATOR
+            .exclusive_access()
+            .alloc_contiguous(pages, 1)
+            .expect("no room for the frame_debug records");
+        let records = PhysAddr::from(first).0 as *mut FrameRecord;
+        for index in 0..frames {
+            unsafe { records.add(index).write(FrameRecord::NEVER_ALLOCATED) };
+        }
+        self.base = l.0;
+        self.records = unsafe { core::slice::from_raw_parts_mut(records, frames) };
+    }
+
+    fn index(&self, ppn: PhysPageNum) -> Option<usize> {
+        ppn.0
+            .checked_sub(self.base)
+            .filter(|&index| index < self.records.len())
+    }
+
+    fn alloc(&mut self, ppn: PhysPageNum, owner: FrameOwner, site: [usize; SITE_DEPTH]) {
+        let record = &mut self.records[self.index(ppn).unwrap()];
+        assert!(
+            !record.allocated,
+            "frame {:#x} handed out twice, first allocated at {:x?}",
+            ppn.0, record.alloc_site
+        );
+        record.allocated = true;
+        record.owner = Some(owner);
+        record.alloc_site = site;
+    }
+
+    fn dealloc(&mut self, ppn: PhysPageNum, site: [usize; SITE_DEPTH]) {
+        let record = match self.index(ppn) {
+            Some(index) => &mut self.records[index],
+            None => panic!(
+                "frame {:#x} freed at {:x?} is outside of the allocator",
+                ppn.0, site
+            ),
+        };
+        if !record.allocated {
+            if record.owner.is_none() {
+                panic!(
+                    "frame {:#x} freed at {:x?} was never allocated",
+                    ppn.0, site
+                );
+            }
+            panic!(
+                "frame {:#x} freed twice: allocated at {:x?}, freed at {:x?} and again at {:x?}",
+                ppn.0, record.alloc_site, record.free_site, site
+            );
+        }
+        record.allocated = false;
+        record.free_site = site;
+    }
+}
+
+#[cfg(feature = "frame_debug")]
+lazy_static! {
+    static ref FRAME_DEBUG: UPIntrFreeCell<FrameDebug> = unsafe {
+        UPIntrFreeCell::new(FrameDebug {
+            base: 0,
+            records: &mut [],
+        })
+    };
+}
+
+#[allow(unused_variables)]
+#[inline(always)]
+fn track(ppn: PhysPageNum, owner: FrameOwner) {
+    #[cfg(feature = "frame_debug")]
+    FRAME_DEBUG
         .exclusive_access()
-        .alloc()
-        .map(FrameTracker::new)
+        .alloc(ppn, owner, call_site());
+}
+
+pub fn frame_alloc() -> Option<FrameTracker> {
+    frame_alloc_for(FrameOwner::Kernel("kernel"))
 }
 
+pub fn frame_alloc_for(owner: FrameOwner) -> Option<FrameTracker> {
+    let ppn = FRAME_ALLOCATOR.exclusive_access().alloc()?;
+    track(ppn, owner);
+    Some(FrameTracker::new(ppn))
+}
+
+/// Contiguous frames in ascending order.
 pub fn frame_alloc_more(num: usize) -> Option<Vec<FrameTracker>> {
-    FRAME_ALLOCATOR
+    let first = frame_alloc_contiguous(num, 1)?;
+    Some(
+        (first.0..first.0 + num)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
+}
+
+/// Contiguous frames without trackers, the first aligned to `align`
+/// frames, for DMA buffers which free them on their own.
+pub(super) fn frame_alloc_contiguous(pages: usize, align: usize) -> Option<PhysPageNum> {
+    let first = FRAME_ALLOCATOR
         .exclusive_access()
-        .alloc_more(num)
-        .map(|x| x.iter().map(|&t| FrameTracker::new(t)).collect())
+        .alloc_contiguous(pages, align)?;
+    for ppn in first.0..first.0 + pages {
+        track(ppn.into(), FrameOwner::Kernel("dma"));
+    }
+    Some(first)
 }
 
 pub fn frame_dealloc(ppn: PhysPageNum) {
+    #[cfg(feature = "frame_debug")]
+    FRAME_DEBUG.exclusive_access().dealloc(ppn, call_site());
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
 }
 
-#[allow(unused)]
+/// Frames still allocated for `owner`.
+#[cfg(feature = "frame_debug")]
+pub fn frames_owned_by(owner: FrameOwner) -> Vec<PhysPageNum> {
+    let debug = FRAME_DEBUG.exclusive_access();
+    debug
+        .records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| record.allocated && record.owner == Some(owner))
+        .map(|(index, _)| PhysPageNum(debug.base + index))
+        .collect()
+}
+
+#[cfg(feature = "ktest")]
 pub fn frame_allocator_test() {
     let mut v: Vec<FrameTracker> = Vec::new();
-    for i in 0..5 {
+    for _ in 0..5 {
         let frame = frame_alloc().unwrap();
         println!("{:?}", frame);
         v.push(frame);
     }
     v.clear();
-    for i in 0..5 {
+    for _ in 0..5 {
         let frame = frame_alloc().unwrap();
         println!("{:?}", frame);
         v.push(frame);
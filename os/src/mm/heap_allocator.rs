@@ -1,8 +1,15 @@
+#[cfg(feature = "kasan")]
+use super::kasan::Kasan;
+use super::slab::SlabAllocator;
 use crate::config::KERNEL_HEAP_SIZE;
-use buddy_system_allocator::LockedHeap;
+
+#[cfg(not(feature = "kasan"))]
+type KernelAllocator = SlabAllocator;
+#[cfg(feature = "kasan")]
+type KernelAllocator = Kasan;
 
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: KernelAllocator = KernelAllocator::new();
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -13,13 +20,11 @@ static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
 pub fn init_heap() {
     unsafe {
-        HEAP_ALLOCATOR
-            .lock()
-            .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
+        HEAP_ALLOCATOR.init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
 }
 
-#[allow(unused)]
+#[cfg(feature = "ktest")]
 pub fn heap_test() {
     use alloc::boxed::Box;
     use alloc::vec::Vec;
@@ -43,3 +48,22 @@ pub fn heap_test() {
     drop(v);
     println!("heap_test passed!");
 }
+
+/// Objects are moved and kept around by kasan, nothing to check there.
+#[cfg(not(feature = "kasan"))]
+#[allow(unused)]
+pub fn slab_test() {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    let objects: Vec<Box<[u8; 100]>> = (0..200).map(|i| Box::new([i as u8; 100])).collect();
+    for (i, object) in objects.iter().enumerate() {
+        assert_eq!(object.as_ptr() as usize % 128, 0);
+        assert!(object.iter().all(|byte| *byte == i as u8));
+    }
+    let (_, slabs) = HEAP_ALLOCATOR.stats()[3];
+    assert!(slabs >= 2);
+    drop(objects);
+    let (_, slabs) = HEAP_ALLOCATOR.stats()[3];
+    assert!(slabs <= 1);
+    println!("slab_test passed!");
+}
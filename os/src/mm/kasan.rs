@@ -0,0 +1,198 @@
+//! A lightweight KASAN for the kernel heap, built with the `kasan` feature.
+//! Every allocation is surrounded by poisoned redzones and starts with a
+//! header recording its size and the call chain that made it; freed objects
+//! are poisoned and held in a quarantine for a while. Out-of-bounds writes
+//! are caught when the object is freed, writes after free when it leaves
+//! the quarantine, and both panic with the allocation site.
+
+use super::slab::SlabAllocator;
+use crate::lang_items::call_site;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::{cell::UnsafeCell, ptr};
+
+const REDZONE: usize = 64;
+const REDZONE_BYTE: u8 = 0xfb;
+/// fresh objects, to make reads of uninitialized memory stand out
+const ALLOC_BYTE: u8 = 0xbe;
+const FREED_BYTE: u8 = 0x6b;
+const QUARANTINE_LEN: usize = 64;
+/// return addresses recorded per allocation
+const SITE_DEPTH: usize = 4;
+const LIVE: usize = 0x6b61_7361_6e4c_4956;
+const FREED: usize = 0x6b61_7361_6e46_5245;
+
+/// Start of every allocation, in front of its redzone.
+#[repr(C)]
+struct Header {
+    state: usize,
+    size: usize,
+    site: [usize; SITE_DEPTH],
+}
+
+#[derive(Clone, Copy)]
+struct Quarantined {
+    base: *mut u8,
+    layout: Layout,
+}
+
+pub struct Kasan {
+    inner: SlabAllocator,
+    lock: AtomicBool,
+    /// ring of freed objects not yet handed back to `inner`
+    quarantine: UnsafeCell<[Option<Quarantined>; QUARANTINE_LEN]>,
+    next: UnsafeCell<usize>,
+}
+
+unsafe impl Sync for Kasan {}
+
+impl Deref for Kasan {
+    type Target = SlabAllocator;
+    fn deref(&self) -> &SlabAllocator {
+        &self.inner
+    }
+}
+
+/// Bytes in front of the object, keeping it aligned.
+fn front(layout: &Layout) -> usize {
+    REDZONE.max(layout.align())
+}
+
+fn outer_layout(layout: &Layout) -> Layout {
+    let size = front(layout) + layout.size() + REDZONE;
+    Layout::from_size_align(size, layout.align().max(8)).unwrap()
+}
+
+unsafe fn poisoned(start: *const u8, len: usize) -> Option<usize> {
+    (0..len).find(|&offset| *start.add(offset) != REDZONE_BYTE)
+}
+
+fn report(header: &Header, ptr: *const u8, what: &str, offset: isize) -> ! {
+    panic!(
+        "kasan: {} at offset {} of the {} byte object at {:#x}, allocated from {:x?}",
+        what, offset, header.size, ptr as usize, header.site
+    );
+}
+
+impl Kasan {
+    pub const fn new() -> Self {
+        Self {
+            inner: SlabAllocator::new(),
+            lock: AtomicBool::new(false),
+            quarantine: UnsafeCell::new([None; QUARANTINE_LEN]),
+            next: UnsafeCell::new(0),
+        }
+    }
+
+    /// Panic if the redzones around the live object at `ptr` were written.
+    unsafe fn check_redzones(&self, ptr: *mut u8, layout: &Layout) {
+        let base = ptr.sub(front(layout));
+        let header = &*(base as *const Header);
+        let header_end = base.add(core::mem::size_of::<Header>());
+        if let Some(offset) = poisoned(header_end, ptr as usize - header_end as usize) {
+            let offset = header_end as isize + offset as isize - ptr as isize;
+            report(header, ptr, "out-of-bounds write", offset);
+        }
+        if let Some(offset) = poisoned(ptr.add(layout.size()), REDZONE) {
+            report(
+                header,
+                ptr,
+                "out-of-bounds write",
+                (layout.size() + offset) as isize,
+            );
+        }
+    }
+
+    /// Put a freed object in quarantine, handing back the oldest one after
+    /// checking that nothing wrote to it.
+    unsafe fn quarantine(&self, entry: Quarantined) {
+        while self
+            .lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        let next = &mut *self.next.get();
+        let slot = &mut (*self.quarantine.get())[*next];
+        *next = (*next + 1) % QUARANTINE_LEN;
+        let evicted = slot.replace(entry);
+        self.lock.store(false, Ordering::Release);
+        if let Some(Quarantined { base, layout }) = evicted {
+            let header = &*(base as *const Header);
+            let ptr = base.add(front(&layout));
+            let size = layout.size() - front(&layout) - REDZONE;
+            if let Some(offset) = (0..size).find(|&offset| *ptr.add(offset) != FREED_BYTE) {
+                report(header, ptr, "write after free", offset as isize);
+            }
+            self.inner.dealloc(base, layout);
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Kasan {
+    #[inline(never)]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let outer = outer_layout(&layout);
+        let base = self.inner.alloc(outer);
+        if base.is_null() {
+            return base;
+        }
+        ptr::write_bytes(base, REDZONE_BYTE, outer.size());
+        let ptr = base.add(front(&layout));
+        ptr::write_bytes(ptr, ALLOC_BYTE, layout.size());
+        (base as *mut Header).write(Header {
+            state: LIVE,
+            size: layout.size(),
+            site: call_site(),
+        });
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let base = ptr.sub(front(&layout));
+        let header = &*(base as *const Header);
+        match header.state {
+            LIVE => {}
+            FREED => report(header, ptr, "double free", 0),
+            _ => panic!(
+                "kasan: free of {:#x}, which is not a heap object",
+                ptr as usize
+            ),
+        }
+        if header.size != layout.size() {
+            report(
+                header,
+                ptr,
+                "free with a wrong size",
+                layout.size() as isize,
+            );
+        }
+        self.check_redzones(ptr, &layout);
+        (*(base as *mut Header)).state = FREED;
+        ptr::write_bytes(ptr, FREED_BYTE, layout.size());
+        self.quarantine(Quarantined {
+            base,
+            layout: outer_layout(&layout),
+        });
+    }
+}
+
+#[allow(unused)]
+pub fn kasan_test() {
+    use alloc::vec::Vec;
+    let object: Vec<u8> = Vec::with_capacity(100);
+    let first = object.as_ptr();
+    unsafe {
+        assert_eq!(*first, ALLOC_BYTE);
+        assert_eq!(*first.add(100), REDZONE_BYTE);
+        assert_eq!(*first.sub(1), REDZONE_BYTE);
+    }
+    drop(object);
+    // the freed object sits in quarantine and is not handed out again
+    let object: Vec<u8> = Vec::with_capacity(100);
+    assert_ne!(object.as_ptr(), first);
+    unsafe { assert_eq!(*first, FREED_BYTE) };
+    println!("kasan_test passed!");
+}
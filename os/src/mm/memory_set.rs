@@ -1,13 +1,18 @@
-use super::{frame_alloc, FrameTracker};
-use super::{PTEFlags, PageTable, PageTableEntry};
+use super::asid::{asids_supported, flush_kernel, kernel_space_activated, Asid, SATP_ASID_SHIFT};
+use super::page_cache;
+use super::{frame_alloc_for, memory_end, FrameOwner, FrameTracker};
+use super::{PTEFlags, PageTable, PageTableEntry, HUGE_PAGE_PAGES};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE};
+#[cfg(feature = "unified")]
+use crate::config::KERNEL_STACK_TOP;
+use crate::config::{MMAP_BASE, MMIO, PAGE_SIZE, TRAMPOLINE};
 use crate::sync::UPIntrFreeCell;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::arch::asm;
+use easy_fs::Inode;
 use lazy_static::*;
 use riscv::register::satp;
 
@@ -36,18 +41,36 @@ pub fn kernel_token() -> usize {
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// None for the kernel space, which uses ASID 0
+    asid: Option<Asid>,
 }
 
 impl MemorySet {
-    pub fn new_bare() -> Self {
+    pub fn new_bare(owner: FrameOwner) -> Self {
         Self {
-            page_table: PageTable::new(),
+            page_table: PageTable::new(owner),
             areas: Vec::new(),
+            asid: Some(Asid::default()),
         }
     }
+    /// satp without the ASID, identifies the page table.
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
+    /// satp to switch to this space.
+    pub fn satp(&mut self) -> usize {
+        let asid = self.asid.as_mut().map_or(0, |asid| asid.get());
+        self.page_table.token() | asid << SATP_ASID_SHIFT
+    }
+    /// Flush the TLB entries of `range` after changing its mappings.
+    fn flush_tlb(&self, range: VPNRange) {
+        let start: usize = VirtAddr::from(range.get_start()).into();
+        let pages = range.get_end().0 - range.get_start().0;
+        match &self.asid {
+            Some(asid) => asid.flush(start, pages),
+            None => flush_kernel(start, pages),
+        }
+    }
     /// Assume that no conflicts.
     pub fn insert_framed_area(
         &mut self,
@@ -68,9 +91,364 @@ impl MemorySet {
             .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
         {
             area.unmap(&mut self.page_table);
+            let range = area.vpn_range;
             self.areas.remove(idx);
+            self.flush_tlb(range);
+        }
+    }
+    /// Remove every area inside `[start_vpn, end_vpn)`, also the pieces an
+    /// area was split into.
+    pub fn remove_areas_in(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        while let Some(start) = self
+            .areas
+            .iter()
+            .map(|area| area.vpn_range)
+            .find(|range| range.get_start() >= start_vpn && range.get_end() <= end_vpn)
+            .map(|range| range.get_start())
+        {
+            self.remove_area_with_start_vpn(start);
+        }
+    }
+    /// Split the area containing `vpn` so that one of its pieces starts there.
+    fn split_area_at(&mut self, vpn: VirtPageNum) {
+        if let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.vpn_range.get_start() < vpn && vpn < area.vpn_range.get_end())
+        {
+            let upper = area.split_off(vpn);
+            self.areas.push(upper);
         }
     }
+    /// Whether the user areas leave no hole in `[start_vpn, end_vpn)`.
+    pub fn covered_by_user_areas(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        let mut ranges: Vec<VPNRange> = self
+            .user_areas_in(start_vpn, end_vpn)
+            .map(|area| area.vpn_range)
+            .collect();
+        ranges.sort_by_key(|range| range.get_start());
+        let mut covered = start_vpn;
+        for range in ranges {
+            if range.get_start() > covered {
+                break;
+            }
+            covered = covered.max(range.get_end());
+        }
+        covered >= end_vpn
+    }
+    fn user_areas_in(
+        &self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+    ) -> impl Iterator<Item = &MapArea> {
+        self.areas.iter().filter(move |area| {
+            area.map_perm.contains(MapPermission::U)
+                && area.vpn_range.get_start() < end_vpn
+                && area.vpn_range.get_end() > start_vpn
+        })
+    }
+    /// Change the permission of `[start_vpn, end_vpn)`, which must be fully
+    /// covered by user areas, return false if it is not. Without any access
+    /// the areas and their frames stay, unreachable until changed again.
+    pub fn protect(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        perm: MapPermission,
+    ) -> bool {
+        if !self.covered_by_user_areas(start_vpn, end_vpn) {
+            return false;
+        }
+        self.split_area_at(start_vpn);
+        self.split_area_at(end_vpn);
+        for area in self.areas.iter_mut().filter(|area| {
+            area.vpn_range.get_start() >= start_vpn && area.vpn_range.get_end() <= end_vpn
+        }) {
+            area.map_perm = perm | MapPermission::U;
+            if perm.contains(MapPermission::W) {
+                area.unshare(&mut self.page_table);
+            }
+            for vpn in area.vpn_range {
+                // dropped pages get the new flags when they are faulted in
+                if area.is_mapped(vpn) {
+                    self.page_table.set_flags(vpn, area.pte_flags(vpn));
+                }
+            }
+        }
+        self.flush_tlb(VPNRange::new(start_vpn, end_vpn));
+        true
+    }
+    /// Free the frames of `[start_vpn, end_vpn)`, which must be covered by
+    /// anonymous framed user areas; the pages read as zero on the next touch. Return
+    /// false if the range is not fully covered or not all anonymous.
+    pub fn drop_pages(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        if !self.usable_for_advice(start_vpn, end_vpn) {
+            return false;
+        }
+        for area in self.areas.iter_mut().filter(|area| {
+            area.map_perm.contains(MapPermission::U)
+                && area.vpn_range.get_start() < end_vpn
+                && area.vpn_range.get_end() > start_vpn
+        }) {
+            for vpn in area.vpn_range {
+                if vpn >= start_vpn && vpn < end_vpn && area.is_mapped(vpn) {
+                    area.unmap_one(&mut self.page_table, vpn);
+                }
+            }
+        }
+        self.flush_tlb(VPNRange::new(start_vpn, end_vpn));
+        true
+    }
+    /// Fault in every dropped page of `[start_vpn, end_vpn)` now, with the
+    /// same conditions as `drop_pages`.
+    pub fn populate(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        if !self.usable_for_advice(start_vpn, end_vpn) {
+            return false;
+        }
+        for vpn in VPNRange::new(start_vpn, end_vpn) {
+            self.fault_in(vpn);
+        }
+        true
+    }
+    fn usable_for_advice(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.covered_by_user_areas(start_vpn, end_vpn)
+            && self
+                .user_areas_in(start_vpn, end_vpn)
+                .all(|area| area.map_type == MapType::Framed && area.file.is_none())
+    }
+    /// Map a zeroed frame at `vpn` if it lies in an accessible anonymous user
+    /// area but has no frame, or give a writable private file mapping its own copy
+    /// of the page cache page there. Return false if there is nothing to
+    /// fault in.
+    pub fn fault_in(&mut self, vpn: VirtPageNum) -> bool {
+        let area = match self.areas.iter_mut().find(|area| {
+            area.map_type == MapType::Framed
+                && area.map_perm.contains(MapPermission::U)
+                && area.vpn_range.get_start() <= vpn
+                && vpn < area.vpn_range.get_end()
+        }) {
+            Some(area) => area,
+            None => return false,
+        };
+        let accessible = area
+            .map_perm
+            .intersects(MapPermission::R | MapPermission::W | MapPermission::X);
+        if area.file.is_none() && !area.is_mapped(vpn) && accessible {
+            area.map_one(&mut self.page_table, vpn);
+        } else if area.copy_on_write.contains(&vpn) && area.map_perm.contains(MapPermission::W) {
+            if !area.copy_page(&mut self.page_table, vpn) {
+                return false;
+            }
+        } else {
+            return false;
+        }
+        self.flush_tlb(VPNRange::new(vpn, VirtPageNum(vpn.0 + 1)));
+        true
+    }
+    /// Lowest free range of `pages` pages from `MMAP_BASE` up.
+    pub fn find_free_range(&self, pages: usize) -> VirtPageNum {
+        let mut start = VirtAddr::from(MMAP_BASE).floor();
+        let mut ranges: Vec<VPNRange> = self.areas.iter().map(|area| area.vpn_range).collect();
+        ranges.sort_by_key(|range| range.get_start());
+        for range in ranges {
+            if range.get_end() <= start {
+                continue;
+            }
+            if range.get_start().0 >= start.0 + pages {
+                break;
+            }
+            start = range.get_end();
+        }
+        start
+    }
+    /// Map the cached pages `frames` of a file mapping at `start_vpn`. The
+    /// file sees what is stored to a shared one, a private one copies a
+    /// page on its first store.
+    pub fn insert_file_mapping(
+        &mut self,
+        start_vpn: VirtPageNum,
+        perm: MapPermission,
+        frames: Vec<Arc<FrameTracker>>,
+        file: FileBacking,
+    ) {
+        let mut area = MapArea::new(
+            start_vpn.into(),
+            VirtPageNum(start_vpn.0 + frames.len()).into(),
+            MapType::Framed,
+            perm | MapPermission::U,
+        );
+        let shared = file.shared;
+        area.file = Some(file);
+        for (vpn, frame) in area.vpn_range.into_iter().zip(frames) {
+            area.map_file_frame(&mut self.page_table, vpn, frame, shared);
+        }
+        self.flush_tlb(area.vpn_range);
+        self.areas.push(area);
+    }
+    /// Map `pages` fresh zeroed pages of anonymous memory at `start_vpn`.
+    pub fn insert_mapping(&mut self, start_vpn: VirtPageNum, pages: usize, perm: MapPermission) {
+        let area = MapArea::new(
+            start_vpn.into(),
+            VirtPageNum(start_vpn.0 + pages).into(),
+            MapType::Framed,
+            perm | MapPermission::U,
+        );
+        self.push(area, None);
+    }
+    /// The pages of shared file mappings in `[start_vpn, end_vpn)` written
+    /// since the last call, marked clean.
+    pub fn take_dirty_pages(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+    ) -> Vec<DirtyPage> {
+        let mut dirty = Vec::new();
+        for area in self.areas.iter() {
+            let file = match &area.file {
+                Some(file) if file.shared => file,
+                _ => continue,
+            };
+            for (&vpn, frame) in area.data_frames.range(start_vpn..end_vpn) {
+                if self.page_table.take_dirty(vpn) {
+                    dirty.push(DirtyPage {
+                        inode: file.inode.clone(),
+                        offset: file.offset + (vpn.0 - area.vpn_range.get_start().0) * PAGE_SIZE,
+                        frame: frame.clone(),
+                    });
+                }
+            }
+        }
+        if !dirty.is_empty() {
+            self.flush_tlb(VPNRange::new(start_vpn, end_vpn));
+        }
+        dirty
+    }
+    /// File behind `[start_vpn, end_vpn)` if a single framed user area
+    /// covers it, with the offset of `start_vpn`.
+    pub fn mapping_at(
+        &self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+    ) -> Option<Option<FileBacking>> {
+        let area = self.areas.iter().find(|area| {
+            area.map_type == MapType::Framed
+                && area.map_perm.contains(MapPermission::U)
+                && area.vpn_range.get_start() <= start_vpn
+                && end_vpn <= area.vpn_range.get_end()
+        })?;
+        Some(area.file.as_ref().map(|file| FileBacking {
+            inode: file.inode.clone(),
+            offset: file.offset + (start_vpn.0 - area.vpn_range.get_start().0) * PAGE_SIZE,
+            shared: file.shared,
+        }))
+    }
+    /// Whether no area overlaps `[start_vpn, end_vpn)`.
+    pub fn range_free(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.areas.iter().all(|area| {
+            area.vpn_range.get_end() <= start_vpn || end_vpn <= area.vpn_range.get_start()
+        })
+    }
+    /// Index of the area of exactly `[start_vpn, end_vpn)`, splitting the
+    /// mapping given by `mapping_at`.
+    fn isolate_area(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> usize {
+        self.split_area_at(start_vpn);
+        self.split_area_at(end_vpn);
+        self.areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start_vpn)
+            .unwrap()
+    }
+    /// Extend the anonymous mapping `[start_vpn, end_vpn)` up to `new_end`,
+    /// which must be free, with fresh zeroed pages.
+    pub fn grow_mapping(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        new_end: VirtPageNum,
+    ) {
+        let index = self.isolate_area(start_vpn, end_vpn);
+        let area = &mut self.areas[index];
+        area.vpn_range = VPNRange::new(start_vpn, new_end);
+        for vpn in VPNRange::new(end_vpn, new_end) {
+            area.map_one(&mut self.page_table, vpn);
+        }
+    }
+    /// Extend the file mapping `[start_vpn, end_vpn)`, up to a free
+    /// `end_vpn + frames.len()`, with the cached pages `frames`.
+    pub fn grow_file_mapping(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        frames: Vec<Arc<FrameTracker>>,
+    ) {
+        let index = self.isolate_area(start_vpn, end_vpn);
+        let area = &mut self.areas[index];
+        let new_end = VirtPageNum(end_vpn.0 + frames.len());
+        area.vpn_range = VPNRange::new(start_vpn, new_end);
+        let shared = area.file.as_ref().map_or(false, |file| file.shared);
+        for (vpn, frame) in VPNRange::new(end_vpn, new_end).into_iter().zip(frames) {
+            area.map_file_frame(&mut self.page_table, vpn, frame, shared);
+        }
+    }
+    /// Move the mapping `[start_vpn, end_vpn)` to the free range at
+    /// `new_start` by remapping its frames, nothing is copied.
+    pub fn move_mapping(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+        new_start: VirtPageNum,
+    ) {
+        let index = self.isolate_area(start_vpn, end_vpn);
+        let area = &mut self.areas[index];
+        let frames = core::mem::take(&mut area.data_frames);
+        for (vpn, frame) in frames {
+            // keep the dirty bit of shared file pages
+            let flags = self.page_table.translate(vpn).unwrap().flags();
+            self.page_table.unmap(vpn);
+            let new_vpn = VirtPageNum(vpn.0 - start_vpn.0 + new_start.0);
+            self.page_table.map(new_vpn, frame.ppn, flags);
+            area.data_frames.insert(new_vpn, frame);
+        }
+        area.copy_on_write = core::mem::take(&mut area.copy_on_write)
+            .into_iter()
+            .map(|vpn| VirtPageNum(vpn.0 - start_vpn.0 + new_start.0))
+            .collect();
+        area.vpn_range = VPNRange::new(
+            new_start,
+            VirtPageNum(new_start.0 + end_vpn.0 - start_vpn.0),
+        );
+        self.flush_tlb(VPNRange::new(start_vpn, end_vpn));
+    }
+    /// Dirty pages of every shared file mapping, before the space goes away.
+    pub fn take_all_dirty_pages(&mut self) -> Vec<DirtyPage> {
+        let ranges: Vec<VPNRange> = self
+            .areas
+            .iter()
+            .filter(|area| area.file.as_ref().map_or(false, |file| file.shared))
+            .map(|area| area.vpn_range)
+            .collect();
+        ranges
+            .into_iter()
+            .flat_map(|range| self.take_dirty_pages(range.get_start(), range.get_end()))
+            .collect()
+    }
+    /// Remove the mappings in `[start_vpn, end_vpn)`, which must be covered
+    /// by user areas, and return their dirty file pages. None if the range
+    /// is not fully covered.
+    pub fn unmap_range(
+        &mut self,
+        start_vpn: VirtPageNum,
+        end_vpn: VirtPageNum,
+    ) -> Option<Vec<DirtyPage>> {
+        if !self.covered_by_user_areas(start_vpn, end_vpn) {
+            return None;
+        }
+        let dirty = self.take_dirty_pages(start_vpn, end_vpn);
+        self.split_area_at(start_vpn);
+        self.split_area_at(end_vpn);
+        self.remove_areas_in(start_vpn, end_vpn);
+        Some(dirty)
+    }
     /// Add a new MapArea into this MemorySet.
     /// Assuming that there are no conflicts in the virtual address
     /// space.
@@ -79,8 +457,79 @@ impl MemorySet {
         if let Some(data) = data {
             map_area.copy_data(&self.page_table, data);
         }
+        self.flush_tlb(map_area.vpn_range);
         self.areas.push(map_area);
     }
+    /// Map a read-only segment of `mem_size` bytes starting with `data`,
+    /// read from `inode` at `offset`, with the frames of the page cache.
+    /// Pages reaching into the zeroed tail of the segment, or which find no
+    /// frame, get a copy.
+    fn push_text(
+        &mut self,
+        mut map_area: MapArea,
+        inode: &Arc<Inode>,
+        offset: usize,
+        data: &[u8],
+        mem_size: usize,
+    ) {
+        let start_vpn = map_area.vpn_range.get_start();
+        let data_start = VirtAddr::from(start_vpn).0 + offset % PAGE_SIZE;
+        let data_end = data_start + data.len();
+        let first_index = offset / PAGE_SIZE;
+        map_area.file = Some(FileBacking {
+            inode: inode.clone(),
+            offset: first_index * PAGE_SIZE,
+            shared: false,
+        });
+        map_area.cached = true;
+        for vpn in map_area.vpn_range {
+            let page_start = VirtAddr::from(vpn).0;
+            let page_end = page_start + PAGE_SIZE;
+            let zeroed = page_end > data_end && mem_size > data.len();
+            let frame = if zeroed {
+                None
+            } else {
+                page_cache::page(inode, first_index + vpn.0 - start_vpn.0)
+            };
+            match frame {
+                Some(frame) => map_area.map_frame(&mut self.page_table, vpn, frame),
+                None => {
+                    map_area.map_one(&mut self.page_table, vpn);
+                    // the part of the page inside data
+                    let from = page_start.max(data_start);
+                    let to = page_end.min(data_end);
+                    if from < to {
+                        map_area.data_frames[&vpn].ppn.get_bytes_array()
+                            [from - page_start..to - page_start]
+                            .copy_from_slice(&data[from - data_start..to - data_start]);
+                    }
+                }
+            }
+        }
+        self.flush_tlb(map_area.vpn_range);
+        self.areas.push(map_area);
+    }
+    /// Give a process about to be traced private copies of its text and of
+    /// the page cache pages its executable private mappings still map, the
+    /// breakpoints planted there must not reach other processes.
+    pub fn unshare_text(&mut self) {
+        let mut ranges = Vec::new();
+        for area in self
+            .areas
+            .iter_mut()
+            .filter(|area| area.cached || area.map_perm.contains(MapPermission::X))
+        {
+            area.unshare(&mut self.page_table);
+            let pages: Vec<VirtPageNum> = area.copy_on_write.iter().copied().collect();
+            for vpn in pages {
+                area.copy_page(&mut self.page_table, vpn);
+            }
+            ranges.push(area.vpn_range);
+        }
+        for range in ranges {
+            self.flush_tlb(range);
+        }
+    }
     /// Mention that trampoline is not collected by areas.
     fn map_trampoline(&mut self) {
         self.page_table.map(
@@ -89,9 +538,30 @@ impl MemorySet {
             PTEFlags::R | PTEFlags::X,
         );
     }
+    /// Make the kernel reachable from a user space, supervisor-only: image,
+    /// physical memory and kernel stacks share the tables of the kernel
+    /// space. MMIO stays in the kernel space, a device access from a user
+    /// space faults over to it, see `trap_from_kernel`.
+    #[cfg(feature = "unified")]
+    fn map_kernel(&mut self) {
+        let mut kernel_space = KERNEL_SPACE.exclusive_access();
+        let kernel: VirtAddr = (stext as usize).into();
+        let stack_top: VirtAddr = KERNEL_STACK_TOP.into();
+        self.page_table.share_root_entries(
+            &mut kernel_space.page_table,
+            kernel.floor(),
+            VirtAddr::from(memory_end()).ceil(),
+        );
+        self.page_table.share_root_entries(
+            &mut kernel_space.page_table,
+            VirtPageNum(stack_top.floor().0 - 1),
+            stack_top.floor(),
+        );
+    }
     /// Without kernel stacks.
     pub fn new_kernel() -> Self {
-        let mut memory_set = Self::new_bare();
+        let mut memory_set = Self::new_bare(FrameOwner::Kernel("kernel space"));
+        memory_set.asid = None;
         // map trampoline
         memory_set.map_trampoline();
         // map kernel sections
@@ -146,7 +616,7 @@ impl MemorySet {
         memory_set.push(
             MapArea::new(
                 (ekernel as usize).into(),
-                MEMORY_END.into(),
+                memory_end().into(),
                 MapType::Identical,
                 MapPermission::R | MapPermission::W,
             ),
@@ -167,11 +637,18 @@ impl MemorySet {
         memory_set
     }
     /// Include sections in elf and trampoline,
-    /// also returns user_sp_base and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
+    /// also returns user_sp_base and entry point. The read-only segments
+    /// map the pages of `file` in the page cache if the elf was read from it.
+    pub fn from_elf(
+        elf_data: &[u8],
+        pid: usize,
+        file: Option<&Arc<Inode>>,
+    ) -> (Self, usize, usize) {
+        let mut memory_set = Self::new_bare(FrameOwner::Process(pid));
         // map trampoline
         memory_set.map_trampoline();
+        #[cfg(feature = "unified")]
+        memory_set.map_kernel();
         // map program headers of elf, with U flag
         let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
         let elf_header = elf.header;
@@ -197,10 +674,23 @@ impl MemorySet {
                 }
                 let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
                 max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                let data =
+                    &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize];
+                match file {
+                    Some(inode)
+                        if !ph_flags.is_write()
+                            && ph.offset() as usize % PAGE_SIZE == start_va.page_offset() =>
+                    {
+                        memory_set.push_text(
+                            map_area,
+                            inode,
+                            ph.offset() as usize,
+                            data,
+                            ph.mem_size() as usize,
+                        );
+                    }
+                    _ => memory_set.push(map_area, Some(data)),
+                }
             }
         }
         let max_end_va: VirtAddr = max_end_vpn.into();
@@ -212,16 +702,48 @@ impl MemorySet {
             elf.header.pt2.entry_point() as usize,
         )
     }
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
-        let mut memory_set = Self::new_bare();
+    pub fn from_existed_user(user_space: &MemorySet, pid: usize) -> MemorySet {
+        let mut memory_set = Self::new_bare(FrameOwner::Process(pid));
         // map trampoline
         memory_set.map_trampoline();
+        #[cfg(feature = "unified")]
+        memory_set.map_kernel();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
+            if area.cached || area.file.as_ref().map_or(false, |file| file.shared) {
+                // the child maps the same pages of the page cache
+                let mut new_area = MapArea::from_another(area);
+                for (&vpn, frame) in area.data_frames.iter() {
+                    new_area.map_frame(&mut memory_set.page_table, vpn, frame.clone());
+                }
+                memory_set.areas.push(new_area);
+                continue;
+            }
             let new_area = MapArea::from_another(area);
             memory_set.push(new_area, None);
             // copy data from another space
             for vpn in area.vpn_range {
+                if !area.is_mapped(vpn) {
+                    // dropped pages stay dropped in the child
+                    memory_set
+                        .areas
+                        .last_mut()
+                        .unwrap()
+                        .unmap_one(&mut memory_set.page_table, vpn);
+                    continue;
+                }
+                if area.copy_on_write.contains(&vpn) {
+                    // still the page cache's, the child maps it as well
+                    let new_area = memory_set.areas.last_mut().unwrap();
+                    new_area.unmap_one(&mut memory_set.page_table, vpn);
+                    new_area.map_file_frame(
+                        &mut memory_set.page_table,
+                        vpn,
+                        area.data_frames[&vpn].clone(),
+                        false,
+                    );
+                    continue;
+                }
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
                 dst_ppn
@@ -231,27 +753,109 @@ impl MemorySet {
         }
         memory_set
     }
+    /// Switch this hart to the space, keeping TLB entries of other ASIDs.
+    #[cfg(feature = "unified")]
+    pub fn switch_in(&mut self) {
+        let satp = self.satp();
+        unsafe {
+            satp::write(satp);
+            if !asids_supported() {
+                asm!("sfence.vma");
+            }
+        }
+    }
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
             satp::write(satp);
             asm!("sfence.vma");
         }
+        kernel_space_activated();
     }
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
+    /// Page ranges and permissions of the areas accessible from user mode.
+    pub fn user_areas(&self) -> Vec<(VPNRange, MapPermission)> {
+        self.areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| (area.vpn_range, area.map_perm))
+            .collect()
+    }
+    /// Frame behind the page at `vpn` if user mode may access it, to be read
+    /// once the space is no longer borrowed.
+    pub fn user_frame(&self, vpn: VirtPageNum) -> Option<Arc<FrameTracker>> {
+        self.translate(vpn)
+            .filter(|pte| pte.is_valid() && pte.user_accessible())?;
+        self.areas
+            .iter()
+            .find(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end())
+            .and_then(|area| area.data_frames.get(&vpn).cloned())
+    }
+    /// Like `user_areas`, with the file behind each area.
+    pub fn user_mappings(&self) -> Vec<(VPNRange, MapPermission, Option<FileBacking>)> {
+        self.areas
+            .iter()
+            .filter(|area| area.map_perm.contains(MapPermission::U))
+            .map(|area| (area.vpn_range, area.map_perm, area.file.clone()))
+            .collect()
+    }
     pub fn recycle_data_pages(&mut self) {
         //*self = Self::new_bare();
         self.areas.clear();
     }
+    /// Free the data pages and the page table of a dead space.
+    #[allow(unused)]
+    pub fn recycle_all_pages(&mut self) {
+        self.areas.clear();
+        self.page_table.release();
+    }
 }
 
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// shared with the page cache for file mappings
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// file of the mapping, None for anonymous memory
+    file: Option<FileBacking>,
+    /// the frames are program text shared through the page cache, they
+    /// must be copied before they may be written
+    cached: bool,
+    /// pages of a private file mapping still on frames of the page cache,
+    /// mapped without W until a store copies them
+    copy_on_write: BTreeSet<VirtPageNum>,
+}
+
+/// The file behind a mapping, a shared one writes its dirty pages back.
+#[derive(Clone)]
+pub struct FileBacking {
+    pub inode: Arc<Inode>,
+    /// file offset of the first page of the area
+    pub offset: usize,
+    pub shared: bool,
+}
+
+/// A dirty page of a shared mapping, to be written back once no lock is
+/// held.
+pub struct DirtyPage {
+    inode: Arc<Inode>,
+    offset: usize,
+    /// the page in the page cache
+    frame: Arc<FrameTracker>,
+}
+
+impl DirtyPage {
+    /// Write the page to its file, without growing the file.
+    pub fn write_back(self) {
+        let len = self.inode.size().saturating_sub(self.offset).min(PAGE_SIZE);
+        if len > 0 {
+            self.inode
+                .write_at(self.offset, &self.frame.ppn.get_bytes_array()[..len]);
+        }
+    }
 }
 
 impl MapArea {
@@ -268,14 +872,38 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            file: None,
+            cached: false,
+            copy_on_write: BTreeSet::new(),
         }
     }
+    /// Cut the area at `vpn`, keeping the lower part and returning the upper.
+    pub fn split_off(&mut self, vpn: VirtPageNum) -> Self {
+        let upper = Self {
+            vpn_range: VPNRange::new(vpn, self.vpn_range.get_end()),
+            data_frames: self.data_frames.split_off(&vpn),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            file: self.file.as_ref().map(|file| FileBacking {
+                inode: file.inode.clone(),
+                offset: file.offset + (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE,
+                shared: file.shared,
+            }),
+            cached: self.cached,
+            copy_on_write: self.copy_on_write.split_off(&vpn),
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        upper
+    }
     pub fn from_another(another: &MapArea) -> Self {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            file: another.file.clone(),
+            cached: another.cached,
+            copy_on_write: BTreeSet::new(),
         }
     }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
@@ -285,9 +913,9 @@ impl MapArea {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc_for(page_table.owner()).unwrap();
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
             }
             MapType::Linear(pn_offset) => {
                 // check for sv39
@@ -298,20 +926,118 @@ impl MapArea {
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
     }
+    /// Map a frame someone else holds as well, in a framed area.
+    pub fn map_frame(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        frame: Arc<FrameTracker>,
+    ) {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.map(vpn, frame.ppn, pte_flags);
+        self.data_frames.insert(vpn, frame);
+    }
+    /// Map a page cache frame in a file mapping, read-only until it is
+    /// copied unless the mapping is shared.
+    fn map_file_frame(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        frame: Arc<FrameTracker>,
+        shared: bool,
+    ) {
+        if !shared {
+            self.copy_on_write.insert(vpn);
+        }
+        page_table.map(vpn, frame.ppn, self.pte_flags(vpn));
+        self.data_frames.insert(vpn, frame);
+    }
+    /// Flags of the page at `vpn`, without W while it is copy-on-write.
+    fn pte_flags(&self, vpn: VirtPageNum) -> PTEFlags {
+        let mut flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        if self.copy_on_write.contains(&vpn) {
+            flags.remove(PTEFlags::W);
+        }
+        flags
+    }
+    /// Replace the page cache frame at `vpn` by a private copy, return false
+    /// if no frame is left.
+    fn copy_page(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let copy = match frame_alloc_for(page_table.owner()) {
+            Some(copy) => copy,
+            None => return false,
+        };
+        let frame = self.data_frames.get_mut(&vpn).unwrap();
+        copy.ppn
+            .get_bytes_array()
+            .copy_from_slice(frame.ppn.get_bytes_array());
+        page_table.unmap(vpn);
+        *frame = Arc::new(copy);
+        self.copy_on_write.remove(&vpn);
+        page_table.map(vpn, self.data_frames[&vpn].ppn, self.pte_flags(vpn));
+        true
+    }
+    /// Replace the page cache frames of the area by private copies, so that
+    /// it can be written.
+    fn unshare(&mut self, page_table: &mut PageTable) {
+        if !self.cached {
+            return;
+        }
+        self.cached = false;
+        for (&vpn, frame) in self.data_frames.iter_mut() {
+            let copy = frame_alloc_for(page_table.owner()).unwrap();
+            copy.ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            let flags = page_table.translate(vpn).unwrap().flags();
+            page_table.unmap(vpn);
+            page_table.map(vpn, copy.ppn, flags);
+            *frame = Arc::new(copy);
+        }
+    }
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        if self.map_type == MapType::Framed {
-            self.data_frames.remove(&vpn);
+        self.copy_on_write.remove(&vpn);
+        if self.map_type == MapType::Framed && self.data_frames.remove(&vpn).is_none() {
+            // dropped by madvise, nothing is mapped
+            return;
         }
         page_table.unmap(vpn);
     }
+    /// False for pages of a framed area that were dropped.
+    pub fn is_mapped(&self, vpn: VirtPageNum) -> bool {
+        self.map_type != MapType::Framed || self.data_frames.contains_key(&vpn)
+    }
+    /// Whether the superpage at `vpn` lies entirely inside an identical area.
+    fn huge_at(&self, vpn: VirtPageNum) -> bool {
+        self.map_type == MapType::Identical
+            && vpn.0 % HUGE_PAGE_PAGES == 0
+            && vpn.0 + HUGE_PAGE_PAGES <= self.vpn_range.get_end().0
+    }
+    /// Identical areas use 2MiB superpages where they cover whole ones and
+    /// 4KiB pages at the unaligned edges.
     pub fn map(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < self.vpn_range.get_end() {
+            if self.huge_at(vpn) {
+                let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+                page_table.map_huge(vpn, PhysPageNum(vpn.0), pte_flags);
+                vpn.0 += HUGE_PAGE_PAGES;
+            } else {
+                self.map_one(page_table, vpn);
+                vpn.step();
+            }
         }
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.unmap_one(page_table, vpn);
+        let mut vpn = self.vpn_range.get_start();
+        while vpn < self.vpn_range.get_end() {
+            if self.huge_at(vpn) {
+                page_table.unmap_huge(vpn);
+                vpn.0 += HUGE_PAGE_PAGES;
+            } else {
+                self.unmap_one(page_table, vpn);
+                vpn.step();
+            }
         }
     }
     /// data: start-aligned but maybe with shorter length
@@ -355,7 +1081,7 @@ bitflags! {
     }
 }
 
-#[allow(unused)]
+#[cfg(feature = "ktest")]
 pub fn remap_test() {
     let mut kernel_space = KERNEL_SPACE.exclusive_access();
     let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
@@ -376,5 +1102,10 @@ pub fn remap_test() {
         .translate(mid_data.floor())
         .unwrap()
         .executable(),);
+    // the end of physical memory lies inside a superpage
+    let last: VirtAddr = (memory_end() - PAGE_SIZE).into();
+    let pte = kernel_space.page_table.translate(last.floor()).unwrap();
+    assert_eq!(pte.ppn().0, last.floor().0);
+    assert!(pte.writable());
     println!("remap_test passed!");
 }
@@ -1,21 +1,75 @@
 mod address;
+mod asid;
+mod dma;
 mod frame_allocator;
 mod heap_allocator;
+#[cfg(feature = "kasan")]
+mod kasan;
 mod memory_set;
+pub mod page_cache;
 mod page_table;
+mod slab;
 
 pub use address::VPNRange;
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
-pub use frame_allocator::{frame_alloc, frame_alloc_more, frame_dealloc, FrameTracker};
-pub use memory_set::{kernel_token, MapArea, MapPermission, MapType, MemorySet, KERNEL_SPACE};
-use page_table::PTEFlags;
+pub use asid::asids_supported;
+pub use dma::{dma_alloc, DmaBuffer};
+#[cfg(feature = "frame_debug")]
+pub use frame_allocator::frames_owned_by;
+pub use frame_allocator::{
+    frame_alloc, frame_alloc_for, frame_alloc_more, frame_dealloc, FrameOwner, FrameTracker,
+};
+pub use memory_set::{
+    kernel_token, DirtyPage, FileBacking, MapArea, MapPermission, MapType, MemorySet, KERNEL_SPACE,
+};
 pub use page_table::{
-    translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTable,
-    PageTableEntry, UserBuffer,
+    translated_byte_buffer, translated_byte_buffer_mut, translated_ref, translated_refmut,
+    translated_str, translated_text_mut, PageTable, PageTableEntry, UserBuffer,
 };
+use page_table::{PTEFlags, HUGE_PAGE_PAGES};
+
+use crate::config::MEMORY_END;
+use crate::fdt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// End of the RAM the kernel starts in, from the device tree or MEMORY_END
+/// without one.
+static MEMORY_END_DETECTED: AtomicUsize = AtomicUsize::new(MEMORY_END);
+
+/// End of physical memory: frames are allocated and the kernel maps memory
+/// up to here.
+pub fn memory_end() -> usize {
+    MEMORY_END_DETECTED.load(Ordering::Relaxed)
+}
+
+/// Check the allocators and the kernel space once they are set up.
+#[cfg(feature = "ktest")]
+pub fn self_test() {
+    heap_allocator::heap_test();
+    frame_allocator::frame_allocator_test();
+    dma::dma_alloc_test();
+    memory_set::remap_test();
+}
 
-pub fn init() {
+/// Set up memory with what the device tree at `dtb` says about it, the
+/// board defaults if there is none.
+pub fn init(dtb: usize) {
     heap_allocator::init_heap();
-    frame_allocator::init_frame_allocator();
+    let map = fdt::memory_map(dtb).unwrap_or_default();
+    extern "C" {
+        fn skernel();
+    }
+    let kernel = skernel as usize;
+    if let Some(&(_, end)) = map
+        .memory
+        .iter()
+        .find(|(start, end)| (*start..*end).contains(&kernel))
+    {
+        // the user half of a unified space begins at BRK_BASE
+        #[cfg(feature = "unified")]
+        let end = end.min(crate::config::BRK_BASE);
+        MEMORY_END_DETECTED.store(end, Ordering::Relaxed);
+    }
+    frame_allocator::init_frame_allocator(&map.reserved);
     KERNEL_SPACE.exclusive_access().activate();
 }
@@ -0,0 +1,255 @@
+//! The pages of files, shared by read and write and the shared mappings.
+//!
+//! A page is read from the disk once and then served from its frame. Shared
+//! mappings map that very frame, so a read sees what was stored through a
+//! mapping at once and a mapping sees what was written. Writes go through
+//! to the disk as they did, stores through a mapping reach it when the
+//! mapping is synced, unmapped or its process exits. The read-only segments
+//! of programs map the frames as well, so every process running a program
+//! shares one copy of its text. Pages no mapping holds are evicted, oldest
+//! first, once more than PAGE_CACHE_PAGES are cached.
+//!
+//! A page being read in is cached from the start, so a write meanwhile
+//! lands in its frame as well and a second reader waits for the first one.
+//! The disk reads it straight into the frame, past the block cache, so the
+//! page is not kept twice.
+
+use super::{frame_alloc_for, FrameOwner, FrameTracker};
+use crate::config::{PAGE_CACHE_PAGES, PAGE_SIZE};
+use crate::sync::{Condvar, UPIntrFreeCell};
+use crate::task::schedule;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::Inode;
+use lazy_static::*;
+
+/// [`Inode::identity`]
+type FileKey = (usize, usize, usize);
+
+struct CachedFile {
+    /// keeps the file system and the inode alive, and with them the key
+    /// unique. Dropping it may free an unlinked file on the disk, so a
+    /// file leaving the cache is dropped once the cache is unlocked.
+    _inode: Arc<Inode>,
+    /// by page index in the file
+    pages: BTreeMap<usize, CachedPage>,
+}
+
+struct CachedPage {
+    frame: Arc<FrameTracker>,
+    /// still being read in by the task which cached it
+    loading: bool,
+}
+
+struct PageCache {
+    files: BTreeMap<FileKey, CachedFile>,
+    /// pages in the order they came in, some may be gone already
+    order: VecDeque<(FileKey, usize)>,
+    cached: usize,
+    /// tasks waiting for a page being read in
+    loaded: Condvar,
+}
+
+impl PageCache {
+    fn lookup(&self, key: &FileKey, index: usize) -> Option<&CachedPage> {
+        self.files.get(key)?.pages.get(&index)
+    }
+
+    /// The page if it is cached and read in.
+    fn get(&self, key: &FileKey, index: usize) -> Option<Arc<FrameTracker>> {
+        self.lookup(key, index)
+            .filter(|page| !page.loading)
+            .map(|page| page.frame.clone())
+    }
+
+    /// Returns the files evicted, see [`CachedFile::_inode`].
+    fn insert(
+        &mut self,
+        inode: &Arc<Inode>,
+        index: usize,
+        frame: Arc<FrameTracker>,
+    ) -> Vec<CachedFile> {
+        let key = inode.identity();
+        self.files
+            .entry(key)
+            .or_insert_with(|| CachedFile {
+                _inode: inode.clone(),
+                pages: BTreeMap::new(),
+            })
+            .pages
+            .insert(
+                index,
+                CachedPage {
+                    frame,
+                    loading: true,
+                },
+            );
+        self.order.push_back((key, index));
+        self.cached += 1;
+        self.evict()
+    }
+
+    /// Drop the oldest pages held by the cache alone until few enough are
+    /// left; mapped pages go to the back.
+    fn evict(&mut self) -> Vec<CachedFile> {
+        let mut evicted = Vec::new();
+        let mut tries = self.order.len();
+        while self.cached > PAGE_CACHE_PAGES && tries > 0 {
+            tries -= 1;
+            let (key, index) = self.order.pop_front().unwrap();
+            let file = match self.files.get_mut(&key) {
+                Some(file) => file,
+                None => continue,
+            };
+            match file.pages.get(&index) {
+                Some(page) if Arc::strong_count(&page.frame) > 1 => {
+                    self.order.push_back((key, index));
+                    continue;
+                }
+                Some(_) => {}
+                None => continue,
+            }
+            file.pages.remove(&index);
+            self.cached -= 1;
+            if file.pages.is_empty() {
+                evicted.extend(self.files.remove(&key));
+            }
+        }
+        evicted
+    }
+}
+
+lazy_static! {
+    static ref PAGE_CACHE: UPIntrFreeCell<PageCache> = unsafe {
+        UPIntrFreeCell::new(PageCache {
+            files: BTreeMap::new(),
+            order: VecDeque::new(),
+            cached: 0,
+            loaded: Condvar::new(),
+        })
+    };
+}
+
+/// Page `index` of the file, read in if it is not cached. None if no frame
+/// is left.
+pub fn page(inode: &Arc<Inode>, index: usize) -> Option<Arc<FrameTracker>> {
+    let key = inode.identity();
+    let (frame, evicted) = loop {
+        let mut cache = PAGE_CACHE.exclusive_access();
+        match cache.lookup(&key, index) {
+            Some(page) if page.loading => {
+                let task_cx_ptr = cache.loaded.wait_no_sched();
+                drop(cache);
+                schedule(task_cx_ptr);
+            }
+            Some(page) => return Some(page.frame.clone()),
+            None => {
+                let frame = Arc::new(frame_alloc_for(FrameOwner::Kernel("page cache"))?);
+                break (frame.clone(), cache.insert(inode, index, frame));
+            }
+        }
+    };
+    drop(evicted);
+    // the disk is waited for without the cache, writes meanwhile go to
+    // the frame too
+    inode.read_at_direct(index * PAGE_SIZE, frame.ppn.get_bytes_array());
+    PAGE_CACHE.exclusive_session(|cache| {
+        // gone if the file was truncated or removed meanwhile
+        if let Some(page) = cache
+            .files
+            .get_mut(&key)
+            .and_then(|file| file.pages.get_mut(&index))
+        {
+            page.loading = false;
+        }
+        cache.loaded.signal_all();
+    });
+    Some(frame)
+}
+
+/// `pages` pages of the file from page `start`, for a shared mapping.
+pub fn pages(inode: &Arc<Inode>, start: usize, pages: usize) -> Option<Vec<Arc<FrameTracker>>> {
+    (start..start + pages)
+        .map(|index| page(inode, index))
+        .collect()
+}
+
+/// Have the disk read ahead the pages in `[offset, offset + len)` of the
+/// file which are not cached yet.
+pub fn read_ahead(inode: &Arc<Inode>, offset: usize, len: usize) {
+    let key = inode.identity();
+    let (first, last) = (offset / PAGE_SIZE, (offset + len).div_ceil(PAGE_SIZE));
+    let missing: Vec<usize> = PAGE_CACHE.exclusive_session(|cache| {
+        (first..last)
+            .filter(|&index| cache.lookup(&key, index).is_none())
+            .collect()
+    });
+    // one request for every run of missing pages
+    let mut runs = missing.iter().peekable();
+    while let Some(&start) = runs.next() {
+        let mut end = start + 1;
+        while runs.next_if_eq(&&end).is_some() {
+            end += 1;
+        }
+        inode.read_ahead(start * PAGE_SIZE, (end - start) * PAGE_SIZE);
+    }
+}
+
+/// Read the file at `offset` as `Inode::read_at` does, from the cache.
+pub fn read(inode: &Arc<Inode>, offset: usize, buf: &mut [u8]) -> usize {
+    let end = inode.size().min(offset + buf.len());
+    let mut pos = offset;
+    while pos < end {
+        let len = (PAGE_SIZE - pos % PAGE_SIZE).min(end - pos);
+        let dst = &mut buf[pos - offset..pos - offset + len];
+        match page(inode, pos / PAGE_SIZE) {
+            Some(frame) => dst.copy_from_slice(
+                &frame.ppn.get_bytes_array()[pos % PAGE_SIZE..pos % PAGE_SIZE + len],
+            ),
+            // out of frames, straight from the disk then
+            None => {
+                inode.read_at(pos, dst);
+            }
+        }
+        pos += len;
+    }
+    if end > offset {
+        inode.accessed();
+    }
+    end.saturating_sub(offset)
+}
+
+/// Write the file at `offset` as `Inode::write_at` does, and into the
+/// cached pages it covers.
+pub fn write(inode: &Arc<Inode>, offset: usize, buf: &[u8]) -> usize {
+    let written = inode.write_at(offset, buf);
+    let key = inode.identity();
+    let (first, last) = (offset / PAGE_SIZE, (offset + written).div_ceil(PAGE_SIZE));
+    PAGE_CACHE.exclusive_session(|cache| {
+        for index in first..last {
+            // pages being read in as well, the disk may have been read
+            // before this write
+            if let Some(CachedPage { frame, .. }) = cache.lookup(&key, index) {
+                let start = (index * PAGE_SIZE).max(offset);
+                let end = ((index + 1) * PAGE_SIZE).min(offset + written);
+                frame.ppn.get_bytes_array()[start % PAGE_SIZE..start % PAGE_SIZE + end - start]
+                    .copy_from_slice(&buf[start - offset..end - offset]);
+            }
+        }
+    });
+    written
+}
+
+/// Forget the pages of a file truncated or removed. Mappings keep the
+/// frames they have, detached from the file.
+pub fn invalidate(inode: &Inode) {
+    let key = inode.identity();
+    let file = PAGE_CACHE.exclusive_session(|cache| {
+        let file = cache.files.remove(&key)?;
+        cache.cached -= file.pages.len();
+        cache.order.retain(|(file_key, _)| *file_key != key);
+        Some(file)
+    });
+    drop(file);
+}
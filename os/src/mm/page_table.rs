@@ -1,4 +1,9 @@
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::{
+    frame_alloc_for, FrameOwner, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr,
+    VirtPageNum,
+};
+use crate::syscall::SysError;
+use crate::task::fault_in_current_page;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -50,20 +55,51 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    /// Valid and pointing at memory rather than at the next level table.
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid()
+            && self
+                .flags()
+                .intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X)
+    }
+    pub fn user_accessible(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
+    /// Something is mapped, maybe a page without any access, which is kept
+    /// without V, see `leaf_flags`.
+    pub fn is_mapped(&self) -> bool {
+        self.bits != 0
+    }
 }
 
+/// Flags of a 4KiB leaf. With none of R, W and X a valid entry points to a
+/// next-level table, so a page without any access is left without V.
+fn leaf_flags(flags: PTEFlags) -> PTEFlags {
+    if flags.intersects(PTEFlags::R | PTEFlags::W | PTEFlags::X) {
+        flags | PTEFlags::V
+    } else {
+        flags - PTEFlags::V
+    }
+}
+
+/// Number of 4KiB pages covered by a level-1 leaf, a 2MiB superpage.
+pub const HUGE_PAGE_PAGES: usize = 1 << 9;
+
 pub struct PageTable {
     root_ppn: PhysPageNum,
     frames: Vec<FrameTracker>,
+    /// owner of the tables and of the frames mapped through them
+    owner: FrameOwner,
 }
 
 /// Assume that it won't oom when creating/mapping.
 impl PageTable {
-    pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
+    pub fn new(owner: FrameOwner) -> Self {
+        let frame = frame_alloc_for(owner).unwrap();
         PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
+            owner,
         }
     }
     /// Temporarily used to get arguments from user space.
@@ -71,20 +107,37 @@ impl PageTable {
         Self {
             root_ppn: PhysPageNum::from(satp & ((1usize << 44) - 1)),
             frames: Vec::new(),
+            owner: FrameOwner::Kernel("page table"),
         }
     }
+    pub fn owner(&self) -> FrameOwner {
+        self.owner
+    }
+    /// Free all tables, the page table must not be used afterwards.
+    pub fn release(&mut self) {
+        self.frames.clear();
+    }
     fn find_pte_create(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        self.find_pte_create_at(vpn, 2)
+    }
+    /// Entry for `vpn` in the table of `level`, 1 for superpages and 2 for pages.
+    fn find_pte_create_at(
+        &mut self,
+        vpn: VirtPageNum,
+        level: usize,
+    ) -> Option<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for (i, idx) in idxs.iter().enumerate() {
             let pte = &mut ppn.get_pte_array()[*idx];
-            if i == 2 {
+            if i == level {
                 result = Some(pte);
                 break;
             }
+            assert!(!pte.is_leaf(), "vpn {:?} is inside a superpage", vpn);
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc_for(self.owner).unwrap();
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
@@ -102,30 +155,119 @@ impl PageTable {
                 result = Some(pte);
                 break;
             }
-            if !pte.is_valid() {
+            if !pte.is_valid() || pte.is_leaf() {
                 return None;
             }
             ppn = pte.ppn();
         }
         result
     }
+    /// Leaf entry mapping `vpn` and the number of pages it covers.
+    fn find_leaf(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, usize)> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for (i, idx) in idxs.iter().enumerate() {
+            let pte = &mut ppn.get_pte_array()[*idx];
+            if i == 2 || pte.is_leaf() {
+                return Some((pte, 1 << (9 * (2 - i))));
+            }
+            if !pte.is_valid() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+        None
+    }
     #[allow(unused)]
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
         let pte = self.find_pte_create(vpn).unwrap();
-        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
-        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        assert!(!pte.is_mapped(), "vpn {:?} is mapped before mapping", vpn);
+        *pte = PageTableEntry::new(ppn, leaf_flags(flags));
     }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte(vpn).unwrap();
-        assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
+        assert!(pte.is_mapped(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
     }
+    /// Point the root entries covering `[start, end)` at the tables of
+    /// `other`, so its later changes there show up in this table as well.
+    #[cfg(feature = "unified")]
+    pub fn share_root_entries(
+        &mut self,
+        other: &mut PageTable,
+        start: VirtPageNum,
+        end: VirtPageNum,
+    ) {
+        let first = start.indexes()[0];
+        let last = VirtPageNum(end.0 - 1).indexes()[0];
+        for idx in first..=last {
+            let theirs = &mut other.root_ppn.get_pte_array()[idx];
+            assert!(!theirs.is_leaf());
+            if !theirs.is_valid() {
+                let frame = frame_alloc_for(other.owner).unwrap();
+                *theirs = PageTableEntry::new(frame.ppn, PTEFlags::V);
+                other.frames.push(frame);
+            }
+            let ours = &mut self.root_ppn.get_pte_array()[idx];
+            assert!(
+                !ours.is_valid(),
+                "root entry {} is in use before sharing",
+                idx
+            );
+            *ours = *theirs;
+        }
+    }
+    /// Replace the flags of the mapped page at `vpn`.
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(
+            pte.is_mapped(),
+            "vpn {:?} is invalid before changing flags",
+            vpn
+        );
+        *pte = PageTableEntry::new(pte.ppn(), leaf_flags(flags));
+    }
+    /// Clear the dirty bit of the page at `vpn`, return whether it was set.
+    pub fn take_dirty(&mut self, vpn: VirtPageNum) -> bool {
+        match self.find_pte(vpn) {
+            Some(pte) if pte.is_valid() && pte.flags().contains(PTEFlags::D) => {
+                *pte = PageTableEntry::new(pte.ppn(), pte.flags() - PTEFlags::D);
+                true
+            }
+            _ => false,
+        }
+    }
+    /// Map a 2MiB superpage, both numbers aligned to `HUGE_PAGE_PAGES`.
+    pub fn map_huge(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        assert_eq!(vpn.0 % HUGE_PAGE_PAGES, 0);
+        assert_eq!(ppn.0 % HUGE_PAGE_PAGES, 0);
+        let pte = self.find_pte_create_at(vpn, 1).unwrap();
+        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+    }
+    pub fn unmap_huge(&mut self, vpn: VirtPageNum) {
+        let (pte, pages) = self.find_leaf(vpn).unwrap();
+        assert!(
+            pte.is_valid() && pages == HUGE_PAGE_PAGES,
+            "vpn {:?} is not a superpage before unmapping",
+            vpn
+        );
+        *pte = PageTableEntry::empty();
+    }
+    /// The entry of the 4KiB page at `vpn`, cut out of its superpage if any.
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| *pte)
+        self.find_leaf(vpn).map(|(pte, pages)| {
+            if pages == 1 || !pte.is_valid() {
+                *pte
+            } else {
+                let ppn = PhysPageNum(pte.ppn().0 + vpn.0 % pages);
+                PageTableEntry::new(ppn, pte.flags())
+            }
+        })
     }
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_pte(va.clone().floor()).map(|pte| {
+        self.translate(va.clone().floor()).map(|pte| {
             let aligned_pa: PhysAddr = pte.ppn().into();
             let offset = va.page_offset();
             let aligned_pa_usize: usize = aligned_pa.into();
@@ -137,18 +279,67 @@ impl PageTable {
     }
 }
 
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+/// Physical address of the user `va`, first faulting in the page if the
+/// current process dropped it or, for a write, still shares it with the
+/// page cache. None unless user mode may access the page the way
+/// `permitted` asks: the kernel writes only where the user may, as
+/// read-only pages can be frames of the page cache.
+fn translate_user_va(
+    page_table: &PageTable,
+    va: VirtAddr,
+    permitted: fn(&PageTableEntry) -> bool,
+) -> Option<PhysAddr> {
+    let vpn = va.floor();
+    if !page_table
+        .translate(vpn)
+        .map_or(false, |pte| pte.is_valid() && permitted(&pte))
+    {
+        fault_in_current_page(page_table.token(), vpn);
+    }
+    let pte = page_table
+        .translate(vpn)
+        .filter(|pte| pte.is_valid() && pte.user_accessible() && permitted(pte))?;
+    Some((PhysAddr::from(pte.ppn()).0 + va.page_offset()).into())
+}
+
+/// The user range `ptr..ptr + len` as slices of the frames behind it, to be
+/// read from, EFAULT if part of it is no readable user memory.
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+) -> Result<Vec<&'static mut [u8]>, SysError> {
+    user_byte_buffer(token, ptr, len, PageTableEntry::readable)
+}
+
+/// Like `translated_byte_buffer`, for the kernel to write to, EFAULT if
+/// part of the range is not writable.
+pub fn translated_byte_buffer_mut(
+    token: usize,
+    ptr: *mut u8,
+    len: usize,
+) -> Result<Vec<&'static mut [u8]>, SysError> {
+    user_byte_buffer(token, ptr, len, PageTableEntry::writable)
+}
+
+fn user_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    permitted: fn(&PageTableEntry) -> bool,
+) -> Result<Vec<&'static mut [u8]>, SysError> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
-    let end = start + len;
+    let end = start.checked_add(len).ok_or(SysError::EFAULT)?;
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = translate_user_va(&page_table, vpn.into(), permitted)
+            .ok_or(SysError::EFAULT)?
+            .floor();
         vpn.step();
-        let mut end_va: VirtAddr = vpn.into();
-        end_va = end_va.min(VirtAddr::from(end));
+        let end_va = VirtAddr::from(vpn).min(VirtAddr::from(end));
         if end_va.page_offset() == 0 {
             v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
         } else {
@@ -156,43 +347,52 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Ok(v)
 }
 
 /// Load a string from other address spaces into kernel space without an end `\0`.
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
+pub fn translated_str(token: usize, ptr: *const u8) -> Result<String, SysError> {
     let page_table = PageTable::from_token(token);
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
+        let pa = translate_user_va(&page_table, VirtAddr::from(va), PageTableEntry::readable)
+            .ok_or(SysError::EFAULT)?;
+        let ch: u8 = *pa.get_mut();
         if ch == 0 {
             break;
         }
         string.push(ch as char);
         va += 1;
     }
-    string
+    Ok(string)
+}
+
+pub fn translated_ref<T>(token: usize, ptr: *const T) -> Result<&'static T, SysError> {
+    let page_table = PageTable::from_token(token);
+    let va = VirtAddr::from(ptr as usize);
+    translate_user_va(&page_table, va, PageTableEntry::readable)
+        .map(|pa| pa.get_ref())
+        .ok_or(SysError::EFAULT)
 }
 
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Result<&'static mut T, SysError> {
     let page_table = PageTable::from_token(token);
-    page_table
-        .translate_va(VirtAddr::from(ptr as usize))
-        .unwrap()
-        .get_ref()
+    let va = VirtAddr::from(ptr as usize);
+    translate_user_va(&page_table, va, PageTableEntry::writable)
+        .map(|pa| pa.get_mut())
+        .ok_or(SysError::EFAULT)
 }
 
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
+/// Like `translated_refmut`, but read-only text will do as well: a debugger
+/// plants breakpoints there, in a tracee whose text was unshared from the
+/// page cache when it was attached.
+pub fn translated_text_mut<T>(token: usize, ptr: *mut T) -> Result<&'static mut T, SysError> {
     let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    let va = VirtAddr::from(ptr as usize);
+    translate_user_va(&page_table, va, |pte| pte.writable() || pte.executable())
+        .map(|pa| pa.get_mut())
+        .ok_or(SysError::EFAULT)
 }
 
 pub struct UserBuffer {
@@ -0,0 +1,184 @@
+//! Slab caches in front of the buddy heap. Small allocations, which are
+//! most of the kernel objects (TCBs, inode handles, pipe buffers, ...), are
+//! served from per-size caches of slabs; a freed object goes back on the
+//! free list of its slab and an empty slab returns to the heap.
+
+use buddy_system_allocator::{Heap, LockedHeap};
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr::{null_mut, NonNull};
+
+/// Slabs are aligned to their size, so the slab of an object is found by
+/// masking its address.
+const SLAB_SIZE: usize = 0x4000;
+const SLAB_LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(SLAB_SIZE, SLAB_SIZE) };
+const SIZE_CLASSES: [usize; 7] = [16, 32, 64, 128, 256, 512, 1024];
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// Header at the start of every slab, the first object slot holds it.
+struct Slab {
+    free: *mut FreeObject,
+    in_use: usize,
+    prev: *mut Slab,
+    next: *mut Slab,
+}
+
+struct SlabCache {
+    object_size: usize,
+    /// slabs with free objects
+    partial: *mut Slab,
+    slabs: usize,
+}
+
+impl SlabCache {
+    const fn new(object_size: usize) -> Self {
+        Self {
+            object_size,
+            partial: null_mut(),
+            slabs: 0,
+        }
+    }
+
+    unsafe fn push_partial(&mut self, slab: *mut Slab) {
+        (*slab).prev = null_mut();
+        (*slab).next = self.partial;
+        if !self.partial.is_null() {
+            (*self.partial).prev = slab;
+        }
+        self.partial = slab;
+    }
+
+    unsafe fn remove_partial(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.partial = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+    }
+
+    unsafe fn grow(&mut self, heap: &mut Heap) -> bool {
+        let slab = match heap.alloc(SLAB_LAYOUT) {
+            Ok(slab) => slab.as_ptr() as *mut Slab,
+            Err(_) => return false,
+        };
+        let header = (core::mem::size_of::<Slab>() + self.object_size - 1) / self.object_size;
+        let mut free = null_mut();
+        for index in (header..SLAB_SIZE / self.object_size).rev() {
+            let object = (slab as usize + index * self.object_size) as *mut FreeObject;
+            (*object).next = free;
+            free = object;
+        }
+        slab.write(Slab {
+            free,
+            in_use: 0,
+            prev: null_mut(),
+            next: null_mut(),
+        });
+        self.push_partial(slab);
+        self.slabs += 1;
+        true
+    }
+
+    unsafe fn alloc(&mut self, heap: &mut Heap) -> *mut u8 {
+        if self.partial.is_null() && !self.grow(heap) {
+            return null_mut();
+        }
+        let slab = self.partial;
+        let object = (*slab).free;
+        (*slab).free = (*object).next;
+        (*slab).in_use += 1;
+        if (*slab).free.is_null() {
+            self.remove_partial(slab);
+        }
+        object as *mut u8
+    }
+
+    unsafe fn dealloc(&mut self, heap: &mut Heap, ptr: *mut u8) {
+        let slab = (ptr as usize & !(SLAB_SIZE - 1)) as *mut Slab;
+        let object = ptr as *mut FreeObject;
+        if (*slab).free.is_null() {
+            self.push_partial(slab);
+        }
+        (*object).next = (*slab).free;
+        (*slab).free = object;
+        (*slab).in_use -= 1;
+        // keep one empty slab around so that alloc/free pairs do not
+        // bounce between the cache and the heap
+        if (*slab).in_use == 0 && self.partial != slab {
+            self.remove_partial(slab);
+            heap.dealloc(NonNull::new_unchecked(slab as *mut u8), SLAB_LAYOUT);
+            self.slabs -= 1;
+        }
+    }
+}
+
+pub struct SlabAllocator {
+    heap: LockedHeap,
+    /// only touched with the heap locked
+    caches: UnsafeCell<[SlabCache; SIZE_CLASSES.len()]>,
+}
+
+unsafe impl Sync for SlabAllocator {}
+
+impl SlabAllocator {
+    pub const fn new() -> Self {
+        Self {
+            heap: LockedHeap::empty(),
+            caches: UnsafeCell::new([
+                SlabCache::new(SIZE_CLASSES[0]),
+                SlabCache::new(SIZE_CLASSES[1]),
+                SlabCache::new(SIZE_CLASSES[2]),
+                SlabCache::new(SIZE_CLASSES[3]),
+                SlabCache::new(SIZE_CLASSES[4]),
+                SlabCache::new(SIZE_CLASSES[5]),
+                SlabCache::new(SIZE_CLASSES[6]),
+            ]),
+        }
+    }
+
+    pub unsafe fn init(&self, start: usize, size: usize) {
+        self.heap.lock().init(start, size);
+    }
+
+    /// Objects are aligned to their size, which covers the alignment.
+    fn class_of(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align());
+        SIZE_CLASSES.iter().position(|&class| size <= class)
+    }
+
+    /// (object size, slabs) of every cache.
+    #[allow(unused)]
+    pub fn stats(&self) -> [(usize, usize); SIZE_CLASSES.len()] {
+        let _heap = self.heap.lock();
+        let caches = unsafe { &*self.caches.get() };
+        let mut stats = [(0, 0); SIZE_CLASSES.len()];
+        for (stat, cache) in stats.iter_mut().zip(caches.iter()) {
+            *stat = (cache.object_size, cache.slabs);
+        }
+        stats
+    }
+}
+
+unsafe impl GlobalAlloc for SlabAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.heap.lock();
+        match Self::class_of(&layout) {
+            Some(class) => (*self.caches.get())[class].alloc(&mut heap),
+            None => heap.alloc(layout).map_or(null_mut(), |ptr| ptr.as_ptr()),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut heap = self.heap.lock();
+        match Self::class_of(&layout) {
+            Some(class) => (*self.caches.get())[class].dealloc(&mut heap, ptr),
+            None => heap.dealloc(NonNull::new_unchecked(ptr), layout),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! A minimal DHCP client, run once at boot to configure the interface.
+
+use super::{LOSE_NET_STACK, MAC_ADDRESS};
+use crate::drivers::NET_DEVICE;
+use crate::sync::UPIntrFreeCell;
+use crate::timer::{get_time, get_time_ms};
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+use lose_net_stack::IPv4;
+
+const ETH_HEADER_LEN: usize = 14;
+const IP_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+const HEADERS_LEN: usize = ETH_HEADER_LEN + IP_HEADER_LEN + UDP_HEADER_LEN;
+/// op .. file, the fixed part of a BOOTP message
+const BOOTP_LEN: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+
+const CLIENT_PORT: u16 = 68;
+const SERVER_PORT: u16 = 67;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS: u8 = 6;
+const OPTION_REQUESTED_IP: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_PARAMETERS: u8 = 55;
+const OPTION_END: u8 = 255;
+
+/// how long to wait for an answer
+const TIMEOUT_MS: usize = 1000;
+const MAX_TRIES: usize = 3;
+
+/// Addresses are in host order, `10.0.2.15` is `0x0a00020f`.
+#[derive(Clone, Copy, Default)]
+pub struct NetConfig {
+    pub ip: u32,
+    pub netmask: u32,
+    pub gateway: u32,
+    pub dns: u32,
+    pub lease_secs: u32,
+}
+
+/// What QEMU user networking hands out, taken when no server answers.
+const FALLBACK: NetConfig = NetConfig {
+    ip: 0x0a00_020f,
+    netmask: 0xffff_ff00,
+    gateway: 0x0a00_0202,
+    dns: 0x0a00_0203,
+    lease_secs: 0,
+};
+
+lazy_static! {
+    pub static ref NET_CONFIG: UPIntrFreeCell<NetConfig> =
+        unsafe { UPIntrFreeCell::new(NetConfig::default()) };
+}
+
+pub fn net_config() -> NetConfig {
+    *NET_CONFIG.exclusive_access()
+}
+
+struct Lease {
+    message_type: u8,
+    server: u32,
+    config: NetConfig,
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// A broadcast frame from 0.0.0.0:68 to 255.255.255.255:67.
+fn build_message(mac: &[u8; 6], xid: u32, message_type: u8, lease: Option<&Lease>) -> Vec<u8> {
+    let mut bootp = vec![0u8; BOOTP_LEN];
+    bootp[0] = 1; // BOOTREQUEST
+    bootp[1] = 1; // ethernet
+    bootp[2] = 6;
+    bootp[4..8].copy_from_slice(&xid.to_be_bytes());
+    // ask for a broadcast answer, we cannot take unicast yet
+    bootp[10] = 0x80;
+    bootp[28..34].copy_from_slice(mac);
+    bootp.extend_from_slice(&MAGIC_COOKIE);
+    bootp.extend_from_slice(&[OPTION_MESSAGE_TYPE, 1, message_type]);
+    if let Some(lease) = lease {
+        bootp.extend_from_slice(&[OPTION_REQUESTED_IP, 4]);
+        bootp.extend_from_slice(&lease.config.ip.to_be_bytes());
+        bootp.extend_from_slice(&[OPTION_SERVER_ID, 4]);
+        bootp.extend_from_slice(&lease.server.to_be_bytes());
+    }
+    bootp.extend_from_slice(&[
+        OPTION_PARAMETERS,
+        3,
+        OPTION_SUBNET_MASK,
+        OPTION_ROUTER,
+        OPTION_DNS,
+        OPTION_END,
+    ]);
+
+    let mut frame = vec![0u8; HEADERS_LEN];
+    frame[0..6].copy_from_slice(&[0xff; 6]);
+    frame[6..12].copy_from_slice(mac);
+    frame[12..14].copy_from_slice(&0x0800u16.to_be_bytes());
+    let ip = &mut frame[ETH_HEADER_LEN..ETH_HEADER_LEN + IP_HEADER_LEN];
+    ip[0] = 0x45;
+    ip[2..4]
+        .copy_from_slice(&((IP_HEADER_LEN + UDP_HEADER_LEN + bootp.len()) as u16).to_be_bytes());
+    ip[8] = 64; // ttl
+    ip[9] = 17; // udp
+    ip[16..20].copy_from_slice(&[0xff; 4]);
+    let sum = checksum(ip);
+    ip[10..12].copy_from_slice(&sum.to_be_bytes());
+    // a zero udp checksum means none
+    let udp = &mut frame[ETH_HEADER_LEN + IP_HEADER_LEN..];
+    udp[0..2].copy_from_slice(&CLIENT_PORT.to_be_bytes());
+    udp[2..4].copy_from_slice(&SERVER_PORT.to_be_bytes());
+    udp[4..6].copy_from_slice(&((UDP_HEADER_LEN + bootp.len()) as u16).to_be_bytes());
+    frame.extend_from_slice(&bootp);
+    frame
+}
+
+/// The lease offered or acknowledged in `frame`, if it answers `xid`.
+fn parse_message(frame: &[u8], xid: u32) -> Option<Lease> {
+    if frame.len() < HEADERS_LEN + BOOTP_LEN + MAGIC_COOKIE.len()
+        || frame[12..14] != 0x0800u16.to_be_bytes()
+        || frame[ETH_HEADER_LEN] != 0x45
+        || frame[ETH_HEADER_LEN + 9] != 17
+    {
+        return None;
+    }
+    let udp = &frame[ETH_HEADER_LEN + IP_HEADER_LEN..];
+    if udp[2..4] != CLIENT_PORT.to_be_bytes() {
+        return None;
+    }
+    let bootp = &udp[UDP_HEADER_LEN..];
+    if bootp[0] != 2
+        || be_u32(&bootp[4..8]) != xid
+        || bootp[BOOTP_LEN..BOOTP_LEN + 4] != MAGIC_COOKIE
+    {
+        return None;
+    }
+    let mut lease = Lease {
+        message_type: 0,
+        server: 0,
+        config: NetConfig {
+            ip: be_u32(&bootp[16..20]),
+            ..NetConfig::default()
+        },
+    };
+    let mut options = &bootp[BOOTP_LEN + 4..];
+    while let Some(&code) = options.first() {
+        match code {
+            OPTION_END => break,
+            OPTION_PAD => {
+                options = &options[1..];
+                continue;
+            }
+            _ => {}
+        }
+        let len = *options.get(1)? as usize;
+        let value = options.get(2..2 + len)?;
+        match (code, len) {
+            (OPTION_MESSAGE_TYPE, 1) => lease.message_type = value[0],
+            (OPTION_SUBNET_MASK, 4) => lease.config.netmask = be_u32(value),
+            // the first router and name server are enough
+            (OPTION_ROUTER, _) if len >= 4 => lease.config.gateway = be_u32(value),
+            (OPTION_DNS, _) if len >= 4 => lease.config.dns = be_u32(value),
+            (OPTION_SERVER_ID, 4) => lease.server = be_u32(value),
+            (OPTION_LEASE_TIME, 4) => lease.config.lease_secs = be_u32(value),
+            _ => {}
+        }
+        options = &options[2 + len..];
+    }
+    Some(lease)
+}
+
+/// Send `message_type` and wait for one of `expected` from the server, at
+/// most TIMEOUT_MS.
+fn exchange(
+    mac: &[u8; 6],
+    xid: u32,
+    message_type: u8,
+    lease: Option<&Lease>,
+    expected: &[u8],
+) -> Option<Lease> {
+    NET_DEVICE.transmit(&build_message(mac, xid, message_type, lease));
+    let mut frame = vec![0u8; 1536];
+    let deadline = get_time_ms() + TIMEOUT_MS;
+    while get_time_ms() < deadline {
+        if !NET_DEVICE.can_recv() {
+            core::hint::spin_loop();
+            continue;
+        }
+        let len = NET_DEVICE.receive(&mut frame);
+        match parse_message(&frame[..len], xid) {
+            Some(reply) if expected.contains(&reply.message_type) => return Some(reply),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn dhcp(mac: &[u8; 6], xid: u32) -> Option<NetConfig> {
+    let offer = exchange(mac, xid, DHCPDISCOVER, None, &[DHCPOFFER])?;
+    let ack = exchange(mac, xid, DHCPREQUEST, Some(&offer), &[DHCPACK, DHCPNAK])?;
+    if ack.message_type == DHCPNAK {
+        return None;
+    }
+    Some(ack.config)
+}
+
+/// Ask the DHCP server for an address and configure the stack with it, or
+/// with FALLBACK if no server answers.
+pub fn init() {
+    let mac = MAC_ADDRESS;
+    let lease = (0..MAX_TRIES).find_map(|attempt| dhcp(&mac, get_time() as u32 ^ attempt as u32));
+    if lease.is_none() {
+        println!("KERN: dhcp: no lease, taking the QEMU default");
+    }
+    let config = lease.unwrap_or(FALLBACK);
+    LOSE_NET_STACK.0.exclusive_access().ip = IPv4::from_u32(config.ip);
+    *NET_CONFIG.exclusive_access() = config;
+    let [a, b, c, d] = config.ip.to_be_bytes();
+    let prefix = config.netmask.count_ones();
+    let [g0, g1, g2, g3] = config.gateway.to_be_bytes();
+    println!(
+        "KERN: dhcp: {}.{}.{}.{}/{} via {}.{}.{}.{}, lease {}s",
+        a, b, c, d, prefix, g0, g1, g2, g3, config.lease_secs
+    );
+}
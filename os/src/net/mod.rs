@@ -1,3 +1,4 @@
+pub mod dhcp;
 pub mod port_table;
 pub mod socket;
 pub mod tcp;
@@ -16,14 +17,17 @@ use crate::{
 
 use self::{port_table::check_accept, socket::set_s_a_by_index};
 
+pub const MAC_ADDRESS: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
 pub struct NetStack(UPIntrFreeCell<LoseStack>);
 
 impl NetStack {
     pub fn new() -> Self {
         unsafe {
+            // unconfigured until dhcp hands out an address
             NetStack(UPIntrFreeCell::new(LoseStack::new(
-                IPv4::new(10, 0, 2, 15),
-                MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]),
+                IPv4::new(0, 0, 0, 0),
+                MacAddress::new(MAC_ADDRESS),
             )))
         }
     }
@@ -113,6 +113,13 @@ pub fn push_data(index: usize, data: Vec<u8>) {
         .push_back(data);
 }
 
+/// Whether a packet is waiting to be read from the socket.
+pub fn has_data(index: usize) -> bool {
+    SOCKET_TABLE.exclusive_access()[index]
+        .as_ref()
+        .is_some_and(|socket| !socket.buffers.is_empty())
+}
+
 pub fn pop_data(index: usize) -> Option<Vec<u8>> {
     let mut socket_table = SOCKET_TABLE.exclusive_access();
 
@@ -6,13 +6,16 @@ use lose_net_stack::TcpFlags;
 
 use crate::{drivers::NET_DEVICE, fs::File};
 
-use super::socket::get_s_a_by_index;
+use super::socket::{get_s_a_by_index, set_s_a_by_index};
 use super::{
     net_interrupt_handler,
     socket::{add_socket, pop_data, remove_socket},
     LOSE_NET_STACK,
 };
 
+/// payload of one segment, an ethernet frame less the ip and tcp headers
+const TCP_MSS: usize = 1460;
+
 // add tcp packet info to this structure
 pub struct TCP {
     pub target: IPv4,
@@ -83,31 +86,58 @@ impl File for TCP {
 
         let len = data.len();
 
-        // get sock and sequence
-        let (ack, seq) = get_s_a_by_index(self.socket_index).map_or((0, 0), |x| x);
+        // large writes go out as several segments
+        for segment in data.chunks(TCP_MSS) {
+            // get sock and sequence
+            let (ack, seq) = get_s_a_by_index(self.socket_index).map_or((0, 0), |x| x);
+
+            let tcp_packet = TCPPacket {
+                source_ip: lose_net_stack.ip,
+                source_mac: lose_net_stack.mac,
+                source_port: self.sport,
+                dest_ip: self.target,
+                dest_mac: MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+                dest_port: self.dport,
+                data_len: segment.len(),
+                seq,
+                ack,
+                flags: TcpFlags::A,
+                win: 65535,
+                urg: 0,
+                data: segment,
+            };
+            NET_DEVICE.transmit(&tcp_packet.build_data());
+            set_s_a_by_index(
+                self.socket_index,
+                ack,
+                seq.wrapping_add(segment.len() as u32),
+            );
+        }
+        len
+    }
+}
 
-        let tcp_packet = TCPPacket {
+impl Drop for TCP {
+    fn drop(&mut self) {
+        // tell the peer we are done, so a body without a length ends
+        let lose_net_stack = LOSE_NET_STACK.0.exclusive_access();
+        let (ack, seq) = get_s_a_by_index(self.socket_index).map_or((0, 0), |x| x);
+        let fin_packet = TCPPacket {
             source_ip: lose_net_stack.ip,
             source_mac: lose_net_stack.mac,
             source_port: self.sport,
             dest_ip: self.target,
             dest_mac: MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
             dest_port: self.dport,
-            data_len: len,
+            data_len: 0,
             seq,
             ack,
-            flags: TcpFlags::A,
+            flags: TcpFlags::F | TcpFlags::A,
             win: 65535,
             urg: 0,
-            data: data.as_ref(),
+            data: &[],
         };
-        NET_DEVICE.transmit(&tcp_packet.build_data());
-        len
-    }
-}
-
-impl Drop for TCP {
-    fn drop(&mut self) {
+        NET_DEVICE.transmit(&fin_packet.build_data());
         remove_socket(self.socket_index)
     }
 }
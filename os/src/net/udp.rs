@@ -1,8 +1,9 @@
 use super::net_interrupt_handler;
-use super::socket::{add_socket, pop_data, remove_socket};
+use super::socket::{add_socket, has_data, pop_data, remove_socket};
 use super::LOSE_NET_STACK;
 use super::NET_DEVICE;
-use crate::fs::File;
+use crate::fs::{File, PollEvents};
+use crate::task::{deliverable_signals_of_current, suspend_current_and_run_next};
 use alloc::vec;
 use lose_net_stack::packets::udp::UDPPacket;
 use lose_net_stack::IPv4;
@@ -54,12 +55,25 @@ impl File for UDP {
                     }
                 }
                 return left;
+            } else if !deliverable_signals_of_current().is_empty() {
+                // interrupted, it reads as nothing
+                return 0;
             } else {
+                // the device is polled, let others run in between
                 net_interrupt_handler();
+                suspend_current_and_run_next();
             }
         }
     }
 
+    fn poll(&self) -> PollEvents {
+        // the device is polled, take in what it received first
+        net_interrupt_handler();
+        let mut events = PollEvents::POLLOUT;
+        events.set(PollEvents::POLLIN, has_data(self.socket_index));
+        events
+    }
+
     fn write(&self, buf: crate::mm::UserBuffer) -> usize {
         let lose_net_stack = LOSE_NET_STACK.0.exclusive_access();
 
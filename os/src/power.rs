@@ -0,0 +1,38 @@
+//! The way down. Whatever the disk is still owed goes out before the SBI
+//! turns the machine off, so the image is left clean.
+
+use crate::board::device_quiesce;
+use crate::fs::umount_all;
+use crate::sbi::shutdown;
+use crate::smp::stop_other_harts;
+use crate::task::{all_processes, write_back_shared_mappings, SignalFlags};
+use crate::DEV_NON_BLOCKING_ACCESS;
+use core::mem::take;
+use easy_fs::block_cache_invalidate_all;
+use riscv::register::sstatus;
+
+/// Kill every process, write back what they and the block cache hold,
+/// detach the shares and quiet the devices, then power off.
+pub fn power_off(failure: bool) -> ! {
+    // nothing is scheduled any more, the block driver has to poll
+    unsafe {
+        sstatus::clear_sie();
+    }
+    // no other hart runs a task or touches the disk from here on
+    stop_other_harts();
+    *DEV_NON_BLOCKING_ACCESS.exclusive_access() = false;
+    let processes = all_processes();
+    for process in processes.iter() {
+        process.inner_exclusive_access().raise(SignalFlags::SIGKILL);
+        write_back_shared_mappings(process);
+        // the files close now rather than when the process would have died
+        let files = take(&mut process.inner_exclusive_access().fd_table);
+        drop(files);
+    }
+    println!("[kernel] killed {} processes", processes.len());
+    block_cache_invalidate_all();
+    umount_all();
+    device_quiesce();
+    println!("[kernel] filesystems synced, powering off");
+    shutdown(failure)
+}
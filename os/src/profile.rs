@@ -0,0 +1,159 @@
+//! Sampling profiler driven by the timer interrupt.
+//!
+//! While it runs, every tick records where the hart was: the pc and pid of
+//! the application, or the pc of the kernel with a few of its callers. The
+//! samples go to a buffer of the hart and are counted up into
+//! /proc/profile, one folded stack per line as flamegraph.pl reads them.
+
+use crate::config::{KERNEL_STACK_SIZE, MAX_HARTS};
+use crate::ksyms::function_name;
+use crate::sync::UPIntrFreeCell;
+use crate::task::hart_id;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use lazy_static::*;
+
+/// samples a hart keeps, later ticks are only counted as dropped
+const SAMPLES_PER_HART: usize = 4096;
+/// callers recorded above the pc of a kernel sample
+pub const MAX_CALLERS: usize = 4;
+
+#[derive(Clone, Copy)]
+struct Sample {
+    /// None for the kernel
+    pid: Option<usize>,
+    pc: usize,
+    /// return addresses, innermost first, 0 past the last one
+    callers: [usize; MAX_CALLERS],
+}
+
+#[derive(Default)]
+struct HartSamples {
+    samples: Vec<Sample>,
+    dropped: usize,
+}
+
+lazy_static! {
+    static ref SAMPLES: Vec<UPIntrFreeCell<HartSamples>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPIntrFreeCell::new(HartSamples::default()) })
+        .collect();
+}
+
+/// 0 while stopped, else one more than the callers to record
+static MODE: AtomicUsize = AtomicUsize::new(0);
+
+/// Throw away the samples so far and sample every tick from now on, with
+/// up to `callers` return addresses of the kernel.
+pub fn start(callers: usize) {
+    MODE.store(0, Relaxed);
+    for hart in SAMPLES.iter() {
+        hart.exclusive_session(|hart| {
+            hart.samples.clear();
+            // ticks must not allocate
+            hart.samples.reserve_exact(SAMPLES_PER_HART);
+            hart.dropped = 0;
+        });
+    }
+    MODE.store(callers.min(MAX_CALLERS) + 1, Relaxed);
+}
+
+/// Stop sampling, the samples stay readable.
+pub fn stop() {
+    MODE.store(0, Relaxed);
+}
+
+pub fn running() -> bool {
+    MODE.load(Relaxed) != 0
+}
+
+fn record(sample: Sample) {
+    SAMPLES[hart_id()].exclusive_session(|hart| {
+        if hart.samples.len() < SAMPLES_PER_HART {
+            hart.samples.push(sample);
+        } else {
+            hart.dropped += 1;
+        }
+    });
+}
+
+/// Called on a timer tick that interrupted application `pid` at `pc`, if
+/// the profiler is [`running`].
+pub fn sample_user(pid: usize, pc: usize) {
+    record(Sample {
+        pid: Some(pid),
+        pc,
+        callers: [0; MAX_CALLERS],
+    });
+}
+
+/// Called on a timer tick that interrupted the kernel at `pc`, with `fp`
+/// the frame pointer it had and `sp` the stack pointer of the trap.
+pub fn sample_kernel(pc: usize, fp: usize, sp: usize) {
+    let mode = MODE.load(Relaxed);
+    if mode == 0 {
+        return;
+    }
+    let mut callers = [0; MAX_CALLERS];
+    let mut fp = fp;
+    // frame pointers are forced on, but only trust them on this stack
+    for ra in callers.iter_mut().take(mode - 1) {
+        if fp <= sp || fp - sp > KERNEL_STACK_SIZE || fp % 8 != 0 {
+            break;
+        }
+        unsafe {
+            *ra = *((fp - 8) as *const usize);
+            fp = *((fp - 16) as *const usize);
+        }
+    }
+    record(Sample {
+        pid: None,
+        pc,
+        callers,
+    });
+}
+
+/// The folded stack of a sample, the kernel part by function.
+fn fold(sample: &Sample) -> String {
+    match sample.pid {
+        Some(pid) => format!("pid {};{:#x}", pid, sample.pc),
+        None => {
+            let mut stack = String::from("kernel");
+            for &ra in sample.callers.iter().rev().filter(|&&ra| ra != 0) {
+                write!(stack, ";{}", function_name(ra)).unwrap();
+            }
+            write!(stack, ";{}", function_name(sample.pc)).unwrap();
+            stack
+        }
+    }
+}
+
+/// The samples of every hart counted by stack, most frequent first, in the
+/// folded format of flamegraph.pl: `pid 3;0x10a4c 12` for an application
+/// and `kernel;os::trap::trap_handler;os::task::schedule 7` for the kernel,
+/// outermost first. Kernel addresses without a symbol stay numbers.
+pub fn report() -> String {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut dropped = 0;
+    for hart in SAMPLES.iter() {
+        hart.exclusive_session(|hart| {
+            for sample in hart.samples.iter() {
+                *counts.entry(fold(sample)).or_insert(0) += 1;
+            }
+            dropped += hart.dropped;
+        });
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1));
+    let mut text = String::new();
+    for (stack, count) in counts {
+        writeln!(text, "{} {}", stack, count).unwrap();
+    }
+    if dropped != 0 {
+        writeln!(text, "dropped {}", dropped).unwrap();
+    }
+    text
+}
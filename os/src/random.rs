@@ -0,0 +1,142 @@
+//! Kernel entropy pool. Interrupt timing, the cycle counter and the RTC are
+//! mixed into a ChaCha20 key; output is the keystream, and the key is
+//! replaced after every request so earlier output cannot be recovered.
+
+use crate::board::rtc_time_ns;
+use crate::sync::UPIntrFreeCell;
+use lazy_static::*;
+use riscv::register::{cycle, time};
+
+const SIGMA: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+/// nonce of the keystream handed out and of the next key
+const OUTPUT_NONCE: [u32; 2] = [0, 0];
+const REKEY_NONCE: [u32; 2] = [1, 0];
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: [u32; 2]) -> [u32; 16] {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&SIGMA);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+    input[14..].copy_from_slice(&nonce);
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    for (word, input) in state.iter_mut().zip(input) {
+        *word = word.wrapping_add(input);
+    }
+    state
+}
+
+pub struct EntropyPool {
+    key: [u32; 8],
+    /// samples gathered since the key was last replaced
+    pending: u64,
+    samples: usize,
+}
+
+impl EntropyPool {
+    fn new() -> Self {
+        let mut pool = Self {
+            key: [0; 8],
+            pending: 0,
+            samples: 0,
+        };
+        pool.add(rtc_time_ns());
+        pool.add(time::read() as u64);
+        pool.add(cycle::read() as u64);
+        pool.rekey();
+        pool
+    }
+
+    fn add(&mut self, sample: u64) {
+        self.pending = (self.pending.rotate_left(13) ^ sample).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        self.samples += 1;
+    }
+
+    /// Fold the pending samples into the key and replace it.
+    fn rekey(&mut self) {
+        self.key[0] ^= self.pending as u32;
+        self.key[1] ^= (self.pending >> 32) as u32;
+        self.key[2] ^= self.samples as u32;
+        self.pending = 0;
+        self.samples = 0;
+        let block = chacha20_block(&self.key, 0, REKEY_NONCE);
+        self.key.copy_from_slice(&block[..8]);
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        self.add(cycle::read() as u64);
+        self.rekey();
+        for (counter, chunk) in buf.chunks_mut(64).enumerate() {
+            let block = chacha20_block(&self.key, counter as u64, OUTPUT_NONCE);
+            for (bytes, word) in chunk.chunks_mut(4).zip(block) {
+                bytes.copy_from_slice(&word.to_le_bytes()[..bytes.len()]);
+            }
+        }
+        self.rekey();
+    }
+}
+
+lazy_static! {
+    pub static ref ENTROPY_POOL: UPIntrFreeCell<EntropyPool> =
+        unsafe { UPIntrFreeCell::new(EntropyPool::new()) };
+}
+
+/// Called for every external interrupt, the arrival time is the entropy.
+pub fn add_interrupt_entropy(irq: usize) {
+    // a sample is dropped rather than waiting for another hart
+    if let Some(mut pool) = ENTROPY_POOL.try_exclusive_access() {
+        pool.add((cycle::read() as u64) << 8 ^ time::read() as u64 ^ irq as u64);
+    }
+}
+
+/// Mix bytes written to the random devices into the pool.
+pub fn add_entropy_bytes(bytes: &[u8]) {
+    let mut pool = ENTROPY_POOL.exclusive_access();
+    for chunk in bytes.chunks(8) {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        pool.add(u64::from_le_bytes(word));
+    }
+}
+
+/// Fill `buf` with random bytes.
+pub fn get_random_bytes(buf: &mut [u8]) {
+    ENTROPY_POOL.exclusive_access().fill(buf);
+}
+
+#[cfg(feature = "ktest")]
+pub fn chacha20_test() {
+    // RFC 8439 section 2.3.2, its 32-bit counter and first nonce word
+    // make up our 64-bit counter
+    let key: [u32; 8] = core::array::from_fn(|i| {
+        let byte = 4 * i as u8;
+        u32::from_le_bytes([byte, byte + 1, byte + 2, byte + 3])
+    });
+    let block = chacha20_block(&key, 0x0900_0000 << 32 | 1, [0x4a00_0000, 0]);
+    assert_eq!(
+        block[..4],
+        [0xe4e7_f110, 0x1559_3bd1, 0x1fdd_0f50, 0xc471_20a3]
+    );
+    println!("chacha20_test passed!");
+}
@@ -13,3 +13,107 @@ pub fn shutdown(failure: bool) -> ! {
     }
     unreachable!()
 }
+
+const EID_BASE: usize = 0x10;
+const FID_PROBE_EXTENSION: usize = 3;
+/// "sPI", inter-processor interrupts
+const EID_IPI: usize = 0x73_5049;
+const FID_SEND_IPI: usize = 0;
+/// "HSM", hart state management
+const EID_HSM: usize = 0x48_534D;
+const FID_HART_START: usize = 0;
+/// "RFNC", remote fences
+const EID_RFENCE: usize = 0x5246_4E43;
+const FID_REMOTE_SFENCE_VMA: usize = 1;
+const FID_REMOTE_SFENCE_VMA_ASID: usize = 2;
+/// "DBTR", the debug triggers extension of SBI 2.0
+const EID_DBTR: usize = 0x4442_5452;
+const FID_DEBUG_NUM_TRIGGERS: usize = 0;
+const FID_DEBUG_SET_SHMEM: usize = 1;
+const FID_DEBUG_INSTALL_TRIGGERS: usize = 3;
+const FID_DEBUG_UNINSTALL_TRIGGERS: usize = 5;
+
+/// Return (error, value) of a call not covered by sbi_rt.
+fn sbi_call(eid: usize, fid: usize, args: [usize; 5]) -> (isize, usize) {
+    let (error, value);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a6") fid,
+            in("a7") eid,
+        );
+    }
+    (error, value)
+}
+
+pub fn probe_extension(eid: usize) -> bool {
+    let (error, value) = sbi_call(EID_BASE, FID_PROBE_EXTENSION, [eid, 0, 0, 0, 0]);
+    error == 0 && value != 0
+}
+
+pub fn debug_triggers_supported() -> bool {
+    probe_extension(EID_DBTR)
+}
+
+/// Number of triggers able to match like `tdata1`.
+pub fn debug_num_triggers(tdata1: usize) -> usize {
+    let (error, value) = sbi_call(EID_DBTR, FID_DEBUG_NUM_TRIGGERS, [tdata1, 0, 0, 0, 0]);
+    if error == 0 {
+        value
+    } else {
+        0
+    }
+}
+
+pub fn debug_set_shmem(phys_addr: usize) -> bool {
+    sbi_call(EID_DBTR, FID_DEBUG_SET_SHMEM, [phys_addr, 0, 0, 0, 0]).0 == 0
+}
+
+/// Triggers are read from and their indexes written to the shared memory.
+pub fn debug_install_triggers(count: usize) -> bool {
+    sbi_call(EID_DBTR, FID_DEBUG_INSTALL_TRIGGERS, [count, 0, 0, 0, 0]).0 == 0
+}
+
+pub fn debug_uninstall_triggers(base: usize, mask: usize) -> bool {
+    sbi_call(
+        EID_DBTR,
+        FID_DEBUG_UNINSTALL_TRIGGERS,
+        [base, mask, 0, 0, 0],
+    )
+    .0 == 0
+}
+
+/// Start `hart` in supervisor mode at the physical address `start`, with
+/// its id in a0 and `opaque` in a1. False if there is no such hart or it
+/// runs already.
+pub fn hart_start(hart: usize, start: usize, opaque: usize) -> bool {
+    sbi_call(EID_HSM, FID_HART_START, [hart, start, opaque, 0, 0]).0 == 0
+}
+
+/// Raise the supervisor software interrupt of the harts in `hart_mask`.
+pub fn send_ipi(hart_mask: usize) {
+    sbi_call(EID_IPI, FID_SEND_IPI, [hart_mask, 0, 0, 0, 0]);
+}
+
+/// Flush [start, start + size) of `asid` from the TLBs of the harts in
+/// `hart_mask`, a size of usize::MAX flushes everything. Without ASID
+/// support all address spaces are flushed.
+pub fn remote_sfence_vma(hart_mask: usize, start: usize, size: usize, asid: Option<usize>) {
+    match asid {
+        Some(asid) => sbi_call(
+            EID_RFENCE,
+            FID_REMOTE_SFENCE_VMA_ASID,
+            [hart_mask, 0, start, size, asid],
+        ),
+        None => sbi_call(
+            EID_RFENCE,
+            FID_REMOTE_SFENCE_VMA,
+            [hart_mask, 0, start, size, 0],
+        ),
+    };
+}
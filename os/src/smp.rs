@@ -0,0 +1,194 @@
+//! Running code on other harts. A call is queued for the hart and an IPI
+//! raises its supervisor software interrupt, whose handler runs whatever
+//! was queued. The caller spins until its call has run, running the calls
+//! queued for its own hart meanwhile so two harts calling each other with
+//! interrupts off do not wait on each other forever.
+//!
+//! The harts besides the boot one are started only to take calls, tasks
+//! are all scheduled on the boot hart.
+
+use crate::config::{KERNEL_STACK_SIZE, MAX_HARTS};
+use crate::mm::kernel_token;
+use crate::sbi::{hart_start, send_ipi};
+use crate::sync::Inbox;
+use crate::task::hart_id;
+use crate::timer::enable_user_counters;
+use crate::trap::set_kernel_trap_entry;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lazy_static::*;
+use riscv::register::{satp, sie, sip, sstatus};
+
+type Func = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    /// calls queued for each hart
+    static ref QUEUES: Vec<Inbox<Func>> = (0..MAX_HARTS).map(|_| Inbox::new()).collect();
+}
+
+/// harts taking calls, a bit for each
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+/// harts asked to stop, checked before any call so that stopping
+/// allocates nothing, as on the panic path
+static STOPPING: AtomicUsize = AtomicUsize::new(0);
+/// the kernel page table, for the harts started after paging is on
+static KERNEL_SATP: AtomicUsize = AtomicUsize::new(0);
+
+/// Where a call leaves its result for the hart waiting on it.
+struct Slot<R> {
+    done: AtomicBool,
+    value: UnsafeCell<Option<R>>,
+}
+
+unsafe impl<R: Send> Sync for Slot<R> {}
+
+/// Take calls on this hart from now on.
+pub fn init() {
+    unsafe { sie::set_ssoft() };
+    ONLINE.fetch_or(1 << hart_id(), Ordering::AcqRel);
+}
+
+/// Start every other hart the SBI knows of and wait until each of them
+/// takes calls.
+pub fn boot_other_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+    KERNEL_SATP.store(kernel_token(), Ordering::Release);
+    for hart in (0..MAX_HARTS).filter(|&hart| hart != hart_id()) {
+        // u128 for a stack aligned to 16 bytes
+        let stack = vec![0u128; KERNEL_STACK_SIZE / 16].into_boxed_slice();
+        let top = stack.as_ptr_range().end as usize;
+        if !hart_start(hart, _start_secondary as usize, top) {
+            continue;
+        }
+        Box::leak(stack);
+        while online_harts() & (1 << hart) == 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// A hart started by [`boot_other_harts`], on the stack it was given. It
+/// turns on paging, the kernel trap entry and the user counters, then
+/// sleeps between calls.
+#[no_mangle]
+fn rust_main_secondary() -> ! {
+    unsafe {
+        satp::write(KERNEL_SATP.load(Ordering::Acquire));
+        asm!("sfence.vma");
+    }
+    set_kernel_trap_entry();
+    enable_user_counters();
+    init();
+    unsafe { sstatus::set_sie() };
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
+
+/// Mask of the harts taking calls.
+pub fn online_harts() -> usize {
+    ONLINE.load(Ordering::Acquire)
+}
+
+/// The supervisor software interrupt: run the calls queued for this hart.
+pub fn handle_ipi() {
+    unsafe { sip::clear_ssoft() };
+    run_calls();
+}
+
+fn run_calls() {
+    if STOPPING.load(Ordering::Acquire) & (1 << hart_id()) != 0 {
+        park();
+    }
+    for func in QUEUES[hart_id()].take_all() {
+        func();
+    }
+}
+
+/// Stop taking calls and idle with interrupts off for good.
+fn park() -> ! {
+    ONLINE.fetch_and(!(1 << hart_id()), Ordering::AcqRel);
+    unsafe { sstatus::clear_sie() };
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
+
+/// Run `func` on `hart` and return its result, None if the hart does not
+/// take calls or stops before it runs. On the current hart it is simply
+/// called.
+pub fn call_on<R, F>(hart: usize, func: F) -> Option<R>
+where
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
+{
+    if hart == hart_id() {
+        return Some(func());
+    }
+    if hart >= MAX_HARTS || online_harts() & (1 << hart) == 0 {
+        return None;
+    }
+    let slot = Arc::new(Slot {
+        done: AtomicBool::new(false),
+        value: UnsafeCell::new(None),
+    });
+    let remote = Arc::clone(&slot);
+    QUEUES[hart].push(Box::new(move || {
+        unsafe { *remote.value.get() = Some(func()) };
+        remote.done.store(true, Ordering::Release);
+    }));
+    send_ipi(1 << hart);
+    while !slot.done.load(Ordering::Acquire) {
+        if online_harts() & (1 << hart) == 0 {
+            return None;
+        }
+        run_calls();
+        core::hint::spin_loop();
+    }
+    unsafe { (*slot.value.get()).take() }
+}
+
+/// Run `func` on every hart taking calls, this one included, and return
+/// the results by hart, as to add up per-hart counters.
+pub fn call_on_each<R, F>(func: F) -> Vec<(usize, R)>
+where
+    R: Send + 'static,
+    F: Fn() -> R + Clone + Send + 'static,
+{
+    (0..MAX_HARTS)
+        .filter(|&hart| online_harts() & (1 << hart) != 0)
+        .filter_map(|hart| call_on(hart, func.clone()).map(|result| (hart, result)))
+        .collect()
+}
+
+/// Park every other hart for good, without waiting for them, so a panic
+/// or power off is not raced by the rest of the kernel.
+pub fn stop_other_harts() {
+    let others = online_harts() & !(1 << hart_id());
+    if others == 0 {
+        return;
+    }
+    STOPPING.fetch_or(others, Ordering::AcqRel);
+    send_ipi(others);
+}
+
+#[cfg(feature = "ktest")]
+pub fn smp_test() {
+    let hart = hart_id();
+    assert_eq!(call_on(hart, move || hart_id() == hart), Some(true));
+    let offline = (0..MAX_HARTS).find(|&hart| online_harts() & (1 << hart) == 0);
+    if let Some(offline) = offline {
+        assert_eq!(call_on(offline, || 0), None);
+    }
+    let harts = call_on_each(hart_id);
+    assert!(harts.iter().all(|&(hart, id)| hart == id));
+    assert_eq!(harts.len(), online_harts().count_ones() as usize);
+    println!("smp_test passed on {} harts!", harts.len());
+}
@@ -0,0 +1,196 @@
+//! Futexes: a thread sleeps on a 32-bit user word until another one wakes
+//! the word up. Queues are keyed by the physical address of the word, so a
+//! futex in a shared mapping works between processes as well.
+
+use super::UPIntrFreeCell;
+use crate::mm::translated_ref;
+use crate::syscall::SysError;
+use crate::task::{
+    current_task, sleep_interruptible, wakeup_task, ProcessControlBlock, TaskControlBlock,
+    TaskStatus,
+};
+use crate::timer::get_time_ns;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Bitset of plain waits and wakes, matching every other one.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+struct Waiter {
+    task: Arc<TaskControlBlock>,
+    bitset: u32,
+}
+
+lazy_static! {
+    static ref FUTEX_QUEUES: UPIntrFreeCell<BTreeMap<usize, VecDeque<Waiter>>> =
+        unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
+}
+
+fn futex_key(token: usize, uaddr: *mut u32) -> Result<usize, SysError> {
+    translated_ref(token, uaddr).map(|word| word as *const u32 as usize)
+}
+
+pub enum FutexWait {
+    Woken,
+    /// the word did not hold the expected value
+    Changed,
+    TimedOut,
+    /// a signal can be delivered
+    Interrupted,
+}
+
+/// A deadline in ns and the clock it is measured with.
+pub type FutexDeadline = (u64, fn() -> u64);
+
+/// Sleep on the word unless it no longer holds `expected`, until a wake
+/// whose bitset shares a bit with `bitset`, until `deadline` passes or until
+/// a signal comes.
+pub fn futex_wait(
+    token: usize,
+    uaddr: *mut u32,
+    expected: u32,
+    bitset: u32,
+    deadline: Option<FutexDeadline>,
+) -> Result<FutexWait, SysError> {
+    let word = translated_ref(token, uaddr)?;
+    let key = word as *const u32 as usize;
+    let task = current_task().unwrap();
+    let mut queues = FUTEX_QUEUES.exclusive_access();
+    // compared with the queues locked, so a wake in between is not lost
+    if *word != expected {
+        return Ok(FutexWait::Changed);
+    }
+    queues.entry(key).or_default().push_back(Waiter {
+        task: Arc::clone(&task),
+        bitset,
+    });
+    drop(queues);
+    // the timer sleeps on the monotonic clock
+    let deadline = deadline
+        .map(|(deadline_ns, now)| get_time_ns().saturating_add(deadline_ns.saturating_sub(now())));
+    loop {
+        let interrupted = sleep_interruptible(deadline);
+        let mut queues = FUTEX_QUEUES.exclusive_access();
+        // a requeue may have moved the waiter to another word
+        let queued = queues.iter_mut().find_map(|(key, queue)| {
+            let index = queue.iter().position(|w| Arc::ptr_eq(&w.task, &task))?;
+            Some((*key, index))
+        });
+        let Some((key, index)) = queued else {
+            return Ok(FutexWait::Woken);
+        };
+        let timed_out = deadline.map_or(false, |deadline| get_time_ns() >= deadline);
+        if interrupted || timed_out {
+            let queue = queues.get_mut(&key).unwrap();
+            queue.remove(index);
+            if queue.is_empty() {
+                queues.remove(&key);
+            }
+            return Ok(if interrupted {
+                FutexWait::Interrupted
+            } else {
+                FutexWait::TimedOut
+            });
+        }
+    }
+}
+
+/// Take at most `count` waiters off the queue of `key` whose bitset matches.
+fn dequeue(
+    queues: &mut BTreeMap<usize, VecDeque<Waiter>>,
+    key: usize,
+    count: usize,
+    bitset: u32,
+) -> VecDeque<Waiter> {
+    let mut taken = VecDeque::new();
+    let Some(queue) = queues.get_mut(&key) else {
+        return taken;
+    };
+    let mut index = 0;
+    while taken.len() < count && index < queue.len() {
+        if queue[index].bitset & bitset != 0 {
+            taken.push_back(queue.remove(index).unwrap());
+        } else {
+            index += 1;
+        }
+    }
+    if queue.is_empty() {
+        queues.remove(&key);
+    }
+    taken
+}
+
+fn wake(waiters: VecDeque<Waiter>) -> usize {
+    let count = waiters.len();
+    for waiter in waiters {
+        // a timer or a signal may have woken it already
+        let blocked = waiter.task.inner_exclusive_access().task_status == TaskStatus::Blocked;
+        if blocked {
+            wakeup_task(waiter.task);
+        }
+    }
+    count
+}
+
+/// Wake at most `count` threads sleeping on the word with a bitset sharing
+/// a bit with `bitset`, returns how many.
+pub fn futex_wake(
+    token: usize,
+    uaddr: *mut u32,
+    count: usize,
+    bitset: u32,
+) -> Result<usize, SysError> {
+    let key = futex_key(token, uaddr)?;
+    let waiters = dequeue(&mut FUTEX_QUEUES.exclusive_access(), key, count, bitset);
+    Ok(wake(waiters))
+}
+
+/// Wake at most `count` threads sleeping on `uaddr` and move at most
+/// `requeue` of the others to `uaddr2`, provided the word still holds
+/// `expected` if given, else EAGAIN. Returns how many were woken and how
+/// many moved.
+pub fn futex_requeue(
+    token: usize,
+    uaddr: *mut u32,
+    count: usize,
+    uaddr2: *mut u32,
+    requeue: usize,
+    expected: Option<u32>,
+) -> Result<(usize, usize), SysError> {
+    let word = translated_ref(token, uaddr)?;
+    let key = word as *const u32 as usize;
+    let key2 = futex_key(token, uaddr2)?;
+    let mut queues = FUTEX_QUEUES.exclusive_access();
+    if expected.map_or(false, |expected| *word != expected) {
+        return Err(SysError::EAGAIN);
+    }
+    let woken = dequeue(&mut queues, key, count, FUTEX_BITSET_MATCH_ANY);
+    let moved = dequeue(&mut queues, key, requeue, FUTEX_BITSET_MATCH_ANY);
+    let moved_count = moved.len();
+    if moved_count > 0 {
+        queues.entry(key2).or_default().extend(moved);
+    }
+    drop(queues);
+    Ok((wake(woken), moved_count))
+}
+
+/// Forget the threads of `process` waiting on a futex, it is exiting and
+/// they will not wait any more.
+pub fn futex_forget(process: &Arc<ProcessControlBlock>) {
+    let forgotten = FUTEX_QUEUES.exclusive_session(|queues| {
+        let mut forgotten = Vec::new();
+        queues.retain(|_, queue| {
+            let (theirs, others): (VecDeque<Waiter>, VecDeque<Waiter>) = queue
+                .drain(..)
+                .partition(|waiter| waiter.task.process.as_ptr() == Arc::as_ptr(process));
+            forgotten.extend(theirs);
+            *queue = others;
+            !queue.is_empty()
+        });
+        forgotten
+    });
+    // the last reference to a thread may go, not with the queues locked
+    drop(forgotten);
+}
@@ -0,0 +1,53 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+struct InboxNode<T> {
+    item: T,
+    next: *mut InboxNode<T>,
+}
+
+/// Items for a hart from the other ones. Any hart pushes with a compare and
+/// swap, the owner takes the whole list at once, so there is no ABA
+/// problem.
+pub struct Inbox<T> {
+    head: AtomicPtr<InboxNode<T>>,
+}
+
+impl<T> Inbox<T> {
+    pub fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+    pub fn push(&self, item: T) {
+        let node = Box::into_raw(Box::new(InboxNode {
+            item,
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*node).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+    /// Everything pushed so far, oldest first.
+    pub fn take_all(&self) -> Vec<T> {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        let mut items = Vec::new();
+        while !node.is_null() {
+            let boxed = unsafe { Box::from_raw(node) };
+            node = boxed.next;
+            items.push(boxed.item);
+        }
+        items.reverse();
+        items
+    }
+}
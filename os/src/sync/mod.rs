@@ -1,9 +1,15 @@
 mod condvar;
+mod futex;
+mod inbox;
 mod mutex;
 mod semaphore;
 mod up;
 
 pub use condvar::Condvar;
+pub use futex::{
+    futex_forget, futex_requeue, futex_wait, futex_wake, FutexWait, FUTEX_BITSET_MATCH_ANY,
+};
+pub use inbox::Inbox;
 pub use mutex::{Mutex, MutexBlocking, MutexSpin};
 pub use semaphore::Semaphore;
 pub use up::{UPIntrFreeCell, UPIntrRefMut};
@@ -0,0 +1,40 @@
+/// Errors of syscalls, returned to user space as the negated linux errno.
+#[allow(clippy::upper_case_acronyms, unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(isize)]
+pub enum SysError {
+    EPERM = 1,
+    ENOENT = 2,
+    ESRCH = 3,
+    EINTR = 4,
+    EIO = 5,
+    EBADF = 9,
+    ECHILD = 10,
+    EAGAIN = 11,
+    ENOMEM = 12,
+    EACCES = 13,
+    EFAULT = 14,
+    EBUSY = 16,
+    EEXIST = 17,
+    ENODEV = 19,
+    ENOTDIR = 20,
+    EISDIR = 21,
+    EINVAL = 22,
+    EMFILE = 24,
+    ENOTTY = 25,
+    ESPIPE = 29,
+    EPIPE = 32,
+    EDEADLK = 35,
+    ENOSYS = 38,
+    ENOTEMPTY = 39,
+    EADDRINUSE = 98,
+    ETIMEDOUT = 110,
+}
+
+pub type SysResult = Result<usize, SysError>;
+
+impl SysError {
+    pub fn errno(self) -> isize {
+        self as isize
+    }
+}
@@ -1,99 +1,615 @@
-use crate::fs::{make_pipe, open_file, OpenFlags};
-use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
+use super::{set_second_result, SysError, SysResult};
+use crate::fs::ioctl::write_arg;
+use crate::fs::{
+    lookup, lookup_parent, make_fifo, make_pipe, make_pty, mount, open_device, open_fifo,
+    open_file, resolve_mount, umount, Dirent, File, OpenFlags, Stat, Statx, DIRENT_NAME_MAX,
+};
+use crate::mm::{
+    page_cache, translated_byte_buffer, translated_byte_buffer_mut, translated_ref,
+    translated_refmut, translated_str, UserBuffer,
+};
 use crate::task::{current_process, current_user_token};
+use crate::timer::{realtime_ns, TimeSpec};
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
+fn is_nonblocking(file: &Arc<dyn File + Send + Sync>) -> bool {
+    file.status()
+        .map_or(false, |status| status.get().contains(OpenFlags::NONBLOCK))
+}
+
+/// Whether a read or write, as `events` says, would go through without
+/// sleeping. A hung up or broken file does not make anyone wait either.
+fn is_ready(file: &Arc<dyn File + Send + Sync>, events: PollEvents) -> bool {
+    file.poll()
+        .intersects(events | PollEvents::POLLHUP | PollEvents::POLLERR)
+}
+
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
     let token = current_user_token();
     let process = current_process();
     let inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
-        return -1;
+        return Err(SysError::EBADF);
     }
     if let Some(file) = &inner.fd_table[fd] {
         if !file.writable() {
-            return -1;
+            return Err(SysError::EBADF);
         }
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        let nonblocking = is_nonblocking(&file);
+        if nonblocking && !is_ready(&file, PollEvents::POLLOUT) {
+            return Err(SysError::EAGAIN);
+        }
+        let written = file.write(UserBuffer::new(translated_byte_buffer(token, buf, len)?));
+        // a pipe has room, but not enough for a write it must not split
+        if nonblocking && written == 0 && len > 0 {
+            return Err(SysError::EAGAIN);
+        }
+        Ok(written)
     } else {
-        -1
+        Err(SysError::EBADF)
     }
 }
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> SysResult {
     let token = current_user_token();
     let process = current_process();
     let inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
-        return -1;
+        return Err(SysError::EBADF);
     }
     if let Some(file) = &inner.fd_table[fd] {
         let file = file.clone();
         if !file.readable() {
-            return -1;
+            return Err(SysError::EBADF);
         }
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
+        if is_nonblocking(&file) && !is_ready(&file, PollEvents::POLLIN) {
+            return Err(SysError::EAGAIN);
+        }
+        let buffers = translated_byte_buffer_mut(token, buf as *mut u8, len)?;
+        Ok(file.read(UserBuffer::new(buffers)))
+    } else {
+        Err(SysError::EBADF)
+    }
+}
+
+/// bytes moved per round of sys_sendfile
+const SENDFILE_CHUNK: usize = 4096;
+
+/// Lend a kernel buffer to `File::read`/`File::write`.
+fn kernel_buffer(buffer: &mut [u8]) -> UserBuffer {
+    UserBuffer::new(vec![unsafe {
+        core::slice::from_raw_parts_mut(buffer.as_mut_ptr(), buffer.len())
+    }])
+}
+
+/// Copy up to `count` bytes from `in_fd` to `out_fd` inside the kernel.
+/// With an `offset` the input is read from there and its own offset is left alone.
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: *mut usize, count: usize) -> SysResult {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let input = inner.fd_table.get(in_fd).cloned().flatten();
+    let output = inner.fd_table.get(out_fd).cloned().flatten();
+    drop(inner);
+    let (input, output) = match (input, output) {
+        (Some(input), Some(output)) if input.readable() && output.writable() => (input, output),
+        _ => return Err(SysError::EBADF),
+    };
+    let mut position = if offset.is_null() {
+        None
     } else {
-        -1
+        let inode = input.inode().ok_or(SysError::ESPIPE)?;
+        Some((inode, *translated_ref(token, offset)?))
+    };
+    let mut buffer = vec![0u8; SENDFILE_CHUNK];
+    let mut sent = 0;
+    while sent < count {
+        let chunk = &mut buffer[..(count - sent).min(SENDFILE_CHUNK)];
+        let len = match position.as_mut() {
+            Some((inode, position)) => {
+                let len = page_cache::read(inode, *position, chunk);
+                *position += len;
+                len
+            }
+            None => input.read(kernel_buffer(chunk)),
+        };
+        if len == 0 {
+            break;
+        }
+        let written = output.write(kernel_buffer(&mut chunk[..len]));
+        sent += written;
+        if written < len {
+            break;
+        }
+    }
+    if let Some((_, position)) = position {
+        *translated_refmut(token, offset)? = position;
     }
+    Ok(sent)
 }
 
-pub fn sys_open(path: *const u8, flags: u32) -> isize {
+/// dirfd of the *at calls naming the working directory, the root here
+pub const AT_FDCWD: isize = -100;
+/// fstatat flag to stat dirfd itself when the path is empty
+pub const AT_EMPTY_PATH: usize = 0x1000;
+
+pub fn file_of(fd: usize) -> Result<Arc<dyn File + Send + Sync>, SysError> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    inner
+        .fd_table
+        .get(fd)
+        .cloned()
+        .flatten()
+        .ok_or(SysError::EBADF)
+}
+
+/// `path` as seen from directory `dirfd`: absolute paths and AT_FDCWD are
+/// taken from the root, anything else is appended to the path the
+/// directory was opened with.
+fn path_at(dirfd: usize, path: String) -> Result<String, SysError> {
+    if path.starts_with('/') || dirfd as isize == AT_FDCWD {
+        return Ok(path);
+    }
+    let dir = file_of(dirfd)?.dir_path().ok_or(SysError::ENOTDIR)?;
+    Ok(format!("{}/{}", dir.trim_end_matches('/'), path))
+}
+
+pub fn sys_open(path: *const u8, flags: u32) -> SysResult {
+    sys_openat(AT_FDCWD as usize, path, flags)
+}
+
+pub fn sys_openat(dirfd: usize, path: *const u8, flags: u32) -> SysResult {
     let process = current_process();
     let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
-        let mut inner = process.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
+    let path = path_at(dirfd, translated_str(token, path)?)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
+    let path = path.as_str();
+    let file: Arc<dyn File + Send + Sync> = if let Some(device) = open_device(path, flags) {
+        device
+    } else if let Some((fs, names)) = resolve_mount(path) {
+        fs.open(&names, flags, path)?
+    } else if let Some(fifo) = open_fifo(path, flags) {
+        // may have blocked until the other end of the FIFO was opened
+        fifo
     } else {
-        -1
+        open_file(path, flags).ok_or(SysError::ENOENT)?
+    };
+    if let Some(status) = file.status() {
+        status.set(flags);
     }
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(file);
+    Ok(fd)
+}
+
+/// unlink flag to remove an empty directory instead of a file
+pub const AT_REMOVEDIR: usize = 0x200;
+
+pub fn sys_mkdir(path: *const u8) -> SysResult {
+    sys_mkdirat(AT_FDCWD as usize, path)
 }
 
-pub fn sys_close(fd: usize) -> isize {
+pub fn sys_mkdirat(dirfd: usize, path: *const u8) -> SysResult {
+    let path = path_at(dirfd, translated_str(current_user_token(), path)?)?;
+    if let Some((fs, names)) = resolve_mount(path.as_str()) {
+        fs.mkdir(&names)?;
+        return Ok(0);
+    }
+    let (dir, name) = lookup_parent(path.as_str()).ok_or(SysError::ENOENT)?;
+    dir.create_dir(name).ok_or(SysError::EEXIST)?;
+    Ok(0)
+}
+
+pub fn sys_unlink(path: *const u8, flags: usize) -> SysResult {
+    sys_unlinkat(AT_FDCWD as usize, path, flags)
+}
+
+pub fn sys_unlinkat(dirfd: usize, path: *const u8, flags: usize) -> SysResult {
+    let path = path_at(dirfd, translated_str(current_user_token(), path)?)?;
+    if let Some((fs, names)) = resolve_mount(path.as_str()) {
+        fs.unlink(&names, flags & AT_REMOVEDIR != 0)?;
+        return Ok(0);
+    }
+    let (dir, name) = lookup_parent(path.as_str()).ok_or(SysError::ENOENT)?;
+    let inode = dir.find(name).ok_or(SysError::ENOENT)?;
+    match (inode.is_dir(), flags & AT_REMOVEDIR != 0) {
+        (true, false) => return Err(SysError::EISDIR),
+        (false, true) => return Err(SysError::ENOTDIR),
+        (true, true) if !inode.is_empty_dir() => return Err(SysError::ENOTEMPTY),
+        _ => {}
+    }
+    dir.unlink(name);
+    // the cache lets go of the file, which is freed with its last user
+    if inode.nlink() == 0 {
+        page_cache::invalidate(&inode);
+    }
+    Ok(0)
+}
+
+pub fn sys_link(old_path: *const u8, new_path: *const u8) -> SysResult {
+    sys_linkat(AT_FDCWD as usize, old_path, AT_FDCWD as usize, new_path)
+}
+
+/// Give the file at `old_path` a second name, directories cannot be linked.
+pub fn sys_linkat(
+    old_dirfd: usize,
+    old_path: *const u8,
+    new_dirfd: usize,
+    new_path: *const u8,
+) -> SysResult {
+    let token = current_user_token();
+    let old_path = path_at(old_dirfd, translated_str(token, old_path)?)?;
+    let new_path = path_at(new_dirfd, translated_str(token, new_path)?)?;
+    let target = lookup(old_path.as_str()).ok_or(SysError::ENOENT)?;
+    if target.is_dir() {
+        return Err(SysError::EPERM);
+    }
+    let (dir, name) = lookup_parent(new_path.as_str()).ok_or(SysError::ENOENT)?;
+    if !dir.link(name, &target) {
+        return Err(SysError::EEXIST);
+    }
+    Ok(0)
+}
+
+fn stat_of_file(file: &Arc<dyn File + Send + Sync>) -> Stat {
+    if let Some(stat) = file.stat() {
+        return stat;
+    }
+    match file.inode() {
+        Some(inode) => Stat::of_inode(&inode),
+        None => Stat::of_device(),
+    }
+}
+
+pub fn sys_fstat(fd: usize, st: *mut Stat) -> SysResult {
+    let stat = stat_of_file(&file_of(fd)?);
+    write_arg(st as usize, &stat)?;
+    Ok(0)
+}
+
+/// Symbolic links do not exist, so AT_SYMLINK_NOFOLLOW changes nothing.
+fn stat_at(dirfd: usize, path: *const u8, flags: usize) -> Result<Stat, SysError> {
+    let path = translated_str(current_user_token(), path)?;
+    if path.is_empty() {
+        if flags & AT_EMPTY_PATH == 0 {
+            return Err(SysError::ENOENT);
+        }
+        return Ok(stat_of_file(&file_of(dirfd)?));
+    }
+    let path = path_at(dirfd, path)?;
+    if let Some((fs, names)) = resolve_mount(path.as_str()) {
+        return fs.stat(&names);
+    }
+    match open_device(path.as_str(), OpenFlags::RDONLY) {
+        Some(_) => Ok(Stat::of_device()),
+        None => Ok(Stat::of_inode(
+            &lookup(path.as_str()).ok_or(SysError::ENOENT)?,
+        )),
+    }
+}
+
+pub fn sys_fstatat(dirfd: usize, path: *const u8, st: *mut Stat, flags: usize) -> SysResult {
+    let stat = stat_at(dirfd, path, flags)?;
+    write_arg(st as usize, &stat)?;
+    Ok(0)
+}
+
+/// bit of the statx mask kept for an extended `struct statx`
+const STATX_RESERVED: u32 = 0x8000_0000;
+
+/// Every field known is filled in whatever `mask` asks for, the mask
+/// of the result tells which ones are.
+pub fn sys_statx(
+    dirfd: usize,
+    path: *const u8,
+    flags: usize,
+    mask: u32,
+    stx: *mut Statx,
+) -> SysResult {
+    if mask & STATX_RESERVED != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let statx = Statx::of_stat(&stat_at(dirfd, path, flags)?);
+    write_arg(stx as usize, &statx)?;
+    Ok(0)
+}
+
+pub fn sys_mkfifo(path: *const u8) -> SysResult {
+    let token = current_user_token();
+    let path = translated_str(token, path)?;
+    if make_fifo(path.as_str()) {
+        Ok(0)
+    } else {
+        Err(SysError::EEXIST)
+    }
+}
+
+/// Mount the 9P share tagged `source` over the directory `target`, the
+/// only `fstype` is "9p". Flags and options are ignored.
+pub fn sys_mount(
+    source: *const u8,
+    target: *const u8,
+    fstype: *const u8,
+    _flags: usize,
+    _data: *const u8,
+) -> SysResult {
+    let token = current_user_token();
+    let source = translated_str(token, source)?;
+    let target = translated_str(token, target)?;
+    let fstype = translated_str(token, fstype)?;
+    mount(source.as_str(), target.as_str(), fstype.as_str())?;
+    Ok(0)
+}
+
+/// Flags are ignored, there is no forced or lazy unmount.
+pub fn sys_umount2(target: *const u8, _flags: usize) -> SysResult {
+    umount(translated_str(current_user_token(), target)?.as_str())?;
+    Ok(0)
+}
+
+/// tv_nsec asking utimensat for the current time
+const UTIME_NOW: usize = (1 << 30) - 1;
+/// tv_nsec asking utimensat to leave a timestamp alone
+const UTIME_OMIT: usize = (1 << 30) - 2;
+
+/// Set atime and mtime from `times`, both to now if it is null. A null
+/// `path` changes `dirfd` itself, the futimens form. Devices keep no
+/// timestamps and accept anything.
+pub fn sys_utimensat(
+    dirfd: usize,
+    path: *const u8,
+    times: *const TimeSpec,
+    _flags: usize,
+) -> SysResult {
+    let token = current_user_token();
+    let inode = if path.is_null() {
+        file_of(dirfd)?.inode()
+    } else {
+        let path = path_at(dirfd, translated_str(token, path)?)?;
+        match open_device(path.as_str(), OpenFlags::RDONLY) {
+            Some(_) => None,
+            None => Some(lookup(path.as_str()).ok_or(SysError::ENOENT)?),
+        }
+    };
+    let now = realtime_ns();
+    let time = |time: TimeSpec| match time.tv_nsec {
+        UTIME_NOW => Ok(Some(now)),
+        UTIME_OMIT => Ok(None),
+        nsec if nsec < 1_000_000_000 => Ok(Some(time.to_ns())),
+        _ => Err(SysError::EINVAL),
+    };
+    let (atime, mtime) = if times.is_null() {
+        (Some(now), Some(now))
+    } else {
+        (
+            time(*translated_ref(token, times)?)?,
+            time(*translated_ref(token, unsafe { times.add(1) })?)?,
+        )
+    };
+    if let Some(inode) = inode {
+        inode.set_times(atime, mtime);
+    }
+    Ok(0)
+}
+
+pub fn sys_close(fd: usize) -> SysResult {
     let process = current_process();
     let mut inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
-        return -1;
+        return Err(SysError::EBADF);
     }
     if inner.fd_table[fd].is_none() {
-        return -1;
+        return Err(SysError::EBADF);
     }
     inner.fd_table[fd].take();
-    0
+    Ok(0)
 }
 
-pub fn sys_pipe(pipe: *mut usize) -> isize {
+/// Install both ends of a new pipe, returns (read_fd, write_fd).
+pub fn pipe_fds(capacity: usize) -> (usize, usize) {
     let process = current_process();
-    let token = current_user_token();
     let mut inner = process.inner_exclusive_access();
-    let (pipe_read, pipe_write) = make_pipe();
+    let (pipe_read, pipe_write) = make_pipe(capacity);
     let read_fd = inner.alloc_fd();
     inner.fd_table[read_fd] = Some(pipe_read);
     let write_fd = inner.alloc_fd();
     inner.fd_table[write_fd] = Some(pipe_write);
-    *translated_refmut(token, pipe) = read_fd;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
-    0
+    (read_fd, write_fd)
+}
+
+pub fn sys_pipe(pipe: *mut usize, capacity: usize) -> SysResult {
+    let token = current_user_token();
+    let (read_fd, write_fd) = pipe_fds(capacity);
+    // without an array the ends are returned as a pair
+    if pipe.is_null() {
+        set_second_result(write_fd);
+        return Ok(read_fd);
+    }
+    *translated_refmut(token, pipe)? = read_fd;
+    *translated_refmut(token, unsafe { pipe.add(1) })? = write_fd;
+    Ok(0)
 }
 
-pub fn sys_dup(fd: usize) -> isize {
+pub fn sys_openpty(fds: *mut usize) -> SysResult {
+    let process = current_process();
+    let token = current_user_token();
+    let mut inner = process.inner_exclusive_access();
+    let (master, slave) = make_pty();
+    let master_fd = inner.alloc_fd();
+    inner.fd_table[master_fd] = Some(master);
+    let slave_fd = inner.alloc_fd();
+    inner.fd_table[slave_fd] = Some(slave);
+    drop(inner);
+    *translated_refmut(token, fds)? = master_fd;
+    *translated_refmut(token, unsafe { fds.add(1) })? = slave_fd;
+    Ok(0)
+}
+
+pub fn sys_dup(fd: usize) -> SysResult {
     let process = current_process();
     let mut inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
-        return -1;
+        return Err(SysError::EBADF);
     }
     if inner.fd_table[fd].is_none() {
-        return -1;
+        return Err(SysError::EBADF);
     }
     let new_fd = inner.alloc_fd();
     inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
-    new_fd as isize
+    Ok(new_fd)
+}
+
+/// fds from here on are refused by sys_dup2, poll and select
+pub const FD_LIMIT: usize = 1024;
+
+/// Make `new_fd` refer to the file of `old_fd`, closing what it had.
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = inner
+        .fd_table
+        .get(old_fd)
+        .cloned()
+        .flatten()
+        .ok_or(SysError::EBADF)?;
+    if new_fd >= FD_LIMIT {
+        return Err(SysError::EBADF);
+    }
+    if inner.fd_table.len() <= new_fd {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    inner.fd_table[new_fd] = Some(file);
+    Ok(new_fd)
+}
+
+const F_DUPFD: usize = 0;
+pub const F_GETFL: usize = 3;
+pub const F_SETFL: usize = 4;
+const F_DUPFD_CLOEXEC: usize = 1030;
+
+/// F_DUPFD takes the lowest free fd from `arg` on. F_GETFL tells the
+/// access mode with APPEND and NONBLOCK, F_SETFL changes only those two,
+/// for every fd sharing the file.
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    let file = file_of(fd)?;
+    match cmd {
+        // there is no close-on-exec, so both are the same
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            if arg >= FD_LIMIT {
+                return Err(SysError::EINVAL);
+            }
+            let process = current_process();
+            let mut inner = process.inner_exclusive_access();
+            let new_fd = inner.alloc_fd_from(arg);
+            inner.fd_table[new_fd] = Some(file);
+            Ok(new_fd)
+        }
+        F_GETFL => {
+            let access = match (file.readable(), file.writable()) {
+                (true, true) => OpenFlags::RDWR,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDONLY,
+            };
+            let status = file
+                .status()
+                .map_or(OpenFlags::empty(), |status| status.get());
+            Ok((access | status).bits() as usize)
+        }
+        // files without status flags ignore them
+        F_SETFL => {
+            if let Some(status) = file.status() {
+                status.set(OpenFlags::from_bits_truncate(arg as u32));
+            }
+            Ok(0)
+        }
+        _ => Err(SysError::EINVAL),
+    }
+}
+
+fn copy_to_user(token: usize, dst: *mut u8, bytes: &[u8]) -> Result<(), SysError> {
+    let mut copied = 0;
+    for slice in translated_byte_buffer_mut(token, dst, bytes.len())? {
+        slice.copy_from_slice(&bytes[copied..copied + slice.len()]);
+        copied += slice.len();
+    }
+    Ok(())
+}
+
+/// The native record: the bare name followed by a NUL.
+fn name_record(dirent: &Dirent) -> Vec<u8> {
+    let mut record = Vec::with_capacity(dirent.name.len() + 1);
+    record.extend_from_slice(dirent.name.as_bytes());
+    record.push(0);
+    record
+}
+
+/// d_ino, d_off, d_reclen and d_type in front of the name
+const DIRENT64_HEADER: usize = 19;
+/// Records of getdents64 keep 8-byte alignment.
+const fn dirent64_len(name_len: usize) -> usize {
+    (DIRENT64_HEADER + name_len + 1 + 7) & !7
+}
+
+/// `struct linux_dirent64`, the name NUL-terminated and padded.
+fn dirent64_record(dirent: &Dirent) -> Vec<u8> {
+    let len = dirent64_len(dirent.name.len());
+    let mut record = Vec::with_capacity(len);
+    record.extend_from_slice(&dirent.ino.to_ne_bytes());
+    record.extend_from_slice(&(dirent.next_offset as i64).to_ne_bytes());
+    record.extend_from_slice(&(len as u16).to_ne_bytes());
+    record.push(dirent.kind);
+    record.extend_from_slice(dirent.name.as_bytes());
+    record.resize(len, 0);
+    record
+}
+
+fn getdents(fd: usize, buf: *mut u8, len: usize, record: fn(&Dirent) -> Vec<u8>) -> SysResult {
+    let file = file_of(fd)?;
+    let records = file.read_dir(len, record).ok_or(SysError::ENOTDIR)?;
+    copy_to_user(current_user_token(), buf, &records)?;
+    Ok(records.len())
+}
+
+/// Fill `buf` with the names of the next entries of directory `fd`, each
+/// followed by a NUL. Returns the bytes written, 0 after the last entry.
+pub fn sys_getdents(fd: usize, buf: *mut u8, len: usize) -> SysResult {
+    // any name has to fit
+    if len < DIRENT_NAME_MAX {
+        return Err(SysError::EINVAL);
+    }
+    getdents(fd, buf, len, name_record)
+}
+
+/// Like `sys_getdents` with `struct linux_dirent64` records, which tell
+/// the inode, type and offset of each entry as well.
+pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> SysResult {
+    if len < dirent64_len(DIRENT_NAME_MAX - 1) {
+        return Err(SysError::EINVAL);
+    }
+    getdents(fd, buf, len, dirent64_record)
+}
+
+/// There is no chdir, everyone works in the root.
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> SysResult {
+    const CWD: &[u8] = b"/\0";
+    if len < CWD.len() {
+        return Err(SysError::EINVAL);
+    }
+    copy_to_user(current_user_token(), buf, CWD)?;
+    Ok(CWD.len())
+}
+
+pub fn sys_ioctl(fd: usize, cmd: u32, arg: usize) -> SysResult {
+    file_of(fd)?.ioctl(cmd, arg)
 }
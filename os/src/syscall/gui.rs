@@ -1,10 +1,16 @@
+use super::SysResult;
+use crate::drivers::gpu::fbcon;
 use crate::drivers::GPU_DEVICE;
+use crate::fs::Surface;
 use crate::mm::{MapArea, MapPermission, MapType, PhysAddr, VirtAddr};
 use crate::task::current_process;
+use alloc::sync::Arc;
 
 const FB_VADDR: usize = 0x10000000;
 
-pub fn sys_framebuffer() -> isize {
+pub fn sys_framebuffer() -> SysResult {
+    // the program draws on its own from now on
+    fbcon::release();
     let fb = GPU_DEVICE.get_framebuffer();
     let len = fb.len();
     // println!("[kernel] FrameBuffer: addr 0x{:X}, len {}", fb.as_ptr() as usize , len);
@@ -25,10 +31,21 @@ pub fn sys_framebuffer() -> isize {
         ),
         None,
     );
-    FB_VADDR as isize
+    Ok(FB_VADDR)
 }
 
-pub fn sys_framebuffer_flush() -> isize {
+pub fn sys_framebuffer_flush() -> SysResult {
     GPU_DEVICE.flush();
-    0
+    Ok(0)
+}
+
+/// A new fd for a `width` x `height` surface of the compositor with its
+/// corner at (`x`, `y`) of the screen, see fs::surface.
+pub fn sys_surface_create(x: isize, y: isize, width: usize, height: usize) -> SysResult {
+    let surface = Surface::new(x, y, width, height)?;
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(Arc::new(surface));
+    Ok(fd)
 }
@@ -0,0 +1,404 @@
+//! The RISC-V Linux syscall numbering, translated onto the native handlers.
+//!
+//! Many native numbers are already the linux ones, but several take
+//! different arguments (openat has a dirfd, pipe2 fills an int array, kill
+//! takes a signal number) and some do not exist at all. A process uses this
+//! table unless its ELF carries the `.rcore.abi` section user_lib adds.
+
+use super::*;
+use crate::fs::OpenFlags;
+use crate::mm::{translated_ref, translated_refmut};
+use crate::task::{
+    current_user_token, deliverable_signals_of_current, suspend_current_and_run_next, SignalFlags,
+};
+use crate::timer::{realtime_ns, TimeVal};
+
+/// Set by every binary linked against user_lib.
+const NATIVE_ABI_SECTION: &str = ".rcore.abi";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallAbi {
+    Native,
+    Linux,
+}
+
+impl SyscallAbi {
+    pub fn of_elf(elf_data: &[u8]) -> Self {
+        match xmas_elf::ElfFile::new(elf_data) {
+            Ok(elf) if elf.find_section_by_name(NATIVE_ABI_SECTION).is_none() => Self::Linux,
+            _ => Self::Native,
+        }
+    }
+}
+
+const LINUX_DUP: usize = 23;
+const LINUX_DUP3: usize = 24;
+const LINUX_IOCTL: usize = 29;
+const LINUX_MKDIRAT: usize = 34;
+const LINUX_UNLINKAT: usize = 35;
+const LINUX_LINKAT: usize = 37;
+const LINUX_OPENAT: usize = 56;
+const LINUX_PIPE2: usize = 59;
+const LINUX_READV: usize = 65;
+const LINUX_WRITEV: usize = 66;
+const LINUX_PSELECT6: usize = 72;
+const LINUX_PPOLL: usize = 73;
+const LINUX_NANOSLEEP: usize = 101;
+const LINUX_RT_SIGRETURN: usize = 139;
+const LINUX_GETTIMEOFDAY: usize = 169;
+const LINUX_GETUID: usize = 174;
+const LINUX_GETEUID: usize = 175;
+const LINUX_GETGID: usize = 176;
+const LINUX_GETEGID: usize = 177;
+const LINUX_GETTID: usize = 178;
+const LINUX_CLONE: usize = 220;
+const LINUX_EXECVE: usize = 221;
+const LINUX_WAIT4: usize = 260;
+
+const O_CREAT: u32 = 0o100;
+const O_TRUNC: u32 = 0o1000;
+const O_APPEND: u32 = 0o2000;
+const O_NONBLOCK: u32 = 0o4000;
+const O_ACCMODE: u32 = 0o3;
+
+/// clone with only `SIGCHLD` as the exit signal is a plain fork
+const SIGCHLD: usize = 17;
+const CLONE_VM: usize = 0x100;
+const CLONE_FS: usize = 0x200;
+const CLONE_FILES: usize = 0x400;
+const CLONE_SIGHAND: usize = 0x800;
+const CLONE_THREAD: usize = 0x1_0000;
+const CLONE_SYSVSEM: usize = 0x4_0000;
+const CLONE_SETTLS: usize = 0x8_0000;
+const CLONE_PARENT_SETTID: usize = 0x10_0000;
+const CLONE_CHILD_CLEARTID: usize = 0x20_0000;
+const CLONE_DETACHED: usize = 0x40_0000;
+const CLONE_CHILD_SETTID: usize = 0x100_0000;
+/// what threads of a process share anyway, a thread must ask for all of it
+const CLONE_THREAD_FLAGS: usize = CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD;
+const CLONE_THREAD_OPTIONAL: usize = CLONE_SYSVSEM
+    | CLONE_SETTLS
+    | CLONE_PARENT_SETTID
+    | CLONE_CHILD_CLEARTID
+    | CLONE_DETACHED
+    | CLONE_CHILD_SETTID;
+
+const WNOHANG: usize = 1;
+
+/// size of the kernel sigset_t, one bit per signal
+const SIGSET_SIZE: usize = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IoVec {
+    base: usize,
+    len: usize,
+}
+
+/// The last argument of pselect6, a mask and its size.
+#[repr(C)]
+struct SigsetArg {
+    set: *const u64,
+    size: usize,
+}
+
+fn open_flags(flags: u32) -> OpenFlags {
+    let mut open_flags = OpenFlags::from_bits_truncate(flags & O_ACCMODE);
+    if flags & O_CREAT != 0 {
+        open_flags |= OpenFlags::CREATE;
+    }
+    if flags & O_TRUNC != 0 {
+        open_flags |= OpenFlags::TRUNC;
+    }
+    if flags & O_APPEND != 0 {
+        open_flags |= OpenFlags::APPEND;
+    }
+    if flags & O_NONBLOCK != 0 {
+        open_flags |= OpenFlags::NONBLOCK;
+    }
+    open_flags
+}
+
+/// The access mode and status flags F_GETFL reports, back in linux bits.
+fn linux_status_flags(flags: OpenFlags) -> u32 {
+    let mut linux_flags = flags.bits() & O_ACCMODE;
+    if flags.contains(OpenFlags::APPEND) {
+        linux_flags |= O_APPEND;
+    }
+    if flags.contains(OpenFlags::NONBLOCK) {
+        linux_flags |= O_NONBLOCK;
+    }
+    linux_flags
+}
+
+/// Linux signal numbers are bit positions of the native flags.
+fn signal_flags(signum: usize) -> Result<u32, SysError> {
+    match signum {
+        0 => Ok(0),
+        signum => SignalFlags::from_signum(signum)
+            .map(|signal| signal.bits())
+            .ok_or(SysError::EINVAL),
+    }
+}
+
+/// Bit `n - 1` of a linux sigset stands for signal `n`, None if null.
+fn sigset(set: *const u64, size: usize) -> Result<Option<SignalFlags>, SysError> {
+    if set.is_null() {
+        return Ok(None);
+    }
+    if size != SIGSET_SIZE {
+        return Err(SysError::EINVAL);
+    }
+    let set = *translated_ref(current_user_token(), set)?;
+    Ok(Some(SignalFlags::from_bits_truncate((set << 1) as u32)))
+}
+
+fn pselect6_sigset(arg: *const SigsetArg) -> Result<Option<SignalFlags>, SysError> {
+    if arg.is_null() {
+        return Ok(None);
+    }
+    let arg = translated_ref(current_user_token(), arg)?;
+    sigset(arg.set, arg.size)
+}
+
+/// Native exit codes are raw, linux wants them in the second byte and a
+/// killing signal in the first.
+fn wait_status(status: i32) -> i32 {
+    if status & 0xff == 0x7f || status == 0xffff {
+        status
+    } else if status < 0 && status > -64 {
+        -status
+    } else {
+        (status & 0xff) << 8
+    }
+}
+
+fn sys_pipe2(fds: *mut i32) -> SysResult {
+    let (read_fd, write_fd) = pipe_fds(0);
+    let token = current_user_token();
+    *translated_refmut(token, fds)? = read_fd as i32;
+    *translated_refmut(token, unsafe { fds.add(1) })? = write_fd as i32;
+    Ok(0)
+}
+
+/// Stops at the first short transfer, like a single read or write would,
+/// or when a nonblocking file runs dry after the first vectors.
+fn sys_readv(fd: usize, iov: *const IoVec, iovcnt: usize) -> SysResult {
+    let token = current_user_token();
+    let mut total = 0;
+    for i in 0..iovcnt {
+        let iov = *translated_ref(token, unsafe { iov.add(i) })?;
+        let len = match sys_read(fd, iov.base as *const u8, iov.len) {
+            // what went through before still counts
+            Err(SysError::EAGAIN) if total > 0 => break,
+            result => result?,
+        };
+        total += len;
+        if len < iov.len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+fn sys_writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> SysResult {
+    let token = current_user_token();
+    let mut total = 0;
+    for i in 0..iovcnt {
+        let iov = *translated_ref(token, unsafe { iov.add(i) })?;
+        if iov.len == 0 {
+            continue;
+        }
+        let len = match sys_write(fd, iov.base as *const u8, iov.len) {
+            // what went through before still counts
+            Err(SysError::EAGAIN) if total > 0 => break,
+            result => result?,
+        };
+        total += len;
+        if len < iov.len {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Linux thread ids share one space with pids: the main thread has the pid,
+/// the others put their index above it.
+fn linux_tid_of(pid: usize, tid: usize) -> usize {
+    if tid == 0 {
+        pid
+    } else {
+        tid << 16 | pid
+    }
+}
+
+fn linux_tid() -> SysResult {
+    Ok(linux_tid_of(sys_getpid()?, sys_gettid()?))
+}
+
+fn sys_gettimeofday(tv: *mut TimeVal) -> SysResult {
+    *translated_refmut(current_user_token(), tv)? = TimeVal::from_ns(realtime_ns());
+    Ok(0)
+}
+
+/// Either a plain fork or a thread the way pthread_create makes one, which
+/// returns from clone on `stack`.
+fn sys_clone(flags: usize, stack: usize, ptid: *mut u32, tls: usize, ctid: *mut u32) -> SysResult {
+    if flags == SIGCHLD && stack == 0 {
+        return sys_fork();
+    }
+    if flags & CLONE_THREAD_FLAGS != CLONE_THREAD_FLAGS
+        || flags & !(CLONE_THREAD_FLAGS | CLONE_THREAD_OPTIONAL) != 0
+    {
+        return Err(SysError::ENOSYS);
+    }
+    let token = current_user_token();
+    // the ids go where asked, which has to be known good before the thread
+    // exists
+    let ptid_slot = if flags & CLONE_PARENT_SETTID != 0 {
+        Some(translated_refmut(token, ptid)?)
+    } else {
+        None
+    };
+    let ctid_slot = if flags & CLONE_CHILD_SETTID != 0 {
+        Some(translated_refmut(token, ctid)?)
+    } else {
+        None
+    };
+    let pid = sys_getpid()?;
+    let parent_cx = *current_trap_cx();
+    let tid = spawn_thread(|task_inner| {
+        let trap_cx = task_inner.get_trap_cx();
+        trap_cx.x = parent_cx.x;
+        trap_cx.sepc = parent_cx.sepc;
+        trap_cx.x[10] = 0;
+        trap_cx.set_sp(stack);
+        if flags & CLONE_SETTLS != 0 {
+            trap_cx.x[4] = tls;
+        }
+        // the ids are in place before the thread runs
+        let linux_tid = linux_tid_of(pid, task_inner.res.as_ref().unwrap().tid) as u32;
+        for slot in [ptid_slot, ctid_slot].into_iter().flatten() {
+            *slot = linux_tid;
+        }
+        if flags & CLONE_CHILD_CLEARTID != 0 {
+            task_inner.clear_child_tid = ctid as usize;
+        }
+    });
+    Ok(linux_tid_of(pid, tid))
+}
+
+/// wait4 blocks in the kernel, native waitpid leaves the retry to user_lib.
+fn sys_wait4(pid: isize, status: *mut i32, options: usize) -> SysResult {
+    let (found_pid, native_status) = loop {
+        match wait_child(pid, options) {
+            Err(SysError::EAGAIN) if options & WNOHANG != 0 => return Ok(0),
+            Err(SysError::EAGAIN) if !deliverable_signals_of_current().is_empty() => {
+                return Err(SysError::EINTR)
+            }
+            Err(SysError::EAGAIN) => suspend_current_and_run_next(),
+            result => break result?,
+        }
+    };
+    if !status.is_null() {
+        *translated_refmut(current_user_token(), status)? = wait_status(native_status);
+    }
+    Ok(found_pid)
+}
+
+/// Everything not listed here is either numbered and called like the
+/// native syscall or unsupported.
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> SysResult {
+    match syscall_id {
+        LINUX_DUP => sys_dup(args[0]),
+        LINUX_DUP3 if args[0] == args[1] => Err(SysError::EINVAL),
+        LINUX_DUP3 => sys_dup2(args[0], args[1]),
+        LINUX_IOCTL => sys_ioctl(args[0], args[1] as u32, args[2]),
+        SYSCALL_FCNTL => match args[1] {
+            F_GETFL => {
+                let flags = sys_fcntl(args[0], F_GETFL, 0)? as u32;
+                Ok(linux_status_flags(OpenFlags::from_bits_truncate(flags)) as usize)
+            }
+            F_SETFL => sys_fcntl(args[0], F_SETFL, open_flags(args[2] as u32).bits() as usize),
+            _ => sys_fcntl(args[0], args[1], args[2]),
+        },
+        LINUX_MKDIRAT => sys_mkdirat(args[0], args[1] as *const u8),
+        LINUX_UNLINKAT => sys_unlinkat(args[0], args[1] as *const u8, args[2]),
+        // linkat flags only concern symbolic links
+        LINUX_LINKAT => sys_linkat(args[0], args[1] as _, args[2], args[3] as _),
+        LINUX_OPENAT => sys_openat(
+            args[0],
+            args[1] as *const u8,
+            open_flags(args[2] as u32).bits(),
+        ),
+        LINUX_PIPE2 => sys_pipe2(args[0] as *mut i32),
+        LINUX_READV => sys_readv(args[0], args[1] as *const IoVec, args[2]),
+        LINUX_WRITEV => sys_writev(args[0], args[1] as *const IoVec, args[2]),
+        LINUX_PSELECT6 => pselect(
+            args[0],
+            args[1] as *mut usize,
+            args[2] as *mut usize,
+            args[3] as *mut usize,
+            args[4] as *const TimeSpec,
+            pselect6_sigset(args[5] as *const SigsetArg)?,
+        ),
+        LINUX_PPOLL => ppoll(
+            args[0] as *mut PollFd,
+            args[1],
+            args[2] as *const TimeSpec,
+            sigset(args[3] as *const u64, args[4])?,
+        ),
+        SYSCALL_SET_TID_ADDRESS => {
+            sys_set_tid_address(args[0])?;
+            linux_tid()
+        }
+        LINUX_NANOSLEEP => sys_nanosleep(args[0] as *const TimeSpec, args[1] as *mut TimeSpec),
+        LINUX_RT_SIGRETURN => sys_sigreturn(),
+        LINUX_GETTIMEOFDAY => sys_gettimeofday(args[0] as *mut TimeVal),
+        // there is a single user, root
+        LINUX_GETUID | LINUX_GETEUID | LINUX_GETGID | LINUX_GETEGID => Ok(0),
+        LINUX_GETTID => linux_tid(),
+        LINUX_CLONE => sys_clone(
+            args[0],
+            args[1],
+            args[2] as *mut u32,
+            args[3],
+            args[4] as *mut u32,
+        ),
+        LINUX_EXECVE => sys_exec(args[0] as *const u8, args[1] as *const usize),
+        LINUX_WAIT4 => sys_wait4(args[0] as isize, args[1] as *mut i32, args[2]),
+        SYSCALL_KILL => sys_kill(args[0] as isize, signal_flags(args[1])?),
+        SYSCALL_GETCWD
+        | SYSCALL_CLOSE
+        | SYSCALL_GETDENTS64
+        | SYSCALL_READ
+        | SYSCALL_WRITE
+        | SYSCALL_SENDFILE
+        | SYSCALL_FSTATAT
+        | SYSCALL_FSTAT
+        | SYSCALL_UTIMENSAT
+        | SYSCALL_EXIT
+        | SYSCALL_EXIT_GROUP
+        | SYSCALL_CLOCK_SETTIME
+        | SYSCALL_CLOCK_GETTIME
+        | SYSCALL_YIELD
+        | SYSCALL_SETPGID
+        | SYSCALL_GETPGID
+        | SYSCALL_PRCTL
+        | SYSCALL_REBOOT
+        | SYSCALL_GETPID
+        | SYSCALL_GETPPID
+        | SYSCALL_BRK
+        | SYSCALL_MUNMAP
+        | SYSCALL_MREMAP
+        | SYSCALL_MMAP
+        | SYSCALL_MPROTECT
+        | SYSCALL_MSYNC
+        | SYSCALL_MADVISE
+        | SYSCALL_GETRANDOM
+        | SYSCALL_STATX
+        | SYSCALL_UMOUNT2
+        | SYSCALL_MOUNT => native_syscall(syscall_id, args),
+        _ => sys_unsupported(syscall_id),
+    }
+}
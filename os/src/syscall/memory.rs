@@ -0,0 +1,301 @@
+use super::{SysError, SysResult};
+use crate::config::{BRK_BASE, MMAP_BASE, PAGE_SIZE};
+use crate::mm::{page_cache, FileBacking, MapPermission, VirtAddr, VirtPageNum};
+use crate::task::current_process;
+use alloc::vec::Vec;
+use bitflags::*;
+
+bitflags! {
+    pub struct Prot: usize {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags! {
+    pub struct MapFlags: usize {
+        const SHARED = 0x01;
+        const PRIVATE = 0x02;
+        const ANONYMOUS = 0x20;
+    }
+}
+
+const MS_ASYNC: usize = 1;
+const MS_INVALIDATE: usize = 2;
+const MS_SYNC: usize = 4;
+
+/// Permission of pages with protection `prot`, writable and executable at
+/// the same time is refused.
+fn prot_to_perm(prot: usize) -> Result<MapPermission, SysError> {
+    let prot = Prot::from_bits(prot).ok_or(SysError::EINVAL)?;
+    if prot.contains(Prot::WRITE | Prot::EXEC) {
+        return Err(SysError::EACCES);
+    }
+    let mut perm = MapPermission::empty();
+    if prot.contains(Prot::READ) {
+        perm |= MapPermission::R;
+    }
+    if prot.contains(Prot::WRITE) {
+        perm |= MapPermission::W | MapPermission::R;
+    }
+    if prot.contains(Prot::EXEC) {
+        perm |= MapPermission::X;
+    }
+    Ok(perm)
+}
+
+/// Map `len` bytes of anonymous memory or of the file `fd` from `offset`,
+/// at an address of the kernel's choice. A shared file mapping maps the
+/// pages of the page cache, written back by `msync`, `munmap` and on exit;
+/// a private one maps them read-only and copies a page on its first store.
+pub fn sys_mmap(
+    _addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> SysResult {
+    let perm = prot_to_perm(prot)?;
+    let flags = MapFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
+    if len == 0
+        || offset % PAGE_SIZE != 0
+        || flags.contains(MapFlags::SHARED) == flags.contains(MapFlags::PRIVATE)
+    {
+        return Err(SysError::EINVAL);
+    }
+    let pages = len.checked_add(PAGE_SIZE - 1).ok_or(SysError::ENOMEM)? / PAGE_SIZE;
+    let process = current_process();
+    if !flags.contains(MapFlags::ANONYMOUS) {
+        let file = process
+            .inner_exclusive_access()
+            .fd_table
+            .get(fd)
+            .cloned()
+            .flatten()
+            .ok_or(SysError::EBADF)?;
+        let inode = file.inode().ok_or(SysError::ENODEV)?;
+        let shared = flags.contains(MapFlags::SHARED);
+        if !file.readable() || (shared && perm.contains(MapPermission::W) && !file.writable()) {
+            return Err(SysError::EACCES);
+        }
+        let backing = FileBacking {
+            inode: inode.clone(),
+            offset,
+            shared,
+        };
+        let frames =
+            page_cache::pages(&inode, offset / PAGE_SIZE, pages).ok_or(SysError::ENOMEM)?;
+        let mut inner = process.inner_exclusive_access();
+        let start_vpn = inner.memory_set.find_free_range(pages);
+        inner
+            .memory_set
+            .insert_file_mapping(start_vpn, perm, frames, backing);
+        return Ok(VirtAddr::from(start_vpn).into());
+    }
+    let mut inner = process.inner_exclusive_access();
+    let start_vpn = inner.memory_set.find_free_range(pages);
+    inner.memory_set.insert_mapping(start_vpn, pages, perm);
+    Ok(VirtAddr::from(start_vpn).into())
+}
+
+/// Remove the mappings of `[start, start + len)`, writing dirty pages of
+/// shared file mappings back first.
+pub fn sys_munmap(start: usize, len: usize) -> SysResult {
+    if start % PAGE_SIZE != 0 || len == 0 {
+        return Err(SysError::EINVAL);
+    }
+    let end = start.checked_add(len).ok_or(SysError::EINVAL)?;
+    let process = current_process();
+    let dirty = process
+        .inner_exclusive_access()
+        .memory_set
+        .unmap_range(VirtAddr::from(start).floor(), VirtAddr::from(end).ceil())
+        .ok_or(SysError::EINVAL)?;
+    // the disk is waited for without the PCB
+    for page in dirty {
+        page.write_back();
+    }
+    Ok(0)
+}
+
+/// Move the end of the heap to `addr`. Like linux, the result is the break
+/// in effect afterwards, so 0 or an address out of range only query it.
+/// Growing over pages mapped otherwise fails with ENOMEM.
+pub fn sys_brk(addr: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !(BRK_BASE..MMAP_BASE).contains(&addr) {
+        return Ok(inner.brk);
+    }
+    let old_end = VirtAddr::from(inner.brk).ceil();
+    let new_end = VirtAddr::from(addr).ceil();
+    if new_end > old_end {
+        if !inner.memory_set.range_free(old_end, new_end) {
+            return Err(SysError::ENOMEM);
+        }
+        let perm = MapPermission::R | MapPermission::W;
+        inner
+            .memory_set
+            .insert_mapping(old_end, new_end.0 - old_end.0, perm);
+    } else if new_end < old_end {
+        // anonymous pages, nothing to write back
+        inner.memory_set.unmap_range(new_end, old_end);
+    }
+    inner.brk = addr;
+    Ok(addr)
+}
+
+const MREMAP_MAYMOVE: usize = 1;
+
+/// Resize the mapping at `old_start`, which must lie in a single one. It
+/// grows in place if the pages after it are free, otherwise with
+/// MREMAP_MAYMOVE its frames are moved to a free range without copying.
+pub fn sys_mremap(old_start: usize, old_len: usize, new_len: usize, flags: usize) -> SysResult {
+    if old_start % PAGE_SIZE != 0 || old_len == 0 || new_len == 0 || flags & !MREMAP_MAYMOVE != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let old_end = old_start.checked_add(old_len).ok_or(SysError::EINVAL)?;
+    let new_end = old_start.checked_add(new_len).ok_or(SysError::ENOMEM)?;
+    let start_vpn = VirtAddr::from(old_start).floor();
+    let old_end_vpn = VirtAddr::from(old_end).ceil();
+    let new_end_vpn = VirtAddr::from(new_end).ceil();
+    let old_pages = old_end_vpn.0 - start_vpn.0;
+    let new_pages = new_end_vpn.0 - start_vpn.0;
+    let process = current_process();
+    let file = process
+        .inner_exclusive_access()
+        .memory_set
+        .mapping_at(start_vpn, old_end_vpn)
+        .ok_or(SysError::EFAULT)?;
+    if new_pages <= old_pages {
+        let dirty = process
+            .inner_exclusive_access()
+            .memory_set
+            .unmap_range(new_end_vpn, old_end_vpn)
+            .unwrap_or_default();
+        for page in dirty {
+            page.write_back();
+        }
+        return Ok(old_start);
+    }
+    // the added pages continue the file, read without the PCB
+    let frames = match file {
+        Some(file) => page_cache::pages(
+            &file.inode,
+            file.offset / PAGE_SIZE + old_pages,
+            new_pages - old_pages,
+        )
+        .ok_or(SysError::ENOMEM)?,
+        None => Vec::new(),
+    };
+    let mut inner = process.inner_exclusive_access();
+    let memory_set = &mut inner.memory_set;
+    if memory_set.mapping_at(start_vpn, old_end_vpn).is_none() {
+        // another thread unmapped it meanwhile
+        return Err(SysError::EFAULT);
+    }
+    if memory_set.range_free(old_end_vpn, new_end_vpn) {
+        if frames.is_empty() {
+            memory_set.grow_mapping(start_vpn, old_end_vpn, new_end_vpn);
+        } else {
+            memory_set.grow_file_mapping(start_vpn, old_end_vpn, frames);
+        }
+        return Ok(old_start);
+    }
+    if flags & MREMAP_MAYMOVE == 0 {
+        return Err(SysError::ENOMEM);
+    }
+    let new_start = memory_set.find_free_range(new_pages);
+    memory_set.move_mapping(start_vpn, old_end_vpn, new_start);
+    let moved_end = VirtPageNum(new_start.0 + old_pages);
+    let grown_end = VirtPageNum(new_start.0 + new_pages);
+    if frames.is_empty() {
+        memory_set.grow_mapping(new_start, moved_end, grown_end);
+    } else {
+        memory_set.grow_file_mapping(new_start, moved_end, frames);
+    }
+    Ok(VirtAddr::from(new_start).into())
+}
+
+/// Write the dirty pages of shared file mappings in `[start, start + len)`
+/// back to their files. The block cache is synced by every write, so
+/// MS_ASYNC completes the write back as MS_SYNC does.
+pub fn sys_msync(start: usize, len: usize, flags: usize) -> SysResult {
+    if start % PAGE_SIZE != 0
+        || flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+        || flags & (MS_ASYNC | MS_SYNC) == MS_ASYNC | MS_SYNC
+    {
+        return Err(SysError::EINVAL);
+    }
+    let end = start.checked_add(len).ok_or(SysError::ENOMEM)?;
+    let (start_vpn, end_vpn) = (VirtAddr::from(start).floor(), VirtAddr::from(end).ceil());
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !inner.memory_set.covered_by_user_areas(start_vpn, end_vpn) {
+        return Err(SysError::ENOMEM);
+    }
+    let dirty = inner.memory_set.take_dirty_pages(start_vpn, end_vpn);
+    drop(inner);
+    for page in dirty {
+        page.write_back();
+    }
+    Ok(0)
+}
+
+/// Change the protection of mapped user pages. Writable and executable at
+/// the same time is refused.
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> SysResult {
+    let perm = prot_to_perm(prot)?;
+    if start % PAGE_SIZE != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let end = start.checked_add(len).ok_or(SysError::ENOMEM)?;
+    if len == 0 {
+        return Ok(0);
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.memory_set.protect(
+        VirtAddr::from(start).floor(),
+        VirtAddr::from(end).ceil(),
+        perm,
+    ) {
+        Ok(0)
+    } else {
+        Err(SysError::ENOMEM)
+    }
+}
+
+const MADV_NORMAL: usize = 0;
+const MADV_RANDOM: usize = 1;
+const MADV_SEQUENTIAL: usize = 2;
+const MADV_WILLNEED: usize = 3;
+const MADV_DONTNEED: usize = 4;
+
+/// Advise the kernel about the use of user pages: DONTNEED frees their frames
+/// so that they read as zero on the next touch, WILLNEED faults in dropped
+/// ones now. The access pattern hints are accepted and ignored.
+pub fn sys_madvise(start: usize, len: usize, advice: usize) -> SysResult {
+    if start % PAGE_SIZE != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let end = start.checked_add(len).ok_or(SysError::ENOMEM)?;
+    let start_vpn = VirtAddr::from(start).floor();
+    let end_vpn = VirtAddr::from(end).ceil();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let done = match advice {
+        MADV_NORMAL | MADV_RANDOM | MADV_SEQUENTIAL => return Ok(0),
+        _ if len == 0 => return Ok(0),
+        MADV_WILLNEED => inner.memory_set.populate(start_vpn, end_vpn),
+        MADV_DONTNEED => inner.memory_set.drop_pages(start_vpn, end_vpn),
+        _ => return Err(SysError::EINVAL),
+    };
+    if done {
+        Ok(0)
+    } else {
+        Err(SysError::ENOMEM)
+    }
+}
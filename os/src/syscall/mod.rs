@@ -1,21 +1,62 @@
+const SYSCALL_GETCWD: usize = 17;
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_FCNTL: usize = 25;
 const SYSCALL_CONNECT: usize = 29;
 const SYSCALL_LISTEN: usize = 30;
 const SYSCALL_ACCEPT: usize = 31;
+const SYSCALL_MKFIFO: usize = 33;
+const SYSCALL_MKDIR: usize = 34;
+const SYSCALL_UNLINK: usize = 35;
+const SYSCALL_LINK: usize = 37;
+const SYSCALL_UMOUNT2: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_GETDENTS64: usize = 61;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_SENDFILE: usize = 71;
+const SYSCALL_PSELECT: usize = 72;
+const SYSCALL_PPOLL: usize = 73;
+const SYSCALL_FSTATAT: usize = 79;
+const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_UTIMENSAT: usize = 88;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_SET_TID_ADDRESS: usize = 96;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_CLOCK_SETTIME: usize = 112;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_PTRACE: usize = 117;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGALTSTACK: usize = 132;
+const SYSCALL_SIGSUSPEND: usize = 133;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGWAIT: usize = 137;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_REBOOT: usize = 142;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_PRCTL: usize = 167;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_MREMAP: usize = 216;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MSYNC: usize = 227;
+const SYSCALL_MADVISE: usize = 233;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_GETRANDOM: usize = 278;
+const SYSCALL_STATX: usize = 291;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
@@ -30,48 +71,204 @@ const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
 const SYSCALL_FRAMEBUFFER: usize = 2000;
 const SYSCALL_FRAMEBUFFER_FLUSH: usize = 2001;
+const SYSCALL_SURFACE_CREATE: usize = 2002;
 const SYSCALL_EVENT_GET: usize = 3000;
 const SYSCALL_KEY_PRESSED: usize = 3001;
+const SYSCALL_IOCTL: usize = 4000;
+const SYSCALL_OPENPTY: usize = 4001;
+const SYSCALL_DUP2: usize = 4002;
+const SYSCALL_GETDENTS: usize = 4003;
+const SYSCALL_PROCESS_LIST: usize = 4004;
+const SYSCALL_OPENAT: usize = 4005;
+const SYSCALL_MKDIRAT: usize = 4006;
+const SYSCALL_UNLINKAT: usize = 4007;
+const SYSCALL_LINKAT: usize = 4008;
+const SYSCALL_NANOSLEEP: usize = 4009;
+const SYSCALL_UINTR_REGISTER: usize = 5000;
+const SYSCALL_UINTR_NOTIFY: usize = 5001;
+const SYSCALL_UINTR_RETURN: usize = 5002;
 
+mod errno;
 mod fs;
 mod gui;
 mod input;
+mod linux;
+mod memory;
 mod net;
+mod poll;
 mod process;
+mod ptrace;
 mod sync;
 mod thread;
+mod uintr;
 
+pub use errno::{SysError, SysResult};
 use fs::*;
 use gui::*;
 use input::*;
+pub use linux::SyscallAbi;
+use memory::*;
 use net::*;
+use poll::*;
 use process::*;
+use ptrace::*;
 use sync::*;
 use thread::*;
+use uintr::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+use crate::fs::Statx;
+use crate::sync::UPIntrFreeCell;
+use crate::task::{current_process, current_trap_cx};
+use crate::timer::{TimeSpec, TimeVal};
+use crate::trace::{trace, TracePoint};
+use alloc::collections::BTreeSet;
+use lazy_static::*;
+
+lazy_static! {
+    /// (pid, syscall id) pairs already reported as unsupported
+    static ref UNSUPPORTED_REPORTED: UPIntrFreeCell<BTreeSet<(usize, usize)>> =
+        unsafe { UPIntrFreeCell::new(BTreeSet::new()) };
+}
+
+fn sys_unsupported(syscall_id: usize) -> SysResult {
+    let pid = current_process().getpid();
+    if UNSUPPORTED_REPORTED
+        .exclusive_access()
+        .insert((pid, syscall_id))
+    {
+        println!(
+            "[kernel] pid {} called unsupported syscall {}",
+            pid, syscall_id
+        );
+    }
+    Err(SysError::ENOSYS)
+}
+
+/// Second result of a syscall returning a pair, handed back in a1.
+fn set_second_result(value: usize) {
+    current_trap_cx().x[11] = value;
+}
+
+/// Arguments come in a0-a5 and the id in a7. The result goes back in a0,
+/// which is wide enough for 64-bit values; syscalls returning a pair put the
+/// second half in a1 with `set_second_result`. Errors are returned as
+/// negative errno values.
+/// Binaries not built against user_lib are taken to use linux numbering.
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    trace(TracePoint::SyscallEnter, [syscall_id, args[0]]);
+    let abi = current_process().inner_exclusive_access().abi;
+    let result = match abi {
+        SyscallAbi::Native => native_syscall(syscall_id, args),
+        SyscallAbi::Linux => linux::syscall(syscall_id, args),
+    };
+    let ret = match result {
+        Ok(ret) => ret as isize,
+        Err(err) => -err.errno(),
+    };
+    trace(TracePoint::SyscallExit, [syscall_id, ret as usize]);
+    ret
+}
+
+fn native_syscall(syscall_id: usize, args: [usize; 6]) -> SysResult {
     match syscall_id {
+        SYSCALL_GETCWD => sys_getcwd(args[0] as *mut u8, args[1]),
         SYSCALL_DUP => sys_dup(args[0]),
+        SYSCALL_FCNTL => sys_fcntl(args[0], args[1], args[2]),
         SYSCALL_CONNECT => sys_connect(args[0] as _, args[1] as _, args[2] as _),
         SYSCALL_LISTEN => sys_listen(args[0] as _),
         SYSCALL_ACCEPT => sys_accept(args[0] as _),
+        SYSCALL_MKFIFO => sys_mkfifo(args[0] as *const u8),
+        SYSCALL_MKDIR => sys_mkdir(args[0] as *const u8),
+        SYSCALL_UNLINK => sys_unlink(args[0] as *const u8, args[1]),
+        SYSCALL_LINK => sys_link(args[0] as *const u8, args[1] as *const u8),
+        SYSCALL_UMOUNT2 => sys_umount2(args[0] as *const u8, args[1]),
+        SYSCALL_MOUNT => sys_mount(
+            args[0] as *const u8,
+            args[1] as *const u8,
+            args[2] as *const u8,
+            args[3],
+            args[4] as *const u8,
+        ),
         SYSCALL_OPEN => sys_open(args[0] as *const u8, args[1] as u32),
         SYSCALL_CLOSE => sys_close(args[0]),
-        SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
+        SYSCALL_PIPE => sys_pipe(args[0] as *mut usize, args[1]),
+        SYSCALL_GETDENTS64 => sys_getdents64(args[0], args[1] as *mut u8, args[2]),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_SENDFILE => sys_sendfile(args[0], args[1], args[2] as *mut usize, args[3]),
+        SYSCALL_PSELECT => sys_pselect(
+            args[0],
+            args[1] as *mut usize,
+            args[2] as *mut usize,
+            args[3] as *mut usize,
+            args[4] as *const TimeSpec,
+            args[5] as *const u32,
+        ),
+        SYSCALL_PPOLL => sys_ppoll(
+            args[0] as *mut PollFd,
+            args[1],
+            args[2] as *const TimeSpec,
+            args[3] as *const u32,
+        ),
+        SYSCALL_FSTATAT => sys_fstatat(args[0], args[1] as *const u8, args[2] as _, args[3]),
+        SYSCALL_FSTAT => sys_fstat(args[0], args[1] as _),
+        SYSCALL_UTIMENSAT => sys_utimensat(
+            args[0],
+            args[1] as *const u8,
+            args[2] as *const TimeSpec,
+            args[3],
+        ),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_EXIT_GROUP => sys_exit_group(args[0] as i32),
+        SYSCALL_SET_TID_ADDRESS => sys_set_tid_address(args[0]),
+        SYSCALL_FUTEX => sys_futex(
+            args[0] as *mut u32,
+            args[1],
+            args[2],
+            args[3] as *const TimeSpec,
+            args[4] as *mut u32,
+            args[5],
+        ),
         SYSCALL_SLEEP => sys_sleep(args[0]),
+        SYSCALL_CLOCK_SETTIME => sys_clock_settime(args[0], args[1] as *const TimeSpec),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1] as *mut TimeSpec),
+        SYSCALL_PTRACE => sys_ptrace(args[0], args[1], args[2], args[3]),
         SYSCALL_YIELD => sys_yield(),
-        SYSCALL_KILL => sys_kill(args[0], args[1] as u32),
-        SYSCALL_GET_TIME => sys_get_time(),
+        SYSCALL_KILL => sys_kill(args[0] as isize, args[1] as u32),
+        SYSCALL_REBOOT => sys_reboot(args[0], args[1], args[2]),
+        SYSCALL_SIGALTSTACK => sys_sigaltstack(args[0] as _, args[1] as _),
+        SYSCALL_SIGSUSPEND => sys_sigsuspend(args[0] as u32),
+        SYSCALL_SIGACTION => sys_sigaction(args[0], args[1] as _, args[2] as _),
+        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0], args[1] as u32),
+        SYSCALL_SIGWAIT => sys_sigwait(args[0] as u32),
+        SYSCALL_SIGRETURN => sys_sigreturn(),
+        SYSCALL_SETPGID => sys_setpgid(args[0], args[1]),
+        SYSCALL_GETPGID => sys_getpgid(args[0]),
+        SYSCALL_PRCTL => sys_prctl(args[0], args[1]),
+        SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_GETPPID => sys_getppid(),
+        SYSCALL_BRK => sys_brk(args[0]),
+        SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
+        SYSCALL_MREMAP => sys_mremap(args[0], args[1], args[2], args[3]),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        SYSCALL_MSYNC => sys_msync(args[0], args[1], args[2]),
+        SYSCALL_MADVISE => sys_madvise(args[0], args[1], args[2]),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32, args[2]),
+        SYSCALL_GETRANDOM => sys_getrandom(args[0] as *mut u8, args[1], args[2]),
+        SYSCALL_STATX => sys_statx(
+            args[0],
+            args[1] as *const u8,
+            args[2],
+            args[3] as u32,
+            args[4] as *mut Statx,
+        ),
         SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
         SYSCALL_GETTID => sys_gettid(),
-        SYSCALL_WAITTID => sys_waittid(args[0]) as isize,
+        SYSCALL_WAITTID => sys_waittid(args[0], args[1] as *mut i32),
         SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] == 1),
         SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
         SYSCALL_MUTEX_UNLOCK => sys_mutex_unlock(args[0]),
@@ -83,8 +280,24 @@ pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
         SYSCALL_CONDVAR_WAIT => sys_condvar_wait(args[0], args[1]),
         SYSCALL_FRAMEBUFFER => sys_framebuffer(),
         SYSCALL_FRAMEBUFFER_FLUSH => sys_framebuffer_flush(),
+        SYSCALL_SURFACE_CREATE => {
+            sys_surface_create(args[0] as isize, args[1] as isize, args[2], args[3])
+        }
         SYSCALL_EVENT_GET => sys_event_get(),
         SYSCALL_KEY_PRESSED => sys_key_pressed(),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1] as u32, args[2]),
+        SYSCALL_OPENPTY => sys_openpty(args[0] as *mut usize),
+        SYSCALL_DUP2 => sys_dup2(args[0], args[1]),
+        SYSCALL_GETDENTS => sys_getdents(args[0], args[1] as *mut u8, args[2]),
+        SYSCALL_PROCESS_LIST => sys_process_list(args[0] as *mut ProcessInfo, args[1]),
+        SYSCALL_OPENAT => sys_openat(args[0], args[1] as *const u8, args[2] as u32),
+        SYSCALL_MKDIRAT => sys_mkdirat(args[0], args[1] as *const u8),
+        SYSCALL_UNLINKAT => sys_unlinkat(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_LINKAT => sys_linkat(args[0], args[1] as _, args[2], args[3] as _),
+        SYSCALL_NANOSLEEP => sys_nanosleep(args[0] as *const TimeSpec, args[1] as *mut TimeSpec),
+        SYSCALL_UINTR_REGISTER => sys_uintr_register(args[0], args[1]),
+        SYSCALL_UINTR_NOTIFY => sys_uintr_notify(args[0]),
+        SYSCALL_UINTR_RETURN => sys_uintr_return(),
+        _ => sys_unsupported(syscall_id),
     }
 }
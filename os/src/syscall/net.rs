@@ -1,3 +1,4 @@
+use super::{SysError, SysResult};
 use crate::net::port_table::{accept, listen, port_acceptable, PortFd};
 use crate::net::udp::UDP;
 use crate::net::{net_interrupt_handler, IPv4};
@@ -5,17 +6,17 @@ use crate::task::{current_process, current_task, current_trap_cx};
 use alloc::sync::Arc;
 
 // just support udp
-pub fn sys_connect(raddr: u32, lport: u16, rport: u16) -> isize {
+pub fn sys_connect(raddr: u32, lport: u16, rport: u16) -> SysResult {
     let process = current_process();
     let mut inner = process.inner_exclusive_access();
     let fd = inner.alloc_fd();
     let udp_node = UDP::new(IPv4::from_u32(raddr), lport, rport);
     inner.fd_table[fd] = Some(Arc::new(udp_node));
-    fd as isize
+    Ok(fd)
 }
 
 // listen a port
-pub fn sys_listen(port: u16) -> isize {
+pub fn sys_listen(port: u16) -> SysResult {
     match listen(port) {
         Some(port_index) => {
             let process = current_process();
@@ -25,14 +26,14 @@ pub fn sys_listen(port: u16) -> isize {
             inner.fd_table[fd] = Some(Arc::new(port_fd));
 
             // NOTICE: this return the port index, not the fd
-            port_index as isize
+            Ok(port_index)
         }
-        None => -1,
+        None => Err(SysError::EADDRINUSE),
     }
 }
 
 // accept a tcp connection
-pub fn sys_accept(port_index: usize) -> isize {
+pub fn sys_accept(port_index: usize) -> SysResult {
     println!("accepting port {}", port_index);
 
     let task = current_task().unwrap();
@@ -49,5 +50,5 @@ pub fn sys_accept(port_index: usize) -> isize {
     }
 
     let cx = current_trap_cx();
-    cx.x[10] as isize
+    Ok(cx.x[10])
 }
@@ -0,0 +1,233 @@
+//! poll and select over the readiness `File::poll` reports. Files have no
+//! wait queues to sleep on, so a waiting task sleeps for a timer tick at a
+//! time and looks at its files again. A signal wakes it at once.
+
+use super::fs::{file_of, FD_LIMIT};
+use super::{SysError, SysResult};
+use crate::config::HZ;
+use crate::fs::PollEvents;
+use crate::mm::{translated_ref, translated_refmut};
+use crate::task::{current_task, current_user_token, sleep_interruptible, SignalFlags};
+use crate::timer::{get_time_ns, TimeSpec};
+use alloc::vec::Vec;
+
+/// Layout compatible with `struct pollfd` of linux.
+#[repr(C)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+/// Bits of an `fd_set`, 1024 fds in words of 64.
+const FD_SET_WORDS: usize = FD_LIMIT / 64;
+
+/// How long a waiting task sleeps before it looks at its files again.
+const POLL_INTERVAL_NS: u64 = 1_000_000_000 / HZ as u64;
+
+/// Run the rest of the syscall with `mask`. The old mask comes back on the
+/// return to user mode, or through sigreturn if a signal interrupted the
+/// wait: as with sigsuspend, no signal can slip in between the mask change
+/// and the wait.
+fn set_temporary_signal_mask(mask: SignalFlags) {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.sigsuspend_mask = Some(inner.signal_mask);
+    inner.signal_mask = mask - SignalFlags::UNCATCHABLE;
+}
+
+/// `timeout` is relative, null for none.
+fn deadline_of(token: usize, timeout: *const TimeSpec) -> Result<Option<u64>, SysError> {
+    if timeout.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(
+        get_time_ns() + translated_ref(token, timeout)?.to_ns(),
+    ))
+}
+
+/// The signal mask at `mask`, None if null.
+fn signal_mask_of(mask: *const u32) -> Result<Option<SignalFlags>, SysError> {
+    if mask.is_null() {
+        return Ok(None);
+    }
+    let mask = *translated_ref(current_user_token(), mask)?;
+    Ok(Some(SignalFlags::from_bits_truncate(mask)))
+}
+
+/// Sleep until `ready` counts something, the deadline passes or a signal
+/// can be delivered.
+pub fn wait_ready(
+    deadline: Option<u64>,
+    mut ready: impl FnMut() -> Result<usize, SysError>,
+) -> SysResult {
+    loop {
+        let count = ready()?;
+        let now = get_time_ns();
+        if count > 0 || deadline.map_or(false, |deadline| now >= deadline) {
+            return Ok(count);
+        }
+        let next_look = now + POLL_INTERVAL_NS;
+        if sleep_interruptible(Some(
+            deadline.map_or(next_look, |deadline| deadline.min(next_look)),
+        )) {
+            return Err(SysError::EINTR);
+        }
+    }
+}
+
+/// Wait until one of `nfds` entries at `fds` is ready, returns how many are.
+/// Negative fds are skipped, closed ones report POLLNVAL.
+pub fn ppoll(
+    fds: *mut PollFd,
+    nfds: usize,
+    timeout: *const TimeSpec,
+    mask: Option<SignalFlags>,
+) -> SysResult {
+    if nfds > FD_LIMIT {
+        return Err(SysError::EINVAL);
+    }
+    let token = current_user_token();
+    let deadline = deadline_of(token, timeout)?;
+    if let Some(mask) = mask {
+        set_temporary_signal_mask(mask);
+    }
+    wait_ready(deadline, || {
+        let mut count = 0;
+        for i in 0..nfds {
+            let pollfd = translated_refmut(token, unsafe { fds.add(i) })?;
+            let revents = if pollfd.fd < 0 {
+                PollEvents::empty()
+            } else if let Ok(file) = file_of(pollfd.fd as usize) {
+                let wanted = PollEvents::from_bits_truncate(pollfd.events as u16)
+                    | PollEvents::POLLHUP
+                    | PollEvents::POLLERR;
+                file.poll() & wanted
+            } else {
+                PollEvents::POLLNVAL
+            };
+            pollfd.revents = revents.bits() as i16;
+            if !revents.is_empty() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    })
+}
+
+pub fn sys_ppoll(
+    fds: *mut PollFd,
+    nfds: usize,
+    timeout: *const TimeSpec,
+    mask: *const u32,
+) -> SysResult {
+    ppoll(fds, nfds, timeout, signal_mask_of(mask)?)
+}
+
+/// The words of an `fd_set` holding the first `nfds` fds, the caller's set
+/// may be no longer than that.
+fn fd_set_words(nfds: usize) -> usize {
+    nfds.div_ceil(64)
+}
+
+fn read_fd_set(
+    token: usize,
+    set: *mut usize,
+    nfds: usize,
+) -> Result<[usize; FD_SET_WORDS], SysError> {
+    let mut words = [0; FD_SET_WORDS];
+    if !set.is_null() {
+        for (i, word) in words[..fd_set_words(nfds)].iter_mut().enumerate() {
+            *word = *translated_ref(token, unsafe { set.add(i) })?;
+        }
+    }
+    Ok(words)
+}
+
+fn write_fd_set(
+    token: usize,
+    set: *mut usize,
+    nfds: usize,
+    words: &[usize; FD_SET_WORDS],
+) -> Result<(), SysError> {
+    if !set.is_null() {
+        for (i, word) in words[..fd_set_words(nfds)].iter().enumerate() {
+            *translated_refmut(token, unsafe { set.add(i) })? = *word;
+        }
+    }
+    Ok(())
+}
+
+/// Wait until one of the first `nfds` fds in the sets is ready, the sets
+/// are left with the ready ones. Returns the number of bits left set.
+/// Nothing raises an exceptional condition, so `exceptfds` only gets cleared.
+pub fn pselect(
+    nfds: usize,
+    readfds: *mut usize,
+    writefds: *mut usize,
+    exceptfds: *mut usize,
+    timeout: *const TimeSpec,
+    mask: Option<SignalFlags>,
+) -> SysResult {
+    if nfds > FD_LIMIT {
+        return Err(SysError::EINVAL);
+    }
+    let token = current_user_token();
+    let deadline = deadline_of(token, timeout)?;
+    let wanted = [
+        read_fd_set(token, readfds, nfds)?,
+        read_fd_set(token, writefds, nfds)?,
+    ];
+    // every fd in a set has to be open
+    let mut files = Vec::new();
+    for fd in 0..nfds {
+        let (word, bit) = (fd / 64, 1 << (fd % 64));
+        if wanted.iter().any(|set| set[word] & bit != 0) {
+            files.push((fd, file_of(fd)?));
+        }
+    }
+    if let Some(mask) = mask {
+        set_temporary_signal_mask(mask);
+    }
+    let mut ready = [[0; FD_SET_WORDS]; 2];
+    let count = wait_ready(deadline, || {
+        ready = [[0; FD_SET_WORDS]; 2];
+        let mut count = 0;
+        for (fd, file) in files.iter() {
+            let (word, bit) = (fd / 64, 1 << (fd % 64));
+            let events = file.poll();
+            // a hung up or broken end reads or writes without blocking
+            let readable = PollEvents::POLLIN | PollEvents::POLLHUP;
+            let writable = PollEvents::POLLOUT | PollEvents::POLLERR;
+            for (set, ready_events) in [readable, writable].into_iter().enumerate() {
+                if wanted[set][word] & bit != 0 && events.intersects(ready_events) {
+                    ready[set][word] |= bit;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    })?;
+    write_fd_set(token, readfds, nfds, &ready[0])?;
+    write_fd_set(token, writefds, nfds, &ready[1])?;
+    write_fd_set(token, exceptfds, nfds, &[0; FD_SET_WORDS])?;
+    Ok(count)
+}
+
+pub fn sys_pselect(
+    nfds: usize,
+    readfds: *mut usize,
+    writefds: *mut usize,
+    exceptfds: *mut usize,
+    timeout: *const TimeSpec,
+    mask: *const u32,
+) -> SysResult {
+    pselect(
+        nfds,
+        readfds,
+        writefds,
+        exceptfds,
+        timeout,
+        signal_mask_of(mask)?,
+    )
+}
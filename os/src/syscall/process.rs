@@ -1,33 +1,109 @@
-use crate::fs::{open_file, OpenFlags};
-use crate::mm::{translated_ref, translated_refmut, translated_str};
+use super::{SysError, SysResult};
+use crate::fs::{open_file, resolve_mount, File, OpenFlags};
+use crate::mm::{translated_byte_buffer_mut, translated_ref, translated_refmut, translated_str};
+use crate::power::power_off;
+use crate::random::get_random_bytes;
 use crate::task::{
-    current_process, current_task, current_user_token, exit_current_and_run_next, pid2process,
-    suspend_current_and_run_next, SignalFlags,
+    all_processes, current_process, current_task, current_user_token,
+    deliverable_signals_of_current, exit_current_and_run_next, exit_group_current_and_run_next,
+    pid2process, process_group, send_signal, suspend_current_and_run_next, ProcessControlBlock,
+    SignalAction, SignalFlags, SignalStack, TaskStatus, INITPROC, MINSIGSTKSZ, SIG_BLOCK,
+    SIG_SETMASK, SIG_UNBLOCK, SS_DISABLE, SS_ONSTACK,
+};
+use crate::timer::{
+    get_time_ms, get_time_ns, realtime_ns, set_realtime_ns, TimeSpec, TimeVal, CLOCK_MONOTONIC,
+    CLOCK_REALTIME,
 };
-use crate::timer::get_time_ms;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+/// waitpid options, as in linux
+pub const WUNTRACED: usize = 2;
+pub const WCONTINUED: usize = 8;
+
 pub fn sys_exit(exit_code: i32) -> ! {
     exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
-pub fn sys_yield() -> isize {
+/// Exit every thread of the process, not only the caller.
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    exit_group_current_and_run_next(exit_code);
+    panic!("Unreachable in sys_exit_group!");
+}
+
+pub fn sys_yield() -> SysResult {
     suspend_current_and_run_next();
-    0
+    Ok(0)
+}
+
+/// Milliseconds since boot, read from mtime. The same time goes to `tv`
+/// in microseconds unless it is null.
+pub fn sys_get_time(tv: *mut TimeVal) -> SysResult {
+    if !tv.is_null() {
+        *translated_refmut(current_user_token(), tv)? = TimeVal::from_ns(get_time_ns());
+    }
+    Ok(get_time_ms())
 }
 
-pub fn sys_get_time() -> isize {
-    get_time_ms() as isize
+pub fn sys_clock_gettime(clock: usize, tp: *mut TimeSpec) -> SysResult {
+    let ns = match clock {
+        CLOCK_REALTIME => realtime_ns(),
+        CLOCK_MONOTONIC => get_time_ns(),
+        _ => return Err(SysError::EINVAL),
+    };
+    *translated_refmut(current_user_token(), tp)? = TimeSpec::from_ns(ns);
+    Ok(0)
 }
 
-pub fn sys_getpid() -> isize {
-    current_task().unwrap().process.upgrade().unwrap().getpid() as isize
+/// Only the wall clock can be set.
+pub fn sys_clock_settime(clock: usize, tp: *const TimeSpec) -> SysResult {
+    if clock != CLOCK_REALTIME {
+        return Err(SysError::EINVAL);
+    }
+    let time = *translated_ref(current_user_token(), tp)?;
+    if time.tv_nsec >= 1_000_000_000 {
+        return Err(SysError::EINVAL);
+    }
+    set_realtime_ns(time.to_ns());
+    Ok(0)
+}
+
+const GRND_NONBLOCK: usize = 1;
+const GRND_RANDOM: usize = 2;
+/// longest request served at once, as on linux
+const GETRANDOM_MAX: usize = 0x1ff_ffff;
+
+/// Fill `buf` from the kernel entropy pool. The pool is seeded at boot, so
+/// it never blocks and both flags change nothing.
+pub fn sys_getrandom(buf: *mut u8, len: usize, flags: usize) -> SysResult {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM) != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let len = len.min(GETRANDOM_MAX);
+    for slice in translated_byte_buffer_mut(current_user_token(), buf, len)? {
+        get_random_bytes(slice);
+    }
+    Ok(len)
 }
 
-pub fn sys_fork() -> isize {
+pub fn sys_getpid() -> SysResult {
+    Ok(current_task().unwrap().process.upgrade().unwrap().getpid())
+}
+
+/// The pid of the parent, 0 for initproc which has none.
+pub fn sys_getppid() -> SysResult {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    Ok(inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid()))
+}
+
+pub fn sys_fork() -> SysResult {
     let current_process = current_process();
     let new_process = current_process.fork();
     let new_pid = new_process.getpid();
@@ -38,53 +114,109 @@ pub fn sys_fork() -> isize {
     // we do not have to move to next instruction since we have done it before
     // for child process, fork returns 0
     trap_cx.x[10] = 0;
-    new_pid as isize
+    Ok(new_pid)
 }
 
-pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
+pub fn sys_exec(path: *const u8, mut args: *const usize) -> SysResult {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = translated_str(token, path)?;
     let mut args_vec: Vec<String> = Vec::new();
     loop {
-        let arg_str_ptr = *translated_ref(token, args);
+        let arg_str_ptr = *translated_ref(token, args)?;
         if arg_str_ptr == 0 {
             break;
         }
-        args_vec.push(translated_str(token, arg_str_ptr as *const u8));
+        args_vec.push(translated_str(token, arg_str_ptr as *const u8)?);
         unsafe {
             args = args.add(1);
         }
     }
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-        let all_data = app_inode.read_all();
-        let process = current_process();
-        let argc = args_vec.len();
-        process.exec(all_data.as_slice(), args_vec);
-        // return argc because cx.x[10] will be covered with it later
-        argc as isize
-    } else {
-        -1
+    let (all_data, inode) = match resolve_mount(path.as_str()) {
+        Some((fs, names)) => (
+            fs.open(&names, OpenFlags::RDONLY, path.as_str())?
+                .read_all()?,
+            None,
+        ),
+        None => {
+            let file = open_file(path.as_str(), OpenFlags::RDONLY).ok_or(SysError::ENOENT)?;
+            (file.read_all(), file.inode())
+        }
+    };
+    let process = current_process();
+    let argc = args_vec.len();
+    process.exec(all_data.as_slice(), inode, args_vec);
+    // let the tracer look at the new program before it runs
+    let mut inner = process.inner_exclusive_access();
+    if inner.ptrace.is_some() {
+        inner.signals |= SignalFlags::SIGTRAP;
+    }
+    // return argc because cx.x[10] will be covered with it later
+    Ok(argc)
+}
+
+/// Does `child` match the `pid` argument of waitpid, whose group is `pgid`?
+fn waitpid_matches(pid: isize, pgid: usize, child: &Arc<ProcessControlBlock>) -> bool {
+    match pid {
+        -1 => true,
+        0 => child.inner_exclusive_access().pgid == pgid,
+        pid if pid < 0 => child.inner_exclusive_access().pgid == pid.unsigned_abs(),
+        pid => pid as usize == child.getpid(),
     }
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// The status of a child which stopped or continued and has not been
+/// reported yet. Tracees are always reported, job control stops only
+/// with WUNTRACED and continued children only with WCONTINUED.
+fn waitpid_status_change(child: &Arc<ProcessControlBlock>, options: usize) -> Option<i32> {
+    let mut child_inner = child.inner_exclusive_access();
+    if let Some(state) = child_inner.ptrace.as_mut() {
+        if let (Some(signal), false) = (state.stop_signal, state.stop_reported) {
+            state.stop_reported = true;
+            return Some(((signal.signum() << 8) | 0x7f) as i32);
+        }
+    }
+    let job = &mut child_inner.job;
+    if options & WUNTRACED != 0 {
+        if let (Some(signal), false) = (job.stop_signal, job.stop_reported) {
+            job.stop_reported = true;
+            return Some(((signal.signum() << 8) | 0x7f) as i32);
+        }
+    }
+    if options & WCONTINUED != 0 && job.continued {
+        job.continued = false;
+        return Some(0xffff);
+    }
+    None
+}
+
+/// If there is not a child process whose pid is same as given, fail with
+/// ECHILD. Else if there is a child process but it is still running, fail
+/// with EAGAIN.
+/// A traced child which has stopped is reported once with the status
+/// `(signum << 8) | 0x7f`, so is a job control stop with WUNTRACED.
+/// WCONTINUED reports a child resumed by SIGCONT once with 0xffff.
+/// Returns the pid found and its status.
+pub fn wait_child(pid: isize, options: usize) -> Result<(usize, i32), SysError> {
     let process = current_process();
     // find a child process
 
     let mut inner = process.inner_exclusive_access();
-    if !inner
+    let pgid = inner.pgid;
+    if !inner.children.iter().any(|p| waitpid_matches(pid, pgid, p)) {
+        return Err(SysError::ECHILD);
+        // ---- release current PCB
+    }
+    let changed = inner
         .children
         .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
-        return -1;
-        // ---- release current PCB
+        .filter(|child| waitpid_matches(pid, pgid, child))
+        .find_map(|child| Some((child.getpid(), waitpid_status_change(child, options)?)));
+    if let Some(changed) = changed {
+        return Ok(changed);
     }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
         // ++++ temporarily access child PCB exclusively
-        p.inner_exclusive_access().is_zombie && (pid == -1 || pid as usize == p.getpid())
+        p.inner_exclusive_access().is_zombie && waitpid_matches(pid, pgid, p)
         // ++++ release child PCB
     });
     if let Some((idx, _)) = pair {
@@ -95,23 +227,293 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
         // ++++ temporarily access child PCB exclusively
         let exit_code = child.inner_exclusive_access().exit_code;
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+        Ok((found_pid, exit_code))
     } else {
-        -2
+        Err(SysError::EAGAIN)
     }
     // ---- release current PCB automatically
 }
 
-pub fn sys_kill(pid: usize, signal: u32) -> isize {
-    if let Some(process) = pid2process(pid) {
-        if let Some(flag) = SignalFlags::from_bits(signal) {
-            process.inner_exclusive_access().signals |= flag;
-            0
-        } else {
-            -1
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> SysResult {
+    let token = current_user_token();
+    // user memory is written without the PCB, it may have to be faulted in
+    let (found_pid, status) = wait_child(pid, options)?;
+    *translated_refmut(token, exit_code_ptr)? = status;
+    Ok(found_pid)
+}
+
+const PROCESS_NAME_LEN: usize = 31;
+
+/// One entry of sys_process_list, 64 bytes.
+#[repr(C)]
+pub struct ProcessInfo {
+    pub pid: usize,
+    pub ppid: usize,
+    pub pgid: usize,
+    pub threads: usize,
+    /// `R`unnable, `S`leeping or `T` stopped
+    pub state: u8,
+    /// NUL-terminated, cut short if need be
+    pub name: [u8; PROCESS_NAME_LEN],
+}
+
+impl ProcessInfo {
+    fn of(process: &Arc<ProcessControlBlock>) -> Self {
+        let inner = process.inner_exclusive_access();
+        let ppid = inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(0, |parent| parent.getpid());
+        let stopped = inner.job.stop_signal.is_some()
+            || inner
+                .ptrace
+                .as_ref()
+                .map_or(false, |state| state.stop_signal.is_some());
+        let runnable = inner
+            .tasks
+            .iter()
+            .flatten()
+            .any(|task| task.inner_exclusive_access().task_status != TaskStatus::Blocked);
+        let mut name = [0u8; PROCESS_NAME_LEN];
+        let len = inner.name.len().min(PROCESS_NAME_LEN - 1);
+        name[..len].copy_from_slice(&inner.name.as_bytes()[..len]);
+        Self {
+            pid: process.getpid(),
+            ppid,
+            pgid: inner.pgid,
+            threads: inner.thread_count(),
+            state: match (stopped, runnable) {
+                (true, _) => b'T',
+                (false, true) => b'R',
+                (false, false) => b'S',
+            },
+            name,
+        }
+    }
+}
+
+/// Describe up to `count` live processes in `buf`, returning how many there are.
+pub fn sys_process_list(buf: *mut ProcessInfo, count: usize) -> SysResult {
+    let token = current_user_token();
+    let processes = all_processes();
+    for (i, process) in processes.iter().take(count).enumerate() {
+        let info = ProcessInfo::of(process);
+        *translated_refmut(token, unsafe { buf.add(i) })? = info;
+    }
+    Ok(processes.len())
+}
+
+/// A positive `pid` names a process, 0 the group of the caller, -1 every
+/// process but initproc and the caller, and any other negative one the group `-pid`.
+pub fn sys_kill(pid: isize, signal: u32) -> SysResult {
+    let flag = SignalFlags::from_bits(signal).ok_or(SysError::EINVAL)?;
+    let targets = match pid {
+        pid if pid > 0 => pid2process(pid as usize).into_iter().collect(),
+        0 => process_group(current_process().inner_exclusive_access().pgid),
+        -1 => {
+            let caller = current_process();
+            all_processes()
+                .into_iter()
+                .filter(|process| {
+                    !Arc::ptr_eq(process, &caller) && !Arc::ptr_eq(process, &INITPROC)
+                })
+                .collect()
+        }
+        pgid => process_group(pgid.unsigned_abs()),
+    };
+    if targets.is_empty() {
+        return Err(SysError::ESRCH);
+    }
+    for process in targets.iter() {
+        send_signal(process, flag);
+    }
+    Ok(0)
+}
+
+/// `pid` 0 is the caller, which may also move its children. `pgid` 0 makes
+/// the process lead a group of its own.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> SysResult {
+    let process = current_process();
+    let target = if pid == 0 || pid == process.getpid() {
+        process.clone()
+    } else {
+        let inner = process.inner_exclusive_access();
+        let child = inner.children.iter().find(|child| child.getpid() == pid);
+        child.cloned().ok_or(SysError::ESRCH)?
+    };
+    let pgid = if pgid == 0 { target.getpid() } else { pgid };
+    target.inner_exclusive_access().pgid = pgid;
+    Ok(0)
+}
+
+pub fn sys_getpgid(pid: usize) -> SysResult {
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        pid2process(pid).ok_or(SysError::ESRCH)?
+    };
+    let pgid = process.inner_exclusive_access().pgid;
+    Ok(pgid)
+}
+
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> SysResult {
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) if !signal.intersects(SignalFlags::UNCATCHABLE) => signal,
+        _ => return Err(SysError::EINVAL),
+    };
+    let token = current_user_token();
+    let process = current_process();
+    let new_action = if action.is_null() {
+        None
+    } else {
+        Some(*translated_ref(token, action)?)
+    };
+    let mut inner = process.inner_exclusive_access();
+    let old = inner.signal_actions.table[signum];
+    if let Some(mut action) = new_action {
+        action.mask.remove(SignalFlags::UNCATCHABLE);
+        inner.signal_actions.table[signum] = action;
+        // a signal which is ignored now is discarded
+        if inner.signal_actions.is_ignored(signal) {
+            inner.signals.remove(signal);
         }
+    }
+    drop(inner);
+    if !old_action.is_null() {
+        *translated_refmut(token, old_action)? = old;
+    }
+    Ok(0)
+}
+
+/// Return the previous mask of the current thread.
+pub fn sys_sigprocmask(how: usize, set: u32) -> SysResult {
+    let set = SignalFlags::from_bits_truncate(set) - SignalFlags::UNCATCHABLE;
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let old_mask = inner.signal_mask;
+    match how {
+        SIG_BLOCK => inner.signal_mask |= set,
+        SIG_UNBLOCK => inner.signal_mask -= set,
+        SIG_SETMASK => inner.signal_mask = set,
+        _ => return Err(SysError::EINVAL),
+    }
+    Ok(old_mask.bits() as usize)
+}
+
+/// Return from a signal handler to the interrupted context.
+pub fn sys_sigreturn() -> SysResult {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if let Some((trap_cx, mask)) = inner.signal_frame.take() {
+        inner.signal_mask = mask;
+        *inner.get_trap_cx() = trap_cx;
+        // a0 of the interrupted context is written back by trap_handler
+        Ok(trap_cx.x[10])
     } else {
-        -1
+        Err(SysError::EINVAL)
+    }
+}
+
+/// Wait with a temporary mask until a signal is delivered, always fails
+/// with EINTR.
+pub fn sys_sigsuspend(mask: u32) -> SysResult {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.sigsuspend_mask = Some(inner.signal_mask);
+    inner.signal_mask = SignalFlags::from_bits_truncate(mask) - SignalFlags::UNCATCHABLE;
+    drop(inner);
+    while deliverable_signals_of_current().is_empty() {
+        suspend_current_and_run_next();
+    }
+    // the old mask is restored once the signal has been handled
+    Err(SysError::EINTR)
+}
+
+/// Take a pending signal in `set` without running its handler, return its number.
+pub fn sys_sigwait(set: u32) -> SysResult {
+    let set = SignalFlags::from_bits_truncate(set);
+    loop {
+        let process = current_process();
+        let mut inner = process.inner_exclusive_access();
+        let pending = inner.signals & set;
+        if !pending.is_empty() {
+            let signum = pending.signum();
+            inner
+                .signals
+                .remove(SignalFlags::from_signum(signum).unwrap());
+            return Ok(signum);
+        }
+        drop(inner);
+        suspend_current_and_run_next();
+    }
+}
+
+/// Query and/or replace the alternate signal stack of the current thread.
+pub fn sys_sigaltstack(stack: *const SignalStack, old_stack: *mut SignalStack) -> SysResult {
+    let token = current_user_token();
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let on_stack = inner.signal_stack.contains(inner.get_trap_cx().x[2]);
+    if !old_stack.is_null() {
+        let mut old = inner.signal_stack;
+        if on_stack {
+            old.flags |= SS_ONSTACK;
+        }
+        *translated_refmut(token, old_stack)? = old;
+    }
+    if !stack.is_null() {
+        let stack = *translated_ref(token, stack)?;
+        // cannot be changed while a handler is running on it
+        if on_stack {
+            return Err(SysError::EPERM);
+        }
+        if stack.flags & !(SS_DISABLE | SS_ONSTACK) != 0 {
+            return Err(SysError::EINVAL);
+        }
+        if stack.flags & SS_DISABLE != 0 {
+            inner.signal_stack = SignalStack::default();
+        } else if stack.size < MINSIGSTKSZ {
+            return Err(SysError::ENOMEM);
+        } else {
+            inner.signal_stack = SignalStack { flags: 0, ..stack };
+        }
+    }
+    Ok(0)
+}
+
+const PR_SET_DUMPABLE: usize = 4;
+const PR_GET_DUMPABLE: usize = 3;
+
+/// Only the dumpable flag, which enables core files, is supported.
+pub fn sys_prctl(option: usize, arg: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match option {
+        PR_GET_DUMPABLE => Ok(inner.dumpable as usize),
+        PR_SET_DUMPABLE if arg <= 1 => {
+            inner.dumpable = arg == 1;
+            Ok(0)
+        }
+        _ => Err(SysError::EINVAL),
+    }
+}
+
+const LINUX_REBOOT_MAGIC1: usize = 0xfee1dead;
+const LINUX_REBOOT_MAGIC2: usize = 672274793;
+const LINUX_REBOOT_CMD_POWER_OFF: usize = 0x4321fedc;
+
+/// Only powering off, the way initproc exiting does.
+pub fn sys_reboot(magic1: usize, magic2: usize, cmd: usize) -> SysResult {
+    if magic1 as u32 as usize != LINUX_REBOOT_MAGIC1 || magic2 != LINUX_REBOOT_MAGIC2 {
+        return Err(SysError::EINVAL);
+    }
+    match cmd as u32 as usize {
+        LINUX_REBOOT_CMD_POWER_OFF => power_off(false),
+        _ => Err(SysError::EINVAL),
     }
 }
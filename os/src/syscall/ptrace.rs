@@ -0,0 +1,176 @@
+use super::{SysError, SysResult};
+use crate::mm::{translated_ref, translated_refmut, translated_text_mut};
+use crate::task::{current_process, current_user_token, pid2process, PtraceState, SignalFlags};
+use crate::trap::{triggers_supported, Trigger};
+
+const PTRACE_TRACEME: usize = 0;
+const PTRACE_PEEKTEXT: usize = 1;
+const PTRACE_PEEKDATA: usize = 2;
+const PTRACE_PEEKUSER: usize = 3;
+const PTRACE_POKETEXT: usize = 4;
+const PTRACE_POKEDATA: usize = 5;
+const PTRACE_POKEUSER: usize = 6;
+const PTRACE_CONT: usize = 7;
+const PTRACE_KILL: usize = 8;
+const PTRACE_SINGLESTEP: usize = 9;
+const PTRACE_GETREGS: usize = 12;
+const PTRACE_SETREGS: usize = 13;
+const PTRACE_ATTACH: usize = 16;
+const PTRACE_DETACH: usize = 17;
+// not in linux: arm a one-shot hardware watchpoint at `addr`, `data` is a
+// combination of WATCH_LOAD and WATCH_STORE
+const PTRACE_SETWATCH: usize = 0x4300;
+const PTRACE_CLEARWATCH: usize = 0x4301;
+const WATCH_LOAD: usize = 1;
+const WATCH_STORE: usize = 2;
+
+/// Registers as seen by the tracer: pc followed by x1..x31, like
+/// `struct user_regs_struct` of riscv64 linux.
+const USER_REGS_COUNT: usize = 32;
+
+/// Results of PEEK requests are stored at `data` as linux does.
+pub fn sys_ptrace(request: usize, pid: usize, addr: usize, data: usize) -> SysResult {
+    let process = current_process();
+    if request == PTRACE_TRACEME {
+        let mut inner = process.inner_exclusive_access();
+        if inner.ptrace.is_some() || inner.parent.is_none() {
+            return Err(SysError::EPERM);
+        }
+        inner.ptrace = Some(PtraceState::default());
+        // breakpoints must not reach other processes running the program
+        inner.memory_set.unshare_text();
+        return Ok(0);
+    }
+    // only children can be traced, so that waitpid sees their stops
+    let tracee = match pid2process(pid) {
+        Some(tracee)
+            if process
+                .inner_exclusive_access()
+                .children
+                .iter()
+                .any(|child| child.getpid() == pid) =>
+        {
+            tracee
+        }
+        _ => return Err(SysError::ESRCH),
+    };
+    let token = current_user_token();
+    let mut inner = tracee.inner_exclusive_access();
+    let tracee_token = inner.memory_set.token();
+    if request == PTRACE_ATTACH {
+        if inner.ptrace.is_some() {
+            return Err(SysError::EPERM);
+        }
+        inner.ptrace = Some(PtraceState::default());
+        inner.memory_set.unshare_text();
+        inner.raise(SignalFlags::SIGSTOP);
+        return Ok(0);
+    }
+    let state = match inner.ptrace.as_mut() {
+        Some(state) => state,
+        None => return Err(SysError::ESRCH),
+    };
+    let stopped_task = match state.stopped_task.clone() {
+        Some(task) => task,
+        // the remaining requests need a stopped tracee
+        None => return Err(SysError::ESRCH),
+    };
+    let trap_cx = stopped_task.inner_exclusive_access().get_trap_cx();
+    // signal to deliver for the resuming requests, 0 for none
+    let signal = SignalFlags::from_signum(data);
+    if matches!(request, PTRACE_CONT | PTRACE_SINGLESTEP | PTRACE_DETACH)
+        && data != 0
+        && signal.is_none()
+    {
+        return Err(SysError::EIO);
+    }
+    match request {
+        PTRACE_PEEKTEXT | PTRACE_PEEKDATA => {
+            *translated_refmut(token, data as *mut usize)? =
+                *translated_ref(tracee_token, addr as *const usize)?;
+        }
+        PTRACE_POKETEXT | PTRACE_POKEDATA => {
+            *translated_text_mut(tracee_token, addr as *mut usize)? = data;
+        }
+        PTRACE_PEEKUSER | PTRACE_POKEUSER => {
+            let index = addr / core::mem::size_of::<usize>();
+            if index >= USER_REGS_COUNT {
+                return Err(SysError::EIO);
+            }
+            let reg = if index == 0 {
+                &mut trap_cx.sepc
+            } else {
+                &mut trap_cx.x[index]
+            };
+            if request == PTRACE_PEEKUSER {
+                *translated_refmut(token, data as *mut usize)? = *reg;
+            } else {
+                *reg = data;
+            }
+        }
+        PTRACE_GETREGS => {
+            for index in 0..USER_REGS_COUNT {
+                let reg = if index == 0 {
+                    trap_cx.sepc
+                } else {
+                    trap_cx.x[index]
+                };
+                *translated_refmut(token, (data as *mut usize).wrapping_add(index))? = reg;
+            }
+        }
+        PTRACE_SETREGS => {
+            for index in 0..USER_REGS_COUNT {
+                let reg = *translated_ref(token, (data as *const usize).wrapping_add(index))?;
+                if index == 0 {
+                    trap_cx.sepc = reg;
+                } else {
+                    trap_cx.x[index] = reg;
+                }
+            }
+        }
+        PTRACE_SETWATCH => {
+            if !triggers_supported() {
+                return Err(SysError::ENODEV);
+            }
+            if data & !(WATCH_LOAD | WATCH_STORE) != 0 || data == 0 {
+                return Err(SysError::EINVAL);
+            }
+            let trigger =
+                Trigger::watchpoint(addr, data & WATCH_LOAD != 0, data & WATCH_STORE != 0);
+            stopped_task
+                .inner_exclusive_access()
+                .debug_triggers
+                .push(trigger);
+        }
+        PTRACE_CLEARWATCH => {
+            stopped_task
+                .inner_exclusive_access()
+                .debug_triggers
+                .retain(|trigger| *trigger == Trigger::single_step());
+        }
+        PTRACE_CONT => {
+            state.clear_single_step(tracee_token);
+            state.resume(signal);
+        }
+        PTRACE_SINGLESTEP => {
+            state.prepare_single_step(tracee_token);
+            state.resume(signal);
+        }
+        PTRACE_KILL => {
+            state.clear_single_step(tracee_token);
+            state.resume(None);
+            inner.raise(SignalFlags::SIGKILL);
+        }
+        PTRACE_DETACH => {
+            state.clear_single_step(tracee_token);
+            stopped_task.inner_exclusive_access().debug_triggers.clear();
+            state.resume(None);
+            inner.ptrace = None;
+            if let Some(signal) = signal {
+                inner.raise(signal);
+            }
+        }
+        _ => return Err(SysError::EIO),
+    }
+    Ok(0)
+}
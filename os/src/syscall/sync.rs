@@ -1,17 +1,47 @@
-use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
-use crate::task::{block_current_and_run_next, current_process, current_task};
-use crate::timer::{add_timer, get_time_ms};
+use super::{SysError, SysResult};
+use crate::mm::{translated_ref, translated_refmut};
+use crate::sync::{
+    futex_requeue, futex_wait, futex_wake, Condvar, FutexWait, Mutex, MutexBlocking, MutexSpin,
+    Semaphore, FUTEX_BITSET_MATCH_ANY,
+};
+use crate::task::{
+    block_current_and_run_next, current_process, current_task, current_user_token,
+    sleep_interruptible,
+};
+use crate::timer::{add_timer, get_time_ms, get_time_ns, realtime_ns, TimeSpec};
 use alloc::sync::Arc;
 
-pub fn sys_sleep(ms: usize) -> isize {
+pub fn sys_sleep(ms: usize) -> SysResult {
     let expire_ms = get_time_ms() + ms;
     let task = current_task().unwrap();
     add_timer(expire_ms, task);
     block_current_and_run_next();
-    0
+    Ok(0)
 }
 
-pub fn sys_mutex_create(blocking: bool) -> isize {
+/// Sleep for `req` unless a signal can be delivered first, which fails
+/// with EINTR and writes the time left to `rem` if it is not null.
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> SysResult {
+    let token = current_user_token();
+    let req = *translated_ref(token, req)?;
+    // a negative tv_sec
+    if req.tv_sec > i64::MAX as usize || req.tv_nsec >= 1_000_000_000 {
+        return Err(SysError::EINVAL);
+    }
+    let deadline = get_time_ns().saturating_add(req.to_ns());
+    while get_time_ns() < deadline {
+        if sleep_interruptible(Some(deadline)) {
+            if !rem.is_null() {
+                let left = deadline.saturating_sub(get_time_ns());
+                *translated_refmut(token, rem)? = TimeSpec::from_ns(left);
+            }
+            return Err(SysError::EINTR);
+        }
+    }
+    Ok(0)
+}
+
+pub fn sys_mutex_create(blocking: bool) -> SysResult {
     let process = current_process();
     let mutex: Option<Arc<dyn Mutex>> = if !blocking {
         Some(Arc::new(MutexSpin::new()))
@@ -27,34 +57,34 @@ pub fn sys_mutex_create(blocking: bool) -> isize {
         .map(|(id, _)| id)
     {
         process_inner.mutex_list[id] = mutex;
-        id as isize
+        Ok(id)
     } else {
         process_inner.mutex_list.push(mutex);
-        process_inner.mutex_list.len() as isize - 1
+        Ok(process_inner.mutex_list.len() - 1)
     }
 }
 
-pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+pub fn sys_mutex_lock(mutex_id: usize) -> SysResult {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
     drop(process_inner);
     drop(process);
     mutex.lock();
-    0
+    Ok(0)
 }
 
-pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+pub fn sys_mutex_unlock(mutex_id: usize) -> SysResult {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
     drop(process_inner);
     drop(process);
     mutex.unlock();
-    0
+    Ok(0)
 }
 
-pub fn sys_semaphore_create(res_count: usize) -> isize {
+pub fn sys_semaphore_create(res_count: usize) -> SysResult {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
     let id = if let Some(id) = process_inner
@@ -72,28 +102,28 @@ pub fn sys_semaphore_create(res_count: usize) -> isize {
             .push(Some(Arc::new(Semaphore::new(res_count))));
         process_inner.semaphore_list.len() - 1
     };
-    id as isize
+    Ok(id)
 }
 
-pub fn sys_semaphore_up(sem_id: usize) -> isize {
+pub fn sys_semaphore_up(sem_id: usize) -> SysResult {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
     drop(process_inner);
     sem.up();
-    0
+    Ok(0)
 }
 
-pub fn sys_semaphore_down(sem_id: usize) -> isize {
+pub fn sys_semaphore_down(sem_id: usize) -> SysResult {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
     drop(process_inner);
     sem.down();
-    0
+    Ok(0)
 }
 
-pub fn sys_condvar_create() -> isize {
+pub fn sys_condvar_create() -> SysResult {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
     let id = if let Some(id) = process_inner
@@ -111,24 +141,97 @@ pub fn sys_condvar_create() -> isize {
             .push(Some(Arc::new(Condvar::new())));
         process_inner.condvar_list.len() - 1
     };
-    id as isize
+    Ok(id)
 }
 
-pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+pub fn sys_condvar_signal(condvar_id: usize) -> SysResult {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
     drop(process_inner);
     condvar.signal();
-    0
+    Ok(0)
 }
 
-pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> SysResult {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
     let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
     let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
     drop(process_inner);
     condvar.wait_with_mutex(mutex);
-    0
+    Ok(0)
+}
+
+/// futex operations, as in linux
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+const FUTEX_REQUEUE: usize = 3;
+const FUTEX_CMP_REQUEUE: usize = 4;
+const FUTEX_WAIT_BITSET: usize = 9;
+const FUTEX_WAKE_BITSET: usize = 10;
+/// every futex is looked up by physical address, private or not
+const FUTEX_PRIVATE_FLAG: usize = 128;
+/// the absolute timeout of FUTEX_WAIT_BITSET is on CLOCK_REALTIME
+const FUTEX_CLOCK_REALTIME: usize = 256;
+
+/// `timeout` is relative for FUTEX_WAIT and absolute for FUTEX_WAIT_BITSET,
+/// no timeout if null. The requeue operations take a count instead.
+pub fn sys_futex(
+    uaddr: *mut u32,
+    op: usize,
+    val: usize,
+    timeout: *const TimeSpec,
+    uaddr2: *mut u32,
+    val3: usize,
+) -> SysResult {
+    let token = current_user_token();
+    let clock: fn() -> u64 = if op & FUTEX_CLOCK_REALTIME != 0 {
+        realtime_ns
+    } else {
+        get_time_ns
+    };
+    let timeout_ns = if timeout.is_null() {
+        None
+    } else {
+        Some(translated_ref(token, timeout)?.to_ns())
+    };
+    let (deadline, bitset) = match op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME) {
+        FUTEX_WAIT => (
+            timeout_ns.map(|ns| (get_time_ns() + ns, get_time_ns as fn() -> u64)),
+            FUTEX_BITSET_MATCH_ANY,
+        ),
+        FUTEX_WAIT_BITSET if val3 as u32 == 0 => return Err(SysError::EINVAL),
+        FUTEX_WAIT_BITSET => (timeout_ns.map(|ns| (ns, clock)), val3 as u32),
+        FUTEX_WAKE => return futex_wake(token, uaddr, val, FUTEX_BITSET_MATCH_ANY),
+        FUTEX_WAKE_BITSET if val3 as u32 == 0 => return Err(SysError::EINVAL),
+        FUTEX_WAKE_BITSET => return futex_wake(token, uaddr, val, val3 as u32),
+        FUTEX_REQUEUE => {
+            let requeue = timeout as usize;
+            let (woken, _) = futex_requeue(token, uaddr, val, uaddr2, requeue, None)?;
+            return Ok(woken);
+        }
+        FUTEX_CMP_REQUEUE => {
+            let requeue = timeout as usize;
+            let expected = Some(val3 as u32);
+            return futex_requeue(token, uaddr, val, uaddr2, requeue, expected)
+                .map(|(woken, moved)| woken + moved);
+        }
+        _ => return Err(SysError::ENOSYS),
+    };
+    match futex_wait(token, uaddr, val as u32, bitset, deadline)? {
+        FutexWait::Woken => Ok(0),
+        FutexWait::Changed => Err(SysError::EAGAIN),
+        FutexWait::TimedOut => Err(SysError::ETIMEDOUT),
+        FutexWait::Interrupted => Err(SysError::EINTR),
+    }
+}
+
+/// When the calling thread exits, 0 is stored at `tidptr` and a futex
+/// waiter there is woken, which is how a thread is joined.
+pub fn sys_set_tid_address(tidptr: usize) -> SysResult {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.clear_child_tid = tidptr;
+    Ok(task_inner.res.as_ref().unwrap().tid)
 }
@@ -1,11 +1,18 @@
+use super::{SysError, SysResult};
 use crate::{
-    mm::kernel_token,
-    task::{add_task, current_task, TaskControlBlock},
-    trap::{trap_handler, TrapContext},
+    mm::translated_refmut,
+    task::{
+        add_task, current_process, current_task, current_user_token, TaskControlBlock,
+        TaskControlBlockInner,
+    },
+    trap::{trap_handler, trap_kernel_satp, TrapContext},
 };
 use alloc::sync::Arc;
 
-pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+/// A thread of the current process with the mask of its creator. It starts
+/// on top of a stack of its own, `init` sets the entry and the rest of the
+/// trap context before it is scheduled. Returns the tid.
+pub fn spawn_thread(init: impl FnOnce(&mut TaskControlBlockInner)) -> usize {
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
     // create a new thread
@@ -18,11 +25,14 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
             .ustack_base,
         true,
     ));
-    // add new task to scheduler
-    add_task(Arc::clone(&new_task));
-    let new_task_inner = new_task.inner_exclusive_access();
+    #[cfg(feature = "rc_audit")]
+    crate::task::track_task(&new_task);
+    // the new thread starts with the mask of its creator
+    new_task.inner_exclusive_access().signal_mask = task.inner_exclusive_access().signal_mask;
+    let mut new_task_inner = new_task.inner_exclusive_access();
     let new_task_res = new_task_inner.res.as_ref().unwrap();
     let new_task_tid = new_task_res.tid;
+    let ustack_top = new_task_res.ustack_top();
     let mut process_inner = process.inner_exclusive_access();
     // add new thread to current process
     let tasks = &mut process_inner.tasks;
@@ -30,56 +40,82 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
         tasks.push(None);
     }
     tasks[new_task_tid] = Some(Arc::clone(&new_task));
-    let new_task_trap_cx = new_task_inner.get_trap_cx();
-    *new_task_trap_cx = TrapContext::app_init_context(
-        entry,
-        new_task_res.ustack_top(),
-        kernel_token(),
+    drop(process_inner);
+    let trap_cx = new_task_inner.get_trap_cx();
+    *trap_cx = TrapContext::app_init_context(
+        0,
+        ustack_top,
+        trap_kernel_satp(),
         new_task.kstack.get_top(),
         trap_handler as usize,
     );
-    (*new_task_trap_cx).x[10] = arg;
-    new_task_tid as isize
+    init(&mut new_task_inner);
+    drop(new_task_inner);
+    // add new task to scheduler
+    add_task(new_task);
+    new_task_tid
+}
+
+pub fn sys_thread_create(entry: usize, arg: usize) -> SysResult {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let (tls, token) = (process_inner.tls.clone(), process_inner.get_user_token());
+    drop(process_inner);
+    Ok(spawn_thread(|task_inner| {
+        let trap_cx = task_inner.get_trap_cx();
+        // the thread gets its own copy of the thread-locals on top of its stack
+        if let Some(tls) = tls {
+            let tp = tls.install(token, trap_cx.x[2]);
+            trap_cx.set_sp(tp);
+            trap_cx.x[4] = tp;
+        }
+        trap_cx.sepc = entry;
+        trap_cx.x[10] = arg;
+    }))
 }
 
-pub fn sys_gettid() -> isize {
-    current_task()
+pub fn sys_gettid() -> SysResult {
+    Ok(current_task()
         .unwrap()
         .inner_exclusive_access()
         .res
         .as_ref()
         .unwrap()
-        .tid as isize
+        .tid)
 }
 
-/// thread does not exist, return -1
-/// thread has not exited yet, return -2
-/// otherwise, return thread's exit code
-pub fn sys_waittid(tid: usize) -> i32 {
+/// thread does not exist, fail with ESRCH
+/// thread has not exited yet, fail with EAGAIN
+/// otherwise, store thread's exit code at `exit_code_ptr` and return `tid`
+pub fn sys_waittid(tid: usize, exit_code_ptr: *mut i32) -> SysResult {
+    // checked before the thread is reaped, so that EFAULT does not lose it;
+    // faulted in without the PCB
+    let exit_code_slot = translated_refmut(current_user_token(), exit_code_ptr)?;
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
     let task_inner = task.inner_exclusive_access();
     let mut process_inner = process.inner_exclusive_access();
     // a thread cannot wait for itself
     if task_inner.res.as_ref().unwrap().tid == tid {
-        return -1;
+        return Err(SysError::EDEADLK);
     }
     let mut exit_code: Option<i32> = None;
-    let waited_task = process_inner.tasks[tid].as_ref();
+    let waited_task = process_inner.tasks.get(tid).and_then(|task| task.as_ref());
     if let Some(waited_task) = waited_task {
         if let Some(waited_exit_code) = waited_task.inner_exclusive_access().exit_code {
             exit_code = Some(waited_exit_code);
         }
     } else {
         // waited thread does not exist
-        return -1;
+        return Err(SysError::ESRCH);
     }
     if let Some(exit_code) = exit_code {
         // dealloc the exited thread
         process_inner.tasks[tid] = None;
-        exit_code
+        *exit_code_slot = exit_code;
+        Ok(tid)
     } else {
         // waited thread has not exited
-        -2
+        Err(SysError::EAGAIN)
     }
 }
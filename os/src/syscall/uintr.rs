@@ -0,0 +1,32 @@
+use super::{SysError, SysResult};
+use crate::task::{current_task, uintr_notify, UintrEvents};
+
+/// Register `handler` for `events`, a zero handler unregisters.
+pub fn sys_uintr_register(handler: usize, events: usize) -> SysResult {
+    let events = UintrEvents::from_bits(events).ok_or(SysError::EINVAL)?;
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.uintr.handler = handler;
+    task_inner.uintr.enabled = events;
+    task_inner.uintr.pending = UintrEvents::empty();
+    Ok(0)
+}
+
+pub fn sys_uintr_notify(pid: usize) -> SysResult {
+    if uintr_notify(pid) {
+        Ok(0)
+    } else {
+        Err(SysError::ESRCH)
+    }
+}
+
+/// Resume the code interrupted by the handler, a0 is the interrupted one.
+pub fn sys_uintr_return() -> SysResult {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let [pc, a0, a7] = task_inner.uintr.saved.take().ok_or(SysError::EINVAL)?;
+    let trap_cx = task_inner.get_trap_cx();
+    trap_cx.sepc = pc;
+    trap_cx.x[17] = a7;
+    Ok(a0)
+}
@@ -0,0 +1,118 @@
+//! Reference audit of the process tree, built with the `rc_audit` feature.
+//!
+//! Children, threads and ready queues hold processes and threads by Arc,
+//! while parents and the process of a thread are only Weak, so that the
+//! tree cannot keep itself alive. Every process and thread is remembered
+//! here by a Weak reference as well; one still alive that cannot be reached
+//! from initproc or the pid table anymore has a strong reference left
+//! somewhere that keeps it from being freed.
+
+use super::{all_processes, ProcessControlBlock, TaskControlBlock, INITPROC};
+use crate::sync::UPIntrFreeCell;
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use lazy_static::*;
+
+#[derive(Default)]
+struct Tracked {
+    processes: Vec<Weak<ProcessControlBlock>>,
+    tasks: Vec<Weak<TaskControlBlock>>,
+}
+
+lazy_static! {
+    static ref TRACKED: UPIntrFreeCell<Tracked> =
+        unsafe { UPIntrFreeCell::new(Tracked::default()) };
+}
+
+pub fn track_process(process: &Arc<ProcessControlBlock>) {
+    TRACKED.exclusive_session(|tracked| {
+        tracked.processes.retain(|weak| weak.strong_count() > 0);
+        tracked.processes.push(Arc::downgrade(process));
+    });
+}
+
+pub fn track_task(task: &Arc<TaskControlBlock>) {
+    TRACKED.exclusive_session(|tracked| {
+        tracked.tasks.retain(|weak| weak.strong_count() > 0);
+        tracked.tasks.push(Arc::downgrade(task));
+    });
+}
+
+/// A line for every process and thread kept alive though nothing in the
+/// tree leads to it, with the strong references left on it, and a summary
+/// line `rc_audit: N leaked of M alive`.
+pub fn audit() -> String {
+    // reachable processes by address, a pid may be reused once freed
+    let mut reachable: BTreeSet<usize> = BTreeSet::new();
+    let mut reachable_tasks: BTreeSet<usize> = BTreeSet::new();
+    let mut queue: Vec<Arc<ProcessControlBlock>> = all_processes();
+    queue.push(INITPROC.clone());
+    while let Some(process) = queue.pop() {
+        if !reachable.insert(Arc::as_ptr(&process) as usize) {
+            continue;
+        }
+        let inner = process.inner_exclusive_access();
+        queue.extend(inner.children.iter().cloned());
+        for task in inner.tasks.iter().flatten() {
+            reachable_tasks.insert(Arc::as_ptr(task) as usize);
+        }
+    }
+    let (processes, tasks) = TRACKED.exclusive_session(|tracked| {
+        (
+            tracked
+                .processes
+                .iter()
+                .filter_map(Weak::upgrade)
+                .collect::<Vec<_>>(),
+            tracked
+                .tasks
+                .iter()
+                .filter_map(Weak::upgrade)
+                .collect::<Vec<_>>(),
+        )
+    });
+    // the strong counts below leave out the references taken just above
+    let mut report = String::new();
+    let mut leaked = 0;
+    for process in processes.iter() {
+        if reachable.contains(&(Arc::as_ptr(process) as usize)) {
+            continue;
+        }
+        leaked += 1;
+        let inner = process.inner_exclusive_access();
+        report += &format!(
+            "process pid={} name={} zombie={} strong={}\n",
+            process.getpid(),
+            inner.name,
+            inner.is_zombie,
+            Arc::strong_count(process) - 1
+        );
+    }
+    for task in tasks.iter() {
+        if reachable_tasks.contains(&(Arc::as_ptr(task) as usize)) {
+            continue;
+        }
+        leaked += 1;
+        let pid = task.process.upgrade().map(|process| process.getpid());
+        let tid = task
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .map(|res| res.tid);
+        report += &format!(
+            "task pid={:?} tid={:?} strong={}\n",
+            pid,
+            tid,
+            Arc::strong_count(task) - 1
+        );
+    }
+    report += &format!(
+        "rc_audit: {} leaked of {} alive\n",
+        leaked,
+        processes.len() + tasks.len()
+    );
+    report
+}
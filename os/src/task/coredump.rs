@@ -0,0 +1,176 @@
+//! Minimal ELF core files, enough for `gdb <app> core.<pid>` to show the
+//! registers of every thread and the user memory at the time of the crash.
+
+use super::{current_process, SignalFlags};
+use crate::config::PAGE_SIZE;
+use crate::fs::{open_file, OpenFlags};
+use crate::mm::{MapPermission, VirtAddr};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+/// `struct elf_prstatus` of riscv64 linux
+const PRSTATUS_SIZE: usize = 376;
+const PRSTATUS_PID_OFFSET: usize = 32;
+const PRSTATUS_REG_OFFSET: usize = 112;
+
+/// what a page without a frame reads as
+static ZERO_PAGE: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// NT_PRSTATUS note for one thread, `regs` holds pc followed by x1..x31.
+fn prstatus_note(signum: u32, pid: u32, ppid: u32, regs: &[usize; 32]) -> Vec<u8> {
+    let mut desc = [0u8; PRSTATUS_SIZE];
+    // si_signo and pr_cursig
+    put_u32(&mut desc, 0, signum);
+    desc[12..14].copy_from_slice(&(signum as u16).to_le_bytes());
+    put_u32(&mut desc, PRSTATUS_PID_OFFSET, pid);
+    put_u32(&mut desc, PRSTATUS_PID_OFFSET + 4, ppid);
+    for (i, reg) in regs.iter().enumerate() {
+        let offset = PRSTATUS_REG_OFFSET + i * 8;
+        desc[offset..offset + 8].copy_from_slice(&(*reg as u64).to_le_bytes());
+    }
+    let mut note = Vec::new();
+    push_u32(&mut note, 5);
+    push_u32(&mut note, PRSTATUS_SIZE as u32);
+    push_u32(&mut note, NT_PRSTATUS);
+    // "CORE\0" padded to 4 bytes
+    note.extend_from_slice(b"CORE\0\0\0\0");
+    note.extend_from_slice(&desc);
+    note
+}
+
+fn push_program_header(
+    buf: &mut Vec<u8>,
+    type_: u32,
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    size: usize,
+    align: usize,
+) {
+    push_u32(buf, type_);
+    push_u32(buf, flags);
+    push_u64(buf, offset as u64);
+    push_u64(buf, vaddr as u64);
+    push_u64(buf, 0);
+    push_u64(buf, size as u64);
+    push_u64(buf, size as u64);
+    push_u64(buf, align as u64);
+}
+
+/// Write `core.<pid>` for the current process killed by `signal`,
+/// return the name of the file. Only the headers are put together in
+/// memory, the pages go to the file one at a time.
+pub fn dump_core_of_current(signal: SignalFlags) -> Option<String> {
+    let process = current_process();
+    let pid = process.getpid();
+    let inner = process.inner_exclusive_access();
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid());
+    // one note per thread
+    let mut notes = Vec::new();
+    for task in inner.tasks.iter().flatten() {
+        let task_inner = task.inner_exclusive_access();
+        let tid = task_inner.res.as_ref().map_or(0, |res| res.tid);
+        let trap_cx = task_inner.get_trap_cx();
+        let mut regs = trap_cx.x;
+        regs[0] = trap_cx.sepc;
+        // threads have no ids of their own, keep them apart for gdb
+        notes.extend(prstatus_note(
+            signal.signum() as u32,
+            (pid + tid) as u32,
+            ppid as u32,
+            &regs,
+        ));
+    }
+    let areas = inner.memory_set.user_areas();
+    drop(inner);
+    let phnum = areas.len() + 1;
+    let data_offset = ELF_HEADER_SIZE + phnum * PROGRAM_HEADER_SIZE;
+    let mut core = Vec::new();
+    // ELF header
+    core.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    core.extend_from_slice(&[0; 8]);
+    push_u16(&mut core, ET_CORE);
+    push_u16(&mut core, EM_RISCV);
+    push_u32(&mut core, 1);
+    push_u64(&mut core, 0);
+    push_u64(&mut core, ELF_HEADER_SIZE as u64);
+    push_u64(&mut core, 0);
+    push_u32(&mut core, 0);
+    push_u16(&mut core, ELF_HEADER_SIZE as u16);
+    push_u16(&mut core, PROGRAM_HEADER_SIZE as u16);
+    push_u16(&mut core, phnum as u16);
+    push_u16(&mut core, 0);
+    push_u16(&mut core, 0);
+    push_u16(&mut core, 0);
+    // program headers, notes first and then page aligned segments
+    push_program_header(&mut core, PT_NOTE, 0, data_offset, 0, notes.len(), 4);
+    let mut offset = (data_offset + notes.len() + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+    for (vpn_range, perm) in areas.iter() {
+        let mut flags = 0;
+        if perm.contains(MapPermission::R) {
+            flags |= PF_R;
+        }
+        if perm.contains(MapPermission::W) {
+            flags |= PF_W;
+        }
+        if perm.contains(MapPermission::X) {
+            flags |= PF_X;
+        }
+        let start: VirtAddr = vpn_range.get_start().into();
+        let size = (vpn_range.get_end().0 - vpn_range.get_start().0) * PAGE_SIZE;
+        push_program_header(&mut core, PT_LOAD, flags, offset, start.0, size, PAGE_SIZE);
+        offset += size;
+    }
+    core.extend_from_slice(&notes);
+    core.resize((core.len() + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE, 0);
+    let name = format!("core.{}", pid);
+    let file = open_file(name.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY)?;
+    if file.write_all(&core) != core.len() {
+        return None;
+    }
+    // the PCB is borrowed for a page at a time, the writes may sleep
+    for (vpn_range, _) in areas.iter() {
+        for vpn in *vpn_range {
+            let frame = process.inner_exclusive_access().memory_set.user_frame(vpn);
+            let page: &[u8] = match &frame {
+                Some(frame) => frame.ppn.get_bytes_array(),
+                None => &ZERO_PAGE,
+            };
+            if file.write_all(page) != PAGE_SIZE {
+                return None;
+            }
+        }
+    }
+    Some(name)
+}
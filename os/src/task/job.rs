@@ -0,0 +1,119 @@
+//! Job control: process groups, and processes stopped until SIGCONT.
+
+use super::manager::all_processes;
+use super::{
+    block_current_and_run_next, current_process, current_task, deliverable_signals_of_current,
+    wakeup_task, ProcessControlBlock, SignalFlags, TaskControlBlock, SIG_DFL,
+};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Signals whose default action is to stop the process.
+pub const STOP_SIGNALS: SignalFlags = SignalFlags::from_bits_truncate(
+    SignalFlags::SIGSTOP.bits()
+        | SignalFlags::SIGTSTP.bits()
+        | SignalFlags::SIGTTIN.bits()
+        | SignalFlags::SIGTTOU.bits(),
+);
+
+#[derive(Default)]
+pub struct JobState {
+    /// signal the process has stopped with, until SIGCONT
+    pub stop_signal: Option<SignalFlags>,
+    /// whether waitpid has told the parent about the current stop
+    pub stop_reported: bool,
+    /// continued since waitpid last looked
+    pub continued: bool,
+    pub stopped_tasks: Vec<Arc<TaskControlBlock>>,
+}
+
+impl JobState {
+    /// Let every stopped thread run again.
+    pub fn resume(&mut self) {
+        if self.stop_signal.take().is_some() {
+            self.continued = true;
+        }
+        for task in self.stopped_tasks.drain(..) {
+            wakeup_task(task);
+        }
+    }
+}
+
+fn notify_parent(process: &ProcessControlBlock) {
+    let parent = process.inner_exclusive_access().parent.clone();
+    if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+        parent.inner_exclusive_access().raise(SignalFlags::SIGCHLD);
+    }
+}
+
+/// Raise `signal` in `process`, SIGCONT and SIGKILL wake it up if it has stopped.
+pub fn send_signal(process: &Arc<ProcessControlBlock>, signal: SignalFlags) {
+    let mut inner = process.inner_exclusive_access();
+    inner.raise(signal);
+    if signal.intersects(STOP_SIGNALS) {
+        inner.signals.remove(SignalFlags::SIGCONT);
+    }
+    if signal.contains(SignalFlags::SIGCONT) {
+        inner.signals.remove(STOP_SIGNALS);
+    }
+    // a stopped tracee has to wake up to die
+    if signal.contains(SignalFlags::SIGKILL) {
+        if let Some(state) = inner.ptrace.as_mut() {
+            state.resume(None);
+        }
+    }
+    if signal.intersects(SignalFlags::SIGCONT | SignalFlags::SIGKILL)
+        && inner.job.stop_signal.is_some()
+    {
+        inner.job.resume();
+        drop(inner);
+        notify_parent(process);
+    }
+}
+
+/// Every live process in group `pgid`.
+pub fn process_group(pgid: usize) -> Vec<Arc<ProcessControlBlock>> {
+    all_processes()
+        .into_iter()
+        .filter(|process| process.inner_exclusive_access().pgid == pgid)
+        .collect()
+}
+
+/// Raise `signal` in every process of group `pgid`, false if there is none.
+pub fn signal_group(pgid: usize, signal: SignalFlags) -> bool {
+    let group = process_group(pgid);
+    for process in group.iter() {
+        send_signal(process, signal);
+    }
+    !group.is_empty()
+}
+
+/// Called before returning to user mode, after a tracer has had its say. A
+/// stop signal left to its default action stops every thread of the process
+/// until SIGCONT arrives.
+pub fn job_stop_current() {
+    let deliverable = deliverable_signals_of_current();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let stop = (1..=super::MAX_SIG)
+        .filter_map(SignalFlags::from_signum)
+        .filter(|signal| deliverable.contains(*signal) && signal.intersects(STOP_SIGNALS))
+        .find(|signal| inner.signal_actions.table[signal.signum()].handler == SIG_DFL);
+    if let Some(signal) = stop {
+        inner.signals.remove(signal);
+        if inner.job.stop_signal.is_none() {
+            inner.job.stop_signal = Some(signal);
+            inner.job.stop_reported = false;
+            drop(inner);
+            notify_parent(&process);
+            inner = process.inner_exclusive_access();
+        }
+    }
+    // SIGKILL gets through
+    if inner.job.stop_signal.is_none() || inner.signals.contains(SignalFlags::SIGKILL) {
+        return;
+    }
+    inner.job.stopped_tasks.push(current_task().unwrap());
+    drop(inner);
+    block_current_and_run_next();
+}
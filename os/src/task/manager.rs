@@ -1,9 +1,13 @@
-use super::{ProcessControlBlock, TaskControlBlock, TaskStatus};
-use crate::sync::UPIntrFreeCell;
+use super::{hart_id, ProcessControlBlock, TaskControlBlock, TaskStatus};
+use crate::config::MAX_HARTS;
+use crate::sync::{Inbox, UPIntrFreeCell};
 use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
+/// Ready queue of one hart, only touched by that hart, so no lock is shared
+/// between harts.
 pub struct TaskManager {
     ready_queue: VecDeque<Arc<TaskControlBlock>>,
 }
@@ -24,25 +28,43 @@ impl TaskManager {
 }
 
 lazy_static! {
-    pub static ref TASK_MANAGER: UPIntrFreeCell<TaskManager> =
-        unsafe { UPIntrFreeCell::new(TaskManager::new()) };
+    static ref READY_QUEUES: Vec<UPIntrFreeCell<TaskManager>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPIntrFreeCell::new(TaskManager::new()) })
+        .collect();
+    /// tasks woken up for a hart by the other ones
+    static ref INBOXES: Vec<Inbox<Arc<TaskControlBlock>>> =
+        (0..MAX_HARTS).map(|_| Inbox::new()).collect();
     pub static ref PID2PCB: UPIntrFreeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
         unsafe { UPIntrFreeCell::new(BTreeMap::new()) };
 }
 
+/// Queue a new or preempted task on the current hart.
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    let hart = hart_id();
+    task.inner_exclusive_access().hart = hart;
+    READY_QUEUES[hart].exclusive_access().add(task);
 }
 
+/// Make a blocked task ready again on the hart it last ran on.
 pub fn wakeup_task(task: Arc<TaskControlBlock>) {
     let mut task_inner = task.inner_exclusive_access();
     task_inner.task_status = TaskStatus::Ready;
+    let hart = task_inner.hart;
     drop(task_inner);
-    add_task(task);
+    if hart == hart_id() {
+        READY_QUEUES[hart].exclusive_access().add(task);
+    } else {
+        INBOXES[hart].push(task);
+    }
 }
 
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.exclusive_access().fetch()
+    let hart = hart_id();
+    let mut queue = READY_QUEUES[hart].exclusive_access();
+    for task in INBOXES[hart].take_all() {
+        queue.add(task);
+    }
+    queue.fetch()
 }
 
 pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
@@ -50,6 +72,10 @@ pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
     map.get(&pid).map(Arc::clone)
 }
 
+pub fn all_processes() -> Vec<Arc<ProcessControlBlock>> {
+    PID2PCB.exclusive_access().values().cloned().collect()
+}
+
 pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
     PID2PCB.exclusive_access().insert(pid, process);
 }
@@ -1,31 +1,57 @@
+#[cfg(feature = "rc_audit")]
+mod audit;
 mod context;
+mod coredump;
 mod id;
+mod job;
 mod manager;
 mod process;
 mod processor;
+mod ptrace;
 mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
+mod tls;
+mod uintr;
 
 use self::id::TaskUserRes;
 use crate::fs::{open_file, OpenFlags};
-use crate::sbi::shutdown;
+use crate::mm::translated_refmut;
+use crate::power::power_off;
+use crate::sync::{futex_forget, futex_wake, FUTEX_BITSET_MATCH_ANY};
+use crate::timer::{add_timer, remove_timer};
 use alloc::{sync::Arc, vec::Vec};
 use lazy_static::*;
 use manager::fetch_task;
-use process::ProcessControlBlock;
+pub use process::ProcessControlBlock;
+use process::ProcessControlBlockInner;
 use switch::__switch;
 
+#[cfg(feature = "rc_audit")]
+pub use audit::{audit, track_task};
 pub use context::TaskContext;
+pub use coredump::dump_core_of_current;
 pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle, IDLE_PID};
-pub use manager::{add_task, pid2process, remove_from_pid2process, wakeup_task};
+pub use job::{job_stop_current, process_group, send_signal, signal_group, JobState};
+pub use manager::{add_task, all_processes, pid2process, remove_from_pid2process, wakeup_task};
 pub use processor::{
     current_kstack_top, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, run_tasks, schedule, take_current_task,
+    current_user_satp, current_user_token, fault_in_current_page, hart_id, run_tasks, schedule,
+    schedule_exited, take_current_task,
+};
+pub use ptrace::{
+    ptrace_step_breakpoint_hit, ptrace_stop_current, ptrace_trigger_hit, PtraceState,
+};
+pub use signal::{
+    SignalAction, SignalActions, SignalFlags, SignalStack, MAX_SIG, MINSIGSTKSZ, SA_ONSTACK,
+    SIG_BLOCK, SIG_DFL, SIG_SETMASK, SIG_UNBLOCK, SS_DISABLE, SS_ONSTACK,
+};
+pub use task::{TaskControlBlock, TaskControlBlockInner, TaskStatus};
+pub use tls::TlsTemplate;
+pub use uintr::{
+    deliver_uintr_of_current, uintr_notify, uintr_tick_current, UintrEvents, UintrState,
 };
-pub use signal::SignalFlags;
-pub use task::{TaskControlBlock, TaskStatus};
 
 pub fn suspend_current_and_run_next() {
     // There must be an application running.
@@ -58,8 +84,75 @@ pub fn block_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// Write the dirty pages of the shared file mappings of `process` back.
+pub fn write_back_shared_mappings(process: &ProcessControlBlock) {
+    let dirty = process
+        .inner_exclusive_access()
+        .memory_set
+        .take_all_dirty_pages();
+    for page in dirty {
+        page.write_back();
+    }
+}
+
 /// Exit the current 'Running' task and run the next task in task list.
+/// End the current thread, and with it the process if it is the main one.
 pub fn exit_current_and_run_next(exit_code: i32) {
+    exit_current(exit_code, false);
+}
+
+/// End the whole process of the current thread, whichever thread it is.
+/// The others find their process gone the next time they would return to
+/// it and leave through [`exit_current_if_reaped`].
+pub fn exit_group_current_and_run_next(exit_code: i32) {
+    exit_current(exit_code, true);
+}
+
+/// Leave the current thread for good if the exit of its process already
+/// took its user resources, without touching any of them. Threads blocked
+/// or preempted when their process ends come here before going back to
+/// user mode.
+pub fn exit_current_if_reaped() {
+    if current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .is_some()
+    {
+        return;
+    }
+    let task = take_current_task().unwrap();
+    // the parent may have reaped the process already
+    if let Some(process) = task.process.upgrade() {
+        let exit_code = process.inner_exclusive_access().exit_code;
+        task.inner_exclusive_access().exit_code = Some(exit_code);
+    }
+    // nothing but this may hold the task and its kstack we are still on
+    schedule_exited(task);
+}
+
+fn exit_current(exit_code: i32, whole_process: bool) {
+    // whoever joins the thread waits for the kernel to clear its id
+    let clear_child_tid = current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .clear_child_tid;
+    if clear_child_tid != 0 {
+        let token = current_user_token();
+        // a bad address is the thread's own business, it goes unnoticed
+        if let Ok(tid) = translated_refmut(token, clear_child_tid as *mut u32) {
+            *tid = 0;
+            let _ = futex_wake(
+                token,
+                clear_child_tid as *mut u32,
+                1,
+                FUTEX_BITSET_MATCH_ANY,
+            );
+        }
+    }
+    // shared file mappings reach their files while the task can still wait
+    // for the disk
+    write_back_shared_mappings(&current_process());
     let task = take_current_task().unwrap();
     let mut task_inner = task.inner_exclusive_access();
     let process = task.process.upgrade().unwrap();
@@ -71,22 +164,16 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     // it will be deallocated when sys_waittid is called
     drop(task_inner);
     drop(task);
-    // however, if this is the main thread of current process
-    // the process should terminate at once
-    if tid == 0 {
+    // however, if this is the main thread of current process or the whole
+    // process exits, the process should terminate at once
+    if tid == 0 || whole_process {
         let pid = process.getpid();
         if pid == IDLE_PID {
             println!(
                 "[kernel] Idle process exit with exit_code {} ...",
                 exit_code
             );
-            if exit_code != 0 {
-                //crate::sbi::shutdown(255); //255 == -1 for err hint
-                shutdown(true);
-            } else {
-                //crate::sbi::shutdown(0); //0 for success hint
-                shutdown(false);
-            }
+            power_off(exit_code != 0);
         }
         remove_from_pid2process(pid);
         let mut process_inner = process.inner_exclusive_access();
@@ -94,12 +181,21 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         process_inner.is_zombie = true;
         // record exit code of main process
         process_inner.exit_code = exit_code;
+        if let Some(parent) = process_inner.parent.as_ref().and_then(|p| p.upgrade()) {
+            parent.inner_exclusive_access().raise(SignalFlags::SIGCHLD);
+        }
 
         {
             // move all child processes under init process
             let mut initproc_inner = INITPROC.inner_exclusive_access();
             for child in process_inner.children.iter() {
-                child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+                let mut child_inner = child.inner_exclusive_access();
+                // detach tracees, they must not stay stopped forever
+                if let Some(mut state) = child_inner.ptrace.take() {
+                    state.resume(None);
+                }
+                child_inner.parent = Some(Arc::downgrade(&INITPROC));
+                drop(child_inner);
                 initproc_inner.children.push(child.clone());
             }
         }
@@ -120,6 +216,9 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         // for now to avoid deadlock/double borrow problem.
         drop(process_inner);
         recycle_res.clear();
+        // threads asleep on a futex go with the process, the queues must
+        // not keep them
+        futex_forget(&process);
 
         let mut process_inner = process.inner_exclusive_access();
         process_inner.children.clear();
@@ -127,12 +226,13 @@ pub fn exit_current_and_run_next(exit_code: i32) {
         process_inner.memory_set.recycle_data_pages();
         // drop file descriptors
         process_inner.fd_table.clear();
-        // Remove all tasks except for the main thread itself.
+        // Remove all tasks except for the main thread and the current one.
         // This is because we are still using the kstack under the TCB
-        // of the main thread. This TCB, including its kstack, will be
+        // of the current thread. This TCB, including its kstack, will be
         // deallocated when the process is reaped via waitpid.
-        while process_inner.tasks.len() > 1 {
-            process_inner.tasks.pop();
+        process_inner.tasks.truncate(tid + 1);
+        for task in process_inner.tasks[1..tid.max(1)].iter_mut() {
+            *task = None;
         }
     }
     drop(process);
@@ -145,7 +245,7 @@ lazy_static! {
     pub static ref INITPROC: Arc<ProcessControlBlock> = {
         let inode = open_file("initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        ProcessControlBlock::new(v.as_slice())
+        ProcessControlBlock::new("initproc", v.as_slice())
     };
 }
 
@@ -153,10 +253,111 @@ pub fn add_initproc() {
     let _initproc = INITPROC.clone();
 }
 
-pub fn check_signals_of_current() -> Option<(i32, &'static str)> {
-    let process = current_process();
+/// Pending signals which are not blocked by the current thread and would
+/// not be dropped, i.e. the ones interrupting sigsuspend.
+pub fn deliverable_signals_of_current() -> SignalFlags {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
     let process_inner = process.inner_exclusive_access();
-    process_inner.signals.check_error()
+    let mask = task.inner_exclusive_access().signal_mask;
+    deliverable_signals(&process_inner, mask)
+}
+
+fn deliverable_signals(process_inner: &ProcessControlBlockInner, mask: SignalFlags) -> SignalFlags {
+    let mut deliverable = SignalFlags::empty();
+    for signum in 1..=MAX_SIG {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        if process_inner.signals.contains(signal)
+            && (!mask.contains(signal) || signal.intersects(SignalFlags::SYNCHRONOUS))
+            && !process_inner.signal_actions.is_ignored(signal)
+        {
+            deliverable |= signal;
+        }
+    }
+    deliverable
+}
+
+/// Block the current thread until it is woken, a signal is raised or the
+/// time passes `deadline`, in nanoseconds since boot. Returns true if a
+/// signal can be delivered, the caller sleeps again otherwise if it wants.
+pub fn sleep_interruptible(deadline: Option<u64>) -> bool {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // checked and blocked at once, a signal raised in between is not missed
+    let process_inner = process.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    if !deliverable_signals(&process_inner, task_inner.signal_mask).is_empty() {
+        return true;
+    }
+    task_inner.interruptible = true;
+    task_inner.task_status = TaskStatus::Blocked;
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    drop(task_inner);
+    drop(process_inner);
+    drop(process);
+    take_current_task();
+    if let Some(deadline) = deadline {
+        add_timer(deadline.div_ceil(1_000_000) as usize, task.clone());
+    }
+    schedule(task_cx_ptr);
+    task.inner_exclusive_access().interruptible = false;
+    if deadline.is_some() {
+        remove_timer(&task);
+    }
+    !deliverable_signals_of_current().is_empty()
+}
+
+/// Called before returning to user mode. Pending signals not blocked by the
+/// current thread are dropped, or redirect the thread to their handler, or
+/// yield the signal which kills the process.
+pub fn handle_signals_of_current() -> Option<SignalFlags> {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    for signum in 1..=MAX_SIG {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        if !process_inner.signals.contains(signal)
+            || (task_inner.signal_mask.contains(signal)
+                && !signal.intersects(SignalFlags::SYNCHRONOUS))
+        {
+            continue;
+        }
+        let action = process_inner.signal_actions.table[signum];
+        if process_inner.signal_actions.is_ignored(signal) {
+            process_inner.signals.remove(signal);
+        } else if action.handler == SIG_DFL {
+            return Some(signal);
+        } else if task_inner.signal_frame.is_none() {
+            // handlers do not nest, others stay pending until sigreturn
+            process_inner.signals.remove(signal);
+            let old_mask = task_inner
+                .sigsuspend_mask
+                .take()
+                .unwrap_or(task_inner.signal_mask);
+            let trap_cx = task_inner.get_trap_cx();
+            task_inner.signal_frame = Some((*trap_cx, old_mask));
+            // switch stacks unless we are on the alternate one already, in
+            // which case sigreturn will bring back the interrupted sp
+            let stack = task_inner.signal_stack;
+            if action.flags & SA_ONSTACK != 0 && stack.enabled() && !stack.contains(trap_cx.x[2]) {
+                trap_cx.set_sp((stack.sp + stack.size) & !0xf);
+            }
+            trap_cx.sepc = action.handler;
+            trap_cx.x[1] = action.restorer;
+            trap_cx.x[10] = signum;
+            task_inner.signal_mask |= action.mask | signal;
+            task_inner.signal_mask.remove(SignalFlags::UNCATCHABLE);
+            break;
+        } else if signal.intersects(SignalFlags::SYNCHRONOUS) {
+            // faulted inside the handler
+            return Some(signal);
+        }
+    }
+    if let Some(mask) = task_inner.sigsuspend_mask.take() {
+        task_inner.signal_mask = mask;
+    }
+    None
 }
 
 pub fn current_add_signal(signal: SignalFlags) {
@@ -1,16 +1,25 @@
+#[cfg(feature = "rc_audit")]
+use super::audit;
 use super::id::RecycleAllocator;
 use super::manager::insert_into_pid2process;
-use super::TaskControlBlock;
-use super::{add_task, SignalFlags};
-use super::{pid_alloc, PidHandle};
+use super::{add_task, JobState, PtraceState, SignalActions, SignalFlags, SignalStack, UintrState};
+use super::{pid_alloc, PidHandle, TlsTemplate};
+use super::{wakeup_task, TaskControlBlock, TaskStatus};
+use crate::config::{BRK_BASE, PAGE_SIZE};
 use crate::fs::{File, Stdin, Stdout};
-use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
+#[cfg(feature = "frame_debug")]
+use crate::mm::{frames_owned_by, FrameOwner};
+use crate::mm::{translated_refmut, MemorySet};
+use crate::random::get_random_bytes;
 use crate::sync::{Condvar, Mutex, Semaphore, UPIntrFreeCell, UPIntrRefMut};
-use crate::trap::{trap_handler, TrapContext};
+use crate::syscall::SyscallAbi;
+use crate::trap::{discard_ext_state, sync_ext_state, trap_handler, trap_kernel_satp, TrapContext};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
+use easy_fs::Inode;
+use xmas_elf::program::Type;
 
 pub struct ProcessControlBlock {
     // immutable
@@ -25,8 +34,25 @@ pub struct ProcessControlBlockInner {
     pub parent: Option<Weak<ProcessControlBlock>>,
     pub children: Vec<Arc<ProcessControlBlock>>,
     pub exit_code: i32,
+    /// argv[0] of the last exec, as shown by ps
+    pub name: String,
+    /// syscall numbering of the running image
+    pub abi: SyscallAbi,
+    /// end of the heap grown by brk, which starts at BRK_BASE
+    pub brk: usize,
+    /// thread-local storage every new thread gets a copy of
+    pub tls: Option<Arc<TlsTemplate>>,
     pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// pending signals, taken by whichever thread does not block them
     pub signals: SignalFlags,
+    pub signal_actions: SignalActions,
+    /// write a core file when killed by a fault
+    pub dumpable: bool,
+    /// set while the parent is tracing this process
+    pub ptrace: Option<PtraceState>,
+    /// process group, signalled as a whole by kill and the tty
+    pub pgid: usize,
+    pub job: JobState,
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
     pub task_res_allocator: RecycleAllocator,
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
@@ -41,7 +67,15 @@ impl ProcessControlBlockInner {
     }
 
     pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+        self.alloc_fd_from(0)
+    }
+
+    /// The lowest free fd not below `min`.
+    pub fn alloc_fd_from(&mut self, min: usize) -> usize {
+        if self.fd_table.len() < min {
+            self.fd_table.resize(min, None);
+        }
+        if let Some(fd) = (min..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
             fd
         } else {
             self.fd_table.push(None);
@@ -49,6 +83,19 @@ impl ProcessControlBlockInner {
         }
     }
 
+    /// Raise `signal`, waking the threads sleeping until a signal comes.
+    pub fn raise(&mut self, signal: SignalFlags) {
+        self.signals |= signal;
+        for task in self.tasks.iter().flatten() {
+            let mut task_inner = task.inner_exclusive_access();
+            if task_inner.interruptible && task_inner.task_status == TaskStatus::Blocked {
+                task_inner.interruptible = false;
+                drop(task_inner);
+                wakeup_task(task.clone());
+            }
+        }
+    }
+
     pub fn alloc_tid(&mut self) -> usize {
         self.task_res_allocator.alloc()
     }
@@ -66,16 +113,41 @@ impl ProcessControlBlockInner {
     }
 }
 
+/// Every frame of a process is gone with its address space, what is left
+/// was leaked by whoever still holds it.
+#[cfg(feature = "frame_debug")]
+impl Drop for ProcessControlBlock {
+    fn drop(&mut self) {
+        self.inner.exclusive_access().memory_set.recycle_all_pages();
+        let leaked = frames_owned_by(FrameOwner::Process(self.pid.0));
+        if !leaked.is_empty() {
+            println!(
+                "[kernel] pid {} exited with {} frames leaked: {:x?}",
+                self.pid.0,
+                leaked.len(),
+                leaked.iter().map(|ppn| ppn.0).collect::<Vec<_>>()
+            );
+        }
+    }
+}
+
 impl ProcessControlBlock {
     pub fn inner_exclusive_access(&self) -> UPIntrRefMut<'_, ProcessControlBlockInner> {
         self.inner.exclusive_access()
     }
 
-    pub fn new(elf_data: &[u8]) -> Arc<Self> {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+    pub fn try_inner_exclusive_access(&self) -> Option<UPIntrRefMut<'_, ProcessControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
+
+    pub fn new(name: &str, elf_data: &[u8]) -> Arc<Self> {
         // allocate a pid
         let pid_handle = pid_alloc();
+        // the first process leads its own group
+        let pgid = pid_handle.0;
+        // memory_set with elf program headers/trampoline/trap context/user stack
+        let (memory_set, ustack_base, entry_point) =
+            MemorySet::from_elf(elf_data, pid_handle.0, None);
         let process = Arc::new(Self {
             pid: pid_handle,
             inner: unsafe {
@@ -85,15 +157,24 @@ impl ProcessControlBlock {
                     parent: None,
                     children: Vec::new(),
                     exit_code: 0,
+                    name: String::from(name),
+                    abi: SyscallAbi::of_elf(elf_data),
+                    brk: BRK_BASE,
+                    tls: TlsTemplate::of_elf(elf_data).map(Arc::new),
                     fd_table: vec![
                         // 0 -> stdin
-                        Some(Arc::new(Stdin)),
+                        Some(Arc::new(Stdin::default())),
                         // 1 -> stdout
-                        Some(Arc::new(Stdout)),
+                        Some(Arc::new(Stdout::default())),
                         // 2 -> stderr
-                        Some(Arc::new(Stdout)),
+                        Some(Arc::new(Stdout::default())),
                     ],
                     signals: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    dumpable: false,
+                    ptrace: None,
+                    pgid,
+                    job: JobState::default(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
@@ -108,19 +189,32 @@ impl ProcessControlBlock {
             ustack_base,
             true,
         ));
+        #[cfg(feature = "rc_audit")]
+        {
+            audit::track_process(&process);
+            audit::track_task(&task);
+        }
         // prepare trap_cx of main thread
         let task_inner = task.inner_exclusive_access();
         let trap_cx = task_inner.get_trap_cx();
-        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        let mut ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
         let kstack_top = task.kstack.get_top();
         drop(task_inner);
+        let process_inner = process.inner_exclusive_access();
+        let (tls, token) = (process_inner.tls.clone(), process_inner.get_user_token());
+        drop(process_inner);
+        let tp = tls.map(|tls| {
+            ustack_top = tls.install(token, ustack_top);
+            ustack_top
+        });
         *trap_cx = TrapContext::app_init_context(
             entry_point,
             ustack_top,
-            KERNEL_SPACE.exclusive_access().token(),
+            trap_kernel_satp(),
             kstack_top,
             trap_handler as usize,
         );
+        trap_cx.x[4] = tp.unwrap_or(0);
         // add main thread to the process
         let mut process_inner = process.inner_exclusive_access();
         process_inner.tasks.push(Some(Arc::clone(&task)));
@@ -131,54 +225,70 @@ impl ProcessControlBlock {
         process
     }
 
-    /// Only support processes with a single thread.
-    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
+    /// Only support processes with a single thread. `file` is the inode
+    /// the elf was read from, whose text pages can then be shared.
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8], file: Option<Arc<Inode>>, args: Vec<String>) {
         assert_eq!(self.inner_exclusive_access().thread_count(), 1);
+        super::write_back_shared_mappings(self);
+        // a tracee gets its own text to plant breakpoints in
+        let file = file.filter(|_| self.inner_exclusive_access().ptrace.is_none());
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, ustack_base, entry_point) =
+            MemorySet::from_elf(elf_data, self.pid.0, file.as_ref());
         let new_token = memory_set.token();
+        // a unified kernel must leave the old space before dropping it
+        #[cfg(feature = "unified")]
+        let memory_set = {
+            let mut memory_set = memory_set;
+            memory_set.switch_in();
+            memory_set
+        };
         // substitute memory_set
         self.inner_exclusive_access().memory_set = memory_set;
+        self.inner_exclusive_access()
+            .signal_actions
+            .reset_handlers();
+        self.inner_exclusive_access().brk = BRK_BASE;
+        let tls = TlsTemplate::of_elf(elf_data).map(Arc::new);
+        self.inner_exclusive_access().tls = tls.clone();
+        if let Some(name) = args.first() {
+            self.inner_exclusive_access().name = name.clone();
+        }
+        let abi = SyscallAbi::of_elf(elf_data);
+        self.inner_exclusive_access().abi = abi;
         // then we alloc user resource for main thread again
         // since memory_set has been changed
         let task = self.inner_exclusive_access().get_task(0);
+        discard_ext_state();
         let mut task_inner = task.inner_exclusive_access();
+        task_inner.signal_frame = None;
+        task_inner.signal_stack = SignalStack::default();
+        task_inner.uintr = UintrState::default();
+        task_inner.clear_child_tid = 0;
         task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
         task_inner.res.as_mut().unwrap().alloc_user_res();
         task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
         // push arguments on user stack
-        let mut user_sp = task_inner.res.as_mut().unwrap().ustack_top();
-        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
-        let argv_base = user_sp;
-        let mut argv: Vec<_> = (0..=args.len())
-            .map(|arg| {
-                translated_refmut(
-                    new_token,
-                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
-                )
-            })
-            .collect();
-        *argv[args.len()] = 0;
-        for i in 0..args.len() {
-            user_sp -= args[i].len() + 1;
-            *argv[i] = user_sp;
-            let mut p = user_sp;
-            for c in args[i].as_bytes() {
-                *translated_refmut(new_token, p as *mut u8) = *c;
-                p += 1;
+        let mut ustack_top = task_inner.res.as_mut().unwrap().ustack_top();
+        let tp = tls.map(|tls| {
+            ustack_top = tls.install(new_token, ustack_top);
+            ustack_top
+        });
+        let (user_sp, argv_base) = match abi {
+            SyscallAbi::Native => push_args(new_token, ustack_top, &args),
+            SyscallAbi::Linux => {
+                push_linux_args(new_token, ustack_top, &args, &elf_aux_vector(elf_data))
             }
-            *translated_refmut(new_token, p as *mut u8) = 0;
-        }
-        // make the user_sp aligned to 8B for k210 platform
-        user_sp -= user_sp % core::mem::size_of::<usize>();
+        };
         // initialize trap_cx
         let mut trap_cx = TrapContext::app_init_context(
             entry_point,
             user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
+            trap_kernel_satp(),
             task.kstack.get_top(),
             trap_handler as usize,
         );
+        trap_cx.x[4] = tp.unwrap_or(0);
         trap_cx.x[10] = args.len();
         trap_cx.x[11] = argv_base;
         *task_inner.get_trap_cx() = trap_cx;
@@ -188,10 +298,10 @@ impl ProcessControlBlock {
     pub fn fork(self: &Arc<Self>) -> Arc<Self> {
         let mut parent = self.inner_exclusive_access();
         assert_eq!(parent.thread_count(), 1);
-        // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
-        let memory_set = MemorySet::from_existed_user(&parent.memory_set);
         // alloc a pid
         let pid = pid_alloc();
+        // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
+        let memory_set = MemorySet::from_existed_user(&parent.memory_set, pid.0);
         // copy fd table
         let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
         for fd in parent.fd_table.iter() {
@@ -211,8 +321,17 @@ impl ProcessControlBlock {
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
                     exit_code: 0,
+                    name: parent.name.clone(),
+                    abi: parent.abi,
+                    brk: parent.brk,
+                    tls: parent.tls.clone(),
                     fd_table: new_fd_table,
                     signals: SignalFlags::empty(),
+                    signal_actions: parent.signal_actions.clone(),
+                    dumpable: parent.dumpable,
+                    ptrace: None,
+                    pgid: parent.pgid,
+                    job: JobState::default(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
@@ -237,6 +356,22 @@ impl ProcessControlBlock {
             // but mention that we allocate a new kstack here
             false,
         ));
+        #[cfg(feature = "rc_audit")]
+        {
+            audit::track_process(&child);
+            audit::track_task(&task);
+        }
+        // the mask, the alternate stack, fp and vector registers are inherited
+        let parent_task = parent.get_task(0);
+        sync_ext_state(&parent_task);
+        let parent_task_inner = parent_task.inner_exclusive_access();
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.signal_mask = parent_task_inner.signal_mask;
+        task_inner.signal_stack = parent_task_inner.signal_stack;
+        task_inner.fp_state = parent_task_inner.fp_state;
+        task_inner.vector_state = parent_task_inner.vector_state.clone();
+        drop(task_inner);
+        drop(parent_task_inner);
         // attach task to child process
         let mut child_inner = child.inner_exclusive_access();
         child_inner.tasks.push(Some(Arc::clone(&task)));
@@ -256,3 +391,115 @@ impl ProcessControlBlock {
         self.pid.0
     }
 }
+
+/// Native binaries get argc in a0 and argv in a1, the strings sit below the
+/// argv array. Returns the stack pointer and the address of argv.
+fn push_args(token: usize, ustack_top: usize, args: &[String]) -> (usize, usize) {
+    let mut user_sp = ustack_top;
+    user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
+    let argv_base = user_sp;
+    let mut argv: Vec<_> = (0..=args.len())
+        .map(|arg| {
+            translated_refmut(
+                token,
+                (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
+            )
+            .unwrap()
+        })
+        .collect();
+    *argv[args.len()] = 0;
+    for i in 0..args.len() {
+        user_sp -= args[i].len() + 1;
+        *argv[i] = user_sp;
+        let mut p = user_sp;
+        for c in args[i].as_bytes() {
+            *translated_refmut(token, p as *mut u8).unwrap() = *c;
+            p += 1;
+        }
+        *translated_refmut(token, p as *mut u8).unwrap() = 0;
+    }
+    // make the user_sp aligned to 8B for k210 platform
+    user_sp -= user_sp % core::mem::size_of::<usize>();
+    (user_sp, argv_base)
+}
+
+const AT_NULL: usize = 0;
+const AT_PHDR: usize = 3;
+const AT_PHENT: usize = 4;
+const AT_PHNUM: usize = 5;
+const AT_PAGESZ: usize = 6;
+const AT_ENTRY: usize = 9;
+const AT_RANDOM: usize = 25;
+
+/// The auxiliary vector entries taken from the ELF. The program headers are
+/// found where PT_PHDR says or else in the segment loaded from offset 0.
+fn elf_aux_vector(elf_data: &[u8]) -> Vec<(usize, usize)> {
+    let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+    let header = &elf.header.pt2;
+    let ph_offset = header.ph_offset();
+    let phdr_of = |wanted: Type| {
+        elf.program_iter().find_map(|ph| match ph.get_type() {
+            Ok(Type::Phdr) if wanted == Type::Phdr => Some(ph.virtual_addr() as usize),
+            Ok(Type::Load) if wanted == Type::Load && ph.offset() == 0 => {
+                Some((ph.virtual_addr() + ph_offset) as usize)
+            }
+            _ => None,
+        })
+    };
+    let phdr = phdr_of(Type::Phdr)
+        .or_else(|| phdr_of(Type::Load))
+        .unwrap_or(0);
+    vec![
+        (AT_PHDR, phdr),
+        (AT_PHENT, header.ph_entry_size() as usize),
+        (AT_PHNUM, header.ph_count() as usize),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, header.entry_point() as usize),
+    ]
+}
+
+/// Linux binaries find everything on the stack: sp points at argc, then
+/// come argv, an empty envp and the auxiliary vector, 16-byte aligned.
+/// The 16 bytes AT_RANDOM points to sit at the very top.
+fn push_linux_args(
+    token: usize,
+    ustack_top: usize,
+    args: &[String],
+    aux: &[(usize, usize)],
+) -> (usize, usize) {
+    let mut user_sp = ustack_top - 16;
+    let random_base = user_sp;
+    let mut random = [0u8; 16];
+    get_random_bytes(&mut random);
+    for (i, byte) in random.iter().enumerate() {
+        *translated_refmut(token, (random_base + i) as *mut u8).unwrap() = *byte;
+    }
+    let mut argv = Vec::new();
+    for arg in args {
+        user_sp -= arg.len() + 1;
+        argv.push(user_sp);
+        for (i, c) in arg.bytes().chain(core::iter::once(0)).enumerate() {
+            *translated_refmut(token, (user_sp + i) as *mut u8).unwrap() = c;
+        }
+    }
+    let mut words = vec![args.len()];
+    words.extend(argv);
+    // argv and envp terminators
+    words.extend([0, 0]);
+    for (key, value) in aux
+        .iter()
+        .chain([(AT_RANDOM, random_base), (AT_NULL, 0)].iter())
+    {
+        words.extend([*key, *value]);
+    }
+    user_sp -= words.len() * core::mem::size_of::<usize>();
+    user_sp &= !0xf;
+    for (i, word) in words.iter().enumerate() {
+        *translated_refmut(
+            token,
+            (user_sp + i * core::mem::size_of::<usize>()) as *mut usize,
+        )
+        .unwrap() = *word;
+    }
+    (user_sp, user_sp + core::mem::size_of::<usize>())
+}
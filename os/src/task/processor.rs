@@ -1,15 +1,23 @@
 use super::__switch;
 use super::{fetch_task, TaskStatus};
 use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
+use crate::config::MAX_HARTS;
+use crate::mm::VirtPageNum;
+#[cfg(feature = "unified")]
+use crate::mm::KERNEL_SPACE;
 use crate::sync::UPIntrFreeCell;
+use crate::trace::{self, TracePoint};
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::arch::asm;
 use lazy_static::*;
 
 pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
     idle_task_cx: TaskContext,
+    /// a task that ended here, dropped once the hart is off its kstack
+    exited: Option<Arc<TaskControlBlock>>,
 }
 
 impl Processor {
@@ -17,6 +25,7 @@ impl Processor {
         Self {
             current: None,
             idle_task_cx: TaskContext::zero_init(),
+            exited: None,
         }
     }
     fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
@@ -31,26 +40,54 @@ impl Processor {
 }
 
 lazy_static! {
-    pub static ref PROCESSOR: UPIntrFreeCell<Processor> =
-        unsafe { UPIntrFreeCell::new(Processor::new()) };
+    static ref PROCESSORS: Vec<UPIntrFreeCell<Processor>> = (0..MAX_HARTS)
+        .map(|_| unsafe { UPIntrFreeCell::new(Processor::new()) })
+        .collect();
+}
+
+/// Id of the hart we run on, kept in tp.
+pub fn hart_id() -> usize {
+    let hart_id;
+    unsafe { asm!("mv {}, tp", out(reg) hart_id) };
+    hart_id
+}
+
+fn processor() -> &'static UPIntrFreeCell<Processor> {
+    &PROCESSORS[hart_id()]
 }
 
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
+        let mut processor = processor().exclusive_access();
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let next_task_cx_ptr = task.inner.exclusive_session(|task_inner| {
                 task_inner.task_status = TaskStatus::Running;
+                task_inner.hart = hart_id();
+                if trace::enabled(TracePoint::SchedSwitch) {
+                    let pid = task.process.upgrade().map_or(0, |process| process.getpid());
+                    let tid = task_inner.res.as_ref().map_or(0, |res| res.tid);
+                    trace::trace(TracePoint::SchedSwitch, [pid, tid]);
+                }
                 &task_inner.task_cx as *const TaskContext
             });
+            // a unified kernel runs on the satp of the current task
+            #[cfg(feature = "unified")]
+            if let Some(process) = task.process.upgrade() {
+                process.inner_exclusive_access().memory_set.switch_in();
+            }
             processor.current = Some(task);
             // release processor manually
             drop(processor);
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            let exited = processor().exclusive_access().exited.take();
+            drop(exited);
+            // the space of the task may be freed while this hart idles
+            #[cfg(feature = "unified")]
+            KERNEL_SPACE.exclusive_access().switch_in();
         } else {
             println!("no tasks available in run_tasks");
         }
@@ -58,11 +95,11 @@ pub fn run_tasks() {
 }
 
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    processor().exclusive_access().take_current()
 }
 
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    processor().exclusive_access().current()
 }
 
 pub fn current_process() -> Arc<ProcessControlBlock> {
@@ -74,6 +111,27 @@ pub fn current_user_token() -> usize {
     task.get_user_token()
 }
 
+/// satp of the current address space, with its ASID.
+pub fn current_user_satp() -> usize {
+    current_process().inner_exclusive_access().memory_set.satp()
+}
+
+/// Give the current process a frame for the page at `vpn` of the space
+/// `token` if it dropped it, return false if there is nothing to fault in.
+/// Also used by the kernel to reach user memory, where the space may be
+/// another one or already borrowed.
+pub fn fault_in_current_page(token: usize, vpn: VirtPageNum) -> bool {
+    let process = match current_task().and_then(|task| task.process.upgrade()) {
+        Some(process) => process,
+        None => return false,
+    };
+    let mut inner = match process.try_inner_exclusive_access() {
+        Some(inner) => inner,
+        None => return false,
+    };
+    inner.memory_set.token() == token && inner.memory_set.fault_in(vpn)
+}
+
 pub fn current_trap_cx() -> &'static mut TrapContext {
     current_task()
         .unwrap()
@@ -104,8 +162,17 @@ pub fn current_kstack_top() -> usize {
 
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
     let idle_task_cx_ptr =
-        PROCESSOR.exclusive_session(|processor| processor.get_idle_task_cx_ptr());
+        processor().exclusive_session(|processor| processor.get_idle_task_cx_ptr());
     unsafe {
         __switch(switched_task_cx_ptr, idle_task_cx_ptr);
     }
 }
+
+/// Switch away from `task`, the current one, for good. It may be the last
+/// reference to the task, so it is only dropped once the hart is back on
+/// its idle stack.
+pub fn schedule_exited(task: Arc<TaskControlBlock>) {
+    processor().exclusive_access().exited = Some(task);
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+}
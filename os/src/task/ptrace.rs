@@ -0,0 +1,249 @@
+//! State of a traced process. A tracee stops whenever a signal is about to
+//! be delivered to it; its parent, the tracer, learns about the stop from
+//! waitpid and resumes it with ptrace.
+
+use super::{
+    block_current_and_run_next, current_process, current_task, deliverable_signals_of_current,
+    wakeup_task, SignalFlags, TaskControlBlock,
+};
+use crate::mm::{translated_ref, translated_text_mut};
+use crate::trap::{triggers_supported, TrapContext, Trigger};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// c.ebreak, used for software single stepping
+const C_EBREAK: u16 = 0x9002;
+const EBREAK: u32 = 0x0010_0073;
+
+#[derive(Default)]
+pub struct PtraceState {
+    /// signal the tracee has stopped with, until the tracer resumes it
+    pub stop_signal: Option<SignalFlags>,
+    /// whether waitpid has told the tracer about the current stop
+    pub stop_reported: bool,
+    pub stopped_task: Option<Arc<TaskControlBlock>>,
+    /// signal chosen by the tracer to be delivered after resuming
+    pub resume_signal: Option<SignalFlags>,
+    /// (address, original halfword) of breakpoints planted to single step
+    pub step_breakpoints: Vec<(usize, u16)>,
+}
+
+impl PtraceState {
+    /// Resume the stopped thread, delivering `signal` to it if any.
+    pub fn resume(&mut self, signal: Option<SignalFlags>) {
+        if let Some(task) = self.stopped_task.take() {
+            self.stop_signal = None;
+            self.resume_signal = signal;
+            wakeup_task(task);
+        }
+    }
+
+    /// Step with an instruction count trigger where the hardware has one,
+    /// by planting breakpoints otherwise.
+    pub fn prepare_single_step(&mut self, token: usize) {
+        if triggers_supported() {
+            let task = self.stopped_task.as_ref().unwrap();
+            task.inner_exclusive_access()
+                .debug_triggers
+                .push(Trigger::single_step());
+        } else {
+            self.plant_step_breakpoints(token);
+        }
+    }
+
+    /// Plant breakpoints at every instruction which may follow the one at
+    /// the pc of the stopped thread.
+    fn plant_step_breakpoints(&mut self, token: usize) {
+        let trap_cx = self
+            .stopped_task
+            .as_ref()
+            .unwrap()
+            .inner_exclusive_access()
+            .get_trap_cx();
+        for pc in next_pcs(token, trap_cx) {
+            if self.step_breakpoints.iter().all(|(addr, _)| *addr != pc) {
+                // a jump to nowhere faults on its own
+                let Ok(slot) = translated_text_mut(token, pc as *mut u16) else {
+                    continue;
+                };
+                self.step_breakpoints.push((pc, *slot));
+                *slot = C_EBREAK;
+            }
+        }
+    }
+
+    /// Undo `prepare_single_step`, watchpoints stay armed.
+    pub fn clear_single_step(&mut self, token: usize) {
+        if let Some(task) = self.stopped_task.as_ref() {
+            task.inner_exclusive_access()
+                .debug_triggers
+                .retain(|trigger| *trigger != Trigger::single_step());
+        }
+        self.clear_step_breakpoints(token);
+    }
+
+    fn clear_step_breakpoints(&mut self, token: usize) {
+        for (addr, inst) in self.step_breakpoints.drain(..) {
+            if let Ok(slot) = translated_text_mut(token, addr as *mut u16) {
+                *slot = inst;
+            }
+        }
+    }
+}
+
+fn sign_extend(value: usize, bits: usize) -> usize {
+    let shift = usize::BITS as usize - bits;
+    (((value << shift) as isize) >> shift) as usize
+}
+
+/// Possible addresses of the instruction executed after the one at sepc.
+fn next_pcs(token: usize, trap_cx: &TrapContext) -> Vec<usize> {
+    let pc = trap_cx.sepc;
+    let Ok(&low) = translated_ref(token, pc as *const u16) else {
+        return Vec::new();
+    };
+    let low = low as usize;
+    let mut pcs = Vec::new();
+    if low & 0b11 != 0b11 {
+        // compressed instruction
+        let inst = low;
+        pcs.push(pc + 2);
+        match (inst & 0b11, inst >> 13) {
+            // c.j
+            (0b01, 0b101) => {
+                let imm = ((inst >> 12) & 1) << 11
+                    | ((inst >> 11) & 1) << 4
+                    | ((inst >> 9) & 0b11) << 8
+                    | ((inst >> 8) & 1) << 10
+                    | ((inst >> 7) & 1) << 6
+                    | ((inst >> 6) & 1) << 7
+                    | ((inst >> 3) & 0b111) << 1
+                    | ((inst >> 2) & 1) << 5;
+                pcs[0] = pc.wrapping_add(sign_extend(imm, 12));
+            }
+            // c.beqz, c.bnez
+            (0b01, 0b110) | (0b01, 0b111) => {
+                let imm = ((inst >> 12) & 1) << 8
+                    | ((inst >> 10) & 0b11) << 3
+                    | ((inst >> 5) & 0b11) << 6
+                    | ((inst >> 3) & 0b11) << 1
+                    | ((inst >> 2) & 1) << 5;
+                pcs.push(pc.wrapping_add(sign_extend(imm, 9)));
+            }
+            // c.jr, c.jalr
+            (0b10, 0b100) => {
+                let rs1 = (inst >> 7) & 0x1f;
+                let rs2 = (inst >> 2) & 0x1f;
+                if rs1 != 0 && rs2 == 0 {
+                    pcs[0] = trap_cx.x[rs1] & !1;
+                }
+            }
+            _ => {}
+        }
+    } else {
+        let high = translated_ref(token, (pc + 2) as *const u16).map_or(0, |&high| high as usize);
+        let inst = low | high << 16;
+        pcs.push(pc + 4);
+        match inst & 0x7f {
+            // jal
+            0x6f => {
+                let imm = ((inst >> 31) & 1) << 20
+                    | ((inst >> 21) & 0x3ff) << 1
+                    | ((inst >> 20) & 1) << 11
+                    | ((inst >> 12) & 0xff) << 12;
+                pcs[0] = pc.wrapping_add(sign_extend(imm, 21));
+            }
+            // jalr
+            0x67 => {
+                let rs1 = (inst >> 15) & 0x1f;
+                let imm = sign_extend(inst >> 20, 12);
+                pcs[0] = trap_cx.x[rs1].wrapping_add(imm) & !1;
+            }
+            // branches
+            0x63 => {
+                let imm = ((inst >> 31) & 1) << 12
+                    | ((inst >> 25) & 0x3f) << 5
+                    | ((inst >> 8) & 0xf) << 1
+                    | ((inst >> 7) & 1) << 11;
+                pcs.push(pc.wrapping_add(sign_extend(imm, 13)));
+            }
+            _ => {}
+        }
+    }
+    pcs
+}
+
+/// Called before returning to user mode. A traced process stops at the
+/// signal which is about to be delivered and lets the tracer decide what
+/// happens to it. SIGKILL is never intercepted.
+pub fn ptrace_stop_current() {
+    let process = current_process();
+    if process.inner_exclusive_access().ptrace.is_none() {
+        return;
+    }
+    let deliverable = deliverable_signals_of_current() - SignalFlags::SIGKILL;
+    if deliverable.is_empty() {
+        return;
+    }
+    let signal = SignalFlags::from_signum(deliverable.signum()).unwrap();
+    let mut inner = process.inner_exclusive_access();
+    inner.signals.remove(signal);
+    let state = inner.ptrace.as_mut().unwrap();
+    state.stop_signal = Some(signal);
+    state.stop_reported = false;
+    state.stopped_task = Some(current_task().unwrap());
+    drop(inner);
+    block_current_and_run_next();
+    // resumed by the tracer, or detached because it exited
+    let mut inner = process.inner_exclusive_access();
+    let resume_signal = inner
+        .ptrace
+        .as_mut()
+        .and_then(|state| state.resume_signal.take());
+    if let Some(signal) = resume_signal {
+        inner.signals |= signal;
+    }
+}
+
+/// Called on a breakpoint exception, return true if it has been planted
+/// for single stepping. The tracee then stops with SIGTRAP.
+pub fn ptrace_step_breakpoint_hit(sepc: usize) -> bool {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let token = inner.memory_set.token();
+    let hit = inner.ptrace.as_mut().map_or(false, |state| {
+        let hit = state.step_breakpoints.iter().any(|(addr, _)| *addr == sepc);
+        if hit {
+            state.clear_step_breakpoints(token);
+        }
+        hit
+    });
+    if hit {
+        inner.signals |= SignalFlags::SIGTRAP;
+    }
+    hit
+}
+
+/// Called on a breakpoint exception, return true if it has been raised by
+/// one of the hardware triggers of the current thread rather than by an
+/// ebreak. Triggers fire once, the tracee then stops with SIGTRAP.
+pub fn ptrace_trigger_hit() -> bool {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.debug_triggers.is_empty() {
+        return false;
+    }
+    let token = task.get_user_token();
+    let sepc = task_inner.get_trap_cx().sepc;
+    let half = |va: usize| translated_ref(token, va as *const u16).ok().copied();
+    let low = half(sepc);
+    let is_ebreak =
+        low == Some(C_EBREAK) || (low == Some(EBREAK as u16) && half(sepc + 2) == Some(0x0010));
+    if is_ebreak {
+        return false;
+    }
+    task_inner.debug_triggers.clear();
+    drop(task_inner);
+    current_process().inner_exclusive_access().signals |= SignalFlags::SIGTRAP;
+    true
+}
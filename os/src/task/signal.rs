@@ -1,29 +1,187 @@
 use bitflags::*;
 
+pub const MAX_SIG: usize = 31;
+/// Values of `SignalAction::handler` with a special meaning.
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+/// `how` argument of sigprocmask
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+/// `SignalAction::flags`: run the handler on the alternate signal stack
+pub const SA_ONSTACK: usize = 0x0800_0000;
+/// `SignalStack::flags`
+pub const SS_ONSTACK: i32 = 1;
+pub const SS_DISABLE: i32 = 2;
+pub const MINSIGSTKSZ: usize = 2048;
+
 bitflags! {
+    /// Bit `n` stands for signal number `n`, as in linux.
     pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
         const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
         const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
         const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
         const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
         const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
+        /// can neither be blocked nor caught
+        const UNCATCHABLE = Self::SIGKILL.bits | Self::SIGSTOP.bits;
+        /// raised by a faulting instruction, retrying it while blocked is pointless
+        const SYNCHRONOUS = Self::SIGILL.bits | Self::SIGTRAP.bits | Self::SIGBUS.bits
+            | Self::SIGFPE.bits | Self::SIGSEGV.bits;
+        /// leave a core file behind when they kill a dumpable process
+        const CORE_DUMPING = Self::SIGQUIT.bits | Self::SIGILL.bits | Self::SIGTRAP.bits
+            | Self::SIGABRT.bits | Self::SIGBUS.bits | Self::SIGFPE.bits | Self::SIGSEGV.bits
+            | Self::SIGXCPU.bits | Self::SIGXFSZ.bits | Self::SIGSYS.bits;
+        /// discarded when the default action is in effect
+        const IGNORED_BY_DEFAULT = Self::SIGCHLD.bits | Self::SIGCONT.bits | Self::SIGURG.bits
+            | Self::SIGWINCH.bits;
     }
 }
 
 impl SignalFlags {
-    pub fn check_error(&self) -> Option<(i32, &'static str)> {
-        if self.contains(Self::SIGINT) {
-            Some((-2, "Killed, SIGINT=2"))
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if (1..=MAX_SIG).contains(&signum) {
+            Self::from_bits(1 << signum)
+        } else {
+            None
+        }
+    }
+
+    /// Number of the lowest signal in the set.
+    pub fn signum(&self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+
+    /// Exit code and message of a process killed by this signal.
+    pub fn fatal_error(&self) -> (i32, &'static str) {
+        let msg = if self.contains(Self::SIGINT) {
+            "Killed, SIGINT=2"
         } else if self.contains(Self::SIGILL) {
-            Some((-4, "Illegal Instruction, SIGILL=4"))
+            "Illegal Instruction, SIGILL=4"
         } else if self.contains(Self::SIGABRT) {
-            Some((-6, "Aborted, SIGABRT=6"))
+            "Aborted, SIGABRT=6"
+        } else if self.contains(Self::SIGTRAP) {
+            "Trace/Breakpoint Trap, SIGTRAP=5"
         } else if self.contains(Self::SIGFPE) {
-            Some((-8, "Erroneous Arithmetic Operation, SIGFPE=8"))
+            "Erroneous Arithmetic Operation, SIGFPE=8"
+        } else if self.contains(Self::SIGKILL) {
+            "Killed, SIGKILL=9"
         } else if self.contains(Self::SIGSEGV) {
-            Some((-11, "Segmentation Fault, SIGSEGV=11"))
+            "Segmentation Fault, SIGSEGV=11"
         } else {
-            None
+            "Terminated by signal"
+        };
+        (-(self.signum() as i32), msg)
+    }
+}
+
+/// Layout shared with user space.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    pub handler: usize,
+    pub flags: usize,
+    /// where the handler returns to, it must invoke sigreturn
+    pub restorer: usize,
+    /// blocked in addition while the handler runs
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            flags: 0,
+            restorer: 0,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
+/// Alternate stack for signal handlers, layout of `stack_t` of linux.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalStack {
+    pub sp: usize,
+    pub flags: i32,
+    pub size: usize,
+}
+
+impl Default for SignalStack {
+    fn default() -> Self {
+        Self {
+            sp: 0,
+            flags: SS_DISABLE,
+            size: 0,
+        }
+    }
+}
+
+impl SignalStack {
+    pub fn enabled(&self) -> bool {
+        self.flags & SS_DISABLE == 0
+    }
+
+    pub fn contains(&self, sp: usize) -> bool {
+        self.enabled() && sp > self.sp && sp <= self.sp + self.size
+    }
+}
+
+#[derive(Clone)]
+pub struct SignalActions {
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
+
+impl SignalActions {
+    /// Caught signals are reset on exec since the handlers are gone.
+    pub fn reset_handlers(&mut self) {
+        for action in self.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+    }
+
+    /// Whether a pending `signal` would simply be dropped.
+    pub fn is_ignored(&self, signal: SignalFlags) -> bool {
+        match self.table[signal.signum()].handler {
+            SIG_IGN => true,
+            SIG_DFL => signal.intersects(SignalFlags::IGNORED_BY_DEFAULT),
+            _ => false,
         }
     }
 }
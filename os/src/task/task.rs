@@ -1,11 +1,15 @@
 use super::id::TaskUserRes;
-use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
-use crate::trap::TrapContext;
+use super::{
+    hart_id, kstack_alloc, KernelStack, ProcessControlBlock, SignalFlags, SignalStack, TaskContext,
+    UintrState,
+};
+use crate::trap::{FpState, TrapContext, Trigger, VectorState};
 use crate::{
     mm::PhysPageNum,
     sync::{UPIntrFreeCell, UPIntrRefMut},
 };
 use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
 pub struct TaskControlBlock {
     // immutable
@@ -32,7 +36,27 @@ pub struct TaskControlBlockInner {
     pub trap_cx_ppn: PhysPageNum,
     pub task_cx: TaskContext,
     pub task_status: TaskStatus,
+    /// hart whose ready queue the task goes back to
+    pub hart: usize,
     pub exit_code: Option<i32>,
+    pub signal_mask: SignalFlags,
+    /// context and mask to restore on sigreturn while a handler is running
+    pub signal_frame: Option<(TrapContext, SignalFlags)>,
+    /// mask replaced by sigsuspend, restored once a signal has been handled
+    pub sigsuspend_mask: Option<SignalFlags>,
+    pub signal_stack: SignalStack,
+    /// hardware triggers armed while this thread runs, set by its tracer
+    pub debug_triggers: Vec<Trigger>,
+    /// registers of emulated floating point instructions
+    pub fp_state: FpState,
+    /// vector registers, saved when another thread takes the vector unit
+    pub vector_state: Option<VectorState>,
+    /// user-level interrupt handler and its pending events
+    pub uintr: UintrState,
+    /// user address of the thread id cleared on exit, set by set_tid_address
+    pub clear_child_tid: usize,
+    /// blocked until a signal comes, among other things
+    pub interruptible: bool,
 }
 
 impl TaskControlBlockInner {
@@ -65,7 +89,18 @@ impl TaskControlBlock {
                     trap_cx_ppn,
                     task_cx: TaskContext::goto_trap_return(kstack_top),
                     task_status: TaskStatus::Ready,
+                    hart: hart_id(),
                     exit_code: None,
+                    signal_mask: SignalFlags::empty(),
+                    signal_frame: None,
+                    sigsuspend_mask: None,
+                    signal_stack: SignalStack::default(),
+                    debug_triggers: Vec::new(),
+                    fp_state: FpState::new(),
+                    vector_state: None,
+                    uintr: UintrState::default(),
+                    clear_child_tid: 0,
+                    interruptible: false,
                 })
             },
         }
@@ -0,0 +1,47 @@
+//! Static thread-local storage. The PT_TLS segment of the image is copied to
+//! the top of the user stack of every thread and tp points at the copy, as
+//! the RISC-V local-exec model expects: variables are at fixed offsets from
+//! tp and there is no control block in front of them.
+
+use crate::mm::translated_byte_buffer_mut;
+use alloc::vec::Vec;
+use xmas_elf::program::Type;
+
+pub struct TlsTemplate {
+    /// initialized part, .tdata
+    image: Vec<u8>,
+    /// .tdata and .tbss together
+    mem_size: usize,
+    align: usize,
+}
+
+impl TlsTemplate {
+    pub fn of_elf(elf_data: &[u8]) -> Option<Self> {
+        let elf = xmas_elf::ElfFile::new(elf_data).ok()?;
+        let ph = elf
+            .program_iter()
+            .find(|ph| ph.get_type() == Ok(Type::Tls))?;
+        let start = ph.offset() as usize;
+        Some(Self {
+            image: elf.input[start..start + ph.file_size() as usize].to_vec(),
+            mem_size: ph.mem_size() as usize,
+            align: (ph.align() as usize).max(1),
+        })
+    }
+
+    /// Copy the block right below `stack_top`. Returns the thread pointer,
+    /// which is also where the stack of the thread starts.
+    pub fn install(&self, token: usize, stack_top: usize) -> usize {
+        // the stack below stays 16-byte aligned
+        let tp = (stack_top - self.mem_size) & !(self.align.max(16) - 1);
+        let mut copied = 0;
+        // the stack was just mapped for the thread
+        for slice in translated_byte_buffer_mut(token, tp as *mut u8, self.mem_size).unwrap() {
+            for byte in slice.iter_mut() {
+                *byte = self.image.get(copied).copied().unwrap_or(0);
+                copied += 1;
+            }
+        }
+        tp
+    }
+}
@@ -0,0 +1,80 @@
+//! User-level interrupts in the spirit of the N extension. A thread registers
+//! a handler and the events it wants; a pending event sends the thread to its
+//! handler on the way back to user mode. Unlike a signal no trap context is
+//! copied: only pc, a0 and a7 are kept, the handler saves everything else and
+//! comes back with `uintr_return`.
+
+use super::{current_task, pid2process};
+use bitflags::*;
+
+bitflags! {
+    pub struct UintrEvents: usize {
+        /// a timer tick preempted the thread
+        const TIMER = 1 << 0;
+        /// another process called `uintr_notify`
+        const NOTIFY = 1 << 1;
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct UintrState {
+    /// user entry of the handler, 0 if none is registered
+    pub handler: usize,
+    pub enabled: UintrEvents,
+    pub pending: UintrEvents,
+    /// pc, a0 and a7 of the interrupted code while the handler runs
+    pub saved: Option<[usize; 3]>,
+}
+
+impl Default for UintrEvents {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl UintrState {
+    fn post(&mut self, events: UintrEvents) {
+        if self.handler != 0 {
+            self.pending |= events & self.enabled;
+        }
+    }
+}
+
+/// Called on a timer interrupt from user mode.
+pub fn uintr_tick_current() {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().uintr.post(UintrEvents::TIMER);
+}
+
+/// Post a notification to every thread of `pid` with a handler, return false
+/// if there is no such process.
+pub fn uintr_notify(pid: usize) -> bool {
+    let process = match pid2process(pid) {
+        Some(process) => process,
+        None => return false,
+    };
+    let process_inner = process.inner_exclusive_access();
+    for task in process_inner.tasks.iter().flatten() {
+        task.inner_exclusive_access()
+            .uintr
+            .post(UintrEvents::NOTIFY);
+    }
+    true
+}
+
+/// Called before returning to user mode, after signals. Enter the handler
+/// with the pending events in a0 unless it is running already.
+pub fn deliver_uintr_of_current() {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let state = task_inner.uintr;
+    if state.saved.is_some() || state.pending.is_empty() || task_inner.signal_frame.is_some() {
+        return;
+    }
+    let trap_cx = task_inner.get_trap_cx();
+    let saved = [trap_cx.sepc, trap_cx.x[10], trap_cx.x[17]];
+    trap_cx.sepc = state.handler;
+    trap_cx.x[10] = state.pending.bits();
+    task_inner.uintr.saved = Some(saved);
+    task_inner.uintr.pending = UintrEvents::empty();
+}
@@ -1,16 +1,64 @@
+use core::arch::asm;
 use core::cmp::Ordering;
 
-use crate::config::CLOCK_FREQ;
+use crate::board::rtc_time_ns;
+use crate::config::{CLOCK_FREQ, HZ};
 use crate::sbi::set_timer;
 use crate::sync::UPIntrFreeCell;
-use crate::task::{wakeup_task, TaskControlBlock};
+use crate::task::{wakeup_task, TaskControlBlock, TaskStatus};
 use alloc::collections::BinaryHeap;
 use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering::Relaxed};
 use lazy_static::*;
 use riscv::register::time;
 
-const TICKS_PER_SEC: usize = 100;
 const MSEC_PER_SEC: usize = 1000;
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / NSEC_PER_SEC) as usize,
+            tv_nsec: (ns % NSEC_PER_SEC) as usize,
+        }
+    }
+    /// Saturates rather than overflow for times too far away.
+    pub fn to_ns(self) -> u64 {
+        (self.tv_sec as u64)
+            .saturating_mul(NSEC_PER_SEC)
+            .saturating_add(self.tv_nsec as u64)
+    }
+}
+
+/// A time in microseconds, as get_time and gettimeofday report it.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+impl TimeVal {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / NSEC_PER_SEC) as usize,
+            tv_usec: (ns % NSEC_PER_SEC / 1000) as usize,
+        }
+    }
+}
+
+/// CLOCK_REALTIME minus CLOCK_MONOTONIC
+static REALTIME_OFFSET_NS: AtomicU64 = AtomicU64::new(0);
 
 pub fn get_time() -> usize {
     time::read()
@@ -20,8 +68,37 @@ pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
 }
 
+/// Nanoseconds since boot.
+pub fn get_time_ns() -> u64 {
+    let ticks = time::read() as u64;
+    let freq = CLOCK_FREQ as u64;
+    ticks / freq * NSEC_PER_SEC + ticks % freq * NSEC_PER_SEC / freq
+}
+
+/// Nanoseconds since the epoch.
+pub fn realtime_ns() -> u64 {
+    REALTIME_OFFSET_NS.load(Relaxed) + get_time_ns()
+}
+
+pub fn set_realtime_ns(ns: u64) {
+    REALTIME_OFFSET_NS.store(ns.saturating_sub(get_time_ns()), Relaxed);
+}
+
+/// Start the wall clock from the RTC, user space may correct it later.
+pub fn init_realtime() {
+    set_realtime_ns(rtc_time_ns());
+}
+
+/// Let applications on this hart read cycle, time and instret themselves,
+/// to time what is too short for a syscall to measure.
+pub fn enable_user_counters() {
+    unsafe {
+        asm!("csrw scounteren, {}", in(reg) 0b111);
+    }
+}
+
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    set_timer(get_time() + CLOCK_FREQ / HZ);
 }
 
 pub struct TimerCondVar {
@@ -59,12 +136,22 @@ pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
     timers.push(TimerCondVar { expire_ms, task });
 }
 
+/// Forget the timers of `task`, which has been woken otherwise.
+pub fn remove_timer(task: &Arc<TaskControlBlock>) {
+    TIMERS.exclusive_session(|timers| timers.retain(|timer| !Arc::ptr_eq(&timer.task, task)));
+}
+
 pub fn check_timer() {
     let current_ms = get_time_ms();
     TIMERS.exclusive_session(|timers| {
         while let Some(timer) = timers.peek() {
             if timer.expire_ms <= current_ms {
-                wakeup_task(Arc::clone(&timer.task));
+                // a sleep cut short by a signal may not have removed it yet
+                let blocked =
+                    timer.task.inner_exclusive_access().task_status == TaskStatus::Blocked;
+                if blocked {
+                    wakeup_task(Arc::clone(&timer.task));
+                }
                 timers.pop();
             } else {
                 break;
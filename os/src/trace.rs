@@ -0,0 +1,153 @@
+//! Static tracepoints, an ftrace of sorts.
+//!
+//! Each tracepoint hit while it is enabled puts a [`TraceRecord`] into a
+//! ring buffer of the hart, overwriting the oldest record once the ring
+//! is full. /proc/trace_events lists the tracepoints and turns them on and
+//! off, reading /proc/trace takes the records out.
+
+use crate::config::{MAX_HARTS, TRACE_RING_SIZE as RING_SIZE};
+use crate::sync::UPIntrFreeCell;
+use crate::task::hart_id;
+use crate::timer::get_time_ns;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use lazy_static::*;
+
+/// The tracepoints, numbered as in the `point` of a record.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TracePoint {
+    /// scause, sepc
+    TrapEnter = 0,
+    /// sepc the application returns to
+    TrapExit = 1,
+    /// pid and tid of the thread switched to
+    SchedSwitch = 2,
+    /// syscall id, first argument
+    SyscallEnter = 3,
+    /// syscall id, return value
+    SyscallExit = 4,
+    /// faulting address, scause
+    PageFault = 5,
+    /// block id, 1 for a write and 0 for a read
+    BlockIo = 6,
+}
+
+pub const TRACE_POINTS: [TracePoint; 7] = [
+    TracePoint::TrapEnter,
+    TracePoint::TrapExit,
+    TracePoint::SchedSwitch,
+    TracePoint::SyscallEnter,
+    TracePoint::SyscallExit,
+    TracePoint::PageFault,
+    TracePoint::BlockIo,
+];
+
+impl TracePoint {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::TrapEnter => "trap_enter",
+            Self::TrapExit => "trap_exit",
+            Self::SchedSwitch => "sched_switch",
+            Self::SyscallEnter => "syscall_enter",
+            Self::SyscallExit => "syscall_exit",
+            Self::PageFault => "page_fault",
+            Self::BlockIo => "block_io",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        TRACE_POINTS
+            .iter()
+            .copied()
+            .find(|point| point.name() == name)
+    }
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// What /proc/trace gives for every hit.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct TraceRecord {
+    /// nanoseconds since boot
+    pub time_ns: u64,
+    pub point: u32,
+    pub hart: u32,
+    pub args: [usize; 2],
+}
+
+struct Ring {
+    records: VecDeque<TraceRecord>,
+    /// records overwritten before anyone read them
+    lost: usize,
+}
+
+lazy_static! {
+    static ref RINGS: Vec<UPIntrFreeCell<Ring>> = (0..MAX_HARTS)
+        .map(|_| unsafe {
+            UPIntrFreeCell::new(Ring {
+                records: VecDeque::with_capacity(RING_SIZE),
+                lost: 0,
+            })
+        })
+        .collect();
+}
+
+/// bit `point` set while the tracepoint is enabled
+static ENABLED: AtomicU32 = AtomicU32::new(0);
+
+pub fn enabled(point: TracePoint) -> bool {
+    ENABLED.load(Relaxed) & point.bit() != 0
+}
+
+pub fn set_enabled(point: TracePoint, enable: bool) {
+    if enable {
+        ENABLED.fetch_or(point.bit(), Relaxed);
+    } else {
+        ENABLED.fetch_and(!point.bit(), Relaxed);
+    }
+}
+
+/// Hit `point`; nothing but a load if it is disabled. Arguments costly to
+/// get are better got after checking [`enabled`].
+#[inline]
+pub fn trace(point: TracePoint, args: [usize; 2]) {
+    if enabled(point) {
+        let hart = hart_id();
+        let record = TraceRecord {
+            time_ns: get_time_ns(),
+            point: point as u32,
+            hart: hart as u32,
+            args,
+        };
+        RINGS[hart].exclusive_session(|ring| {
+            if ring.records.len() == RING_SIZE {
+                ring.records.pop_front();
+                ring.lost += 1;
+            }
+            ring.records.push_back(record);
+        });
+    }
+}
+
+/// Take out up to `max` records, oldest first on each hart; records of
+/// different harts are only ordered by their time.
+pub fn drain(max: usize) -> Vec<TraceRecord> {
+    let mut records = Vec::new();
+    for ring in RINGS.iter() {
+        ring.exclusive_session(|ring| {
+            let count = ring.records.len().min(max - records.len());
+            records.extend(ring.records.drain(..count));
+        });
+    }
+    records
+}
+
+/// Records overwritten so far on every hart.
+pub fn lost() -> usize {
+    RINGS
+        .iter()
+        .map(|ring| ring.exclusive_session(|ring| ring.lost))
+        .sum()
+}
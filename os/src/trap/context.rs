@@ -1,7 +1,19 @@
 use riscv::register::sstatus::{self, Sstatus, SPP};
 
+pub const FS_SHIFT: usize = 13;
+pub const VS_SHIFT: usize = 9;
+
+/// Values of the sstatus fields tracking the state of an extension unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtState {
+    Off = 0,
+    Initial = 1,
+    Clean = 2,
+    Dirty = 3,
+}
+
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TrapContext {
     pub x: [usize; 32],
     pub sstatus: Sstatus,
@@ -9,12 +21,33 @@ pub struct TrapContext {
     pub kernel_satp: usize,
     pub kernel_sp: usize,
     pub trap_handler: usize,
+    /// nonzero if switching satp needs sfence.vma, without ASID support
+    pub tlb_flush: usize,
+    /// hart the thread returns to user mode on, put back in tp on traps
+    pub hart_id: usize,
 }
 
 impl TrapContext {
     pub fn set_sp(&mut self, sp: usize) {
         self.x[2] = sp;
     }
+    fn sstatus_bits(&mut self) -> &mut usize {
+        // Sstatus is a plain usize, which trap.S relies on as well
+        unsafe { &mut *(&mut self.sstatus as *mut Sstatus as *mut usize) }
+    }
+    /// The sstatus field at `shift`, FS or VS.
+    pub fn ext_state(&self, shift: usize) -> ExtState {
+        match (self.sstatus.bits() >> shift) & 3 {
+            0 => ExtState::Off,
+            1 => ExtState::Initial,
+            2 => ExtState::Clean,
+            _ => ExtState::Dirty,
+        }
+    }
+    pub fn set_ext_state(&mut self, shift: usize, state: ExtState) {
+        let bits = self.sstatus_bits();
+        *bits = *bits & !(3 << shift) | (state as usize) << shift;
+    }
     pub fn app_init_context(
         entry: usize,
         sp: usize,
@@ -32,8 +65,13 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            tlb_flush: 0,
+            hart_id: 0,
         };
         cx.set_sp(sp);
+        // floating point and vector registers are handed out on first use
+        cx.set_ext_state(FS_SHIFT, ExtState::Off);
+        cx.set_ext_state(VS_SHIFT, ExtState::Off);
         cx
     }
 }
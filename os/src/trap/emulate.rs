@@ -0,0 +1,161 @@
+//! Emulation of user instructions the hart refuses to execute.
+
+use super::TrapContext;
+use crate::mm::{PageTable, VirtAddr};
+use crate::task::{fault_in_current_page, SignalFlags};
+
+/// Access a user byte through the page table of `token`, checking the same
+/// permissions the hardware would.
+fn user_byte(token: usize, va: usize, write: bool) -> Result<&'static mut u8, SignalFlags> {
+    let va = VirtAddr::from(va);
+    let page_table = PageTable::from_token(token);
+    // a page dropped with madvise, or one a private file mapping is to copy
+    // before a store, is faulted in as the hardware access would
+    if !page_table
+        .translate(va.floor())
+        .map_or(false, |pte| pte.is_valid() && (!write || pte.writable()))
+    {
+        fault_in_current_page(token, va.floor());
+    }
+    let pte = page_table
+        .translate(va.floor())
+        .ok_or(SignalFlags::SIGSEGV)?;
+    if !pte.is_valid()
+        || !pte.user_accessible()
+        || !(if write {
+            pte.writable()
+        } else {
+            pte.readable()
+        })
+    {
+        return Err(SignalFlags::SIGSEGV);
+    }
+    Ok(&mut pte.ppn().get_bytes_array()[va.page_offset()])
+}
+
+/// Little-endian load of `len` bytes, one byte at a time.
+pub fn read_user(token: usize, va: usize, len: usize) -> Result<u64, SignalFlags> {
+    let mut value = 0u64;
+    for i in (0..len).rev() {
+        value = value << 8 | *user_byte(token, va + i, false)? as u64;
+    }
+    Ok(value)
+}
+
+/// Little-endian store of the low `len` bytes of `value`. Every byte is
+/// checked before the first one is written.
+pub fn write_user(token: usize, va: usize, len: usize, value: u64) -> Result<(), SignalFlags> {
+    for i in 0..len {
+        user_byte(token, va + i, true)?;
+    }
+    for i in 0..len {
+        *user_byte(token, va + i, true)? = (value >> (8 * i)) as u8;
+    }
+    Ok(())
+}
+
+/// Fetch the instruction at `pc`, returning it with its length in bytes.
+pub fn fetch_instruction(token: usize, pc: usize) -> Result<(u32, usize), SignalFlags> {
+    let low = read_user(token, pc, 2)? as u32;
+    if low & 0b11 != 0b11 {
+        Ok((low, 2))
+    } else {
+        let high = read_user(token, pc + 2, 2)? as u32;
+        Ok((low | high << 16, 4))
+    }
+}
+
+const LOAD_MISALIGNED: usize = 4;
+const STORE_MISALIGNED: usize = 6;
+
+pub fn is_misaligned(code: usize) -> bool {
+    code == LOAD_MISALIGNED || code == STORE_MISALIGNED
+}
+
+/// Length of the instruction at `pc` from its low opcode bits, 4 if it
+/// cannot be read.
+pub fn instruction_len(token: usize, pc: usize) -> usize {
+    match read_user(token, pc, 2) {
+        Ok(low) if low & 0b11 != 0b11 => 2,
+        _ => 4,
+    }
+}
+
+fn sign_extend(value: u64, bits: usize) -> u64 {
+    let shift = 64 - bits;
+    (((value << shift) as i64) >> shift) as u64
+}
+
+enum Access {
+    /// register, width, sign extended
+    Load(usize, usize, bool),
+    /// register, width
+    Store(usize, usize),
+}
+
+fn decode_access(inst: u32, len: usize) -> Option<Access> {
+    let inst = inst as usize;
+    if len == 4 {
+        let funct3 = (inst >> 12) & 0b111;
+        let rd = (inst >> 7) & 0x1f;
+        let rs2 = (inst >> 20) & 0x1f;
+        return match (inst & 0x7f, funct3) {
+            // lh, lw, ld
+            (0x03, 0b001) => Some(Access::Load(rd, 2, true)),
+            (0x03, 0b010) => Some(Access::Load(rd, 4, true)),
+            (0x03, 0b011) => Some(Access::Load(rd, 8, true)),
+            // lhu, lwu
+            (0x03, 0b101) => Some(Access::Load(rd, 2, false)),
+            (0x03, 0b110) => Some(Access::Load(rd, 4, false)),
+            // sh, sw, sd
+            (0x23, 0b001) => Some(Access::Store(rs2, 2)),
+            (0x23, 0b010) => Some(Access::Store(rs2, 4)),
+            (0x23, 0b011) => Some(Access::Store(rs2, 8)),
+            _ => None,
+        };
+    }
+    // registers x8..x15 in the CL/CS formats
+    let rd_prime = 8 + ((inst >> 2) & 0b111);
+    let rd = (inst >> 7) & 0x1f;
+    let rs2 = (inst >> 2) & 0x1f;
+    match (inst & 0b11, inst >> 13) {
+        // c.lw, c.ld, c.sw, c.sd
+        (0b00, 0b010) => Some(Access::Load(rd_prime, 4, true)),
+        (0b00, 0b011) => Some(Access::Load(rd_prime, 8, true)),
+        (0b00, 0b110) => Some(Access::Store(rd_prime, 4)),
+        (0b00, 0b111) => Some(Access::Store(rd_prime, 8)),
+        // c.lwsp, c.ldsp, c.swsp, c.sdsp
+        (0b10, 0b010) if rd != 0 => Some(Access::Load(rd, 4, true)),
+        (0b10, 0b011) if rd != 0 => Some(Access::Load(rd, 8, true)),
+        (0b10, 0b110) => Some(Access::Store(rs2, 4)),
+        (0b10, 0b111) => Some(Access::Store(rs2, 8)),
+        _ => None,
+    }
+}
+
+/// Perform a misaligned integer load or store byte by byte and step over it.
+/// `addr` is the faulting address reported in stval. Accesses that cannot
+/// be emulated raise SIGBUS, bad addresses SIGSEGV.
+pub fn emulate_misaligned(
+    token: usize,
+    cx: &mut TrapContext,
+    addr: usize,
+) -> Result<(), SignalFlags> {
+    let (inst, len) = fetch_instruction(token, cx.sepc)?;
+    match decode_access(inst, len).ok_or(SignalFlags::SIGBUS)? {
+        Access::Load(rd, width, signed) => {
+            let mut value = read_user(token, addr, width)?;
+            if signed {
+                value = sign_extend(value, width * 8);
+            }
+            if rd != 0 {
+                cx.x[rd] = value as usize;
+            }
+        }
+        Access::Store(rs2, width) => {
+            write_user(token, addr, width, cx.x[rs2] as u64)?;
+        }
+    }
+    cx.sepc += len;
+    Ok(())
+}
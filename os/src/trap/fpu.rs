@@ -0,0 +1,763 @@
+//! Software emulation of the F and D extensions for harts without an FPU.
+//!
+//! Only integer arithmetic is used here, so the kernel never needs the FPU
+//! it is emulating. Results follow the RISC-V rules: every NaN produced is
+//! the canonical one and single values are NaN-boxed in the registers.
+
+use super::emulate::{fetch_instruction, read_user, write_user};
+use super::TrapContext;
+use crate::task::SignalFlags;
+use core::cmp::Ordering;
+
+/// Floating point registers and fcsr of a thread whose FP instructions are
+/// emulated, or saved while another thread has the FPU.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FpState {
+    pub f: [u64; 32],
+    pub fcsr: u32,
+}
+
+impl FpState {
+    pub const fn new() -> Self {
+        Self {
+            f: [0; 32],
+            fcsr: 0,
+        }
+    }
+}
+
+// accrued exception flags
+const NX: u32 = 1 << 0;
+const UF: u32 = 1 << 1;
+const OF: u32 = 1 << 2;
+const DZ: u32 = 1 << 3;
+const NV: u32 = 1 << 4;
+
+// rounding modes
+const RNE: u32 = 0;
+const RTZ: u32 = 1;
+const RDN: u32 = 2;
+const RUP: u32 = 3;
+const RMM: u32 = 4;
+const DYN: u32 = 7;
+
+// floating point csrs
+const CSR_FFLAGS: usize = 1;
+const CSR_FRM: usize = 2;
+const CSR_FCSR: usize = 3;
+
+#[derive(Clone, Copy)]
+struct Format {
+    exp_bits: u32,
+    frac_bits: u32,
+}
+
+const SINGLE: Format = Format {
+    exp_bits: 8,
+    frac_bits: 23,
+};
+const DOUBLE: Format = Format {
+    exp_bits: 11,
+    frac_bits: 52,
+};
+
+/// Shift `sig` right by `shift` bits rounding as `rm` says. Returns the
+/// rounded value and whether anything was lost.
+fn shift_round(sign: bool, sig: u128, shift: i32, rm: u32) -> (u128, bool) {
+    if shift <= 0 {
+        return (sig << -shift, false);
+    }
+    let (mant, half_cmp, inexact) = if shift > 128 {
+        (0, Ordering::Less, true)
+    } else if shift == 128 {
+        (0, sig.cmp(&(1 << 127)), sig != 0)
+    } else {
+        let rem = sig & ((1 << shift) - 1);
+        (sig >> shift, rem.cmp(&(1 << (shift - 1))), rem != 0)
+    };
+    let round_up = match rm {
+        RNE => half_cmp == Ordering::Greater || (half_cmp == Ordering::Equal && mant & 1 == 1),
+        RTZ => false,
+        RDN => sign && inexact,
+        RUP => !sign && inexact,
+        _ => half_cmp != Ordering::Less,
+    };
+    (mant + round_up as u128, inexact)
+}
+
+impl Format {
+    fn sign_bit(self) -> u64 {
+        1 << (self.exp_bits + self.frac_bits)
+    }
+    fn max_exp(self) -> u64 {
+        (1 << self.exp_bits) - 1
+    }
+    fn frac_mask(self) -> u64 {
+        (1 << self.frac_bits) - 1
+    }
+    fn bias(self) -> i32 {
+        (1 << (self.exp_bits - 1)) - 1
+    }
+    /// Exponent of the lowest significand bit of subnormals.
+    fn min_exp(self) -> i32 {
+        1 - self.bias() - self.frac_bits as i32
+    }
+    fn quiet_bit(self) -> u64 {
+        1 << (self.frac_bits - 1)
+    }
+    fn canonical_nan(self) -> u64 {
+        self.max_exp() << self.frac_bits | self.quiet_bit()
+    }
+    fn zero(self, sign: bool) -> u64 {
+        if sign {
+            self.sign_bit()
+        } else {
+            0
+        }
+    }
+    fn infinity(self, sign: bool) -> u64 {
+        self.zero(sign) | self.max_exp() << self.frac_bits
+    }
+    fn max_finite(self, sign: bool) -> u64 {
+        self.zero(sign) | (self.max_exp() - 1) << self.frac_bits | self.frac_mask()
+    }
+    fn sign(self, bits: u64) -> bool {
+        bits & self.sign_bit() != 0
+    }
+    fn biased_exp(self, bits: u64) -> u64 {
+        (bits >> self.frac_bits) & self.max_exp()
+    }
+    fn is_nan(self, bits: u64) -> bool {
+        self.biased_exp(bits) == self.max_exp() && bits & self.frac_mask() != 0
+    }
+    fn is_signaling(self, bits: u64) -> bool {
+        self.is_nan(bits) && bits & self.quiet_bit() == 0
+    }
+    fn is_inf(self, bits: u64) -> bool {
+        self.biased_exp(bits) == self.max_exp() && bits & self.frac_mask() == 0
+    }
+    fn is_zero(self, bits: u64) -> bool {
+        bits & !self.sign_bit() == 0
+    }
+    fn negate(self, bits: u64) -> u64 {
+        bits ^ self.sign_bit()
+    }
+    /// Finite nonzero value as significand * 2^exponent.
+    fn unpack(self, bits: u64) -> (u128, i32) {
+        let exp = self.biased_exp(bits);
+        let frac = (bits & self.frac_mask()) as u128;
+        if exp == 0 {
+            (frac, self.min_exp())
+        } else {
+            (
+                frac | 1 << self.frac_bits,
+                exp as i32 - self.bias() - self.frac_bits as i32,
+            )
+        }
+    }
+    /// Round sig * 2^exp to this format.
+    fn round_pack(self, sign: bool, sig: u128, exp: i32, rm: u32, flags: &mut u32) -> u64 {
+        if sig == 0 {
+            return self.zero(sign);
+        }
+        let msb_exp = exp + (127 - sig.leading_zeros()) as i32;
+        let mut lsb_exp = (msb_exp - self.frac_bits as i32).max(self.min_exp());
+        let (mut mant, inexact) = shift_round(sign, sig, lsb_exp - exp, rm);
+        if mant == 1 << (self.frac_bits + 1) {
+            mant >>= 1;
+            lsb_exp += 1;
+        }
+        let biased = if mant >> self.frac_bits == 0 {
+            0
+        } else {
+            (lsb_exp - self.min_exp() + 1) as u64
+        };
+        if biased >= self.max_exp() {
+            *flags |= OF | NX;
+            return match rm {
+                RTZ => self.max_finite(sign),
+                RDN if !sign => self.max_finite(sign),
+                RUP if sign => self.max_finite(sign),
+                _ => self.infinity(sign),
+            };
+        }
+        if inexact {
+            *flags |= NX;
+            if biased == 0 {
+                *flags |= UF;
+            }
+        }
+        self.zero(sign) | biased << self.frac_bits | (mant as u64 & self.frac_mask())
+    }
+
+    fn one(self) -> u64 {
+        (self.bias() as u64) << self.frac_bits
+    }
+    /// a * b + c with a single rounding, also used for add and multiply.
+    /// A missing addend leaves the sign of a zero product alone.
+    fn mul_add(self, a: u64, b: u64, c: Option<u64>, rm: u32, flags: &mut u32) -> u64 {
+        let c = c.unwrap_or(self.zero(self.sign(a) ^ self.sign(b)));
+        if self.is_signaling(a) || self.is_signaling(b) || self.is_signaling(c) {
+            *flags |= NV;
+        }
+        if (self.is_inf(a) && self.is_zero(b)) || (self.is_zero(a) && self.is_inf(b)) {
+            *flags |= NV;
+            return self.canonical_nan();
+        }
+        if self.is_nan(a) || self.is_nan(b) || self.is_nan(c) {
+            return self.canonical_nan();
+        }
+        let prod_sign = self.sign(a) ^ self.sign(b);
+        let c_sign = self.sign(c);
+        if self.is_inf(a) || self.is_inf(b) {
+            if self.is_inf(c) && c_sign != prod_sign {
+                *flags |= NV;
+                return self.canonical_nan();
+            }
+            return self.infinity(prod_sign);
+        }
+        if self.is_inf(c) {
+            return c;
+        }
+        // the product of two significands fits in 106 bits, so it is exact
+        let (prod, prod_exp) = if self.is_zero(a) || self.is_zero(b) {
+            (0, 0)
+        } else {
+            let (sa, ea) = self.unpack(a);
+            let (sb, eb) = self.unpack(b);
+            (sa * sb, ea + eb)
+        };
+        let (addend, addend_exp) = if self.is_zero(c) {
+            (0, 0)
+        } else {
+            self.unpack(c)
+        };
+        if prod == 0 && addend == 0 {
+            return self.zero(if prod_sign == c_sign {
+                prod_sign
+            } else {
+                rm == RDN
+            });
+        }
+        let (sign, sig, exp) = add_exact((prod_sign, prod, prod_exp), (c_sign, addend, addend_exp));
+        if sig == 0 {
+            return self.zero(rm == RDN);
+        }
+        self.round_pack(sign, sig, exp, rm, flags)
+    }
+
+    fn div(self, a: u64, b: u64, rm: u32, flags: &mut u32) -> u64 {
+        if self.is_signaling(a) || self.is_signaling(b) {
+            *flags |= NV;
+        }
+        if self.is_nan(a) || self.is_nan(b) {
+            return self.canonical_nan();
+        }
+        let sign = self.sign(a) ^ self.sign(b);
+        if (self.is_inf(a) && self.is_inf(b)) || (self.is_zero(a) && self.is_zero(b)) {
+            *flags |= NV;
+            return self.canonical_nan();
+        }
+        if self.is_inf(a) {
+            return self.infinity(sign);
+        }
+        if self.is_inf(b) || self.is_zero(a) {
+            return self.zero(sign);
+        }
+        if self.is_zero(b) {
+            *flags |= DZ;
+            return self.infinity(sign);
+        }
+        let (sa, ea) = self.unpack(a);
+        let (sb, eb) = self.unpack(b);
+        // leave at least 70 quotient bits
+        let shift = sa.leading_zeros() - 1;
+        let dividend = sa << shift;
+        let mut quotient = dividend / sb;
+        if dividend % sb != 0 {
+            quotient |= 1;
+        }
+        self.round_pack(sign, quotient, ea - shift as i32 - eb, rm, flags)
+    }
+
+    fn sqrt(self, a: u64, rm: u32, flags: &mut u32) -> u64 {
+        if self.is_nan(a) {
+            if self.is_signaling(a) {
+                *flags |= NV;
+            }
+            return self.canonical_nan();
+        }
+        if self.is_zero(a) {
+            return a;
+        }
+        if self.sign(a) {
+            *flags |= NV;
+            return self.canonical_nan();
+        }
+        if self.is_inf(a) {
+            return a;
+        }
+        let (sig, exp) = self.unpack(a);
+        let mut shift = sig.leading_zeros() as i32 - 7;
+        if (exp - shift) & 1 != 0 {
+            shift += 1;
+        }
+        let (mut root, inexact) = isqrt(sig << shift);
+        if inexact {
+            root |= 1;
+        }
+        self.round_pack(false, root, (exp - shift) / 2, rm, flags)
+    }
+
+    /// Ordering key of a non-NaN value, equal for both zeros.
+    fn key(self, bits: u64) -> i64 {
+        let magnitude = (bits & !self.sign_bit()) as i64;
+        if self.sign(bits) {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// feq, flt and fle, `signaling` for the latter two.
+    fn compare(self, a: u64, b: u64, signaling: bool, flags: &mut u32) -> Option<Ordering> {
+        if self.is_nan(a) || self.is_nan(b) {
+            if signaling || self.is_signaling(a) || self.is_signaling(b) {
+                *flags |= NV;
+            }
+            return None;
+        }
+        Some(self.key(a).cmp(&self.key(b)))
+    }
+
+    fn min_max(self, a: u64, b: u64, max: bool, flags: &mut u32) -> u64 {
+        if self.is_signaling(a) || self.is_signaling(b) {
+            *flags |= NV;
+        }
+        match (self.is_nan(a), self.is_nan(b)) {
+            (true, true) => return self.canonical_nan(),
+            (true, false) => return b,
+            (false, true) => return a,
+            _ => {}
+        }
+        // -0.0 is smaller than +0.0 here
+        let a_first = match self.key(a).cmp(&self.key(b)) {
+            Ordering::Less => true,
+            Ordering::Greater => false,
+            Ordering::Equal => self.sign(a),
+        };
+        if a_first != max {
+            a
+        } else {
+            b
+        }
+    }
+
+    fn class(self, bits: u64) -> usize {
+        let sign = self.sign(bits);
+        let index = if self.is_nan(bits) {
+            return if self.is_signaling(bits) {
+                1 << 8
+            } else {
+                1 << 9
+            };
+        } else if self.is_inf(bits) {
+            0
+        } else if self.biased_exp(bits) != 0 {
+            1
+        } else if !self.is_zero(bits) {
+            2
+        } else {
+            3
+        };
+        if sign {
+            1 << index
+        } else {
+            1 << (7 - index)
+        }
+    }
+
+    /// fcvt to a `width` bit integer, saturating invalid inputs. The result
+    /// is sign-extended to 64 bits as the ISA requires.
+    fn to_int(self, a: u64, signed: bool, width: u32, rm: u32, flags: &mut u32) -> u64 {
+        let max = if signed {
+            (1u128 << (width - 1)) - 1
+        } else {
+            (1u128 << width) - 1
+        };
+        let sign = self.sign(a) && !self.is_nan(a);
+        let (magnitude, inexact) = if self.is_nan(a) || self.is_inf(a) {
+            (u128::MAX, false)
+        } else if self.is_zero(a) {
+            (0, false)
+        } else {
+            let (sig, exp) = self.unpack(a);
+            if exp > 64 {
+                (u128::MAX, false)
+            } else {
+                shift_round(sign, sig, -exp, rm)
+            }
+        };
+        let in_range = if !sign {
+            magnitude <= max
+        } else if signed {
+            magnitude <= max + 1
+        } else {
+            magnitude == 0
+        };
+        let value = if !in_range {
+            *flags |= NV;
+            match (sign, signed) {
+                (false, _) => max as u64,
+                (true, true) => (max + 1).wrapping_neg() as u64,
+                (true, false) => 0,
+            }
+        } else {
+            if inexact {
+                *flags |= NX;
+            }
+            if sign {
+                (magnitude as u64).wrapping_neg()
+            } else {
+                magnitude as u64
+            }
+        };
+        if width == 32 {
+            value as u32 as i32 as i64 as u64
+        } else {
+            value
+        }
+    }
+
+    fn from_int(self, sign: bool, magnitude: u64, rm: u32, flags: &mut u32) -> u64 {
+        self.round_pack(sign, magnitude as u128, 0, rm, flags)
+    }
+
+    /// fcvt.s.d and fcvt.d.s
+    fn convert(self, to: Format, a: u64, rm: u32, flags: &mut u32) -> u64 {
+        let sign = self.sign(a);
+        if self.is_nan(a) {
+            if self.is_signaling(a) {
+                *flags |= NV;
+            }
+            to.canonical_nan()
+        } else if self.is_inf(a) {
+            to.infinity(sign)
+        } else if self.is_zero(a) {
+            to.zero(sign)
+        } else {
+            let (sig, exp) = self.unpack(a);
+            to.round_pack(sign, sig, exp, rm, flags)
+        }
+    }
+
+    fn load(self, fp: &FpState, reg: usize) -> u64 {
+        match self.exp_bits {
+            // a single is only valid when NaN-boxed
+            8 if fp.f[reg] >> 32 != 0xffff_ffff => SINGLE.canonical_nan(),
+            8 => fp.f[reg] & 0xffff_ffff,
+            _ => fp.f[reg],
+        }
+    }
+
+    fn store(self, fp: &mut FpState, reg: usize, bits: u64) {
+        fp.f[reg] = match self.exp_bits {
+            8 => 0xffff_ffff_0000_0000 | bits,
+            _ => bits,
+        };
+    }
+}
+
+/// Exact sum of two signed values sig * 2^exp. Bits shifted out of the
+/// smaller one are folded into its lowest bit.
+fn add_exact(a: (bool, u128, i32), b: (bool, u128, i32)) -> (bool, u128, i32) {
+    if a.1 == 0 {
+        return b;
+    }
+    if b.1 == 0 {
+        return a;
+    }
+    // put the top bit of both at bit 124, leaving room for the carry
+    let normalize = |(sign, sig, exp): (bool, u128, i32)| {
+        let shift = sig.leading_zeros() as i32 - 3;
+        (sign, sig << shift, exp - shift)
+    };
+    let (mut big, mut small) = (normalize(a), normalize(b));
+    if small.2 > big.2 {
+        core::mem::swap(&mut big, &mut small);
+    }
+    let distance = (big.2 - small.2) as u32;
+    let aligned = if distance >= 128 {
+        1
+    } else {
+        let lost = small.1 & ((1 << distance) - 1) != 0;
+        small.1 >> distance | lost as u128
+    };
+    if big.0 == small.0 {
+        (big.0, big.1 + aligned, big.2)
+    } else if big.1 >= aligned {
+        (big.0, big.1 - aligned, big.2)
+    } else {
+        (small.0, aligned - big.1, big.2)
+    }
+}
+
+/// Integer square root and whether it was inexact.
+fn isqrt(mut n: u128) -> (u128, bool) {
+    let mut root = 0u128;
+    let mut bit = 1u128 << 126;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if n >= root + bit {
+            n -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    (root, n != 0)
+}
+
+fn sign_extend(value: usize, bits: usize) -> usize {
+    let shift = usize::BITS as usize - bits;
+    (((value << shift) as isize) >> shift) as usize
+}
+
+/// Emulate the floating point instruction at sepc on `fp` and step over
+/// it. Anything else is left to SIGILL.
+pub fn emulate_fp(token: usize, cx: &mut TrapContext, fp: &mut FpState) -> Result<(), SignalFlags> {
+    let (inst, len) = fetch_instruction(token, cx.sepc)?;
+    let inst = inst as usize;
+    if len == 2 {
+        emulate_compressed(token, cx, fp, inst)?;
+    } else {
+        emulate_full(token, cx, fp, inst)?;
+    }
+    cx.sepc += len;
+    Ok(())
+}
+
+/// c.fld, c.fsd, c.fldsp and c.fsdsp
+fn emulate_compressed(
+    token: usize,
+    cx: &mut TrapContext,
+    fp: &mut FpState,
+    inst: usize,
+) -> Result<(), SignalFlags> {
+    let reg_prime = |shift: usize| 8 + ((inst >> shift) & 0b111);
+    match (inst & 0b11, inst >> 13) {
+        (0b00, 0b001) | (0b00, 0b101) => {
+            let offset = ((inst >> 10) & 0b111) << 3 | ((inst >> 5) & 0b11) << 6;
+            let addr = cx.x[reg_prime(7)].wrapping_add(offset);
+            if inst >> 13 == 0b001 {
+                fp.f[reg_prime(2)] = read_user(token, addr, 8)?;
+            } else {
+                write_user(token, addr, 8, fp.f[reg_prime(2)])?;
+            }
+        }
+        (0b10, 0b001) => {
+            let offset =
+                ((inst >> 12) & 1) << 5 | ((inst >> 5) & 0b11) << 3 | ((inst >> 2) & 0b111) << 6;
+            fp.f[(inst >> 7) & 0x1f] = read_user(token, cx.x[2].wrapping_add(offset), 8)?;
+        }
+        (0b10, 0b101) => {
+            let offset = ((inst >> 10) & 0b111) << 3 | ((inst >> 7) & 0b111) << 6;
+            write_user(
+                token,
+                cx.x[2].wrapping_add(offset),
+                8,
+                fp.f[(inst >> 2) & 0x1f],
+            )?;
+        }
+        _ => return Err(SignalFlags::SIGILL),
+    }
+    Ok(())
+}
+
+fn emulate_full(
+    token: usize,
+    cx: &mut TrapContext,
+    fp: &mut FpState,
+    inst: usize,
+) -> Result<(), SignalFlags> {
+    let rd = (inst >> 7) & 0x1f;
+    let funct3 = (inst >> 12) & 0b111;
+    let rs1 = (inst >> 15) & 0x1f;
+    let rs2 = (inst >> 20) & 0x1f;
+    let rs3 = inst >> 27;
+    let set_x = |cx: &mut TrapContext, value: u64| {
+        if rd != 0 {
+            cx.x[rd] = value as usize;
+        }
+    };
+    let format = |fmt: usize| match fmt {
+        0 => Ok(SINGLE),
+        1 => Ok(DOUBLE),
+        _ => Err(SignalFlags::SIGILL),
+    };
+    let rounding = |fcsr: u32| {
+        let rm = match funct3 as u32 {
+            DYN => (fcsr >> 5) & 0b111,
+            rm => rm,
+        };
+        if rm > RMM {
+            Err(SignalFlags::SIGILL)
+        } else {
+            Ok(rm)
+        }
+    };
+    let mut flags = 0;
+    match inst & 0x7f {
+        // flw, fld
+        0x07 => {
+            let addr = cx.x[rs1].wrapping_add(sign_extend(inst >> 20, 12));
+            match funct3 {
+                0b010 => SINGLE.store(fp, rd, read_user(token, addr, 4)?),
+                0b011 => DOUBLE.store(fp, rd, read_user(token, addr, 8)?),
+                _ => return Err(SignalFlags::SIGILL),
+            }
+        }
+        // fsw, fsd
+        0x27 => {
+            let offset = sign_extend((inst >> 25) << 5 | (inst >> 7) & 0x1f, 12);
+            let addr = cx.x[rs1].wrapping_add(offset);
+            match funct3 {
+                0b010 => write_user(token, addr, 4, fp.f[rs2])?,
+                0b011 => write_user(token, addr, 8, fp.f[rs2])?,
+                _ => return Err(SignalFlags::SIGILL),
+            }
+        }
+        // fmadd, fmsub, fnmsub, fnmadd
+        opcode @ (0x43 | 0x47 | 0x4b | 0x4f) => {
+            let f = format((inst >> 25) & 0b11)?;
+            let rm = rounding(fp.fcsr)?;
+            let (mut a, b, mut c) = (f.load(fp, rs1), f.load(fp, rs2), f.load(fp, rs3));
+            if opcode == 0x4b || opcode == 0x4f {
+                a = f.negate(a);
+            }
+            if opcode == 0x47 || opcode == 0x4f {
+                c = f.negate(c);
+            }
+            let result = f.mul_add(a, b, Some(c), rm, &mut flags);
+            f.store(fp, rd, result);
+        }
+        0x53 => {
+            let f = format((inst >> 25) & 0b11)?;
+            let (a, b) = (f.load(fp, rs1), f.load(fp, rs2));
+            match inst >> 27 {
+                // fadd, fsub, fmul, fdiv, fsqrt
+                op @ (0x00 | 0x01 | 0x02 | 0x03 | 0x0b) => {
+                    let rm = rounding(fp.fcsr)?;
+                    let result = match op {
+                        0x00 => f.mul_add(a, f.one(), Some(b), rm, &mut flags),
+                        0x01 => f.mul_add(a, f.one(), Some(f.negate(b)), rm, &mut flags),
+                        0x02 => f.mul_add(a, b, None, rm, &mut flags),
+                        0x03 => f.div(a, b, rm, &mut flags),
+                        _ => f.sqrt(a, rm, &mut flags),
+                    };
+                    f.store(fp, rd, result);
+                }
+                // fsgnj, fsgnjn, fsgnjx
+                0x04 => {
+                    let sign = match funct3 {
+                        0 => b & f.sign_bit(),
+                        1 => !b & f.sign_bit(),
+                        2 => (a ^ b) & f.sign_bit(),
+                        _ => return Err(SignalFlags::SIGILL),
+                    };
+                    f.store(fp, rd, a & !f.sign_bit() | sign);
+                }
+                // fmin, fmax
+                0x05 if funct3 < 2 => {
+                    let result = f.min_max(a, b, funct3 == 1, &mut flags);
+                    f.store(fp, rd, result);
+                }
+                // fcvt.s.d, fcvt.d.s
+                0x08 => {
+                    let rm = rounding(fp.fcsr)?;
+                    let from = format(rs2)?;
+                    let result = from.convert(f, from.load(fp, rs1), rm, &mut flags);
+                    f.store(fp, rd, result);
+                }
+                // fle, flt, feq
+                0x14 => {
+                    let result = match funct3 {
+                        0 => matches!(
+                            f.compare(a, b, true, &mut flags),
+                            Some(Ordering::Less | Ordering::Equal)
+                        ),
+                        1 => f.compare(a, b, true, &mut flags) == Some(Ordering::Less),
+                        2 => f.compare(a, b, false, &mut flags) == Some(Ordering::Equal),
+                        _ => return Err(SignalFlags::SIGILL),
+                    };
+                    set_x(cx, result as u64);
+                }
+                // fcvt.w, fcvt.wu, fcvt.l, fcvt.lu
+                0x18 if rs2 < 4 => {
+                    let rm = rounding(fp.fcsr)?;
+                    let width = if rs2 < 2 { 32 } else { 64 };
+                    let result = f.to_int(a, rs2 & 1 == 0, width, rm, &mut flags);
+                    set_x(cx, result);
+                }
+                // fcvt from w, wu, l, lu
+                0x1a if rs2 < 4 => {
+                    let rm = rounding(fp.fcsr)?;
+                    let x = cx.x[rs1] as u64;
+                    let (sign, magnitude) = match rs2 {
+                        0 => ((x as i32) < 0, (x as i32).unsigned_abs() as u64),
+                        1 => (false, x as u32 as u64),
+                        2 => ((x as i64) < 0, (x as i64).unsigned_abs()),
+                        _ => (false, x),
+                    };
+                    let result = f.from_int(sign, magnitude, rm, &mut flags);
+                    f.store(fp, rd, result);
+                }
+                // fmv.x.w, fmv.x.d, fclass
+                0x1c if rs2 == 0 => match funct3 {
+                    0 if f.exp_bits == 8 => set_x(cx, fp.f[rs1] as u32 as i32 as i64 as u64),
+                    0 => set_x(cx, fp.f[rs1]),
+                    1 => set_x(cx, f.class(a) as u64),
+                    _ => return Err(SignalFlags::SIGILL),
+                },
+                // fmv.w.x, fmv.d.x
+                0x1e if rs2 == 0 && funct3 == 0 => {
+                    let x = cx.x[rs1] as u64;
+                    f.store(fp, rd, if f.exp_bits == 8 { x & 0xffff_ffff } else { x });
+                }
+                _ => return Err(SignalFlags::SIGILL),
+            }
+        }
+        // csr instructions on fflags, frm and fcsr
+        0x73 if funct3 & 0b11 != 0 => {
+            let (shift, mask) = match inst >> 20 {
+                CSR_FFLAGS => (0, 0x1f),
+                CSR_FRM => (5, 0x7),
+                CSR_FCSR => (0, 0xff),
+                _ => return Err(SignalFlags::SIGILL),
+            };
+            let old = (fp.fcsr >> shift) & mask;
+            let source = if funct3 & 0b100 != 0 {
+                rs1 as u32
+            } else {
+                cx.x[rs1] as u32
+            };
+            let new = match funct3 & 0b11 {
+                1 => source,
+                2 => old | source,
+                _ => old & !source,
+            };
+            // csrrs and csrrc with x0 only read
+            if funct3 & 0b11 == 1 || rs1 != 0 {
+                fp.fcsr = fp.fcsr & !(mask << shift) | (new & mask) << shift;
+            }
+            set_x(cx, old as u64);
+        }
+        _ => return Err(SignalFlags::SIGILL),
+    }
+    fp.fcsr |= flags;
+    Ok(())
+}
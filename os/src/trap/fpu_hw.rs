@@ -0,0 +1,48 @@
+//! The hardware FPU, its registers are switched lazily through sstatus.FS
+//! and kept in the same `FpState` the emulation uses without one.
+
+use super::lazy::{ExtUnit, Owner};
+use super::{FpState, FS_SHIFT};
+use crate::sync::UPIntrFreeCell;
+use crate::task::TaskControlBlockInner;
+use core::arch::global_asm;
+use lazy_static::*;
+
+global_asm!(include_str!("fpu_hw.S"));
+
+extern "C" {
+    fn __fpu_probe() -> usize;
+    fn __fpu_save(state: *mut FpState);
+    fn __fpu_restore(state: *const FpState);
+}
+
+lazy_static! {
+    static ref FPU_PRESENT: bool = unsafe { __fpu_probe() != 0 };
+    static ref FPU_OWNER: Owner = unsafe { UPIntrFreeCell::new(None) };
+}
+
+pub struct Fpu;
+
+impl ExtUnit for Fpu {
+    const SHIFT: usize = FS_SHIFT;
+
+    fn present() -> bool {
+        *FPU_PRESENT
+    }
+
+    fn owner() -> &'static Owner {
+        &FPU_OWNER
+    }
+
+    fn save(task_inner: &mut TaskControlBlockInner) {
+        unsafe { __fpu_save(&mut task_inner.fp_state) };
+    }
+
+    fn restore(task_inner: &mut TaskControlBlockInner) {
+        unsafe { __fpu_restore(&task_inner.fp_state) };
+    }
+
+    fn discard(task_inner: &mut TaskControlBlockInner) {
+        task_inner.fp_state = FpState::new();
+    }
+}
@@ -0,0 +1,126 @@
+//! Lazy context switching of extension register files. User threads start
+//! with the unit off in sstatus, the first instruction using it traps as
+//! illegal and hands the registers to the thread, saving them for the
+//! previous owner if that one had dirtied them. Threads which never use a
+//! unit cost nothing.
+
+use super::fpu_hw::Fpu;
+use super::vector::Vector;
+use super::{ExtState, TrapContext};
+use crate::sync::UPIntrFreeCell;
+use crate::task::{current_task, TaskControlBlock, TaskControlBlockInner};
+use alloc::sync::{Arc, Weak};
+use core::arch::asm;
+
+pub type Owner = UPIntrFreeCell<Option<Weak<TaskControlBlock>>>;
+
+pub trait ExtUnit {
+    /// sstatus field of the unit
+    const SHIFT: usize;
+    fn present() -> bool;
+    /// thread whose registers are loaded in the unit
+    fn owner() -> &'static Owner;
+    /// Registers to the save area of a thread, the unit is on.
+    fn save(task_inner: &mut TaskControlBlockInner);
+    fn restore(task_inner: &mut TaskControlBlockInner);
+    /// Reset the save area, as on exec.
+    fn discard(task_inner: &mut TaskControlBlockInner);
+}
+
+/// Run `f` with the unit accessible from the kernel.
+fn with_unit<U: ExtUnit>(f: impl FnOnce()) {
+    let bits = 3usize << U::SHIFT;
+    unsafe { asm!("csrs sstatus, {}", in(reg) bits) };
+    f();
+    unsafe { asm!("csrc sstatus, {}", in(reg) bits) };
+}
+
+fn is_owner<U: ExtUnit>(task: &Arc<TaskControlBlock>) -> bool {
+    match U::owner().exclusive_access().as_ref() {
+        Some(owner) => owner.as_ptr() == Arc::as_ptr(task),
+        None => false,
+    }
+}
+
+/// Write the registers back to the owner if it has modified them, then
+/// revoke its access unless it keeps the unit.
+fn save_owner<U: ExtUnit>(keep: bool) {
+    let owner = if keep {
+        U::owner().exclusive_access().clone()
+    } else {
+        U::owner().exclusive_access().take()
+    };
+    let owner = match owner.and_then(|owner| owner.upgrade()) {
+        Some(owner) => owner,
+        None => return,
+    };
+    let mut owner_inner = owner.inner_exclusive_access();
+    // an exited thread has no trap context left
+    if owner_inner.res.is_none() {
+        return;
+    }
+    let trap_cx = owner_inner.get_trap_cx();
+    let state = trap_cx.ext_state(U::SHIFT);
+    if !keep {
+        // it traps on its next use of the unit
+        trap_cx.set_ext_state(U::SHIFT, ExtState::Off);
+    } else if state == ExtState::Dirty {
+        trap_cx.set_ext_state(U::SHIFT, ExtState::Clean);
+    }
+    if state == ExtState::Dirty {
+        with_unit::<U>(|| U::save(&mut owner_inner));
+    }
+}
+
+/// Called on an illegal instruction, return true if the current thread has
+/// been given the unit and should retry the instruction.
+pub fn handle_first_use<U: ExtUnit>(cx: &mut TrapContext) -> bool {
+    if !U::present() || cx.ext_state(U::SHIFT) != ExtState::Off {
+        return false;
+    }
+    let task = current_task().unwrap();
+    if !is_owner::<U>(&task) {
+        save_owner::<U>(false);
+        with_unit::<U>(|| U::restore(&mut task.inner_exclusive_access()));
+        *U::owner().exclusive_access() = Some(Arc::downgrade(&task));
+    }
+    cx.set_ext_state(U::SHIFT, ExtState::Clean);
+    true
+}
+
+/// Called before returning to user mode: a thread which is not the owner,
+/// e.g. after fork or sigreturn, must not see the registers of another one.
+pub fn check_owner<U: ExtUnit>(task: &Arc<TaskControlBlock>, cx: &mut TrapContext) {
+    if cx.ext_state(U::SHIFT) != ExtState::Off && !is_owner::<U>(task) {
+        cx.set_ext_state(U::SHIFT, ExtState::Off);
+    }
+}
+
+/// Bring the save area of `task` up to date, e.g. to copy it on fork.
+pub fn sync<U: ExtUnit>(task: &Arc<TaskControlBlock>) {
+    if is_owner::<U>(task) {
+        save_owner::<U>(true);
+    }
+}
+
+/// Drop the registers of the current thread.
+pub fn discard<U: ExtUnit>() {
+    let task = current_task().unwrap();
+    if is_owner::<U>(&task) {
+        *U::owner().exclusive_access() = None;
+    }
+    U::discard(&mut task.inner_exclusive_access());
+}
+
+/// Bring the saved extension registers of `task` up to date, e.g. before
+/// fork copies them.
+pub fn sync_ext_state(task: &Arc<TaskControlBlock>) {
+    sync::<Fpu>(task);
+    sync::<Vector>(task);
+}
+
+/// Drop the extension registers of the current thread, as on exec.
+pub fn discard_ext_state() {
+    discard::<Fpu>();
+    discard::<Vector>();
+}
@@ -1,26 +1,44 @@
 mod context;
+mod emulate;
+mod fpu;
+mod fpu_hw;
+mod lazy;
+mod trigger;
+mod vector;
 
 use crate::config::TRAMPOLINE;
+use crate::drivers::gpu::fbcon;
+use crate::mm::{asids_supported, kernel_token, VirtAddr};
+use crate::profile;
+use crate::smp;
 use crate::syscall::syscall;
 use crate::task::{
-    check_signals_of_current, current_add_signal, current_trap_cx, current_trap_cx_user_va,
-    current_user_token, exit_current_and_run_next, suspend_current_and_run_next, SignalFlags,
+    current_add_signal, current_process, current_task, current_trap_cx, current_trap_cx_user_va,
+    current_user_satp, current_user_token, deliver_uintr_of_current, dump_core_of_current,
+    exit_current_if_reaped, exit_group_current_and_run_next, fault_in_current_page,
+    handle_signals_of_current, hart_id, job_stop_current, ptrace_step_breakpoint_hit,
+    ptrace_stop_current, ptrace_trigger_hit, suspend_current_and_run_next, uintr_tick_current,
+    SignalFlags,
 };
 use crate::timer::{check_timer, set_next_trigger};
+use crate::trace::{self, TracePoint};
 use core::arch::{asm, global_asm};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, sip, sscratch, sstatus, stval, stvec,
+    satp, sie, sip, sscratch, sstatus, stval, stvec,
 };
 
 global_asm!(include_str!("trap.S"));
 
 pub fn init() {
     set_kernel_trap_entry();
+    trigger::init();
+    vector::init();
 }
 
-fn set_kernel_trap_entry() {
+/// Also all a hart needs that only takes calls, see smp.
+pub fn set_kernel_trap_entry() {
     extern "C" {
         fn __alltraps();
         fn __alltraps_k();
@@ -32,6 +50,15 @@ fn set_kernel_trap_entry() {
     }
 }
 
+/// satp for `__alltraps` to switch to, 0 to stay in the user space.
+pub fn trap_kernel_satp() -> usize {
+    if cfg!(feature = "unified") {
+        0
+    } else {
+        kernel_token()
+    }
+}
+
 fn set_user_trap_entry() {
     unsafe {
         stvec::write(TRAMPOLINE as usize, TrapMode::Direct);
@@ -59,50 +86,105 @@ fn disable_supervisor_interrupt() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    uninstall_triggers();
     let scause = scause::read();
     let stval = stval::read();
     // println!("into {:?}", scause.cause());
+    if trace::enabled(TracePoint::TrapEnter) {
+        trace::trace(TracePoint::TrapEnter, [scause.bits(), current_trap_cx().sepc]);
+    }
+    if let Trap::Exception(
+        Exception::StorePageFault | Exception::InstructionPageFault | Exception::LoadPageFault,
+    ) = scause.cause()
+    {
+        trace::trace(TracePoint::PageFault, [stval, scause.bits()]);
+    }
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
-            // jump to next instruction anyway
+            // jump to next instruction anyway, ecall may come from RVC code
             let mut cx = current_trap_cx();
-            cx.sepc += 4;
+            cx.sepc += emulate::instruction_len(current_user_token(), cx.sepc);
 
             enable_supervisor_interrupt();
 
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
+            // the process may have ended while the syscall blocked
+            exit_current_if_reaped();
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
+        // pages dropped with madvise come back zeroed on the next touch,
+        // private file mappings copy a page cache page on the first store
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+            if fault_in_current_page(current_user_token(), VirtAddr::from(stval).floor()) => {}
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            /*
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
-                scause.cause(),
-                stval,
-                current_trap_cx().sepc,
-            );
-            */
-            current_add_signal(SignalFlags::SIGSEGV);
+            user_fault(scause, stval, SignalFlags::SIGSEGV);
+        }
+        Trap::Exception(Exception::InstructionMisaligned) => {
+            user_fault(scause, stval, SignalFlags::SIGBUS);
+        }
+        // the riscv crate does not name the load-misaligned cause
+        _ if scause.is_exception() && emulate::is_misaligned(scause.code()) => {
+            if let Err(signal) =
+                emulate::emulate_misaligned(current_user_token(), current_trap_cx(), stval)
+            {
+                current_add_signal(signal);
+            }
+        }
+        Trap::Exception(Exception::Breakpoint)
+            if ptrace_step_breakpoint_hit(current_trap_cx().sepc) || ptrace_trigger_hit() => {}
+        Trap::Exception(Exception::Breakpoint) => {
+            // a tracer sees it as a stop with sepc still pointing at the ebreak
+            current_add_signal(SignalFlags::SIGTRAP);
         }
-        Trap::Exception(Exception::IllegalInstruction) => {
-            current_add_signal(SignalFlags::SIGILL);
+        // the first FP or vector instruction of a thread gets it the unit
+        Trap::Exception(Exception::IllegalInstruction)
+            if lazy::handle_first_use::<Fpu>(current_trap_cx())
+                || lazy::handle_first_use::<Vector>(current_trap_cx()) => {}
+        Trap::Exception(Exception::IllegalInstruction) if !Fpu::present() => {
+            // F/D instructions on a hart without an FPU are emulated
+            let token = current_user_token();
+            let task = current_task().unwrap();
+            let mut task_inner = task.inner_exclusive_access();
+            let trap_cx = task_inner.get_trap_cx();
+            let result = fpu::emulate_fp(token, trap_cx, &mut task_inner.fp_state);
+            drop(task_inner);
+            if let Err(signal) = result {
+                current_add_signal(signal);
+            }
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            if profile::running() {
+                profile::sample_user(current_process().getpid(), current_trap_cx().sepc);
+            }
             set_next_trigger();
             check_timer();
+            fbcon::flush();
+            uintr_tick_current();
             suspend_current_and_run_next();
         }
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
             crate::board::irq_handler();
         }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            smp::handle_ipi();
+        }
+        // only the application is at fault for anything else it raises
+        Trap::Exception(_) => {
+            user_fault(scause, stval, SignalFlags::SIGILL);
+        }
         _ => {
             panic!(
                 "Unsupported trap {:?}, stval = {:#x}!",
@@ -111,20 +193,77 @@ pub fn trap_handler() -> ! {
             );
         }
     }
-    // check signals
-    if let Some((errno, msg)) = check_signals_of_current() {
+    // a thread preempted while another one ended the process goes no further
+    exit_current_if_reaped();
+    // a traced process reports signals to its tracer first
+    ptrace_stop_current();
+    // then stop signals may stop it until SIGCONT
+    job_stop_current();
+    // handle signals
+    if let Some(signal) = handle_signals_of_current() {
+        let (errno, msg) = signal.fatal_error();
         println!("[kernel] {}", msg);
-        exit_current_and_run_next(errno);
+        if signal.intersects(SignalFlags::CORE_DUMPING)
+            && current_process().inner_exclusive_access().dumpable
+        {
+            // writing the file may wait for the block device
+            enable_supervisor_interrupt();
+            if let Some(name) = dump_core_of_current(signal) {
+                println!("[kernel] core dumped to {}", name);
+            }
+        }
+        // a fatal signal ends every thread of the process
+        exit_group_current_and_run_next(errno);
     }
+    deliver_uintr_of_current();
     trap_return();
 }
 
+/// Report an exception raised by the current application and signal it.
+fn user_fault(scause: scause::Scause, stval: usize, signal: SignalFlags) {
+    println!(
+        "[kernel] {:?} in application {}, scause = {:#x}, stval = {:#x}, sepc = {:#x}",
+        scause.cause(),
+        current_process().getpid(),
+        scause.bits(),
+        stval,
+        current_trap_cx().sepc,
+    );
+    current_add_signal(signal);
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // a thread created but never run before its process ended
+    exit_current_if_reaped();
     disable_supervisor_interrupt();
+    {
+        let task = current_task().unwrap();
+        lazy::check_owner::<Fpu>(&task, current_trap_cx());
+        lazy::check_owner::<Vector>(&task, current_trap_cx());
+        let task_inner = task.inner_exclusive_access();
+        if !task_inner.debug_triggers.is_empty() {
+            install_triggers(&task_inner.debug_triggers);
+        }
+    }
     set_user_trap_entry();
     let trap_cx_user_va = current_trap_cx_user_va();
-    let user_satp = current_user_token();
+    // a unified kernel already runs on the satp of the task, see run_tasks,
+    // unless it went over to the kernel space to reach MMIO
+    let (user_satp, tlb_flush) = if cfg!(feature = "unified") {
+        // compared by root table, the ASID may read back as 0
+        if satp::read().ppn() == current_user_token() & ((1usize << 44) - 1) {
+            (0, 0)
+        } else {
+            (current_user_satp(), !asids_supported() as usize)
+        }
+    } else {
+        (current_user_satp(), !asids_supported() as usize)
+    };
+    let trap_cx = current_trap_cx();
+    trace::trace(TracePoint::TrapExit, [trap_cx.sepc, 0]);
+    trap_cx.tlb_flush = tlb_flush;
+    trap_cx.hart_id = hart_id();
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -138,13 +277,14 @@ pub fn trap_return() -> ! {
             restore_va = in(reg) restore_va,
             in("a0") trap_cx_user_va,
             in("a1") user_satp,
+            in("a2") tlb_flush,
             options(noreturn)
         );
     }
 }
 
 #[no_mangle]
-pub fn trap_from_kernel(_trap_cx: &TrapContext) {
+pub fn trap_from_kernel(trap_cx: &TrapContext) {
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
@@ -152,10 +292,27 @@ pub fn trap_from_kernel(_trap_cx: &TrapContext) {
             crate::board::irq_handler();
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            // the context sits right below the frames it interrupted
+            let sp = trap_cx as *const TrapContext as usize;
+            profile::sample_kernel(trap_cx.sepc, trap_cx.x[8], sp);
             set_next_trigger();
             check_timer();
+            fbcon::flush();
             // do not schedule now
         }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            smp::handle_ipi();
+        }
+        // a unified space leaves MMIO out, the kernel reaches it on its own
+        // space until trap_return switches back
+        #[cfg(feature = "unified")]
+        Trap::Exception(Exception::LoadPageFault) | Trap::Exception(Exception::StorePageFault)
+            if crate::config::MMIO
+                .iter()
+                .any(|&(base, size)| (base..base + size).contains(&stval)) =>
+        {
+            crate::mm::KERNEL_SPACE.exclusive_access().switch_in();
+        }
         _ => {
             panic!(
                 "Unsupported trap from kernel: {:?}, stval = {:#x}!",
@@ -166,4 +323,12 @@ pub fn trap_from_kernel(_trap_cx: &TrapContext) {
     }
 }
 
-pub use context::TrapContext;
+pub use context::{ExtState, TrapContext, FS_SHIFT, VS_SHIFT};
+pub use fpu::FpState;
+use fpu_hw::Fpu;
+use lazy::ExtUnit;
+pub use lazy::{discard_ext_state, sync_ext_state};
+use trigger::{install_triggers, uninstall_triggers};
+pub use trigger::{triggers_supported, Trigger};
+use vector::Vector;
+pub use vector::VectorState;
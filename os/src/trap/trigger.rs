@@ -0,0 +1,116 @@
+//! Hardware triggers of the debug module, programmed through the SBI debug
+//! triggers extension. They are installed right before returning to a
+//! thread which asked for them and removed on the next trap, so that other
+//! threads never hit them.
+
+use crate::sbi::{
+    debug_install_triggers, debug_num_triggers, debug_set_shmem, debug_triggers_supported,
+    debug_uninstall_triggers,
+};
+use crate::sync::UPIntrFreeCell;
+use lazy_static::*;
+
+const MAX_TRIGGERS: usize = 2;
+const TYPE_SHIFT: usize = 60;
+const TYPE_ICOUNT: usize = 3;
+const TYPE_MCONTROL6: usize = 6;
+// icount
+const ICOUNT_COUNT_SHIFT: usize = 10;
+const ICOUNT_U: usize = 1 << 6;
+// mcontrol6, action 0 raises a breakpoint exception
+const MCONTROL6_U: usize = 1 << 3;
+const MCONTROL6_STORE: usize = 1 << 1;
+const MCONTROL6_LOAD: usize = 1 << 0;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Trigger {
+    tdata1: usize,
+    tdata2: usize,
+}
+
+impl Trigger {
+    /// Trap after one instruction has been executed in user mode.
+    pub fn single_step() -> Self {
+        Self {
+            tdata1: TYPE_ICOUNT << TYPE_SHIFT | 1 << ICOUNT_COUNT_SHIFT | ICOUNT_U,
+            tdata2: 0,
+        }
+    }
+
+    /// Trap before a user mode load and/or store at `addr`.
+    pub fn watchpoint(addr: usize, load: bool, store: bool) -> Self {
+        let mut tdata1 = TYPE_MCONTROL6 << TYPE_SHIFT | MCONTROL6_U;
+        if load {
+            tdata1 |= MCONTROL6_LOAD;
+        }
+        if store {
+            tdata1 |= MCONTROL6_STORE;
+        }
+        Self {
+            tdata1,
+            tdata2: addr,
+        }
+    }
+}
+
+/// Shared memory of the SBI extension, one (tdata1, tdata2, tdata3)
+/// triple per trigger. The kernel is identically mapped.
+#[repr(C, align(16))]
+struct SharedMemory([[usize; 3]; MAX_TRIGGERS]);
+
+struct TriggerManager {
+    /// whether icount and mcontrol6 triggers are available
+    supported: bool,
+    shmem: SharedMemory,
+    /// indexes of installed triggers
+    installed: [Option<usize>; MAX_TRIGGERS],
+}
+
+lazy_static! {
+    static ref TRIGGERS: UPIntrFreeCell<TriggerManager> = unsafe {
+        UPIntrFreeCell::new(TriggerManager {
+            supported: false,
+            shmem: SharedMemory([[0; 3]; MAX_TRIGGERS]),
+            installed: [None; MAX_TRIGGERS],
+        })
+    };
+}
+
+/// Probe the SBI implementation once at boot.
+pub fn init() {
+    let mut manager = TRIGGERS.exclusive_access();
+    manager.supported = debug_triggers_supported()
+        && debug_num_triggers(TYPE_ICOUNT << TYPE_SHIFT) > 0
+        && debug_num_triggers(TYPE_MCONTROL6 << TYPE_SHIFT) > 0
+        && debug_set_shmem(&manager.shmem as *const _ as usize);
+}
+
+pub fn triggers_supported() -> bool {
+    TRIGGERS.exclusive_access().supported
+}
+
+/// Install `triggers` for the next return to user mode.
+pub fn install_triggers(triggers: &[Trigger]) {
+    let mut manager = TRIGGERS.exclusive_access();
+    if !manager.supported {
+        return;
+    }
+    for trigger in triggers.iter().take(MAX_TRIGGERS) {
+        manager.shmem.0[0] = [trigger.tdata1, trigger.tdata2, 0];
+        if debug_install_triggers(1) {
+            let index = manager.shmem.0[0][0];
+            let slot = manager.installed.iter().position(|slot| slot.is_none());
+            manager.installed[slot.unwrap()] = Some(index);
+        }
+    }
+}
+
+/// Remove the triggers installed for the thread which has just trapped.
+pub fn uninstall_triggers() {
+    let mut manager = TRIGGERS.exclusive_access();
+    for slot in manager.installed.iter_mut() {
+        if let Some(index) = slot.take() {
+            debug_uninstall_triggers(index, 1);
+        }
+    }
+}
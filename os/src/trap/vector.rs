@@ -0,0 +1,75 @@
+//! The V extension, its registers are switched lazily through sstatus.VS.
+
+use super::lazy::{ExtUnit, Owner};
+use super::VS_SHIFT;
+use crate::sync::UPIntrFreeCell;
+use crate::task::TaskControlBlockInner;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use lazy_static::*;
+
+global_asm!(include_str!("vector.S"));
+
+extern "C" {
+    fn __vector_probe() -> usize;
+    fn __vector_save(regs: *mut u8, csrs: *mut [usize; 4]);
+    fn __vector_restore(regs: *const u8, csrs: *const [usize; 4]);
+}
+
+lazy_static! {
+    /// bytes of a vector register, 0 without the V extension
+    static ref VLENB: usize = unsafe { __vector_probe() };
+    static ref VECTOR_OWNER: Owner = unsafe { UPIntrFreeCell::new(None) };
+}
+
+/// v0-v31 and vstart, vcsr, vl, vtype of a thread.
+#[derive(Clone)]
+pub struct VectorState {
+    regs: Vec<u8>,
+    csrs: [usize; 4],
+}
+
+impl VectorState {
+    fn new() -> Self {
+        Self {
+            regs: vec![0; *VLENB * 32],
+            // vill until the thread runs vsetvl
+            csrs: [0, 0, 0, 1 << 63],
+        }
+    }
+}
+
+pub fn init() {
+    if *VLENB != 0 {
+        println!("[kernel] V extension, VLEN = {} bits", *VLENB * 8);
+    }
+}
+
+pub struct Vector;
+
+impl ExtUnit for Vector {
+    const SHIFT: usize = VS_SHIFT;
+
+    fn present() -> bool {
+        *VLENB != 0
+    }
+
+    fn owner() -> &'static Owner {
+        &VECTOR_OWNER
+    }
+
+    fn save(task_inner: &mut TaskControlBlockInner) {
+        let state = task_inner.vector_state.get_or_insert_with(VectorState::new);
+        unsafe { __vector_save(state.regs.as_mut_ptr(), &mut state.csrs) };
+    }
+
+    fn restore(task_inner: &mut TaskControlBlockInner) {
+        let state = task_inner.vector_state.get_or_insert_with(VectorState::new);
+        unsafe { __vector_restore(state.regs.as_ptr(), &state.csrs) };
+    }
+
+    fn discard(task_inner: &mut TaskControlBlockInner) {
+        task_inner.vector_state = None;
+    }
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, fstatat, mkdirat, open, openat, read, unlinkat, write, OpenFlags, Stat,
+    AT_EMPTY_PATH, AT_FDCWD, AT_REMOVEDIR, EBADF, ENOTDIR, S_IFCHR, S_IFDIR, S_IFMT, S_IFREG,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdirat(AT_FDCWD, "at_test_dir\0"), 0);
+    let dir = open("at_test_dir\0", OpenFlags::RDONLY);
+    assert!(dir >= 0);
+    assert_eq!(mkdirat(dir, "sub\0"), 0);
+
+    // a relative name is looked up in the directory, `..` included
+    let fd = openat(dir, "sub/../file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"hello"), 5);
+    close(fd as usize);
+    let fd = open("/at_test_dir/file\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fd as usize, &mut buf), 5);
+    assert_eq!(&buf[..5], b"hello");
+
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd as usize, &mut st), 0);
+    assert_eq!(st.mode & S_IFMT, S_IFREG);
+    assert_eq!(st.size, 5);
+    assert_eq!(st.nlink, 1);
+    let ino = st.ino;
+    close(fd as usize);
+    let mut st = Stat::default();
+    assert_eq!(fstatat(dir, "file\0", &mut st, 0), 0);
+    assert_eq!(st.ino, ino);
+    assert_eq!(fstatat(dir, "\0", &mut st, AT_EMPTY_PATH), 0);
+    assert_eq!(st.mode & S_IFMT, S_IFDIR);
+    assert_eq!(fstatat(AT_FDCWD, "at_test_dir/sub\0", &mut st, 0), 0);
+    assert_eq!(st.mode & S_IFMT, S_IFDIR);
+    assert_eq!(fstat(1, &mut st), 0);
+    assert_eq!(st.mode & S_IFMT, S_IFCHR);
+
+    // absolute paths ignore dirfd, a file or a closed fd is no directory
+    let mut st = Stat::default();
+    assert_eq!(fstatat(dir, "/at_test_dir/file\0", &mut st, 0), 0);
+    let fd = open("/at_test_dir/file\0", OpenFlags::RDONLY);
+    assert_eq!(openat(fd, "x\0", OpenFlags::RDONLY), -ENOTDIR);
+    close(fd as usize);
+    assert_eq!(openat(fd, "x\0", OpenFlags::RDONLY), -EBADF);
+
+    assert_eq!(unlinkat(dir, "file\0", 0), 0);
+    assert!(fstatat(dir, "file\0", &mut st, 0) < 0);
+    assert_eq!(unlinkat(dir, "sub\0", AT_REMOVEDIR), 0);
+    close(dir as usize);
+    assert_eq!(unlinkat(AT_FDCWD, "at_test_dir\0", AT_REMOVEDIR), 0);
+    println!("at_test passed!");
+    0
+}
@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+
+const ENOSYS: isize = 38;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut ret: isize;
+    unsafe {
+        asm!("ecall", inlateout("x10") 0isize => ret, in("x17") 0xdead);
+    }
+    assert_eq!(ret, -ENOSYS);
+    // the second call is not reported again
+    unsafe {
+        asm!("ecall", inlateout("x10") 0isize => ret, in("x17") 0xdead);
+    }
+    assert_eq!(ret, -ENOSYS);
+    println!("bad_syscall passed!");
+    0
+}
@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, ioctl, open, write, OpenFlags, AFMT_S16_LE, SNDCTL_DSP_CHANNELS, SNDCTL_DSP_SETFMT,
+    SNDCTL_DSP_SPEED, SNDCTL_DSP_SYNC,
+};
+
+const RATE: i32 = 22050;
+/// frequency in Hz and length in eighths, 0 Hz is a rest
+const TUNE: [(u32, u32); 14] = [
+    (262, 2),
+    (262, 2),
+    (392, 2),
+    (392, 2),
+    (440, 2),
+    (440, 2),
+    (392, 4),
+    (349, 2),
+    (349, 2),
+    (330, 2),
+    (330, 2),
+    (294, 2),
+    (294, 2),
+    (262, 4),
+];
+
+/// Square waves through /dev/dsp, run with SOUND=on.
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("/dev/dsp\0", OpenFlags::WRONLY);
+    if fd < 0 {
+        println!("beep: no sound device");
+        return -1;
+    }
+    let fd = fd as usize;
+    let mut format = AFMT_S16_LE;
+    let mut channels = 1i32;
+    let mut rate = RATE;
+    if ioctl(fd, SNDCTL_DSP_SETFMT, &mut format as *mut _ as usize) != 0
+        || ioctl(fd, SNDCTL_DSP_CHANNELS, &mut channels as *mut _ as usize) != 0
+        || ioctl(fd, SNDCTL_DSP_SPEED, &mut rate as *mut _ as usize) != 0
+    {
+        println!("beep: 16-bit mono at {}Hz is not supported", RATE);
+        return -1;
+    }
+    let mut samples = [0u8; 2048];
+    for (frequency, eighths) in TUNE {
+        let frames = rate as u32 * eighths / 8;
+        let mut frame = 0;
+        while frame < frames {
+            let count = (frames - frame).min(samples.len() as u32 / 2);
+            for i in 0..count {
+                // short silence at the end of every note keeps them apart
+                let t = frame + i;
+                let sample: i16 = if frequency == 0 || frames - t < rate as u32 / 50 {
+                    0
+                } else if t * frequency * 2 / rate as u32 % 2 == 0 {
+                    6000
+                } else {
+                    -6000
+                };
+                samples[i as usize * 2..][..2].copy_from_slice(&sample.to_le_bytes());
+            }
+            write(fd, &samples[..count as usize * 2]);
+            frame += count;
+        }
+    }
+    ioctl(fd, SNDCTL_DSP_SYNC, 0);
+    close(fd);
+    0
+}
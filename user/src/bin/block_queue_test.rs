@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, open, read, unlink, waitpid, write, OpenFlags};
+
+const WORKERS: usize = 4;
+const FILE_LEN: usize = 32 * 1024;
+const CHUNK: usize = 700;
+
+fn byte_at(worker: usize, offset: usize) -> u8 {
+    (offset % 253 + worker * 61) as u8
+}
+
+/// Write a file of its own and read it back, while the others do the same
+/// and their requests meet in the queue of the disk.
+fn work(worker: usize) {
+    let name = [b'q', b'u', b'e', b'u', b'e', b'0' + worker as u8, 0];
+    let name = core::str::from_utf8(&name).unwrap();
+    let fd = open(name, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let mut chunk = [0u8; CHUNK];
+    let mut offset = 0;
+    while offset < FILE_LEN {
+        let len = CHUNK.min(FILE_LEN - offset);
+        for (i, byte) in chunk[..len].iter_mut().enumerate() {
+            *byte = byte_at(worker, offset + i);
+        }
+        assert_eq!(write(fd as usize, &chunk[..len]), len as isize);
+        offset += len;
+    }
+    close(fd as usize);
+    let fd = open(name, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut offset = 0;
+    loop {
+        let len = read(fd as usize, &mut chunk);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for (i, &byte) in chunk[..len as usize].iter().enumerate() {
+            assert_eq!(byte, byte_at(worker, offset + i));
+        }
+        offset += len as usize;
+    }
+    assert_eq!(offset, FILE_LEN);
+    close(fd as usize);
+    assert_eq!(unlink(name), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pids = [0usize; WORKERS];
+    for (worker, pid) in pids.iter_mut().enumerate() {
+        let child = fork();
+        if child == 0 {
+            work(worker);
+            exit(0);
+        }
+        *pid = child as usize;
+    }
+    for pid in pids {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+        assert_eq!(exit_code, 0);
+    }
+    println!("block_queue_test passed!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{brk, mmap, munmap, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 0x1000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let base = brk(0);
+    assert!(base > 0);
+    let base = base as usize;
+    // an address out of range only queries
+    assert_eq!(brk(usize::MAX), base as isize);
+
+    // the heap grows and is usable
+    let end = base + 3 * PAGE_SIZE + 100;
+    assert_eq!(brk(end), end as isize);
+    assert_eq!(brk(0), end as isize);
+    let heap = unsafe { core::slice::from_raw_parts_mut(base as *mut u8, end - base) };
+    assert!(heap.iter().all(|&byte| byte == 0));
+    heap.fill(0x5a);
+    assert!(heap.iter().all(|&byte| byte == 0x5a));
+
+    // shrinking drops the pages, growing again brings fresh ones
+    assert_eq!(brk(base + PAGE_SIZE), (base + PAGE_SIZE) as isize);
+    assert_eq!(brk(end), end as isize);
+    let heap = unsafe { core::slice::from_raw_parts(base as *const u8, end - base) };
+    assert!(heap[..PAGE_SIZE].iter().all(|&byte| byte == 0x5a));
+    assert!(heap[PAGE_SIZE..].iter().all(|&byte| byte == 0));
+
+    // mappings stay clear of the heap
+    let map = mmap(
+        PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(map > 0);
+    assert!(map as usize >= end);
+    assert_eq!(munmap(map as usize, PAGE_SIZE), 0);
+
+    assert_eq!(brk(base), base as isize);
+    println!("brk_test passed!");
+    0
+}
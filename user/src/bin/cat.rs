@@ -3,30 +3,42 @@
 
 #[macro_use]
 extern crate user_lib;
-extern crate alloc;
 
-use user_lib::{close, open, read, OpenFlags};
+use user_lib::{close, open, read, strerror, write, OpenFlags};
 
+/// Copy `fd` to stdout, false on a read error.
+fn copy_out(fd: usize) -> bool {
+    let mut buf = [0u8; 512];
+    loop {
+        match read(fd, &mut buf) {
+            0 => return true,
+            len if len < 0 => return false,
+            len => {
+                write(1, &buf[..len as usize]);
+            }
+        }
+    }
+}
+
+/// cat [FILE]..., stdin without arguments
 #[no_mangle]
 pub fn main(argc: usize, argv: &[&str]) -> i32 {
-    println!("argc = {}", argc);
-    for (i, arg) in argv.iter().enumerate() {
-        println!("argv[{}] = {}", i, arg);
-    }
-    assert!(argc == 2);
-    let fd = open(argv[1], OpenFlags::RDONLY);
-    if fd == -1 {
-        panic!("Error occurred when opening file");
+    if argc == 1 {
+        return if copy_out(0) { 0 } else { 1 };
     }
-    let fd = fd as usize;
-    let mut buf = [0u8; 256];
-    loop {
-        let size = read(fd, &mut buf) as usize;
-        if size == 0 {
-            break;
+    let mut exit_code = 0;
+    for path in &argv[1..] {
+        let fd = open(path, OpenFlags::RDONLY);
+        if fd < 0 {
+            println!("cat: {}: {}", path, strerror(fd));
+            exit_code = 1;
+            continue;
+        }
+        if !copy_out(fd as usize) {
+            println!("cat: {}: read error", path);
+            exit_code = 1;
         }
-        print!("{}", core::str::from_utf8(&buf[..size]).unwrap());
+        close(fd as usize);
     }
-    close(fd);
-    0
+    exit_code
 }
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{close, fork, open, prctl, read, waitpid, OpenFlags, PR_SET_DUMPABLE};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(prctl(PR_SET_DUMPABLE, 1), 0);
+        unsafe {
+            core::ptr::null_mut::<u8>().write_volatile(0);
+        }
+        unreachable!();
+    }
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, -11);
+    let name = format!("core.{}\0", pid);
+    let fd = open(name.as_str(), OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let mut header = [0u8; 64];
+    assert_eq!(read(fd as usize, &mut header), 64);
+    close(fd as usize);
+    assert_eq!(&header[..4], b"\x7fELF");
+    // ET_CORE for EM_RISCV
+    assert_eq!(&header[16..20], &[4, 0, 243, 0]);
+    println!("core_dump_test passed!");
+    0
+}
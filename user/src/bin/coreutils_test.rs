@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, exec, fork, open, read, read_dir, waitpid, write, OpenFlags};
+
+const CONTENT: &[u8] = b"alpha\nbeta\ngamma\n";
+
+/// Run a utility and return its exit code.
+fn run(args: &[&str]) -> i32 {
+    let args: Vec<String> = args.iter().map(|arg| format!("{}\0", arg)).collect();
+    let mut argv: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+    argv.push(core::ptr::null());
+    let pid = fork();
+    if pid == 0 {
+        exec(args[0].as_str(), argv.as_slice());
+        panic!("cannot exec {}", args[0]);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 64];
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    buf[..len as usize].to_vec()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("cu_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    write(fd as usize, CONTENT);
+    close(fd as usize);
+
+    let script: &[(&[&str], i32)] = &[
+        (&["echo", "coreutils"], 0),
+        (&["mkdir", "cu_dir"], 0),
+        (&["mkdir", "cu_dir"], 1),
+        (&["cp", "cu_file", "cu_dir"], 0),
+        (&["ln", "cu_dir/cu_file", "cu_link"], 0),
+        (&["ln", "cu_dir", "cu_dirlink"], 1),
+        (&["grep", "beta", "cu_link"], 0),
+        (&["grep", "delta", "cu_link"], 1),
+        (&["wc", "cu_file", "cu_link"], 0),
+        (&["mv", "cu_link", "cu_moved"], 0),
+        (&["ls", "cu_dir"], 0),
+        (&["cat", "cu_moved"], 0),
+        (&["cat", "cu_link"], 1),
+        (&["rm", "cu_dir"], 1),
+        (&["rm", "-d", "cu_dir"], 1),
+        (&["rm", "cu_dir/cu_file"], 0),
+    ];
+    for (args, expected) in script {
+        assert_eq!(run(args), *expected, "{:?}", args);
+    }
+    // the copy and the link share the data, which outlives one of the names
+    assert_eq!(read_file("cu_moved\0"), CONTENT);
+    let script: &[(&[&str], i32)] = &[
+        (&["rm", "-d", "cu_dir"], 0),
+        (&["ls", "cu_dir"], 1),
+        (&["rm", "cu_file", "cu_moved"], 0),
+        (&["ps"], 0),
+        (&["kill", "-USR1", "100000"], 1),
+        (&["kill", "-BOGUS", "1"], 1),
+        (&["sleep", "0.1"], 0),
+    ];
+    for (args, expected) in script {
+        assert_eq!(run(args), *expected, "{:?}", args);
+    }
+    let names = read_dir("/\0").unwrap();
+    assert!(!names.iter().any(|name| name.starts_with("cu_")));
+    println!("coreutils_test passed!");
+    0
+}
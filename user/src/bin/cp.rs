@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+#[macro_use]
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::{close, open, read, read_dir, strerror, write, OpenFlags};
+
+/// `dest`, or `dest/basename(src)` if `dest` is a directory.
+fn target(src: &str, dest: &str) -> String {
+    if read_dir(dest).is_some() {
+        let name = src.rsplit('/').next().unwrap_or(src);
+        format!("{}/{}\0", dest.trim_end_matches('/'), name)
+    } else {
+        format!("{}\0", dest)
+    }
+}
+
+/// cp SOURCE DEST
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 3 {
+        println!("usage: cp SOURCE DEST");
+        return 1;
+    }
+    if read_dir(argv[1]).is_some() {
+        println!("cp: {}: Is a directory", argv[1]);
+        return 1;
+    }
+    let src = open(argv[1], OpenFlags::RDONLY);
+    if src < 0 {
+        println!("cp: {}: {}", argv[1], strerror(src));
+        return 1;
+    }
+    let dest_path = target(argv[1], argv[2]);
+    let dest = open(dest_path.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
+    if dest < 0 {
+        println!("cp: {}: {}", argv[2], strerror(dest));
+        close(src as usize);
+        return 1;
+    }
+    let (src, dest) = (src as usize, dest as usize);
+    let mut buf = [0u8; 512];
+    let mut exit_code = 0;
+    loop {
+        let len = read(src, &mut buf);
+        if len <= 0 {
+            if len < 0 {
+                println!("cp: {}: read error", argv[1]);
+                exit_code = 1;
+            }
+            break;
+        }
+        write(dest, &buf[..len as usize]);
+    }
+    close(src);
+    close(dest);
+    exit_code
+}
@@ -0,0 +1,15 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("Try to execute an ebreak without a debugger...");
+    println!("Kernel should kill this application with SIGTRAP!");
+    unsafe {
+        core::arch::asm!("ebreak");
+    }
+    0
+}
@@ -0,0 +1,24 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+/// echo [-n] [STRING]...
+#[no_mangle]
+pub fn main(_argc: usize, argv: &[&str]) -> i32 {
+    let (newline, words) = match argv.get(1) {
+        Some(&"-n") => (false, &argv[2..]),
+        _ => (true, &argv[1..]),
+    };
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            print!(" ");
+        }
+        print!("{}", word);
+    }
+    if newline {
+        println!("");
+    }
+    0
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, poll, read, OpenFlags, PollEvents, PollFd, EAGAIN};
+
+/// `struct input_event`
+const EVENT_SIZE: usize = 24;
+
+/// Nobody types into the graphical window while the tests run, so the
+/// queues stay empty.
+#[no_mangle]
+pub fn main() -> i32 {
+    let nonblocking = OpenFlags::RDONLY | OpenFlags::NONBLOCK;
+    let keyboard = open("/dev/input/event0\0", nonblocking);
+    let mouse = open("/dev/input/event1\0", nonblocking);
+    // a second reader of the same device has a queue of its own
+    let again = open("/dev/input/event0\0", nonblocking);
+    assert!(keyboard >= 0 && mouse >= 0 && again >= 0);
+    let (keyboard, mouse, again) = (keyboard as usize, mouse as usize, again as usize);
+
+    let mut buf = [0u8; 2 * EVENT_SIZE];
+    for fd in [keyboard, mouse, again] {
+        let got = read(fd, &mut buf);
+        assert!(got == -EAGAIN || got as usize % EVENT_SIZE == 0);
+    }
+
+    let mut fds = [
+        PollFd::new(keyboard, PollEvents::POLLIN),
+        PollFd::new(mouse, PollEvents::POLLIN),
+    ];
+    let ready = poll(&mut fds, 0);
+    assert!(ready >= 0);
+    for fd in fds.iter() {
+        assert!(!fd.revents().contains(PollEvents::POLLOUT));
+    }
+
+    close(keyboard);
+    close(mouse);
+    close(again);
+    println!("evdev_test passed!");
+    0
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, exit_group, fork, getpid, getppid, sleep, thread_create, waitpid, waittid, yield_,
+};
+
+const GROUP_EXIT_CODE: i32 = 42;
+
+fn spin() -> ! {
+    loop {
+        yield_();
+    }
+}
+
+/// Blocked on a timer that goes off after the process is gone.
+fn nap() -> ! {
+    sleep(50);
+    exit(1)
+}
+
+fn end_all() -> ! {
+    sleep(10);
+    exit_group(GROUP_EXIT_CODE)
+}
+
+/// Run `child` in a new process and return its exit code.
+fn run(child: impl Fn() -> i32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(child());
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = getpid();
+    assert_eq!(run(move || (getppid() != pid) as i32), 0);
+    // a thread other than the main one ends them all, the main thread
+    // waiting for one that never exits on its own
+    let exit_code = run(|| {
+        let spinner = thread_create(spin as usize, 0);
+        thread_create(nap as usize, 0);
+        thread_create(end_all as usize, 0);
+        waittid(spinner as usize);
+        1
+    });
+    assert_eq!(exit_code, GROUP_EXIT_CODE);
+    // the napping thread wakes up to a process that is gone
+    sleep(100);
+    println!("exit_group_test passed!");
+    0
+}
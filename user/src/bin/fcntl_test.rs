@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fcntl, open, pipe, read, unlinkat, write, OpenFlags, AT_FDCWD, EAGAIN, EINVAL, F_DUPFD,
+    F_GETFL, F_SETFL,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let (read_end, write_end) = (fds[0], fds[1]);
+    assert_eq!(
+        fcntl(read_end, F_GETFL, 0),
+        OpenFlags::RDONLY.bits() as isize
+    );
+    assert_eq!(
+        fcntl(write_end, F_GETFL, 0),
+        OpenFlags::WRONLY.bits() as isize
+    );
+
+    // an empty pipe fails reads instead of blocking
+    let nonblock = OpenFlags::NONBLOCK.bits() as usize;
+    assert_eq!(fcntl(read_end, F_SETFL, nonblock), 0);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(read_end, &mut buf), -EAGAIN);
+    assert_eq!(write(write_end, b"abc"), 3);
+    assert_eq!(read(read_end, &mut buf), 3);
+    assert_eq!(&buf[..3], b"abc");
+
+    // duplicates start at the minimum and share the status flags
+    let dup_fd = fcntl(read_end, F_DUPFD, 10);
+    assert!(dup_fd >= 10);
+    let flags = OpenFlags::from_bits_truncate(fcntl(dup_fd as usize, F_GETFL, 0) as u32);
+    assert!(flags.contains(OpenFlags::NONBLOCK));
+    assert_eq!(fcntl(dup_fd as usize, F_SETFL, 0), 0);
+    let flags = OpenFlags::from_bits_truncate(fcntl(read_end, F_GETFL, 0) as u32);
+    assert!(!flags.contains(OpenFlags::NONBLOCK));
+    assert_eq!(fcntl(read_end, F_DUPFD, 4096), -EINVAL);
+    close(dup_fd as usize);
+
+    // a full pipe fails writes
+    assert_eq!(fcntl(write_end, F_SETFL, nonblock), 0);
+    let chunk = [0u8; 256];
+    let mut written = 0;
+    loop {
+        let len = write(write_end, &chunk);
+        if len < 0 {
+            assert_eq!(len, -EAGAIN);
+            break;
+        }
+        written += len;
+    }
+    assert!(written > 0);
+    close(read_end);
+    close(write_end);
+
+    // O_APPEND at open or set later puts every write at the end
+    let fd = open("fcntl_test_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"hello"), 5);
+    close(fd as usize);
+    let fd = open("fcntl_test_file\0", OpenFlags::WRONLY | OpenFlags::APPEND) as usize;
+    let flags = OpenFlags::from_bits_truncate(fcntl(fd, F_GETFL, 0) as u32);
+    assert!(flags.contains(OpenFlags::APPEND));
+    assert_eq!(write(fd, b", "), 2);
+    close(fd);
+    let fd = open("fcntl_test_file\0", OpenFlags::WRONLY) as usize;
+    assert_eq!(fcntl(fd, F_SETFL, OpenFlags::APPEND.bits() as usize), 0);
+    assert_eq!(write(fd, b"world"), 5);
+    close(fd);
+    let fd = open("fcntl_test_file\0", OpenFlags::RDONLY) as usize;
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd, &mut buf), 12);
+    assert_eq!(&buf[..12], b"hello, world");
+    close(fd);
+    assert_eq!(unlinkat(AT_FDCWD, "fcntl_test_file\0", 0), 0);
+    println!("fcntl_test passed!");
+    0
+}
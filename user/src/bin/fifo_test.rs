@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fork, mkfifo, open, read, wait, write, OpenFlags};
+
+static FIFO: &str = "fifo_test_fifo\0";
+static STR: &str = "Hello, fifo!";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the FIFO stays on disk after a previous run
+    mkfifo(FIFO);
+    if fork() == 0 {
+        // blocks until the parent opens the read end
+        let fd = open(FIFO, OpenFlags::WRONLY);
+        assert!(fd > 0);
+        assert_eq!(write(fd as usize, STR.as_bytes()), STR.len() as isize);
+        close(fd as usize);
+        0
+    } else {
+        let fd = open(FIFO, OpenFlags::RDONLY);
+        assert!(fd > 0);
+        let mut buffer = [0u8; 32];
+        let len_read = read(fd as usize, &mut buffer) as usize;
+        assert_eq!(core::str::from_utf8(&buffer[..len_read]).unwrap(), STR);
+        // the writer has gone
+        assert_eq!(read(fd as usize, &mut buffer), 0);
+        close(fd as usize);
+        let mut exit_code: i32 = 0;
+        wait(&mut exit_code);
+        assert_eq!(exit_code, 0);
+        println!("fifo_test passed!");
+        0
+    }
+}
@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+use core::hint::black_box;
+use user_lib::{exit, fork, wait, yield_};
+
+fn sqrt(x: f64) -> f64 {
+    let root: f64;
+    unsafe { asm!("fsqrt.d {0}, {1}", out(freg) root, in(freg) x) };
+    root
+}
+
+fn mul_add(a: f64, b: f64, c: f64) -> f64 {
+    let result: f64;
+    unsafe {
+        asm!("fmadd.d {0}, {1}, {2}, {3}", out(freg) result, in(freg) a, in(freg) b, in(freg) c)
+    };
+    result
+}
+
+fn set_fs0(value: f64) {
+    unsafe { asm!("fmv.d fs0, {0}", in(freg) value) };
+}
+
+fn get_fs0() -> f64 {
+    let value: f64;
+    unsafe { asm!("fmv.d {0}, fs0", out(freg) value) };
+    value
+}
+
+/// Runs natively with an FPU and through the kernel's emulation without one.
+#[no_mangle]
+pub fn main() -> i32 {
+    let (a, b) = (black_box(1.5f64), black_box(-0.25f64));
+    assert_eq!(a + b, 1.25);
+    assert_eq!(a - b, 1.75);
+    assert_eq!(a * b, -0.375);
+    assert_eq!(a / b, -6.0);
+    assert_eq!(sqrt(black_box(2.25f64)), 1.5);
+    assert_eq!(mul_add(a, b, 1.0), 0.625);
+    assert_eq!(black_box(1.0f64) / black_box(3.0f64), 1.0 / 3.0);
+    assert!(black_box(0.0f64) / black_box(0.0f64) != 0.0 / black_box(0.0f64));
+    assert_eq!(black_box(1.0f64) / black_box(0.0f64), f64::INFINITY);
+    assert!(a > b && b < 0.0 && a.max(b) == a && a.min(b) == b);
+    assert_eq!(black_box(-2.7f64) as i32, -2);
+    assert_eq!(black_box(-2.7f64) as u64, 0);
+    assert_eq!(black_box(1e20f64) as i64, i64::MAX);
+    assert_eq!(black_box(-7i64) as f64, -7.0);
+    assert_eq!(black_box(0.1f32) as f64 as f32, 0.1f32);
+    let (x, y) = (black_box(3.0f32), black_box(0.5f32));
+    assert_eq!(x * y + x / y, 7.5);
+    assert_eq!(-(x - y * 2.0), -2.0);
+    assert_eq!(black_box(1e-45f32) / 2.0, 0.0);
+    assert_eq!(black_box(f32::MAX) * 2.0, f32::INFINITY);
+
+    // fp registers of two processes survive switches between them
+    set_fs0(1.5);
+    let child = fork() == 0;
+    let expected = if child { -2.5 } else { 1.5 };
+    set_fs0(expected);
+    for _ in 0..100 {
+        yield_();
+        assert_eq!(get_fs0(), expected);
+    }
+    if child {
+        exit(0);
+    }
+    let mut exit_code = 0;
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+    println!("fp_test passed!");
+    0
+}
@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use user_lib::{
+    exit, futex_wait, futex_wake, set_tid_address, sleep, thread_create, waittid, EAGAIN,
+};
+
+const THREADS: usize = 4;
+/// nonzero until the kernel clears it when the thread exits
+static TIDS: [AtomicU32; THREADS] = [
+    AtomicU32::new(u32::MAX),
+    AtomicU32::new(u32::MAX),
+    AtomicU32::new(u32::MAX),
+    AtomicU32::new(u32::MAX),
+];
+
+fn worker(i: usize) -> ! {
+    let tid = set_tid_address(&TIDS[i]);
+    TIDS[i].store(tid as u32, Ordering::SeqCst);
+    sleep(20 * (THREADS - i));
+    exit(i as i32)
+}
+
+/// Join the way pthread_join does, without waittid.
+fn join(word: &AtomicU32) {
+    loop {
+        let tid = word.load(Ordering::SeqCst);
+        if tid == 0 {
+            break;
+        }
+        futex_wait(word, tid);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let word = AtomicU32::new(1);
+    assert_eq!(futex_wait(&word, 2), -EAGAIN);
+    assert_eq!(futex_wake(&word, 1), 0);
+
+    let tids: [isize; THREADS] = core::array::from_fn(|i| thread_create(worker as usize, i));
+    for word in TIDS.iter() {
+        join(word);
+    }
+    for (i, tid) in tids.iter().enumerate() {
+        assert_eq!(waittid(*tid as usize), i as isize);
+    }
+    println!("futex_join_test passed!");
+    0
+}
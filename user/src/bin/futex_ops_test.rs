@@ -0,0 +1,122 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use user_lib::{
+    clock_gettime, exit, futex_cmp_requeue, futex_wait, futex_wait_bitset, futex_wait_timeout,
+    futex_wake, futex_wake_bitset, get_time, getpid, kill, sigaction, sigprocmask, thread_create,
+    waittid, yield_, SignalAction, SignalFlags, TimeSpec, CLOCK_MONOTONIC, EAGAIN, EINTR,
+    ETIMEDOUT, FUTEX_BITSET_MATCH_ANY, SIG_BLOCK, SIG_SETMASK,
+};
+
+static WORD: AtomicU32 = AtomicU32::new(0);
+static TARGET: AtomicU32 = AtomicU32::new(0);
+/// bit i is set once waiter i is back
+static WOKEN: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPTED: AtomicUsize = AtomicUsize::new(0);
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+/// Threads asleep on `word`, counted by requeueing them onto it again.
+fn sleepers(word: &AtomicU32) -> usize {
+    futex_cmp_requeue(word, 0, word, usize::MAX, word.load(Ordering::SeqCst)) as usize
+}
+
+/// Wait until `count` threads sleep on `word`.
+fn wait_asleep(word: &AtomicU32, count: usize) {
+    while sleepers(word) < count {
+        yield_();
+    }
+}
+
+fn bitset_waiter(i: usize) -> ! {
+    futex_wait_bitset(&WORD, 0, None, 1 << i);
+    WOKEN.fetch_or(1 << i, Ordering::SeqCst);
+    exit(0)
+}
+
+fn plain_waiter(i: usize) -> ! {
+    futex_wait(&WORD, 0);
+    WOKEN.fetch_or(1 << i, Ordering::SeqCst);
+    exit(0)
+}
+
+fn interrupted_waiter(_: usize) -> ! {
+    INTERRUPTED.store(-futex_wait(&WORD, 0) as usize, Ordering::SeqCst);
+    exit(0)
+}
+
+extern "C" fn usr1_handler(_signum: usize) {
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+fn timeouts() {
+    let timeout = TimeSpec {
+        tv_sec: 0,
+        tv_nsec: 50_000_000,
+    };
+    let start = get_time();
+    assert_eq!(futex_wait_timeout(&WORD, 0, &timeout), -ETIMEDOUT);
+    assert!(get_time() - start >= 50, "woke up early");
+    // a deadline in the past returns at once
+    let mut now = TimeSpec::default();
+    clock_gettime(CLOCK_MONOTONIC, &mut now);
+    let result = futex_wait_bitset(&WORD, 0, Some(&now), FUTEX_BITSET_MATCH_ANY);
+    assert_eq!(result, -ETIMEDOUT);
+}
+
+fn bitsets() {
+    READY.store(0, Ordering::SeqCst);
+    WOKEN.store(0, Ordering::SeqCst);
+    let tids = [0, 1].map(|i| thread_create(bitset_waiter as usize, i));
+    wait_asleep(&WORD, 2);
+    assert_eq!(futex_wake_bitset(&WORD, usize::MAX, 1 << 1), 1);
+    assert_eq!(waittid(tids[1] as usize), 0);
+    assert_eq!(WOKEN.load(Ordering::SeqCst), 1 << 1);
+    assert_eq!(sleepers(&WORD), 1);
+    assert_eq!(futex_wake_bitset(&WORD, usize::MAX, 1 << 0), 1);
+    assert_eq!(waittid(tids[0] as usize), 0);
+}
+
+fn requeue() {
+    WOKEN.store(0, Ordering::SeqCst);
+    let tids = [0, 1, 2].map(|i| thread_create(plain_waiter as usize, i));
+    wait_asleep(&WORD, 3);
+    assert_eq!(futex_cmp_requeue(&WORD, 1, &TARGET, 2, 1), -EAGAIN);
+    assert_eq!(futex_cmp_requeue(&WORD, 1, &TARGET, usize::MAX, 0), 3);
+    assert_eq!(sleepers(&WORD), 0);
+    assert_eq!(sleepers(&TARGET), 2);
+    assert_eq!(futex_wake(&TARGET, usize::MAX), 2);
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0);
+    }
+    assert_eq!(WOKEN.load(Ordering::SeqCst), 0b111);
+}
+
+/// A signal the waiter can take ends its wait with EINTR.
+fn signals() {
+    let action = SignalAction::new(usr1_handler as usize, SignalFlags::empty());
+    assert_eq!(sigaction(10, Some(&action), None), 0);
+    // only the waiter, which starts with no signal blocked, can take it
+    let old_mask = sigprocmask(SIG_BLOCK, SignalFlags::SIGUSR1);
+    let tid = thread_create(interrupted_waiter as usize, 0);
+    wait_asleep(&WORD, 1);
+    assert_eq!(kill(getpid() as usize, SignalFlags::SIGUSR1.bits()), 0);
+    assert_eq!(waittid(tid as usize), 0);
+    assert_eq!(INTERRUPTED.load(Ordering::SeqCst), EINTR as usize);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    assert_eq!(sleepers(&WORD), 0);
+    sigprocmask(SIG_SETMASK, old_mask);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    timeouts();
+    bitsets();
+    requeue();
+    signals();
+    println!("futex_ops_test passed!");
+    0
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, get_timeval, sleep, TimeVal};
+
+fn micros(tv: &TimeVal) -> usize {
+    tv.tv_sec * 1_000_000 + tv.tv_usec
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the milliseconds returned, read just after, agree with the TimeVal
+    let mut before = TimeVal::default();
+    let ms = get_timeval(&mut before) as usize;
+    assert!(before.tv_usec < 1_000_000);
+    assert!(ms >= micros(&before) / 1000 && ms - micros(&before) / 1000 <= 1);
+
+    // the clock moves forward at the rate sleep keeps to
+    sleep(50);
+    let mut after = TimeVal::default();
+    get_timeval(&mut after);
+    let elapsed = micros(&after) - micros(&before);
+    assert!(elapsed >= 50_000, "elapsed {}us", elapsed);
+    assert!(get_time() as usize >= micros(&after) / 1000);
+    println!("get_time_test passed!");
+    0
+}
@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dirents64, fstat, getcwd, getdents, getdents64, mkdirat, open, read_dir, unlinkat,
+    OpenFlags, Stat, AT_FDCWD, AT_REMOVEDIR, DT_DIR, DT_REG, EINVAL,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut cwd = [0u8; 16];
+    assert_eq!(getcwd(&mut cwd), 2);
+    assert_eq!(&cwd[..2], b"/\0");
+    let names = read_dir("/\0").unwrap();
+    assert!(names.iter().any(|name| name == "getdents_test"));
+    assert!(names.iter().any(|name| name == "initproc"));
+    // a directory cannot be written, a file cannot be listed
+    assert!(open("/\0", OpenFlags::RDWR) < 0);
+    let fd = open("initproc\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 64];
+    assert!(getdents(fd as usize, &mut buf) < 0);
+    close(fd as usize);
+
+    // linux_dirent64 records carry the inode, the type and the next offset
+    assert_eq!(mkdirat(AT_FDCWD, "getdents_test_dir\0"), 0);
+    let fd = open("/\0", OpenFlags::RDONLY) as usize;
+    let mut small = [0u8; 16];
+    assert_eq!(getdents64(fd, &mut small), -EINVAL);
+    let mut buf = [0u8; 256];
+    let (mut found_file, mut found_dir) = (false, false);
+    let mut last_off = 0;
+    loop {
+        let len = getdents64(fd, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for dirent in dirents64(&buf[..len as usize]) {
+            assert!(dirent.off > last_off);
+            last_off = dirent.off;
+            if dirent.name == "getdents_test" {
+                let app = open("getdents_test\0", OpenFlags::RDONLY) as usize;
+                let mut st = Stat::default();
+                assert_eq!(fstat(app, &mut st), 0);
+                close(app);
+                assert_eq!(dirent.ino, st.ino);
+                assert_eq!(dirent.kind, DT_REG);
+                found_file = true;
+            } else if dirent.name == "getdents_test_dir" {
+                assert_eq!(dirent.kind, DT_DIR);
+                found_dir = true;
+            }
+        }
+    }
+    assert!(found_file && found_dir);
+    close(fd);
+    assert_eq!(unlinkat(AT_FDCWD, "getdents_test_dir\0", AT_REMOVEDIR), 0);
+    println!("getdents_test passed!");
+    0
+}
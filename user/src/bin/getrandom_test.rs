@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, getrandom, open, read, write, OpenFlags, EINVAL};
+
+const GRND_NONBLOCK: usize = 1;
+
+static mut LARGE: [u8; 3 * 4096 + 100] = [0; 3 * 4096 + 100];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut first = [0u8; 64];
+    let mut second = [0u8; 64];
+    assert_eq!(getrandom(&mut first, 8), -EINVAL);
+    assert_eq!(getrandom(&mut first, 0), 64);
+    assert_eq!(getrandom(&mut second, GRND_NONBLOCK), 64);
+    assert_ne!(first, second);
+    assert!(first.iter().any(|&byte| byte != 0));
+
+    // a request across pages, every block of it is fresh
+    let large = unsafe { &mut LARGE };
+    assert_eq!(getrandom(large, 0), large.len() as isize);
+    for (i, block) in large.chunks(64).enumerate() {
+        assert!(large.chunks(64).skip(i + 1).all(|other| other != block));
+    }
+    // roughly half of the bits are set
+    let ones: u32 = large.iter().map(|byte| byte.count_ones()).sum();
+    let bits = large.len() as u32 * 8;
+    assert!(ones > bits * 45 / 100 && ones < bits * 55 / 100);
+
+    // the device nodes read from the same pool
+    for device in ["/dev/urandom\0", "/dev/random\0"] {
+        let fd = open(device, OpenFlags::RDWR);
+        assert!(fd > 0);
+        let fd = fd as usize;
+        assert_eq!(read(fd, &mut first), 64);
+        assert_eq!(read(fd, &mut second), 64);
+        assert_ne!(first, second);
+        assert_eq!(write(fd, b"more entropy"), 12);
+        close(fd);
+    }
+    println!("getrandom_test passed!");
+    0
+}
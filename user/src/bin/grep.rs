@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{close, open, read, strerror, OpenFlags};
+
+fn read_all(fd: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            return data;
+        }
+        data.extend_from_slice(&buf[..len as usize]);
+    }
+}
+
+/// Print the lines containing `pattern`, prefixed with `name` if given.
+fn grep(pattern: &str, data: &[u8], name: Option<&str>) -> bool {
+    let mut found = false;
+    for line in data.split(|b| *b == b'\n') {
+        let line = core::str::from_utf8(line).unwrap_or("");
+        if line.contains(pattern) {
+            found = true;
+            match name {
+                Some(name) => println!("{}:{}", name, line),
+                None => println!("{}", line),
+            }
+        }
+    }
+    found
+}
+
+/// grep PATTERN [FILE]..., exit code 0 if some line matched, 1 if none
+/// and 2 on errors
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("usage: grep PATTERN [FILE]...");
+        return 2;
+    }
+    let pattern = argv[1];
+    if argc == 2 {
+        return if grep(pattern, &read_all(0), None) {
+            0
+        } else {
+            1
+        };
+    }
+    let mut found = false;
+    for path in &argv[2..] {
+        let fd = open(path, OpenFlags::RDONLY);
+        if fd < 0 {
+            println!("grep: {}: {}", path, strerror(fd));
+            return 2;
+        }
+        let data = read_all(fd as usize);
+        close(fd as usize);
+        let name = if argc > 3 { Some(*path) } else { None };
+        found |= grep(pattern, &data, name);
+    }
+    if found {
+        0
+    } else {
+        1
+    }
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+//! Paint on the whole screen with the mouse or the tablet, read from
+//! /dev/input: the left button draws, the right one erases, c clears and
+//! q or Esc quits.
+
+extern crate user_lib;
+
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::{Dimensions, Drawable, Point, Primitive, RgbColor, Size};
+use embedded_graphics::primitives::{Line, PrimitiveStyle};
+use user_lib::{
+    Display, InputDevices, ABS_RANGE, ABS_X, ABS_Y, BTN_LEFT, BTN_RIGHT, EV_ABS, EV_KEY, EV_REL,
+    EV_SYN, KEY_C, KEY_ESC, KEY_Q, REL_X, REL_Y, VIRTGPU_XRES, VIRTGPU_YRES,
+};
+
+const BACKGROUND: Rgb888 = Rgb888::WHITE;
+
+fn clamp(v: i32, max: u32) -> i32 {
+    v.max(0).min(max as i32 - 1)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut disp = Display::new(Size::new(VIRTGPU_XRES, VIRTGPU_YRES));
+    let input = InputDevices::open();
+    disp.clear(BACKGROUND).unwrap();
+    disp.flush();
+    let mut pen: Option<PrimitiveStyle<Rgb888>> = None;
+    let mut last = Point::new(VIRTGPU_XRES as i32 / 2, VIRTGPU_YRES as i32 / 2);
+    let mut here = last;
+    while let Some(event) = input.wait_event() {
+        match (event.event_type, event.code) {
+            (EV_KEY, KEY_ESC | KEY_Q) if event.value == 1 => break,
+            (EV_KEY, KEY_C) if event.value == 1 => {
+                disp.clear(BACKGROUND).unwrap();
+                disp.flush();
+            }
+            (EV_KEY, BTN_LEFT) => {
+                pen = (event.value == 1).then(|| PrimitiveStyle::with_stroke(Rgb888::BLUE, 3));
+            }
+            (EV_KEY, BTN_RIGHT) => {
+                pen = (event.value == 1).then(|| PrimitiveStyle::with_stroke(BACKGROUND, 15));
+            }
+            (EV_REL, REL_X) => here.x = clamp(here.x + event.value, VIRTGPU_XRES),
+            (EV_REL, REL_Y) => here.y = clamp(here.y + event.value, VIRTGPU_YRES),
+            (EV_ABS, ABS_X) => here.x = event.value * VIRTGPU_XRES as i32 / ABS_RANGE,
+            (EV_ABS, ABS_Y) => here.y = event.value * VIRTGPU_YRES as i32 / ABS_RANGE,
+            // a move is over once both of its axes came
+            (EV_SYN, _) => {
+                if let Some(style) = pen {
+                    let line = Line::new(last, here).into_styled(style);
+                    if line.bounding_box().size != Size::zero() {
+                        line.draw(&mut disp).unwrap();
+                        disp.flush();
+                    }
+                }
+                last = here;
+            }
+            _ => {}
+        }
+    }
+    0
+}
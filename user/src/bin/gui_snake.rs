@@ -5,7 +5,10 @@ extern crate alloc;
 extern crate user_lib;
 
 use user_lib::console::getchar;
-use user_lib::{key_pressed, sleep, Display, VIRTGPU_XRES, VIRTGPU_YRES};
+use user_lib::{
+    getrandom, key_pressed, sleep, Display, InputDevices, EV_KEY, KEY_A, KEY_D, KEY_DOWN, KEY_ENTER,
+    KEY_ESC, KEY_LEFT, KEY_Q, KEY_RIGHT, KEY_S, KEY_UP, KEY_W, VIRTGPU_XRES, VIRTGPU_YRES,
+};
 
 use embedded_graphics::pixelcolor::*;
 use embedded_graphics::prelude::{Drawable, Point, RgbColor, Size};
@@ -128,8 +131,9 @@ struct Food<T: PixelColor> {
 
 impl<T: PixelColor> Food<T> {
     pub fn new(color: T, size_x: u32, size_y: u32) -> Self {
-        let seed = 4;
-        let rng = oorandom::Rand32::new(seed);
+        let mut seed = [0u8; 8];
+        getrandom(&mut seed, 0);
+        let rng = oorandom::Rand32::new(u64::from_le_bytes(seed));
         Food {
             size_x,
             size_y,
@@ -325,10 +329,26 @@ mod tests {
 
 const LF: u8 = 0x0au8;
 const CR: u8 = 0x0du8;
+
+/// A direction for a key of the virtio keyboard, None for quitting.
+fn key_direction(code: u16) -> Option<Option<Direction>> {
+    match code {
+        KEY_ESC | KEY_ENTER | KEY_Q => Some(None),
+        KEY_UP | KEY_W => Some(Some(Direction::Up)),
+        KEY_DOWN | KEY_S => Some(Some(Direction::Down)),
+        KEY_LEFT | KEY_A => Some(Some(Direction::Left)),
+        KEY_RIGHT | KEY_D => Some(Some(Direction::Right)),
+        _ => None,
+    }
+}
+
+/// Steered with wasd on the serial console or with the arrow keys in the
+/// graphical window, which come through /dev/input.
 #[no_mangle]
 pub fn main() -> i32 {
     let mut disp = Display::new(Size::new(VIRTGPU_XRES, VIRTGPU_YRES));
     let mut game = SnakeGame::<20, Rgb888>::new(1280, 800, 20, 20, Rgb888::RED, Rgb888::YELLOW, 200);
+    let input = InputDevices::open();
     let _ = disp.clear(Rgb888::BLACK).unwrap();
     loop {
         if key_pressed() {
@@ -343,6 +363,17 @@ pub fn main() -> i32 {
                 _ => (),
             }
         }
+        while let Some(event) = input.event() {
+            // presses and their repeats
+            if event.event_type != EV_KEY || event.value == 0 {
+                continue;
+            }
+            match key_direction(event.code) {
+                Some(Some(direction)) => game.set_direction(direction),
+                Some(None) => return 0,
+                None => (),
+            }
+        }
         let _ = disp.clear(Rgb888::BLACK).unwrap();
         game.draw(&mut disp);
         disp.flush();
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+#[macro_use]
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use user_lib::{fork, waitpid};
+
+/// far more than the old 32 KiB arena
+const BIG: usize = 1 << 20;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // one large block
+    let mut big = vec![0u8; BIG];
+    for (i, byte) in big.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    assert!(big.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+
+    // growing by push goes through realloc and keeps the contents
+    let mut grown: Vec<usize> = Vec::new();
+    for i in 0..100_000 {
+        grown.push(i);
+    }
+    assert!(grown.iter().enumerate().all(|(i, &word)| word == i));
+    grown.truncate(10);
+    grown.shrink_to_fit();
+    assert_eq!(grown, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+    // many small blocks, freed and taken again
+    for round in 0..4 {
+        let boxes: Vec<Box<[usize; 8]>> = (0..4096).map(|i| Box::new([i + round; 8])).collect();
+        assert!(boxes.iter().enumerate().all(|(i, b)| b[7] == i + round));
+    }
+    drop(big);
+    let again = vec![1u8; BIG];
+    assert_eq!(again[BIG - 1], 1);
+
+    // the child has its own copy of the heap
+    let pid = fork();
+    if pid == 0 {
+        let child = vec![2u8; BIG];
+        assert_eq!(again[0] + child[0], 3);
+        user_lib::exit(0);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+    println!("heap_test passed!");
+    0
+}
@@ -0,0 +1,152 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+// use http://localhost:6201/ to access the server, every file of the root directory is served
+
+use user_lib::{
+    accept, close, exit, fork, getpid, listen, open, read, sendfile, waitpid_nb, write, OpenFlags,
+};
+
+const PORT: u16 = 80;
+const INDEX: &str = "index.html";
+const INDEX_PAGE: &str = "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>rCore-Tutorial-v3</title></head>
+<body>
+<h1>rCore-Tutorial-v3</h1>
+<p>This page was read from easy-fs and sent by sendfile.</p>
+</body>
+</html>
+";
+/// whatever the file holds, sendfile stops at its end
+const MAX_FILE_LEN: usize = 1 << 30;
+
+/// Put a default page in place unless there is one already.
+fn create_index() {
+    let fd = open(INDEX, OpenFlags::RDONLY);
+    if fd >= 0 {
+        close(fd as usize);
+        return;
+    }
+    let fd = open(INDEX, OpenFlags::CREATE | OpenFlags::WRONLY);
+    if fd >= 0 {
+        write(fd as usize, INDEX_PAGE.as_bytes());
+        close(fd as usize);
+    }
+}
+
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit('.').next() {
+        Some("html") | Some("htm") => "text/html",
+        Some("txt") => "text/plain",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("png") => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+fn respond_error(client: usize, status: &str) {
+    let body = status.as_bytes();
+    write(client, b"HTTP/1.0 ");
+    write(client, body);
+    write(
+        client,
+        b"\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n",
+    );
+    write(client, body);
+    write(client, b"\n");
+}
+
+/// Answer one HTTP/1.0 request, returning the status sent.
+fn serve(client: usize) -> &'static str {
+    let mut request = [0u8; 1024];
+    let len = read(client, &mut request);
+    if len <= 0 {
+        return "-";
+    }
+    let request = core::str::from_utf8(&request[..len as usize]).unwrap_or("");
+    let mut words = request.lines().next().unwrap_or("").split(' ');
+    let (method, path) = match (words.next(), words.next()) {
+        (Some(method), Some(path)) => (method, path),
+        _ => {
+            respond_error(client, "400 Bad Request");
+            return "400";
+        }
+    };
+    if method != "GET" && method != "HEAD" {
+        respond_error(client, "501 Not Implemented");
+        return "501";
+    }
+    // the file system is flat, nothing below the root
+    let name = match path.trim_start_matches('/') {
+        "" => INDEX,
+        name if name.contains('/') || name.contains("..") => {
+            respond_error(client, "404 Not Found");
+            return "404";
+        }
+        name => name,
+    };
+    let mut path_z = [0u8; 64];
+    if name.len() >= path_z.len() {
+        respond_error(client, "404 Not Found");
+        return "404";
+    }
+    path_z[..name.len()].copy_from_slice(name.as_bytes());
+    let file = open(
+        core::str::from_utf8(&path_z[..name.len() + 1]).unwrap(),
+        OpenFlags::RDONLY,
+    );
+    if file < 0 {
+        respond_error(client, "404 Not Found");
+        return "404";
+    }
+    let file = file as usize;
+    // without a length the body ends when the connection closes
+    write(client, b"HTTP/1.0 200 OK\r\nContent-Type: ");
+    write(client, content_type(name).as_bytes());
+    write(client, b"\r\nConnection: close\r\n\r\n");
+    if method == "GET" {
+        let mut offset = 0;
+        sendfile(client, file, Some(&mut offset), MAX_FILE_LEN);
+    }
+    close(file);
+    "200"
+}
+
+fn reap_children() {
+    let mut exit_code = 0;
+    while waitpid_nb(usize::MAX, &mut exit_code) > 0 {}
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    create_index();
+    let port = listen(PORT);
+    if port < 0 {
+        println!("[httpd] failed to listen on port {}", PORT);
+        return -1;
+    }
+    println!("[httpd] serving the root directory on port {}", PORT);
+    loop {
+        let client = accept(port as usize);
+        if client < 1 {
+            println!("[httpd] failed to accept a client");
+            return -1;
+        }
+        let client = client as usize;
+        // each connection is served by a child, so a slow client holds up nobody
+        let pid = fork();
+        if pid == 0 {
+            let status = serve(client);
+            println!("[httpd] pid {}: {}", getpid(), status);
+            close(client);
+            exit(0);
+        }
+        close(client);
+        reap_children();
+    }
+}
@@ -3,17 +3,43 @@
 
 extern crate user_lib;
 
-use user_lib::{exec, fork, wait, yield_};
+use user_lib::{close, dup2, exec, fork, open, wait, yield_, OpenFlags, ECHILD};
+
+/// Run a shell on the terminal at `path`; returns only if it is missing.
+fn shell_on(path: &str) {
+    let fd = open(path, OpenFlags::RDWR);
+    if fd < 0 {
+        return;
+    }
+    for std_fd in 0..3 {
+        dup2(fd as usize, std_fd);
+    }
+    close(fd as usize);
+    exec("user_shell\0", &[core::ptr::null::<u8>()]);
+}
 
 #[no_mangle]
 fn main() -> i32 {
+    if fork() == 0 {
+        exec("sntp\0", &[core::ptr::null::<u8>()]);
+    }
+    // a second shell on the virtio-console port named "shell", if any
+    if fork() == 0 {
+        shell_on("/dev/virtio-ports/shell\0");
+        return 0;
+    }
+    // and one on the second virtual terminal, ^] 2 shows it
+    if fork() == 0 {
+        shell_on("/dev/tty2\0");
+        return 0;
+    }
     if fork() == 0 {
         exec("user_shell\0", &[core::ptr::null::<u8>()]);
     } else {
         loop {
             let mut exit_code: i32 = 0;
             let pid = wait(&mut exit_code);
-            if pid == -1 {
+            if pid == -ECHILD {
                 yield_();
                 continue;
             }
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, eviocgname, ioctl, open, unlinkat, OpenFlags, RtcTime, AT_FDCWD, EBADF, EFAULT, EINVAL,
+    ENOTTY, EVIOCGVERSION, FBIOGET_VSCREENINFO, RTC_RD_TIME, RTC_SET_TIME, TCGETS,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let rtc = open("/dev/rtc0\0", OpenFlags::RDONLY);
+    assert!(rtc >= 0);
+    let rtc = rtc as usize;
+    let mut now = RtcTime::default();
+    assert_eq!(ioctl(rtc, RTC_RD_TIME, &mut now as *mut _ as usize), 0);
+    assert!(now.year >= 70 && (0..12).contains(&now.mon) && (1..=31).contains(&now.mday));
+
+    // a leap day, which was a thursday
+    let leap_day = RtcTime {
+        sec: 56,
+        min: 34,
+        hour: 12,
+        mday: 29,
+        mon: 1,
+        year: 124,
+        ..Default::default()
+    };
+    assert_eq!(ioctl(rtc, RTC_SET_TIME, &leap_day as *const _ as usize), 0);
+    let mut read_back = RtcTime::default();
+    assert_eq!(
+        ioctl(rtc, RTC_RD_TIME, &mut read_back as *mut _ as usize),
+        0
+    );
+    assert_eq!(
+        (
+            read_back.year,
+            read_back.mon,
+            read_back.mday,
+            read_back.hour
+        ),
+        (124, 1, 29, 12)
+    );
+    assert_eq!((read_back.wday, read_back.yday), (4, 59));
+    let bad_day = RtcTime {
+        mday: 30,
+        ..leap_day
+    };
+    assert_eq!(
+        ioctl(rtc, RTC_SET_TIME, &bad_day as *const _ as usize),
+        -EINVAL
+    );
+    assert_eq!(ioctl(rtc, RTC_SET_TIME, &now as *const _ as usize), 0);
+
+    // arguments are checked against the address space
+    assert_eq!(ioctl(rtc, RTC_RD_TIME, 0), -EFAULT);
+    assert_eq!(ioctl(rtc, RTC_SET_TIME, usize::MAX - 4), -EFAULT);
+    assert_eq!(ioctl(rtc, TCGETS, 0), -ENOTTY);
+    close(rtc);
+
+    let event = open("/dev/input/event0\0", OpenFlags::RDONLY) as usize;
+    let mut version: i32 = 0;
+    assert_eq!(
+        ioctl(event, EVIOCGVERSION, &mut version as *mut _ as usize),
+        0
+    );
+    assert_eq!(version, 0x010001);
+    let mut name = [0xffu8; 7];
+    assert_eq!(
+        ioctl(event, eviocgname(name.len()), name.as_mut_ptr() as usize),
+        7
+    );
+    assert_eq!(&name, b"virtio\0");
+    close(event);
+
+    let fb = open("/dev/fb0\0", OpenFlags::RDONLY) as usize;
+    let mut var_info = [0u32; 40];
+    assert_eq!(
+        ioctl(fb, FBIOGET_VSCREENINFO, var_info.as_mut_ptr() as usize),
+        0
+    );
+    // xres, yres and bits_per_pixel
+    assert!(var_info[0] > 0 && var_info[1] > 0);
+    assert_eq!(var_info[6], 32);
+    close(fb);
+
+    // files without a device behind them know no requests
+    let fd = open("ioctl_test_file\0", OpenFlags::CREATE | OpenFlags::WRONLY) as usize;
+    assert_eq!(ioctl(fd, TCGETS, 0), -ENOTTY);
+    close(fd);
+    assert_eq!(unlinkat(AT_FDCWD, "ioctl_test_file\0", 0), 0);
+    assert_eq!(ioctl(fd, TCGETS, 0), -EBADF);
+
+    println!("ioctl_test passed!");
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, getpgid, killpg, setpgid, sleep, waitpid_options, wifcontinued, wifstopped, wstopsig,
+    yield_, SignalFlags, WCONTINUED, WNOHANG, WUNTRACED,
+};
+
+const SIGTSTP: usize = 20;
+const SIGKILL_STATUS: i32 = -9;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        loop {
+            yield_();
+        }
+    }
+    let pid = pid as usize;
+    assert_eq!(setpgid(pid, 0), 0);
+    assert_eq!(getpgid(pid), pid as isize);
+    let mut status: i32 = 0;
+    assert_eq!(killpg(pid, SignalFlags::SIGTSTP.bits()), 0);
+    assert_eq!(
+        waitpid_options(-(pid as isize), &mut status, WUNTRACED),
+        pid as isize
+    );
+    assert!(wifstopped(status) && wstopsig(status) == SIGTSTP);
+    // a stop is reported once
+    sleep(10);
+    assert!(waitpid_options(pid as isize, &mut status, WNOHANG | WUNTRACED) < 0);
+    assert_eq!(killpg(pid, SignalFlags::SIGCONT.bits()), 0);
+    assert_eq!(
+        waitpid_options(pid as isize, &mut status, WCONTINUED),
+        pid as isize
+    );
+    assert!(wifcontinued(status));
+    assert_eq!(killpg(pid, SignalFlags::SIGKILL.bits()), 0);
+    assert_eq!(waitpid_options(pid as isize, &mut status, 0), pid as isize);
+    assert_eq!(status, SIGKILL_STATUS);
+    println!("job_control_test passed!");
+    0
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, waitpid};
+
+/// start of the kernel image, mapped in kernel space only
+const KERNEL_BASE: usize = 0x8020_0000;
+/// the trampoline is in every address space but without the U flag
+const TRAMPOLINE: usize = usize::MAX - 0x1000 + 1;
+
+fn read(addr: usize) {
+    let value = unsafe { (addr as *const usize).read_volatile() };
+    println!(
+        "read {:#x} from {:#x}, kernel memory is exposed!",
+        value, addr
+    );
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("Into Test kernel_read, we will read kernel memory from user mode...");
+    println!("Kernel should kill this application!");
+    let pid = fork();
+    if pid == 0 {
+        read(TRAMPOLINE);
+        return 0;
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, -11);
+    read(KERNEL_BASE);
+    0
+}
@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{kill, killpg, strerror, SignalFlags};
+
+const SIGNALS: [(&str, usize); 10] = [
+    ("HUP", 1),
+    ("INT", 2),
+    ("QUIT", 3),
+    ("KILL", 9),
+    ("USR1", 10),
+    ("USR2", 12),
+    ("TERM", 15),
+    ("CONT", 18),
+    ("STOP", 19),
+    ("TSTP", 20),
+];
+
+/// `-9`, `-KILL` or `-SIGKILL`
+fn parse_signal(arg: &str) -> Option<usize> {
+    let name = arg.strip_prefix('-')?;
+    if let Ok(signum) = name.parse() {
+        return Some(signum);
+    }
+    let name = name.strip_prefix("SIG").unwrap_or(name);
+    SIGNALS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, signum)| *signum)
+}
+
+/// kill [-SIGNAL] PID..., a negative pid is a process group
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let (signum, pids) = match argv.get(1) {
+        Some(arg) if arg.starts_with('-') && argc > 2 => match parse_signal(arg) {
+            Some(signum) => (signum, &argv[2..]),
+            None => {
+                println!("kill: {}: invalid signal", arg);
+                return 1;
+            }
+        },
+        _ => (15, &argv[1..]),
+    };
+    if !(1..32).contains(&signum) {
+        println!("kill: {}: invalid signal", signum);
+        return 1;
+    }
+    let signal = SignalFlags::from_bits(1 << signum).unwrap();
+    if pids.is_empty() {
+        println!("usage: kill [-SIGNAL] PID...");
+        return 1;
+    }
+    let mut exit_code = 0;
+    for pid in pids {
+        let ret = match pid.parse::<isize>() {
+            Ok(pid) if pid > 0 => kill(pid as usize, signal.bits()),
+            Ok(pid) if pid < 0 => killpg(pid.unsigned_abs(), signal.bits()),
+            _ => {
+                println!("kill: {}: invalid pid", pid);
+                exit_code = 1;
+                continue;
+            }
+        };
+        if ret < 0 {
+            println!("kill: {}: {}", pid, strerror(ret));
+            exit_code = 1;
+        }
+    }
+    exit_code
+}
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, open, read, OpenFlags};
+
+fn ksyms() -> String {
+    let fd = open("/proc/ksyms\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut text = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        text.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(text).unwrap()
+}
+
+/// The symbols are in order and name the kernel entry. A kernel built
+/// without the Makefile has none, which is fine as well.
+#[no_mangle]
+pub fn main() -> i32 {
+    let text = ksyms();
+    let mut last = 0;
+    let mut entry = false;
+    for line in text.lines() {
+        let (addr, name) = line.split_once(' ').unwrap();
+        let addr = usize::from_str_radix(addr, 16).unwrap();
+        assert!(addr > last, "{} out of order", line);
+        last = addr;
+        entry |= name.ends_with("rust_main");
+    }
+    assert!(text.is_empty() || entry, "no rust_main in /proc/ksyms");
+    println!("ksyms_test passed!");
+    0
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+
+//! Latency of the basic kernel paths, one line per benchmark in a fixed
+//! format to diff between kernels:
+//!
+//! `lat <name> <rounds> <ns per round> ns <cycles per round> cycles`
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, cycles, exit, fork, get_time, getpid, pipe, read, waitpid, write, yield_};
+
+const SYSCALL_ROUNDS: usize = 100000;
+const YIELD_ROUNDS: usize = 10000;
+const PIPE_ROUNDS: usize = 5000;
+const FORK_ROUNDS: usize = 200;
+
+/// Run `round` `rounds` times and report the mean of a round.
+fn bench(name: &str, rounds: usize, mut round: impl FnMut()) {
+    let (start_ms, start_cycles) = (get_time(), cycles());
+    for _ in 0..rounds {
+        round();
+    }
+    let elapsed_cycles = cycles() - start_cycles;
+    let elapsed_ms = (get_time() - start_ms) as usize;
+    println!(
+        "lat {} {} {} ns {} cycles",
+        name,
+        rounds,
+        elapsed_ms * 1000000 / rounds,
+        elapsed_cycles / rounds
+    );
+}
+
+/// Yield to a child yielding as well, each round is two switches.
+fn bench_yield() {
+    let pid = fork();
+    if pid == 0 {
+        for _ in 0..YIELD_ROUNDS {
+            yield_();
+        }
+        exit(0);
+    }
+    bench("yield", YIELD_ROUNDS, || {
+        yield_();
+    });
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+}
+
+/// A byte sent to a child and back over two pipes.
+fn bench_pipe() {
+    let (mut ping, mut pong) = ([0usize; 2], [0usize; 2]);
+    assert_eq!(pipe(&mut ping), 0);
+    assert_eq!(pipe(&mut pong), 0);
+    let mut byte = [0u8; 1];
+    let pid = fork();
+    if pid == 0 {
+        close(ping[1]);
+        close(pong[0]);
+        while read(ping[0], &mut byte) == 1 {
+            write(pong[1], &byte);
+        }
+        exit(0);
+    }
+    close(ping[0]);
+    close(pong[1]);
+    bench("pipe", PIPE_ROUNDS, || {
+        write(ping[1], &byte);
+        assert_eq!(read(pong[0], &mut byte), 1);
+    });
+    close(ping[1]);
+    close(pong[0]);
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    bench("null_syscall", SYSCALL_ROUNDS, || {
+        getpid();
+    });
+    bench_yield();
+    bench_pipe();
+    bench("fork", FORK_ROUNDS, || {
+        let pid = fork();
+        if pid == 0 {
+            exit(0);
+        }
+        let mut exit_code = 0;
+        waitpid(pid as usize, &mut exit_code);
+    });
+    0
+}
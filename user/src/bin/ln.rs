@@ -0,0 +1,22 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{link, strerror};
+
+/// ln TARGET LINK_NAME, hard links only
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 3 {
+        println!("usage: ln TARGET LINK_NAME");
+        return 1;
+    }
+    let ret = link(argv[1], argv[2]);
+    if ret < 0 {
+        println!("ln: {}: {}", argv[2], strerror(ret));
+        return 1;
+    }
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read_dir, strerror, OpenFlags};
+
+fn list(path: &str) -> bool {
+    if let Some(mut names) = read_dir(path) {
+        names.sort();
+        for name in names.iter() {
+            println!("{}", name);
+        }
+        return true;
+    }
+    // not a directory, a file lists itself
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        println!("ls: {}: {}", path, strerror(fd));
+        return false;
+    }
+    close(fd as usize);
+    println!("{}", path);
+    true
+}
+
+/// ls [PATH]..., the current directory without arguments
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 1 {
+        return if list(".\0") { 0 } else { 1 };
+    }
+    let mut exit_code = 0;
+    for (i, path) in argv[1..].iter().enumerate() {
+        if argc > 2 {
+            if i > 0 {
+                println!("");
+            }
+            println!("{}:", path);
+        }
+        if !list(path) {
+            exit_code = 1;
+        }
+    }
+    exit_code
+}
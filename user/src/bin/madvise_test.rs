@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fork, madvise, pipe, read, waitpid, write, EINVAL, ENOMEM, MADV_DONTNEED, MADV_NORMAL,
+    MADV_WILLNEED,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const PAGES: usize = 64;
+const WORKERS: usize = 4;
+const ROUNDS: usize = 32;
+
+#[repr(C, align(4096))]
+struct Buffer([u8; PAGES * PAGE_SIZE]);
+
+static mut BUFFER: Buffer = Buffer([0; PAGES * PAGE_SIZE]);
+
+fn page(index: usize) -> &'static mut [u8] {
+    unsafe { &mut BUFFER.0[index * PAGE_SIZE..(index + 1) * PAGE_SIZE] }
+}
+
+fn fill(pages: core::ops::Range<usize>, seed: u8) {
+    for index in pages {
+        for (offset, byte) in page(index).iter_mut().enumerate() {
+            *byte = (seed ^ index as u8 ^ offset as u8) | 1;
+        }
+    }
+}
+
+fn filled(index: usize, seed: u8) -> bool {
+    page(index)
+        .iter()
+        .enumerate()
+        .all(|(offset, &byte)| byte == (seed ^ index as u8 ^ offset as u8) | 1)
+}
+
+fn zeroed(index: usize) -> bool {
+    page(index).iter().all(|&byte| byte == 0)
+}
+
+fn advise(pages: core::ops::Range<usize>, advice: usize) -> isize {
+    let start = page(pages.start).as_ptr() as usize;
+    madvise(start, pages.len() * PAGE_SIZE, advice)
+}
+
+/// Touch the whole buffer and give it back to the kernel, over and over.
+fn worker(seed: u8) {
+    for round in 0..ROUNDS {
+        let seed = seed.wrapping_add(round as u8);
+        fill(0..PAGES, seed);
+        assert!((0..PAGES).all(|index| filled(index, seed)));
+        assert_eq!(advise(0..PAGES, MADV_DONTNEED), 0);
+        assert!((0..PAGES).all(zeroed));
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = page(0).as_ptr() as usize;
+    assert_eq!(madvise(start + 1, PAGE_SIZE, MADV_DONTNEED), -EINVAL);
+    assert_eq!(madvise(start, PAGE_SIZE, 100), -EINVAL);
+    assert_eq!(madvise(0x7000_0000, PAGE_SIZE, MADV_DONTNEED), -ENOMEM);
+    assert_eq!(advise(0..PAGES, MADV_NORMAL), 0);
+
+    // dropped pages read back as zero, the others keep their data
+    fill(0..PAGES, 0x5a);
+    assert_eq!(advise(8..24, MADV_DONTNEED), 0);
+    assert!((0..8).chain(24..PAGES).all(|index| filled(index, 0x5a)));
+    assert!((8..24).all(zeroed));
+    fill(8..16, 0x33);
+    assert!((8..16).all(|index| filled(index, 0x33)));
+
+    // prefetching brings the rest back, still zeroed
+    assert_eq!(advise(8..24, MADV_WILLNEED), 0);
+    assert!((16..24).all(zeroed));
+    assert!((8..16).all(|index| filled(index, 0x33)));
+
+    // the kernel writes into a dropped page
+    assert_eq!(advise(30..31, MADV_DONTNEED), 0);
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(write(fds[1], b"madvise"), 7);
+    assert_eq!(read(fds[0], &mut page(30)[..7]), 7);
+    assert_eq!(&page(30)[..7], b"madvise");
+    close(fds[0]);
+    close(fds[1]);
+
+    // a child inherits the dropped pages as dropped
+    assert_eq!(advise(40..48, MADV_DONTNEED), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert!((40..48).all(zeroed));
+        assert!(filled(39, 0x5a));
+        user_lib::exit(0);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+
+    // memory pressure: workers touch and drop the buffer concurrently
+    let mut pids = [0isize; WORKERS];
+    for (index, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            worker(index as u8 * 61);
+            user_lib::exit(0);
+        }
+    }
+    for pid in pids {
+        let mut exit_code = 0;
+        waitpid(pid as usize, &mut exit_code);
+        assert_eq!(exit_code, 0);
+    }
+    println!("madvise_test passed!");
+    0
+}
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{
+    close, getpid, mmap, munmap, open, read, unlink, write, OpenFlags, MAP_ANONYMOUS, MAP_PRIVATE,
+    MAP_SHARED, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const FILE: &str = "maps_file\0";
+
+fn read_all(path: &str) -> String {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0, "open {}", path);
+    let mut text = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        text.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(text).unwrap()
+}
+
+/// The line of the area starting at `start`, split into its fields.
+fn area(maps: &str, start: usize) -> Option<Vec<String>> {
+    let prefix = format!("{:x}-", start);
+    maps.lines()
+        .find(|line| line.starts_with(&prefix))
+        .map(|line| line.split(' ').map(String::from).collect())
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let anon = mmap(
+        2 * PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(anon > 0);
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, &[7u8; PAGE_SIZE]), PAGE_SIZE as isize);
+    let shared = mmap(PAGE_SIZE, PROT_READ, MAP_SHARED, fd as usize, 0);
+    assert!(shared > 0);
+
+    let maps = read_all("/proc/self/maps\0");
+    print!("{}", maps);
+    let fields = area(&maps, anon as usize).expect("no anonymous area");
+    let end = format!("{:x}", anon as usize + 2 * PAGE_SIZE);
+    assert_eq!(fields[0].split_once('-').unwrap().1, end);
+    assert_eq!(fields[1], "rw-p");
+    assert_eq!((fields[3].as_str(), fields[4].as_str()), ("00:00", "0"));
+    let fields = area(&maps, shared as usize).expect("no file area");
+    assert_eq!(fields[1], "r--s");
+    assert_eq!(fields[3], "00:00");
+    assert_ne!(fields[4], "0");
+    // the program text and the stack of the main thread are there too
+    assert!(maps.lines().any(|line| line.contains(" r-xp ")));
+    assert!(maps.lines().any(|line| line.ends_with(" [stack]")));
+
+    // the same through the pid, and gone once unmapped
+    assert_eq!(munmap(anon as usize, 2 * PAGE_SIZE), 0);
+    let maps = read_all(&format!("/proc/{}/maps\0", getpid()));
+    assert!(area(&maps, anon as usize).is_none());
+    assert!(area(&maps, shared as usize).is_some());
+
+    assert_eq!(munmap(shared as usize, PAGE_SIZE), 0);
+    close(fd as usize);
+    assert_eq!(unlink(FILE), 0);
+    assert!(open("/proc/100000/maps\0", OpenFlags::RDONLY) < 0);
+    println!("maps_test passed!");
+    0
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+
+#[repr(C, align(8))]
+struct Buffer([u8; 32]);
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buffer = Buffer([0; 32]);
+    let base = buffer.0.as_mut_ptr() as usize;
+    for offset in 1..8 {
+        let addr = base + offset;
+        let value: usize = 0x8877_6655_4433_2211 + offset;
+        let (double, word, half): (usize, isize, usize);
+        unsafe {
+            asm!("sd {0}, 0({1})", in(reg) value, in(reg) addr);
+            asm!("ld {0}, 0({1})", out(reg) double, in(reg) addr);
+            asm!("lw {0}, 4({1})", out(reg) word, in(reg) addr);
+            asm!("lhu {0}, 6({1})", out(reg) half, in(reg) addr);
+        }
+        assert_eq!(double, value);
+        assert_eq!(word, (value >> 32) as u32 as i32 as isize);
+        assert_eq!(half, value >> 48);
+        assert_eq!(buffer.0[offset], value as u8);
+        assert_eq!(buffer.0[offset + 7], (value >> 56) as u8);
+    }
+    println!("misaligned passed!");
+    0
+}
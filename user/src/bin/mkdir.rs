@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mkdir, strerror};
+
+/// mkdir DIRECTORY...
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 1 {
+        println!("usage: mkdir DIRECTORY...");
+        return 1;
+    }
+    let mut exit_code = 0;
+    for path in &argv[1..] {
+        let ret = mkdir(path);
+        if ret < 0 {
+            println!("mkdir: {}: {}", path, strerror(ret));
+            exit_code = 1;
+        }
+    }
+    exit_code
+}
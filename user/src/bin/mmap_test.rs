@@ -0,0 +1,122 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fork, mmap, msync, munmap, open, read, waitpid, write, OpenFlags, EACCES, EBADF, EINVAL,
+    MAP_ANONYMOUS, MAP_PRIVATE, MAP_SHARED, MS_ASYNC, MS_SYNC, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const FILE: &str = "mmap_file\0";
+const FILE_LEN: usize = 6000;
+
+/// too big for the user stack
+static mut DATA: [u8; FILE_LEN] = [0; FILE_LEN];
+
+fn byte_at(offset: usize) -> u8 {
+    b'a' + (offset % 26) as u8
+}
+
+fn create_file() {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let data = unsafe { &mut DATA };
+    for (offset, byte) in data.iter_mut().enumerate() {
+        *byte = byte_at(offset);
+    }
+    assert_eq!(write(fd as usize, data), FILE_LEN as isize);
+    close(fd as usize);
+}
+
+/// Whole content of the file, which must not have grown.
+fn read_file() -> &'static [u8] {
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let data = unsafe { &mut DATA };
+    let mut rest = [0u8; 16];
+    assert_eq!(read(fd as usize, data), FILE_LEN as isize);
+    assert_eq!(read(fd as usize, &mut rest), 0);
+    close(fd as usize);
+    data
+}
+
+fn map(fd: usize, prot: usize, flags: usize) -> &'static mut [u8] {
+    let start = mmap(FILE_LEN, prot, flags, fd, 0);
+    assert!(start > 0);
+    unsafe { core::slice::from_raw_parts_mut(start as *mut u8, 2 * PAGE_SIZE) }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    create_file();
+    let fd = open(FILE, OpenFlags::RDWR) as usize;
+    let ro_fd = open(FILE, OpenFlags::RDONLY) as usize;
+    let rw = PROT_READ | PROT_WRITE;
+    assert_eq!(mmap(FILE_LEN, rw, MAP_SHARED | MAP_PRIVATE, fd, 0), -EINVAL);
+    assert_eq!(mmap(FILE_LEN, rw, MAP_SHARED, fd, 1), -EINVAL);
+    assert_eq!(mmap(FILE_LEN, rw, MAP_SHARED, 100, 0), -EBADF);
+    assert_eq!(mmap(FILE_LEN, rw, MAP_SHARED, ro_fd, 0), -EACCES);
+
+    // the file shows through, the tail of the last page is zero
+    let shared = map(fd, rw, MAP_SHARED);
+    assert!((0..FILE_LEN).all(|offset| shared[offset] == byte_at(offset)));
+    assert!(shared[FILE_LEN..].iter().all(|&byte| byte == 0));
+
+    // msync makes the edits durable, past the end nothing is written
+    shared[10] = b'X';
+    shared[5000] = b'Y';
+    shared[FILE_LEN + 1] = b'Z';
+    let start = shared.as_ptr() as usize;
+    assert_eq!(msync(start, PAGE_SIZE, MS_SYNC | MS_ASYNC), -EINVAL);
+    assert_eq!(msync(start, 2 * PAGE_SIZE, MS_SYNC), 0);
+    let data = read_file();
+    assert_eq!((data[10], data[5000], data[11]), (b'X', b'Y', byte_at(11)));
+
+    // munmap writes back what msync has not seen yet
+    shared[20] = b'W';
+    assert_eq!(msync(start, PAGE_SIZE, MS_ASYNC), 0);
+    shared[4100] = b'V';
+    assert_eq!(munmap(start, 2 * PAGE_SIZE), 0);
+    let data = read_file();
+    assert_eq!((data[20], data[4100]), (b'W', b'V'));
+
+    // a private mapping sees the file until it stores to a page, which it
+    // then has a copy of
+    let private = map(ro_fd, rw, MAP_PRIVATE);
+    let shared = map(fd, rw, MAP_SHARED);
+    assert_eq!(private[10], b'X');
+    private[10] = b'P';
+    shared[4100] = b'S';
+    assert_eq!((shared[10], private[4100]), (b'X', b'S'));
+    shared[20] = b'T';
+    assert_eq!(private[20], b'W');
+    assert_eq!(munmap(private.as_ptr() as usize, 2 * PAGE_SIZE), 0);
+    assert_eq!(munmap(shared.as_ptr() as usize, 2 * PAGE_SIZE), 0);
+    let data = read_file();
+    assert_eq!((data[10], data[20], data[4100]), (b'X', b'T', b'S'));
+
+    // anonymous memory is zeroed and gone after munmap
+    let anonymous = mmap(3 * PAGE_SIZE, rw, MAP_PRIVATE | MAP_ANONYMOUS, 0, 0);
+    assert!(anonymous > 0);
+    let page = unsafe { core::slice::from_raw_parts_mut(anonymous as *mut u8, 3 * PAGE_SIZE) };
+    assert!(page.iter().all(|&byte| byte == 0));
+    page[PAGE_SIZE] = 1;
+    assert_eq!(munmap(anonymous as usize + PAGE_SIZE, PAGE_SIZE), 0);
+    assert_eq!(page[2 * PAGE_SIZE], 0);
+    let pid = fork();
+    if pid == 0 {
+        unsafe { (anonymous as *const u8).add(PAGE_SIZE).read_volatile() };
+        user_lib::exit(0);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, -11);
+
+    close(fd);
+    close(ro_fd);
+    println!("mmap_test passed!");
+    0
+}
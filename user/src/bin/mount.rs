@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mount, strerror};
+
+/// mount [-t 9p] TAG DIRECTORY, for a host directory shared over virtio-9p
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let args = match argc {
+        3 => &argv[1..],
+        5 if argv[1] == "-t" && argv[2] == "9p" => &argv[3..],
+        _ => {
+            println!("usage: mount [-t 9p] TAG DIRECTORY");
+            return 1;
+        }
+    };
+    let ret = mount(args[0], args[1], "9p\0");
+    if ret < 0 {
+        println!("mount: {}: {}", args[1], strerror(ret));
+        return 1;
+    }
+    0
+}
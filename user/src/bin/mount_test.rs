@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, mkdir, mount, open, rmdir, umount, unlink, OpenFlags, EINVAL, ENODEV, ENOENT, ENOTDIR,
+};
+
+/// Only the checks before a share is attached, the usual QEMU command
+/// line has no virtio-9p device.
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mkdir("mount_test_dir\0"), 0);
+    let fd = open("mount_test_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    close(fd as usize);
+
+    assert_eq!(mount("host\0", "mount_test_dir\0", "ext4\0"), -ENODEV);
+    assert_eq!(mount("host\0", "mount_test_none\0", "9p\0"), -ENOENT);
+    assert_eq!(mount("host\0", "mount_test_file\0", "9p\0"), -ENOTDIR);
+    assert_eq!(mount("no_such_tag\0", "mount_test_dir\0", "9p\0"), -ENOENT);
+    assert_eq!(umount("mount_test_dir\0"), -EINVAL);
+
+    assert_eq!(unlink("mount_test_file\0"), 0);
+    assert_eq!(rmdir("mount_test_dir\0"), 0);
+    println!("mount_test passed!");
+    0
+}
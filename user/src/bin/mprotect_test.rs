@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, mprotect, waitpid, EACCES, EINVAL, ENOMEM, PROT_EXEC, PROT_NONE, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+
+#[repr(C, align(4096))]
+struct Pages([u8; 3 * PAGE_SIZE]);
+
+static mut PAGES: Pages = Pages([0; 3 * PAGE_SIZE]);
+
+fn page(index: usize) -> *mut u8 {
+    unsafe { PAGES.0.as_mut_ptr().add(index * PAGE_SIZE) }
+}
+
+fn run(code: *mut u8) -> usize {
+    let f: extern "C" fn() -> usize = unsafe { core::mem::transmute(code) };
+    f()
+}
+
+/// Run `f` in a child and return its exit code.
+fn in_child(f: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        user_lib::exit(0);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    exit_code
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let start = page(0) as usize;
+    assert_eq!(mprotect(start + 1, PAGE_SIZE, PROT_READ), -EINVAL);
+    assert_eq!(mprotect(start, PAGE_SIZE, PROT_WRITE | PROT_EXEC), -EACCES);
+    assert_eq!(mprotect(0x7000_0000, PAGE_SIZE, PROT_READ), -ENOMEM);
+
+    // read-only page in the middle of the data segment
+    unsafe { page(1).write_volatile(7) };
+    assert_eq!(mprotect(page(1) as usize, PAGE_SIZE, PROT_READ), 0);
+    assert_eq!(unsafe { page(1).read_volatile() }, 7);
+    unsafe { page(0).write_volatile(1) };
+    unsafe { page(2).write_volatile(2) };
+    assert_eq!(in_child(|| unsafe { page(1).write_volatile(8) }), -11);
+    assert_eq!(
+        mprotect(page(1) as usize, PAGE_SIZE, PROT_READ | PROT_WRITE),
+        0
+    );
+    unsafe { page(1).write_volatile(8) };
+    assert_eq!(unsafe { page(1).read_volatile() }, 8);
+
+    // without any access the page faults, and keeps its data for later
+    assert_eq!(mprotect(page(1) as usize, PAGE_SIZE, PROT_NONE), 0);
+    assert_eq!(
+        in_child(|| {
+            unsafe { page(1).read_volatile() };
+        }),
+        -11
+    );
+    assert_eq!(mprotect(page(1) as usize, PAGE_SIZE, PROT_READ), 0);
+    assert_eq!(unsafe { page(1).read_volatile() }, 8);
+
+    // write code, then flip the page to executable and run it
+    let code: [u32; 2] = [
+        0x02a0_0513, // li a0, 42
+        0x0000_8067, // ret
+    ];
+    let jit = page(2) as *mut u32;
+    for (i, inst) in code.iter().enumerate() {
+        unsafe { jit.add(i).write_volatile(*inst) };
+    }
+    assert_eq!(
+        in_child(|| {
+            run(page(2));
+        }),
+        -11
+    );
+    assert_eq!(mprotect(jit as usize, PAGE_SIZE, PROT_READ | PROT_EXEC), 0);
+    unsafe { core::arch::asm!("fence.i") };
+    assert_eq!(run(page(2)), 42);
+    assert_eq!(in_child(|| unsafe { page(2).write_volatile(0) }), -11);
+    println!("mprotect_test passed!");
+    0
+}
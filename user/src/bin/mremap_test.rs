@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, mmap, mremap, munmap, waitpid, EFAULT, EINVAL, ENOMEM, MAP_ANONYMOUS, MAP_PRIVATE,
+    MREMAP_MAYMOVE, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+
+fn map(pages: usize) -> usize {
+    let start = mmap(
+        pages * PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+        0,
+        0,
+    );
+    assert!(start > 0);
+    start as usize
+}
+
+fn words(start: usize, pages: usize) -> &'static mut [usize] {
+    let len = pages * PAGE_SIZE / core::mem::size_of::<usize>();
+    unsafe { core::slice::from_raw_parts_mut(start as *mut usize, len) }
+}
+
+/// Exit code of a child reading the word at `va`.
+fn touch_in_child(va: usize) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        unsafe { (va as *const usize).read_volatile() };
+        user_lib::exit(0);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    exit_code
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let buffer = map(2);
+    assert_eq!(mremap(buffer + 1, PAGE_SIZE, PAGE_SIZE, 0), -EINVAL);
+    assert_eq!(mremap(buffer, PAGE_SIZE, PAGE_SIZE, 2), -EINVAL);
+    assert_eq!(mremap(0x7000_0000, PAGE_SIZE, 2 * PAGE_SIZE, 0), -EFAULT);
+    for (index, word) in words(buffer, 2).iter_mut().enumerate() {
+        *word = index;
+    }
+
+    // nothing after it yet, so it grows in place
+    assert_eq!(
+        mremap(buffer, 2 * PAGE_SIZE, 4 * PAGE_SIZE, 0),
+        buffer as isize
+    );
+    let grown = words(buffer, 4);
+    let half = grown.len() / 2;
+    assert!(grown[..half].iter().enumerate().all(|(i, &w)| w == i));
+    assert!(grown[half..].iter().all(|&w| w == 0));
+
+    // a neighbour blocks it, so it may only move
+    let neighbour = map(1);
+    assert_eq!(neighbour, buffer + 4 * PAGE_SIZE);
+    assert_eq!(mremap(buffer, 4 * PAGE_SIZE, 8 * PAGE_SIZE, 0), -ENOMEM);
+    let moved = mremap(buffer, 4 * PAGE_SIZE, 8 * PAGE_SIZE, MREMAP_MAYMOVE);
+    assert!(moved > 0 && moved as usize != buffer);
+    let moved = moved as usize;
+    let words_moved = words(moved, 8);
+    assert!(words_moved[..half].iter().enumerate().all(|(i, &w)| w == i));
+    assert!(words_moved[half..].iter().all(|&w| w == 0));
+    assert_eq!(touch_in_child(buffer), -11);
+    assert_eq!(touch_in_child(neighbour), 0);
+
+    // shrinking gives the tail back
+    assert_eq!(mremap(moved, 8 * PAGE_SIZE, PAGE_SIZE, 0), moved as isize);
+    assert_eq!(words(moved, 1)[1], 1);
+    assert_eq!(touch_in_child(moved + PAGE_SIZE), -11);
+
+    assert_eq!(munmap(moved, PAGE_SIZE), 0);
+    assert_eq!(munmap(neighbour, PAGE_SIZE), 0);
+    println!("mremap_test passed!");
+    0
+}
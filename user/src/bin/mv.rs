@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+#[macro_use]
+extern crate alloc;
+
+use user_lib::{link, read_dir, strerror, unlink, ENOENT};
+
+/// mv SOURCE DEST, as a new link and the removal of the old one. Directories
+/// cannot be linked, so they cannot be moved either.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 3 {
+        println!("usage: mv SOURCE DEST");
+        return 1;
+    }
+    let (src, dest) = (argv[1], argv[2]);
+    let dest = if read_dir(dest).is_some() {
+        let name = src.rsplit('/').next().unwrap_or(src);
+        format!("{}/{}\0", dest.trim_end_matches('/'), name)
+    } else {
+        format!("{}\0", dest)
+    };
+    if dest.trim_end_matches('\0') == src {
+        return 0;
+    }
+    // an existing destination is replaced
+    let ret = unlink(dest.as_str());
+    if ret < 0 && ret != -ENOENT {
+        println!("mv: {}: {}", argv[2], strerror(ret));
+        return 1;
+    }
+    let ret = link(src, dest.as_str());
+    if ret < 0 {
+        println!("mv: {}: {}", src, strerror(ret));
+        return 1;
+    }
+    unlink(src);
+    0
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, get_time, getppid, kill, nanosleep, sigaction, sleep, waitpid, SignalAction,
+    SignalFlags, TimeSpec, EINTR, EINVAL,
+};
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn usr1_handler(_signum: usize) {
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // sleeps at least as long as asked
+    let start = get_time();
+    let req = TimeSpec {
+        tv_sec: 0,
+        tv_nsec: 30_000_000,
+    };
+    assert_eq!(nanosleep(&req, None), 0);
+    assert!(get_time() - start >= 30);
+
+    let req = TimeSpec {
+        tv_sec: 0,
+        tv_nsec: 1_000_000_000,
+    };
+    assert_eq!(nanosleep(&req, None), -EINVAL);
+    // a negative tv_sec
+    let req = TimeSpec {
+        tv_sec: usize::MAX,
+        tv_nsec: 0,
+    };
+    assert_eq!(nanosleep(&req, None), -EINVAL);
+
+    // a handled signal cuts it short and tells what was left
+    let action = SignalAction::new(usr1_handler as usize, SignalFlags::empty());
+    assert_eq!(sigaction(10, Some(&action), None), 0);
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        kill(getppid() as usize, SignalFlags::SIGUSR1.bits());
+        exit(0);
+    }
+    let req = TimeSpec {
+        tv_sec: 2,
+        tv_nsec: 0,
+    };
+    let mut rem = TimeSpec::default();
+    assert_eq!(nanosleep(&req, Some(&mut rem)), -EINTR);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    assert!(rem.tv_sec == 1 || rem.tv_sec == 0 && rem.tv_nsec > 0);
+    assert!(rem.tv_nsec < 1_000_000_000);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("nanosleep_test passed!");
+    0
+}
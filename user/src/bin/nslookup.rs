@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{resolve, resolve_v6};
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 2 {
+        println!("usage: nslookup <name>");
+        return -1;
+    }
+    let name = argv[1];
+    match resolve(name) {
+        Some(ip) => {
+            let [a, b, c, d] = ip.to_be_bytes();
+            println!("{} has address {}.{}.{}.{}", name, a, b, c, d);
+        }
+        None => {
+            println!("{}: no address found", name);
+            return -1;
+        }
+    }
+    if let Some(ip) = resolve_v6(name) {
+        print!("{} has IPv6 address ", name);
+        for (i, pair) in ip.chunks(2).enumerate() {
+            let sep = if i == 0 { "" } else { ":" };
+            print!("{}{:x}", sep, u16::from_be_bytes([pair[0], pair[1]]));
+        }
+        println!("");
+    }
+    // the second lookup is served from the cache
+    assert_eq!(resolve(name), resolve(name));
+    0
+}
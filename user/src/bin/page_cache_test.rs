@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fork, mmap, munmap, open, read, unlink, waitpid, write, OpenFlags, MAP_SHARED,
+    PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+const FILE: &str = "page_cache_file\0";
+const FILE_LEN: usize = 2 * PAGE_SIZE;
+
+/// too big for the user stack
+static mut DATA: [u8; FILE_LEN] = [0; FILE_LEN];
+
+/// Whole content of the file through read().
+fn read_file() -> &'static [u8] {
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let data = unsafe { &mut DATA };
+    assert_eq!(read(fd as usize, data), FILE_LEN as isize);
+    close(fd as usize);
+    data
+}
+
+/// Overwrite the start of the file through write().
+fn write_file(bytes: &[u8]) {
+    let fd = open(FILE, OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, bytes), bytes.len() as isize);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let data = unsafe { &mut DATA };
+    data.fill(b'.');
+    assert_eq!(write(fd as usize, data), FILE_LEN as isize);
+    close(fd as usize);
+
+    let fd = open(FILE, OpenFlags::RDWR) as usize;
+    let start = mmap(FILE_LEN, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    assert!(start > 0);
+    let shared = unsafe { core::slice::from_raw_parts_mut(start as *mut u8, FILE_LEN) };
+
+    // stores show in read() before any msync
+    shared[1] = b'm';
+    shared[PAGE_SIZE + 1] = b'n';
+    let data = read_file();
+    assert_eq!((data[1], data[PAGE_SIZE + 1]), (b'm', b'n'));
+
+    // write() shows in the mapping
+    write_file(b"w");
+    assert_eq!((shared[0], shared[1]), (b'w', b'm'));
+
+    // so do the stores of a child sharing it
+    let pid = fork();
+    if pid == 0 {
+        shared[2] = b'c';
+        user_lib::exit(0);
+    }
+    let mut exit_code = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 0);
+    assert_eq!(shared[2], b'c');
+    assert_eq!(read_file()[2], b'c');
+
+    // and everything is in the file once unmapped
+    assert_eq!(munmap(start as usize, FILE_LEN), 0);
+    close(fd);
+    assert_eq!(&read_file()[..3], b"wmc");
+    assert_eq!(read_file()[PAGE_SIZE + 1], b'n');
+    assert_eq!(unlink(FILE), 0);
+    println!("page_cache_test passed!");
+    0
+}
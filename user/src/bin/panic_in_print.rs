@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::fmt::{self, Display, Formatter};
+
+/// Panics halfway through being printed, with stdout locked.
+struct Faulty;
+
+impl Display for Faulty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "half a line")?;
+        panic!("formatting failed");
+    }
+}
+
+/// The panic report prints with stdout still locked by this thread, it
+/// must go out and the process die by SIGABRT rather than hang.
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("{}", Faulty);
+    0
+}
@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{close, fork, pipe, read, wait, write};
+use user_lib::{close, fork, pipe, pipe_pair, read, wait, write};
 
 static STR: &str = "Hello, world!";
 
@@ -38,6 +38,14 @@ pub fn main() -> i32 {
         let mut child_exit_code: i32 = 0;
         wait(&mut child_exit_code);
         assert_eq!(child_exit_code, 0);
+        // both ends returned in registers reuse the freed fds
+        let (read_end, write_end) = pipe_pair().unwrap();
+        assert_eq!((read_end, write_end), (3, 4));
+        assert_eq!(write(write_end, STR.as_bytes()), STR.len() as isize);
+        let mut buffer = [0u8; 32];
+        assert_eq!(read(read_end, &mut buffer), STR.len() as isize);
+        close(read_end);
+        close(write_end);
         println!("pipetest passed!");
         0
     }
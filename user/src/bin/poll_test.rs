@@ -0,0 +1,154 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    close, exit, fork, get_time, getpid, kill, pipe, poll, ppoll, pselect, sigaction, sigprocmask,
+    sleep, wait, write, FdSet, PollEvents, PollFd, SignalAction, SignalFlags, TimeSpec, EINTR,
+    SIG_BLOCK, SIG_SETMASK,
+};
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn usr1_handler(_signum: usize) {
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+fn poll_pipe() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+    let mut pollfds = [
+        PollFd::new(read_fd, PollEvents::POLLIN),
+        PollFd::new(write_fd, PollEvents::POLLOUT),
+    ];
+    // only the write end is ready
+    assert_eq!(poll(&mut pollfds, 0), 1);
+    assert!(pollfds[0].revents().is_empty());
+    assert_eq!(pollfds[1].revents(), PollEvents::POLLOUT);
+
+    // nothing to read, the timeout expires
+    let start = get_time();
+    assert_eq!(poll(&mut pollfds[..1], 50), 0);
+    assert!(get_time() - start >= 50);
+
+    assert_eq!(write(write_fd, b"x"), 1);
+    assert_eq!(poll(&mut pollfds, -1), 2);
+    assert_eq!(pollfds[0].revents(), PollEvents::POLLIN);
+
+    // a closed write end hangs up, a closed fd is invalid
+    close(write_fd);
+    assert_eq!(poll(&mut pollfds, 0), 2);
+    assert!(pollfds[0].revents().contains(PollEvents::POLLHUP));
+    assert_eq!(pollfds[1].revents(), PollEvents::POLLNVAL);
+    close(read_fd);
+}
+
+fn select_pipe() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let (read_fd, write_fd) = (fds[0], fds[1]);
+    let mut readfds = FdSet::default();
+    readfds.insert(read_fd);
+    let zero = TimeSpec::default();
+    assert_eq!(
+        pselect(
+            read_fd + 1,
+            Some(&mut readfds),
+            None,
+            None,
+            Some(&zero),
+            None
+        ),
+        0
+    );
+    assert!(!readfds.contains(read_fd));
+
+    // woken by a write of another process
+    let pid = fork();
+    if pid == 0 {
+        sleep(20);
+        write(write_fd, b"x");
+        exit(0);
+    }
+    let mut readfds = FdSet::default();
+    readfds.insert(read_fd);
+    let mut writefds = FdSet::default();
+    writefds.insert(write_fd);
+    let nfds = read_fd.max(write_fd) + 1;
+    assert_eq!(
+        pselect(nfds, None, Some(&mut writefds), None, None, None),
+        1
+    );
+    assert!(writefds.contains(write_fd));
+    assert_eq!(pselect(nfds, Some(&mut readfds), None, None, None, None), 1);
+    assert!(readfds.contains(read_fd));
+    let mut exit_code = 0;
+    wait(&mut exit_code);
+    close(read_fd);
+    close(write_fd);
+}
+
+fn mask_swap() {
+    let pid = getpid() as usize;
+    let action = SignalAction::new(usr1_handler as usize, SignalFlags::empty());
+    assert_eq!(sigaction(10, Some(&action), None), 0);
+    sigprocmask(SIG_BLOCK, SignalFlags::SIGUSR1);
+    kill(pid, SignalFlags::SIGUSR1.bits());
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut pollfds = [PollFd::new(fds[0], PollEvents::POLLIN)];
+
+    // still blocked during the wait, it times out
+    let timeout = TimeSpec {
+        tv_sec: 0,
+        tv_nsec: 10_000_000,
+    };
+    assert_eq!(
+        ppoll(&mut pollfds, Some(&timeout), Some(SignalFlags::SIGUSR1)),
+        0
+    );
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 0);
+
+    // unblocked for the wait only: the pending signal interrupts it
+    assert_eq!(
+        ppoll(&mut pollfds, None, Some(SignalFlags::empty())),
+        -EINTR
+    );
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    let old = sigprocmask(SIG_SETMASK, SignalFlags::empty());
+    assert!(old.contains(SignalFlags::SIGUSR1));
+
+    // the same with select
+    sigprocmask(SIG_BLOCK, SignalFlags::SIGUSR1);
+    kill(pid, SignalFlags::SIGUSR1.bits());
+    let mut readfds = FdSet::default();
+    readfds.insert(fds[0]);
+    assert_eq!(
+        pselect(
+            fds[0] + 1,
+            Some(&mut readfds),
+            None,
+            None,
+            None,
+            Some(SignalFlags::empty())
+        ),
+        -EINTR
+    );
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+    assert!(sigprocmask(SIG_SETMASK, SignalFlags::empty()).contains(SignalFlags::SIGUSR1));
+    close(fds[0]);
+    close(fds[1]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    poll_pipe();
+    select_pipe();
+    mask_swap();
+    println!("poll_test passed!");
+    0
+}
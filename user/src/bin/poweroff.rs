@@ -0,0 +1,14 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::poweroff;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let err = poweroff();
+    println!("poweroff: {}", user_lib::strerror(err));
+    1
+}
@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{close, get_time, getpid, open, read, write, OpenFlags};
+
+fn control(command: &str) {
+    let fd = open("/proc/profile\0", OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(
+        write(fd as usize, command.as_bytes()),
+        command.len() as isize
+    );
+    close(fd as usize);
+}
+
+fn report() -> String {
+    let fd = open("/proc/profile\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut text = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        text.extend_from_slice(&buf[..len as usize]);
+    }
+    close(fd as usize);
+    String::from_utf8(text).unwrap()
+}
+
+/// Spin for a few ticks of the profiler and find them in /proc/profile.
+#[no_mangle]
+pub fn main() -> i32 {
+    control("2\n");
+    let start = get_time();
+    let mut sum = 0usize;
+    while get_time() - start < 300 {
+        sum = sum.wrapping_add(1);
+    }
+    control("0\n");
+    let text = report();
+    let me = format!("pid {};", getpid());
+    let mut mine = 0;
+    for line in text.lines() {
+        let (stack, count) = line.rsplit_once(' ').unwrap();
+        let count: usize = count.parse().unwrap();
+        assert!(count > 0);
+        if stack.starts_with(me.as_str()) {
+            mine += count;
+        }
+    }
+    assert!(
+        mine > 0,
+        "no samples of pid {} after {} loops",
+        getpid(),
+        sum
+    );
+    // stopped, so nothing comes in any more
+    assert_eq!(report(), text);
+    println!("profile_test passed!");
+    0
+}
@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::process_list;
+
+/// ps, every live process
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut processes = process_list();
+    processes.sort_by_key(|process| process.pid);
+    println!("  PID  PPID  PGID S THR CMD");
+    for process in processes.iter() {
+        println!(
+            "{:5} {:5} {:5} {} {:3} {}",
+            process.pid,
+            process.ppid,
+            process.pgid,
+            process.state as char,
+            process.threads,
+            process.name()
+        );
+    }
+    0
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exec, exit, fork, ptrace, waitpid, wifstopped, wstopsig, UserRegs, PTRACE_CONT, PTRACE_GETREGS,
+    PTRACE_PEEKTEXT, PTRACE_SINGLESTEP, PTRACE_TRACEME,
+};
+
+const SIGTRAP: usize = 5;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(ptrace(PTRACE_TRACEME, 0, 0, 0), 0);
+        // stops with SIGTRAP before the first instruction
+        exec("hello_world\0", &[core::ptr::null::<u8>()]);
+        exit(-1);
+    }
+    let pid = pid as usize;
+    let mut status: i32 = 0;
+    assert_eq!(waitpid(pid, &mut status), pid as isize);
+    assert!(wifstopped(status) && wstopsig(status) == SIGTRAP);
+    let mut regs: UserRegs = [0; 32];
+    assert_eq!(
+        ptrace(PTRACE_GETREGS, pid, 0, regs.as_mut_ptr() as usize),
+        0
+    );
+    let entry = regs[0];
+    let mut inst: usize = 0;
+    assert_eq!(
+        ptrace(PTRACE_PEEKTEXT, pid, entry, &mut inst as *mut _ as usize),
+        0
+    );
+    assert_ne!(inst, 0);
+    // execute exactly one instruction
+    assert_eq!(ptrace(PTRACE_SINGLESTEP, pid, 0, 0), 0);
+    assert_eq!(waitpid(pid, &mut status), pid as isize);
+    assert!(wifstopped(status) && wstopsig(status) == SIGTRAP);
+    assert_eq!(
+        ptrace(PTRACE_GETREGS, pid, 0, regs.as_mut_ptr() as usize),
+        0
+    );
+    assert_ne!(regs[0], entry);
+    // the code is intact after stepping
+    let mut inst_after: usize = 0;
+    ptrace(
+        PTRACE_PEEKTEXT,
+        pid,
+        entry,
+        &mut inst_after as *mut _ as usize,
+    );
+    assert_eq!(inst, inst_after);
+    assert_eq!(ptrace(PTRACE_CONT, pid, 0, 0), 0);
+    assert_eq!(waitpid(pid, &mut status), pid as isize);
+    assert_eq!(status, 0);
+    println!("ptrace_test passed!");
+    0
+}
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, openpty, read, write};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(openpty(&mut fds), 0);
+    let (master, slave) = (fds[0], fds[1]);
+    // type a line with a typo on the master side
+    write(master, b"hellp\x7fo\r");
+    let mut buf = [0u8; 32];
+    // the slave sees the cooked line
+    let len = read(slave, &mut buf) as usize;
+    assert_eq!(&buf[..len], b"hello\n");
+    // the master sees the echo
+    let len = read(master, &mut buf) as usize;
+    assert_eq!(&buf[..len], b"hellp\x08 \x08o\n");
+    // output of the slave goes to the master untouched
+    write(slave, b"world");
+    let len = read(master, &mut buf) as usize;
+    assert_eq!(&buf[..len], b"world");
+    close(slave);
+    assert_eq!(read(master, &mut buf), 0);
+    close(master);
+    println!("pty_test passed!");
+    0
+}
@@ -4,12 +4,14 @@
 #[macro_use]
 extern crate user_lib;
 use oorandom;
+use user_lib::getrandom;
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("random num  program!");
-    let seed = 4;
-    let mut rng = oorandom::Rand32::new(seed);
+    let mut seed = [0u8; 8];
+    assert_eq!(getrandom(&mut seed, 0), 8);
+    let mut rng = oorandom::Rand32::new(u64::from_le_bytes(seed));
     println!("OORandom: Random number 32bit: {}", rng.rand_i32());
     println!("OORandom: Random number range: {}", rng.rand_range(1..100));
     0
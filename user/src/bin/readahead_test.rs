@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, unlink, write, OpenFlags};
+
+const FILE: &str = "readahead_file\0";
+const FILE_LEN: usize = 96 * 1024;
+const CHUNK: usize = 1000;
+
+fn byte_at(offset: usize, round: usize) -> u8 {
+    (offset / 7 + offset % 251 + round) as u8
+}
+
+/// Write `[start, end)` of the file with the bytes of `round`.
+fn fill(fd: usize, start: usize, end: usize, round: usize) {
+    let mut chunk = [0u8; CHUNK];
+    let mut offset = start;
+    while offset < end {
+        let len = CHUNK.min(end - offset);
+        for (i, byte) in chunk[..len].iter_mut().enumerate() {
+            *byte = byte_at(offset + i, round);
+        }
+        assert_eq!(write(fd, &chunk[..len]), len as isize);
+        offset += len;
+    }
+}
+
+/// Read the file from `fd` up to `end` in odd chunks, checking every byte
+/// with `expected`.
+fn check(fd: usize, start: usize, end: usize, expected: impl Fn(usize) -> u8) {
+    let mut chunk = [0u8; CHUNK];
+    let mut offset = start;
+    while offset < end {
+        let len = CHUNK.min(end - offset);
+        assert_eq!(read(fd, &mut chunk[..len]), len as isize);
+        for (i, &byte) in chunk[..len].iter().enumerate() {
+            assert_eq!(byte, expected(offset + i), "offset {}", offset + i);
+        }
+        offset += len;
+    }
+}
+
+/// A new file, none of its pages cached.
+fn create_file() {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    fill(fd as usize, 0, FILE_LEN, 0);
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // one long sequential read
+    create_file();
+    let reader = open(FILE, OpenFlags::RDONLY) as usize;
+    check(reader, 0, FILE_LEN, |offset| byte_at(offset, 0));
+    let mut rest = [0u8; 16];
+    assert_eq!(read(reader, &mut rest), 0);
+    close(reader);
+    assert_eq!(unlink(FILE), 0);
+
+    // what is written ahead of a reader is what it reads, even if the disk
+    // has read those blocks ahead already
+    create_file();
+    let reader = open(FILE, OpenFlags::RDONLY) as usize;
+    check(reader, 0, FILE_LEN / 2, |offset| byte_at(offset, 0));
+    let writer = open(FILE, OpenFlags::WRONLY) as usize;
+    // nothing seeks, so the writer rewrites the first half as it was and
+    // the part ahead of the reader anew
+    fill(writer, 0, FILE_LEN / 2, 0);
+    fill(writer, FILE_LEN / 2, FILE_LEN, 1);
+    close(writer);
+    check(reader, FILE_LEN / 2, FILE_LEN, |offset| byte_at(offset, 1));
+    close(reader);
+
+    assert_eq!(unlink(FILE), 0);
+    println!("readahead_test passed!");
+    0
+}
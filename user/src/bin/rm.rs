@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{rmdir, strerror, unlink, EISDIR};
+
+/// rm [-d] FILE..., `-d` removes empty directories as well
+#[no_mangle]
+pub fn main(_argc: usize, argv: &[&str]) -> i32 {
+    let (dirs, paths) = match argv.get(1) {
+        Some(&"-d") => (true, &argv[2..]),
+        _ => (false, &argv[1..]),
+    };
+    if paths.is_empty() {
+        println!("usage: rm [-d] FILE...");
+        return 1;
+    }
+    let mut exit_code = 0;
+    for path in paths {
+        let mut ret = unlink(path);
+        if dirs && ret == -EISDIR {
+            ret = rmdir(path);
+        }
+        if ret < 0 {
+            println!("rm: {}: {}", path, strerror(ret));
+            exit_code = 1;
+        }
+    }
+    exit_code
+}
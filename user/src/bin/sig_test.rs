@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, getpid, kill, sigaction, sigprocmask, sigsuspend, sigwait, wait, SignalAction,
+    SignalFlags, EINTR, EINVAL, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK,
+};
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn usr1_handler(signum: usize) {
+    assert_eq!(signum, 10);
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = getpid() as usize;
+    let action = SignalAction::new(usr1_handler as usize, SignalFlags::empty());
+    assert_eq!(sigaction(10, Some(&action), None), 0);
+    // SIGKILL cannot be caught
+    assert_eq!(sigaction(9, Some(&action), None), -EINVAL);
+
+    // delivered on the way back from kill
+    kill(pid, SignalFlags::SIGUSR1.bits());
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+
+    // stays pending while blocked
+    sigprocmask(SIG_BLOCK, SignalFlags::SIGUSR1);
+    kill(pid, SignalFlags::SIGUSR1.bits());
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    let old = sigprocmask(SIG_UNBLOCK, SignalFlags::SIGUSR1);
+    assert!(old.contains(SignalFlags::SIGUSR1));
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+
+    // synchronous consumption
+    sigprocmask(SIG_BLOCK, SignalFlags::SIGUSR2);
+    kill(pid, SignalFlags::SIGUSR2.bits());
+    assert_eq!(sigwait(SignalFlags::SIGUSR2), 12);
+
+    // wait for a signal sent by another process
+    sigprocmask(SIG_SETMASK, SignalFlags::SIGUSR1);
+    if fork() == 0 {
+        kill(pid, SignalFlags::SIGUSR1.bits());
+        exit(0);
+    }
+    assert_eq!(sigsuspend(SignalFlags::empty()), -EINTR);
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 3);
+    // the mask is back after sigsuspend
+    assert!(sigprocmask(SIG_SETMASK, SignalFlags::empty()).contains(SignalFlags::SIGUSR1));
+    let mut exit_code: i32 = 0;
+    wait(&mut exit_code);
+    println!("sig_test passed!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, sigaction, sigaltstack, waitpid, SignalAction, SignalFlags, SignalStack,
+    SA_ONSTACK, SS_ONSTACK,
+};
+
+const ALT_STACK_SIZE: usize = 8192;
+static mut ALT_STACK: [u8; ALT_STACK_SIZE] = [0; ALT_STACK_SIZE];
+
+extern "C" fn segv_handler(_signum: usize) {
+    let mut stack = SignalStack::new(unsafe { &mut ALT_STACK });
+    sigaltstack(None, Some(&mut stack));
+    // the overflowed stack is unusable, so we never return
+    exit(if stack.flags & SS_ONSTACK != 0 {
+        42
+    } else {
+        -1
+    });
+}
+
+fn recurse(depth: usize) -> usize {
+    let frame = [depth; 64];
+    core::hint::black_box(&frame);
+    recurse(depth + 1) + frame[depth % 64]
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        let stack = SignalStack::new(unsafe { &mut ALT_STACK });
+        assert_eq!(sigaltstack(Some(&stack), None), 0);
+        let mut action = SignalAction::new(segv_handler as usize, SignalFlags::empty());
+        action.flags = SA_ONSTACK;
+        assert_eq!(sigaction(11, Some(&action), None), 0);
+        // overflow the stack into the guard page
+        recurse(0);
+        unreachable!();
+    }
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    assert_eq!(exit_code, 42);
+    println!("sigaltstack_test passed!");
+    0
+}
@@ -4,27 +4,36 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exit, fork, get_time, sleep, waitpid};
+use user_lib::sleep;
 
-fn sleepy() {
-    let time: usize = 100;
-    for i in 0..5 {
-        sleep(time);
-        println!("sleep {} x {} msecs.", i + 1, time);
+/// sleep SECONDS, fractions such as 0.5 included
+fn parse_ms(arg: &str) -> Option<usize> {
+    let (secs, frac) = arg.split_once('.').unwrap_or((arg, ""));
+    let secs: usize = if secs.is_empty() {
+        0
+    } else {
+        secs.parse().ok()?
+    };
+    let mut ms = 0;
+    for (i, digit) in frac.bytes().take(3).enumerate() {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        ms += (digit - b'0') as usize * [100, 10, 1][i];
     }
-    exit(0);
+    Some(secs * 1000 + ms)
 }
 
 #[no_mangle]
-pub fn main() -> i32 {
-    let current_time = get_time();
-    let pid = fork();
-    let mut exit_code: i32 = 0;
-    if pid == 0 {
-        sleepy();
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    match argv.get(1).and_then(|arg| parse_ms(arg)) {
+        Some(ms) if argc == 2 => {
+            sleep(ms);
+            0
+        }
+        _ => {
+            println!("usage: sleep SECONDS");
+            1
+        }
     }
-    assert!(waitpid(pid as usize, &mut exit_code) == pid && exit_code == 0);
-    println!("use {} msecs.", get_time() - current_time);
-    println!("sleep pass.");
-    0
 }
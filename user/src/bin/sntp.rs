@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_settime, close, connect, exit, fork, get_time, kill, read, sleep, waitpid, waitpid_nb,
+    write, SignalFlags, TimeSpec, CLOCK_REALTIME,
+};
+
+/// time.google.com, reached through QEMU's user networking
+const NTP_SERVER: u32 = 216 << 24 | 239 << 16 | 35 << 8;
+const NTP_PORT: u16 = 123;
+const LOCAL_PORT: u16 = 4123;
+const PACKET_LEN: usize = 48;
+/// seconds between 1900-01-01 (NTP) and 1970-01-01 (Unix)
+const NTP_UNIX_DELTA: u64 = 2_208_988_800;
+const TIMEOUT_MS: isize = 3000;
+
+fn be_u32(bytes: &[u8]) -> u64 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64
+}
+
+/// Ask the server once and set the wall clock from its transmit timestamp.
+fn query() -> i32 {
+    let fd = connect(NTP_SERVER, LOCAL_PORT, NTP_PORT);
+    if fd < 0 {
+        println!("[sntp] no network");
+        return -1;
+    }
+    let fd = fd as usize;
+    let mut packet = [0u8; PACKET_LEN];
+    // LI = 0, VN = 4, Mode = 3 (client)
+    packet[0] = 0x23;
+    write(fd, &packet);
+    let len = read(fd, &mut packet);
+    close(fd);
+    // Mode = 4 (server), and a server that is synchronized
+    if len < PACKET_LEN as isize || packet[0] & 0x7 != 4 || packet[1] == 0 {
+        println!("[sntp] bad reply");
+        return -1;
+    }
+    let seconds = be_u32(&packet[40..44]);
+    if seconds < NTP_UNIX_DELTA {
+        println!("[sntp] bad timestamp");
+        return -1;
+    }
+    let time = TimeSpec {
+        tv_sec: (seconds - NTP_UNIX_DELTA) as usize,
+        tv_nsec: ((be_u32(&packet[44..48]) * 1_000_000_000) >> 32) as usize,
+    };
+    if clock_settime(CLOCK_REALTIME, &time) != 0 {
+        println!("[sntp] clock_settime failed");
+        return -1;
+    }
+    println!("[sntp] wall clock set to {}s since the epoch", time.tv_sec);
+    0
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exit(query());
+    }
+    let pid = pid as usize;
+    let start = get_time();
+    let mut exit_code = 0;
+    while get_time() - start < TIMEOUT_MS {
+        if waitpid_nb(pid, &mut exit_code) as usize == pid {
+            return exit_code;
+        }
+        sleep(50);
+    }
+    println!("[sntp] no reply, keeping the clock from the RTC");
+    kill(pid, SignalFlags::SIGKILL.bits());
+    waitpid(pid, &mut exit_code);
+    -1
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstatat, open, statx, unlinkat, write, OpenFlags, Stat, Statx, AT_EMPTY_PATH, AT_FDCWD,
+    STATX_BASIC_STATS, STATX_SIZE, STATX_TYPE, S_IFCHR, S_IFDIR, S_IFMT, S_IFREG,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("statx_test_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"hello statx"), 11);
+
+    // statx reports the same file fstatat does
+    let mut stx = Statx::default();
+    assert_eq!(
+        statx(
+            AT_FDCWD,
+            "statx_test_file\0",
+            0,
+            STATX_BASIC_STATS,
+            &mut stx
+        ),
+        0
+    );
+    assert_eq!(
+        stx.mask & (STATX_TYPE | STATX_SIZE),
+        STATX_TYPE | STATX_SIZE
+    );
+    assert_eq!(stx.mode as u32 & S_IFMT, S_IFREG);
+    assert_eq!(stx.size, 11);
+    assert_eq!(stx.nlink, 1);
+    let mut st = Stat::default();
+    assert_eq!(fstatat(AT_FDCWD, "statx_test_file\0", &mut st, 0), 0);
+    assert_eq!(stx.ino, st.ino);
+    assert_eq!(stx.blocks, st.blocks as u64);
+
+    // an empty path with AT_EMPTY_PATH looks at the fd itself
+    let mut stx = Statx::default();
+    assert_eq!(statx(fd, "\0", AT_EMPTY_PATH, STATX_SIZE, &mut stx), 0);
+    assert_eq!(stx.size, 11);
+    assert!(statx(fd, "\0", 0, STATX_SIZE, &mut stx) < 0);
+    close(fd as usize);
+
+    assert_eq!(statx(AT_FDCWD, "/\0", 0, STATX_TYPE, &mut stx), 0);
+    assert_eq!(stx.mode as u32 & S_IFMT, S_IFDIR);
+    assert_eq!(statx(1, "\0", AT_EMPTY_PATH, STATX_TYPE, &mut stx), 0);
+    assert_eq!(stx.mode as u32 & S_IFMT, S_IFCHR);
+    // a device has no size to report
+    assert_eq!(stx.mask & STATX_SIZE, 0);
+    // STATX__RESERVED
+    assert!(statx(AT_FDCWD, "/\0", 0, 0x8000_0000, &mut stx) < 0);
+
+    assert_eq!(unlinkat(AT_FDCWD, "statx_test_file\0", 0), 0);
+    assert!(statx(AT_FDCWD, "statx_test_file\0", 0, STATX_TYPE, &mut stx) < 0);
+    println!("statx_test passed!");
+    0
+}
@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::{DrawTarget, Point, RgbColor, Size};
+use embedded_graphics::primitives::Rectangle;
+use user_lib::{
+    fcntl, ioctl, read, OpenFlags, Surface, SurfaceEvent, EAGAIN, EFAULT, EINVAL, ENOTTY, F_SETFL,
+    SURFACE_DAMAGE, VIRTGPU_XRES, VIRTGPU_YRES,
+};
+
+/// `struct surface_damage`, to hand the kernel rectangles `Surface::damage`
+/// would clip.
+#[repr(C)]
+struct Damage {
+    pixels: usize,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let size = Size::new(64, 48);
+    assert_eq!(Surface::new(0, 0, Size::new(0, 48)).err(), Some(-EINVAL));
+    assert_eq!(
+        Surface::new(0, 0, Size::new(VIRTGPU_XRES + 1, 48)).err(),
+        Some(-EINVAL)
+    );
+    // not a pixel of it on the screen
+    assert_eq!(Surface::new(-64, 0, size).err(), Some(-EINVAL));
+    assert_eq!(
+        Surface::new(0, VIRTGPU_YRES as i32, size).err(),
+        Some(-EINVAL)
+    );
+
+    let mut below = Surface::new(100, 100, size).unwrap();
+    let mut above = Surface::new(132, 124, size).unwrap();
+    below.clear(Rgb888::RED).unwrap();
+    above.clear(Rgb888::GREEN).unwrap();
+    assert_eq!(below.damage_all(), 0);
+    assert_eq!(above.damage_all(), 0);
+    // clipped to the surface by the library
+    assert_eq!(
+        above.damage(&Rectangle::new(Point::new(32, 24), Size::new(100, 100))),
+        0
+    );
+
+    let mut damage = Damage {
+        pixels: 0,
+        x: 60,
+        y: 0,
+        width: 8,
+        height: 1,
+    };
+    assert_eq!(
+        ioctl(above.fd(), SURFACE_DAMAGE, &damage as *const _ as usize),
+        -EINVAL
+    );
+    damage.x = 0;
+    assert_eq!(
+        ioctl(above.fd(), SURFACE_DAMAGE, &damage as *const _ as usize),
+        -EFAULT
+    );
+    assert_eq!(ioctl(above.fd(), 0x5401, 0), -ENOTTY);
+
+    // no input unless someone uses the graphical window
+    let nonblock = OpenFlags::NONBLOCK.bits() as usize;
+    assert_eq!(fcntl(below.fd(), F_SETFL, nonblock), 0);
+    let mut buf = [0u8; core::mem::size_of::<SurfaceEvent>()];
+    let got = read(below.fd(), &mut buf);
+    assert!(got == -EAGAIN || got == buf.len() as isize);
+
+    drop(above);
+    drop(below);
+    println!("surface_test passed!");
+    0
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use user_lib::{Condvar, Mutex, Semaphore, Thread};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 1000;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // results come back through join
+    let squares: Vec<_> = (0..THREADS).map(|i| Thread::spawn(move || i * i)).collect();
+    let squares: Vec<usize> = squares.into_iter().map(|handle| handle.join()).collect();
+    assert_eq!(squares, [0, 1, 4, 9]);
+
+    // the mutex keeps every increment
+    let counter = Arc::new(Mutex::new(0usize));
+    let workers: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let counter = counter.clone();
+            Thread::spawn(move || {
+                for _ in 0..ROUNDS {
+                    *counter.lock() += 1;
+                }
+            })
+        })
+        .collect();
+    workers.into_iter().for_each(|handle| handle.join());
+    assert_eq!(*counter.lock(), THREADS * ROUNDS);
+
+    // the condvar hands a value over
+    let pair = Arc::new((Mutex::new(None), Condvar::new()));
+    let producer = {
+        let pair = pair.clone();
+        Thread::spawn(move || {
+            let (slot, ready) = &*pair;
+            *slot.lock() = Some(42);
+            ready.notify_one();
+        })
+    };
+    let (slot, ready) = &*pair;
+    let value = ready.wait_while(slot.lock(), |value| value.is_none());
+    assert_eq!(*value, Some(42));
+    drop(value);
+    producer.join();
+
+    // the semaphore orders two threads
+    let sem = Arc::new(Semaphore::new(0));
+    let waiter = {
+        let sem = sem.clone();
+        Thread::spawn(move || {
+            sem.down();
+            "woken"
+        })
+    };
+    sem.up();
+    assert_eq!(waiter.join(), "woken");
+    println!("sync_wrappers_test passed!");
+    0
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, exit, fork, mprotect, waitpid, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 0x1000;
+const NAME: &str = "text_share_test\0";
+const COPIES: usize = 4;
+
+/// in the read-only segment, which every copy of the program shares
+static GREETING: [u8; 8] = *b"original";
+
+fn greeting() -> [u8; 8] {
+    unsafe { core::ptr::read_volatile(&GREETING) }
+}
+
+/// Run a fresh copy of the program which checks the greeting.
+fn spawn_check() -> usize {
+    let pid = fork();
+    if pid == 0 {
+        let args = [NAME.as_ptr(), "check\0".as_ptr(), core::ptr::null::<u8>()];
+        exec(NAME, &args);
+        exit(-1);
+    }
+    pid as usize
+}
+
+#[no_mangle]
+pub fn main(argc: usize, _argv: &[&str]) -> i32 {
+    if argc > 1 {
+        return if &greeting() == b"original" { 0 } else { 1 };
+    }
+    // a writable copy of the page does not change the page of the file
+    let pid = fork();
+    if pid == 0 {
+        let page = GREETING.as_ptr() as usize & !(PAGE_SIZE - 1);
+        assert_eq!(mprotect(page, PAGE_SIZE, PROT_READ | PROT_WRITE), 0);
+        unsafe { (GREETING.as_ptr() as *mut u8).write_volatile(b'O') };
+        assert_eq!(&greeting(), b"Original");
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(&greeting(), b"original");
+
+    // neither does it for copies running at once
+    let pids: [usize; COPIES] = core::array::from_fn(|_| spawn_check());
+    for pid in pids {
+        assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+        assert_eq!(exit_code, 0);
+    }
+    println!("text_share_test passed!");
+    0
+}
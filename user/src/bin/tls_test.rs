@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+#![feature(thread_local)]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{exit, thread_create, waittid};
+
+#[thread_local]
+static mut INITIALIZED: usize = 42;
+#[thread_local]
+static mut ZEROED: usize = 0;
+
+/// Every thread must see the initial values, whatever the others did.
+fn bump(rounds: usize) -> i32 {
+    unsafe {
+        if INITIALIZED != 42 || ZEROED != 0 {
+            return -1;
+        }
+        for _ in 0..rounds {
+            INITIALIZED += 1;
+            ZEROED += 2;
+        }
+        if INITIALIZED != 42 + rounds || ZEROED != 2 * rounds {
+            return -2;
+        }
+    }
+    0
+}
+
+fn thread_main(rounds: usize) -> ! {
+    exit(bump(rounds))
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let tids: Vec<_> = (1..=4)
+        .map(|rounds| thread_create(thread_main as usize, rounds * 100))
+        .collect();
+    assert_eq!(bump(7), 0, "main thread");
+    for tid in tids {
+        assert_eq!(waittid(tid as usize), 0, "thread {}", tid);
+    }
+    println!("tls_test passed!");
+    0
+}
@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, getpid, open, read, write, OpenFlags};
+
+/// `struct trace_record` of the kernel
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct TraceRecord {
+    time_ns: u64,
+    point: u32,
+    hart: u32,
+    args: [usize; 2],
+}
+
+const SYSCALL_ENTER: u32 = 3;
+const SYSCALL_EXIT: u32 = 4;
+const SYSCALL_GETPID: usize = 172;
+
+fn control(command: &str) {
+    let fd = open("/proc/trace_events\0", OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(
+        write(fd as usize, command.as_bytes()),
+        command.len() as isize
+    );
+    close(fd as usize);
+}
+
+/// Take out every record, calling `f` on each.
+fn drain(mut f: impl FnMut(&TraceRecord)) {
+    let fd = open("/proc/trace\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut records = [TraceRecord::default(); 16];
+    let size = core::mem::size_of::<TraceRecord>();
+    loop {
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(records.as_mut_ptr() as *mut u8, records.len() * size)
+        };
+        let len = read(fd as usize, buf);
+        assert!(len >= 0 && len as usize % size == 0);
+        if len == 0 {
+            break;
+        }
+        records[..len as usize / size].iter().for_each(&mut f);
+    }
+    close(fd as usize);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    drain(|_| {});
+    control("syscall_enter 1\nsyscall_exit 1\n");
+    let pid = getpid();
+    control("all 0\n");
+    let (mut entered, mut returned) = (false, false);
+    drain(|record| match (record.point, record.args[0]) {
+        (SYSCALL_ENTER, SYSCALL_GETPID) => entered = true,
+        (SYSCALL_EXIT, SYSCALL_GETPID) => {
+            assert!(entered);
+            assert_eq!(record.args[1], pid as usize);
+            returned = true;
+        }
+        (SYSCALL_ENTER | SYSCALL_EXIT, _) => {}
+        (point, _) => panic!("tracepoint {} was off", point),
+    });
+    assert!(entered && returned);
+    // nothing is recorded while all are off
+    getpid();
+    drain(|record| panic!("tracepoint {} was off", record.point));
+    println!("trace_test passed!");
+    0
+}
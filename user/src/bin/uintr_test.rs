@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, get_time, getpid, uintr_notify, uintr_register, uintr_unregister, wait, yield_,
+    ESRCH, UINTR_NOTIFY, UINTR_TIMER,
+};
+
+static NOTIFIED: AtomicUsize = AtomicUsize::new(0);
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn handler(events: usize) {
+    if events & UINTR_NOTIFY != 0 {
+        NOTIFIED.fetch_add(1, Ordering::SeqCst);
+    }
+    if events & UINTR_TIMER != 0 {
+        TICKS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = getpid() as usize;
+    assert_eq!(uintr_register(handler, UINTR_NOTIFY), 0);
+
+    // taken on the way back from the syscall, which still returns its value
+    assert_eq!(uintr_notify(pid), 0);
+    assert_eq!(NOTIFIED.load(Ordering::SeqCst), 1);
+
+    // from another process
+    let start = get_time();
+    if fork() == 0 {
+        exit(if uintr_notify(pid) == 0 { 0 } else { 1 });
+    }
+    while NOTIFIED.load(Ordering::SeqCst) < 2 {
+        yield_();
+    }
+    println!("notification from the child after {}ms", get_time() - start);
+    let mut exit_code = 0;
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+
+    // timer ticks interrupt a busy loop
+    assert_eq!(uintr_register(handler, UINTR_NOTIFY | UINTR_TIMER), 0);
+    let start = get_time();
+    while TICKS.load(Ordering::SeqCst) == 0 {
+        assert!(get_time() - start < 1000, "no timer upcall");
+    }
+
+    assert_eq!(uintr_unregister(), 0);
+    uintr_notify(pid);
+    assert_eq!(NOTIFIED.load(Ordering::SeqCst), 2);
+    assert_eq!(uintr_notify(usize::MAX), -ESRCH);
+    println!("uintr_test passed!");
+    0
+}
@@ -0,0 +1,22 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{strerror, umount};
+
+/// umount DIRECTORY
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 2 {
+        println!("usage: umount DIRECTORY");
+        return 1;
+    }
+    let ret = umount(argv[1]);
+    if ret < 0 {
+        println!("umount: {}: {}", argv[1], strerror(ret));
+        return 1;
+    }
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fstat, open, read, unlink, write, OpenFlags, Stat};
+
+const FILE: &str = "unlink_open_file\0";
+const OTHER: &str = "unlink_open_other\0";
+const DATA: &[u8] = b"still here after unlink";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open(FILE, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, DATA), DATA.len() as isize);
+    close(fd);
+
+    let fd = open(FILE, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(unlink(FILE), 0);
+    assert!(open(FILE, OpenFlags::RDONLY) < 0);
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    assert_eq!(st.nlink, 0);
+
+    // a new file would get the blocks of the old one if they were freed
+    let other = open(OTHER, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(other > 0);
+    assert_eq!(write(other as usize, &[b'x'; 512]), 512);
+    close(other as usize);
+
+    let mut buf = [0u8; 64];
+    assert_eq!(read(fd, &mut buf), DATA.len() as isize);
+    assert_eq!(&buf[..DATA.len()], DATA);
+    close(fd);
+    assert_eq!(unlink(OTHER), 0);
+    println!("unlink_open_test passed!");
+    0
+}
@@ -2,6 +2,7 @@
 #![no_main]
 #![allow(clippy::println_empty_string)]
 
+#[macro_use]
 extern crate alloc;
 
 #[macro_use]
@@ -11,12 +12,53 @@ const LF: u8 = 0x0au8;
 const CR: u8 = 0x0du8;
 const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
+const ESC: u8 = 0x1bu8;
+const TAB: u8 = 0x09u8;
 const LINE_START: &str = ">> ";
+/// commands remembered by `history` and the arrow keys
+const HISTORY_LEN: usize = 64;
 
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::{
+    close, dup2, exec, fork, getpid, killpg, open, pipe, read_dir, setpgid, sigaction, strerror,
+    tcgetattr, tcsetattr, tcsetpgrp, waitpid_options, wifcontinued, wifstopped, LocalModes,
+    OpenFlags, SignalAction, SignalFlags, Termios, ECHILD, SIG_DFL, SIG_IGN, WCONTINUED, WNOHANG,
+    WUNTRACED,
+};
+
+/// Signals typed on the terminal, left to the foreground job.
+const JOB_CONTROL_SIGNALS: [usize; 3] = [2, 3, 20]; // SIGINT, SIGQUIT, SIGTSTP
+const SIGCHLD: usize = 17;
+
+/// Set by the SIGCHLD handler, the jobs are reaped before the next prompt.
+static CHILD_CHANGED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn sigchld_handler(_signum: usize) {
+    CHILD_CHANGED.store(true, Ordering::Relaxed);
+}
+
+#[derive(PartialEq, Eq)]
+enum JobStatus {
+    Running,
+    Stopped,
+}
+
+struct Job {
+    id: usize,
+    pgid: usize,
+    /// children which have not exited yet
+    pids: Vec<usize>,
+    command: String,
+    status: JobStatus,
+}
+
+#[derive(Default)]
+struct Jobs {
+    jobs: Vec<Job>,
+}
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -26,57 +68,445 @@ struct ProcessArguments {
     args_addr: Vec<*const u8>,
 }
 
-impl ProcessArguments {
-    pub fn new(command: &str) -> Self {
-        let args: Vec<_> = command.split(' ').collect();
-        let mut args_copy: Vec<String> = args
-            .iter()
-            .filter(|&arg| !arg.is_empty())
-            .map(|&arg| {
-                let mut string = String::new();
-                string.push_str(arg);
-                string.push('\0');
-                string
-            })
-            .collect();
-
-        // redirect input
-        let mut input = String::new();
-        if let Some((idx, _)) = args_copy
-            .iter()
-            .enumerate()
-            .find(|(_, arg)| arg.as_str() == "<\0")
-        {
-            input = args_copy[idx + 1].clone();
-            args_copy.drain(idx..=idx + 1);
+/// Split at blanks, `|`, `<`, `>` and `&` are words of their own even when glued to others.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    for c in line.chars() {
+        match c {
+            ' ' | '\t' | '|' | '<' | '>' | '&' => {
+                if !word.is_empty() {
+                    tokens.push(core::mem::take(&mut word));
+                }
+                if c != ' ' && c != '\t' {
+                    tokens.push(String::from(c));
+                }
+            }
+            _ => word.push(c),
         }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
 
-        // redirect output
+impl ProcessArguments {
+    /// None if a redirection has no file or the command is empty.
+    pub fn new(tokens: &[String]) -> Option<Self> {
+        let mut input = String::new();
         let mut output = String::new();
-        if let Some((idx, _)) = args_copy
-            .iter()
-            .enumerate()
-            .find(|(_, arg)| arg.as_str() == ">\0")
-        {
-            output = args_copy[idx + 1].clone();
-            args_copy.drain(idx..=idx + 1);
+        let mut args_copy: Vec<String> = Vec::new();
+        let mut tokens = tokens.iter();
+        while let Some(token) = tokens.next() {
+            let target = match token.as_str() {
+                "<" => &mut input,
+                ">" => &mut output,
+                _ => {
+                    args_copy.push(format!("{}\0", token));
+                    continue;
+                }
+            };
+            match tokens.next() {
+                Some(file) if file != "<" && file != ">" => *target = format!("{}\0", file),
+                _ => return None,
+            }
+        }
+        if args_copy.is_empty() {
+            return None;
         }
 
         let mut args_addr: Vec<*const u8> = args_copy.iter().map(|arg| arg.as_ptr()).collect();
         args_addr.push(core::ptr::null::<u8>());
 
-        Self {
+        Some(Self {
             input,
             output,
             args_copy,
             args_addr,
+        })
+    }
+}
+
+/// Open `path` and move it to `fd`, the exit code on failure.
+fn redirect(path: &str, flags: OpenFlags, fd: usize) -> Result<(), i32> {
+    let file_fd = open(path, flags);
+    if file_fd < 0 {
+        println!(
+            "Error when opening file {}: {}",
+            path.trim_end_matches('\0'),
+            strerror(file_fd)
+        );
+        return Err(-4);
+    }
+    let file_fd = file_fd as usize;
+    assert_eq!(dup2(file_fd, fd), fd as isize);
+    close(file_fd);
+    Ok(())
+}
+
+/// Parse a pipeline such as `cat file | grep x > out &`, and whether it
+/// runs in the background.
+fn parse_pipeline(tokens: &[String]) -> Option<(Vec<ProcessArguments>, bool)> {
+    let (background, tokens) = match tokens.split_last() {
+        Some((last, rest)) if last == "&" => (true, rest),
+        _ => (false, tokens),
+    };
+    if tokens.iter().any(|token| token == "&") {
+        return None;
+    }
+    let process_arguments_list: Option<Vec<_>> = tokens
+        .split(|token| token == "|")
+        .map(ProcessArguments::new)
+        .collect();
+    let process_arguments_list = process_arguments_list?;
+    // only the ends of a pipeline may be redirected
+    let last = process_arguments_list.len() - 1;
+    for (i, process_args) in process_arguments_list.iter().enumerate() {
+        if (i > 0 && !process_args.input.is_empty())
+            || (i < last && !process_args.output.is_empty())
+        {
+            return None;
+        }
+    }
+    Some((process_arguments_list, background))
+}
+
+/// Run every command of the pipeline in a process group of its own led by
+/// the first one, returning the pids of the children.
+fn spawn_pipeline(process_arguments_list: &[ProcessArguments]) -> Result<Vec<isize>, i32> {
+    // create pipes
+    let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
+    for _ in 1..process_arguments_list.len() {
+        let mut pipe_fd = [0usize; 2];
+        pipe(&mut pipe_fd);
+        pipes_fd.push(pipe_fd);
+    }
+    let mut children: Vec<_> = Vec::new();
+    for (i, process_argument) in process_arguments_list.iter().enumerate() {
+        let pgid = children.first().copied().unwrap_or(0) as usize;
+        let pid = fork();
+        if pid == 0 {
+            // both sides set the group, whichever runs first
+            setpgid(0, pgid);
+            for signum in JOB_CONTROL_SIGNALS {
+                sigaction(
+                    signum,
+                    Some(&SignalAction::new(SIG_DFL, SignalFlags::empty())),
+                    None,
+                );
+            }
+            let input = &process_argument.input;
+            let output = &process_argument.output;
+            let args_copy = &process_argument.args_copy;
+            let args_addr = &process_argument.args_addr;
+            // receive input from the previous process
+            if i > 0 {
+                dup2(pipes_fd[i - 1][0], 0);
+            }
+            // send output to the next process
+            if i < process_arguments_list.len() - 1 {
+                dup2(pipes_fd[i][1], 1);
+            }
+            // close all pipe ends inherited from the parent process
+            for pipe_fd in pipes_fd.iter() {
+                close(pipe_fd[0]);
+                close(pipe_fd[1]);
+            }
+            if !input.is_empty() {
+                redirect(input, OpenFlags::RDONLY, 0)?;
+            }
+            if !output.is_empty() {
+                redirect(output, OpenFlags::CREATE | OpenFlags::WRONLY, 1)?;
+            }
+            // execute new application
+            let err = exec(args_copy[0].as_str(), args_addr.as_slice());
+            println!("Error when executing: {}", strerror(err));
+            return Err(-4);
+        } else {
+            setpgid(pid as usize, pgid);
+            children.push(pid);
+        }
+    }
+    for pipe_fd in pipes_fd.iter() {
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+    }
+    Ok(children)
+}
+
+impl Jobs {
+    fn add(&mut self, pgid: usize, pids: Vec<usize>, command: &str) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            pgid,
+            pids,
+            command: String::from(command.trim()),
+            status: JobStatus::Running,
+        });
+        id
+    }
+
+    /// `%n` or `n`, the latest job if there is no argument.
+    fn find(&mut self, arg: Option<&String>) -> Option<&mut Job> {
+        match arg {
+            None => self.jobs.last_mut(),
+            Some(arg) => {
+                let id: usize = arg.trim_start_matches('%').parse().ok()?;
+                self.jobs.iter_mut().find(|job| job.id == id)
+            }
+        }
+    }
+
+    /// Collect whatever happened to the jobs, without waiting.
+    fn reap(&mut self) {
+        for job in self.jobs.iter_mut() {
+            let mut status = 0;
+            loop {
+                let pid = waitpid_options(
+                    -(job.pgid as isize),
+                    &mut status,
+                    WNOHANG | WUNTRACED | WCONTINUED,
+                );
+                if pid <= 0 {
+                    if pid == -ECHILD {
+                        job.pids.clear();
+                    }
+                    break;
+                }
+                if wifstopped(status) {
+                    job.status = JobStatus::Stopped;
+                } else if wifcontinued(status) {
+                    job.status = JobStatus::Running;
+                } else {
+                    job.pids.retain(|child| *child != pid as usize);
+                }
+            }
+        }
+        self.jobs.retain(|job| {
+            if job.pids.is_empty() {
+                println!("[{}] Done\t{}", job.id, job.command);
+            }
+            !job.pids.is_empty()
+        });
+    }
+
+    /// Give the terminal to job `id` and wait until it exits or stops.
+    fn wait_foreground(&mut self, id: usize) {
+        let index = self.jobs.iter().position(|job| job.id == id).unwrap();
+        let job = &mut self.jobs[index];
+        tcsetpgrp(0, job.pgid);
+        let mut status = 0;
+        while !job.pids.is_empty() {
+            let pid = waitpid_options(-(job.pgid as isize), &mut status, WUNTRACED);
+            if pid < 0 {
+                job.pids.clear();
+            } else if wifstopped(status) {
+                job.status = JobStatus::Stopped;
+                println!("");
+                println!("[{}] Stopped\t{}", job.id, job.command);
+                break;
+            } else {
+                job.pids.retain(|child| *child != pid as usize);
+            }
+        }
+        tcsetpgrp(0, getpid() as usize);
+        if job.pids.is_empty() {
+            self.jobs.remove(index);
+        }
+    }
+
+    /// `jobs`, `fg` and `bg`, false if `tokens` is no builtin.
+    fn builtin(&mut self, tokens: &[String]) -> bool {
+        match tokens[0].as_str() {
+            "jobs" => {
+                for job in self.jobs.iter() {
+                    let status = match job.status {
+                        JobStatus::Running => "Running",
+                        JobStatus::Stopped => "Stopped",
+                    };
+                    println!("[{}] {}\t{}", job.id, status, job.command);
+                }
+            }
+            "fg" | "bg" => match self.find(tokens.get(1)) {
+                None => println!("{}: no such job", tokens[0]),
+                Some(job) => {
+                    job.status = JobStatus::Running;
+                    let id = job.id;
+                    if tokens[0] == "fg" {
+                        println!("{}", job.command);
+                        // hand over the terminal before the job can read from it
+                        tcsetpgrp(0, job.pgid);
+                        killpg(job.pgid, SignalFlags::SIGCONT.bits());
+                        self.wait_foreground(id);
+                    } else {
+                        println!("[{}] {} &", id, job.command);
+                        killpg(job.pgid, SignalFlags::SIGCONT.bits());
+                    }
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+struct History {
+    entries: Vec<String>,
+    /// entry shown by the arrow keys, `entries.len()` for the line being typed
+    cursor: usize,
+    /// the line being typed, kept while looking at older entries
+    draft: String,
+}
+
+impl History {
+    fn push(&mut self, line: &str) {
+        let line = line.trim();
+        if !line.is_empty() && self.entries.last().map(String::as_str) != Some(line) {
+            if self.entries.len() == HISTORY_LEN {
+                self.entries.remove(0);
+            }
+            self.entries.push(String::from(line));
+        }
+        self.cursor = self.entries.len();
+        self.draft.clear();
+    }
+
+    /// The entry before the one shown, None at the oldest.
+    fn up(&mut self, line: &str) -> Option<&str> {
+        if self.cursor == 0 {
+            return None;
+        }
+        if self.cursor == self.entries.len() {
+            self.draft = String::from(line);
+        }
+        self.cursor -= 1;
+        Some(&self.entries[self.cursor])
+    }
+
+    /// The entry after the one shown, ending with the line being typed.
+    fn down(&mut self) -> Option<&str> {
+        if self.cursor == self.entries.len() {
+            return None;
+        }
+        self.cursor += 1;
+        Some(self.entries.get(self.cursor).unwrap_or(&self.draft))
+    }
+
+    fn builtin(&self, tokens: &[String]) -> bool {
+        if tokens[0] != "history" {
+            return false;
         }
+        for (i, entry) in self.entries.iter().enumerate() {
+            println!("{:5}  {}", i + 1, entry);
+        }
+        true
+    }
+}
+
+/// Erase what has been typed and show `new` instead.
+fn replace_line(line: &mut String, new: &str) {
+    for _ in 0..line.len() {
+        print!("{} {}", BS as char, BS as char);
+    }
+    print!("{}", new);
+    line.clear();
+    line.push_str(new);
+}
+
+/// Lead a process group of our own and let ^C and ^Z reach the foreground job.
+fn init_job_control() {
+    setpgid(0, 0);
+    tcsetpgrp(0, getpid() as usize);
+    for signum in JOB_CONTROL_SIGNALS {
+        sigaction(
+            signum,
+            Some(&SignalAction::new(SIG_IGN, SignalFlags::empty())),
+            None,
+        );
+    }
+    let handler = SignalAction::new(sigchld_handler as usize, SignalFlags::empty());
+    sigaction(SIGCHLD, Some(&handler), None);
+}
+
+/// The line editor wants every key as it is typed and does its own echo,
+/// only ^C and ^Z are left to the terminal.
+fn init_terminal() {
+    let mut termios = Termios::default();
+    if tcgetattr(0, &mut termios) == 0 {
+        termios.lflag &= !(LocalModes::ICANON | LocalModes::ECHO).bits();
+        termios.lflag |= LocalModes::ISIG.bits();
+        tcsetattr(0, &termios);
+    }
+}
+
+const BUILTINS: [&str; 4] = ["bg", "fg", "history", "jobs"];
+
+/// Complete the last word of `line`, a command name if it is the first word
+/// of a command and a file name otherwise. Several candidates are listed.
+fn complete(line: &mut String) {
+    let start = line
+        .rfind(|c| matches!(c, ' ' | '\t' | '|' | '<' | '>' | '&'))
+        .map_or(0, |i| i + 1);
+    let is_command = line[..start]
+        .trim_end()
+        .chars()
+        .last()
+        .map_or(true, |c| c == '|' || c == '&');
+    let word = String::from(&line[start..]);
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word.as_str()),
+    };
+    // programs are looked up from the root
+    let dir_path = if dir.is_empty() {
+        String::from("/\0")
+    } else {
+        format!("{}\0", dir)
+    };
+    let mut candidates: Vec<String> = read_dir(dir_path.as_str()).unwrap_or_default();
+    if is_command && dir.is_empty() {
+        candidates.extend(BUILTINS.iter().map(|name| String::from(*name)));
+    }
+    candidates.retain(|name| name.starts_with(prefix));
+    candidates.sort();
+    candidates.dedup();
+    let common = match candidates.first() {
+        None => return,
+        Some(first) => candidates.iter().fold(first.len(), |len, name| {
+            first
+                .bytes()
+                .zip(name.bytes())
+                .take(len)
+                .take_while(|(a, b)| a == b)
+                .count()
+        }),
+    };
+    let mut completion = String::from(&candidates[0][prefix.len()..common]);
+    if candidates.len() == 1 {
+        completion.push(' ');
+    } else if completion.is_empty() {
+        println!("");
+        for name in candidates.iter() {
+            print!("{}  ", name);
+        }
+        println!("");
+        print!("{}{}", LINE_START, line);
+        return;
     }
+    print!("{}", completion);
+    line.push_str(&completion);
 }
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    init_job_control();
+    init_terminal();
+    let mut jobs = Jobs::default();
+    let mut history = History::default();
     let mut line: String = String::new();
     print!("{}", LINE_START);
     loop {
@@ -84,119 +514,51 @@ pub fn main() -> i32 {
         match c {
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
-                    let splited: Vec<_> = line.as_str().split('|').collect();
-                    let process_arguments_list: Vec<_> = splited
-                        .iter()
-                        .map(|&cmd| ProcessArguments::new(cmd))
-                        .collect();
-                    let mut valid = true;
-                    for (i, process_args) in process_arguments_list.iter().enumerate() {
-                        if i == 0 {
-                            if !process_args.output.is_empty() {
-                                valid = false;
-                            }
-                        } else if i == process_arguments_list.len() - 1 {
-                            if !process_args.input.is_empty() {
-                                valid = false;
-                            }
-                        } else if !process_args.output.is_empty() || !process_args.input.is_empty()
-                        {
-                            valid = false;
-                        }
-                    }
-                    if process_arguments_list.len() == 1 {
-                        valid = true;
-                    }
-                    if !valid {
-                        println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
-                    } else {
-                        // create pipes
-                        let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
-                        if !process_arguments_list.is_empty() {
-                            for _ in 0..process_arguments_list.len() - 1 {
-                                let mut pipe_fd = [0usize; 2];
-                                pipe(&mut pipe_fd);
-                                pipes_fd.push(pipe_fd);
-                            }
-                        }
-                        let mut children: Vec<_> = Vec::new();
-                        for (i, process_argument) in process_arguments_list.iter().enumerate() {
-                            let pid = fork();
-                            if pid == 0 {
-                                let input = &process_argument.input;
-                                let output = &process_argument.output;
-                                let args_copy = &process_argument.args_copy;
-                                let args_addr = &process_argument.args_addr;
-                                // redirect input
-                                if !input.is_empty() {
-                                    let input_fd = open(input.as_str(), OpenFlags::RDONLY);
-                                    if input_fd == -1 {
-                                        println!("Error when opening file {}", input);
-                                        return -4;
-                                    }
-                                    let input_fd = input_fd as usize;
-                                    close(0);
-                                    assert_eq!(dup(input_fd), 0);
-                                    close(input_fd);
-                                }
-                                // redirect output
-                                if !output.is_empty() {
-                                    let output_fd = open(
-                                        output.as_str(),
-                                        OpenFlags::CREATE | OpenFlags::WRONLY,
-                                    );
-                                    if output_fd == -1 {
-                                        println!("Error when opening file {}", output);
-                                        return -4;
-                                    }
-                                    let output_fd = output_fd as usize;
-                                    close(1);
-                                    assert_eq!(dup(output_fd), 1);
-                                    close(output_fd);
-                                }
-                                // receive input from the previous process
-                                if i > 0 {
-                                    close(0);
-                                    let read_end = pipes_fd.get(i - 1).unwrap()[0];
-                                    assert_eq!(dup(read_end), 0);
-                                }
-                                // send output to the next process
-                                if i < process_arguments_list.len() - 1 {
-                                    close(1);
-                                    let write_end = pipes_fd.get(i).unwrap()[1];
-                                    assert_eq!(dup(write_end), 1);
-                                }
-                                // close all pipe ends inherited from the parent process
-                                for pipe_fd in pipes_fd.iter() {
-                                    close(pipe_fd[0]);
-                                    close(pipe_fd[1]);
-                                }
-                                // execute new application
-                                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
-                                    println!("Error when executing!");
-                                    return -4;
-                                }
-                                unreachable!();
+                history.push(&line);
+                let tokens = tokenize(line.as_str());
+                if !tokens.is_empty() && !history.builtin(&tokens) && !jobs.builtin(&tokens) {
+                    match parse_pipeline(&tokens) {
+                        None => println!("Invalid command: {}", line),
+                        Some((process_arguments_list, background)) => {
+                            // a child that failed to exec leaves the shell here
+                            let children = match spawn_pipeline(&process_arguments_list) {
+                                Ok(children) => children,
+                                Err(exit_code) => return exit_code,
+                            };
+                            let pgid = children[0] as usize;
+                            let pids = children.into_iter().map(|pid| pid as usize).collect();
+                            let command = line.trim_end().trim_end_matches('&');
+                            let id = jobs.add(pgid, pids, command);
+                            if background {
+                                println!("[{}] {}", id, pgid);
                             } else {
-                                children.push(pid);
+                                jobs.wait_foreground(id);
                             }
                         }
-                        for pipe_fd in pipes_fd.iter() {
-                            close(pipe_fd[0]);
-                            close(pipe_fd[1]);
-                        }
-                        let mut exit_code: i32 = 0;
-                        for pid in children.into_iter() {
-                            let exit_pid = waitpid(pid as usize, &mut exit_code);
-                            assert_eq!(pid, exit_pid);
-                            //println!("Shell: Process {} exited with code {}", pid, exit_code);
-                        }
                     }
-                    line.clear();
                 }
+                if CHILD_CHANGED.swap(false, Ordering::Relaxed) {
+                    jobs.reap();
+                }
+                line.clear();
                 print!("{}", LINE_START);
             }
+            // arrow keys arrive as ESC [ A .. ESC [ D
+            ESC => {
+                if getchar() != b'[' {
+                    continue;
+                }
+                let entry = match getchar() {
+                    b'A' => history.up(&line),
+                    b'B' => history.down(),
+                    _ => None,
+                };
+                if let Some(entry) = entry {
+                    let entry = String::from(entry);
+                    replace_line(&mut line, &entry);
+                }
+            }
+            TAB => complete(&mut line),
             BS | DL => {
                 if !line.is_empty() {
                     print!("{}", BS as char);
@@ -5,7 +5,8 @@
 extern crate user_lib;
 
 // not in SUCC_TESTS & FAIL_TESTS
-// count_lines, infloop, user_shell, usertests
+// count_lines, infloop, lat_bench, poweroff, user_shell, usertests
+// the coreutils (ls, echo, rm, mkdir, cp, mv, ln, grep, wc, ps, kill, sleep) run in coreutils_test
 
 // item of TESTS : app_name(argv_0), argv_1, argv_2, argv_3, exit_code
 static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
@@ -27,18 +28,65 @@ static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("phil_din_mutex\0", "\0", "\0", "\0", 0),
     ("pipe_large_test\0", "\0", "\0", "\0", 0),
     ("pipetest\0", "\0", "\0", "\0", 0),
+    ("pty_test\0", "\0", "\0", "\0", 0),
+    ("fifo_test\0", "\0", "\0", "\0", 0),
+    ("sig_test\0", "\0", "\0", "\0", 0),
+    ("sigaltstack_test\0", "\0", "\0", "\0", 0),
+    ("core_dump_test\0", "\0", "\0", "\0", 0),
+    ("ptrace_test\0", "\0", "\0", "\0", 0),
+    ("job_control_test\0", "\0", "\0", "\0", 0),
+    ("vt_test\0", "\0", "\0", "\0", 0),
+    ("misaligned\0", "\0", "\0", "\0", 0),
+    ("fp_test\0", "\0", "\0", "\0", 0),
+    ("vector_test\0", "\0", "\0", "\0", 0),
+    ("bad_syscall\0", "\0", "\0", "\0", 0),
+    ("uintr_test\0", "\0", "\0", "\0", 0),
+    ("mprotect_test\0", "\0", "\0", "\0", 0),
+    ("madvise_test\0", "\0", "\0", "\0", 0),
+    ("mmap_test\0", "\0", "\0", "\0", 0),
+    ("brk_test\0", "\0", "\0", "\0", 0),
+    ("maps_test\0", "\0", "\0", "\0", 0),
+    ("page_cache_test\0", "\0", "\0", "\0", 0),
+    ("unlink_open_test\0", "\0", "\0", "\0", 0),
+    ("text_share_test\0", "\0", "\0", "\0", 0),
+    ("readahead_test\0", "\0", "\0", "\0", 0),
+    ("block_queue_test\0", "\0", "\0", "\0", 0),
+    ("mremap_test\0", "\0", "\0", "\0", 0),
+    ("getrandom_test\0", "\0", "\0", "\0", 0),
+    ("heap_test\0", "\0", "\0", "\0", 0),
+    ("getdents_test\0", "\0", "\0", "\0", 0),
+    ("at_test\0", "\0", "\0", "\0", 0),
+    ("poll_test\0", "\0", "\0", "\0", 0),
+    ("statx_test\0", "\0", "\0", "\0", 0),
+    ("utime_test\0", "\0", "\0", "\0", 0),
+    ("fcntl_test\0", "\0", "\0", "\0", 0),
+    ("ioctl_test\0", "\0", "\0", "\0", 0),
+    ("mount_test\0", "\0", "\0", "\0", 0),
+    ("surface_test\0", "\0", "\0", "\0", 0),
+    ("evdev_test\0", "\0", "\0", "\0", 0),
+    ("profile_test\0", "\0", "\0", "\0", 0),
+    ("trace_test\0", "\0", "\0", "\0", 0),
+    ("ksyms_test\0", "\0", "\0", "\0", 0),
+    ("exit_group_test\0", "\0", "\0", "\0", 0),
+    ("nanosleep_test\0", "\0", "\0", "\0", 0),
+    ("get_time_test\0", "\0", "\0", "\0", 0),
+    ("coreutils_test\0", "\0", "\0", "\0", 0),
+    ("sync_wrappers_test\0", "\0", "\0", "\0", 0),
     ("adder_peterson_spin\0", "\0", "\0", "\0", 0),
     ("adder_peterson_yield\0", "\0", "\0", "\0", 0),
     ("adder_mutex_blocking\0", "\0", "\0", "\0", 0),
     ("adder_mutex_spin\0", "\0", "\0", "\0", 0),
     ("run_pipe_test\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
-    ("sleep\0", "\0", "\0", "\0", 0),
+    ("sleep_test\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
     ("sync_sem\0", "\0", "\0", "\0", 0),
     ("condsync_sem\0", "\0", "\0", "\0", 0),
     ("condsync_condvar\0", "\0", "\0", "\0", 0),
     ("threads_arg\0", "\0", "\0", "\0", 0),
+    ("tls_test\0", "\0", "\0", "\0", 0),
+    ("futex_join_test\0", "\0", "\0", "\0", 0),
+    ("futex_ops_test\0", "\0", "\0", "\0", 0),
     ("threads\0", "\0", "\0", "\0", 0),
     ("yield\0", "\0", "\0", "\0", 0),
     ("barrier_fail\0", "\0", "\0", "\0", 0),
@@ -49,76 +97,121 @@ static FAIL_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("stack_overflow\0", "\0", "\0", "\0", -11),
     ("race_adder_loop\0", "\0", "\0", "\0", -6),
     ("priv_csr\0", "\0", "\0", "\0", -4),
+    ("ebreak\0", "\0", "\0", "\0", -5),
+    ("panic_in_print\0", "\0", "\0", "\0", -6),
     ("priv_inst\0", "\0", "\0", "\0", -4),
     ("store_fault\0", "\0", "\0", "\0", -11),
+    ("kernel_read\0", "\0", "\0", "\0", -11),
     ("until_timeout\0", "\0", "\0", "\0", -6),
     ("adder\0", "\0", "\0", "\0", -6),
     ("adder_simple_spin\0", "\0", "\0", "\0", -6),
     ("adder_simple_yield\0", "\0", "\0", "\0", -6),
 ];
 
-use user_lib::{exec, fork, waitpid};
+use user_lib::{
+    close, exec, fork, get_time, killpg, open, read, setpgid, sleep, waitpid, waitpid_nb,
+    OpenFlags, SignalFlags,
+};
+
+/// A test still running after this long is killed and counted as failed.
+const TIMEOUT_MS: isize = 60_000;
+const POLL_MS: usize = 10;
+
+/// Wait for `pid`, killing its process group, which is the test and what
+/// it forked, once `TIMEOUT_MS` has passed. Returns `None` on a timeout.
+fn wait_with_timeout(pid: usize) -> Option<i32> {
+    let start = get_time();
+    let mut exit_code: i32 = 0;
+    loop {
+        if waitpid_nb(pid, &mut exit_code) as usize == pid {
+            return Some(exit_code);
+        }
+        if get_time() - start > TIMEOUT_MS {
+            killpg(pid, SignalFlags::SIGKILL.bits());
+            assert_eq!(waitpid(pid, &mut exit_code) as usize, pid);
+            return None;
+        }
+        sleep(POLL_MS);
+    }
+}
 
 fn run_tests(tests: &[(&str, &str, &str, &str, i32)]) -> i32 {
     let mut pass_num = 0;
-    let mut arr: [*const u8; 4] = [
-        core::ptr::null::<u8>(),
-        core::ptr::null::<u8>(),
-        core::ptr::null::<u8>(),
-        core::ptr::null::<u8>(),
-    ];
-
     for test in tests {
-        println!("Usertests: Running {}", test.0);
+        let name = test.0.trim_end_matches('\0');
+        println!("Usertests: Running {}", name);
+        let mut arr = [core::ptr::null::<u8>(); 4];
         arr[0] = test.0.as_ptr();
-        if test.1 != "\0" {
-            arr[1] = test.1.as_ptr();
-            arr[2] = core::ptr::null::<u8>();
-            arr[3] = core::ptr::null::<u8>();
-            if test.2 != "\0" {
-                arr[2] = test.2.as_ptr();
-                arr[3] = core::ptr::null::<u8>();
-                if test.3 != "\0" {
-                    arr[3] = test.3.as_ptr();
-                } else {
-                    arr[3] = core::ptr::null::<u8>();
-                }
-            } else {
-                arr[2] = core::ptr::null::<u8>();
-                arr[3] = core::ptr::null::<u8>();
+        for (i, arg) in [test.1, test.2, test.3].iter().enumerate() {
+            if *arg == "\0" {
+                break;
             }
-        } else {
-            arr[1] = core::ptr::null::<u8>();
-            arr[2] = core::ptr::null::<u8>();
-            arr[3] = core::ptr::null::<u8>();
+            arr[i + 1] = arg.as_ptr();
         }
 
+        // each test leads a group of its own, set on both sides so that
+        // neither the exec nor a timeout can come first
         let pid = fork();
         if pid == 0 {
+            setpgid(0, 0);
             exec(test.0, &arr[..]);
             panic!("unreachable!");
-        } else {
-            let mut exit_code: i32 = Default::default();
-            let wait_pid = waitpid(pid as usize, &mut exit_code);
-            assert_eq!(pid, wait_pid);
-            if exit_code == test.4 {
-                // summary apps with  exit_code
-                pass_num = pass_num + 1;
+        }
+        setpgid(pid as usize, pid as usize);
+        // one line per test: `RESULT name key=value...`
+        match wait_with_timeout(pid as usize) {
+            Some(exit_code) if exit_code == test.4 => {
+                pass_num += 1;
+                println!("Usertests: PASS {} pid={} exit={}", name, pid, exit_code);
             }
-            println!(
-                "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
-                test.0, pid, exit_code
-            );
+            Some(exit_code) => println!(
+                "Usertests: FAIL {} pid={} exit={} expected={}",
+                name, pid, exit_code, test.4
+            ),
+            None => println!(
+                "Usertests: FAIL {} pid={} timeout={}ms",
+                name, pid, TIMEOUT_MS
+            ),
         }
     }
     pass_num
 }
 
+/// Print what a kernel built with `RC_AUDIT=on` finds still alive after
+/// every test was reaped.
+fn print_rc_audit() {
+    let fd = open("/proc/rc_audit\0", OpenFlags::RDONLY);
+    if fd < 0 {
+        return;
+    }
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        print!(
+            "{}",
+            core::str::from_utf8(&buf[..len as usize]).unwrap_or("?")
+        );
+    }
+    close(fd as usize);
+}
+
 #[no_mangle]
 pub fn main() -> i32 {
     let succ_num = run_tests(SUCC_TESTS);
     let err_num = run_tests(FAIL_TESTS);
-    if succ_num == SUCC_TESTS.len() as i32 && err_num == FAIL_TESTS.len() as i32 {
+    print_rc_audit();
+    let total = SUCC_TESTS.len() + FAIL_TESTS.len();
+    let passed = (succ_num + err_num) as usize;
+    println!(
+        "Usertests: SUMMARY total={} passed={} failed={}",
+        total,
+        passed,
+        total - passed
+    );
+    if passed == total {
         println!(
             "{} of sueecssed apps, {} of failed apps run correctly. \nUsertests passed!",
             SUCC_TESTS.len(),
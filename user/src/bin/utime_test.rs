@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    clock_gettime, close, fstat, futimens, open, read, unlinkat, utimensat, write, OpenFlags, Stat,
+    TimeSpec, AT_FDCWD, CLOCK_REALTIME, EINVAL, ENOENT, UTIME_NOW, UTIME_OMIT,
+};
+
+fn stat_of(fd: usize) -> Stat {
+    let mut st = Stat::default();
+    assert_eq!(fstat(fd, &mut st), 0);
+    st
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut now = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_REALTIME, &mut now), 0);
+    let fd = open("utime_test_file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+
+    // a new file is stamped with the wall clock
+    let st = stat_of(fd);
+    assert!(st.mtime_sec as usize >= now.tv_sec);
+    assert_eq!(st.atime_sec, st.mtime_sec);
+    assert_eq!(st.ctime_sec, st.mtime_sec);
+
+    let times = [
+        TimeSpec {
+            tv_sec: 1000,
+            tv_nsec: 5,
+        },
+        TimeSpec {
+            tv_sec: 2000,
+            tv_nsec: 7,
+        },
+    ];
+    assert_eq!(utimensat(AT_FDCWD, "utime_test_file\0", Some(&times), 0), 0);
+    let st = stat_of(fd);
+    assert_eq!((st.atime_sec, st.atime_nsec), (1000, 5));
+    assert_eq!((st.mtime_sec, st.mtime_nsec), (2000, 7));
+    assert!(st.ctime_sec as usize >= now.tv_sec);
+
+    // UTIME_OMIT keeps atime, UTIME_NOW takes the clock
+    let times = [
+        TimeSpec {
+            tv_sec: 0,
+            tv_nsec: UTIME_OMIT,
+        },
+        TimeSpec {
+            tv_sec: 0,
+            tv_nsec: UTIME_NOW,
+        },
+    ];
+    assert_eq!(futimens(fd, Some(&times)), 0);
+    let st = stat_of(fd);
+    assert_eq!(st.atime_sec, 1000);
+    assert!(st.mtime_sec as usize >= now.tv_sec);
+
+    // writing moves mtime, reading an atime older than mtime moves atime
+    assert_eq!(utimensat(AT_FDCWD, "utime_test_file\0", None, 0), 0);
+    let times = [
+        TimeSpec {
+            tv_sec: 1000,
+            tv_nsec: 0,
+        },
+        TimeSpec {
+            tv_sec: 1000,
+            tv_nsec: 0,
+        },
+    ];
+    assert_eq!(futimens(fd, Some(&times)), 0);
+    assert_eq!(write(fd, b"touched"), 7);
+    let st = stat_of(fd);
+    assert!(st.mtime_sec as usize >= now.tv_sec);
+    assert_eq!(st.atime_sec, 1000);
+    close(fd);
+    let fd = open("utime_test_file\0", OpenFlags::RDONLY) as usize;
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fd, &mut buf), 7);
+    assert!(stat_of(fd).atime_sec as usize >= now.tv_sec);
+
+    let bad = [
+        TimeSpec {
+            tv_sec: 0,
+            tv_nsec: 1_000_000_000,
+        },
+        TimeSpec::default(),
+    ];
+    assert_eq!(futimens(fd, Some(&bad)), -EINVAL);
+    assert_eq!(utimensat(AT_FDCWD, "no_such_file\0", None, 0), -ENOENT);
+    close(fd);
+    assert_eq!(unlinkat(AT_FDCWD, "utime_test_file\0", 0), 0);
+    println!("utime_test passed!");
+    0
+}
@@ -0,0 +1,70 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::arch::asm;
+use user_lib::{exit, fork, sigaction, wait, yield_, SignalAction, SignalFlags};
+
+extern "C" fn no_vector(_signum: usize) {
+    println!("no V extension, vector_test skipped");
+    exit(0);
+}
+
+/// Load `values` into v1 with vl = 4 and SEW = 64.
+fn load_v1(values: &[u64; 4]) {
+    unsafe {
+        asm!(
+            ".option push",
+            ".option arch, +v",
+            "vsetivli zero, 4, e64, m1, ta, ma",
+            "vle64.v v1, ({0})",
+            ".option pop",
+            in(reg) values.as_ptr(),
+        )
+    };
+}
+
+/// Store v1 with the vl and vtype left by `load_v1`.
+fn store_v1() -> [u64; 4] {
+    let mut values = [0u64; 4];
+    unsafe {
+        asm!(
+            ".option push",
+            ".option arch, +v",
+            "vse64.v v1, ({0})",
+            ".option pop",
+            in(reg) values.as_mut_ptr(),
+        )
+    };
+    values
+}
+
+/// Vector registers of two processes survive switches between them.
+#[no_mangle]
+pub fn main() -> i32 {
+    let action = SignalAction::new(no_vector as usize, SignalFlags::empty());
+    sigaction(4, Some(&action), None);
+    load_v1(&[1, 2, 3, 4]);
+    let child = fork() == 0;
+    let expected = if child {
+        let values = [5, 6, 7, 8];
+        load_v1(&values);
+        values
+    } else {
+        [1, 2, 3, 4]
+    };
+    for _ in 0..100 {
+        yield_();
+        assert_eq!(store_v1(), expected);
+    }
+    if child {
+        exit(0);
+    }
+    let mut exit_code = 0;
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+    println!("vector_test passed!");
+    0
+}
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, getpid, open, tcgetpgrp, tcsetpgrp, vt_activate, vt_active, write, OpenFlags,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the third terminal is left free by initproc
+    let fd = open("/dev/tty3\0", OpenFlags::RDWR);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert!(open("/dev/tty9\0", OpenFlags::RDWR) < 0);
+    // each terminal has a foreground group of its own
+    let foreground = tcgetpgrp(0);
+    assert_eq!(tcsetpgrp(fd, getpid() as usize), 0);
+    assert_eq!(tcgetpgrp(fd), getpid());
+    assert_eq!(tcgetpgrp(0), foreground);
+    // output goes to the history of a terminal not shown
+    let text = b"vt_test on tty3\n";
+    assert_eq!(write(fd, text), text.len() as isize);
+    assert_eq!(vt_active(0), 1);
+    assert_eq!(vt_activate(fd, 3), 0);
+    assert_eq!(vt_active(fd), 3);
+    assert_eq!(vt_activate(0, 1), 0);
+    assert_eq!(vt_active(0), 1);
+    assert!(vt_activate(fd, 9) < 0);
+    close(fd);
+    println!("vt_test passed!");
+    0
+}
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, strerror, OpenFlags};
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+    lines: usize,
+    words: usize,
+    bytes: usize,
+}
+
+fn count(fd: usize) -> Counts {
+    let mut counts = Counts::default();
+    let mut in_word = false;
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(fd, &mut buf);
+        if len <= 0 {
+            return counts;
+        }
+        for b in &buf[..len as usize] {
+            counts.bytes += 1;
+            if *b == b'\n' {
+                counts.lines += 1;
+            }
+            let blank = b.is_ascii_whitespace();
+            if !blank && !in_word {
+                counts.words += 1;
+            }
+            in_word = !blank;
+        }
+    }
+}
+
+fn report(counts: Counts, name: &str) {
+    println!(
+        "{:7} {:7} {:7} {}",
+        counts.lines, counts.words, counts.bytes, name
+    );
+}
+
+/// wc [FILE]..., lines, words and bytes of each and their total
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 1 {
+        report(count(0), "");
+        return 0;
+    }
+    let mut total = Counts::default();
+    let mut exit_code = 0;
+    for path in &argv[1..] {
+        let fd = open(path, OpenFlags::RDONLY);
+        if fd < 0 {
+            println!("wc: {}: {}", path, strerror(fd));
+            exit_code = 1;
+            continue;
+        }
+        let counts = count(fd as usize);
+        close(fd as usize);
+        report(counts, path);
+        total.lines += counts.lines;
+        total.words += counts.words;
+        total.bytes += counts.bytes;
+    }
+    if argc > 2 {
+        report(total, "total");
+    }
+    exit_code
+}
@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+//! A ball bouncing in a window of the compositor. Space pauses it, q or
+//! Esc closes the window. Run it next to win_paint: `win_bounce &`.
+
+#[macro_use]
+extern crate user_lib;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::{DrawTarget, Drawable, Point, Primitive, RgbColor, Size};
+use embedded_graphics::primitives::{Circle, PrimitiveStyle, Rectangle};
+use user_lib::{sleep, Surface, EV_KEY, KEY_ESC, KEY_Q, KEY_SPACE};
+
+const WIDTH: u32 = 480;
+const HEIGHT: u32 = 360;
+const DIAMETER: u32 = 40;
+const FRAME_MS: usize = 20;
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let at = |i: usize, default: i32| {
+        if argc > i {
+            argv[i].parse().unwrap_or(default)
+        } else {
+            default
+        }
+    };
+    let mut surface = match Surface::new(at(1, 160), at(2, 120), Size::new(WIDTH, HEIGHT)) {
+        Ok(surface) => surface,
+        Err(err) => {
+            println!("win_bounce: no surface: {}", user_lib::strerror(err));
+            return 1;
+        }
+    };
+    surface.clear(Rgb888::new(0x20, 0x20, 0x30)).unwrap();
+    surface.damage_all();
+    let ball = PrimitiveStyle::with_fill(Rgb888::new(0xff, 0x90, 0x20));
+    let background = PrimitiveStyle::with_fill(Rgb888::new(0x20, 0x20, 0x30));
+    let (mut position, mut velocity) = (Point::new(20, 30), Point::new(5, 3));
+    let mut paused = false;
+    loop {
+        while let Some(event) = surface.event() {
+            if event.event_type != EV_KEY || event.value != 1 {
+                continue;
+            }
+            match event.code {
+                KEY_ESC | KEY_Q => return 0,
+                KEY_SPACE => paused = !paused,
+                _ => {}
+            }
+        }
+        if !paused {
+            let old = Rectangle::new(position, Size::new(DIAMETER, DIAMETER));
+            old.into_styled(background).draw(&mut surface).unwrap();
+            position += velocity;
+            if position.x < 0 || position.x + DIAMETER as i32 > WIDTH as i32 {
+                velocity.x = -velocity.x;
+                position.x += 2 * velocity.x;
+            }
+            if position.y < 0 || position.y + DIAMETER as i32 > HEIGHT as i32 {
+                velocity.y = -velocity.y;
+                position.y += 2 * velocity.y;
+            }
+            Circle::new(position, DIAMETER)
+                .into_styled(ball)
+                .draw(&mut surface)
+                .unwrap();
+            surface.damage(&old);
+            surface.damage(&Rectangle::new(position, Size::new(DIAMETER, DIAMETER)));
+        }
+        sleep(FRAME_MS);
+    }
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+//! Paint with the mouse in a window of the compositor: the left button
+//! draws, the right one erases, c clears and q or Esc closes the window.
+
+#[macro_use]
+extern crate user_lib;
+
+use embedded_graphics::pixelcolor::Rgb888;
+use embedded_graphics::prelude::{
+    Dimensions, DrawTarget, Drawable, Point, Primitive, RgbColor, Size,
+};
+use embedded_graphics::primitives::{Line, PrimitiveStyle};
+use user_lib::{Surface, BTN_LEFT, BTN_RIGHT, EV_ABS, EV_KEY, KEY_C, KEY_ESC, KEY_Q};
+
+const WIDTH: u32 = 560;
+const HEIGHT: u32 = 400;
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let at = |i: usize, default: i32| {
+        if argc > i {
+            argv[i].parse().unwrap_or(default)
+        } else {
+            default
+        }
+    };
+    let mut surface = match Surface::new(at(1, 480), at(2, 300), Size::new(WIDTH, HEIGHT)) {
+        Ok(surface) => surface,
+        Err(err) => {
+            println!("win_paint: no surface: {}", user_lib::strerror(err));
+            return 1;
+        }
+    };
+    surface.clear(Rgb888::WHITE).unwrap();
+    surface.damage_all();
+    let mut pen: Option<PrimitiveStyle<Rgb888>> = None;
+    let mut last = Point::zero();
+    while let Some(event) = surface.wait_event() {
+        let here = Point::new(event.x, event.y);
+        match (event.event_type, event.code) {
+            (EV_KEY, KEY_ESC | KEY_Q) if event.value == 1 => break,
+            (EV_KEY, KEY_C) if event.value == 1 => {
+                surface.clear(Rgb888::WHITE).unwrap();
+                surface.damage_all();
+            }
+            (EV_KEY, BTN_LEFT) => {
+                pen = (event.value == 1).then(|| PrimitiveStyle::with_stroke(Rgb888::BLUE, 3));
+            }
+            (EV_KEY, BTN_RIGHT) => {
+                pen = (event.value == 1).then(|| PrimitiveStyle::with_stroke(Rgb888::WHITE, 15));
+            }
+            (EV_ABS, _) => {
+                if let Some(style) = pen {
+                    let line = Line::new(last, here).into_styled(style);
+                    line.draw(&mut surface).unwrap();
+                    surface.damage(&line.bounding_box());
+                }
+            }
+            _ => {}
+        }
+        last = here;
+    }
+    0
+}
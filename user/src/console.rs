@@ -1,21 +1,139 @@
+use core::cell::UnsafeCell;
 use core::fmt::{self, Write};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 const STDIN: usize = 0;
 const STDOUT: usize = 1;
+const STDOUT_BUFFER_SIZE: usize = 1024;
 
-use super::{read, write};
+use super::{gettid, read, write, yield_};
 
-struct Stdout;
+/// Line-buffered standard output, whole lines go out in one write.
+struct Stdout {
+    buffer: [u8; STDOUT_BUFFER_SIZE],
+    len: usize,
+}
+
+impl Stdout {
+    const fn new() -> Self {
+        Self {
+            buffer: [0; STDOUT_BUFFER_SIZE],
+            len: 0,
+        }
+    }
+    /// Write out the first `end` bytes.
+    fn flush_to(&mut self, end: usize) {
+        if end == 0 {
+            return;
+        }
+        write(STDOUT, &self.buffer[..end]);
+        self.buffer.copy_within(end..self.len, 0);
+        self.len -= end;
+    }
+    /// Write out every complete line, keeping a partial one.
+    fn flush_lines(&mut self) {
+        if let Some(last) = self.buffer[..self.len].iter().rposition(|&c| c == b'\n') {
+            self.flush_to(last + 1);
+        }
+    }
+    fn flush(&mut self) {
+        self.flush_to(self.len);
+    }
+}
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        write(STDOUT, s.as_bytes());
+        for &c in s.as_bytes() {
+            // a line longer than the buffer is written in pieces
+            if self.len == STDOUT_BUFFER_SIZE {
+                self.flush();
+            }
+            self.buffer[self.len] = c;
+            self.len += 1;
+        }
         Ok(())
     }
 }
 
+/// Shared by the threads of a process, waiters give up the cpu. A thread
+/// printing while it holds the lock already, from a panic or a signal
+/// handler, goes ahead instead of waiting for itself.
+struct StdoutCell {
+    /// tid + 1 of the holder, 0 if none
+    owner: AtomicUsize,
+    stdout: UnsafeCell<Stdout>,
+}
+
+unsafe impl Sync for StdoutCell {}
+
+struct StdoutGuard<'a> {
+    cell: &'a StdoutCell,
+    /// taken again by its holder, which unlocks it
+    nested: bool,
+}
+
+impl StdoutCell {
+    fn lock(&self) -> StdoutGuard<'_> {
+        let me = gettid() as usize + 1;
+        loop {
+            match self
+                .owner
+                .compare_exchange(0, me, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    return StdoutGuard {
+                        cell: self,
+                        nested: false,
+                    }
+                }
+                Err(owner) if owner == me => {
+                    return StdoutGuard {
+                        cell: self,
+                        nested: true,
+                    }
+                }
+                Err(_) => yield_(),
+            };
+        }
+    }
+}
+
+impl Deref for StdoutGuard<'_> {
+    type Target = Stdout;
+    fn deref(&self) -> &Stdout {
+        unsafe { &*self.cell.stdout.get() }
+    }
+}
+
+impl DerefMut for StdoutGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Stdout {
+        unsafe { &mut *self.cell.stdout.get() }
+    }
+}
+
+impl Drop for StdoutGuard<'_> {
+    fn drop(&mut self) {
+        if !self.nested {
+            self.cell.owner.store(0, Ordering::Release);
+        }
+    }
+}
+
+static STDOUT_CELL: StdoutCell = StdoutCell {
+    owner: AtomicUsize::new(0),
+    stdout: UnsafeCell::new(Stdout::new()),
+};
+
 pub fn print(args: fmt::Arguments) {
-    Stdout.write_fmt(args).unwrap();
+    let mut stdout = STDOUT_CELL.lock();
+    stdout.write_fmt(args).unwrap();
+    stdout.flush_lines();
+}
+
+/// Write out what print! left in the buffer, such as a prompt.
+pub fn flush() {
+    STDOUT_CELL.lock().flush();
 }
 
 #[macro_export]
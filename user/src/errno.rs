@@ -0,0 +1,62 @@
+//! Syscalls fail with the negated errno.
+
+pub const EPERM: isize = 1;
+pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EINTR: isize = 4;
+pub const EIO: isize = 5;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EACCES: isize = 13;
+pub const EFAULT: isize = 14;
+pub const EBUSY: isize = 16;
+pub const EEXIST: isize = 17;
+pub const ENODEV: isize = 19;
+pub const ENOTDIR: isize = 20;
+pub const EISDIR: isize = 21;
+pub const EINVAL: isize = 22;
+pub const EMFILE: isize = 24;
+pub const ENOTTY: isize = 25;
+pub const ESPIPE: isize = 29;
+pub const EPIPE: isize = 32;
+pub const EDEADLK: isize = 35;
+pub const ENOSYS: isize = 38;
+pub const ENOTEMPTY: isize = 39;
+pub const EADDRINUSE: isize = 98;
+pub const ETIMEDOUT: isize = 110;
+
+/// Describe an errno, either sign is accepted.
+pub fn strerror(errno: isize) -> &'static str {
+    match errno.abs() {
+        0 => "Success",
+        EPERM => "Operation not permitted",
+        ENOENT => "No such file or directory",
+        ESRCH => "No such process",
+        EINTR => "Interrupted system call",
+        EIO => "I/O error",
+        EBADF => "Bad file descriptor",
+        ECHILD => "No child processes",
+        EAGAIN => "Try again",
+        ENOMEM => "Out of memory",
+        EACCES => "Permission denied",
+        EFAULT => "Bad address",
+        EBUSY => "Device or resource busy",
+        EEXIST => "File exists",
+        ENODEV => "No such device",
+        ENOTDIR => "Not a directory",
+        EISDIR => "Is a directory",
+        EINVAL => "Invalid argument",
+        EMFILE => "Too many open files",
+        ENOTTY => "Not a typewriter",
+        ESPIPE => "Illegal seek",
+        EPIPE => "Broken pipe",
+        EDEADLK => "Resource deadlock would occur",
+        ENOSYS => "Function not implemented",
+        ENOTEMPTY => "Directory not empty",
+        EADDRINUSE => "Address already in use",
+        ETIMEDOUT => "Connection timed out",
+        _ => "Unknown error",
+    }
+}
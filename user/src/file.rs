@@ -1,4 +1,7 @@
 use super::*;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
 
 bitflags! {
     pub struct OpenFlags: u32 {
@@ -7,24 +10,487 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const APPEND = 1 << 11;
+        const NONBLOCK = 1 << 12;
     }
 }
 
+pub const F_DUPFD: usize = 0;
+pub const F_GETFL: usize = 3;
+pub const F_SETFL: usize = 4;
+
+pub const AT_REMOVEDIR: usize = 0x200;
+/// dirfd of the *at calls for the working directory, which is the root
+pub const AT_FDCWD: isize = -100;
+pub const AT_EMPTY_PATH: usize = 0x1000;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct StatxTimestamp {
+    pub sec: i64,
+    pub nsec: u32,
+    __reserved: i32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Statx {
+    pub mask: u32,
+    pub blksize: u32,
+    pub attributes: u64,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u16,
+    __spare0: u16,
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    pub attributes_mask: u64,
+    pub atime: StatxTimestamp,
+    pub btime: StatxTimestamp,
+    pub ctime: StatxTimestamp,
+    pub mtime: StatxTimestamp,
+    pub rdev_major: u32,
+    pub rdev_minor: u32,
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    __spare2: [u64; 14],
+}
+
+/// tv_nsec of utimensat for the current time
+pub const UTIME_NOW: usize = (1 << 30) - 1;
+/// tv_nsec of utimensat to leave a timestamp alone
+pub const UTIME_OMIT: usize = (1 << 30) - 2;
+
+pub const STATX_TYPE: u32 = 0x1;
+pub const STATX_MODE: u32 = 0x2;
+pub const STATX_SIZE: u32 = 0x200;
+pub const STATX_BASIC_STATS: u32 = 0x7ff;
+
+bitflags! {
+    pub struct PollEvents: i16 {
+        const POLLIN = 0x1;
+        const POLLPRI = 0x2;
+        const POLLOUT = 0x4;
+        const POLLERR = 0x8;
+        const POLLHUP = 0x10;
+        const POLLNVAL = 0x20;
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: PollEvents) -> Self {
+        Self {
+            fd: fd as i32,
+            events: events.bits(),
+            revents: 0,
+        }
+    }
+    pub fn revents(&self) -> PollEvents {
+        PollEvents::from_bits_truncate(self.revents)
+    }
+}
+
+/// A set of fds below 1024 for `pselect`.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct FdSet([usize; 16]);
+
+impl FdSet {
+    pub fn insert(&mut self, fd: usize) {
+        self.0[fd / 64] |= 1 << (fd % 64);
+    }
+    pub fn contains(&self, fd: usize) -> bool {
+        self.0[fd / 64] & (1 << (fd % 64)) != 0
+    }
+}
+
+pub const DT_FIFO: u8 = 1;
+pub const DT_DIR: u8 = 4;
+pub const DT_REG: u8 = 8;
+
+/// One record of getdents64, borrowed from the buffer it was read into.
+pub struct Dirent64<'a> {
+    pub ino: u64,
+    pub off: i64,
+    pub kind: u8,
+    pub name: &'a str,
+}
+
+/// Walk the records getdents64 left in `buf`.
+pub fn dirents64(buf: &[u8]) -> impl Iterator<Item = Dirent64<'_>> {
+    let mut rest = buf;
+    core::iter::from_fn(move || {
+        if rest.len() < 19 {
+            return None;
+        }
+        let reclen = u16::from_ne_bytes(rest[16..18].try_into().unwrap()) as usize;
+        let (record, next) = rest.split_at(reclen);
+        rest = next;
+        let name = &record[19..];
+        let name_len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        Some(Dirent64 {
+            ino: u64::from_ne_bytes(record[..8].try_into().unwrap()),
+            off: i64::from_ne_bytes(record[8..16].try_into().unwrap()),
+            kind: record[18],
+            name: core::str::from_utf8(&name[..name_len]).unwrap_or(""),
+        })
+    })
+}
+
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFIFO: u32 = 0o010000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: i32,
+    __pad2: i32,
+    pub blocks: i64,
+    pub atime_sec: i64,
+    pub atime_nsec: u64,
+    pub mtime_sec: i64,
+    pub mtime_nsec: u64,
+    pub ctime_sec: i64,
+    pub ctime_nsec: u64,
+    __unused: [u32; 2],
+}
+
+pub const TCGETS: u32 = 0x5401;
+pub const TCSETS: u32 = 0x5402;
+pub const TCSETSW: u32 = 0x5403;
+pub const TCSETSF: u32 = 0x5404;
+pub const TIOCGPGRP: u32 = 0x540f;
+pub const TIOCSPGRP: u32 = 0x5410;
+pub const VT_GETSTATE: u32 = 0x5603;
+pub const VT_ACTIVATE: u32 = 0x5606;
+
+/// `struct vt_stat`, terminals numbered from 1.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct VtStat {
+    pub active: u16,
+    pub signal: u16,
+    pub state: u16,
+}
+
+pub const IOC_WRITE: u32 = 1;
+pub const IOC_READ: u32 = 2;
+
+/// `_IOC` of linux: direction and argument size above type and number.
+pub const fn ioc(dir: u32, type_: u8, nr: u8, size: usize) -> u32 {
+    dir << 30 | (size as u32) << 16 | (type_ as u32) << 8 | nr as u32
+}
+pub const fn ior<T>(type_: u8, nr: u8) -> u32 {
+    ioc(IOC_READ, type_, nr, core::mem::size_of::<T>())
+}
+pub const fn iow<T>(type_: u8, nr: u8) -> u32 {
+    ioc(IOC_WRITE, type_, nr, core::mem::size_of::<T>())
+}
+pub const fn iowr<T>(type_: u8, nr: u8) -> u32 {
+    ioc(IOC_READ | IOC_WRITE, type_, nr, core::mem::size_of::<T>())
+}
+
+pub const FBIOGET_VSCREENINFO: u32 = 0x4600;
+pub const FBIOGET_FSCREENINFO: u32 = 0x4602;
+pub const FBIOPAN_DISPLAY: u32 = 0x4606;
+pub const EVIOCGVERSION: u32 = ior::<i32>(b'E', 0x01);
+/// Name of an input device into a buffer of `len` bytes.
+pub const fn eviocgname(len: usize) -> u32 {
+    ioc(IOC_READ, b'E', 0x06, len)
+}
+pub const SNDCTL_DSP_SYNC: u32 = ioc(0, b'P', 1, 0);
+pub const SNDCTL_DSP_SPEED: u32 = iowr::<i32>(b'P', 2);
+pub const SNDCTL_DSP_SETFMT: u32 = iowr::<i32>(b'P', 5);
+pub const SNDCTL_DSP_CHANNELS: u32 = iowr::<i32>(b'P', 6);
+pub const AFMT_U8: i32 = 0x08;
+pub const AFMT_S16_LE: i32 = 0x10;
+pub const RTC_RD_TIME: u32 = ior::<RtcTime>(b'p', 0x09);
+pub const RTC_SET_TIME: u32 = iow::<RtcTime>(b'p', 0x0a);
+
+/// `struct rtc_time` of linux, `mon` from 0 and `year` since 1900.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RtcTime {
+    pub sec: i32,
+    pub min: i32,
+    pub hour: i32,
+    pub mday: i32,
+    pub mon: i32,
+    pub year: i32,
+    pub wday: i32,
+    pub yday: i32,
+    pub isdst: i32,
+}
+
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+
+bitflags! {
+    pub struct InputModes: u32 {
+        const ICRNL = 0o400;
+    }
+
+    pub struct LocalModes: u32 {
+        const ISIG = 0o1;
+        const ICANON = 0o2;
+        const ECHO = 0o10;
+        const ECHOE = 0o20;
+        const ECHONL = 0o100;
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; 19],
+}
+
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+/// F_DUPFD, F_GETFL or F_SETFL, the flags are `OpenFlags` bits.
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+/// Make `new_fd` a copy of `old_fd`, closing what it referred to.
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    sys_dup2(old_fd, new_fd)
+}
+/// Names of the next entries of directory `fd`, each ending with a NUL.
+pub fn getdents(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents(fd, buf)
+}
+/// Fill `buf` with `struct linux_dirent64` records, see `Dirent64`.
+pub fn getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    sys_getdents64(fd, buf)
+}
+/// Every entry of directory `path`, None if it cannot be listed.
+pub fn read_dir(path: &str) -> Option<Vec<String>> {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut names = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = getdents(fd, &mut buf);
+        if len <= 0 {
+            close(fd);
+            return if len == 0 { Some(names) } else { None };
+        }
+        for name in buf[..len as usize]
+            .split(|b| *b == 0)
+            .filter(|name| !name.is_empty())
+        {
+            names.push(String::from_utf8_lossy(name).into_owned());
+        }
+    }
+}
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    sys_getcwd(buf)
+}
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits)
 }
+pub fn mkfifo(path: &str) -> isize {
+    sys_mkfifo(path)
+}
+pub fn mkdir(path: &str) -> isize {
+    sys_mkdir(path)
+}
+/// Remove a file, a directory goes with `rmdir` once it is empty.
+pub fn unlink(path: &str) -> isize {
+    sys_unlink(path, 0)
+}
+pub fn rmdir(path: &str) -> isize {
+    sys_unlink(path, AT_REMOVEDIR)
+}
+/// Another name for the file at `old_path`.
+pub fn link(old_path: &str, new_path: &str) -> isize {
+    sys_link(old_path, new_path)
+}
+/// Mount the host directory shared as `tag` over the directory `target`,
+/// both NUL-terminated. `fstype` can only be "9p\0".
+pub fn mount(tag: &str, target: &str, fstype: &str) -> isize {
+    sys_mount(tag, target, fstype)
+}
+pub fn umount(target: &str) -> isize {
+    sys_umount2(target, 0)
+}
+/// `path` relative to directory `dirfd`, or AT_FDCWD for the usual lookup.
+pub fn openat(dirfd: isize, path: &str, flags: OpenFlags) -> isize {
+    sys_openat(dirfd, path, flags.bits)
+}
+pub fn mkdirat(dirfd: isize, path: &str) -> isize {
+    sys_mkdirat(dirfd, path)
+}
+/// `flags` is 0 or AT_REMOVEDIR.
+pub fn unlinkat(dirfd: isize, path: &str, flags: usize) -> isize {
+    sys_unlinkat(dirfd, path, flags)
+}
+pub fn linkat(old_dirfd: isize, old_path: &str, new_dirfd: isize, new_path: &str) -> isize {
+    sys_linkat(old_dirfd, old_path, new_dirfd, new_path)
+}
+pub fn fstat(fd: usize, st: &mut Stat) -> isize {
+    sys_fstat(fd, st)
+}
+/// With AT_EMPTY_PATH and an empty path, `dirfd` itself is looked at.
+pub fn fstatat(dirfd: isize, path: &str, st: &mut Stat, flags: usize) -> isize {
+    sys_fstatat(dirfd, path, st, flags)
+}
+fn nullable<T>(value: Option<&T>) -> *const T {
+    value.map_or(core::ptr::null(), |value| value as *const T)
+}
+fn nullable_mut<T>(value: Option<&mut T>) -> *mut T {
+    value.map_or(core::ptr::null_mut(), |value| value as *mut T)
+}
+/// Wait until one of `fds` is ready, for at most `timeout` if given. With
+/// a `mask`, it replaces the signal mask for the time of the wait only.
+/// Returns how many entries have their `revents` set.
+pub fn ppoll(fds: &mut [PollFd], timeout: Option<&TimeSpec>, mask: Option<SignalFlags>) -> isize {
+    let mask = mask.map(|mask| mask.bits());
+    sys_ppoll(fds, nullable(timeout), nullable(mask.as_ref()))
+}
+pub fn poll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    let timeout = TimeSpec {
+        tv_sec: timeout_ms as usize / 1000,
+        tv_nsec: timeout_ms as usize % 1000 * 1_000_000,
+    };
+    let timeout = if timeout_ms < 0 { None } else { Some(&timeout) };
+    ppoll(fds, timeout, None)
+}
+/// select(2) over the fds below `nfds`, sets are left with the ready fds.
+pub fn pselect(
+    nfds: usize,
+    readfds: Option<&mut FdSet>,
+    writefds: Option<&mut FdSet>,
+    exceptfds: Option<&mut FdSet>,
+    timeout: Option<&TimeSpec>,
+    mask: Option<SignalFlags>,
+) -> isize {
+    let mask = mask.map(|mask| mask.bits());
+    sys_pselect(
+        nfds,
+        nullable_mut(readfds),
+        nullable_mut(writefds),
+        nullable_mut(exceptfds),
+        nullable(timeout),
+        nullable(mask.as_ref()),
+    )
+}
+/// Like `fstatat`, `mask` names the fields wanted.
+pub fn statx(dirfd: isize, path: &str, flags: usize, mask: u32, stx: &mut Statx) -> isize {
+    sys_statx(dirfd, path, flags, mask, stx)
+}
+/// Set atime and mtime of `path`, both to now without `times`.
+pub fn utimensat(dirfd: isize, path: &str, times: Option<&[TimeSpec; 2]>, flags: usize) -> isize {
+    let times = times.map_or(core::ptr::null(), |times| times.as_ptr());
+    sys_utimensat(dirfd, path.as_ptr(), times, flags)
+}
+pub fn futimens(fd: usize, times: Option<&[TimeSpec; 2]>) -> isize {
+    let times = times.map_or(core::ptr::null(), |times| times.as_ptr());
+    sys_utimensat(fd as isize, core::ptr::null(), times, 0)
+}
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
-    sys_pipe(pipe_fd)
+    sys_pipe(pipe_fd, 0)
+}
+/// Create a pipe whose buffer holds `capacity` bytes, clamped by the kernel.
+pub fn pipe_with_capacity(pipe_fd: &mut [usize], capacity: usize) -> isize {
+    sys_pipe(pipe_fd, capacity)
+}
+/// Create a pipe, returning its read end and write end.
+pub fn pipe_pair() -> Option<(usize, usize)> {
+    match sys_pipe_pair(0) {
+        (read_fd, write_fd) if read_fd >= 0 => Some((read_fd as usize, write_fd)),
+        _ => None,
+    }
+}
+/// Create a pseudo-terminal, `fds[0]` is the master and `fds[1]` the slave.
+pub fn openpty(fds: &mut [usize]) -> isize {
+    sys_openpty(fds)
 }
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+    // show a pending prompt before waiting for input
+    if fd == 0 {
+        console::flush();
+    }
     sys_read(fd, buf)
 }
 pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
+/// Copy `count` bytes from `in_fd` to `out_fd`, from `offset` if given.
+pub fn sendfile(out_fd: usize, in_fd: usize, offset: Option<&mut usize>, count: usize) -> isize {
+    let offset = offset.map_or(core::ptr::null_mut(), |offset| offset as *mut usize);
+    sys_sendfile(out_fd, in_fd, offset, count)
+}
+pub fn ioctl(fd: usize, cmd: u32, arg: usize) -> isize {
+    sys_ioctl(fd, cmd, arg)
+}
+pub fn tcgetattr(fd: usize, termios: &mut Termios) -> isize {
+    sys_ioctl(fd, TCGETS, termios as *mut _ as usize)
+}
+pub fn tcsetattr(fd: usize, termios: &Termios) -> isize {
+    sys_ioctl(fd, TCSETS, termios as *const _ as usize)
+}
+/// The foreground process group of the terminal, which gets its ^C and ^Z.
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid: i32 = 0;
+    match sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut _ as usize) {
+        0 => pgid as isize,
+        err => err,
+    }
+}
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    let pgid = pgid as i32;
+    sys_ioctl(fd, TIOCSPGRP, &pgid as *const _ as usize)
+}
+/// The virtual terminal shown, from 1, `fd` being any of them.
+pub fn vt_active(fd: usize) -> isize {
+    let mut stat = VtStat::default();
+    match sys_ioctl(fd, VT_GETSTATE, &mut stat as *mut _ as usize) {
+        0 => stat.active as isize,
+        err => err,
+    }
+}
+/// Show virtual terminal `vt`, from 1, as typing ^] and its digit does.
+pub fn vt_activate(fd: usize, vt: usize) -> isize {
+    sys_ioctl(fd, VT_ACTIVATE, vt)
+}
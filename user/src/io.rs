@@ -1,6 +1,9 @@
 use super::*;
+use alloc::format;
+use alloc::vec::Vec;
 use embedded_graphics::pixelcolor::Rgb888;
-use embedded_graphics::prelude::{RgbColor, Size};
+use embedded_graphics::prelude::{Dimensions, Pixel, RgbColor, Size};
+use embedded_graphics::primitives::Rectangle;
 use embedded_graphics::{draw_target::DrawTarget, prelude::OriginDimensions};
 use virtio_input_decoder::Decoder;
 pub use virtio_input_decoder::{DecodeType, Key, KeyType, Mouse};
@@ -115,3 +118,236 @@ impl InputEvent {
         .ok()
     }
 }
+
+pub const EV_SYN: u16 = 0;
+pub const EV_KEY: u16 = 1;
+pub const EV_REL: u16 = 2;
+/// a tablet moved; from a surface, the pointer moved to `x` and `y`
+pub const EV_ABS: u16 = 3;
+pub const REL_X: u16 = 0;
+pub const REL_Y: u16 = 1;
+pub const ABS_X: u16 = 0;
+pub const ABS_Y: u16 = 1;
+/// the positions of the QEMU tablet go from 0 to this, excluded
+pub const ABS_RANGE: i32 = 0x8000;
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+
+pub const KEY_ESC: u16 = 1;
+pub const KEY_Q: u16 = 16;
+pub const KEY_W: u16 = 17;
+pub const KEY_ENTER: u16 = 28;
+pub const KEY_A: u16 = 30;
+pub const KEY_S: u16 = 31;
+pub const KEY_D: u16 = 32;
+pub const KEY_C: u16 = 46;
+pub const KEY_SPACE: u16 = 57;
+pub const KEY_UP: u16 = 103;
+pub const KEY_LEFT: u16 = 105;
+pub const KEY_RIGHT: u16 = 106;
+pub const KEY_DOWN: u16 = 108;
+
+/// `struct input_event`, what the evdev files give.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct TimedInputEvent {
+    pub sec: i64,
+    pub usec: i64,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+/// The input devices there are, /dev/input/event0 and on, read together.
+pub struct InputDevices {
+    fds: Vec<usize>,
+}
+
+impl InputDevices {
+    pub fn open() -> Self {
+        let fds = (0..)
+            .map(|n| open(&format!("/dev/input/event{}\0", n), OpenFlags::NONBLOCK))
+            .take_while(|&fd| fd >= 0)
+            .map(|fd| fd as usize)
+            .collect();
+        Self { fds }
+    }
+    /// The next event of any of the devices, None if there is none now.
+    pub fn event(&self) -> Option<TimedInputEvent> {
+        let mut event = TimedInputEvent::default();
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(
+                &mut event as *mut _ as *mut u8,
+                core::mem::size_of::<TimedInputEvent>(),
+            )
+        };
+        self.fds
+            .iter()
+            .any(|&fd| read(fd, buf) == buf.len() as isize)
+            .then(|| event)
+    }
+    /// The next event of any of the devices, waiting for one.
+    pub fn wait_event(&self) -> Option<TimedInputEvent> {
+        let mut fds: Vec<PollFd> = self
+            .fds
+            .iter()
+            .map(|&fd| PollFd::new(fd, PollEvents::POLLIN))
+            .collect();
+        loop {
+            if let Some(event) = self.event() {
+                return Some(event);
+            }
+            if poll(&mut fds, -1) < 0 {
+                return None;
+            }
+        }
+    }
+}
+
+impl Drop for InputDevices {
+    fn drop(&mut self) {
+        for &fd in self.fds.iter() {
+            close(fd);
+        }
+    }
+}
+
+/// `struct surface_damage` of the kernel.
+#[repr(C)]
+struct SurfaceDamage {
+    pixels: usize,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+pub const SURFACE_DAMAGE: u32 = iow::<SurfaceDamage>(b'S', 0x01);
+
+/// Input routed to a surface. `x` and `y` are where the pointer is,
+/// relative to the surface.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug)]
+pub struct SurfaceEvent {
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl SurfaceEvent {
+    pub fn decode(&self) -> Option<DecodeType> {
+        Decoder::decode(
+            self.event_type as usize,
+            self.code as usize,
+            self.value as usize,
+        )
+        .ok()
+    }
+}
+
+/// A window of the kernel compositor. Drawing goes to a buffer of the
+/// program, `damage` shows a part of it on the screen.
+pub struct Surface {
+    fd: usize,
+    size: Size,
+    pixels: Vec<u8>,
+}
+
+impl Surface {
+    /// A surface of `size` with its corner at (`x`, `y`) of the screen,
+    /// on top of the others. Err holds the negated errno.
+    pub fn new(x: i32, y: i32, size: Size) -> Result<Self, isize> {
+        let fd = sys_surface_create(
+            x as isize,
+            y as isize,
+            size.width as usize,
+            size.height as usize,
+        );
+        if fd < 0 {
+            return Err(fd);
+        }
+        Ok(Self {
+            fd: fd as usize,
+            size,
+            pixels: [0, 0, 0, 0xff].repeat((size.width * size.height) as usize),
+        })
+    }
+    pub fn fd(&self) -> usize {
+        self.fd
+    }
+    /// Show `area` of the buffer, clipped to the surface.
+    pub fn damage(&self, area: &Rectangle) -> isize {
+        let area = area.intersection(&self.bounding_box());
+        let damage = SurfaceDamage {
+            pixels: self.pixels.as_ptr() as usize,
+            x: area.top_left.x as u32,
+            y: area.top_left.y as u32,
+            width: area.size.width,
+            height: area.size.height,
+        };
+        ioctl(self.fd, SURFACE_DAMAGE, &damage as *const _ as usize)
+    }
+    pub fn damage_all(&self) -> isize {
+        self.damage(&self.bounding_box())
+    }
+    /// The next event for the surface, None if there is none yet.
+    pub fn event(&self) -> Option<SurfaceEvent> {
+        let mut fds = [PollFd::new(self.fd, PollEvents::POLLIN)];
+        if poll(&mut fds, 0) <= 0 {
+            return None;
+        }
+        self.wait_event()
+    }
+    /// The next event for the surface, waiting for one.
+    pub fn wait_event(&self) -> Option<SurfaceEvent> {
+        let mut event = SurfaceEvent::default();
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(
+                &mut event as *mut _ as *mut u8,
+                core::mem::size_of::<SurfaceEvent>(),
+            )
+        };
+        if read(self.fd, buf) == buf.len() as isize {
+            Some(event)
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        close(self.fd);
+    }
+}
+
+impl OriginDimensions for Surface {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Surface {
+    type Color = Rgb888;
+
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = embedded_graphics::Pixel<Self::Color>>,
+    {
+        let (width, height) = (self.size.width as i32, self.size.height as i32);
+        for Pixel(point, color) in pixels {
+            if point.x < 0 || point.y < 0 || point.x >= width || point.y >= height {
+                continue;
+            }
+            let idx = (point.y * width + point.x) as usize * 4;
+            self.pixels[idx] = color.b();
+            self.pixels[idx + 1] = color.g();
+            self.pixels[idx + 2] = color.r();
+        }
+        Ok(())
+    }
+}
@@ -1,18 +1,52 @@
-use super::{getpid, kill, SignalFlags};
+use super::{console, getpid, kill, SignalFlags};
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// no frame of interest is further up than the stack is big
+const MAX_STACK_WALK: usize = 4096 * 2;
+const MAX_FRAMES: usize = 16;
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
 
 #[panic_handler]
 fn panic_handler(panic_info: &core::panic::PanicInfo) -> ! {
-    let err = panic_info.message().unwrap();
-    if let Some(location) = panic_info.location() {
-        println!(
-            "Panicked at {}:{}, {}",
-            location.file(),
-            location.line(),
-            err
-        );
-    } else {
-        println!("Panicked: {}", err);
+    // a panic while reporting one goes straight to the abort
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        // what was printed before the panic comes first
+        console::flush();
+        let err = panic_info.message().unwrap();
+        if let Some(location) = panic_info.location() {
+            println!(
+                "Panicked at {}:{}, {}",
+                location.file(),
+                location.line(),
+                err
+            );
+        } else {
+            println!("Panicked: {}", err);
+        }
+        backtrace();
     }
+    // dies by SIGABRT, the exit code is -6
     kill(getpid() as usize, SignalFlags::SIGABRT.bits());
     unreachable!()
 }
+
+/// Walk the frame pointers while they stay on the current stack.
+fn backtrace() {
+    let (mut fp, sp): (usize, usize);
+    unsafe { asm!("mv {}, s0", "mv {}, sp", out(reg) fp, out(reg) sp) };
+    println!("---START BACKTRACE---");
+    for i in 0..MAX_FRAMES {
+        if fp < sp + 16 || fp - sp > MAX_STACK_WALK || fp % 8 != 0 {
+            break;
+        }
+        let ra = unsafe { *((fp - 8) as *const usize) };
+        if ra == 0 {
+            break;
+        }
+        println!("#{}:ra={:#x}", i, ra);
+        fp = unsafe { *((fp - 16) as *const usize) };
+    }
+    println!("---END   BACKTRACE---");
+}
@@ -5,13 +5,16 @@
 
 #[macro_use]
 pub mod console;
+mod errno;
 mod file;
 mod io;
 mod lang_items;
+mod mm;
 mod net;
 mod sync;
 mod syscall;
 mod task;
+mod uintr;
 
 extern crate alloc;
 #[macro_use]
@@ -19,32 +22,34 @@ extern crate bitflags;
 
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
+use mm::UserHeap;
+pub use errno::*;
 pub use file::*;
 pub use io::*;
+pub use mm::*;
 pub use net::*;
 pub use sync::*;
 use syscall::*;
 pub use task::*;
-
-const USER_HEAP_SIZE: usize = 32768;
-
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
+pub use uintr::*;
 
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: UserHeap = UserHeap(LockedHeap::empty());
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
     panic!("Heap allocation error, layout = {:?}", layout);
 }
 
+/// Tells the kernel this binary uses the native syscall numbers rather
+/// than the linux ones.
+#[used]
+#[link_section = ".rcore.abi"]
+static NATIVE_ABI: [u8; 8] = *b"rcore\0\0\0";
+
 #[no_mangle]
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
-    unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
-    }
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
         let str_start =
@@ -0,0 +1,112 @@
+use super::*;
+use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{copy_nonoverlapping, null_mut, NonNull};
+
+pub const PROT_NONE: usize = 0;
+pub const PROT_READ: usize = 1 << 0;
+pub const PROT_WRITE: usize = 1 << 1;
+pub const PROT_EXEC: usize = 1 << 2;
+
+pub const MAP_SHARED: usize = 0x01;
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+pub const MREMAP_MAYMOVE: usize = 1;
+
+pub const MS_ASYNC: usize = 1;
+pub const MS_INVALIDATE: usize = 2;
+pub const MS_SYNC: usize = 4;
+
+pub const MADV_NORMAL: usize = 0;
+pub const MADV_WILLNEED: usize = 3;
+pub const MADV_DONTNEED: usize = 4;
+
+/// Map `len` bytes of `fd` from `offset`, or of zeroed memory with
+/// `MAP_ANONYMOUS`, returning the address or a negative errno.
+pub fn mmap(len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    sys_mmap(0, len, prot, flags, fd, offset)
+}
+
+/// Move the end of the heap to `addr` and return the end in effect, which
+/// `brk(0)` only queries. A negative errno if the pages are taken.
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+
+pub fn munmap(start: usize, len: usize) -> isize {
+    sys_munmap(start, len)
+}
+
+/// Resize a mapping, returning its possibly new address or a negative errno.
+pub fn mremap(old_start: usize, old_len: usize, new_len: usize, flags: usize) -> isize {
+    sys_mremap(old_start, old_len, new_len, flags)
+}
+
+pub fn msync(start: usize, len: usize, flags: usize) -> isize {
+    sys_msync(start, len, flags)
+}
+
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(start, len, prot)
+}
+
+pub fn madvise(start: usize, len: usize, advice: usize) -> isize {
+    sys_madvise(start, len, advice)
+}
+
+/// heap grows by at least this much at a time
+const HEAP_CHUNK: usize = 64 * 1024;
+
+/// A buddy heap that maps more anonymous memory when it runs out.
+pub struct UserHeap(pub LockedHeap);
+
+/// Size of the buddy block serving `layout`.
+fn block_size(layout: &Layout) -> usize {
+    layout
+        .size()
+        .max(layout.align())
+        .max(core::mem::size_of::<usize>())
+        .next_power_of_two()
+}
+
+unsafe impl GlobalAlloc for UserHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // twice the block, so an aligned one fits whatever mmap returns
+        let len = (2 * block_size(&layout)).max(HEAP_CHUNK);
+        let start = mmap(
+            len,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+            0,
+            0,
+        );
+        if start < 0 {
+            return null_mut();
+        }
+        heap.add_to_heap(start as usize, start as usize + len);
+        heap.alloc(layout).map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        // still the same block, nothing to move
+        if block_size(&new_layout) == block_size(&layout) {
+            return ptr;
+        }
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
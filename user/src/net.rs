@@ -1,4 +1,7 @@
 use super::*;
+use alloc::string::String;
+use alloc::vec::Vec;
+use lazy_static::*;
 
 pub fn connect(ip: u32, sport: u16, dport: u16) -> isize {
     sys_connect(ip, sport, dport)
@@ -11,3 +14,196 @@ pub fn listen(sport: u16) -> isize {
 pub fn accept(socket_fd: usize) -> isize {
     sys_accept(socket_fd)
 }
+
+/// Where the kernel tells the DNS server DHCP handed out.
+const RESOLV_CONF: &str = "/proc/resolv.conf\0";
+const DNS_PORT: u16 = 53;
+/// a query with no reply by then has failed
+const DNS_TIMEOUT_MS: isize = 2000;
+pub const DNS_TYPE_A: u16 = 1;
+pub const DNS_TYPE_AAAA: u16 = 28;
+const DNS_CLASS_IN: u16 = 1;
+const DNS_HEADER_LEN: usize = 12;
+/// answers are kept at most this long whatever their ttl says
+const DNS_MAX_TTL_MS: isize = 300_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpAddr {
+    V4(u32),
+    V6([u8; 16]),
+}
+
+struct DnsCacheEntry {
+    name: String,
+    qtype: u16,
+    addr: IpAddr,
+    expires: isize,
+}
+
+lazy_static! {
+    static ref DNS_CACHE: Mutex<Vec<DnsCacheEntry>> = Mutex::new(Vec::new());
+}
+
+/// `a.b.c.d` as connect() wants it.
+pub fn parse_ipv4(name: &str) -> Option<u32> {
+    let mut ip = 0u32;
+    let mut parts = 0;
+    for part in name.split('.') {
+        ip = ip << 8 | part.parse::<u8>().ok()? as u32;
+        parts += 1;
+    }
+    (parts == 4).then_some(ip)
+}
+
+/// The IPv4 address of `name`, which may already be one.
+pub fn resolve(name: &str) -> Option<u32> {
+    if let Some(ip) = parse_ipv4(name) {
+        return Some(ip);
+    }
+    match lookup(name, DNS_TYPE_A)? {
+        IpAddr::V4(ip) => Some(ip),
+        IpAddr::V6(_) => None,
+    }
+}
+
+pub fn resolve_v6(name: &str) -> Option<[u8; 16]> {
+    match lookup(name, DNS_TYPE_AAAA)? {
+        IpAddr::V6(ip) => Some(ip),
+        IpAddr::V4(_) => None,
+    }
+}
+
+/// The first `qtype` record of `name`, from the cache or the DNS server.
+pub fn lookup(name: &str, qtype: u16) -> Option<IpAddr> {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let now = get_time();
+    let cached = {
+        let mut cache = DNS_CACHE.lock();
+        cache.retain(|entry| entry.expires > now);
+        cache
+            .iter()
+            .find(|entry| entry.qtype == qtype && entry.name == name)
+            .map(|entry| entry.addr)
+    };
+    if cached.is_some() {
+        return cached;
+    }
+    // the query is sent without the cache, lookups of other threads go on
+    let (addr, ttl_secs) = dns_query(&name, qtype)?;
+    DNS_CACHE.lock().push(DnsCacheEntry {
+        name,
+        qtype,
+        addr,
+        expires: now + (ttl_secs as isize * 1000).min(DNS_MAX_TTL_MS),
+    });
+    Some(addr)
+}
+
+/// The first `nameserver` of /proc/resolv.conf.
+fn dns_server() -> Option<u32> {
+    let fd = open(RESOLV_CONF, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut text = [0u8; 256];
+    let len = read(fd as usize, &mut text);
+    close(fd as usize);
+    let text = core::str::from_utf8(&text[..len.max(0) as usize]).ok()?;
+    text.lines()
+        .find_map(|line| line.strip_prefix("nameserver "))
+        .and_then(|ip| parse_ipv4(ip.trim()))
+}
+
+fn dns_query(name: &str, qtype: u16) -> Option<(IpAddr, u32)> {
+    if name.is_empty() || name.len() > 253 {
+        return None;
+    }
+    let id = get_time() as u16 ^ getpid() as u16;
+    let mut query = Vec::new();
+    query.extend_from_slice(&id.to_be_bytes());
+    // recursion desired, one question
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return None;
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+
+    // a fresh local port, the same one may still be bound
+    let sport = 49152 + (id & 0x3fff);
+    let fd = connect(dns_server()?, sport, DNS_PORT);
+    if fd < 0 {
+        return None;
+    }
+    let mut reply = [0u8; 512];
+    write(fd as usize, &query);
+    let mut fds = [PollFd::new(fd as usize, PollEvents::POLLIN)];
+    let len = if poll(&mut fds, DNS_TIMEOUT_MS) == 1 {
+        read(fd as usize, &mut reply)
+    } else {
+        0
+    };
+    close(fd as usize);
+    if len < DNS_HEADER_LEN as isize {
+        return None;
+    }
+    parse_dns_reply(&reply[..len as usize], id, qtype)
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]))
+}
+
+/// Offset just past the (possibly compressed) name at `at`.
+fn skip_dns_name(message: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(at)?;
+        match len {
+            0 => return Some(at + 1),
+            // a pointer ends the name
+            len if len & 0xc0 == 0xc0 => return Some(at + 2),
+            len => at += 1 + len as usize,
+        }
+    }
+}
+
+fn parse_dns_reply(reply: &[u8], id: u16, qtype: u16) -> Option<(IpAddr, u32)> {
+    let flags = be_u16(reply, 2)?;
+    // the answer to our query, without an error
+    if be_u16(reply, 0)? != id || flags & 0x8000 == 0 || flags & 0xf != 0 {
+        return None;
+    }
+    let questions = be_u16(reply, 4)?;
+    let answers = be_u16(reply, 6)?;
+    let mut at = DNS_HEADER_LEN;
+    for _ in 0..questions {
+        at = skip_dns_name(reply, at)? + 4;
+    }
+    // CNAMEs come first, the address records follow them
+    for _ in 0..answers {
+        at = skip_dns_name(reply, at)?;
+        let rtype = be_u16(reply, at)?;
+        let ttl = (be_u16(reply, at + 4)? as u32) << 16 | be_u16(reply, at + 6)? as u32;
+        let rdlength = be_u16(reply, at + 8)? as usize;
+        let data = reply.get(at + 10..at + 10 + rdlength)?;
+        at += 10 + rdlength;
+        match (rtype, rdlength) {
+            (DNS_TYPE_A, 4) if rtype == qtype => {
+                let ip = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                return Some((IpAddr::V4(ip), ttl));
+            }
+            (DNS_TYPE_AAAA, 16) if rtype == qtype => {
+                let mut ip = [0u8; 16];
+                ip.copy_from_slice(data);
+                return Some((IpAddr::V6(ip), ttl));
+            }
+            _ => {}
+        }
+    }
+    None
+}
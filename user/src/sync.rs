@@ -1,4 +1,8 @@
 use super::*;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::ptr::null;
+use core::sync::atomic::AtomicU32;
 
 pub fn mutex_create() -> isize {
     sys_mutex_create(false)
@@ -30,3 +34,167 @@ pub fn condvar_signal(condvar_id: usize) {
 pub fn condvar_wait(condvar_id: usize, mutex_id: usize) {
     sys_condvar_wait(condvar_id, mutex_id);
 }
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+pub const FUTEX_REQUEUE: usize = 3;
+pub const FUTEX_CMP_REQUEUE: usize = 4;
+pub const FUTEX_WAIT_BITSET: usize = 9;
+pub const FUTEX_WAKE_BITSET: usize = 10;
+pub const FUTEX_CLOCK_REALTIME: usize = 256;
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Sleep while `word` holds `expected`, -EAGAIN if it did not.
+pub fn futex_wait(word: &AtomicU32, expected: u32) -> isize {
+    sys_futex(word.as_ptr(), FUTEX_WAIT, expected as usize, 0, null(), 0)
+}
+/// Like `futex_wait`, -ETIMEDOUT once `timeout` has passed.
+pub fn futex_wait_timeout(word: &AtomicU32, expected: u32, timeout: &TimeSpec) -> isize {
+    let timeout = timeout as *const TimeSpec as usize;
+    sys_futex(
+        word.as_ptr(),
+        FUTEX_WAIT,
+        expected as usize,
+        timeout,
+        null(),
+        0,
+    )
+}
+/// Only woken by a wake whose bitset shares a bit with `bitset`. The
+/// optional `deadline` is absolute on CLOCK_MONOTONIC.
+pub fn futex_wait_bitset(
+    word: &AtomicU32,
+    expected: u32,
+    deadline: Option<&TimeSpec>,
+    bitset: u32,
+) -> isize {
+    let deadline = deadline.map_or(0, |deadline| deadline as *const TimeSpec as usize);
+    let (op, val3) = (FUTEX_WAIT_BITSET, bitset as usize);
+    sys_futex(word.as_ptr(), op, expected as usize, deadline, null(), val3)
+}
+/// Wake at most `count` threads sleeping on `word`, returns how many.
+pub fn futex_wake(word: &AtomicU32, count: usize) -> isize {
+    sys_futex(word.as_ptr(), FUTEX_WAKE, count, 0, null(), 0)
+}
+pub fn futex_wake_bitset(word: &AtomicU32, count: usize, bitset: u32) -> isize {
+    let (op, val3) = (FUTEX_WAKE_BITSET, bitset as usize);
+    sys_futex(word.as_ptr(), op, count, 0, null(), val3)
+}
+/// Wake `count` waiters of `word` and move up to `requeue` others to
+/// `target`, if `word` still holds `expected`. Returns woken plus moved.
+pub fn futex_cmp_requeue(
+    word: &AtomicU32,
+    count: usize,
+    target: &AtomicU32,
+    requeue: usize,
+    expected: u32,
+) -> isize {
+    let (op, val3) = (FUTEX_CMP_REQUEUE, expected as usize);
+    sys_futex(word.as_ptr(), op, count, requeue, target.as_ptr(), val3)
+}
+/// `word` is set to 0 and woken when the calling thread exits, so another
+/// thread can join it with `futex_wait`. Returns the tid.
+pub fn set_tid_address(word: &AtomicU32) -> isize {
+    sys_set_tid_address(word.as_ptr())
+}
+
+/// A blocking kernel mutex guarding `T`, the kernel object is never freed.
+pub struct Mutex<T> {
+    id: usize,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub fn new(data: T) -> Self {
+        let id = mutex_blocking_create();
+        assert!(id >= 0, "mutex_create failed");
+        Self {
+            id: id as usize,
+            data: UnsafeCell::new(data),
+        }
+    }
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        mutex_lock(self.id);
+        MutexGuard { mutex: self }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        mutex_unlock(self.mutex.id);
+    }
+}
+
+pub struct Condvar {
+    id: usize,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        let id = condvar_create();
+        assert!(id >= 0, "condvar_create failed");
+        Self { id: id as usize }
+    }
+    /// Release the mutex of `guard` while waiting, holding it again on return.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        condvar_wait(self.id, guard.mutex.id);
+        guard
+    }
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+    pub fn notify_one(&self) {
+        condvar_signal(self.id);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Semaphore {
+    id: usize,
+}
+
+impl Semaphore {
+    pub fn new(count: usize) -> Self {
+        let id = semaphore_create(count);
+        assert!(id >= 0, "semaphore_create failed");
+        Self { id: id as usize }
+    }
+    pub fn up(&self) {
+        semaphore_up(self.id);
+    }
+    pub fn down(&self) {
+        semaphore_down(self.id);
+    }
+}
@@ -1,21 +1,66 @@
+use crate::{
+    FdSet, PollFd, ProcessInfo, SignalAction, SignalStack, Stat, Statx, TimeSpec, TimeVal,
+};
+
+const SYSCALL_GETCWD: usize = 17;
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_FCNTL: usize = 25;
 const SYSCALL_CONNECT: usize = 29;
 const SYSCALL_LISTEN: usize = 30;
 const SYSCALL_ACCEPT: usize = 31;
+const SYSCALL_MKFIFO: usize = 33;
+const SYSCALL_MKDIR: usize = 34;
+const SYSCALL_UNLINK: usize = 35;
+const SYSCALL_LINK: usize = 37;
+const SYSCALL_UMOUNT2: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
+const SYSCALL_GETDENTS64: usize = 61;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_SENDFILE: usize = 71;
+const SYSCALL_PSELECT: usize = 72;
+const SYSCALL_PPOLL: usize = 73;
+const SYSCALL_FSTATAT: usize = 79;
+const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_UTIMENSAT: usize = 88;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_SET_TID_ADDRESS: usize = 96;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SLEEP: usize = 101;
+const SYSCALL_CLOCK_SETTIME: usize = 112;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_PTRACE: usize = 117;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGALTSTACK: usize = 132;
+const SYSCALL_SIGSUSPEND: usize = 133;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGWAIT: usize = 137;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_REBOOT: usize = 142;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_PRCTL: usize = 167;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_MREMAP: usize = 216;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MSYNC: usize = 227;
+const SYSCALL_MADVISE: usize = 233;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_GETRANDOM: usize = 278;
+const SYSCALL_STATX: usize = 291;
 const SYSCALL_THREAD_CREATE: usize = 1000;
 const SYSCALL_GETTID: usize = 1001;
 const SYSCALL_WAITTID: usize = 1002;
@@ -30,8 +75,21 @@ const SYSCALL_CONDVAR_SIGNAL: usize = 1031;
 const SYSCALL_CONDVAR_WAIT: usize = 1032;
 const SYSCALL_FRAMEBUFFER: usize = 2000;
 const SYSCALL_FRAMEBUFFER_FLUSH: usize = 2001;
+const SYSCALL_SURFACE_CREATE: usize = 2002;
 const SYSCALL_EVENT_GET: usize = 3000;
 const SYSCALL_KEY_PRESSED: usize = 3001;
+const SYSCALL_IOCTL: usize = 4000;
+const SYSCALL_OPENPTY: usize = 4001;
+const SYSCALL_DUP2: usize = 4002;
+const SYSCALL_GETDENTS: usize = 4003;
+const SYSCALL_PROCESS_LIST: usize = 4004;
+const SYSCALL_OPENAT: usize = 4005;
+const SYSCALL_MKDIRAT: usize = 4006;
+const SYSCALL_UNLINKAT: usize = 4007;
+const SYSCALL_LINKAT: usize = 4008;
+const SYSCALL_NANOSLEEP: usize = 4009;
+const SYSCALL_UINTR_REGISTER: usize = 5000;
+const SYSCALL_UINTR_NOTIFY: usize = 5001;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -47,10 +105,63 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    syscall_pair(id, args).0
+}
+
+/// For syscalls returning a second value in a1.
+fn syscall_pair(id: usize, args: [usize; 6]) -> (isize, usize) {
+    let mut ret: isize;
+    let mut second: usize;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            inlateout("x11") args[1] => second,
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    (ret, second)
+}
+
+pub fn sys_getcwd(buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETCWD, [buf.as_mut_ptr() as usize, buf.len(), 0])
+}
+
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    syscall(SYSCALL_DUP2, [old_fd, new_fd, 0])
+}
+
+pub fn sys_getdents(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETDENTS, [fd, buf.as_mut_ptr() as usize, buf.len()])
+}
+
+pub fn sys_getdents64(fd: usize, buf: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_GETDENTS64,
+        [fd, buf.as_mut_ptr() as usize, buf.len()],
+    )
+}
+
+pub fn sys_process_list(buf: &mut [ProcessInfo]) -> isize {
+    syscall(
+        SYSCALL_PROCESS_LIST,
+        [buf.as_mut_ptr() as usize, buf.len(), 0],
+    )
+}
+
 pub fn sys_connect(dest: u32, sport: u16, dport: u16) -> isize {
     syscall(
         SYSCALL_CONNECT,
@@ -67,16 +178,164 @@ pub fn sys_accept(socket_fd: usize) -> isize {
     syscall(SYSCALL_ACCEPT, [socket_fd, 0, 0])
 }
 
+pub fn sys_mkfifo(path: &str) -> isize {
+    syscall(SYSCALL_MKFIFO, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_mkdir(path: &str) -> isize {
+    syscall(SYSCALL_MKDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_unlink(path: &str, flags: usize) -> isize {
+    syscall(SYSCALL_UNLINK, [path.as_ptr() as usize, flags, 0])
+}
+
+pub fn sys_link(old_path: &str, new_path: &str) -> isize {
+    syscall(
+        SYSCALL_LINK,
+        [old_path.as_ptr() as usize, new_path.as_ptr() as usize, 0],
+    )
+}
+
+pub fn sys_mount(source: &str, target: &str, fstype: &str) -> isize {
+    syscall6(
+        SYSCALL_MOUNT,
+        [
+            source.as_ptr() as usize,
+            target.as_ptr() as usize,
+            fstype.as_ptr() as usize,
+            0,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_umount2(target: &str, flags: usize) -> isize {
+    syscall(SYSCALL_UMOUNT2, [target.as_ptr() as usize, flags, 0])
+}
+
 pub fn sys_open(path: &str, flags: u32) -> isize {
     syscall(SYSCALL_OPEN, [path.as_ptr() as usize, flags as usize, 0])
 }
 
+pub fn sys_openat(dirfd: isize, path: &str, flags: u32) -> isize {
+    syscall(
+        SYSCALL_OPENAT,
+        [dirfd as usize, path.as_ptr() as usize, flags as usize],
+    )
+}
+
+pub fn sys_mkdirat(dirfd: isize, path: &str) -> isize {
+    syscall(SYSCALL_MKDIRAT, [dirfd as usize, path.as_ptr() as usize, 0])
+}
+
+pub fn sys_unlinkat(dirfd: isize, path: &str, flags: usize) -> isize {
+    syscall(
+        SYSCALL_UNLINKAT,
+        [dirfd as usize, path.as_ptr() as usize, flags],
+    )
+}
+
+pub fn sys_linkat(old_dirfd: isize, old_path: &str, new_dirfd: isize, new_path: &str) -> isize {
+    syscall6(
+        SYSCALL_LINKAT,
+        [
+            old_dirfd as usize,
+            old_path.as_ptr() as usize,
+            new_dirfd as usize,
+            new_path.as_ptr() as usize,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_ppoll(fds: &mut [PollFd], timeout: *const TimeSpec, mask: *const i32) -> isize {
+    syscall6(
+        SYSCALL_PPOLL,
+        [
+            fds.as_mut_ptr() as usize,
+            fds.len(),
+            timeout as usize,
+            mask as usize,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_pselect(
+    nfds: usize,
+    readfds: *mut FdSet,
+    writefds: *mut FdSet,
+    exceptfds: *mut FdSet,
+    timeout: *const TimeSpec,
+    mask: *const i32,
+) -> isize {
+    syscall6(
+        SYSCALL_PSELECT,
+        [
+            nfds,
+            readfds as usize,
+            writefds as usize,
+            exceptfds as usize,
+            timeout as usize,
+            mask as usize,
+        ],
+    )
+}
+
+pub fn sys_fstat(fd: usize, st: &mut Stat) -> isize {
+    syscall(SYSCALL_FSTAT, [fd, st as *mut Stat as usize, 0])
+}
+
+pub fn sys_fstatat(dirfd: isize, path: &str, st: &mut Stat, flags: usize) -> isize {
+    syscall6(
+        SYSCALL_FSTATAT,
+        [
+            dirfd as usize,
+            path.as_ptr() as usize,
+            st as *mut Stat as usize,
+            flags,
+            0,
+            0,
+        ],
+    )
+}
+
+pub fn sys_utimensat(dirfd: isize, path: *const u8, times: *const TimeSpec, flags: usize) -> isize {
+    syscall6(
+        SYSCALL_UTIMENSAT,
+        [dirfd as usize, path as usize, times as usize, flags, 0, 0],
+    )
+}
+
+pub fn sys_statx(dirfd: isize, path: &str, flags: usize, mask: u32, stx: &mut Statx) -> isize {
+    syscall6(
+        SYSCALL_STATX,
+        [
+            dirfd as usize,
+            path.as_ptr() as usize,
+            flags,
+            mask as usize,
+            stx as *mut Statx as usize,
+            0,
+        ],
+    )
+}
+
 pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0])
 }
 
-pub fn sys_pipe(pipe: &mut [usize]) -> isize {
-    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
+pub fn sys_pipe(pipe: &mut [usize], capacity: usize) -> isize {
+    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, capacity, 0])
+}
+
+/// Returns the read end and the write end of a new pipe.
+pub fn sys_pipe_pair(capacity: usize) -> (isize, usize) {
+    syscall_pair(SYSCALL_PIPE, [0, capacity, 0, 0, 0, 0])
 }
 
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
@@ -90,15 +349,35 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: *mut usize, count: usize) -> isize {
+    syscall6(
+        SYSCALL_SENDFILE,
+        [out_fd, in_fd, offset as usize, count, 0, 0],
+    )
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
     panic!("sys_exit never returns!");
 }
 
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    syscall(SYSCALL_EXIT_GROUP, [exit_code as usize, 0, 0]);
+    panic!("sys_exit_group never returns!");
+}
+
 pub fn sys_sleep(sleep_ms: usize) -> isize {
     syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
 }
 
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> isize {
+    syscall(SYSCALL_NANOSLEEP, [req as usize, rem as usize, 0])
+}
+
+pub fn sys_ptrace(request: usize, pid: usize, addr: usize, data: usize) -> isize {
+    syscall6(SYSCALL_PTRACE, [request, pid, addr, data, 0, 0])
+}
+
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
@@ -107,14 +386,108 @@ pub fn sys_kill(pid: usize, signal: i32) -> isize {
     syscall(SYSCALL_KILL, [pid, signal as usize, 0])
 }
 
-pub fn sys_get_time() -> isize {
-    syscall(SYSCALL_GET_TIME, [0, 0, 0])
+pub fn sys_sigaltstack(stack: *const SignalStack, old_stack: *mut SignalStack) -> isize {
+    syscall(SYSCALL_SIGALTSTACK, [stack as usize, old_stack as usize, 0])
+}
+
+pub fn sys_sigsuspend(mask: i32) -> isize {
+    syscall(SYSCALL_SIGSUSPEND, [mask as usize, 0, 0])
+}
+
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_sigprocmask(how: usize, set: i32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [how, set as usize, 0])
+}
+
+pub fn sys_sigwait(set: i32) -> isize {
+    syscall(SYSCALL_SIGWAIT, [set as usize, 0, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+pub fn sys_reboot(cmd: usize) -> isize {
+    syscall(SYSCALL_REBOOT, [0xfee1dead, 672274793, cmd])
+}
+
+pub fn sys_prctl(option: usize, arg: usize) -> isize {
+    syscall(SYSCALL_PRCTL, [option, arg, 0])
+}
+
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall6(SYSCALL_MMAP, [addr, len, prot, flags, fd, offset])
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0])
+}
+
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [start, len, 0])
+}
+
+pub fn sys_mremap(old_start: usize, old_len: usize, new_len: usize, flags: usize) -> isize {
+    syscall6(SYSCALL_MREMAP, [old_start, old_len, new_len, flags, 0, 0])
+}
+
+pub fn sys_msync(start: usize, len: usize, flags: usize) -> isize {
+    syscall(SYSCALL_MSYNC, [start, len, flags])
+}
+
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_madvise(start: usize, len: usize, advice: usize) -> isize {
+    syscall(SYSCALL_MADVISE, [start, len, advice])
+}
+
+pub fn sys_get_time(tv: *mut TimeVal) -> isize {
+    syscall(SYSCALL_GET_TIME, [tv as usize, 0, 0])
+}
+
+pub fn sys_clock_gettime(clock: usize, ts: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock, ts as *mut _ as usize, 0])
+}
+
+pub fn sys_clock_settime(clock: usize, ts: &TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_SETTIME, [clock, ts as *const _ as usize, 0])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
 }
 
 pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0, 0, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
@@ -126,8 +499,15 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
-pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
+pub fn sys_waitpid(pid: isize, exit_code: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, options])
+}
+
+pub fn sys_getrandom(buf: &mut [u8], flags: usize) -> isize {
+    syscall(
+        SYSCALL_GETRANDOM,
+        [buf.as_mut_ptr() as usize, buf.len(), flags],
+    )
 }
 
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
@@ -138,8 +518,8 @@ pub fn sys_gettid() -> isize {
     syscall(SYSCALL_GETTID, [0; 3])
 }
 
-pub fn sys_waittid(tid: usize) -> isize {
-    syscall(SYSCALL_WAITTID, [tid, 0, 0])
+pub fn sys_waittid(tid: usize, exit_code: *mut i32) -> isize {
+    syscall(SYSCALL_WAITTID, [tid, exit_code as usize, 0])
 }
 
 pub fn sys_mutex_create(blocking: bool) -> isize {
@@ -166,6 +546,24 @@ pub fn sys_semaphore_down(sem_id: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_DOWN, [sem_id, 0, 0])
 }
 
+pub fn sys_futex(
+    uaddr: *const u32,
+    op: usize,
+    val: usize,
+    timeout: usize,
+    uaddr2: *const u32,
+    val3: usize,
+) -> isize {
+    syscall6(
+        SYSCALL_FUTEX,
+        [uaddr as usize, op, val, timeout, uaddr2 as usize, val3],
+    )
+}
+
+pub fn sys_set_tid_address(tidptr: *const u32) -> isize {
+    syscall(SYSCALL_SET_TID_ADDRESS, [tidptr as usize, 0, 0])
+}
+
 pub fn sys_condvar_create() -> isize {
     syscall(SYSCALL_CONDVAR_CREATE, [0, 0, 0])
 }
@@ -186,6 +584,13 @@ pub fn sys_framebuffer_flush() -> isize {
     syscall(SYSCALL_FRAMEBUFFER_FLUSH, [0, 0, 0])
 }
 
+pub fn sys_surface_create(x: isize, y: isize, width: usize, height: usize) -> isize {
+    syscall6(
+        SYSCALL_SURFACE_CREATE,
+        [x as usize, y as usize, width, height, 0, 0],
+    )
+}
+
 pub fn sys_event_get() -> isize {
     syscall(SYSCALL_EVENT_GET, [0, 0, 0])
 }
@@ -193,3 +598,19 @@ pub fn sys_event_get() -> isize {
 pub fn sys_key_pressed() -> isize {
     syscall(SYSCALL_KEY_PRESSED, [0, 0, 0])
 }
+
+pub fn sys_ioctl(fd: usize, cmd: u32, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd as usize, arg])
+}
+
+pub fn sys_openpty(fds: &mut [usize]) -> isize {
+    syscall(SYSCALL_OPENPTY, [fds.as_mut_ptr() as usize, 0, 0])
+}
+
+pub fn sys_uintr_register(handler: usize, events: usize) -> isize {
+    syscall(SYSCALL_UINTR_REGISTER, [handler, events, 0])
+}
+
+pub fn sys_uintr_notify(pid: usize) -> isize {
+    syscall(SYSCALL_UINTR_NOTIFY, [pid, 0, 0])
+}
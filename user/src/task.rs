@@ -1,59 +1,245 @@
 use super::*;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
 
 pub fn exit(exit_code: i32) -> ! {
+    console::flush();
     sys_exit(exit_code);
 }
+/// End every thread of the process, whichever one calls it.
+pub fn exit_group(exit_code: i32) -> ! {
+    console::flush();
+    sys_exit_group(exit_code);
+}
 pub fn yield_() -> isize {
     sys_yield()
 }
+/// Milliseconds since boot.
 pub fn get_time() -> isize {
-    sys_get_time()
+    sys_get_time(core::ptr::null_mut())
+}
+/// The time since boot in microseconds, returning it in milliseconds too.
+pub fn get_timeval(tv: &mut TimeVal) -> isize {
+    sys_get_time(tv)
+}
+/// Cycles of the hart so far, read without entering the kernel.
+pub fn cycles() -> usize {
+    let cycles;
+    unsafe { core::arch::asm!("rdcycle {}", out(reg) cycles) };
+    cycles
+}
+/// Fill `buf` from the kernel entropy pool, returning the bytes written.
+pub fn getrandom(buf: &mut [u8], flags: usize) -> isize {
+    sys_getrandom(buf, flags)
+}
+pub fn clock_gettime(clock: usize, ts: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clock, ts)
+}
+pub fn clock_settime(clock: usize, ts: &TimeSpec) -> isize {
+    sys_clock_settime(clock, ts)
 }
 pub fn getpid() -> isize {
     sys_getpid()
 }
+pub fn getppid() -> isize {
+    sys_getppid()
+}
 pub fn fork() -> isize {
+    // or the child would print it again
+    console::flush();
     sys_fork()
 }
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
+    console::flush();
     sys_exec(path, args)
 }
 
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
+        match sys_waitpid(-1, exit_code as *mut _, 0) {
+            exit_pid if exit_pid == -EAGAIN => {
                 yield_();
             }
-            // -1 or a real pid
+            // -ECHILD or a real pid
             exit_pid => return exit_pid,
         }
     }
 }
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
+    waitpid_options(pid as isize, exit_code, 0)
+}
+
+pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid as isize, exit_code as *mut _, 0)
+}
+
+/// `pid` may also be 0 or `-pgid` for any child in a process group.
+pub fn waitpid_options(pid: isize, exit_code: &mut i32, options: usize) -> isize {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
+        match sys_waitpid(pid, exit_code as *mut _, options) {
+            exit_pid if exit_pid == -EAGAIN && options & WNOHANG == 0 => {
                 yield_();
             }
-            // -1 or a real pid
+            // -ECHILD, -EAGAIN with WNOHANG or a real pid
             exit_pid => return exit_pid,
         }
     }
 }
 
-pub fn waitpid_nb(pid: usize, exit_code: &mut i32) -> isize {
-    sys_waitpid(pid as isize, exit_code as *mut _)
-}
+/// waitpid options
+pub const WNOHANG: usize = 1;
+pub const WUNTRACED: usize = 2;
+pub const WCONTINUED: usize = 8;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+pub const SA_ONSTACK: usize = 0x0800_0000;
+pub const SS_ONSTACK: i32 = 1;
+pub const SS_DISABLE: i32 = 2;
+pub const MINSIGSTKSZ: usize = 2048;
 
 bitflags! {
     pub struct SignalFlags: i32 {
+        const SIGHUP    = 1 << 1;
         const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
         const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
         const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
         const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
         const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
+    }
+}
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+/// `handler` is SIG_DFL, SIG_IGN or the address of an `extern "C" fn(usize)`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    pub handler: usize,
+    pub flags: usize,
+    pub restorer: usize,
+    pub mask: SignalFlags,
+}
+
+impl SignalAction {
+    pub fn new(handler: usize, mask: SignalFlags) -> Self {
+        Self {
+            handler,
+            flags: 0,
+            restorer: 0,
+            mask,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalStack {
+    pub sp: usize,
+    pub flags: i32,
+    pub size: usize,
+}
+
+impl SignalStack {
+    pub fn new(stack: &'static mut [u8]) -> Self {
+        Self {
+            sp: stack.as_mut_ptr() as usize,
+            flags: 0,
+            size: stack.len(),
+        }
+    }
+}
+
+/// What ps shows of a process.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ProcessInfo {
+    pub pid: usize,
+    pub ppid: usize,
+    pub pgid: usize,
+    pub threads: usize,
+    /// `R`unnable, `S`leeping or `T` stopped
+    pub state: u8,
+    pub name: [u8; 31],
+}
+
+impl ProcessInfo {
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(self.name.len());
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
+/// Every live process, zombies excluded.
+pub fn process_list() -> Vec<ProcessInfo> {
+    let empty = ProcessInfo {
+        pid: 0,
+        ppid: 0,
+        pgid: 0,
+        threads: 0,
+        state: 0,
+        name: [0; 31],
+    };
+    let mut list = vec![empty; 16];
+    loop {
+        let count = sys_process_list(&mut list) as usize;
+        // processes come and go, ask again if they did not fit
+        if count <= list.len() {
+            list.truncate(count);
+            return list;
+        }
+        list.resize(count + 8, empty);
     }
 }
 
@@ -61,23 +247,212 @@ pub fn kill(pid: usize, signal: i32) -> isize {
     sys_kill(pid, signal)
 }
 
+/// Send `signal` to every process in group `pgid`.
+pub fn killpg(pgid: usize, signal: i32) -> isize {
+    sys_kill(-(pgid as isize) as usize, signal)
+}
+
+const REBOOT_CMD_POWER_OFF: usize = 0x4321fedc;
+
+/// Kill everything, sync the disk and turn the machine off; returns only
+/// on an error.
+pub fn poweroff() -> isize {
+    console::flush();
+    sys_reboot(REBOOT_CMD_POWER_OFF)
+}
+
+/// `pid` 0 is the caller, `pgid` 0 makes it a group of its own.
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+/// Signal handlers return here.
+extern "C" fn sigreturn_trampoline() -> ! {
+    sys_sigreturn();
+    unreachable!()
+}
+
+pub fn sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    let action = action.map(|action| SignalAction {
+        restorer: sigreturn_trampoline as usize,
+        ..*action
+    });
+    sys_sigaction(
+        signum,
+        action
+            .as_ref()
+            .map_or(core::ptr::null(), |action| action as *const _),
+        old_action.map_or(core::ptr::null_mut(), |action| action as *mut _),
+    )
+}
+
+/// Return the previous mask.
+pub fn sigprocmask(how: usize, set: SignalFlags) -> SignalFlags {
+    SignalFlags::from_bits_truncate(sys_sigprocmask(how, set.bits()) as i32)
+}
+
+pub fn sigsuspend(mask: SignalFlags) -> isize {
+    sys_sigsuspend(mask.bits())
+}
+
+pub fn sigaltstack(stack: Option<&SignalStack>, old_stack: Option<&mut SignalStack>) -> isize {
+    sys_sigaltstack(
+        stack.map_or(core::ptr::null(), |stack| stack as *const _),
+        old_stack.map_or(core::ptr::null_mut(), |stack| stack as *mut _),
+    )
+}
+
+/// Return the number of the signal taken.
+pub fn sigwait(set: SignalFlags) -> isize {
+    sys_sigwait(set.bits())
+}
+
+pub const PR_GET_DUMPABLE: usize = 3;
+pub const PR_SET_DUMPABLE: usize = 4;
+
+pub fn prctl(option: usize, arg: usize) -> isize {
+    sys_prctl(option, arg)
+}
+
+pub const PTRACE_TRACEME: usize = 0;
+pub const PTRACE_PEEKTEXT: usize = 1;
+pub const PTRACE_PEEKDATA: usize = 2;
+pub const PTRACE_PEEKUSER: usize = 3;
+pub const PTRACE_POKETEXT: usize = 4;
+pub const PTRACE_POKEDATA: usize = 5;
+pub const PTRACE_POKEUSER: usize = 6;
+pub const PTRACE_CONT: usize = 7;
+pub const PTRACE_KILL: usize = 8;
+pub const PTRACE_SINGLESTEP: usize = 9;
+pub const PTRACE_GETREGS: usize = 12;
+pub const PTRACE_SETREGS: usize = 13;
+pub const PTRACE_ATTACH: usize = 16;
+pub const PTRACE_DETACH: usize = 17;
+/// one-shot hardware watchpoint, only where the debug triggers are available
+pub const PTRACE_SETWATCH: usize = 0x4300;
+pub const PTRACE_CLEARWATCH: usize = 0x4301;
+pub const WATCH_LOAD: usize = 1;
+pub const WATCH_STORE: usize = 2;
+
+/// pc followed by x1..x31
+pub type UserRegs = [usize; 32];
+
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> isize {
+    sys_ptrace(request, pid, addr, data)
+}
+
+/// Whether a status from waitpid reports a stopped tracee.
+pub fn wifstopped(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
+
+pub fn wstopsig(status: i32) -> usize {
+    ((status >> 8) & 0xff) as usize
+}
+
+/// Whether a status from waitpid with WCONTINUED reports a resumed child.
+pub fn wifcontinued(status: i32) -> bool {
+    status == 0xffff
+}
+
 pub fn sleep(sleep_ms: usize) {
     sys_sleep(sleep_ms);
 }
 
+/// Sleep for `req`, a delivered signal ends it early with -EINTR and the
+/// time left in `rem`.
+pub fn nanosleep(req: &TimeSpec, rem: Option<&mut TimeSpec>) -> isize {
+    sys_nanosleep(req, rem.map_or(core::ptr::null_mut(), |rem| rem as *mut _))
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
 pub fn gettid() -> isize {
     sys_gettid()
 }
+/// Wait for the thread `tid` and return its exit code. There is no error
+/// to tell from an exit code: waiting for no thread of the process, or for
+/// the caller itself, panics.
 pub fn waittid(tid: usize) -> isize {
+    let mut exit_code = 0;
     loop {
-        match sys_waittid(tid) {
-            -2 => {
+        match sys_waittid(tid, &mut exit_code) {
+            result if result == -EAGAIN => {
                 yield_();
             }
-            exit_code => return exit_code,
+            result if result < 0 => panic!("waittid({}) failed: {}", tid, result),
+            _ => return exit_code as isize,
+        }
+    }
+}
+
+/// Where a spawned thread leaves its result for `join`.
+struct ThreadResult<T>(UnsafeCell<Option<T>>);
+
+/// Written by the thread before it exits, read by the joiner after.
+unsafe impl<T: Send> Sync for ThreadResult<T> {}
+
+struct ThreadStart<F, T> {
+    f: F,
+    result: Arc<ThreadResult<T>>,
+}
+
+/// Entry of a spawned thread. `exit` does not return, so the box and the
+/// reference to the result are given back before it.
+fn thread_start<F: FnOnce() -> T, T>(start: *mut ThreadStart<F, T>) -> ! {
+    let ThreadStart { f, result } = *unsafe { Box::from_raw(start) };
+    unsafe { *result.0.get() = Some(f()) };
+    drop(result);
+    exit(0)
+}
+
+pub struct Thread;
+
+impl Thread {
+    /// Run `f` in a new thread of this process.
+    pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let result = Arc::new(ThreadResult(UnsafeCell::new(None)));
+        let start = Box::into_raw(Box::new(ThreadStart {
+            f,
+            result: result.clone(),
+        }));
+        let tid = thread_create(thread_start::<F, T> as usize, start as usize);
+        if tid <= 0 {
+            drop(unsafe { Box::from_raw(start) });
+            panic!("thread_create failed");
+        }
+        JoinHandle {
+            tid: tid as usize,
+            result,
         }
     }
 }
+
+pub struct JoinHandle<T> {
+    tid: usize,
+    result: Arc<ThreadResult<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn tid(&self) -> usize {
+        self.tid
+    }
+    /// Wait for the thread and take what its closure returned.
+    pub fn join(self) -> T {
+        waittid(self.tid);
+        unsafe { (*self.result.0.get()).take() }.expect("thread exited without a result")
+    }
+}
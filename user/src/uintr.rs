@@ -0,0 +1,75 @@
+use super::*;
+use core::arch::global_asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub const UINTR_TIMER: usize = 1 << 0;
+pub const UINTR_NOTIFY: usize = 1 << 1;
+
+/// Rust handler called by `__uintr_entry`, as a plain fn address.
+#[no_mangle]
+static UINTR_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+// The kernel enters here with the events in a0 and keeps only pc, a0 and a7
+// of the interrupted code. Registers the handler may clobber are saved on the
+// stack; the handler must not touch floating point registers.
+global_asm!(
+    "
+    .globl __uintr_entry
+__uintr_entry:
+    addi sp, sp, -16*8
+    sd ra, 0*8(sp)
+    sd t0, 1*8(sp)
+    sd t1, 2*8(sp)
+    sd t2, 3*8(sp)
+    sd t3, 4*8(sp)
+    sd t4, 5*8(sp)
+    sd t5, 6*8(sp)
+    sd t6, 7*8(sp)
+    sd a1, 8*8(sp)
+    sd a2, 9*8(sp)
+    sd a3, 10*8(sp)
+    sd a4, 11*8(sp)
+    sd a5, 12*8(sp)
+    sd a6, 13*8(sp)
+    la t0, UINTR_HANDLER
+    ld t0, 0(t0)
+    jalr t0
+    ld ra, 0*8(sp)
+    ld t0, 1*8(sp)
+    ld t1, 2*8(sp)
+    ld t2, 3*8(sp)
+    ld t3, 4*8(sp)
+    ld t4, 5*8(sp)
+    ld t5, 6*8(sp)
+    ld t6, 7*8(sp)
+    ld a1, 8*8(sp)
+    ld a2, 9*8(sp)
+    ld a3, 10*8(sp)
+    ld a4, 11*8(sp)
+    ld a5, 12*8(sp)
+    ld a6, 13*8(sp)
+    addi sp, sp, 16*8
+    # SYSCALL_UINTR_RETURN restores pc, a0 and a7
+    li a7, 5002
+    ecall
+"
+);
+
+extern "C" {
+    fn __uintr_entry();
+}
+
+/// Have `handler` called with the pending events among `events`, whenever
+/// the thread returns to user mode.
+pub fn uintr_register(handler: extern "C" fn(usize), events: usize) -> isize {
+    UINTR_HANDLER.store(handler as usize, Ordering::SeqCst);
+    sys_uintr_register(__uintr_entry as usize, events)
+}
+
+pub fn uintr_unregister() -> isize {
+    sys_uintr_register(0, 0)
+}
+
+pub fn uintr_notify(pid: usize) -> isize {
+    sys_uintr_notify(pid)
+}